@@ -1,7 +1,14 @@
 #![feature(bool_to_option)]
 
 use anyhow::Result;
-use loadstone_config::{codegen::generate_modules, security::SecurityMode, Configuration};
+use loadstone_config::{
+    codegen::{
+        check_feature_flags, generate_application_linker_script, generate_c_header,
+        generate_modules,
+    },
+    security::SecurityMode,
+    Configuration,
+};
 use std::fs;
 
 fn configure_runner(target: &str) {
@@ -34,12 +41,42 @@ fn process_configuration_file() -> Result<()> {
     };
 
     validate_feature_flags_against_configuration(&configuration);
-    generate_modules(env!("CARGO_MANIFEST_DIR"), &configuration)?;
+    expose_build_information(&configuration);
+    println!("cargo:rerun-if-env-changed=LOADSTONE_CHECK_ONLY");
+    if std::env::var("LOADSTONE_CHECK_ONLY").is_ok() {
+        return Ok(());
+    }
+
+    println!("cargo:rerun-if-env-changed=LOADSTONE_FORCE_REGENERATE");
+    let force = std::env::var("LOADSTONE_FORCE_REGENERATE").is_ok();
+    generate_modules(env!("CARGO_MANIFEST_DIR"), &configuration, force)?;
+
+    println!("cargo:rerun-if-env-changed=LOADSTONE_APP_MEMORY_X");
+    if let Ok(path) = std::env::var("LOADSTONE_APP_MEMORY_X") {
+        generate_application_linker_script(&configuration, path)?;
+    }
+
+    println!("cargo:rerun-if-env-changed=LOADSTONE_C_HEADER");
+    if let Ok(path) = std::env::var("LOADSTONE_C_HEADER") {
+        generate_c_header(&configuration, path)?;
+    }
     configure_runner(&configuration.port.to_string());
 
     Ok(())
 }
 
+/// Exposes the port and security mode Loadstone is built for to the on-device CLI,
+/// through the `LOADSTONE_PORT` and `LOADSTONE_SECURITY_MODE` environment variables.
+fn expose_build_information(configuration: &Configuration) {
+    let security = &configuration.security_configuration;
+    let security_mode = match security.security_mode {
+        SecurityMode::P256ECDSA => "P256 ECDSA".to_owned(),
+        SecurityMode::Crc => format!("CRC32 ({:?})", security.crc_algorithm),
+    };
+    println!("cargo:rustc-env=LOADSTONE_PORT={}", configuration.port);
+    println!("cargo:rustc-env=LOADSTONE_SECURITY_MODE={}", security_mode);
+}
+
 fn validate_feature_flags_against_configuration(configuration: &Configuration) {
     let supplied_flags: Vec<_> = std::env::vars()
         .filter_map(|(k, _)| {
@@ -48,24 +85,13 @@ fn validate_feature_flags_against_configuration(configuration: &Configuration) {
         })
         .collect();
 
-    let missing_flags: Vec<_> = configuration
-        .required_feature_flags()
-        .map(|s| s.replace("-", "_"))
-        .filter(|f| !&supplied_flags.contains(&(*f).to_owned()))
-        .collect();
-
-    if configuration.security_configuration.security_mode != SecurityMode::P256ECDSA
-        && supplied_flags.contains(&"ecdsa_verify".to_owned())
-    {
-        panic!("Configuration mismatch. Configuration file does not specify ECDSA security mode, \
-                but the `ecdsa-verify` flag was supplied. Try again without `ecdsa-verify` for CRC mode.");
-    }
-
-    if !missing_flags.is_empty() {
+    if let Err(mismatches) = check_feature_flags(configuration, &supplied_flags) {
+        let required_flags: Vec<_> = configuration.required_feature_flags().collect();
         panic!(
-            "\r\n\r\nThe configuration file requires flags that haven't been supplied. \
+            "\r\n\r\nThe configuration file doesn't match the supplied feature flags:\r\n{}\r\n\
             Please build again with `--features={}`\r\n\r\n",
-            missing_flags.join(","),
+            mismatches.iter().map(|m| format!("  * {}\r\n", m)).collect::<String>(),
+            required_flags.join(","),
         );
     }
 }
@@ -0,0 +1,50 @@
+//! Generates the autogenerated modules of several ports at once, from a folder
+//! of .ron configuration files. Run from the Loadstone root folder:
+//!
+//! `cargo run --manifest-path loadstone_config/Cargo.toml --bin generate_ports -- <folder> [--force | --summary]`
+//!
+//! Exits with an error if any of the configurations failed to generate. With `--summary`,
+//! prints a human readable summary of every configuration instead of generating them.
+use anyhow::{anyhow, Result};
+use loadstone_config::{codegen::generate_batch, Configuration};
+use std::{fs, path::Path};
+
+fn main() -> Result<()> {
+    let arguments: Vec<String> = std::env::args().skip(1).collect();
+    let force = arguments.iter().any(|argument| argument == "--force");
+    let configurations_path =
+        arguments.iter().find(|argument| !argument.starts_with("--")).ok_or_else(|| {
+            anyhow!("Usage: generate_ports <configuration folder> [--force | --summary]")
+        })?;
+    if arguments.iter().any(|argument| argument == "--summary") {
+        return print_summaries(configurations_path);
+    }
+
+    let outcomes = generate_batch(".", configurations_path, force)?;
+    for outcome in &outcomes {
+        match &outcome.result {
+            Ok(port) => println!("[OK] {} -> {}", outcome.path.display(), port),
+            Err(error) => println!("[FAILED] {}: {}", outcome.path.display(), error),
+        }
+    }
+
+    let failures = outcomes.iter().filter(|outcome| outcome.result.is_err()).count();
+    if failures > 0 {
+        return Err(anyhow!("{} of {} configurations failed.", failures, outcomes.len()));
+    }
+    Ok(())
+}
+
+/// Prints the summary of every .ron configuration in a folder, in file name order.
+fn print_summaries<P: AsRef<Path>>(configurations_path: P) -> Result<()> {
+    let mut paths = fs::read_dir(configurations_path)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.retain(|path| path.extension().map_or(false, |extension| extension == "ron"));
+    paths.sort();
+    for path in paths {
+        let configuration: Configuration = ron::from_str(&fs::read_to_string(&path)?)?;
+        println!("# {}\n\n{}", path.display(), configuration.summary());
+    }
+    Ok(())
+}
@@ -0,0 +1,128 @@
+//! Generation of several ports in one go, from a folder of .ron configuration files.
+use std::{
+    any::Any,
+    fs,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::{port::Port, Configuration};
+
+use super::generate_port_modules;
+
+/// Result of generating a single configuration file as part of a batch.
+pub struct BatchOutcome {
+    /// The .ron configuration file the port was generated from.
+    pub path: PathBuf,
+    /// The generated port, or the reason the configuration couldn't be generated.
+    pub result: Result<Port>,
+}
+
+/// Generates the port modules for every .ron file in a folder, each into the
+/// src/ports/<port>/autogenerated folder of its own port. Configurations are
+/// processed in file name order, and a configuration that fails to parse or
+/// generate is reported in its outcome without stopping the rest of the batch.
+/// Two configurations for the same port are rejected, as the second would
+/// overwrite the first.
+///
+/// Only the per-port modules are generated. The linker script and verifying
+/// key belong to a single build, and are generated when building Loadstone
+/// with that configuration.
+pub fn generate_batch<P: AsRef<Path>, Q: AsRef<Path>>(
+    loadstone_path: P,
+    configurations_path: Q,
+    force: bool,
+) -> Result<Vec<BatchOutcome>> {
+    let mut paths = fs::read_dir(configurations_path)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.retain(|path| path.extension().map_or(false, |extension| extension == "ron"));
+    paths.sort();
+
+    let mut generated_ports = Vec::new();
+    let outcomes = paths
+        .into_iter()
+        .map(|path| {
+            let result = generate_file(&loadstone_path, &path, force, &mut generated_ports);
+            BatchOutcome { path, result }
+        })
+        .collect();
+    Ok(outcomes)
+}
+
+fn generate_file<P: AsRef<Path>>(
+    loadstone_path: P,
+    path: &Path,
+    force: bool,
+    generated_ports: &mut Vec<Port>,
+) -> Result<Port> {
+    let configuration: Configuration = ron::from_str(&fs::read_to_string(path)?)?;
+    let port = configuration.port;
+    if generated_ports.contains(&port) {
+        return Err(anyhow!("Port {} was already generated by an earlier configuration.", port));
+    }
+    generated_ports.push(port);
+
+    // Generation panics on invalid configurations, as it's normally run from the build
+    // script. Catch them here so a single bad configuration doesn't end the batch.
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        generate_port_modules(&loadstone_path, &configuration, force)
+    }))
+    .map_err(|payload| anyhow!("Generation failed: {}", panic_message(&*payload)))??;
+    Ok(port)
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown error")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        codegen::AUTOGENERATED_MARKER,
+        features::BootMetrics,
+        security::{SecurityConfiguration, SecurityMode},
+    };
+
+    fn configuration(port: Port) -> Configuration {
+        let mut configuration = Configuration { port, ..Default::default() };
+        configuration.security_configuration =
+            SecurityConfiguration { security_mode: SecurityMode::Crc, ..Default::default() };
+        configuration.memory_configuration.external_flash = None;
+        configuration.feature_configuration.boot_metrics = BootMetrics::Disabled;
+        configuration
+    }
+
+    #[test]
+    fn batch_generates_every_port_in_the_folder() {
+        let root = std::env::temp_dir().join("loadstone_codegen_batch");
+        fs::remove_dir_all(&root).ok();
+        let (loadstone_path, configurations_path) = (root.join("loadstone"), root.join("configs"));
+        fs::create_dir_all(&configurations_path).unwrap();
+        for port in [Port::Stm32F412, Port::Wgm160P].iter() {
+            let ron = ron::to_string(&configuration(*port)).unwrap();
+            fs::write(configurations_path.join(format!("{}.ron", port)), ron).unwrap();
+        }
+        fs::write(configurations_path.join("notes.txt"), "Not a configuration.").unwrap();
+
+        let outcomes = generate_batch(&loadstone_path, &configurations_path, false).unwrap();
+        assert_eq!(2, outcomes.len());
+        assert!(outcomes.iter().all(|outcome| outcome.result.is_ok()));
+
+        let generated = |port: &str, file: &str| {
+            let folder = loadstone_path.join(format!("src/ports/{}/autogenerated", port));
+            fs::read_to_string(folder.join(file)).unwrap()
+        };
+        assert!(generated("stm32f412", "mod.rs").contains(AUTOGENERATED_MARKER));
+        assert!(generated("wgm160p", "mod.rs").contains(AUTOGENERATED_MARKER));
+        assert_ne!(generated("stm32f412", "devices.rs"), generated("wgm160p", "devices.rs"));
+        fs::remove_dir_all(&root).ok();
+    }
+}
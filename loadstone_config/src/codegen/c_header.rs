@@ -0,0 +1,165 @@
+use std::{fmt::Write as _, fs::OpenOptions, io::Write, path::Path};
+
+use crate::{memory::Bank, Configuration};
+use anyhow::{anyhow, Result};
+
+/// Identifies the flash chip a bank lives in, through `LOADSTONE_BANK_<n>_FLASH`.
+const FLASH_CHIPS: [(&str, u32); 2] = [("MCU", 0), ("EXTERNAL", 1)];
+
+/// Generates `loadstone_memory_map.h`, describing the same memory map as the Rust
+/// `memory_map.rs` module to applications written in C.
+pub fn generate_c_header<P: AsRef<Path>>(configuration: &Configuration, path: P) -> Result<()> {
+    let mut file = OpenOptions::new().write(true).create(true).truncate(true).open(path)?;
+    file.write_all(c_header(configuration)?.as_bytes())?;
+    Ok(())
+}
+
+/// Contents of `loadstone_memory_map.h`: the bootloader region, the boot bank, every
+/// bank with the same 1-based index Loadstone reports it by, and the location of each
+/// metadata region in MCU flash. Metadata regions that aren't configured are left
+/// undefined, so applications can test for them with `#ifdef`.
+pub fn c_header(configuration: &Configuration) -> Result<String> {
+    let memory_configuration = &configuration.memory_configuration;
+    let internal = &memory_configuration.internal_memory_map;
+    let external = &memory_configuration.external_memory_map;
+    let boot_bank = internal
+        .bootable_index
+        .and_then(|index| internal.banks.get(index))
+        .ok_or(anyhow!("Bootable bank is undefined in configuration file."))?;
+    let golden_banks = memory_configuration.golden_banks();
+
+    let banks =
+        internal.banks.iter().map(|bank| (FLASH_CHIPS[0], bank.start_address, bank)).chain(
+            external.banks.iter().map(|b| (FLASH_CHIPS[1], external.absolute_address(b), b)),
+        );
+
+    let mut header = String::new();
+    writeln!(header, "/* This file is autogenerated! Don't modify it manually, as it will be")?;
+    writeln!(header, " * overwritten in the next project build. Generation logic for this file")?;
+    writeln!(header, " * is defined in `loadstone_config/src/codegen/c_header.rs` */")?;
+    writeln!(header, "#ifndef LOADSTONE_MEMORY_MAP_H")?;
+    writeln!(header, "#define LOADSTONE_MEMORY_MAP_H")?;
+    writeln!(header)?;
+    for (name, value) in FLASH_CHIPS.iter() {
+        define(&mut header, &format!("LOADSTONE_FLASH_{}", name), *value)?;
+    }
+    writeln!(header)?;
+    define(&mut header, "LOADSTONE_BOOTLOADER_ORIGIN", internal.bootloader_location)?;
+    define(&mut header, "LOADSTONE_BOOTLOADER_SIZE", internal.bootloader_length_kb * 1024)?;
+    define(&mut header, "LOADSTONE_BOOT_BANK_ORIGIN", boot_bank.start_address)?;
+    define(&mut header, "LOADSTONE_BOOT_BANK_SIZE", size(boot_bank))?;
+    writeln!(header)?;
+    define(&mut header, "LOADSTONE_BANK_COUNT", banks.clone().count() as u32)?;
+    for (i, ((chip, _), location, bank)) in banks.enumerate() {
+        let prefix = format!("LOADSTONE_BANK_{}", i + 1);
+        define(&mut header, &format!("{}_ORIGIN", prefix), location)?;
+        define(&mut header, &format!("{}_SIZE", prefix), size(bank))?;
+        writeln!(header, "#define {}_FLASH LOADSTONE_FLASH_{}", prefix, chip)?;
+        define(&mut header, &format!("{}_GOLDEN", prefix), golden_banks.contains(&i) as u32)?;
+    }
+
+    let metadata = [
+        ("LOADSTONE_BOOT_COUNTER_ORIGIN", internal.boot_counter_location),
+        ("LOADSTONE_VERIFICATION_CACHE_ORIGIN", internal.verification_cache_location),
+        (
+            "LOADSTONE_STAGING_ROTATION_STATE_ORIGIN",
+            internal.staging_rotation.as_ref().map(|r| r.state_location),
+        ),
+    ];
+    if metadata.iter().any(|(_, location)| location.is_some()) {
+        writeln!(header)?;
+    }
+    for (name, location) in metadata.iter() {
+        if let Some(location) = location {
+            define(&mut header, name, *location)?;
+        }
+    }
+    writeln!(header)?;
+    writeln!(header, "#endif /* LOADSTONE_MEMORY_MAP_H */")?;
+    Ok(header)
+}
+
+fn size(bank: &Bank) -> u32 { bank.size_kb * 1024 }
+
+/// Writes an unsigned hexadecimal `#define`, so addresses read the same as in the
+/// configuration.
+fn define(header: &mut String, name: &str, value: u32) -> Result<()> {
+    Ok(writeln!(header, "#define {} 0x{:08X}u", name, value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{memory::StagingRotation, port::Port};
+
+    fn defines(header: &str) -> Vec<(&str, &str)> {
+        header
+            .lines()
+            .filter_map(|line| line.strip_prefix("#define "))
+            .filter_map(|line| {
+                let mut words = line.split_whitespace();
+                Some((words.next()?, words.next()?))
+            })
+            .collect()
+    }
+
+    fn value(header: &str, name: &str) -> Option<u32> {
+        let (_, value) = defines(header).into_iter().find(|(n, _)| *n == name)?;
+        u32::from_str_radix(value.strip_prefix("0x")?.strip_suffix('u')?, 16).ok()
+    }
+
+    #[test]
+    fn emitted_defines_match_the_configured_addresses() {
+        let mut configuration = Configuration::preset(Port::Stm32F412);
+        configuration.memory_configuration.external_memory_map.base_address = 0x9000_0000;
+        configuration.memory_configuration.internal_memory_map.boot_counter_location =
+            Some(0x0801_0000);
+        let header = c_header(&configuration).unwrap();
+
+        let memory_configuration = &configuration.memory_configuration;
+        let internal = &memory_configuration.internal_memory_map;
+        let boot_bank = &internal.banks[internal.bootable_index.unwrap()];
+        assert_eq!(Some(boot_bank.start_address), value(&header, "LOADSTONE_BOOT_BANK_ORIGIN"));
+        assert_eq!(Some(boot_bank.size_kb * 1024), value(&header, "LOADSTONE_BOOT_BANK_SIZE"));
+        assert_eq!(
+            Some(internal.bootloader_location),
+            value(&header, "LOADSTONE_BOOTLOADER_ORIGIN")
+        );
+
+        let external = &memory_configuration.external_memory_map;
+        let banks: Vec<_> = internal
+            .banks
+            .iter()
+            .map(|b| b.start_address)
+            .chain(external.banks.iter().map(|b| external.absolute_address(b)))
+            .collect();
+        assert_eq!(Some(banks.len() as u32), value(&header, "LOADSTONE_BANK_COUNT"));
+        for (i, origin) in banks.iter().enumerate() {
+            let name = format!("LOADSTONE_BANK_{}_ORIGIN", i + 1);
+            assert_eq!(Some(*origin), value(&header, &name), "{}", name);
+        }
+        // Bank 2 is the golden MCU bank, bank 3 the update bank in external flash.
+        assert_eq!(Some(1), value(&header, "LOADSTONE_BANK_2_GOLDEN"));
+        assert_eq!(Some(0), value(&header, "LOADSTONE_BANK_3_GOLDEN"));
+        assert!(defines(&header).contains(&("LOADSTONE_BANK_3_FLASH", "LOADSTONE_FLASH_EXTERNAL")));
+
+        assert_eq!(Some(0x0801_0000), value(&header, "LOADSTONE_BOOT_COUNTER_ORIGIN"));
+        assert_eq!(None, value(&header, "LOADSTONE_VERIFICATION_CACHE_ORIGIN"));
+    }
+
+    #[test]
+    fn staging_rotation_state_is_emitted_when_configured() {
+        let mut configuration = Configuration::preset(Port::Stm32F412);
+        configuration.memory_configuration.internal_memory_map.staging_rotation =
+            Some(StagingRotation { indices: Default::default(), state_location: 0x0800_C000 });
+        let header = c_header(&configuration).unwrap();
+        assert_eq!(Some(0x0800_C000), value(&header, "LOADSTONE_STAGING_ROTATION_STATE_ORIGIN"));
+    }
+
+    #[test]
+    fn boot_bank_is_required() {
+        let mut configuration = Configuration::preset(Port::Stm32F412);
+        configuration.memory_configuration.internal_memory_map.bootable_index = None;
+        assert!(c_header(&configuration).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+//! Cross validation of a configuration against the cargo feature flags Loadstone is
+//! being built with. Mismatches are collected and reported all at once, rather than
+//! surfacing one by one as panics halfway through code generation.
+
+use std::fmt::Display;
+
+use enum_iterator::IntoEnumIterator;
+
+use crate::{
+    features::{BootMetrics, Serial},
+    port::Port,
+    security::SecurityMode,
+    Configuration,
+};
+
+/// Inconsistency between a configuration and the supplied feature flags.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mismatch {
+    /// A feature flag required by the configuration wasn't supplied.
+    MissingFlag(&'static str),
+    /// The feature flag for a port other than the configured one was supplied.
+    ForeignPortFlag(Port),
+    /// The `ecdsa-verify` flag was supplied, but the configuration uses CRC verification.
+    UnexpectedEcdsaFlag,
+    /// The `cli` flag was supplied, but the configuration excludes the CLI.
+    UnexpectedCliFlag,
+    /// Serial communication is enabled for a port that doesn't support it.
+    SerialUnsupported(Port),
+    /// Boot timing metrics are enabled for a port that doesn't support them.
+    TimingUnsupported(Port),
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mismatch::MissingFlag(flag) => {
+                write!(f, "The configuration requires the `{}` feature flag.", flag)
+            }
+            Mismatch::ForeignPortFlag(port) => write!(
+                f,
+                "The `{}` feature flag was supplied, but the configuration targets another port.",
+                port
+            ),
+            Mismatch::UnexpectedEcdsaFlag => write!(
+                f,
+                "The `ecdsa-verify` feature flag was supplied, but the configuration \
+                 doesn't specify ECDSA security mode."
+            ),
+            Mismatch::UnexpectedCliFlag => write!(
+                f,
+                "The `cli` feature flag was supplied, but the configuration excludes the CLI. \
+                 It's a default flag, so build with `--no-default-features`."
+            ),
+            Mismatch::SerialUnsupported(port) => {
+                write!(f, "Serial features are enabled, but `{}` doesn't support them.", port)
+            }
+            Mismatch::TimingUnsupported(port) => {
+                write!(f, "Boot timing metrics are enabled, but `{}` doesn't support them.", port)
+            }
+        }
+    }
+}
+
+/// Checks a configuration against the supplied feature flags, returning every mismatch
+/// found. Flags may be spelled with dashes (`ecdsa-verify`) or in the underscored form
+/// cargo exposes to build scripts (`ecdsa_verify`).
+pub fn check_feature_flags<S: AsRef<str>>(
+    configuration: &Configuration,
+    supplied_flags: &[S],
+) -> Result<(), Vec<Mismatch>> {
+    let supplied: Vec<String> =
+        supplied_flags.iter().map(|f| f.as_ref().to_lowercase().replace("_", "-")).collect();
+    let supplied = |flag: &str| supplied.iter().any(|f| f == flag);
+    let port = configuration.port;
+
+    let mut mismatches: Vec<Mismatch> = configuration
+        .required_feature_flags()
+        .filter(|flag| !supplied(flag))
+        .map(Mismatch::MissingFlag)
+        .collect();
+
+    mismatches.extend(
+        Port::into_enum_iter()
+            .filter(|p| *p != port && supplied(&p.to_string()))
+            .map(Mismatch::ForeignPortFlag),
+    );
+
+    if configuration.security_configuration.security_mode != SecurityMode::P256ECDSA
+        && supplied("ecdsa-verify")
+    {
+        mismatches.push(Mismatch::UnexpectedEcdsaFlag);
+    }
+
+    if configuration.feature_configuration.exclude_cli && supplied("cli") {
+        mismatches.push(Mismatch::UnexpectedCliFlag);
+    }
+
+    if configuration.feature_configuration.serial.enabled() && !Serial::supported(&port) {
+        mismatches.push(Mismatch::SerialUnsupported(port));
+    }
+
+    if matches!(configuration.feature_configuration.boot_metrics, BootMetrics::Enabled {
+        timing: true
+    }) && !BootMetrics::timing_supported(&port)
+    {
+        mismatches.push(Mismatch::TimingUnsupported(port));
+    }
+
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(mismatches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{features::SerialLogLevel, pins};
+
+    fn crc_configuration(port: Port) -> Configuration {
+        let mut configuration = Configuration::default();
+        configuration.port = port;
+        configuration.security_configuration.security_mode = SecurityMode::Crc;
+        configuration.feature_configuration.serial = Serial::Disabled;
+        configuration.feature_configuration.boot_metrics = BootMetrics::Disabled;
+        configuration.feature_configuration.serial_log_level = SerialLogLevel::Off;
+        configuration
+    }
+
+    #[test]
+    fn matching_flags_pass() {
+        let configuration = crc_configuration(Port::Stm32F412);
+        assert_eq!(Ok(()), check_feature_flags(&configuration, &["stm32f412", "defmt_default"]));
+    }
+
+    #[test]
+    fn missing_flags_are_reported() {
+        let mut configuration = crc_configuration(Port::Stm32F412);
+        configuration.security_configuration.security_mode = SecurityMode::P256ECDSA;
+        assert_eq!(
+            Err(vec![Mismatch::MissingFlag("stm32f412"), Mismatch::MissingFlag("ecdsa-verify")]),
+            check_feature_flags::<&str>(&configuration, &[])
+        );
+    }
+
+    #[test]
+    fn flags_for_other_ports_are_reported() {
+        let configuration = crc_configuration(Port::Stm32F412);
+        assert_eq!(
+            Err(vec![Mismatch::ForeignPortFlag(Port::Wgm160P)]),
+            check_feature_flags(&configuration, &["stm32f412", "wgm160p"])
+        );
+    }
+
+    #[test]
+    fn ecdsa_flag_without_ecdsa_mode_is_reported() {
+        let configuration = crc_configuration(Port::Stm32F412);
+        assert_eq!(
+            Err(vec![Mismatch::UnexpectedEcdsaFlag]),
+            check_feature_flags(&configuration, &["STM32F412", "ECDSA_VERIFY"])
+        );
+    }
+
+    #[test]
+    fn cli_flag_is_reported_when_the_configuration_excludes_the_cli() {
+        let mut configuration = crc_configuration(Port::Stm32F412);
+        assert_eq!(Ok(()), check_feature_flags(&configuration, &["stm32f412", "cli"]));
+
+        configuration.feature_configuration.exclude_cli = true;
+        assert_eq!(
+            Err(vec![Mismatch::UnexpectedCliFlag]),
+            check_feature_flags(&configuration, &["stm32f412", "cli"])
+        );
+        assert_eq!(Ok(()), check_feature_flags(&configuration, &["stm32f412"]));
+    }
+
+    #[test]
+    fn unsupported_port_features_are_reported() {
+        let mut configuration = crc_configuration(Port::Wgm160P);
+        configuration.feature_configuration.serial = Serial::Enabled {
+            recovery_enabled: false,
+            tx_pin: pins::serial_tx(&Port::Stm32F412).next().unwrap(),
+            rx_pin: pins::serial_rx(&Port::Stm32F412).next().unwrap(),
+        };
+        configuration.feature_configuration.boot_metrics = BootMetrics::Enabled { timing: true };
+        assert_eq!(
+            Err(vec![
+                Mismatch::SerialUnsupported(Port::Wgm160P),
+                Mismatch::TimingUnsupported(Port::Wgm160P),
+            ]),
+            check_feature_flags(&configuration, &["wgm160p"])
+        );
+    }
+
+    #[test]
+    fn all_mismatches_are_reported_at_once() {
+        let mut configuration = crc_configuration(Port::Wgm160P);
+        configuration.feature_configuration.boot_metrics = BootMetrics::Enabled { timing: true };
+        let mismatches =
+            check_feature_flags(&configuration, &["stm32f412", "ecdsa-verify"]).unwrap_err();
+        assert_eq!(4, mismatches.len());
+    }
+}
@@ -19,6 +19,8 @@ pub fn generate<P: AsRef<Path>>(
         crate::port::Port::Stm32F412 => {
             generate_serial_stm32(configuration, &mut code)?;
             generate_flash_stm32(configuration, &mut code)?;
+            generate_status_led_stm32(configuration, &mut code)?;
+            generate_recovery_pin_stm32(configuration, &mut code)?;
         }
         crate::port::Port::Wgm160P => {}
     }
@@ -36,11 +38,15 @@ fn generate_flash_stm32(
         code.append_all(quote!{
             use blue_hal::hal::time;
             use super::pin_configuration::*;
-            pub fn construct_flash(qspi_pins: QspiPins, qspi: stm32pac::QUADSPI) -> Option<ExternalFlash> {
+            pub fn construct_flash(
+                qspi_pins: QspiPins,
+                qspi: stm32pac::QUADSPI,
+            ) -> Result<Option<ExternalFlash>, crate::error::Error> {
                 let qspi_config = qspi::Config::<mode::Single>::default().with_flash_size(24).unwrap();
                 let qspi = Qspi::from_config(qspi, qspi_pins, qspi_config).unwrap();
-                let external_flash = ExternalFlash::with_timeout(qspi, time::Milliseconds(5000)).unwrap();
-                Some(external_flash)
+                ExternalFlash::with_timeout(qspi, time::Milliseconds(5000))
+                    .map(Some)
+                    .map_err(Into::into)
             }
         })
     } else {
@@ -48,18 +54,77 @@ fn generate_flash_stm32(
             use blue_hal::hal::time;
             use super::pin_configuration::*;
             #[allow(unused)]
-            pub fn construct_flash(qspi_pins: QspiPins, qspi: stm32pac::QUADSPI) -> Option<ExternalFlash> { None }
+            pub fn construct_flash(
+                qspi_pins: QspiPins,
+                qspi: stm32pac::QUADSPI,
+            ) -> Result<Option<ExternalFlash>, crate::error::Error> {
+                Ok(None)
+            }
         })
     }
     Ok(())
 }
 
+fn generate_status_led_stm32(
+    configuration: &Configuration,
+    code: &mut quote::__private::TokenStream,
+) -> Result<()> {
+    if configuration.feature_configuration.status_led.enabled() {
+        code.append_all(quote! {
+            use super::pin_configuration::StatusLedPin;
+            use blue_hal::drivers::led::{LogicLevel, MonochromaticLed};
+            pub type Led = MonochromaticLed<StatusLedPin>;
+            pub fn construct_status_led(pin: StatusLedPin) -> Option<Led> {
+                Some(MonochromaticLed::new(pin, LogicLevel::Direct))
+            }
+        });
+    } else {
+        code.append_all(quote! {
+            use super::pin_configuration::StatusLedPin;
+            pub type Led = crate::devices::status_led::NullLed;
+            #[allow(unused)]
+            pub fn construct_status_led(_pin: StatusLedPin) -> Option<Led> { None }
+        });
+    }
+    Ok(())
+}
+
+fn generate_recovery_pin_stm32(
+    configuration: &Configuration,
+    code: &mut quote::__private::TokenStream,
+) -> Result<()> {
+    if configuration.feature_configuration.recovery_pin.enabled() {
+        code.append_all(quote! {
+            use super::pin_configuration::RecoveryInputPin;
+            pub type RecoveryInput = RecoveryInputPin;
+            pub fn construct_recovery_pin(pin: RecoveryInputPin) -> Option<RecoveryInput> {
+                Some(pin)
+            }
+        });
+    } else {
+        code.append_all(quote! {
+            use super::pin_configuration::RecoveryInputPin;
+            pub type RecoveryInput = crate::devices::recovery_pin::NullPin;
+            #[allow(unused)]
+            pub fn construct_recovery_pin(_pin: RecoveryInputPin) -> Option<RecoveryInput> { None }
+        });
+    }
+    Ok(())
+}
+
 fn generate_serial_stm32(
     configuration: &Configuration,
     code: &mut quote::__private::TokenStream,
 ) -> Result<()> {
     if let Serial::Enabled { tx_pin, .. } = &configuration.feature_configuration.serial {
         let peripheral = format_ident!("{}", tx_pin.peripheral.to_lowercase());
+        let peripheral_type = format_ident!("{}", tx_pin.peripheral.to_uppercase());
+        // USART2 sits on the APB1 bus, while USART1 and USART6 sit on APB2.
+        let peripheral_clock = if tx_pin.peripheral.to_uppercase() == "USART2" {
+            format_ident!("pclk1")
+        } else {
+            format_ident!("pclk2")
+        };
         code.append_all(quote! {
             use super::pin_configuration::{UsartPins, Serial};
             use blue_hal::stm32pac;
@@ -76,7 +141,25 @@ fn generate_serial_stm32(
                 let serial_config = serial::config::Config::default().baudrate(time::Bps(115200));
                 Some(#peripheral.constrain(serial_pins, serial_config, clocks).unwrap())
             }
+
+            pub fn baud_control(clocks: &Clocks) -> Option<crate::devices::baud::BaudControl> {
+                Some(crate::devices::baud::BaudControl {
+                    clock_hz: clocks.#peripheral_clock().0,
+                    write_divisor,
+                })
+            }
+
+            fn write_divisor(divisor: u16) {
+                // NOTE(Safety): Only the baud rate register is written, and the serial
+                // driver keeps no state derived from it.
+                unsafe { (*stm32pac::#peripheral_type::ptr()).brr.write(|w| w.bits(divisor as u32)) }
+            }
         });
+        if configuration.feature_configuration.serial_interrupt_rx {
+            generate_serial_interrupt_rx_stm32(&tx_pin.peripheral, code);
+        } else {
+            generate_serial_polling_rx(code);
+        }
     } else {
         code.append_all(quote! {
             use super::pin_configuration::{UsartPins, Serial};
@@ -92,7 +175,74 @@ fn generate_serial_stm32(
             ) -> Option<Serial> {
                 None
             }
+
+            #[allow(unused)]
+            pub fn baud_control(_clocks: &Clocks) -> Option<crate::devices::baud::BaudControl> {
+                None
+            }
         });
+        generate_serial_polling_rx(code);
     }
     Ok(())
 }
+
+/// Serial used by the bootloader when bytes are read by polling the USART.
+fn generate_serial_polling_rx(code: &mut quote::__private::TokenStream) {
+    code.append_all(quote! {
+        pub type BootSerial = Serial;
+        pub fn buffer_serial(serial: Option<Serial>) -> Option<BootSerial> { serial }
+    });
+}
+
+/// Serial used by the bootloader when received bytes are pushed into a ring buffer by the
+/// USART interrupt, which `buffer_serial` enables and dropping the serial disables.
+fn generate_serial_interrupt_rx_stm32(peripheral: &str, code: &mut quote::__private::TokenStream) {
+    let peripheral_type = format_ident!("{}", peripheral.to_uppercase());
+    code.append_all(quote! {
+        use crate::devices::serial_rx::{BufferedSerial, RxRing};
+        use blue_hal::drivers::stm32f4::systick::SysTick;
+        use blue_hal::stm32pac::interrupt;
+
+        /// Bytes buffered between the USART interrupt and the bootloader. At 115200 baud
+        /// this covers over 80ms without draining, longer than an MCU flash sector erase.
+        const RX_BUFFER_SIZE: usize = 1024;
+        static SERIAL_RX: RxRing<RX_BUFFER_SIZE> = RxRing::new();
+
+        pub type BootSerial = BufferedSerial<Serial, SysTick, RX_BUFFER_SIZE>;
+
+        pub fn buffer_serial(serial: Option<Serial>) -> Option<BootSerial> {
+            let serial = serial?;
+            // NOTE(Safety): Only the reception interrupt enable bit is set, and the handler
+            // below is the only code reading the data register from then on.
+            unsafe {
+                (*stm32pac::#peripheral_type::ptr()).cr1.modify(|_, w| w.rxneie().set_bit());
+                cortex_m::peripheral::NVIC::unmask(stm32pac::Interrupt::#peripheral_type);
+            }
+            Some(BufferedSerial::new(serial, &SERIAL_RX, release_serial_interrupt))
+        }
+
+        /// Undoes `buffer_serial` once the serial is dropped before the jump, so the
+        /// image doesn't start with an unmasked interrupt it has no handler for.
+        fn release_serial_interrupt() {
+            cortex_m::peripheral::NVIC::mask(stm32pac::Interrupt::#peripheral_type);
+            // NOTE(Safety): Only the reception interrupt enable bit is cleared.
+            unsafe {
+                (*stm32pac::#peripheral_type::ptr()).cr1.modify(|_, w| w.rxneie().clear_bit());
+            }
+        }
+
+        #[interrupt]
+        fn #peripheral_type() {
+            // NOTE(Safety): Status and data registers are read only, which clears the
+            // interrupt and any overrun flag as a side effect.
+            let usart = unsafe { &*stm32pac::#peripheral_type::ptr() };
+            let status = usart.sr.read();
+            if status.ore().bit_is_set() {
+                SERIAL_RX.flag_overrun();
+            }
+            if status.rxne().bit_is_set() || status.ore().bit_is_set() {
+                SERIAL_RX.push(usart.dr.read().bits() as u8);
+            }
+        }
+    });
+}
@@ -1,8 +1,21 @@
-use std::{fs::OpenOptions, io::Write};
+use std::{fs::OpenOptions, io::Write, path::Path};
 
-use crate::{port::LinkerScriptConstants, Configuration};
+use crate::{
+    memory::RamReservation,
+    port::{LinkerArea, LinkerScriptConstants},
+    Configuration,
+};
 use anyhow::{anyhow, Result};
 
+/// Flash reserved at the end of the bootable bank for the image trailer (golden string,
+/// magic string and CRC or signature), which the application binary must not overlap.
+const IMAGE_TRAILER_RESERVATION_KB: u32 = 1;
+
+/// RAM reserved at the start of RAM for the application's vector table, when it is
+/// copied there before booting. A power of two no smaller than the vector table, so
+/// the table's start satisfies the `VTOR` alignment requirements.
+pub const RAM_VECTOR_TABLE_RESERVATION_KB: u32 = 1;
+
 /// Generates the linker script `memory.x`, which describes the amount and location
 /// of flash and RAM memory available to a particular Loadstone instance.
 pub fn generate_linker_script(configuration: &Configuration) -> Result<()> {
@@ -17,21 +30,134 @@ pub fn generate_linker_script(configuration: &Configuration) -> Result<()> {
     if std::env::var("CARGO_FEATURE_RELOCATE_TO_BOOTABLE_BANK").is_ok() {
         relocate_to_bootable_bank(&mut constants, configuration)?;
     }
+    reserve_ram_vector_table(&mut constants.ram, configuration);
+
+    let ram_reservation = configuration.memory_configuration.ram_reservation.as_ref();
+    file.write_all(memory_x(&constants.flash, &constants.ram, ram_reservation)?.as_bytes())?;
+    Ok(())
+}
+
+/// Checks that every MCU bank lies within the flash region of the port's linker script,
+/// so a bank placed in flash the linker doesn't know about fails generation instead of
+/// at runtime. The region is taken before any relocation to the bootable bank, as
+/// relocation only narrows where the demo application's own code goes. External banks
+/// live outside the MCU address space, and aren't checked.
+pub fn check_banks_within_flash(configuration: &Configuration) -> Result<()> {
+    let flash = configuration
+        .port
+        .linker_script_constants()
+        .ok_or(anyhow!("Current board doesn't have linker script constants defined."))?
+        .flash;
+    let (flash_start, flash_end) = (flash.origin as u64, flash.origin as u64 + flash.size as u64);
+    let memory_map = &configuration.memory_configuration.internal_memory_map;
+    for (index, bank) in memory_map.banks.iter().enumerate() {
+        let bank_start = bank.start_address as u64;
+        let bank_end = bank_start + bank.size_kb as u64 * 1024;
+        if bank_start < flash_start || bank_end > flash_end {
+            return Err(anyhow!(
+                "MCU bank {} ({:#010x} - {:#010x}) lies outside the linker script flash \
+                region ({:#010x} - {:#010x}).",
+                index,
+                bank_start,
+                bank_end,
+                flash_start,
+                flash_end
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Generates a `memory.x` for an application booted by Loadstone, placing its flash
+/// in the bootable bank so it can't overwrite the bootloader or any other bank.
+pub fn generate_application_linker_script<P: AsRef<Path>>(
+    configuration: &Configuration,
+    path: P,
+) -> Result<()> {
+    let mut file = OpenOptions::new().write(true).create(true).truncate(true).open(path)?;
+    file.write_all(application_linker_script(configuration)?.as_bytes())?;
+    Ok(())
+}
+
+/// Contents of the application-facing `memory.x`. Flash spans the bootable bank minus
+/// the space reserved for the image trailer, and RAM spans the port's whole RAM minus
+/// the vector table reservation, if any. The stack and heap, if reserved, are carved
+/// out of the top of RAM.
+pub fn application_linker_script(configuration: &Configuration) -> Result<String> {
+    let mut constants = configuration
+        .port
+        .linker_script_constants()
+        .ok_or(anyhow!("Current board doesn't have linker script constants defined."))?;
+    reserve_ram_vector_table(&mut constants.ram, configuration);
+    let memory_map = &configuration.memory_configuration.internal_memory_map;
+    let bootable_bank = memory_map
+        .bootable_index
+        .and_then(|index| memory_map.banks.get(index))
+        .ok_or(anyhow!("Bootable bank is undefined in configuration file."))?;
+    let length_kb = bootable_bank
+        .size_kb
+        .checked_sub(IMAGE_TRAILER_RESERVATION_KB)
+        .filter(|length| *length > 0)
+        .ok_or(anyhow!("Bootable bank is too small to hold an application."))?;
+
+    let flash = LinkerArea { origin: bootable_bank.start_address, size: length_kb as usize * 1024 };
+    let ram_reservation = configuration.memory_configuration.ram_reservation.as_ref();
+    memory_x(&flash, &constants.ram, ram_reservation)
+}
+
+/// Describes the flash and RAM regions. With a RAM reservation, the RAM region shrinks
+/// to what's left for static variables, and the reserved stack and heap follow it: the
+/// heap is described by `_heap_start` and `_heap_size`, and the stack by `_stack_size`
+/// and `_stack_start` (the top of RAM, where `cortex-m-rt` starts the stack).
+fn memory_x(
+    flash: &LinkerArea,
+    ram: &LinkerArea,
+    ram_reservation: Option<&RamReservation>,
+) -> Result<String> {
+    let ram_kb = (ram.size / 1024) as u32;
+    let reserved_kb = ram_reservation.map_or(0, RamReservation::total_kb);
+    if ram_reservation.map_or(false, |r| !r.fits(ram_kb)) {
+        return Err(anyhow!(
+            "Stack and heap reservation ({}K) leaves no RAM for static variables ({}K available).",
+            reserved_kb,
+            ram_kb
+        ));
+    }
 
-    write!(
-        file,
+    let mut script = format!(
         "MEMORY\n\
          {{\n\
              FLASH : ORIGIN = 0x{:08X}, LENGTH = {}K\n\
              RAM : ORIGIN = 0x{:08X}, LENGTH = {}K\n\
          }}\n",
-        constants.flash.origin,
-        constants.flash.size / 1024,
-        constants.ram.origin,
-        constants.ram.size / 1024,
-    )?;
+        flash.origin,
+        flash.size / 1024,
+        ram.origin,
+        ram_kb - reserved_kb,
+    );
+    if let Some(reservation) = ram_reservation {
+        if let Some(heap_size_kb) = reservation.heap_size_kb {
+            script.push_str(&format!(
+                "_heap_start = ORIGIN(RAM) + LENGTH(RAM);\n_heap_size = {}K;\n",
+                heap_size_kb
+            ));
+        }
+        script.push_str(&format!(
+            "_stack_size = {}K;\n_stack_start = ORIGIN(RAM) + LENGTH(RAM) + {}K;\n",
+            reservation.stack_size_kb, reserved_kb
+        ));
+    }
+    Ok(script)
+}
 
-    Ok(())
+/// Removes the vector table reservation from the start of RAM, if the configuration
+/// copies the application's vector table there.
+fn reserve_ram_vector_table(ram: &mut LinkerArea, configuration: &Configuration) {
+    if configuration.feature_configuration.ram_vector_table {
+        let reservation = RAM_VECTOR_TABLE_RESERVATION_KB as usize * 1024;
+        ram.origin += reservation as u32;
+        ram.size = ram.size.saturating_sub(reservation);
+    }
 }
 
 #[allow(unused)]
@@ -47,3 +173,110 @@ fn relocate_to_bootable_bank(
     constants.flash.origin = bootable_address;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{memory::Bank, port::Port};
+
+    #[test]
+    fn application_linker_script_targets_bootable_bank() {
+        let mut configuration = Configuration::default();
+        configuration.port = Port::Stm32F412;
+        let scratch_bank = Bank { start_address: 0x08010000, size_kb: 64 };
+        let bootable_bank = Bank { start_address: 0x08020000, size_kb: 896 };
+        configuration.memory_configuration.internal_memory_map.banks =
+            vec![scratch_bank, bootable_bank];
+        configuration.memory_configuration.internal_memory_map.bootable_index = Some(1);
+
+        let script = application_linker_script(&configuration).unwrap();
+        assert!(script.contains("FLASH : ORIGIN = 0x08020000, LENGTH = 895K"));
+        assert!(script.contains("RAM : ORIGIN = 0x20000000, LENGTH = 256K"));
+    }
+
+    #[test]
+    fn ram_vector_table_is_reserved_from_application_ram() {
+        let mut configuration = Configuration::default();
+        configuration.port = Port::Stm32F412;
+        configuration.memory_configuration.internal_memory_map.banks =
+            vec![Bank { start_address: 0x08020000, size_kb: 896 }];
+        configuration.memory_configuration.internal_memory_map.bootable_index = Some(0);
+        configuration.feature_configuration.ram_vector_table = true;
+
+        let script = application_linker_script(&configuration).unwrap();
+        assert!(script.contains("RAM : ORIGIN = 0x20000400, LENGTH = 255K"));
+        assert!(
+            configuration.port.vector_table_size().unwrap()
+                <= RAM_VECTOR_TABLE_RESERVATION_KB as usize * 1024
+        );
+    }
+
+    #[test]
+    fn banks_outside_the_linker_flash_region_are_rejected() {
+        let mut configuration = Configuration::default();
+        configuration.port = Port::Stm32F412;
+        let scratch_bank = Bank { start_address: 0x08010000, size_kb: 64 };
+        let bootable_bank = Bank { start_address: 0x08020000, size_kb: 896 };
+        configuration.memory_configuration.internal_memory_map.banks =
+            vec![scratch_bank, bootable_bank];
+        assert!(check_banks_within_flash(&configuration).is_ok());
+
+        // Runs past the end of flash.
+        configuration.memory_configuration.internal_memory_map.banks[1].size_kb = 1024;
+        assert!(check_banks_within_flash(&configuration).is_err());
+
+        // Entirely outside flash, in RAM.
+        configuration.memory_configuration.internal_memory_map.banks[1] =
+            Bank { start_address: 0x20000000, size_kb: 16 };
+        let error = check_banks_within_flash(&configuration).unwrap_err();
+        assert!(error.to_string().starts_with("MCU bank 1 (0x20000000 - 0x20004000)"));
+    }
+
+    fn configuration_with_ram_reservation(reservation: RamReservation) -> Configuration {
+        let mut configuration = Configuration::default();
+        configuration.port = Port::Stm32F412;
+        configuration.memory_configuration.internal_memory_map.banks =
+            vec![Bank { start_address: 0x08020000, size_kb: 896 }];
+        configuration.memory_configuration.internal_memory_map.bootable_index = Some(0);
+        configuration.memory_configuration.ram_reservation = Some(reservation);
+        configuration
+    }
+
+    #[test]
+    fn reserved_stack_and_heap_are_carved_out_of_the_top_of_ram() {
+        let reservation = RamReservation { stack_size_kb: 16, heap_size_kb: Some(32) };
+        let script =
+            application_linker_script(&configuration_with_ram_reservation(reservation)).unwrap();
+        assert!(script.contains("RAM : ORIGIN = 0x20000000, LENGTH = 208K"));
+        assert!(script.contains("_heap_start = ORIGIN(RAM) + LENGTH(RAM);"));
+        assert!(script.contains("_heap_size = 32K;"));
+        assert!(script.contains("_stack_size = 16K;"));
+        assert!(script.contains("_stack_start = ORIGIN(RAM) + LENGTH(RAM) + 48K;"));
+
+        let reservation = RamReservation { stack_size_kb: 8, heap_size_kb: None };
+        let script =
+            application_linker_script(&configuration_with_ram_reservation(reservation)).unwrap();
+        assert!(script.contains("RAM : ORIGIN = 0x20000000, LENGTH = 248K"));
+        assert!(script.contains("_stack_start = ORIGIN(RAM) + LENGTH(RAM) + 8K;"));
+        assert!(!script.contains("_heap"));
+    }
+
+    #[test]
+    fn over_large_stack_is_rejected() {
+        let reservation = RamReservation { stack_size_kb: 256, heap_size_kb: None };
+        let configuration = configuration_with_ram_reservation(reservation);
+        assert!(application_linker_script(&configuration).is_err());
+
+        let reservation = RamReservation { stack_size_kb: 200, heap_size_kb: Some(56) };
+        let configuration = configuration_with_ram_reservation(reservation);
+        assert!(application_linker_script(&configuration).is_err());
+    }
+
+    #[test]
+    fn application_linker_script_requires_bootable_bank() {
+        let mut configuration = Configuration::default();
+        configuration.memory_configuration.internal_memory_map.banks =
+            vec![Bank { start_address: 0x08020000, size_kb: 896 }];
+        assert!(application_linker_script(&configuration).is_err());
+    }
+}
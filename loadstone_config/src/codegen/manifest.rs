@@ -0,0 +1,85 @@
+//! Change detection for the autogenerated modules of a port.
+//!
+//! Every module is generated from a slice of the configuration. The manifest stores
+//! a digest of the slice each module was last generated from, so modules whose slice
+//! didn't change since are left untouched (e.g. editing the greetings only rewrites
+//! the top level module). The manifest lives alongside the modules, and is discarded
+//! along with them when generation is forced.
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Name of the manifest file within the autogenerated folder.
+pub const MANIFEST_FILENAME: &str = "manifest.txt";
+
+/// Digests of the configuration slices the modules of a port were generated from.
+pub struct Manifest {
+    path: PathBuf,
+    digests: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    /// Loads the manifest of an autogenerated folder. A missing or unreadable manifest,
+    /// or one written by another version of the generator, is treated as empty, so
+    /// every module is regenerated.
+    pub fn load<P: AsRef<Path>>(autogenerated_folder_path: P) -> Self {
+        let path = autogenerated_folder_path.as_ref().join(MANIFEST_FILENAME);
+        let contents = fs::read_to_string(&path).unwrap_or_default();
+        let mut lines = contents.lines();
+        let digests = if lines.next() == Some(header().as_str()) {
+            lines
+                .filter_map(|line| line.split_once(' '))
+                .map(|(module, digest)| (module.to_owned(), digest.to_owned()))
+                .collect()
+        } else {
+            BTreeMap::new()
+        };
+        Self { path, digests }
+    }
+
+    /// Generates a module, unless it exists and was last generated from the same inputs.
+    /// Returns whether the module was generated.
+    pub fn generate_if_changed<T, F>(
+        &mut self,
+        module: &str,
+        inputs: &T,
+        generate: F,
+    ) -> Result<bool>
+    where
+        T: Serialize,
+        F: FnOnce() -> Result<()>,
+    {
+        let digest = digest(inputs)?;
+        let exists = self.path.with_file_name(module).exists();
+        if exists && self.digests.get(module) == Some(&digest) {
+            return Ok(false);
+        }
+        generate()?;
+        self.digests.insert(module.to_owned(), digest);
+        Ok(true)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let entries =
+            self.digests.iter().map(|(module, digest)| format!("{} {}\n", module, digest));
+        let contents: String = Some(format!("{}\n", header())).into_iter().chain(entries).collect();
+        fs::write(&self.path, contents)?;
+        Ok(())
+    }
+}
+
+/// First line of the manifest. Output may change between generator versions even for
+/// the same configuration, so a manifest from another version is discarded.
+fn header() -> String { format!("loadstone_config {}", env!("CARGO_PKG_VERSION")) }
+
+/// Hex encoded SHA256 of the serialized inputs.
+fn digest<T: Serialize>(inputs: &T) -> Result<String> {
+    let hash = Sha256::digest(ron::to_string(inputs)?.as_bytes());
+    Ok(hash.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
@@ -1,14 +1,21 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use quote::{format_ident, quote};
-use std::{fs::OpenOptions, io::Write, path::Path};
+use std::{collections::BTreeSet, fs::OpenOptions, io::Write, iter, path::Path};
 
 use crate::{
-    memory::{ExternalMemoryMap, InternalMemoryMap, MemoryConfiguration},
+    memory::{
+        internal_flash, internal_flash_sectors, ExternalMemoryMap, InternalMemoryMap,
+        MemoryConfiguration, SectorRegion,
+    },
     port::{Port, Subfamily},
+    security::SecurityMode,
 };
 
 use super::prettify_file;
 
+/// Index of the first MCU bank. Banks are numbered from there across flash chips.
+const BASE_INDEX: usize = 1;
+
 /// Generates the `memory_map.rs` module, containing a description of the MCU
 /// flash banks and, if applicable, external flash banks for a particular
 /// Loadstone instance.
@@ -19,26 +26,183 @@ pub fn generate<P: AsRef<Path>>(
 ) -> Result<()> {
     let filename = autogenerated_folder_path.as_ref().join("memory_map.rs");
     let mut file = OpenOptions::new().write(true).create(true).truncate(true).open(&filename)?;
-    let base_index = 1usize;
+    let base_index = BASE_INDEX;
     let imports = generate_imports(&memory_configuration, port)?;
-    let mcu_banks = generate_mcu_banks(
-        base_index,
-        &memory_configuration.internal_memory_map,
-        memory_configuration.golden_index,
-    )?;
+    let golden_banks = memory_configuration.golden_banks();
+    let mcu_banks =
+        generate_mcu_banks(base_index, &memory_configuration.internal_memory_map, &golden_banks)?;
     let external_banks = generate_external_banks(
         memory_configuration.internal_memory_map.banks.len() + base_index,
         &memory_configuration.external_memory_map,
-        memory_configuration.golden_index,
+        &golden_banks,
     )?;
 
+    let sectors = internal_flash_sectors(port);
+    if let Some(bank) = memory_configuration
+        .internal_memory_map
+        .banks
+        .iter()
+        .find(|b| !b.is_sector_aligned(&sectors))
+    {
+        panic!(
+            "MCU bank at {:#010x} does not start at a flash sector boundary",
+            bank.start_address
+        );
+    }
+    let mcu_sectors = generate_mcu_sectors(&sectors)?;
+    let external_erase_size = generate_external_erase_size(memory_configuration)?;
+
+    if !memory_configuration.internal_memory_map.boot_counter_placement_valid(&sectors) {
+        panic!("The boot counter must be placed at the start of an otherwise unused flash sector");
+    }
+    let boot_counter = generate_boot_counter(&memory_configuration.internal_memory_map)?;
+
+    if !memory_configuration.internal_memory_map.staging_bank_valid(&golden_banks) {
+        panic!("The staging bank must be an MCU bank that is neither bootable nor golden");
+    }
+    let staging_bank =
+        generate_staging_bank(base_index, &memory_configuration.internal_memory_map)?;
+
+    if !memory_configuration.internal_memory_map.staging_rotation_valid(&golden_banks, &sectors) {
+        panic!(
+            "Staging rotation banks must be regular MCU banks other than the staging bank, \
+            with their state at the start of an otherwise unused flash sector"
+        );
+    }
+    let staging_rotation =
+        generate_staging_rotation(base_index, &memory_configuration.internal_memory_map)?;
+
+    if !memory_configuration.internal_memory_map.backup_bank_valid(&golden_banks) {
+        panic!(
+            "The backup bank must be an MCU bank that is neither bootable, golden nor used \
+            to stage updates"
+        );
+    }
+    let backup_bank = generate_backup_bank(base_index, &memory_configuration.internal_memory_map)?;
+
+    if !memory_configuration.internal_memory_map.verification_cache_placement_valid(&sectors) {
+        panic!(
+            "The verification cache must be placed at the start of an otherwise unused flash sector"
+        );
+    }
+    let verification_cache =
+        generate_verification_cache(&memory_configuration.internal_memory_map)?;
+    let bootloader_region = generate_bootloader_region(&memory_configuration.internal_memory_map)?;
+
     file.write_all(imports.as_bytes())?;
     file.write_all(mcu_banks.as_bytes())?;
     file.write_all(external_banks.as_bytes())?;
+    file.write_all(mcu_sectors.as_bytes())?;
+    file.write_all(external_erase_size.as_bytes())?;
+    file.write_all(boot_counter.as_bytes())?;
+    file.write_all(staging_bank.as_bytes())?;
+    file.write_all(staging_rotation.as_bytes())?;
+    file.write_all(backup_bank.as_bytes())?;
+    file.write_all(verification_cache.as_bytes())?;
+    file.write_all(bootloader_region.as_bytes())?;
     prettify_file(filename).ok();
     Ok(())
 }
 
+/// Checks that the verification cache isn't enabled alongside signed images, whose
+/// signatures its unkeyed tag would let a forged image skip. The configuration flags
+/// the same (see `RequiredConfigurationStep::VerificationCacheUnsigned`), but this
+/// catches .ron files that were never opened in the GUI.
+pub fn check_verification_cache_allowed(
+    memory_configuration: &MemoryConfiguration,
+    security_mode: SecurityMode,
+) -> Result<()> {
+    if memory_configuration.internal_memory_map.verification_cache_allowed(security_mode) {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "The verification cache can't be enabled with ECDSA image verification, as its \
+            tag isn't keyed. Disable it, or switch to CRC32 mode."
+        ))
+    }
+}
+
+/// Checks that the bank indices emitted in `memory_map.rs` fit the `u8` banks are
+/// identified by on the device, and that the bootable and golden roles, if any, refer to
+/// existing banks without overlapping. Indices count up from the base index across flash
+/// chips, so they are unique and contiguous as long as the last one fits; that bound is
+/// all that is checked of them. The device asserts the same on boot (see
+/// `Bootloader::verify_bank_correctness`), so a malformed memory map fails generation
+/// instead of panicking the device.
+pub fn check_bank_indices(memory_configuration: &MemoryConfiguration) -> Result<()> {
+    let internal = &memory_configuration.internal_memory_map;
+    let number_of_banks =
+        internal.banks.len() + memory_configuration.external_memory_map.banks.len();
+    if number_of_banks + BASE_INDEX > 256 {
+        return Err(anyhow!(
+            "{} banks are configured, but bank indices only go up to {}. Some banks would \
+            share an index, or be out of sequence.",
+            number_of_banks,
+            u8::MAX
+        ));
+    }
+
+    let golden_banks = memory_configuration.golden_banks();
+    match internal.bootable_index {
+        Some(index) if index >= internal.banks.len() => {
+            return Err(anyhow!(
+                "Bootable bank {} doesn't exist. There are only {} MCU banks.",
+                index + BASE_INDEX,
+                internal.banks.len()
+            ))
+        }
+        Some(index) if golden_banks.contains(&index) => {
+            return Err(anyhow!("Bank {} can't be both bootable and golden.", index + BASE_INDEX))
+        }
+        _ => {}
+    }
+    if let Some(index) = golden_banks.iter().find(|i| **i >= number_of_banks) {
+        return Err(anyhow!(
+            "Golden bank {} doesn't exist. There are only {} banks.",
+            index + BASE_INDEX,
+            number_of_banks
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that every bank lies within the user writable area of its flash chip. This is
+/// what `Configuration::cleanup` enforces and `required_configuration_steps` reports,
+/// repeated here so a configuration that went through neither fails generation instead
+/// of describing banks the device can't reach.
+pub fn check_banks_within_chips(
+    memory_configuration: &MemoryConfiguration,
+    port: &Port,
+) -> Result<()> {
+    let internal_flash = internal_flash(port);
+    let chips = [
+        (&memory_configuration.internal_memory_map.banks, Some(&internal_flash)),
+        (
+            &memory_configuration.external_memory_map.banks,
+            memory_configuration.external_flash.as_ref(),
+        ),
+    ];
+    let banks = chips.iter().flat_map(|(banks, chip)| banks.iter().map(move |b| (b, *chip)));
+    for (index, (bank, chip)) in banks.enumerate() {
+        match chip {
+            Some(chip) if !chip.contains(bank) => {
+                let bank_end = bank.start_address as u64 + bank.size_kb as u64 * 1024;
+                return Err(anyhow!(
+                    "Bank {} ({:#010x} - {:#010x}) doesn't fit within {} ({:#010x} - {:#010x}).",
+                    index + BASE_INDEX,
+                    bank.start_address,
+                    bank_end,
+                    chip.name,
+                    chip.start,
+                    chip.end
+                ));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
 fn generate_imports(memory_configuration: &MemoryConfiguration, port: &Port) -> Result<String> {
     let external_address: Vec<_> = match &memory_configuration.external_flash {
         Some(external_flash) if external_flash.name.to_lowercase().contains("n25q128a") => {
@@ -81,16 +245,17 @@ fn generate_imports(memory_configuration: &MemoryConfiguration, port: &Port) ->
 fn generate_external_banks(
     base_index: usize,
     map: &ExternalMemoryMap,
-    golden_index: Option<usize>,
+    golden_banks: &BTreeSet<usize>,
 ) -> Result<String> {
     let number_of_external_banks = map.banks.len();
     let index: Vec<u8> =
         map.banks.iter().enumerate().map(|(i, _)| (i + base_index) as u8).collect();
     let bootable = vec![false; number_of_external_banks];
-    let location: Vec<u32> = map.banks.iter().map(|b| b.start_address).collect();
+    let location: Vec<u32> = map.banks.iter().map(|b| map.absolute_address(b)).collect();
     let size: Vec<usize> = map.banks.iter().map(|b| (b.size_kb * 1024) as usize).collect();
-    let golden: Vec<bool> =
-        (0..number_of_external_banks).map(|i| Some((i + base_index).saturating_sub(1)) == golden_index).collect();
+    let golden: Vec<bool> = (0..number_of_external_banks)
+        .map(|i| golden_banks.contains(&(i + base_index).saturating_sub(1)))
+        .collect();
 
     let code = quote! {
         const NUMBER_OF_EXTERNAL_BANKS: usize = #number_of_external_banks;
@@ -110,7 +275,7 @@ fn generate_external_banks(
 fn generate_mcu_banks(
     base_index: usize,
     map: &InternalMemoryMap,
-    golden_index: Option<usize>,
+    golden_banks: &BTreeSet<usize>,
 ) -> Result<String> {
     let number_of_mcu_banks = map.banks.len();
     let index: Vec<u8> =
@@ -119,7 +284,7 @@ fn generate_mcu_banks(
         (0..number_of_mcu_banks).map(|i| Some(i) == map.bootable_index).collect();
     let location: Vec<u32> = map.banks.iter().map(|b| b.start_address).collect();
     let size: Vec<usize> = map.banks.iter().map(|b| (b.size_kb * 1024) as usize).collect();
-    let golden: Vec<bool> = (0..number_of_mcu_banks).map(|i| Some(i) == golden_index).collect();
+    let golden: Vec<bool> = (0..number_of_mcu_banks).map(|i| golden_banks.contains(&i)).collect();
 
     let code = quote! {
         const NUMBER_OF_MCU_BANKS: usize = #number_of_mcu_banks;
@@ -135,3 +300,309 @@ fn generate_mcu_banks(
     };
     Ok(format!("{}", code))
 }
+
+fn generate_mcu_sectors(sectors: &[SectorRegion]) -> Result<String> {
+    let number_of_mcu_sector_regions = sectors.len();
+    let start: Vec<usize> = sectors.iter().map(|r| r.start as usize).collect();
+    let sector_size: Vec<usize> = sectors.iter().map(|r| r.sector_size as usize).collect();
+    let sector_count: Vec<usize> = sectors.iter().map(|r| r.sector_count as usize).collect();
+
+    let code = quote! {
+        const NUMBER_OF_MCU_SECTOR_REGIONS: usize = #number_of_mcu_sector_regions;
+        pub static MCU_SECTORS: [image::SectorRegion; NUMBER_OF_MCU_SECTOR_REGIONS] = [
+            #(image::SectorRegion {
+                start: #start,
+                sector_size: #sector_size,
+                sector_count: #sector_count,
+            }),*
+        ];
+    };
+    Ok(format!("{}", code))
+}
+
+fn generate_external_erase_size(memory_configuration: &MemoryConfiguration) -> Result<String> {
+    let region_size = memory_configuration.external_flash.as_ref().map_or(1, |f| f.region_size);
+    if let Some(bank) = memory_configuration
+        .external_memory_map
+        .banks
+        .iter()
+        .find(|b| !b.is_erase_multiple(region_size))
+    {
+        panic!(
+            "External bank at {:#010x} is {}KB, which is not a multiple of the {}KB erase size",
+            bank.start_address,
+            bank.size_kb,
+            region_size / 1024
+        );
+    }
+    let region_size = region_size as usize;
+
+    let code = quote! {
+        pub const EXTERNAL_ERASE_SIZE: usize = #region_size;
+    };
+    Ok(format!("{}", code))
+}
+
+fn generate_boot_counter(map: &InternalMemoryMap) -> Result<String> {
+    let location = match map.boot_counter_location {
+        Some(location) => quote! { Some(McuAddress(#location)) },
+        None => quote! { None },
+    };
+
+    let code = quote! {
+        pub const BOOT_COUNTER: Option<McuAddress> = #location;
+    };
+    Ok(format!("{}", code))
+}
+
+fn generate_staging_bank(base_index: usize, map: &InternalMemoryMap) -> Result<String> {
+    let index = match map.staging_index {
+        Some(index) => {
+            let index = (index + base_index) as u8;
+            quote! { Some(#index) }
+        }
+        None => quote! { None },
+    };
+
+    let code = quote! {
+        pub const MCU_STAGING_BANK: Option<u8> = #index;
+    };
+    Ok(format!("{}", code))
+}
+
+fn generate_staging_rotation(base_index: usize, map: &InternalMemoryMap) -> Result<String> {
+    let (banks, state) = match (&map.staging_rotation, map.staging_index) {
+        (Some(rotation), Some(staging_index)) => {
+            let banks: Vec<u8> = iter::once(staging_index)
+                .chain(rotation.indices.iter().copied())
+                .map(|index| (index + base_index) as u8)
+                .collect();
+            let location = rotation.state_location;
+            (quote! { &[#(#banks),*] }, quote! { Some(McuAddress(#location)) })
+        }
+        _ => (quote! { &[] }, quote! { None }),
+    };
+
+    let code = quote! {
+        pub const MCU_STAGING_ROTATION: &[u8] = #banks;
+        pub const STAGING_ROTATION_STATE: Option<McuAddress> = #state;
+    };
+    Ok(format!("{}", code))
+}
+
+fn generate_backup_bank(base_index: usize, map: &InternalMemoryMap) -> Result<String> {
+    let index = match map.backup_index {
+        Some(index) => {
+            let index = (index + base_index) as u8;
+            quote! { Some(#index) }
+        }
+        None => quote! { None },
+    };
+
+    let code = quote! {
+        pub const MCU_BACKUP_BANK: Option<u8> = #index;
+    };
+    Ok(format!("{}", code))
+}
+
+fn generate_verification_cache(map: &InternalMemoryMap) -> Result<String> {
+    let location = match map.verification_cache_location {
+        Some(location) => quote! { Some(McuAddress(#location)) },
+        None => quote! { None },
+    };
+
+    let code = quote! {
+        pub const VERIFICATION_CACHE: Option<McuAddress> = #location;
+    };
+    Ok(format!("{}", code))
+}
+
+fn generate_bootloader_region(map: &InternalMemoryMap) -> Result<String> {
+    let location = map.bootloader_location;
+    let length = map.bootloader_length_kb as usize * 1024;
+    let code = quote! {
+        pub const BOOTLOADER_REGION: crate::devices::usage::BootloaderRegion<McuAddress> =
+            crate::devices::usage::BootloaderRegion {
+                location: McuAddress(#location),
+                length: #length,
+            };
+    };
+    Ok(format!("{}", code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{external_flash, Bank, StagingRotation};
+
+    fn two_bank_configuration() -> MemoryConfiguration {
+        MemoryConfiguration {
+            internal_memory_map: InternalMemoryMap {
+                banks: vec![Bank { start_address: 0x0802_0000, size_kb: 128 }, Bank {
+                    start_address: 0x0804_0000,
+                    size_kb: 128,
+                }],
+                bootable_index: Some(0),
+                ..Default::default()
+            },
+            golden_indices: [1].iter().copied().collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn well_formed_bank_indices_pass_the_check() {
+        assert!(check_bank_indices(&two_bank_configuration()).is_ok());
+    }
+
+    #[test]
+    fn banks_that_would_share_an_index_are_rejected() {
+        let mut memory_configuration = two_bank_configuration();
+        // With 256 banks in total, the last one's index would wrap around to zero.
+        memory_configuration.external_memory_map.banks =
+            vec![Bank { start_address: 0x0000, size_kb: 4 }; 254];
+        let error = check_bank_indices(&memory_configuration).unwrap_err();
+        assert!(error.to_string().contains("share an index"), "{}", error);
+
+        memory_configuration.external_memory_map.banks.pop();
+        assert!(check_bank_indices(&memory_configuration).is_ok());
+    }
+
+    #[test]
+    fn overlapping_or_dangling_bank_roles_are_rejected() {
+        let mut memory_configuration = two_bank_configuration();
+        memory_configuration.golden_indices = [0].iter().copied().collect();
+        assert!(check_bank_indices(&memory_configuration).is_err());
+
+        let mut memory_configuration = two_bank_configuration();
+        memory_configuration.golden_indices = [2].iter().copied().collect();
+        assert!(check_bank_indices(&memory_configuration).is_err());
+
+        let mut memory_configuration = two_bank_configuration();
+        memory_configuration.internal_memory_map.bootable_index = Some(2);
+        assert!(check_bank_indices(&memory_configuration).is_err());
+    }
+
+    #[test]
+    fn verification_cache_is_rejected_with_signed_images() {
+        let mut memory_configuration = two_bank_configuration();
+        memory_configuration.internal_memory_map.verification_cache_location = Some(0x0801_0000);
+        assert!(check_verification_cache_allowed(&memory_configuration, SecurityMode::Crc).is_ok());
+        let error =
+            check_verification_cache_allowed(&memory_configuration, SecurityMode::P256ECDSA)
+                .unwrap_err();
+        assert!(error.to_string().contains("isn't keyed"), "{}", error);
+    }
+
+    #[test]
+    fn banks_outside_their_flash_chip_are_rejected() {
+        let mut memory_configuration = two_bank_configuration();
+        assert!(check_banks_within_chips(&memory_configuration, &Port::Stm32F412).is_ok());
+
+        // Runs past the end of the MCU flash.
+        memory_configuration.internal_memory_map.banks[1].size_kb = 1024;
+        let error = check_banks_within_chips(&memory_configuration, &Port::Stm32F412).unwrap_err();
+        assert!(error.to_string().starts_with("Bank 2 (0x08040000 - 0x08140000)"), "{}", error);
+
+        // Exceeds the capacity of the external chip.
+        let mut memory_configuration = two_bank_configuration();
+        let chip = external_flash(&Port::Stm32F412).next().unwrap();
+        memory_configuration.external_memory_map.banks =
+            vec![Bank { start_address: chip.end - 0x1000, size_kb: 8 }];
+        memory_configuration.external_flash = Some(chip);
+        let error = check_banks_within_chips(&memory_configuration, &Port::Stm32F412).unwrap_err();
+        assert!(error.to_string().starts_with("Bank 3"), "{}", error);
+    }
+
+    #[test]
+    fn external_bank_locations_include_base_address() {
+        let mut map = ExternalMemoryMap {
+            banks: vec![Bank { start_address: 0x1000, size_kb: 4 }],
+            base_address: 0,
+        };
+        let code = generate_external_banks(1, &map, &BTreeSet::new()).unwrap();
+        assert!(code.contains(&format!("ExternalAddress ({}u32)", 0x1000)));
+
+        map.base_address = 0x9000_0000;
+        let code = generate_external_banks(1, &map, &BTreeSet::new()).unwrap();
+        assert!(code.contains(&format!("ExternalAddress ({}u32)", 0x9000_1000u32)));
+    }
+
+    #[test]
+    #[should_panic(expected = "not a multiple of the 4KB erase size")]
+    fn external_bank_size_must_be_a_multiple_of_the_erase_size() {
+        let memory_configuration = MemoryConfiguration {
+            external_flash: external_flash(&Port::Stm32F412).next(),
+            external_memory_map: ExternalMemoryMap {
+                banks: vec![Bank { start_address: 0x0000, size_kb: 6 }],
+                base_address: 0,
+            },
+            ..Default::default()
+        };
+        generate_external_erase_size(&memory_configuration).unwrap();
+    }
+
+    #[test]
+    fn every_golden_bank_is_flagged() {
+        let internal = InternalMemoryMap {
+            banks: vec![Bank { start_address: 0x0801_0000, size_kb: 64 }, Bank {
+                start_address: 0x0802_0000,
+                size_kb: 128,
+            }],
+            bootable_index: Some(0),
+            ..Default::default()
+        };
+        let external = ExternalMemoryMap {
+            banks: vec![Bank { start_address: 0x0000, size_kb: 4 }, Bank {
+                start_address: 0x1000,
+                size_kb: 4,
+            }],
+            base_address: 0,
+        };
+        let golden_banks: BTreeSet<usize> = [1, 3].iter().copied().collect();
+
+        let mcu = generate_mcu_banks(1, &internal, &golden_banks).unwrap();
+        let external = generate_external_banks(3, &external, &golden_banks).unwrap();
+        assert_eq!(1, mcu.matches("is_golden : true").count());
+        assert_eq!(1, external.matches("is_golden : true").count());
+        let (regular, golden) =
+            (external.find("is_golden : false"), external.find("is_golden : true"));
+        assert!(regular.unwrap() < golden.unwrap());
+    }
+
+    #[test]
+    fn staging_bank_is_referenced_by_bank_index() {
+        let mut map = InternalMemoryMap { staging_index: Some(1), ..Default::default() };
+        assert!(generate_staging_bank(1, &map).unwrap().contains("Some (2u8)"));
+        map.staging_index = None;
+        assert!(generate_staging_bank(1, &map).unwrap().contains("= None"));
+    }
+
+    #[test]
+    fn staging_rotation_starts_with_the_staging_bank() {
+        let mut map = InternalMemoryMap { staging_index: Some(1), ..Default::default() };
+        let code = generate_staging_rotation(1, &map).unwrap();
+        assert!(code.contains("MCU_STAGING_ROTATION : & [u8] = & [] ;"));
+        assert!(code.contains("STAGING_ROTATION_STATE : Option < McuAddress > = None ;"));
+
+        map.staging_rotation = Some(StagingRotation {
+            indices: [4, 2].iter().copied().collect(),
+            state_location: 0x0802_0000,
+        });
+        let code = generate_staging_rotation(1, &map).unwrap();
+        assert!(code.contains("& [2u8 , 3u8 , 5u8]"));
+        assert!(code.contains(&format!("Some (McuAddress ({}u32))", 0x0802_0000u32)));
+    }
+
+    #[test]
+    fn bootloader_region_matches_the_configured_bounds() {
+        let map = InternalMemoryMap {
+            bootloader_location: 0x0800_0000,
+            bootloader_length_kb: 64,
+            ..Default::default()
+        };
+        let code = generate_bootloader_region(&map).unwrap();
+        assert!(code.contains(&format!("location : McuAddress ({}u32)", 0x0800_0000u32)));
+        assert!(code.contains(&format!("length : {}usize", 64 * 1024)));
+    }
+}
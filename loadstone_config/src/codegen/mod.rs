@@ -1,50 +1,128 @@
 //! Generates code from parsed .ron configuration. This is where
 //! concrete Loadstone modules are constructed from user configuration
 //! gathered from the web app GUI.
-use p256::ecdsa::VerifyingKey;
-use std::str::FromStr;
-use quote::{__private::Span, quote};
+use quote::{__private::Span, format_ident, quote};
 use std::{
     fs::{self, OpenOptions},
     io::{self, Write},
-    path::Path,
+    path::{Path, PathBuf},
     process::Command,
 };
 use syn::LitStr;
 
-use crate::{Configuration, features::{BootMetrics, Greetings, Serial, UpdateSignal}, security::SecurityMode};
-use anyhow::Result;
+use crate::{Configuration, port::Port, features::{BootMetrics, Greetings, NoImageFallback, RamClear, RecoveryPin, RecoveryTimeout, Serial, SerialLogLevel, UpdateSignal}, security::{CliAuthentication, SecurityMode}};
+use anyhow::{anyhow, Result};
 
-use self::linker_script::generate_linker_script;
+use self::linker_script::{check_banks_within_flash, generate_linker_script};
+pub use self::linker_script::{
+    application_linker_script, generate_application_linker_script, RAM_VECTOR_TABLE_RESERVATION_KB,
+};
+pub use self::check::{check_feature_flags, Mismatch};
+pub use self::batch::{generate_batch, BatchOutcome};
+pub use self::c_header::{c_header, generate_c_header};
+use self::manifest::Manifest;
 mod memory_map;
 mod linker_script;
 mod pins;
 mod devices;
+mod check;
+mod batch;
+mod manifest;
+mod c_header;
+
+/// Marker present in every autogenerated top level module, used to tell generated
+/// folders apart from user files before deleting anything.
+const AUTOGENERATED_MARKER: &str = "This entire module is autogenerated";
 
 /// Transforms a `Configuration` struct into a set of source code files
 /// that will be compiled into `Loadstone`. The resulting source is written
-/// to src/ports/<port>/autogenerated.
+/// to src/ports/<port>/autogenerated. If `force` is set, the folder is
+/// removed first so no stale files from a previous configuration survive.
 pub fn generate_modules<P: AsRef<Path>>(
     loadstone_path: P,
     configuration: &Configuration,
+    force: bool,
 ) -> Result<()> {
-    let autogenerated_folder_path = loadstone_path.as_ref().join(
-        format!("src/ports/{}/autogenerated", configuration.port)
-    );
-    fs::create_dir(&autogenerated_folder_path).ok();
+    generate_port_modules(&loadstone_path, configuration, force)?;
     generate_linker_script(&configuration)?;
-    generate_top_level_module(&autogenerated_folder_path, configuration)?;
-
     if std::env::var("CARGO_FEATURE_ECDSA_VERIFY").is_ok() {
         generate_key(loadstone_path, configuration)?;
     }
-    memory_map::generate(
-        &autogenerated_folder_path,
+    Ok(())
+}
+
+/// Writes the modules under src/ports/<port>/autogenerated, leaving out the
+/// linker script and verifying key, which are shared by every port and depend
+/// on the build features, so they are always regenerated.
+///
+/// Each module is only rewritten if the part of the configuration it's generated
+/// from changed since the last generation, as recorded in the folder's manifest.
+fn generate_port_modules<P: AsRef<Path>>(
+    loadstone_path: P,
+    configuration: &Configuration,
+    force: bool,
+) -> Result<()> {
+    check_banks_within_flash(configuration)?;
+    memory_map::check_bank_indices(&configuration.memory_configuration)?;
+    memory_map::check_banks_within_chips(&configuration.memory_configuration, &configuration.port)?;
+    memory_map::check_verification_cache_allowed(
         &configuration.memory_configuration,
-        &configuration.port,
+        configuration.security_configuration.security_mode,
     )?;
-    pins::generate(&autogenerated_folder_path, &configuration)?;
-    devices::generate(&autogenerated_folder_path, &configuration)?;
+    if force {
+        clean_autogenerated_folder(&loadstone_path, &configuration.port)?;
+    }
+    let autogenerated_folder_path =
+        autogenerated_folder_path(&loadstone_path, &configuration.port);
+    fs::create_dir_all(&autogenerated_folder_path)?;
+
+    let port = &configuration.port;
+    let memory = &configuration.memory_configuration;
+    let features = &configuration.feature_configuration;
+    let pin_inputs = (
+        port,
+        &features.serial,
+        &features.status_led,
+        &features.recovery_pin,
+        &memory.external_flash,
+    );
+
+    let mut manifest = Manifest::load(&autogenerated_folder_path);
+    manifest.generate_if_changed("mod.rs", configuration, || {
+        generate_top_level_module(&autogenerated_folder_path, configuration)
+    })?;
+    manifest.generate_if_changed("memory_map.rs", &(port, memory, memory.golden_banks()), || {
+        memory_map::generate(&autogenerated_folder_path, memory, port)
+    })?;
+    manifest.generate_if_changed("pin_configuration.rs", &pin_inputs, || {
+        pins::generate(&autogenerated_folder_path, &configuration)
+    })?;
+    manifest.generate_if_changed("devices.rs", &pin_inputs, || {
+        devices::generate(&autogenerated_folder_path, &configuration)
+    })?;
+    manifest.save()
+}
+
+fn autogenerated_folder_path<P: AsRef<Path>>(loadstone_path: P, port: &Port) -> PathBuf {
+    loadstone_path.as_ref().join(format!("src/ports/{}/autogenerated", port))
+}
+
+/// Removes the autogenerated folder of a port, if present. Refuses to touch
+/// a folder whose top level module wasn't generated by Loadstone, so user
+/// files are never deleted.
+pub fn clean_autogenerated_folder<P: AsRef<Path>>(loadstone_path: P, port: &Port) -> Result<()> {
+    let path = autogenerated_folder_path(loadstone_path, port);
+    if !path.exists() {
+        return Ok(());
+    }
+    let top_level_module = fs::read_to_string(path.join("mod.rs")).unwrap_or_default();
+    if !top_level_module.contains(AUTOGENERATED_MARKER) {
+        return Err(anyhow!(
+            "Refusing to clean {}: it doesn't look like an autogenerated folder.",
+            path.display()
+        ));
+    }
+    fs::remove_dir_all(&path)?;
     Ok(())
 }
 
@@ -58,8 +136,10 @@ fn generate_key<P: AsRef<Path>>(loadstone_path: P, configuration: &Configuration
         "src/devices/assets/key.sec1"
     );
 
-    let key = VerifyingKey::from_str(&configuration.security_configuration.verifying_key_raw)
-        .expect("Supplied public key is not valid");
+    let key = configuration
+        .security_configuration
+        .verifying_key()
+        .map_err(|error| anyhow!("Supplied public key is not valid: {}", error))?;
 
     let mut file = OpenOptions::new().write(true).create(true).truncate(true).open(&key_path)?;
     file.write_all(key.to_encoded_point(false).as_bytes())?;
@@ -112,6 +192,131 @@ fn generate_top_level_module<P: AsRef<Path>>(
         Greetings::Custom { demo,..} => LitStr::new(&demo, Span::call_site()),
     };
 
+    let boot_delay_ms = configuration.feature_configuration.boot_delay_ms;
+    if boot_delay_ms > 0 && !BootMetrics::timing_supported(&configuration.port) {
+        panic!(
+            "Boot delay enabled for a port that doesn't support timing: {:?}",
+            configuration.port
+        );
+    }
+
+    let serial_log_level = configuration.feature_configuration.serial_log_level;
+    if serial_log_level != SerialLogLevel::Off && !serial_enabled {
+        panic!("Serial log level set, but serial communication is disabled.");
+    }
+    let serial_log_level = format_ident!("{:?}", serial_log_level);
+
+    let crc_polynomial = configuration.security_configuration.crc_algorithm.polynomial();
+    let strict_scan = configuration.security_configuration.strict_scan;
+    let max_scan_bytes = max_scan_bytes(configuration);
+    let min_image_size = configuration.security_configuration.min_image_size();
+
+    let cli_credentials = match configuration.security_configuration.cli_authentication {
+        CliAuthentication::Enabled { salt, hash } => quote! {
+            Some(crate::devices::cli::Credentials { salt: [#(#salt),*], hash: [#(#hash),*] })
+        },
+        CliAuthentication::Disabled => quote! { None },
+    };
+
+    let ram_vector_table = if configuration.feature_configuration.ram_vector_table {
+        let length = configuration.port.vector_table_size().unwrap_or_else(|| {
+            panic!("RAM vector table enabled for a port that doesn't support it: {:?}",
+                configuration.port)
+        });
+        let address = configuration
+            .port
+            .linker_script_constants()
+            .ok_or(anyhow!("Current board doesn't have linker script constants defined."))?
+            .ram
+            .origin as usize;
+        quote! {
+            Some(crate::devices::bootloader::RamVectorTable { address: #address, length: #length })
+        }
+    } else {
+        quote! { None }
+    };
+
+    let self_check = if configuration.feature_configuration.bootloader_self_check {
+        let map = &configuration.memory_configuration.internal_memory_map;
+        let location = map.bootloader_location as usize;
+        let length = map.bootloader_length_kb as usize * 1024;
+        quote! {
+            Some(crate::devices::bootloader::SelfCheck { location: #location, length: #length })
+        }
+    } else {
+        quote! { None }
+    };
+
+    let jump_validation = if configuration.feature_configuration.jump_validation {
+        let ram = configuration
+            .port
+            .linker_script_constants()
+            .ok_or(anyhow!("Current board doesn't have linker script constants defined."))?
+            .ram;
+        let (ram_start, ram_size) = (ram.origin as usize, ram.size);
+        quote! {
+            Some(crate::devices::bootloader::JumpValidation {
+                ram_start: #ram_start,
+                ram_size: #ram_size,
+            })
+        }
+    } else {
+        quote! { None }
+    };
+
+    let ram_clear = match configuration.feature_configuration.ram_clear {
+        RamClear::Enabled { offset_kb, size_kb } => {
+            let ram = configuration
+                .port
+                .linker_script_constants()
+                .ok_or(anyhow!("Current board doesn't have linker script constants defined."))?
+                .ram;
+            let start = ram.origin as usize + offset_kb as usize * 1024;
+            let length = size_kb as usize * 1024;
+            if start + length > ram.origin as usize + ram.size {
+                return Err(anyhow!("RAM clear region extends past the end of RAM."));
+            }
+            quote! {
+                Some(crate::devices::bootloader::RamClear { start: #start, length: #length })
+            }
+        }
+        RamClear::Disabled => quote! { None },
+    };
+
+    let recovery_protocol =
+        format_ident!("{:?}", configuration.feature_configuration.recovery_protocol);
+
+    let no_image_fallback = match configuration.feature_configuration.no_image_fallback {
+        NoImageFallback::Panic => quote! { crate::devices::bootloader::NoImageFallback::Panic },
+        NoImageFallback::Retry { delay_ms } => {
+            quote! { crate::devices::bootloader::NoImageFallback::Retry { delay_ms: #delay_ms } }
+        }
+        NoImageFallback::DiagnosticLoop => {
+            quote! { crate::devices::bootloader::NoImageFallback::DiagnosticLoop }
+        }
+    };
+
+    let recovery_timeout = match configuration.feature_configuration.recovery_timeout {
+        RecoveryTimeout::Enabled { timeout_s, reboot } => {
+            let timeout_ms = timeout_s * 1000;
+            quote! {
+                Some(crate::devices::bootloader::RecoveryTimeout {
+                    timeout_ms: #timeout_ms,
+                    reboot: #reboot,
+                })
+            }
+        }
+        RecoveryTimeout::Disabled => quote! { None },
+    };
+
+    let recovery_pin_active_level = match &configuration.feature_configuration.recovery_pin {
+        RecoveryPin::Enabled { active_level, .. } => format_ident!("{:?}", active_level),
+        RecoveryPin::Disabled => format_ident!("High"),
+    };
+
+    let verify_writes = configuration.feature_configuration.verify_writes;
+    let golden_override = configuration.feature_configuration.golden_override;
+
     let update_signal = configuration.feature_configuration.update_signal;
     let update_signal_enabled = matches!(update_signal, UpdateSignal::Enabled);
 
@@ -127,6 +332,18 @@ fn generate_top_level_module<P: AsRef<Path>>(
         #[allow(unused)]
         pub const RECOVERY_ENABLED: bool = #recovery_enabled;
         #[allow(unused)]
+        pub const RECOVERY_PROTOCOL: crate::devices::file_transfer::Protocol =
+            crate::devices::file_transfer::Protocol::#recovery_protocol;
+        #[allow(unused)]
+        pub const NO_IMAGE_FALLBACK: crate::devices::bootloader::NoImageFallback =
+            #no_image_fallback;
+        #[allow(unused)]
+        pub const RECOVERY_TIMEOUT: Option<crate::devices::bootloader::RecoveryTimeout> =
+            #recovery_timeout;
+        #[allow(unused)]
+        pub const RECOVERY_PIN_ACTIVE_LEVEL: crate::devices::recovery_pin::ActiveLevel =
+            crate::devices::recovery_pin::ActiveLevel::#recovery_pin_active_level;
+        #[allow(unused)]
         pub const BOOT_TIME_METRICS_ENABLED: bool = #boot_time_metrics_enabled;
         #[allow(unused)]
         pub const LOADSTONE_GREETING: &str = #loadstone_greeting;
@@ -134,6 +351,36 @@ fn generate_top_level_module<P: AsRef<Path>>(
         pub const DEMO_APP_GREETING: &str = #demo_app_greeting;
         #[allow(unused)]
         pub const UPDATE_SIGNAL_ENABLED: bool = #update_signal_enabled;
+        #[allow(unused)]
+        pub const BOOT_DELAY_MS: u32 = #boot_delay_ms;
+        #[allow(unused)]
+        pub const CRC_POLYNOMIAL: u32 = #crc_polynomial;
+        #[allow(unused)]
+        pub const STRICT_SCAN: bool = #strict_scan;
+        #[allow(unused)]
+        pub const MAX_SCAN_BYTES: usize = #max_scan_bytes as usize;
+        #[allow(unused)]
+        pub const MIN_IMAGE_SIZE: usize = #min_image_size as usize;
+        #[allow(unused)]
+        #[cfg(feature = "cli")]
+        pub const CLI_CREDENTIALS: Option<crate::devices::cli::Credentials> = #cli_credentials;
+        #[allow(unused)]
+        pub const SERIAL_LOG_LEVEL: crate::devices::serial_log::Level =
+            crate::devices::serial_log::Level::#serial_log_level;
+        #[allow(unused)]
+        pub const RAM_VECTOR_TABLE: Option<crate::devices::bootloader::RamVectorTable> =
+            #ram_vector_table;
+        #[allow(unused)]
+        pub const SELF_CHECK: Option<crate::devices::bootloader::SelfCheck> = #self_check;
+        #[allow(unused)]
+        pub const JUMP_VALIDATION: Option<crate::devices::bootloader::JumpValidation> =
+            #jump_validation;
+        #[allow(unused)]
+        pub const RAM_CLEAR: Option<crate::devices::bootloader::RamClear> = #ram_clear;
+        #[allow(unused)]
+        pub const VERIFY_WRITES: bool = #verify_writes;
+        #[allow(unused)]
+        pub const GOLDEN_OVERRIDE: bool = #golden_override;
     };
 
     file.write_all(format!("{}", code).as_bytes())?;
@@ -141,7 +388,134 @@ fn generate_top_level_module<P: AsRef<Path>>(
     Ok(())
 }
 
+/// Bytes scanned for the magic string before a bank is considered empty. Unless
+/// configured, scanning stops at the size of the bootable bank.
+fn max_scan_bytes(configuration: &Configuration) -> u32 {
+    let map = &configuration.memory_configuration.internal_memory_map;
+    let bootable_bank_size =
+        map.bootable_index.and_then(|index| map.banks.get(index)).map(|bank| bank.size_kb * 1024);
+    configuration
+        .security_configuration
+        .max_scan_bytes
+        .or(bootable_bank_size)
+        .unwrap_or(u32::MAX)
+}
+
 fn prettify_file<P: AsRef<Path>>(path: P) -> io::Result<()> {
     Command::new("rustfmt").arg(path.as_ref()).spawn()?.wait()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_folder(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("loadstone_codegen_{}", name));
+        fs::remove_dir_all(&path).ok();
+        path
+    }
+
+    #[test]
+    fn scan_limit_defaults_to_the_bootable_bank_size() {
+        let mut configuration = Configuration::default();
+        let map = &mut configuration.memory_configuration.internal_memory_map;
+        map.banks = vec![
+            crate::memory::Bank { start_address: 0x0801_0000, size_kb: 64 },
+            crate::memory::Bank { start_address: 0x0802_0000, size_kb: 128 },
+        ];
+        map.bootable_index = Some(0);
+        assert_eq!(max_scan_bytes(&configuration), 64 * 1024);
+
+        configuration.security_configuration.max_scan_bytes = Some(4096);
+        assert_eq!(max_scan_bytes(&configuration), 4096);
+
+        configuration.security_configuration.max_scan_bytes = None;
+        configuration.memory_configuration.internal_memory_map.bootable_index = None;
+        assert_eq!(max_scan_bytes(&configuration), u32::MAX);
+    }
+
+    #[test]
+    fn forced_clean_removes_stale_generated_files() {
+        let loadstone_path = scratch_folder("stale");
+        let folder = autogenerated_folder_path(&loadstone_path, &Port::Stm32F412);
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(folder.join("mod.rs"), format!("//! {}.", AUTOGENERATED_MARKER)).unwrap();
+        fs::write(folder.join("stale_pins.rs"), "").unwrap();
+
+        clean_autogenerated_folder(&loadstone_path, &Port::Stm32F412).unwrap();
+        assert!(!folder.join("stale_pins.rs").exists());
+        assert!(folder.parent().unwrap().exists());
+        fs::remove_dir_all(&loadstone_path).ok();
+    }
+
+    #[test]
+    fn only_modules_affected_by_a_change_are_regenerated() {
+        let loadstone_path = scratch_folder("incremental");
+        let folder = autogenerated_folder_path(&loadstone_path, &Port::Stm32F412);
+        let mut configuration = Configuration::default();
+        configuration.security_configuration.security_mode = SecurityMode::Crc;
+        configuration.memory_configuration.external_flash = None;
+        generate_port_modules(&loadstone_path, &configuration, false).unwrap();
+
+        // Clobber every module, so any regenerated module is told apart by its contents.
+        let modules = ["mod.rs", "memory_map.rs", "pin_configuration.rs", "devices.rs"];
+        let clobber =
+            |modules: &[&str]| modules.iter().for_each(|m| fs::write(folder.join(m), "").unwrap());
+        let regenerated = || {
+            modules
+                .iter()
+                .filter(|m| !fs::read_to_string(folder.join(m)).unwrap().is_empty())
+                .copied()
+                .collect::<Vec<_>>()
+        };
+
+        clobber(&modules);
+        generate_port_modules(&loadstone_path, &configuration, false).unwrap();
+        assert!(regenerated().is_empty());
+
+        configuration.feature_configuration.greetings =
+            Greetings::Custom { loadstone: "Hi".into(), demo: "Hello".into() };
+        generate_port_modules(&loadstone_path, &configuration, false).unwrap();
+        assert_eq!(regenerated(), ["mod.rs"]);
+
+        clobber(&modules);
+        let bank = crate::memory::Bank { start_address: 0x0802_0000, size_kb: 128 };
+        configuration.memory_configuration.internal_memory_map.banks.push(bank);
+        generate_port_modules(&loadstone_path, &configuration, false).unwrap();
+        assert_eq!(regenerated(), ["mod.rs", "memory_map.rs"]);
+
+        clobber(&modules);
+        configuration.feature_configuration.status_led = crate::features::StatusLed::Enabled {
+            pin: crate::pins::status_led(&Port::Stm32F412).next().unwrap(),
+        };
+        generate_port_modules(&loadstone_path, &configuration, false).unwrap();
+        assert_eq!(regenerated(), ["mod.rs", "pin_configuration.rs", "devices.rs"]);
+
+        // Missing modules are always regenerated.
+        clobber(&modules);
+        fs::remove_file(folder.join("devices.rs")).unwrap();
+        generate_port_modules(&loadstone_path, &configuration, false).unwrap();
+        assert_eq!(regenerated(), ["devices.rs"]);
+
+        // Forcing regenerates everything, as the manifest is cleaned along with the modules.
+        configuration.feature_configuration.greetings = Greetings::Default;
+        generate_port_modules(&loadstone_path, &configuration, false).unwrap();
+        clobber(&modules[1..]);
+        generate_port_modules(&loadstone_path, &configuration, true).unwrap();
+        assert_eq!(regenerated(), modules);
+        fs::remove_dir_all(&loadstone_path).ok();
+    }
+
+    #[test]
+    fn clean_refuses_to_remove_unrecognized_folders() {
+        let loadstone_path = scratch_folder("user");
+        let folder = autogenerated_folder_path(&loadstone_path, &Port::Stm32F412);
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(folder.join("mod.rs"), "// Handwritten port.").unwrap();
+
+        assert!(clean_autogenerated_folder(&loadstone_path, &Port::Stm32F412).is_err());
+        assert!(folder.join("mod.rs").exists());
+        fs::remove_dir_all(&loadstone_path).ok();
+    }
+}
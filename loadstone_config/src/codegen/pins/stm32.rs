@@ -4,13 +4,20 @@ use quote::{format_ident, quote, TokenStreamExt};
 use std::{array::IntoIter, fs::File, io::Write};
 use syn::{Ident, Index};
 
-use crate::{features::Serial, Configuration};
+use crate::{
+    features::{RecoveryPin, Serial, StatusLed},
+    Configuration,
+};
 
 struct InputPinTokens {
     bank: char,
     index: Index,
     mode: Ident,
 }
+struct OutputPinTokens {
+    bank: char,
+    index: Index,
+}
 struct SerialPinTokens {
     bank: char,
     index: Index,
@@ -69,6 +76,36 @@ fn generate_pin_constructor(
             Box::new(None.into_iter())
         };
 
+    let status_led_pin_structs: Box<dyn Iterator<Item = Ident>> =
+        if let StatusLed::Enabled { pin } = &configuration.feature_configuration.status_led {
+            Box::new(Some(format_ident!("gpio{}", pin.bank)).into_iter())
+        } else {
+            Box::new(None.into_iter())
+        };
+
+    let status_led_pin_fields: Box<dyn Iterator<Item = Ident>> =
+        if let StatusLed::Enabled { pin } = &configuration.feature_configuration.status_led {
+            Box::new(Some(format_ident!("p{}{}", pin.bank, pin.index)).into_iter())
+        } else {
+            Box::new(None.into_iter())
+        };
+
+    let recovery_pin_structs: Box<dyn Iterator<Item = Ident>> =
+        if let RecoveryPin::Enabled { pin, .. } = &configuration.feature_configuration.recovery_pin
+        {
+            Box::new(Some(format_ident!("gpio{}", pin.bank)).into_iter())
+        } else {
+            Box::new(None.into_iter())
+        };
+
+    let recovery_pin_fields: Box<dyn Iterator<Item = Ident>> =
+        if let RecoveryPin::Enabled { pin, .. } = &configuration.feature_configuration.recovery_pin
+        {
+            Box::new(Some(format_ident!("p{}{}", pin.bank, pin.index)).into_iter())
+        } else {
+            Box::new(None.into_iter())
+        };
+
     // TODO expose in configuration file
     let qspi_pin_structs: Box<dyn Iterator<Item = Ident>> =
         if configuration.memory_configuration.external_flash.is_some() {
@@ -100,12 +137,14 @@ fn generate_pin_constructor(
 
     code.append_all(quote! {
         #[allow(unused)]
-        pub fn pins(#(#gpio_fields: stm32pac::#pac_gpio_fields),*, rcc: &mut stm32pac::RCC) -> (UsartPins, QspiPins) {
+        pub fn pins(#(#gpio_fields: stm32pac::#pac_gpio_fields),*, rcc: &mut stm32pac::RCC) -> (UsartPins, QspiPins, StatusLedPin, RecoveryInputPin) {
 
             #(let #gpio_fields = #gpio_fields.split(rcc);)*
             (
                 (#(#serial_pin_structs.#serial_pin_fields),*),
-                (#(#qspi_pin_structs.#qspi_pin_fields),*)
+                (#(#qspi_pin_structs.#qspi_pin_fields),*),
+                (#(#status_led_pin_structs.#status_led_pin_fields),*),
+                (#(#recovery_pin_structs.#recovery_pin_fields),*)
             )
 
         }
@@ -139,6 +178,26 @@ fn generate_imports_and_types(
             pub type Serial = blue_hal::hal::null::NullSerial;
         });
     }
+    if let StatusLed::Enabled { pin } = &configuration.feature_configuration.status_led {
+        let pin = format_ident!("P{}{}", pin.bank, pin.index);
+        code.append_all(quote! {
+            pub type StatusLedPin = #pin<Output<PushPull>>;
+        });
+    } else {
+        code.append_all(quote! {
+            pub type StatusLedPin = ();
+        });
+    }
+    if let RecoveryPin::Enabled { pin, .. } = &configuration.feature_configuration.recovery_pin {
+        let pin = format_ident!("P{}{}", pin.bank, pin.index);
+        code.append_all(quote! {
+            pub type RecoveryInputPin = #pin<Input<Floating>>;
+        });
+    } else {
+        code.append_all(quote! {
+            pub type RecoveryInputPin = ();
+        });
+    }
     if let Some(_) = &configuration.memory_configuration.external_flash {
         code.append_all(quote! {
             use blue_hal::drivers::micron::n25q128a_flash::MicronN25q128a;
@@ -170,6 +229,7 @@ fn generate_imports_and_types(
 fn generate_gpio_macros(configuration: &Configuration, code: &mut quote::__private::TokenStream) {
     for bank in 'a'..='h' {
         let input_tokens = input_tokens(configuration).filter(|t| t.bank == bank).collect_vec();
+        let output_tokens = output_tokens(configuration).filter(|t| t.bank == bank).collect_vec();
         let serial_tokens = serial_tokens(configuration).filter(|t| t.bank == bank).collect_vec();
         let qspi_flash_pin_tokens =
             qspi_flash_pin_tokens(configuration).filter(|t| t.bank == bank).collect_vec();
@@ -177,6 +237,8 @@ fn generate_gpio_macros(configuration: &Configuration, code: &mut quote::__priva
         let input_index = input_tokens.iter().map(|t| &t.index);
         let input_mode = input_tokens.iter().map(|t| &t.mode);
 
+        let output_index = output_tokens.iter().map(|t| &t.index);
+
         let serial_index = serial_tokens.iter().map(|t| &t.index);
         let serial_mode = serial_tokens.iter().map(|t| &t.mode);
         let serial_direction = serial_tokens.iter().map(|t| &t.direction);
@@ -191,6 +253,7 @@ fn generate_gpio_macros(configuration: &Configuration, code: &mut quote::__priva
         code.append_all(quote! {
             gpio!(#bank, [
                 #((#input_index, Input<#input_mode>),)*
+                #((#output_index, Output<PushPull>),)*
                 #((#serial_index, #serial_mode as #serial_direction<#serial_peripheral>),)*
                 #((#qspi_flash_index, #qspi_flash_mode as #qspi_flash_earmark),)*
             ]);
@@ -198,11 +261,30 @@ fn generate_gpio_macros(configuration: &Configuration, code: &mut quote::__priva
     }
 }
 
-fn input_tokens(_configuration: &Configuration) -> Box<dyn Iterator<Item = InputPinTokens>> {
-    Box::new(IntoIter::new([
-        InputPinTokens { bank: 'a', index: 0.into(), mode: format_ident!("Floating") },
-        InputPinTokens { bank: 'a', index: 1.into(), mode: format_ident!("Floating") },
-    ]))
+fn input_tokens(configuration: &Configuration) -> Box<dyn Iterator<Item = InputPinTokens>> {
+    let mut pins = vec![('a', 0), ('a', 1)];
+    if let RecoveryPin::Enabled { pin, .. } = &configuration.feature_configuration.recovery_pin {
+        let pin = (pin.bank.chars().nth(0).unwrap(), pin.index);
+        if !pins.contains(&pin) {
+            pins.push(pin);
+        }
+    }
+    Box::new(pins.into_iter().map(|(bank, index)| InputPinTokens {
+        bank,
+        index: (index as usize).into(),
+        mode: format_ident!("Floating"),
+    }))
+}
+
+fn output_tokens(configuration: &Configuration) -> Box<dyn Iterator<Item = OutputPinTokens>> {
+    if let StatusLed::Enabled { pin } = &configuration.feature_configuration.status_led {
+        Box::new(IntoIter::new([OutputPinTokens {
+            bank: pin.bank.chars().nth(0).unwrap(),
+            index: (pin.index as usize).into(),
+        }]))
+    } else {
+        Box::new(None.into_iter())
+    }
 }
 
 fn serial_tokens(configuration: &Configuration) -> Box<dyn Iterator<Item = SerialPinTokens>> {
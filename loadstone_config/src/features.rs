@@ -1,8 +1,12 @@
 use std::borrow::Cow;
 
+use enum_iterator::IntoEnumIterator;
 use serde::{Deserialize, Serialize};
 
-use crate::{pins::PeripheralPin, port::Port};
+use crate::{
+    pins::{self, InputPin, OutputPin, PeripheralPin},
+    port::Port,
+};
 
 /// Collection of Loadstone features that are optional or
 /// somehow configurable.
@@ -12,6 +16,59 @@ pub struct FeatureConfiguration {
     pub boot_metrics: BootMetrics,
     pub update_signal: UpdateSignal,
     pub greetings: Greetings,
+    /// Time in milliseconds Loadstone holds before jumping to a verified
+    /// image, during which a keypress over serial diverts into recovery mode.
+    /// A value of zero disables the hold entirely.
+    #[serde(default)]
+    pub boot_delay_ms: u32,
+    /// Most verbose level of bootloader log messages mirrored over serial.
+    #[serde(default)]
+    pub serial_log_level: SerialLogLevel,
+    #[serde(default)]
+    pub status_led: StatusLed,
+    /// Copy the application's vector table to the start of RAM before booting, and
+    /// point `VTOR` there, for lower interrupt latency. This permanently reserves
+    /// the first kilobyte of RAM, for both Loadstone and the application.
+    #[serde(default)]
+    pub ram_vector_table: bool,
+    /// Verify a CRC32 of Loadstone's own flash region on every boot, refusing to boot
+    /// any image (only offering serial recovery) if it doesn't match. Requires the
+    /// bootloader binary to be post-processed by the signing tool's `--bootloader` mode.
+    #[serde(default)]
+    pub bootloader_self_check: bool,
+    /// Check that an image's initial stack pointer lies in RAM and its reset handler
+    /// within the image before jumping to it, rejecting the image otherwise.
+    #[serde(default)]
+    pub jump_validation: bool,
+    /// Protocol used to receive images over serial in recovery mode.
+    #[serde(default)]
+    pub recovery_protocol: RecoveryProtocol,
+    #[serde(default)]
+    pub recovery_pin: RecoveryPin,
+    /// What to do when there's no image to boot or restore, and serial recovery is disabled.
+    #[serde(default)]
+    pub no_image_fallback: NoImageFallback,
+    /// Bound on serial recovery sessions, which otherwise wait for a transfer forever.
+    #[serde(default)]
+    pub recovery_timeout: RecoveryTimeout,
+    /// Read back images as they're copied into flash, aborting the copy on the first
+    /// chunk that doesn't match. Catches failed writes right away, at the cost of speed.
+    #[serde(default)]
+    pub verify_writes: bool,
+    /// Let golden images replace the current image, like regular ones, when no regular bank
+    /// holds a valid image. By default, golden images only serve as a last resort restore.
+    #[serde(default)]
+    pub golden_override: bool,
+    /// Leave the demo app CLI and boot manager out of the build, for size. Loadstone
+    /// itself keeps serial recovery. Requires building without the default `cli` flag.
+    #[serde(default)]
+    pub exclude_cli: bool,
+    /// Buffer received serial bytes from the USART interrupt, instead of polling for them.
+    /// Prevents overruns during fast recovery transfers. Requires serial.
+    #[serde(default)]
+    pub serial_interrupt_rx: bool,
+    #[serde(default)]
+    pub ram_clear: RamClear,
 }
 
 /// Feature that governs whether loadstone will relay boot information
@@ -84,6 +141,140 @@ impl Serial {
     pub fn enabled(&self) -> bool { matches!(self, Serial::Enabled { .. }) }
 }
 
+/// Serial protocol for recovery mode. YMODEM announces the image size up front, so
+/// Loadstone can refuse an image too large for the destination bank before writing it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, IntoEnumIterator)]
+pub enum RecoveryProtocol {
+    XModem,
+    YModem,
+}
+
+impl Default for RecoveryProtocol {
+    fn default() -> Self { RecoveryProtocol::XModem }
+}
+
+/// Last resort when no bank holds an image to boot or restore, and serial recovery
+/// is disabled. A panic leaves a headless device stuck until someone resets it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoImageFallback {
+    /// Halt with a panic.
+    Panic,
+    /// Retry the restore every `delay_ms` milliseconds until an image shows up.
+    Retry { delay_ms: u32 },
+    /// Serve a minimal set of diagnostic commands over serial (listing banks, retrying
+    /// the restore and rebooting). Requires serial.
+    DiagnosticLoop,
+}
+
+impl Default for NoImageFallback {
+    fn default() -> Self { NoImageFallback::Panic }
+}
+
+/// Overall timeout of a serial recovery session, so an unattended device doesn't wait
+/// for a host forever. Requires serial recovery, and a time source.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum RecoveryTimeout {
+    Enabled {
+        /// Seconds a session waits for a transfer to start.
+        timeout_s: u32,
+        /// Reboot once the session times out, to retry a normal boot. Otherwise,
+        /// Loadstone stays in recovery and starts a new session.
+        reboot: bool,
+    },
+    Disabled,
+}
+
+impl Default for RecoveryTimeout {
+    fn default() -> Self { RecoveryTimeout::Disabled }
+}
+
+/// Status LED feature. If enabled, Loadstone signals its state by blinking an LED:
+/// slowly while scanning banks, quickly during serial recovery, and solid right
+/// before jumping to the application.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum StatusLed {
+    Enabled {
+        /// Hardware pin driving the LED.
+        pin: OutputPin,
+    },
+    Disabled,
+}
+
+impl Default for StatusLed {
+    fn default() -> Self { Self::Disabled }
+}
+
+impl StatusLed {
+    /// Whether a port is capable of driving a status LED.
+    pub fn supported(port: &Port) -> bool { pins::status_led(port).count() > 0 }
+
+    pub fn enabled(&self) -> bool { matches!(self, StatusLed::Enabled { .. }) }
+}
+
+/// RAM clear feature. If enabled, Loadstone zeroes a region of RAM right before jumping
+/// to the application, so secrets it handled (e.g. decryption keys) don't linger there.
+/// The boot info relayed to the application, the RAM vector table and Loadstone's live
+/// stack are always spared.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RamClear {
+    Enabled {
+        /// Start of the region, in kilobytes from the start of RAM.
+        offset_kb: u32,
+        /// Size of the region in kilobytes.
+        size_kb: u32,
+    },
+    Disabled,
+}
+
+impl Default for RamClear {
+    fn default() -> Self { Self::Disabled }
+}
+
+/// Recovery pin feature. If enabled, holding the pin at its active level while
+/// Loadstone starts forces serial recovery mode, even if the current image is valid.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum RecoveryPin {
+    Enabled {
+        /// Hardware pin sampled at boot.
+        pin: InputPin,
+        /// Level at which the pin requests recovery.
+        active_level: ActiveLevel,
+    },
+    Disabled,
+}
+
+impl Default for RecoveryPin {
+    fn default() -> Self { Self::Disabled }
+}
+
+impl RecoveryPin {
+    /// Whether a port has any pin suitable to trigger recovery.
+    pub fn supported(port: &Port) -> bool { pins::recovery_pin(port).count() > 0 }
+
+    pub fn enabled(&self) -> bool { matches!(self, RecoveryPin::Enabled { .. }) }
+}
+
+/// Logic level of an input pin.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, IntoEnumIterator)]
+pub enum ActiveLevel {
+    High,
+    Low,
+}
+
+/// Serial log level. Bootloader log messages of this severity or higher are
+/// printed over serial with a level prefix, in addition to `defmt`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, IntoEnumIterator)]
+pub enum SerialLogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+}
+
+impl Default for SerialLogLevel {
+    fn default() -> Self { SerialLogLevel::Off }
+}
+
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum UpdateSignal {
     Disabled,
@@ -11,10 +11,17 @@
 
 use std::{array::IntoIter, fmt::Display};
 
-use features::{BootMetrics, FeatureConfiguration, Serial};
-use memory::{external_flash, MemoryConfiguration};
+use features::{
+    BootMetrics, FeatureConfiguration, NoImageFallback, RamClear, RecoveryPin, RecoveryTimeout,
+    Serial, SerialLogLevel, StatusLed,
+};
+use memory::{
+    external_flash, external_flash_base_addresses, internal_flash, internal_flash_sectors,
+    ram_size_kb, Bank, FlashChip, MemoryConfiguration, RamBudget,
+};
 use port::Port;
-use security::{SecurityConfiguration, SecurityMode};
+use ron::ser::PrettyConfig;
+use security::{KeyError, SecurityConfiguration, SecurityMode};
 use serde::{Deserialize, Serialize};
 
 pub mod port;
@@ -23,6 +30,18 @@ pub mod memory;
 pub mod features;
 pub mod security;
 pub mod codegen;
+mod preset;
+mod summary;
+
+/// Layout of a serialized .ron configuration.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RonFormat {
+    /// Indented, with one field per line, so configurations kept under version
+    /// control produce readable diffs.
+    Pretty,
+    /// A single line, for embedding in links or environment variables.
+    Compact,
+}
 
 #[derive(Serialize, Deserialize, Default, Debug)]
 /// Defines all configuration for a "codegen" loadstone port. This struct
@@ -58,6 +77,10 @@ impl Configuration {
             flags.push("ecdsa-verify");
         };
 
+        if self.feature_configuration.serial_log_level != SerialLogLevel::Off {
+            flags.push("serial-log");
+        };
+
         flags.into_iter()
     }
 
@@ -68,14 +91,109 @@ impl Configuration {
             self.memory_configuration.internal_memory_map.bootable_index.is_none()
                 .then_some(RequiredConfigurationStep::BootableBank),
 
+            (!self.memory_configuration.internal_memory_map.banks.iter()
+                .all(|b| b.is_sector_aligned(&internal_flash_sectors(&self.port))))
+                .then_some(RequiredConfigurationStep::SectorAlignedBanks),
+
+            (!self.memory_configuration.internal_memory_map
+                .boot_counter_placement_valid(&internal_flash_sectors(&self.port)))
+                .then_some(RequiredConfigurationStep::BootCounterSector),
+
+            (!self.memory_configuration.internal_memory_map.banks.iter()
+                .all(|b| internal_flash(&self.port).contains(b)))
+                .then_some(RequiredConfigurationStep::InternalBanksFit),
+
+            self.memory_configuration.external_flash.as_ref()
+                .filter(|chip| !self.memory_configuration.external_memory_map.banks.iter()
+                    .all(|b| chip.contains(b)))
+                .map(|_| RequiredConfigurationStep::ExternalBanksFit),
+
             (self.security_configuration.security_mode == SecurityMode::P256ECDSA
-                && self.security_configuration.verifying_key_raw.is_empty())
+                && self.security_configuration.verifying_key_raw.trim().is_empty())
                 .then_some(RequiredConfigurationStep::PublicKey),
 
+            (self.security_configuration.security_mode == SecurityMode::P256ECDSA)
+                .then(|| self.security_configuration.verifying_key().err())
+                .flatten()
+                .filter(|error| *error != KeyError::Missing)
+                .map(RequiredConfigurationStep::PublicKeyInvalid),
+
+            (!self.memory_configuration.internal_memory_map
+                .verification_cache_allowed(self.security_configuration.security_mode))
+                .then_some(RequiredConfigurationStep::VerificationCacheUnsigned),
+
+            (!self.ram_budget().fits())
+                .then_some(RequiredConfigurationStep::RamReservationFits),
+
         ])
         .flatten()
     }
 
+    /// RAM in KB available to static variables, the stack and the heap, once the vector
+    /// table reservation (if any) is taken out of the port's RAM.
+    pub fn available_ram_kb(&self) -> u32 {
+        let budget = self.ram_budget();
+        budget.total_kb.saturating_sub(budget.vector_table_kb)
+    }
+
+    /// How the port's RAM is split between the regions this configuration reserves and
+    /// static variables.
+    pub fn ram_budget(&self) -> RamBudget {
+        RamBudget {
+            total_kb: ram_size_kb(&self.port),
+            vector_table_kb: if self.feature_configuration.ram_vector_table {
+                codegen::RAM_VECTOR_TABLE_RESERVATION_KB
+            } else {
+                0
+            },
+            stack_and_heap_kb: self
+                .memory_configuration
+                .ram_reservation
+                .as_ref()
+                .map_or(0, |r| r.total_kb()),
+        }
+    }
+
+    /// Conservative estimate of the flash space, in KB, that a release Loadstone binary
+    /// needs with this configuration's feature set. See [`footprint`] for the figures.
+    pub fn estimated_minimum_bootloader_length_kb(&self) -> u32 {
+        let features = &self.feature_configuration;
+        let recovery = matches!(features.serial, Serial::Enabled { recovery_enabled: true, .. });
+        let ecdsa = self.security_configuration.security_mode == SecurityMode::P256ECDSA;
+        #[rustfmt::skip]
+        let optional_costs = IntoIter::new([
+            (features.serial.enabled(), footprint::SERIAL_KB),
+            (recovery, footprint::RECOVERY_KB),
+            (features.serial_log_level != SerialLogLevel::Off, footprint::SERIAL_LOG_KB),
+            (ecdsa, footprint::ECDSA_KB),
+            (self.memory_configuration.external_flash.is_some(), footprint::EXTERNAL_FLASH_KB),
+        ]);
+        let optional_kb: u32 = optional_costs.filter_map(|(on, kb)| on.then_some(kb)).sum();
+        footprint::BASE_KB + optional_kb
+    }
+
+    /// Issues with the configuration that don't prevent generating a loadstone binary,
+    /// but are likely to cause problems further down the line.
+    pub fn warnings(&self) -> impl Iterator<Item = ConfigurationWarning> {
+        let estimated_minimum_kb = self.estimated_minimum_bootloader_length_kb();
+        (self.memory_configuration.internal_memory_map.bootloader_length_kb < estimated_minimum_kb)
+            .then_some(ConfigurationWarning::BootloaderTooSmall { estimated_minimum_kb })
+            .into_iter()
+    }
+
+    /// Serializes the configuration into a .ron string of the given format.
+    pub fn to_ron(&self, format: RonFormat) -> Result<String, ron::Error> {
+        match format {
+            RonFormat::Pretty => ron::ser::to_string_pretty(
+                self,
+                PrettyConfig::new()
+                    .with_indentor("    ".to_owned())
+                    .with_separate_tuple_members(true),
+            ),
+            RonFormat::Compact => ron::ser::to_string(self),
+        }
+    }
+
     /// Cleans up the configuration, enforcing all internal invariants.
     // TODO replace with typestates / type safety wherever possible, by adjusting the loadstone
     // front app to match.
@@ -84,12 +202,75 @@ impl Configuration {
             self.feature_configuration.serial = Serial::Disabled;
         }
 
+        if !self.feature_configuration.serial.enabled() {
+            self.feature_configuration.serial_log_level = SerialLogLevel::Off;
+            self.feature_configuration.serial_interrupt_rx = false;
+        }
+
         if !features::BootMetrics::timing_supported(&self.port) {
             if let BootMetrics::Enabled{timing} = &mut self.feature_configuration.boot_metrics {
                 *timing = false
             }
         }
 
+        // The boot delay relies on the same time source as boot timing metrics.
+        if !features::BootMetrics::timing_supported(&self.port) {
+            self.feature_configuration.boot_delay_ms = 0;
+        }
+
+        // So does the delay between restore retries, so they're retried back to back instead.
+        if !features::BootMetrics::timing_supported(&self.port) {
+            if let NoImageFallback::Retry { delay_ms } =
+                &mut self.feature_configuration.no_image_fallback
+            {
+                *delay_ms = 0;
+            }
+        }
+
+        if !self.feature_configuration.serial.enabled()
+            && self.feature_configuration.no_image_fallback == NoImageFallback::DiagnosticLoop
+        {
+            self.feature_configuration.no_image_fallback = NoImageFallback::Panic;
+        }
+
+        // Recovery sessions are timed with the same source, and there's none to time without
+        // serial recovery.
+        if !features::BootMetrics::timing_supported(&self.port)
+            || !matches!(self.feature_configuration.serial, Serial::Enabled {
+                recovery_enabled: true,
+                ..
+            })
+        {
+            self.feature_configuration.recovery_timeout = RecoveryTimeout::Disabled;
+        }
+
+        if self.port.vector_table_size().is_none() {
+            self.feature_configuration.ram_vector_table = false;
+        }
+
+        // The cleared region can't extend past the end of RAM.
+        if let RamClear::Enabled { offset_kb, size_kb } = &mut self.feature_configuration.ram_clear
+        {
+            let ram_kb = ram_size_kb(&self.port);
+            *offset_kb = (*offset_kb).min(ram_kb);
+            *size_kb = (*size_kb).min(ram_kb - *offset_kb);
+        }
+
+        if let StatusLed::Enabled { pin } = &self.feature_configuration.status_led {
+            if !pins::status_led(&self.port).any(|p| &p == pin) {
+                self.feature_configuration.status_led = StatusLed::Disabled;
+            }
+        }
+
+        // The recovery pin only diverts into serial recovery, so it's useless without it.
+        let serial = &self.feature_configuration.serial;
+        let recovery_enabled = matches!(serial, Serial::Enabled { recovery_enabled: true, .. });
+        if let RecoveryPin::Enabled { pin, .. } = &self.feature_configuration.recovery_pin {
+            if !recovery_enabled || !pins::recovery_pin(&self.port).any(|p| &p == pin) {
+                self.feature_configuration.recovery_pin = RecoveryPin::Disabled;
+            }
+        }
+
         if !external_flash(&self.port).any(|f| Some(f) == self.memory_configuration.external_flash)
         {
             self.memory_configuration.external_flash = None;
@@ -98,15 +279,136 @@ impl Configuration {
         if self.memory_configuration.external_flash.is_none() {
             self.memory_configuration.external_memory_map.banks.clear();
         }
+
+        if let Some(chip) = &self.memory_configuration.external_flash {
+            for bank in &mut self.memory_configuration.external_memory_map.banks {
+                bank.snap_to_erase_multiple(chip.region_size);
+            }
+        }
+
+        if !external_flash_base_addresses(&self.port)
+            .contains(&self.memory_configuration.external_memory_map.base_address)
+        {
+            self.memory_configuration.external_memory_map.base_address = 0;
+        }
+
+        self.truncate_overflowing_banks();
+
+        if let Some(bootable) = self.memory_configuration.internal_memory_map.bootable_index {
+            self.memory_configuration.golden_indices.remove(&bootable);
+        }
+
+        let sectors = internal_flash_sectors(&self.port);
+        let memory = &mut self.memory_configuration;
+        if !memory.internal_memory_map.staging_bank_valid(&memory.golden_banks()) {
+            memory.internal_memory_map.staging_index = None;
+        }
+        if !memory.internal_memory_map.staging_rotation_valid(&memory.golden_banks(), &sectors) {
+            memory.internal_memory_map.staging_rotation = None;
+        }
+        if !memory.internal_memory_map.backup_bank_valid(&memory.golden_banks()) {
+            memory.internal_memory_map.backup_index = None;
+        }
+        if !memory.internal_memory_map.verification_cache_placement_valid(&sectors) {
+            memory.internal_memory_map.verification_cache_location = None;
+        }
+
+        // There's nothing to override with if no golden bank is left.
+        if self.memory_configuration.golden_banks().is_empty() {
+            self.feature_configuration.golden_override = false;
+        }
+    }
+
+    /// Drops every bank from the first one that doesn't fit within its flash chip
+    /// onwards, keeping the bootable and golden indices pointing at the same banks.
+    fn truncate_overflowing_banks(&mut self) {
+        let memory = &mut self.memory_configuration;
+        let internal_flash = internal_flash(&self.port);
+        let fitting = |banks: &[Bank], chip: Option<&FlashChip>| {
+            banks.iter().take_while(|b| chip.map_or(false, |c| c.contains(b))).count()
+        };
+
+        let old_internal_count = memory.internal_memory_map.banks.len();
+        let internal_count = fitting(&memory.internal_memory_map.banks, Some(&internal_flash));
+        let external_count = fitting(
+            &memory.external_memory_map.banks,
+            memory.external_flash.as_ref(),
+        );
+        memory.internal_memory_map.banks.truncate(internal_count);
+        memory.external_memory_map.banks.truncate(external_count);
+
+        memory.internal_memory_map.bootable_index =
+            memory.internal_memory_map.bootable_index.filter(|i| *i < internal_count);
+        memory.golden_indices = memory
+            .golden_banks()
+            .into_iter()
+            .filter_map(|i| {
+                if i < old_internal_count {
+                    (i < internal_count).then_some(i)
+                } else {
+                    let external_index = i - old_internal_count;
+                    (external_index < external_count).then_some(internal_count + external_index)
+                }
+            })
+            .collect();
+        memory.golden_index = None;
+    }
+}
+
+/// Estimated flash footprint of Loadstone and its optional features, in KB, measured
+/// on release builds and rounded up generously. These are only meant to catch
+/// obviously undersized bootloader regions at configuration time, rather than
+/// at link time; the linker remains the final authority.
+pub mod footprint {
+    /// Core bootloader with CRC verification and no optional features.
+    pub const BASE_KB: u32 = 20;
+    /// Serial driver and command line interface.
+    pub const SERIAL_KB: u32 = 16;
+    /// XMODEM based recovery mode.
+    pub const RECOVERY_KB: u32 = 4;
+    /// Mirroring of log messages over serial.
+    pub const SERIAL_LOG_KB: u32 = 2;
+    /// P256 ECDSA verification, including the heap it requires.
+    pub const ECDSA_KB: u32 = 28;
+    /// External flash driver.
+    pub const EXTERNAL_FLASH_KB: u32 = 6;
+}
+
+/// Configuration issues worth warning about, which don't block generating a binary.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigurationWarning {
+    BootloaderTooSmall { estimated_minimum_kb: u32 },
+}
+
+impl Display for ConfigurationWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigurationWarning::BootloaderTooSmall { estimated_minimum_kb } => write!(
+                f,
+                "[Memory Map] The bootloader region is likely too small for the selected \
+                features (estimated minimum {}KB)",
+                estimated_minimum_kb
+            ),
+        }
     }
 }
 
 /// Configuration steps that may be required to properly define a loadstone binary.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum RequiredConfigurationStep {
     PublicKey,
+    /// A public key was supplied, but can't be used.
+    PublicKeyInvalid(KeyError),
+    /// The verification cache can't vouch for signed images, as its tag isn't keyed.
+    VerificationCacheUnsigned,
     SerialTxPin,
     SerialRxPin,
     BootableBank,
+    SectorAlignedBanks,
+    BootCounterSector,
+    InternalBanksFit,
+    ExternalBanksFit,
+    RamReservationFits,
 }
 
 impl Display for RequiredConfigurationStep {
@@ -115,9 +417,421 @@ impl Display for RequiredConfigurationStep {
             RequiredConfigurationStep::PublicKey => {
                 "[Security] Provide P256 ECDSA public key or enable CRC32 mode"
             }
+            RequiredConfigurationStep::PublicKeyInvalid(error) => {
+                return write!(f, "[Security] {}", error);
+            }
+            RequiredConfigurationStep::VerificationCacheUnsigned => {
+                "[Security] Disable the verification cache or enable CRC32 mode, as the cache \
+                can't tell signed images from forged ones"
+            }
             RequiredConfigurationStep::SerialTxPin => "[Features] Define Serial Tx pin",
             RequiredConfigurationStep::SerialRxPin => "[Features] Define Serial Rx pin",
             RequiredConfigurationStep::BootableBank => "[Memory Map] Define a bootable bank",
+            RequiredConfigurationStep::SectorAlignedBanks => {
+                "[Memory Map] Align all MCU banks to the start of a flash sector"
+            }
+            RequiredConfigurationStep::BootCounterSector => {
+                "[Memory Map] Place the boot counter in an MCU flash sector used by nothing else"
+            }
+            RequiredConfigurationStep::InternalBanksFit => {
+                "[Memory Map] Fit all MCU banks within the MCU flash"
+            }
+            RequiredConfigurationStep::ExternalBanksFit => {
+                "[Memory Map] Fit all external banks within the external flash chip"
+            }
+            RequiredConfigurationStep::RamReservationFits => {
+                "[Memory Map] Leave some RAM free of the stack, heap and vector table reservations"
+            }
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{features::Serial, memory::RamReservation};
+
+    fn minimal_configuration() -> Configuration {
+        let mut configuration = Configuration::default();
+        configuration.feature_configuration.serial = Serial::Disabled;
+        configuration.security_configuration.security_mode = SecurityMode::Crc;
+        configuration.memory_configuration.external_flash = None;
+        configuration
+    }
+
+    const TEST_KEY: &str = "-----BEGIN PUBLIC KEY-----\n\
+        MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEPdEmj0oKViN8nvnri0I6JZsy7PQp\n\
+        v7TUuHT5jFnFsx4xxOmA+MyGXk/fsZHnKiUfWb4smzrWxJCKKwI2vHBw8A==\n\
+        -----END PUBLIC KEY-----\n";
+
+    fn key_steps(verifying_key_raw: &str) -> Vec<RequiredConfigurationStep> {
+        let mut configuration = minimal_configuration();
+        configuration.security_configuration.security_mode = SecurityMode::P256ECDSA;
+        configuration.security_configuration.verifying_key_raw = verifying_key_raw.to_owned();
+        configuration.required_configuration_steps().collect()
+    }
+
+    #[test]
+    fn malformed_public_keys_are_flagged_with_the_reason() {
+        assert!(key_steps(TEST_KEY).is_empty());
+        assert_eq!(vec![RequiredConfigurationStep::PublicKey], key_steps(""));
+
+        // The second line of the key is missing.
+        let lines: Vec<_> = TEST_KEY.lines().collect();
+        let truncated = [lines[0], lines[1], lines[3]].join("\n");
+        assert_eq!(
+            vec![RequiredConfigurationStep::PublicKeyInvalid(KeyError::NotP256)],
+            key_steps(&truncated)
+        );
+        assert_eq!(
+            vec![RequiredConfigurationStep::PublicKeyInvalid(KeyError::NotPem)],
+            key_steps("not a key at all")
+        );
+
+        // Keys don't matter in CRC mode.
+        let mut configuration = minimal_configuration();
+        configuration.security_configuration.verifying_key_raw = "not a key at all".to_owned();
+        assert!(configuration.complete());
+    }
+
+    #[test]
+    fn verification_cache_with_signed_images_makes_the_configuration_incomplete() {
+        let mut configuration = minimal_configuration();
+        configuration.memory_configuration.internal_memory_map.verification_cache_location =
+            Some(0x0801_0000);
+        assert!(!configuration
+            .required_configuration_steps()
+            .any(|step| step == RequiredConfigurationStep::VerificationCacheUnsigned));
+
+        configuration.security_configuration.security_mode = SecurityMode::P256ECDSA;
+        configuration.security_configuration.verifying_key_raw = TEST_KEY.to_owned();
+        let steps: Vec<_> = configuration.required_configuration_steps().collect();
+        assert!(steps.contains(&RequiredConfigurationStep::VerificationCacheUnsigned));
+    }
+
+    #[test]
+    fn minimal_feature_set_needs_only_the_base_footprint() {
+        let configuration = minimal_configuration();
+        assert_eq!(footprint::BASE_KB, configuration.estimated_minimum_bootloader_length_kb());
+    }
+
+    #[test]
+    fn each_feature_adds_to_the_estimated_footprint() {
+        let mut configuration = minimal_configuration();
+        configuration.security_configuration.security_mode = SecurityMode::P256ECDSA;
+        assert_eq!(
+            footprint::BASE_KB + footprint::ECDSA_KB,
+            configuration.estimated_minimum_bootloader_length_kb()
+        );
+
+        configuration.memory_configuration.external_flash =
+            external_flash(&configuration.port).next();
+        assert_eq!(
+            footprint::BASE_KB + footprint::ECDSA_KB + footprint::EXTERNAL_FLASH_KB,
+            configuration.estimated_minimum_bootloader_length_kb()
+        );
+    }
+
+    #[test]
+    fn undersized_bootloader_region_produces_a_warning() {
+        let mut configuration = minimal_configuration();
+        configuration.memory_configuration.internal_memory_map.bootloader_length_kb =
+            footprint::BASE_KB;
+        assert_eq!(0, configuration.warnings().count());
+
+        configuration.security_configuration.security_mode = SecurityMode::P256ECDSA;
+        assert_eq!(
+            vec![ConfigurationWarning::BootloaderTooSmall {
+                estimated_minimum_kb: footprint::BASE_KB + footprint::ECDSA_KB
+            }],
+            configuration.warnings().collect::<Vec<_>>()
+        );
+    }
+
+    fn over_provisioned_configuration() -> Configuration {
+        let mut configuration = minimal_configuration();
+        let memory = &mut configuration.memory_configuration;
+        memory.internal_memory_map.banks = vec![
+            Bank { start_address: 0x0801_0000, size_kb: 64 },
+            Bank { start_address: 0x0802_0000, size_kb: 896 },
+            Bank { start_address: 0x0810_0000, size_kb: 128 },
+        ];
+        memory.internal_memory_map.bootable_index = Some(0);
+        memory.external_flash = external_flash(&configuration.port).next();
+        memory.external_memory_map.banks = vec![
+            Bank { start_address: 0x0000_0000, size_kb: 8192 },
+            Bank { start_address: 0x0080_0000, size_kb: 16384 },
+        ];
+        configuration
+    }
+
+    #[test]
+    fn over_provisioned_banks_make_the_configuration_incomplete() {
+        let mut configuration = over_provisioned_configuration();
+        let steps: Vec<_> = configuration.required_configuration_steps().collect();
+        assert!(steps.contains(&RequiredConfigurationStep::InternalBanksFit));
+        assert!(steps.contains(&RequiredConfigurationStep::ExternalBanksFit));
+        assert!(!configuration.complete());
+
+        configuration.memory_configuration.external_memory_map.banks.pop();
+        let steps: Vec<_> = configuration.required_configuration_steps().collect();
+        assert!(steps.contains(&RequiredConfigurationStep::InternalBanksFit));
+        assert!(!steps.contains(&RequiredConfigurationStep::ExternalBanksFit));
+    }
+
+    #[test]
+    fn pretty_and_compact_ron_parse_back_to_the_same_configuration() {
+        let configuration = over_provisioned_configuration();
+        let pretty = configuration.to_ron(RonFormat::Pretty).unwrap();
+        let compact = configuration.to_ron(RonFormat::Compact).unwrap();
+        assert!(pretty.lines().count() > 1);
+        assert_eq!(1, compact.lines().count());
+
+        let from_pretty: Configuration = ron::from_str(&pretty).unwrap();
+        let from_compact: Configuration = ron::from_str(&compact).unwrap();
+        assert_eq!(
+            from_pretty.to_ron(RonFormat::Compact).unwrap(),
+            from_compact.to_ron(RonFormat::Compact).unwrap()
+        );
+        assert_eq!(compact, from_compact.to_ron(RonFormat::Compact).unwrap());
+    }
+
+    #[test]
+    fn configurations_predating_optional_features_parse_with_them_off() {
+        let configuration: Configuration = ron::from_str(
+            "(port:Wgm160P,memory_configuration:(internal_memory_map:(bootloader_location:0,\
+            bootloader_length_kb:1,banks:[(start_address:4096,size_kb:4,),],\
+            bootable_index:Some(0),),external_memory_map:(banks:[],),external_flash:None,\
+            golden_index:Some(3),),feature_configuration:(serial:Disabled,\
+            boot_metrics:Enabled(timing:false,),update_signal:Enabled,greetings:Default,),\
+            security_configuration:(security_mode:Crc,verifying_key_raw:\"\",),)",
+        )
+        .unwrap();
+
+        let features = &configuration.feature_configuration;
+        assert_eq!(0, features.boot_delay_ms);
+        assert!(!features.ram_vector_table);
+        assert!(!features.bootloader_self_check);
+        assert!(!features.jump_validation);
+        assert!(!configuration.security_configuration.strict_scan);
+        let memory = &configuration.memory_configuration;
+        assert_eq!(None, memory.internal_memory_map.boot_counter_location);
+        assert_eq!(0, memory.external_memory_map.base_address);
+    }
+
+    #[test]
+    fn cleanup_resets_external_base_addresses_no_driver_honours() {
+        let mut configuration = over_provisioned_configuration();
+        configuration.memory_configuration.external_memory_map.base_address = 0x9000_0000;
+        configuration.cleanup();
+        assert_eq!(0, configuration.memory_configuration.external_memory_map.base_address);
+    }
+
+    #[test]
+    fn cleanup_snaps_external_banks_to_the_erase_size() {
+        let mut configuration = over_provisioned_configuration();
+        let memory = &mut configuration.memory_configuration;
+        memory.external_memory_map.banks = vec![Bank { start_address: 0x0000_0000, size_kb: 6 }];
+        configuration.cleanup();
+
+        let memory = &configuration.memory_configuration;
+        let region_size = memory.external_flash.as_ref().unwrap().region_size;
+        assert_eq!(8, memory.external_memory_map.banks[0].size_kb);
+        assert!(memory.external_memory_map.banks[0].is_erase_multiple(region_size));
+    }
+
+    #[test]
+    fn cleanup_truncates_overflowing_banks_and_fixes_indices() {
+        let mut configuration = over_provisioned_configuration();
+        // Golden banks in both flash chips, the external one pointing at the first external bank.
+        configuration.memory_configuration.golden_indices = [1, 3].iter().copied().collect();
+        configuration.cleanup();
+
+        let memory = &configuration.memory_configuration;
+        assert_eq!(2, memory.internal_memory_map.banks.len());
+        assert_eq!(1, memory.external_memory_map.banks.len());
+        assert_eq!(Some(0), memory.internal_memory_map.bootable_index);
+        assert_eq!(vec![1, 2], memory.golden_indices.iter().copied().collect::<Vec<_>>());
+        assert!(configuration.complete());
+    }
+
+    #[test]
+    fn cleanup_migrates_legacy_golden_index_and_spares_bootable_bank() {
+        let mut configuration = over_provisioned_configuration();
+        configuration.memory_configuration.golden_index = Some(1);
+        configuration.memory_configuration.golden_indices = [0].iter().copied().collect();
+        configuration.cleanup();
+
+        let memory = &configuration.memory_configuration;
+        assert_eq!(None, memory.golden_index);
+        assert_eq!(vec![1], memory.golden_indices.iter().copied().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn cleanup_disables_golden_override_without_golden_banks() {
+        let mut configuration = Configuration::preset(Port::Stm32F412);
+        configuration.feature_configuration.golden_override = true;
+        configuration.cleanup();
+        assert!(configuration.feature_configuration.golden_override);
+
+        configuration.memory_configuration.golden_indices.clear();
+        configuration.cleanup();
+        assert!(!configuration.feature_configuration.golden_override);
+    }
+
+    #[test]
+    fn cleanup_drops_staging_bank_that_is_not_a_regular_internal_bank() {
+        let mut configuration = over_provisioned_configuration();
+        configuration.memory_configuration.internal_memory_map.staging_index = Some(1);
+        configuration.cleanup();
+        assert_eq!(Some(1), configuration.memory_configuration.internal_memory_map.staging_index);
+
+        configuration.memory_configuration.golden_indices = [1].iter().copied().collect();
+        configuration.cleanup();
+        assert_eq!(None, configuration.memory_configuration.internal_memory_map.staging_index);
+
+        // The third internal bank doesn't fit, and is dropped along with the staging index.
+        let mut configuration = over_provisioned_configuration();
+        configuration.memory_configuration.internal_memory_map.staging_index = Some(2);
+        configuration.cleanup();
+        assert_eq!(None, configuration.memory_configuration.internal_memory_map.staging_index);
+    }
+
+    #[test]
+    fn cleanup_drops_backup_bank_that_is_not_a_regular_internal_bank() {
+        let mut configuration = over_provisioned_configuration();
+        configuration.memory_configuration.internal_memory_map.backup_index = Some(1);
+        configuration.cleanup();
+        assert_eq!(Some(1), configuration.memory_configuration.internal_memory_map.backup_index);
+
+        configuration.memory_configuration.internal_memory_map.staging_index = Some(1);
+        configuration.cleanup();
+        assert_eq!(None, configuration.memory_configuration.internal_memory_map.backup_index);
+    }
+
+    #[test]
+    fn cleanup_disables_status_led_on_pins_foreign_to_the_port() {
+        let mut configuration = minimal_configuration();
+        let pin = pins::status_led(&configuration.port).next().unwrap();
+        configuration.feature_configuration.status_led = StatusLed::Enabled { pin };
+        configuration.cleanup();
+        assert!(configuration.feature_configuration.status_led.enabled());
+
+        configuration.port = Port::Wgm160P;
+        configuration.cleanup();
+        assert!(!configuration.feature_configuration.status_led.enabled());
+    }
+
+    #[test]
+    fn cleanup_disables_recovery_pin_without_serial_recovery() {
+        let mut configuration = minimal_configuration();
+        let port = configuration.port;
+        configuration.feature_configuration.serial = Serial::Enabled {
+            recovery_enabled: true,
+            tx_pin: pins::serial_tx(&port).next().unwrap(),
+            rx_pin: pins::serial_rx(&port).next().unwrap(),
+        };
+        let pin = pins::recovery_pin(&port).next().unwrap();
+        configuration.feature_configuration.recovery_pin =
+            RecoveryPin::Enabled { pin, active_level: features::ActiveLevel::High };
+        configuration.cleanup();
+        assert!(configuration.feature_configuration.recovery_pin.enabled());
+
+        if let Serial::Enabled { recovery_enabled, .. } =
+            &mut configuration.feature_configuration.serial
+        {
+            *recovery_enabled = false;
+        }
+        configuration.cleanup();
+        assert!(!configuration.feature_configuration.recovery_pin.enabled());
+    }
+
+    #[test]
+    fn cleanup_disables_interrupt_reception_without_serial() {
+        let mut configuration = minimal_configuration();
+        configuration.feature_configuration.serial_interrupt_rx = true;
+        configuration.cleanup();
+        assert!(!configuration.feature_configuration.serial_interrupt_rx);
+    }
+
+    #[test]
+    fn cleanup_keeps_the_cleared_ram_region_within_ram() {
+        let mut configuration = minimal_configuration();
+        configuration.feature_configuration.ram_clear =
+            RamClear::Enabled { offset_kb: 64, size_kb: 1024 };
+        configuration.cleanup();
+        let ram_kb = ram_size_kb(&configuration.port);
+        assert_eq!(
+            RamClear::Enabled { offset_kb: 64, size_kb: ram_kb - 64 },
+            configuration.feature_configuration.ram_clear
+        );
+    }
+
+    #[test]
+    fn cleanup_keeps_no_image_fallbacks_within_the_port_capabilities() {
+        let mut configuration = minimal_configuration();
+        configuration.feature_configuration.no_image_fallback = NoImageFallback::DiagnosticLoop;
+        configuration.cleanup();
+        assert_eq!(NoImageFallback::Panic, configuration.feature_configuration.no_image_fallback);
+
+        let retry = NoImageFallback::Retry { delay_ms: 1000 };
+        configuration.feature_configuration.no_image_fallback = retry;
+        configuration.cleanup();
+        assert_eq!(retry, configuration.feature_configuration.no_image_fallback);
+
+        configuration.port = Port::Wgm160P;
+        configuration.cleanup();
+        assert_eq!(
+            NoImageFallback::Retry { delay_ms: 0 },
+            configuration.feature_configuration.no_image_fallback
+        );
+    }
+
+    #[test]
+    fn cleanup_disables_recovery_timeout_without_timed_serial_recovery() {
+        let timeout = RecoveryTimeout::Enabled { timeout_s: 60, reboot: true };
+        let mut configuration = Configuration::preset(Port::Stm32F412);
+        configuration.feature_configuration.recovery_timeout = timeout;
+        configuration.cleanup();
+        assert_eq!(timeout, configuration.feature_configuration.recovery_timeout);
+
+        if let Serial::Enabled { recovery_enabled, .. } =
+            &mut configuration.feature_configuration.serial
+        {
+            *recovery_enabled = false;
+        }
+        configuration.cleanup();
+        assert_eq!(RecoveryTimeout::Disabled, configuration.feature_configuration.recovery_timeout);
+
+        let mut configuration = Configuration::preset(Port::Wgm160P);
+        configuration.feature_configuration.recovery_timeout = timeout;
+        configuration.cleanup();
+        assert_eq!(RecoveryTimeout::Disabled, configuration.feature_configuration.recovery_timeout);
+    }
+
+    #[test]
+    fn over_budget_ram_reservation_is_flagged_on_a_small_ram_port() {
+        let mut configuration = Configuration::preset(Port::Wgm160P);
+        configuration.memory_configuration.ram_reservation =
+            Some(RamReservation { stack_size_kb: 64, heap_size_kb: Some(64) });
+        let budget = configuration.ram_budget();
+        assert_eq!(128, budget.total_kb);
+        assert_eq!(None, budget.free_kb());
+        assert!(configuration
+            .required_configuration_steps()
+            .any(|step| step == RequiredConfigurationStep::RamReservationFits));
+
+        // The same reservation fits in the larger RAM of another port.
+        configuration.port = Port::Stm32F412;
+        assert_eq!(Some(128), configuration.ram_budget().free_kb());
+        assert!(!configuration
+            .required_configuration_steps()
+            .any(|step| step == RequiredConfigurationStep::RamReservationFits));
+
+        // As does a smaller one on the original port, down to the last kilobyte.
+        configuration.port = Port::Wgm160P;
+        configuration.memory_configuration.ram_reservation =
+            Some(RamReservation { stack_size_kb: 64, heap_size_kb: Some(63) });
+        assert_eq!(Some(1), configuration.ram_budget().free_kb());
+    }
+}
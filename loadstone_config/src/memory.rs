@@ -1,6 +1,10 @@
+use loadstone_image_format::{
+    Algorithm, FRAME_SIZE, GOLDEN_STRING, MAGIC_STRING, NO_AUTO_UPDATE_STRING,
+};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
 
-use crate::port::Port;
+use crate::{port::Port, security::SecurityMode};
 
 /// Helper macro for kilobytes in any type (simply multiplies by 1024).
 #[macro_export(local_inner_macros)]
@@ -23,6 +27,66 @@ pub struct Bank {
 impl Bank {
     /// Address immediately after the end of this bank.
     pub fn end_address(&self) -> u32 { self.start_address + self.size_kb * 1024 }
+
+    /// Whether this bank starts at the beginning of an erasable sector. Misaligned
+    /// banks risk clobbering their neighbours when erased and rewritten.
+    pub fn is_sector_aligned(&self, sectors: &[SectorRegion]) -> bool {
+        sectors.iter().any(|r| r.is_sector_start(self.start_address))
+    }
+
+    /// Whether this bank spans a whole number of erasable regions of the given size.
+    /// Otherwise, erasing the bank's tail also erases the start of whatever follows it.
+    pub fn is_erase_multiple(&self, region_size: u32) -> bool {
+        (self.size_kb * 1024) % region_size == 0
+    }
+
+    /// Rounds the bank size to the nearest whole number of erasable regions, and to
+    /// no fewer than one.
+    pub fn snap_to_erase_multiple(&mut self, region_size: u32) {
+        let region_kb = (region_size / 1024).max(1);
+        let regions = ((self.size_kb + region_kb / 2) / region_kb).max(1);
+        self.size_kb = regions * region_kb;
+    }
+
+    /// Largest image, in bytes, that still fits the bank once signed in a security mode.
+    /// See [`trailer_overhead`].
+    pub fn usable_image_size(&self, security_mode: SecurityMode, golden: bool) -> u32 {
+        (self.size_kb * 1024).saturating_sub(trailer_overhead(security_mode, golden))
+    }
+}
+
+/// Bytes the signing tool appends to an image in a security mode: the magic string,
+/// the algorithm identifier and the CRC or signature, preceded by the golden string for
+/// golden images. The no-auto-update string and the frame are always counted, as any
+/// image may carry them.
+pub fn trailer_overhead(security_mode: SecurityMode, golden: bool) -> u32 {
+    let flags = NO_AUTO_UPDATE_STRING.len() + if golden { GOLDEN_STRING.len() } else { 0 };
+    let overhead = flags
+        + MAGIC_STRING.len()
+        + Algorithm::ID_SIZE
+        + FRAME_SIZE
+        + security_mode.algorithm().digest_size();
+    overhead as u32
+}
+
+/// Run of contiguous, equally sized erasable sectors in a flash chip.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SectorRegion {
+    /// Address of the first sector in the region.
+    pub start: u32,
+    /// Size in bytes of each sector in the region.
+    pub sector_size: u32,
+    /// Number of sectors in the region.
+    pub sector_count: u32,
+}
+
+impl SectorRegion {
+    /// Whether an address coincides with the start of a sector in this region.
+    pub fn is_sector_start(&self, address: u32) -> bool {
+        address >= self.start
+            && address < self.start + self.sector_size * self.sector_count
+            && (address - self.start) % self.sector_size == 0
+    }
 }
 
 /// Memory map for an internal (MCU) flash. This must contain the loadstone bootloader itself
@@ -33,6 +97,117 @@ pub struct InternalMemoryMap {
     pub bootloader_length_kb: u32,
     pub banks: Vec<Bank>,
     pub bootable_index: Option<usize>,
+    /// Start of the flash sector reserved for the persistent boot counter, if any.
+    #[serde(default)]
+    pub boot_counter_location: Option<u32>,
+    /// Index of the bank that serial updates are received into and verified in, before
+    /// being promoted to the bootable bank. Must be neither bootable nor golden.
+    #[serde(default)]
+    pub staging_index: Option<usize>,
+    /// Banks taking turns with the staging bank, if serial updates rotate between them.
+    #[serde(default)]
+    pub staging_rotation: Option<StagingRotation>,
+    /// Start of the flash sector that caches the last verified boot image, if any. Cached
+    /// images are booted without verifying them in full, which is faster but less safe.
+    #[serde(default)]
+    pub verification_cache_location: Option<u32>,
+    /// Index of the bank the boot image is mirrored into once the application confirms
+    /// it healthy, to be restored from should a later image fail. Must be a regular bank,
+    /// taking no part in staging serial updates.
+    #[serde(default)]
+    pub backup_index: Option<usize>,
+}
+
+/// Rotation of serial updates over several staging banks, so high update rates wear
+/// them evenly rather than always erasing the same bank. Each update is received into
+/// the bank following the last one used, recorded in a wear leveled region of flash.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StagingRotation {
+    /// Banks that take turns with the staging bank. Like the staging bank, they must be
+    /// neither bootable nor golden, and are erased after every update staged in them.
+    pub indices: BTreeSet<usize>,
+    /// Start of the flash sector that records the last bank an update was staged in.
+    pub state_location: u32,
+}
+
+impl InternalMemoryMap {
+    /// Start addresses of the flash sectors used by neither the bootloader nor any bank,
+    /// which are suitable to hold the boot counter.
+    pub fn free_sectors(&self, sectors: &[SectorRegion]) -> Vec<u32> {
+        let bootloader_end = self.bootloader_location + self.bootloader_length_kb * 1024;
+        let overlaps = |start: u32, end: u32| {
+            (start < bootloader_end && end > self.bootloader_location)
+                || self.banks.iter().any(|b| start < b.end_address() && end > b.start_address)
+        };
+        sectors
+            .iter()
+            .flat_map(|r| (0..r.sector_count).map(move |i| (r.start + i * r.sector_size, r)))
+            .filter(|(start, region)| !overlaps(*start, start + region.sector_size))
+            .map(|(start, _)| start)
+            .collect()
+    }
+
+    /// Whether the staging bank, if any, exists and is neither bootable nor golden.
+    pub fn staging_bank_valid(&self, golden_indices: &BTreeSet<usize>) -> bool {
+        self.staging_index.map_or(true, |i| {
+            i < self.banks.len() && Some(i) != self.bootable_index && !golden_indices.contains(&i)
+        })
+    }
+
+    /// Whether the backup bank, if any, exists and is neither bootable, golden nor used to
+    /// stage serial updates.
+    pub fn backup_bank_valid(&self, golden_indices: &BTreeSet<usize>) -> bool {
+        self.backup_index.map_or(true, |i| {
+            i < self.banks.len()
+                && Some(i) != self.bootable_index
+                && Some(i) != self.staging_index
+                && !golden_indices.contains(&i)
+                && !self.staging_rotation.as_ref().map_or(false, |r| r.indices.contains(&i))
+        })
+    }
+
+    /// Whether the boot counter, if enabled, sits alone at the start of a free sector.
+    pub fn boot_counter_placement_valid(&self, sectors: &[SectorRegion]) -> bool {
+        self.boot_counter_location.map_or(true, |l| self.free_sectors(sectors).contains(&l))
+    }
+
+    /// Whether the verification cache, if enabled, sits alone at the start of a free sector.
+    pub fn verification_cache_placement_valid(&self, sectors: &[SectorRegion]) -> bool {
+        self.verification_cache_location.map_or(true, |l| {
+            Some(l) != self.boot_counter_location
+                && Some(l) != self.staging_rotation.as_ref().map(|r| r.state_location)
+                && self.free_sectors(sectors).contains(&l)
+        })
+    }
+
+    /// Whether the verification cache, if enabled, can be trusted under the security mode.
+    /// Its tag isn't keyed, so anyone able to write the MCU flash can forge one, and with
+    /// signed images that would let an unsigned image boot.
+    pub fn verification_cache_allowed(&self, security_mode: SecurityMode) -> bool {
+        self.verification_cache_location.is_none() || security_mode != SecurityMode::P256ECDSA
+    }
+
+    /// Whether the staging rotation, if any, extends a staging bank with other regular
+    /// banks, and records its state alone at the start of a free sector.
+    pub fn staging_rotation_valid(
+        &self,
+        golden_indices: &BTreeSet<usize>,
+        sectors: &[SectorRegion],
+    ) -> bool {
+        self.staging_rotation.as_ref().map_or(true, |rotation| {
+            let regular = |i: &usize| {
+                *i < self.banks.len()
+                    && Some(*i) != self.bootable_index
+                    && Some(*i) != self.staging_index
+                    && !golden_indices.contains(i)
+            };
+            self.staging_index.is_some()
+                && !rotation.indices.is_empty()
+                && rotation.indices.iter().all(regular)
+                && Some(rotation.state_location) != self.boot_counter_location
+                && self.free_sectors(sectors).contains(&rotation.state_location)
+        })
+    }
 }
 
 /// Memory map for an optional external flash chip. This cannot contain a bootable
@@ -40,6 +215,15 @@ pub struct InternalMemoryMap {
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ExternalMemoryMap {
     pub banks: Vec<Bank>,
+    /// Address at which the external flash is exposed to the MCU (e.g. when the
+    /// QSPI peripheral is memory mapped). Bank addresses are relative to it.
+    #[serde(default)]
+    pub base_address: u32,
+}
+
+impl ExternalMemoryMap {
+    /// Address of a bank as seen by Loadstone, taking the base address into account.
+    pub fn absolute_address(&self, bank: &Bank) -> u32 { self.base_address + bank.start_address }
 }
 
 impl Default for InternalMemoryMap {
@@ -49,6 +233,11 @@ impl Default for InternalMemoryMap {
             bootloader_length_kb: 64,
             banks: Vec::new(),
             bootable_index: None,
+            boot_counter_location: None,
+            staging_index: None,
+            staging_rotation: None,
+            verification_cache_location: None,
+            backup_index: None,
         }
     }
 }
@@ -61,10 +250,72 @@ pub struct MemoryConfiguration {
     pub internal_memory_map: InternalMemoryMap,
     pub external_memory_map: ExternalMemoryMap,
     pub external_flash: Option<FlashChip>,
+    /// Indices of the golden banks, counting internal banks first and external banks after.
+    #[serde(default)]
+    pub golden_indices: BTreeSet<usize>,
+    /// Single golden bank index used by older configurations. It is only read, and
+    /// is folded into `golden_indices` on cleanup.
+    #[serde(default, skip_serializing)]
     pub golden_index: Option<usize>,
+    /// Stack and heap reserved at the top of RAM, if any.
+    #[serde(default)]
+    pub ram_reservation: Option<RamReservation>,
+}
+
+/// Stack and optional heap reserved at the top of RAM, out of reach of static variables.
+/// Statics that would grow into the reservation fail at link time, rather than being
+/// silently overwritten by the stack at runtime.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RamReservation {
+    /// Stack size in kilobytes. The stack starts at the very top of RAM and grows down.
+    pub stack_size_kb: u32,
+    /// Heap size in kilobytes, if any. The heap sits right below the stack.
+    pub heap_size_kb: Option<u32>,
+}
+
+impl Default for RamReservation {
+    fn default() -> Self { Self { stack_size_kb: 16, heap_size_kb: None } }
+}
+
+impl RamReservation {
+    /// Kilobytes reserved for the stack and heap together.
+    pub fn total_kb(&self) -> u32 { self.stack_size_kb + self.heap_size_kb.unwrap_or(0) }
+
+    /// Whether the reservation leaves some of `ram_size_kb` for static variables.
+    pub fn fits(&self, ram_size_kb: u32) -> bool { self.total_kb() < ram_size_kb }
+}
+
+/// Split of a port's RAM between the regions reserved out of reach of static variables
+/// and what's left for them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RamBudget {
+    /// Kilobytes of RAM on the port.
+    pub total_kb: u32,
+    /// Kilobytes reserved at the start of RAM for the application's vector table.
+    pub vector_table_kb: u32,
+    /// Kilobytes reserved at the top of RAM for the stack and heap.
+    pub stack_and_heap_kb: u32,
+}
+
+impl RamBudget {
+    /// Kilobytes taken by every reservation together.
+    pub fn reserved_kb(&self) -> u32 { self.vector_table_kb + self.stack_and_heap_kb }
+
+    /// Kilobytes left for static variables, or `None` if the reservations leave none.
+    pub fn free_kb(&self) -> Option<u32> {
+        self.total_kb.checked_sub(self.reserved_kb()).filter(|kb| *kb > 0)
+    }
+
+    /// Whether the reservations leave some RAM for static variables.
+    pub fn fits(&self) -> bool { self.free_kb().is_some() }
 }
 
 impl MemoryConfiguration {
+    /// Indices of all golden banks, including the one set by older configurations.
+    pub fn golden_banks(&self) -> BTreeSet<usize> {
+        self.golden_indices.iter().copied().chain(self.golden_index).collect()
+    }
+
     /// Address from where the application image will boot, coinciding
     /// with the start address of the bootable bank.
     pub fn bootable_address(&self) -> Option<u32> {
@@ -92,6 +343,17 @@ pub struct FlashChip {
     pub region_size: u32,
 }
 
+impl FlashChip {
+    /// Bytes of the user writable area left past an address.
+    pub fn available_space(&self, from: u32) -> u32 { self.end.saturating_sub(from) }
+
+    /// Whether a bank lies entirely within the user writable area.
+    pub fn contains(&self, bank: &Bank) -> bool {
+        let end = bank.start_address as u64 + bank.size_kb as u64 * 1024;
+        bank.start_address >= self.start && end <= self.end as u64
+    }
+}
+
 /// The MCU flash available for a port. All ports must have exactly one
 /// main MCU flash for Loadstone to correctly function.
 pub fn internal_flash(port: &Port) -> FlashChip {
@@ -113,6 +375,28 @@ pub fn internal_flash(port: &Port) -> FlashChip {
     }
 }
 
+/// Kilobytes of RAM available on a port, starting at the origin of its RAM region.
+pub fn ram_size_kb(port: &Port) -> u32 {
+    match port {
+        Port::Stm32F412 => 256,
+        Port::Wgm160P => 128,
+    }
+}
+
+/// Erasable sector layout of the MCU flash available for a port.
+pub fn internal_flash_sectors(port: &Port) -> Vec<SectorRegion> {
+    match port {
+        Port::Stm32F412 => vec![
+            SectorRegion { start: 0x0800_0000, sector_size: KB!(16), sector_count: 4 },
+            SectorRegion { start: 0x0801_0000, sector_size: KB!(64), sector_count: 1 },
+            SectorRegion { start: 0x0802_0000, sector_size: KB!(128), sector_count: 7 },
+        ],
+        Port::Wgm160P => {
+            vec![SectorRegion { start: 0x0000_0000, sector_size: KB!(4), sector_count: 512 }]
+        }
+    }
+}
+
 /// Returns an iterator over all the flash chips compatible with the current
 /// port (a driver exists for them).
 pub fn external_flash(port: &Port) -> impl Iterator<Item = FlashChip> {
@@ -128,3 +412,207 @@ pub fn external_flash(port: &Port) -> impl Iterator<Item = FlashChip> {
         Port::Wgm160P => None.into_iter(),
     }
 }
+
+/// Addresses at which external flash can be exposed to the MCU for a port.
+pub fn external_flash_base_addresses(port: &Port) -> Vec<u32> {
+    match port {
+        // Indirect QSPI access only: the n25q128a driver doesn't memory map the chip
+        // yet, so banks can't be placed in the QSPI region at 0x9000_0000.
+        Port::Stm32F412 => vec![0x0000_0000],
+        Port::Wgm160P => vec![0x0000_0000],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory_map() -> InternalMemoryMap {
+        InternalMemoryMap {
+            bootloader_location: 0x0800_0000,
+            bootloader_length_kb: 32,
+            banks: vec![Bank { start_address: 0x0800_8000, size_kb: 16 }],
+            bootable_index: Some(0),
+            boot_counter_location: None,
+            staging_index: None,
+            staging_rotation: None,
+            verification_cache_location: None,
+            backup_index: None,
+        }
+    }
+
+    #[test]
+    fn usable_image_size_accounts_for_the_trailer_of_each_security_mode() {
+        let bank = Bank { start_address: 0x0800_8000, size_kb: 16 };
+        let crc = bank.usable_image_size(SecurityMode::Crc, false);
+        let ecdsa = bank.usable_image_size(SecurityMode::P256ECDSA, false);
+        assert_eq!(KB!(16) - crc, trailer_overhead(SecurityMode::Crc, false));
+        assert_eq!(60, crc - ecdsa);
+
+        let golden_crc = bank.usable_image_size(SecurityMode::Crc, true);
+        assert_eq!(GOLDEN_STRING.len() as u32, crc - golden_crc);
+        assert_eq!(crc - ecdsa, golden_crc - bank.usable_image_size(SecurityMode::P256ECDSA, true));
+
+        let tiny = Bank { start_address: 0x0800_8000, size_kb: 0 };
+        assert_eq!(0, tiny.usable_image_size(SecurityMode::P256ECDSA, true));
+    }
+
+    #[test]
+    fn free_sectors_exclude_bootloader_and_banks() {
+        let sectors = internal_flash_sectors(&Port::Stm32F412);
+        let free = memory_map().free_sectors(&sectors);
+        assert_eq!(Some(&0x0800_C000), free.first());
+        assert!(!free.contains(&0x0800_4000));
+        assert!(!free.contains(&0x0800_8000));
+        assert!(free.contains(&0x0801_0000));
+    }
+
+    #[test]
+    fn boot_counter_must_occupy_a_free_sector() {
+        let sectors = internal_flash_sectors(&Port::Stm32F412);
+        let mut memory_map = memory_map();
+        assert!(memory_map.boot_counter_placement_valid(&sectors));
+
+        memory_map.boot_counter_location = Some(0x0801_0000);
+        assert!(memory_map.boot_counter_placement_valid(&sectors));
+
+        memory_map.boot_counter_location = Some(0x0800_8000);
+        assert!(!memory_map.boot_counter_placement_valid(&sectors));
+
+        memory_map.boot_counter_location = Some(0x0801_0004);
+        assert!(!memory_map.boot_counter_placement_valid(&sectors));
+    }
+
+    #[test]
+    fn verification_cache_needs_a_sector_of_its_own() {
+        let sectors = internal_flash_sectors(&Port::Stm32F412);
+        let mut memory_map = memory_map();
+        memory_map.verification_cache_location = Some(0x0801_0000);
+        assert!(memory_map.verification_cache_placement_valid(&sectors));
+
+        memory_map.boot_counter_location = Some(0x0801_0000);
+        assert!(!memory_map.verification_cache_placement_valid(&sectors));
+
+        memory_map.boot_counter_location = Some(0x0800_C000);
+        memory_map.verification_cache_location = Some(0x0800_8000);
+        assert!(!memory_map.verification_cache_placement_valid(&sectors));
+    }
+
+    #[test]
+    fn verification_cache_is_refused_with_signed_images() {
+        let mut memory_map = memory_map();
+        assert!(memory_map.verification_cache_allowed(SecurityMode::P256ECDSA));
+
+        memory_map.verification_cache_location = Some(0x0801_0000);
+        assert!(memory_map.verification_cache_allowed(SecurityMode::Crc));
+        assert!(!memory_map.verification_cache_allowed(SecurityMode::P256ECDSA));
+    }
+
+    #[test]
+    fn staging_rotation_needs_regular_banks_and_a_free_sector() {
+        let sectors = internal_flash_sectors(&Port::Stm32F412);
+        let mut memory_map = memory_map();
+        memory_map.banks.push(Bank { start_address: 0x0800_C000, size_kb: 16 });
+        memory_map.banks.push(Bank { start_address: 0x0801_0000, size_kb: 64 });
+        memory_map.staging_index = Some(1);
+        let rotation = |indices: &[usize], state_location: u32| {
+            Some(StagingRotation { indices: indices.iter().copied().collect(), state_location })
+        };
+        let no_golden = BTreeSet::new();
+
+        memory_map.staging_rotation = rotation(&[2], 0x0802_0000);
+        assert!(memory_map.staging_rotation_valid(&no_golden, &sectors));
+
+        // Golden, bootable and staging banks can't take turns.
+        assert!(!memory_map.staging_rotation_valid(&[2].iter().copied().collect(), &sectors));
+        memory_map.staging_rotation = rotation(&[0, 2], 0x0802_0000);
+        assert!(!memory_map.staging_rotation_valid(&no_golden, &sectors));
+        memory_map.staging_rotation = rotation(&[1, 2], 0x0802_0000);
+        assert!(!memory_map.staging_rotation_valid(&no_golden, &sectors));
+
+        // The state needs a sector of its own.
+        memory_map.staging_rotation = rotation(&[2], 0x0801_0000);
+        assert!(!memory_map.staging_rotation_valid(&no_golden, &sectors));
+        memory_map.staging_rotation = rotation(&[2], 0x0802_0000);
+        memory_map.boot_counter_location = Some(0x0802_0000);
+        assert!(!memory_map.staging_rotation_valid(&no_golden, &sectors));
+
+        // There's nothing to rotate with, without a staging bank.
+        memory_map.boot_counter_location = None;
+        memory_map.staging_index = None;
+        assert!(!memory_map.staging_rotation_valid(&no_golden, &sectors));
+    }
+
+    #[test]
+    fn backup_bank_must_be_a_regular_bank_outside_staging() {
+        let mut memory_map = memory_map();
+        memory_map.banks.push(Bank { start_address: 0x0800_C000, size_kb: 16 });
+        memory_map.banks.push(Bank { start_address: 0x0801_0000, size_kb: 64 });
+        let no_golden = BTreeSet::new();
+
+        memory_map.backup_index = Some(2);
+        assert!(memory_map.backup_bank_valid(&no_golden));
+        assert!(!memory_map.backup_bank_valid(&[2].iter().copied().collect()));
+
+        memory_map.backup_index = Some(0);
+        assert!(!memory_map.backup_bank_valid(&no_golden));
+        memory_map.backup_index = Some(3);
+        assert!(!memory_map.backup_bank_valid(&no_golden));
+
+        memory_map.backup_index = Some(1);
+        memory_map.staging_index = Some(1);
+        assert!(!memory_map.backup_bank_valid(&no_golden));
+        memory_map.staging_index = Some(2);
+        memory_map.staging_rotation = Some(StagingRotation {
+            indices: [1].iter().copied().collect(),
+            state_location: 0x0802_0000,
+        });
+        assert!(!memory_map.backup_bank_valid(&no_golden));
+    }
+
+    #[test]
+    fn external_bank_addresses_are_relative_to_base() {
+        let mut map = ExternalMemoryMap {
+            banks: vec![Bank { start_address: 0x0000_0000, size_kb: 64 }, Bank {
+                start_address: 0x0001_0000,
+                size_kb: 64,
+            }],
+            base_address: 0,
+        };
+        let addresses = |map: &ExternalMemoryMap| {
+            map.banks.iter().map(|b| map.absolute_address(b)).collect::<Vec<_>>()
+        };
+        assert_eq!(vec![0x0000_0000, 0x0001_0000], addresses(&map));
+
+        map.base_address = 0x9000_0000;
+        assert_eq!(vec![0x9000_0000, 0x9001_0000], addresses(&map));
+    }
+
+    #[test]
+    fn misaligned_bank_sizes_snap_to_the_erase_size() {
+        let mut bank = Bank { start_address: 0, size_kb: 9 };
+        assert!(!bank.is_erase_multiple(KB!(4)));
+        bank.snap_to_erase_multiple(KB!(4));
+        assert_eq!(8, bank.size_kb);
+        assert!(bank.is_erase_multiple(KB!(4)));
+
+        bank.size_kb = 1;
+        bank.snap_to_erase_multiple(KB!(4));
+        assert_eq!(4, bank.size_kb);
+
+        bank.size_kb = 14;
+        bank.snap_to_erase_multiple(KB!(4));
+        assert_eq!(16, bank.size_kb);
+    }
+
+    #[test]
+    fn banks_past_the_end_of_the_chip_are_not_contained() {
+        let chip = internal_flash(&Port::Stm32F412);
+        assert!(chip.contains(&Bank { start_address: 0x0802_0000, size_kb: 896 }));
+        assert!(!chip.contains(&Bank { start_address: 0x0802_0000, size_kb: 1024 }));
+        assert!(!chip.contains(&Bank { start_address: 0x0700_0000, size_kb: 16 }));
+        assert!(!chip.contains(&Bank { start_address: 0x0800_0000, size_kb: u32::MAX }));
+        assert_eq!(KB!(64), chip.available_space(0x080F_0000));
+        assert_eq!(0, chip.available_space(0x0900_0000));
+    }
+}
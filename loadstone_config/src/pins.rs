@@ -36,6 +36,44 @@ impl Display for PeripheralPin {
     }
 }
 
+/// A pin configured as a raw push-pull output.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct OutputPin {
+    /// Pin bank (the "B" in PB1).
+    pub bank: Bank,
+    /// Pin index (the "1" in PB1).
+    pub index: u32,
+}
+
+impl OutputPin {
+    const fn new(bank: Bank, index: u32) -> Self { Self { bank, index } }
+}
+
+impl Display for OutputPin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "P{}{}", self.bank, self.index)
+    }
+}
+
+/// A pin configured as a raw floating input.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct InputPin {
+    /// Pin bank (the "B" in PB1).
+    pub bank: Bank,
+    /// Pin index (the "1" in PB1).
+    pub index: u32,
+}
+
+impl InputPin {
+    const fn new(bank: Bank, index: u32) -> Self { Self { bank, index } }
+}
+
+impl Display for InputPin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "P{}{}", self.bank, self.index)
+    }
+}
+
 /// Returns an iterator over the possible serial transmission pins for this port.
 pub fn serial_tx(port: &Port) -> Box<dyn Iterator<Item = PeripheralPin>> {
     match port {
@@ -69,3 +107,34 @@ pub fn serial_rx(port: &Port) -> Box<dyn Iterator<Item = PeripheralPin>> {
         Port::Wgm160P => Box::new(None.into_iter()),
     }
 }
+
+/// Returns an iterator over the possible status LED pins for this port. These are
+/// the user LEDs of the port's reference boards, minus any pin that doubles as a
+/// serial pin.
+pub fn status_led(port: &Port) -> Box<dyn Iterator<Item = OutputPin>> {
+    match port {
+        Port::Stm32F412 => Box::new(IntoIter::new([
+            OutputPin::new(Cow::from("e"), 0),
+            OutputPin::new(Cow::from("e"), 1),
+            OutputPin::new(Cow::from("e"), 2),
+            OutputPin::new(Cow::from("e"), 3),
+            OutputPin::new(Cow::from("b"), 0),
+            OutputPin::new(Cow::from("b"), 14),
+        ])),
+        Port::Wgm160P => Box::new(None.into_iter()),
+    }
+}
+
+/// Returns an iterator over the possible recovery pins for this port. These are the
+/// user buttons of the port's reference boards, which are externally pulled to their
+/// inactive level, so the pins are left floating.
+pub fn recovery_pin(port: &Port) -> Box<dyn Iterator<Item = InputPin>> {
+    match port {
+        Port::Stm32F412 => Box::new(IntoIter::new([
+            InputPin::new(Cow::from("a"), 0),
+            InputPin::new(Cow::from("a"), 1),
+            InputPin::new(Cow::from("c"), 13),
+        ])),
+        Port::Wgm160P => Box::new(None.into_iter()),
+    }
+}
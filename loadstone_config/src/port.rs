@@ -1,6 +1,6 @@
 use std::fmt::Display;
 
-use crate::KB;
+use crate::{memory::ram_size_kb, KB};
 use enum_iterator::IntoEnumIterator;
 use serde::{Deserialize, Serialize};
 
@@ -82,15 +82,25 @@ impl Port {
     pub fn linker_script_constants(&self) -> Option<LinkerScriptConstants> {
         match self {
             Port::Stm32F412 => Some(LinkerScriptConstants {
-                flash: LinkerArea { origin: 0x08000000, size: KB!(896) },
-                ram: LinkerArea { origin: 0x20000000, size: KB!(256) },
+                flash: LinkerArea { origin: 0x08000000, size: KB!(1024) },
+                ram: LinkerArea { origin: 0x20000000, size: KB!(ram_size_kb(self) as usize) },
             }),
             Port::Wgm160P => Some(LinkerScriptConstants {
                 flash: LinkerArea { origin: 0x00000000, size: KB!(1024) },
-                ram: LinkerArea { origin: 0x20000000, size: KB!(128) },
+                ram: LinkerArea { origin: 0x20000000, size: KB!(ram_size_kb(self) as usize) },
             }),
         }
     }
+
+    /// Size in bytes of this port's full vector table (core exceptions followed by
+    /// device interrupts), if known.
+    pub fn vector_table_size(&self) -> Option<usize> {
+        match self {
+            // 16 core exceptions and 97 device interrupts, one word each.
+            Port::Stm32F412 => Some((16 + 97) * 4),
+            Port::Wgm160P => None,
+        }
+    }
 }
 
 /// Constants to be propagated to the linker script for this port.
@@ -0,0 +1,143 @@
+//! Recommended starting configurations for each supported port.
+//!
+//! A preset lays out the reference board of a port the way Loadstone is most commonly
+//! deployed on it: the bootloader at the start of MCU flash, a bootable bank followed by
+//! a golden bank, an update bank in external flash (if the port has one), serial over the
+//! pins routed to the board's debug probe, and CRC image verification. Every field can
+//! still be tweaked afterwards.
+
+use crate::{
+    features::{BootMetrics, FeatureConfiguration, Serial, UpdateSignal},
+    memory::{
+        external_flash, internal_flash, Bank, ExternalMemoryMap, InternalMemoryMap,
+        MemoryConfiguration,
+    },
+    pins::{self, PeripheralPin},
+    port::Port,
+    security::{SecurityConfiguration, SecurityMode},
+    Configuration, KB,
+};
+
+impl Configuration {
+    /// Recommended configuration for a port's reference board, complete enough to
+    /// generate a Loadstone binary right away.
+    pub fn preset(port: Port) -> Configuration {
+        Configuration {
+            port,
+            memory_configuration: memory_preset(port),
+            feature_configuration: feature_preset(port),
+            security_configuration: SecurityConfiguration {
+                security_mode: SecurityMode::Crc,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+fn memory_preset(port: Port) -> MemoryConfiguration {
+    let flash = internal_flash(&port);
+    let (bootable_start, bank_size_kb) = match port {
+        // The bootloader takes the four 16KB sectors, and the banks start past the
+        // 64KB sector, where sectors are 128KB.
+        Port::Stm32F412 => (0x0802_0000, 384),
+        Port::Wgm160P => (flash.start + KB!(64), 512),
+    };
+    let bootable = Bank { start_address: bootable_start, size_kb: bank_size_kb };
+    let golden = Bank { start_address: bootable.end_address(), size_kb: bank_size_kb };
+    let internal_memory_map = InternalMemoryMap {
+        bootloader_location: flash.start,
+        bootloader_length_kb: 64,
+        banks: vec![bootable, golden],
+        bootable_index: Some(0),
+        ..Default::default()
+    };
+    let external_flash = external_flash(&port).next();
+    let external_memory_map = ExternalMemoryMap {
+        banks: external_flash
+            .iter()
+            .map(|_| Bank { start_address: 0, size_kb: bank_size_kb })
+            .collect(),
+        base_address: 0,
+    };
+    MemoryConfiguration {
+        internal_memory_map,
+        external_memory_map,
+        external_flash,
+        golden_indices: [1].iter().copied().collect(),
+        ..Default::default()
+    }
+}
+
+fn feature_preset(port: Port) -> FeatureConfiguration {
+    let serial = match reference_serial_pins(port) {
+        Some((tx_pin, rx_pin)) => Serial::Enabled { recovery_enabled: true, tx_pin, rx_pin },
+        None => Serial::Disabled,
+    };
+    FeatureConfiguration {
+        serial,
+        boot_metrics: BootMetrics::Enabled { timing: BootMetrics::timing_supported(&port) },
+        update_signal: match port {
+            Port::Stm32F412 => UpdateSignal::Enabled,
+            Port::Wgm160P => UpdateSignal::Disabled,
+        },
+        jump_validation: true,
+        ..Default::default()
+    }
+}
+
+/// Serial pins routed to the virtual COM port of the port's reference board, if any.
+fn reference_serial_pins(port: Port) -> Option<(PeripheralPin, PeripheralPin)> {
+    let virtual_com_port = |pin: &PeripheralPin| pin.peripheral == "USART2" && pin.bank == "a";
+    match port {
+        Port::Stm32F412 => Some((
+            pins::serial_tx(&port).find(virtual_com_port)?,
+            pins::serial_rx(&port).find(virtual_com_port)?,
+        )),
+        Port::Wgm160P => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enum_iterator::IntoEnumIterator;
+
+    #[test]
+    fn every_preset_is_complete_in_crc_mode() {
+        for port in Port::into_enum_iter() {
+            let configuration = Configuration::preset(port);
+            assert_eq!(port, configuration.port);
+            assert_eq!(SecurityMode::Crc, configuration.security_configuration.security_mode);
+            let steps: Vec<_> = configuration.required_configuration_steps().collect();
+            assert!(steps.is_empty(), "{} preset is missing {:?}", port, steps);
+            assert_eq!(0, configuration.warnings().count(), "{} preset has warnings", port);
+        }
+    }
+
+    #[test]
+    fn presets_already_satisfy_every_invariant() {
+        for port in Port::into_enum_iter() {
+            let preset = Configuration::preset(port);
+            let mut cleaned_up = Configuration::preset(port);
+            cleaned_up.cleanup();
+            assert_eq!(
+                preset.to_ron(crate::RonFormat::Compact).unwrap(),
+                cleaned_up.to_ron(crate::RonFormat::Compact).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn stm32f412_preset_uses_the_discovery_board_serial_port() {
+        let configuration = Configuration::preset(Port::Stm32F412);
+        match configuration.feature_configuration.serial {
+            Serial::Enabled { tx_pin, rx_pin, recovery_enabled } => {
+                assert!(recovery_enabled);
+                assert_eq!("Pa2", tx_pin.to_string());
+                assert_eq!("Pa3", rx_pin.to_string());
+            }
+            Serial::Disabled => panic!("Serial should be enabled"),
+        }
+        assert!(configuration.memory_configuration.external_flash.is_some());
+    }
+}
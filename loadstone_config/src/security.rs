@@ -1,4 +1,8 @@
+use loadstone_image_format::Algorithm;
+use p256::ecdsa::VerifyingKey;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{fmt::Display, str::FromStr};
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum SecurityMode {
@@ -15,6 +19,47 @@ impl Default for SecurityMode {
     fn default() -> Self { SecurityMode::P256ECDSA }
 }
 
+impl SecurityMode {
+    /// Algorithm images are signed with in this mode, as recorded in their trailer.
+    pub fn algorithm(&self) -> Algorithm {
+        match self {
+            SecurityMode::Crc => Algorithm::Crc32,
+            SecurityMode::P256ECDSA => Algorithm::P256,
+        }
+    }
+}
+
+/// CRC32 variant used to verify images in CRC mode. Loadstone and the
+/// signing tool must agree on it for images to verify.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CrcAlgorithm {
+    /// IEEE 802.3 CRC32 (as used by zlib and Ethernet).
+    Ieee,
+    /// CRC32C (as used by iSCSI and ext4).
+    Castagnoli,
+    /// Any other reflected CRC32, defined by its polynomial in reversed form.
+    Custom { polynomial: u32 },
+}
+
+impl Default for CrcAlgorithm {
+    fn default() -> Self { CrcAlgorithm::Ieee }
+}
+
+impl CrcAlgorithm {
+    /// Polynomial in reversed (LSB first) form, as expected by the `crc` crate.
+    pub fn polynomial(&self) -> u32 {
+        match self {
+            CrcAlgorithm::Ieee => 0xedb88320,
+            CrcAlgorithm::Castagnoli => 0x82f63b78,
+            CrcAlgorithm::Custom { polynomial } => *polynomial,
+        }
+    }
+}
+
+/// Size in bytes of the smallest Cortex-M vector table: the initial stack pointer and
+/// the 15 core exception vectors.
+pub const MINIMAL_VECTOR_TABLE_SIZE: u32 = 16 * 4;
+
 /// Defines how Loadstone will aproach guaranteeing image security
 /// (integrity, secrecy and authenticity).
 #[derive(Default, Clone, Serialize, Deserialize, Debug)]
@@ -22,4 +67,122 @@ pub struct SecurityConfiguration {
     pub security_mode: SecurityMode,
     /// String format (PEM) of the verifying public key.
     pub verifying_key_raw: String,
+    /// CRC32 variant to verify images with. Only relevant in CRC mode.
+    #[serde(default)]
+    pub crc_algorithm: CrcAlgorithm,
+    /// Changes how banks whose first byte is 0xFF are scanned. In ECDSA mode they are
+    /// quickly rejected as empty unless this is set, which makes booting much faster when
+    /// some banks are erased, but wrongly rejects valid images that start with 0xFF. In
+    /// CRC mode they are scanned in full unless this is set, and only then rejected early.
+    #[serde(default)]
+    pub strict_scan: bool,
+    /// Maximum number of bytes scanned for the magic string before a bank is considered
+    /// empty. Defaults to the size of the bootable bank, as no larger image could boot.
+    #[serde(default)]
+    pub max_scan_bytes: Option<u32>,
+    /// Size in bytes below which an image is rejected, even if it verifies. Defaults to
+    /// [`MINIMAL_VECTOR_TABLE_SIZE`], as no smaller image could hold a vector table.
+    #[serde(default)]
+    pub min_image_size: Option<u32>,
+    /// Password protection of the demo app CLI's destructive commands.
+    #[serde(default)]
+    pub cli_authentication: CliAuthentication,
+}
+
+impl SecurityConfiguration {
+    /// Size in bytes of the smallest image Loadstone accepts.
+    pub fn min_image_size(&self) -> u32 { self.min_image_size.unwrap_or(MINIMAL_VECTOR_TABLE_SIZE) }
+
+    /// The verifying key, parsed as Loadstone will embed it. See [`parse_verifying_key`].
+    pub fn verifying_key(&self) -> Result<VerifyingKey, KeyError> {
+        parse_verifying_key(&self.verifying_key_raw)
+    }
+}
+
+/// Why a verifying key can't be used to check image signatures.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyError {
+    /// No key was supplied.
+    Missing,
+    /// The key isn't enclosed in PEM public key markers.
+    NotPem,
+    /// The key is PEM encoded, but doesn't hold a valid P256 point (e.g. it was
+    /// truncated, or belongs to another curve).
+    NotP256,
+}
+
+impl Display for KeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            KeyError::Missing => "No public key supplied",
+            KeyError::NotPem => {
+                "Public key must be PEM encoded, between `-----BEGIN PUBLIC KEY-----` and \
+                `-----END PUBLIC KEY-----` lines"
+            }
+            KeyError::NotP256 => {
+                "Public key is not a valid P256 point. Check it was pasted in full, and was \
+                generated for the P256 (prime256v1) curve"
+            }
+        })
+    }
+}
+
+/// Parses a PEM encoded P256 public key, as Loadstone embeds it to verify image signatures.
+pub fn parse_verifying_key(raw: &str) -> Result<VerifyingKey, KeyError> {
+    if raw.trim().is_empty() {
+        return Err(KeyError::Missing);
+    }
+    if !raw.contains("-----BEGIN PUBLIC KEY-----") || !raw.contains("-----END PUBLIC KEY-----") {
+        return Err(KeyError::NotPem);
+    }
+    VerifyingKey::from_str(raw).map_err(|_| KeyError::NotP256)
+}
+
+/// Number of leading SHA-256 digest bytes kept in a key fingerprint.
+pub const FINGERPRINT_LENGTH: usize = 8;
+
+/// Identifies a public key by the truncated SHA-256 digest of its uncompressed SEC1
+/// encoding, matching the fingerprint Loadstone's `key_fingerprint` command displays.
+pub fn fingerprint(key: &VerifyingKey) -> [u8; FINGERPRINT_LENGTH] {
+    let digest = Sha256::digest(key.to_encoded_point(false).as_bytes());
+    let mut fingerprint = [0u8; FINGERPRINT_LENGTH];
+    fingerprint.copy_from_slice(&digest[..FINGERPRINT_LENGTH]);
+    fingerprint
+}
+
+/// Size in bytes of the salt prepended to the CLI password before hashing.
+pub const CLI_SALT_SIZE: usize = 16;
+
+/// Whether the demo app's destructive CLI commands (`flash`, `format`, ...) require
+/// a `login` first. Only a salted SHA-256 hash of the password is stored, never
+/// the password itself.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CliAuthentication {
+    Disabled,
+    Enabled { salt: [u8; CLI_SALT_SIZE], hash: [u8; 32] },
+}
+
+impl Default for CliAuthentication {
+    fn default() -> Self { CliAuthentication::Disabled }
+}
+
+impl CliAuthentication {
+    /// Protects the CLI with a password, hashed with the given (random) salt.
+    pub fn with_password(password: &str, salt: [u8; CLI_SALT_SIZE]) -> Self {
+        CliAuthentication::Enabled { salt, hash: salted_hash(&salt, password) }
+    }
+
+    /// Passwords are typed as a CLI argument, so they are limited to the characters
+    /// the CLI accepts in one.
+    pub fn password_valid(password: &str) -> bool {
+        !password.is_empty() && password.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+}
+
+/// SHA-256 of the salt followed by the password, matching the check on the device.
+pub fn salted_hash(salt: &[u8], password: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(password.as_bytes());
+    hasher.finalize().into()
 }
@@ -0,0 +1,282 @@
+//! Human readable report of a whole configuration.
+//!
+//! Unlike the .ron serialization, the summary is meant to be read rather than parsed:
+//! addresses are resolved, banks are numbered the way Loadstone reports them, and only
+//! the settings relevant to the enabled features are listed. It's suited for pasting
+//! into change reviews or tickets.
+
+use std::fmt::Write;
+
+use crate::{
+    features::{
+        BootMetrics, Greetings, NoImageFallback, RamClear, RecoveryPin, RecoveryTimeout, Serial,
+        StatusLed,
+    },
+    memory::{internal_flash, Bank, FlashChip},
+    security::{fingerprint, CliAuthentication, SecurityMode},
+    Configuration,
+};
+
+impl Configuration {
+    /// Multi-line report of the port, memory map, features and security settings.
+    pub fn summary(&self) -> String {
+        let mut summary = String::new();
+        // Writing to a `String` can't fail.
+        self.write_summary(&mut summary).unwrap();
+        summary
+    }
+
+    fn write_summary(&self, f: &mut String) -> std::fmt::Result {
+        writeln!(f, "Loadstone configuration for {}", self.port)?;
+        writeln!(f)?;
+        self.write_memory_map(f)?;
+        writeln!(f)?;
+        self.write_features(f)?;
+        writeln!(f)?;
+        self.write_security(f)
+    }
+
+    fn write_memory_map(&self, f: &mut String) -> std::fmt::Result {
+        let memory = &self.memory_configuration;
+        let internal = &memory.internal_memory_map;
+        writeln!(f, "[Memory Map]")?;
+        writeln!(
+            f,
+            "* Bootloader: {} ({}KB)",
+            range(internal.bootloader_location, internal.bootloader_length_kb),
+            internal.bootloader_length_kb
+        )?;
+
+        let golden = memory.golden_banks();
+        let rotation = internal.staging_rotation.as_ref().map(|r| &r.indices);
+        let role = |index: usize| {
+            let roles = [
+                (internal.bootable_index == Some(index), "bootable"),
+                (golden.contains(&index), "golden"),
+                (internal.staging_index == Some(index), "staging"),
+                (rotation.map_or(false, |indices| indices.contains(&index)), "staging rotation"),
+                (internal.backup_index == Some(index), "backup"),
+            ];
+            let roles: Vec<_> = roles.iter().filter(|(is, _)| *is).map(|(_, role)| *role).collect();
+            if roles.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", roles.join(", "))
+            }
+        };
+
+        let internal_flash = internal_flash(&self.port);
+        let external = &memory.external_memory_map;
+        let chips: [(Option<&FlashChip>, Vec<(u32, &Bank)>); 2] = [
+            (Some(&internal_flash), internal.banks.iter().map(|b| (b.start_address, b)).collect()),
+            (
+                memory.external_flash.as_ref(),
+                external.banks.iter().map(|b| (external.absolute_address(b), b)).collect(),
+            ),
+        ];
+        // Banks are numbered across chips, with the same 1-based index as on the device.
+        let mut index = 0;
+        for (chip, banks) in chips.iter() {
+            if let Some(chip) = chip {
+                writeln!(f, "* {}:", chip.name)?;
+                for (i, (address, bank)) in banks.iter().enumerate() {
+                    writeln!(
+                        f,
+                        "  * Bank {}: {} ({}KB){}",
+                        index + i + 1,
+                        range(*address, bank.size_kb),
+                        bank.size_kb,
+                        role(index + i)
+                    )?;
+                }
+            }
+            index += banks.len();
+        }
+
+        let metadata = [
+            ("Boot counter", internal.boot_counter_location),
+            ("Verification cache", internal.verification_cache_location),
+            (
+                "Staging rotation state",
+                internal.staging_rotation.as_ref().map(|r| r.state_location),
+            ),
+        ];
+        for (name, location) in metadata.iter() {
+            if let Some(location) = location {
+                writeln!(f, "* {}: 0x{:08X}", name, location)?;
+            }
+        }
+        if let Some(reservation) = &memory.ram_reservation {
+            writeln!(
+                f,
+                "* RAM reservation: {}KB stack, {}KB heap",
+                reservation.stack_size_kb,
+                reservation.heap_size_kb.unwrap_or(0)
+            )?;
+        }
+        Ok(())
+    }
+
+    fn write_features(&self, f: &mut String) -> std::fmt::Result {
+        let features = &self.feature_configuration;
+        writeln!(f, "[Features]")?;
+        match &features.serial {
+            Serial::Enabled { recovery_enabled, tx_pin, rx_pin } => {
+                writeln!(f, "* Serial: {} (TX {}, RX {})", tx_pin.peripheral, tx_pin, rx_pin)?;
+                writeln!(f, "  * Log level: {:?}", features.serial_log_level)?;
+                writeln!(
+                    f,
+                    "  * Interrupt driven reception: {}",
+                    yes_no(features.serial_interrupt_rx)
+                )?;
+                if *recovery_enabled {
+                    writeln!(f, "  * Recovery: {:?}", features.recovery_protocol)?;
+                    if let RecoveryTimeout::Enabled { timeout_s, reboot } =
+                        features.recovery_timeout
+                    {
+                        let expiry = if reboot { "reboot" } else { "start over" };
+                        writeln!(f, "  * Recovery timeout: {}s, then {}", timeout_s, expiry)?;
+                    }
+                } else {
+                    writeln!(f, "  * Recovery: Disabled")?;
+                }
+            }
+            Serial::Disabled => writeln!(f, "* Serial: Disabled")?,
+        }
+        if let RecoveryPin::Enabled { pin, active_level } = &features.recovery_pin {
+            writeln!(f, "* Recovery pin: {} (active {:?})", pin, active_level)?;
+        }
+        let no_image_fallback = match features.no_image_fallback {
+            NoImageFallback::Panic => "Panic".to_owned(),
+            NoImageFallback::Retry { delay_ms } => format!("Retry every {}ms", delay_ms),
+            NoImageFallback::DiagnosticLoop => "Diagnostic loop".to_owned(),
+        };
+        writeln!(f, "* No image fallback: {}", no_image_fallback)?;
+        match features.boot_metrics {
+            BootMetrics::Enabled { timing } => {
+                writeln!(f, "* Boot metrics: Enabled (timing: {})", yes_no(timing))?
+            }
+            BootMetrics::Disabled => writeln!(f, "* Boot metrics: Disabled")?,
+        }
+        writeln!(f, "* Update signal: {:?}", features.update_signal)?;
+        writeln!(f, "* Boot delay: {}ms", features.boot_delay_ms)?;
+        if let StatusLed::Enabled { pin } = &features.status_led {
+            writeln!(f, "* Status LED: {}", pin)?;
+        }
+        if let Greetings::Custom { loadstone, demo } = &features.greetings {
+            writeln!(f, "* Greetings: {:?} (demo app: {:?})", loadstone, demo)?;
+        }
+        match features.ram_clear {
+            RamClear::Enabled { offset_kb, size_kb } => {
+                writeln!(f, "* RAM clear: {}KB from {}KB into RAM", size_kb, offset_kb)?
+            }
+            RamClear::Disabled => writeln!(f, "* RAM clear: Disabled")?,
+        }
+        let flags = [
+            ("RAM vector table", features.ram_vector_table),
+            ("Bootloader self check", features.bootloader_self_check),
+            ("Jump validation", features.jump_validation),
+            ("Verify writes", features.verify_writes),
+            ("Golden override", features.golden_override),
+            ("Demo app CLI", !features.exclude_cli),
+        ];
+        for (name, enabled) in flags.iter() {
+            writeln!(f, "* {}: {}", name, yes_no(*enabled))?;
+        }
+        Ok(())
+    }
+
+    fn write_security(&self, f: &mut String) -> std::fmt::Result {
+        let security = &self.security_configuration;
+        writeln!(f, "[Security]")?;
+        match security.security_mode {
+            SecurityMode::Crc => writeln!(f, "* Mode: CRC32 ({:?})", security.crc_algorithm)?,
+            SecurityMode::P256ECDSA => {
+                writeln!(f, "* Mode: P256 ECDSA")?;
+                match security.verifying_key() {
+                    Ok(key) => {
+                        let fingerprint: Vec<_> =
+                            fingerprint(&key).iter().map(|b| format!("{:02X}", b)).collect();
+                        writeln!(f, "* Key fingerprint: {}", fingerprint.join(":"))?
+                    }
+                    Err(error) => writeln!(f, "* Key fingerprint: Unavailable ({})", error)?,
+                }
+            }
+        }
+        writeln!(f, "* Strict scan: {}", yes_no(security.strict_scan))?;
+        match security.max_scan_bytes {
+            Some(bytes) => writeln!(f, "* Max scan bytes: {}", bytes)?,
+            None => writeln!(f, "* Max scan bytes: Bootable bank size")?,
+        }
+        writeln!(f, "* Min image size: {} bytes", security.min_image_size())?;
+        let cli_authentication = match security.cli_authentication {
+            CliAuthentication::Enabled { .. } => "Enabled",
+            CliAuthentication::Disabled => "Disabled",
+        };
+        writeln!(f, "* CLI authentication: {}", cli_authentication)
+    }
+}
+
+/// Address range covered by a region, end exclusive.
+fn range(start: u32, size_kb: u32) -> String {
+    format!("0x{:08X}..0x{:08X}", start, start + size_kb * 1024)
+}
+
+fn yes_no(enabled: bool) -> &'static str {
+    if enabled {
+        "Yes"
+    } else {
+        "No"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{features::RecoveryTimeout, port::Port, security::SecurityMode, Configuration};
+
+    const TEST_KEY: &str = "-----BEGIN PUBLIC KEY-----\n\
+        MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEPdEmj0oKViN8nvnri0I6JZsy7PQp\n\
+        v7TUuHT5jFnFsx4xxOmA+MyGXk/fsZHnKiUfWb4smzrWxJCKKwI2vHBw8A==\n\
+        -----END PUBLIC KEY-----\n";
+
+    #[test]
+    fn summary_lists_the_port_banks_features_and_key() {
+        let mut configuration = Configuration::preset(Port::Stm32F412);
+        configuration.memory_configuration.internal_memory_map.boot_counter_location =
+            Some(0x0801_0000);
+        configuration.security_configuration.security_mode = SecurityMode::P256ECDSA;
+        configuration.security_configuration.verifying_key_raw = TEST_KEY.to_owned();
+        configuration.feature_configuration.recovery_timeout =
+            RecoveryTimeout::Enabled { timeout_s: 300, reboot: true };
+        let summary = configuration.summary();
+
+        let expected = [
+            "Loadstone configuration for stm32f412",
+            "* Bootloader: 0x08000000..0x08010000 (64KB)",
+            "  * Bank 1: 0x08020000..0x08080000 (384KB) [bootable]",
+            "  * Bank 2: 0x08080000..0x080E0000 (384KB) [golden]",
+            "  * Bank 3: 0x00000000..0x00060000 (384KB)",
+            "* Boot counter: 0x08010000",
+            "* Serial: USART2 (TX Pa2, RX Pa3)",
+            "  * Recovery: XModem",
+            "  * Recovery timeout: 300s, then reboot",
+            "* Jump validation: Yes",
+            "* Mode: P256 ECDSA",
+            "* Min image size: 64 bytes",
+        ];
+        for line in expected.iter() {
+            assert!(summary.lines().any(|l| l == *line), "{:?} missing from:\n{}", line, summary);
+        }
+        let fingerprint = summary.lines().find_map(|l| l.strip_prefix("* Key fingerprint: "));
+        assert_eq!(Some(8 * 3 - 1), fingerprint.map(str::len));
+    }
+
+    #[test]
+    fn unusable_keys_are_reported_instead_of_a_fingerprint() {
+        let mut configuration = Configuration::preset(Port::Wgm160P);
+        configuration.security_configuration.security_mode = SecurityMode::P256ECDSA;
+        let summary = configuration.summary();
+        assert!(summary.contains("* Key fingerprint: Unavailable (No public key supplied)"));
+        assert!(summary.contains("* Serial: Disabled"));
+    }
+}
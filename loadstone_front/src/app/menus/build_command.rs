@@ -0,0 +1,42 @@
+//! This module manages the `Build Command` dropdown menu, which
+//! shows the cargo invocation that builds Loadstone locally from
+//! the current configuration, feature flags included.
+
+use eframe::egui::{Color32, Label, Ui};
+use itertools::Itertools;
+use loadstone_config::Configuration;
+
+use crate::app::menus::generate::LOCAL_OUTPUT_FILENAME;
+
+/// Command line building Loadstone from a local copy of the configuration. Builds that
+/// exclude the CLI opt out of the default flags, keeping `defmt-default`.
+pub fn cargo_command(configuration: &Configuration) -> String {
+    let (default_features, flags) = if configuration.feature_configuration.exclude_cli {
+        (" --no-default-features", vec!["defmt-default"])
+    } else {
+        ("", vec![])
+    };
+    format!(
+        "LOADSTONE_CONFIG=`cat {}` cargo build --bin loadstone{} --features \"{}\"",
+        LOCAL_OUTPUT_FILENAME,
+        default_features,
+        flags.into_iter().chain(configuration.required_feature_flags()).join(","),
+    )
+}
+
+/// Renders the cargo command implied by the current configuration, which updates
+/// as options change.
+pub fn show_build_command(ui: &mut Ui, configuration: &Configuration) {
+    ui.label(format!(
+        "Run from the Loadstone repository, next to the generated {} file:",
+        LOCAL_OUTPUT_FILENAME
+    ));
+    let command = cargo_command(configuration);
+    ui.add(Label::new(&command).code().text_color(Color32::LIGHT_BLUE));
+    ui.horizontal_wrapped(|ui| {
+        if ui.button("Copy command").clicked() {
+            ui.output().copied_text = command;
+        }
+        ui.label("Copy the command to the clipboard.");
+    });
+}
@@ -5,11 +5,10 @@
 
 use base64::write::EncoderWriter as Base64Encoder;
 use itertools::Itertools;
-use ron::ser::PrettyConfig;
 use std::{fs::OpenOptions, io::Write, sync::Arc};
 
 use anyhow::Result;
-use loadstone_config::Configuration;
+use loadstone_config::{codegen, Configuration, RonFormat};
 use reqwest_wasm::{Response, StatusCode};
 
 use futures::future::FutureExt;
@@ -30,7 +29,7 @@ const ACTIONS_URL_LEAF: &str = "loadstone/actions";
 const GITHUB_TOKEN_INSTRUCTIONS: &str = "https://docs.github.com/en/github/\
     authenticating-to-github/keeping-your-account-and-data-secure/creating-a-personal-access-token";
 
-const LOCAL_OUTPUT_FILENAME: &str = "loadstone_config.ron";
+pub const LOCAL_OUTPUT_FILENAME: &str = "loadstone_config.ron";
 
 /// Renders the image generation menu.
 pub fn generate<'a>(
@@ -40,9 +39,15 @@ pub fn generate<'a>(
     git_ref_field: &mut String,
     git_fork_field: &mut String,
     last_request_response: &mut Arc<Mutex<Option<Result<Response, reqwest_wasm::Error>>>>,
+    force_regenerate: &mut bool,
+    local_generation_error: &mut Option<String>,
+    compact_output: &mut bool,
     configuration: &Configuration,
 ) {
     if configuration.complete() {
+        for warning in configuration.warnings() {
+            ui.colored_label(Color32::YELLOW, format!("\u{26A0} {}.", warning));
+        }
         if frame.is_web() {
             ui.group(|ui| {
                 generate_in_ci(
@@ -55,10 +60,16 @@ pub fn generate<'a>(
                 );
             });
             ui.group(|ui| {
-                generate_download(ui, configuration);
+                generate_download(ui, compact_output, configuration);
             });
         } else {
-            generate_native(ui, configuration);
+            generate_native(
+                ui,
+                force_regenerate,
+                local_generation_error,
+                compact_output,
+                configuration,
+            );
         }
     } else {
         ui.label("Provide the missing configuration to generate the loadstone binary:");
@@ -68,19 +79,37 @@ pub fn generate<'a>(
     }
 }
 
+/// Pretty printed .ron files produce readable diffs when kept under version control,
+/// so compact output is only used when explicitly requested.
+fn output_format(compact_output: bool) -> RonFormat {
+    if compact_output {
+        RonFormat::Compact
+    } else {
+        RonFormat::Pretty
+    }
+}
+
+fn compact_output_checkbox(ui: &mut Ui, compact_output: &mut bool) {
+    ui.horizontal_wrapped(|ui| {
+        ui.checkbox(compact_output, "Compact output");
+        ui.label("Write the .ron file on a single line, e.g. for embedding in a script.");
+    });
+}
+
 /// Renders a link to download the finished .ron file.
-fn generate_download(ui: &mut Ui, configuration: &Configuration) {
+fn generate_download(ui: &mut Ui, compact_output: &mut bool, configuration: &Configuration) {
     ui.heading("Option 2: Local");
     ui.horizontal_wrapped(|ui| {
         if ui.button("Download").clicked() {
             download_file(
                 "loadstone_config.ron",
-                &ron::ser::to_string_pretty(&configuration, PrettyConfig::default()).unwrap(),
+                &configuration.to_ron(output_format(*compact_output)).unwrap(),
             )
             .unwrap();
         }
         ui.label("Download the .ron file to build Loadstone locally.");
     });
+    compact_output_checkbox(ui, compact_output);
 }
 
 /// Automatically triggers a Loadstone build in Github Actions. By default, this requires a
@@ -119,7 +148,8 @@ fn generate_in_ci(
     ui.horizontal_wrapped(|ui| {
         ui.set_enabled(!personal_access_token_field.is_empty());
         if ui.button("Trigger Build").clicked() {
-            let ron = ron::ser::to_string_pretty(&configuration, PrettyConfig::default())
+            let ron = configuration
+                .to_ron(RonFormat::Pretty)
                 .unwrap_or("Invalid Configuration Supplied".into());
             generate_web(&configuration, &personal_access_token_field, &git_ref_field, &git_fork_field, &ron, last_request_response)
                 .unwrap();
@@ -160,33 +190,56 @@ fn generate_in_ci(
 }
 
 /// Generates a .ron file and saves it to the current directory. This is the
-/// only available approach when running loadstone_front natively.
-fn generate_native(ui: &mut Ui, configuration: &Configuration) {
+/// only available approach when running loadstone_front natively. Optionally
+/// removes the previously autogenerated code for the port, so the next build
+/// reflects this configuration exactly.
+fn generate_native(
+    ui: &mut Ui,
+    force_regenerate: &mut bool,
+    local_generation_error: &mut Option<String>,
+    compact_output: &mut bool,
+    configuration: &Configuration,
+) {
     ui.group(|ui| {
         ui.heading("Local generation");
         ui.horizontal_wrapped(|ui| {
             if ui.button("Generate").clicked() {
-                // TODO clean up unwraps
-                let mut file = OpenOptions::new()
-                    .write(true)
-                    .create(true)
-                    .truncate(true)
-                    .open(LOCAL_OUTPUT_FILENAME)
-                    .unwrap();
-                file.write_all(
-                    ron::ser::to_string_pretty(&configuration, PrettyConfig::default())
-                        .unwrap()
-                        .as_bytes(),
-                )
-                .unwrap();
+                *local_generation_error =
+                    generate_locally(*force_regenerate, *compact_output, configuration)
+                        .err()
+                        .map(|e| e.to_string());
             }
             ui.label("Generate a");
             ui.colored_label(Color32::LIGHT_BLUE, LOCAL_OUTPUT_FILENAME);
             ui.label("file to be used locally to build Loadstone.");
         });
+        ui.horizontal_wrapped(|ui| {
+            ui.checkbox(force_regenerate, "Clean regeneration");
+            ui.label("Remove previously autogenerated code, so no stale files survive.");
+        });
+        compact_output_checkbox(ui, compact_output);
+        if let Some(error) = local_generation_error {
+            ui.colored_label(Color32::RED, format!("Failed to generate: {}", error));
+        }
     });
 }
 
+/// Writes the .ron file for a configuration to the current directory, first removing
+/// the previously autogenerated code if `force_regenerate` is set.
+fn generate_locally(
+    force_regenerate: bool,
+    compact_output: bool,
+    configuration: &Configuration,
+) -> Result<()> {
+    if force_regenerate {
+        codegen::clean_autogenerated_folder(".", &configuration.port)?;
+    }
+    let mut file =
+        OpenOptions::new().write(true).create(true).truncate(true).open(LOCAL_OUTPUT_FILENAME)?;
+    file.write_all(configuration.to_ron(output_format(compact_output))?.as_bytes())?;
+    Ok(())
+}
+
 /// Generates a loadstone image when loadstone_front is ran as a web application. Offers
 /// both a download link and an automated Github Actions CI trigger.
 fn generate_web(
@@ -1,18 +1,26 @@
-use std::cmp::{self, max};
+use std::{
+    cmp::{self, max},
+    collections::BTreeSet,
+};
 
 use crate::app::menus::memory_map::normalize::normalize;
 
 use eframe::egui::{self, Button, Color32, Label, Slider};
 use loadstone_config::{
-    memory::{self, Bank, ExternalMemoryMap, FlashChip, InternalMemoryMap},
+    memory::{
+        self, Bank, ExternalMemoryMap, FlashChip, InternalMemoryMap, RamBudget, RamReservation,
+        StagingRotation,
+    },
     port::Port,
+    security::SecurityMode,
     KB,
 };
 
 static BOOTLOADER_MAX_LENGTH_KB: u32 = 128;
 static GOLDEN_TOOLTIP: &'static str =
     "Mark this bank as golden (used as a fallback in case of corruption)\r\n \
-    Only one non-bootable bank may be golden, and only golden banks can store golden images.";
+    Any non-bootable bank may be golden, and only golden banks can store golden images.\r\n \
+    Golden banks are tried in order when restoring, internal banks first.";
 
 mod normalize;
 
@@ -24,8 +32,10 @@ pub fn configure_memory_map(
     internal_memory_map: &mut InternalMemoryMap,
     external_memory_map: &mut ExternalMemoryMap,
     external_flash: &mut Option<FlashChip>,
-    golden_index: &mut Option<usize>,
+    golden_indices: &mut BTreeSet<usize>,
     port: &Port,
+    minimum_bootloader_length_kb: u32,
+    security_mode: SecurityMode,
 ) {
     let internal_flash = memory::internal_flash(port);
 
@@ -34,7 +44,7 @@ pub fn configure_memory_map(
         external_memory_map,
         &internal_flash,
         external_flash,
-        golden_index,
+        golden_indices,
         port,
     );
 
@@ -49,10 +59,27 @@ pub fn configure_memory_map(
         ui.separator();
         ui.label("Bootloader:");
         select_bootloader_location(ui, internal_memory_map, &internal_flash);
-        select_bootloader_length(ui, internal_memory_map, &internal_flash);
+        select_bootloader_length(
+            ui,
+            internal_memory_map,
+            &internal_flash,
+            minimum_bootloader_length_kb,
+        );
         ui.label("Banks:");
         ui.separator();
-        configure_internal_banks(ui, internal_memory_map, &internal_flash, golden_index);
+        configure_internal_banks(
+            ui,
+            internal_memory_map,
+            &internal_flash,
+            golden_indices,
+            security_mode,
+        );
+        ui.separator();
+        configure_boot_counter(ui, internal_memory_map, port);
+        configure_staging_bank(ui, internal_memory_map, golden_indices);
+        configure_staging_rotation(ui, internal_memory_map, golden_indices, port);
+        configure_backup_bank(ui, internal_memory_map, golden_indices);
+        configure_verification_cache(ui, internal_memory_map, port, security_mode);
     });
 
     ui.separator();
@@ -76,6 +103,7 @@ pub fn configure_memory_map(
         ui.separator();
 
         if let Some(external_flash) = external_flash {
+            configure_external_base_address(ui, external_memory_map, port);
             ui.label("Banks:");
             ui.separator();
             configure_external_banks(
@@ -83,17 +111,328 @@ pub fn configure_memory_map(
                 external_memory_map,
                 internal_memory_map,
                 external_flash,
-                golden_index,
+                golden_indices,
+                security_mode,
             );
         }
     });
 }
 
+/// Renders the menu to reserve a stack and optional heap at the top of RAM, out of the
+/// RAM left once any vector table reservation is taken out, followed by the RAM budget.
+pub fn configure_ram_reservation(
+    ui: &mut egui::Ui,
+    ram_reservation: &mut Option<RamReservation>,
+    budget: RamBudget,
+) {
+    let available_ram_kb = budget.total_kb.saturating_sub(budget.vector_table_kb);
+    ui.group(|ui| {
+        ui.horizontal_wrapped(|ui| {
+            let mut enabled = ram_reservation.is_some();
+            ui.checkbox(&mut enabled, "Reserve stack");
+            ui.label(format!("Out of {}KB of RAM.", available_ram_kb));
+            match (enabled, ram_reservation.is_some()) {
+                (true, false) => *ram_reservation = Some(RamReservation::default()),
+                (false, true) => *ram_reservation = None,
+                _ => {}
+            }
+        });
+        let reservation = match ram_reservation {
+            Some(reservation) => reservation,
+            None => return ram_budget(ui, budget),
+        };
+        let max_kb = available_ram_kb.saturating_sub(1).max(1);
+        ui.horizontal_wrapped(|ui| {
+            ui.add(
+                Slider::new(&mut reservation.stack_size_kb, 1..=max_kb)
+                    .clamp_to_range(true)
+                    .suffix("KB"),
+            );
+            ui.label("Stack size");
+        });
+        ui.horizontal_wrapped(|ui| {
+            let mut heap = reservation.heap_size_kb.is_some();
+            ui.checkbox(&mut heap, "Heap");
+            match (heap, reservation.heap_size_kb) {
+                (true, None) => reservation.heap_size_kb = Some(1),
+                (false, Some(_)) => reservation.heap_size_kb = None,
+                _ => {}
+            }
+            if let Some(heap_size_kb) = reservation.heap_size_kb.as_mut() {
+                ui.add(Slider::new(heap_size_kb, 1..=max_kb).clamp_to_range(true).suffix("KB"));
+            }
+        });
+        if !reservation.fits(available_ram_kb) {
+            ui.colored_label(Color32::RED, "The stack and heap leave no RAM for static variables.");
+        }
+        ram_budget(ui, RamBudget { stack_and_heap_kb: reservation.total_kb(), ..budget });
+    });
+}
+
+/// Renders how much of the port's RAM is reserved, and how much is left for static
+/// variables.
+fn ram_budget(ui: &mut egui::Ui, budget: RamBudget) {
+    let free = match budget.free_kb() {
+        Some(free_kb) => format!("{}KB left for static variables", free_kb),
+        None => "nothing left for static variables".to_owned(),
+    };
+    ui.label(format!(
+        "RAM budget: {}KB of {}KB reserved (vector table {}KB, stack and heap {}KB), {}.",
+        budget.reserved_kb(),
+        budget.total_kb,
+        budget.vector_table_kb,
+        budget.stack_and_heap_kb,
+        free
+    ));
+}
+
+/// Renders the selector for the flash sector that holds the persistent boot counter,
+/// offering only the sectors left free by the bootloader and banks.
+fn configure_boot_counter(
+    ui: &mut egui::Ui,
+    internal_memory_map: &mut InternalMemoryMap,
+    port: &Port,
+) {
+    let free_sectors = internal_memory_map.free_sectors(&memory::internal_flash_sectors(port));
+    ui.horizontal_wrapped(|ui| {
+        ui.label("Boot counter:");
+        egui::ComboBox::from_id_source("boot_counter_location")
+            .selected_text(match internal_memory_map.boot_counter_location {
+                Some(location) => format!("Sector at 0x{:08x}", location),
+                None => "Disabled".to_owned(),
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(
+                    &mut internal_memory_map.boot_counter_location,
+                    None,
+                    "Disabled",
+                );
+                for sector in free_sectors.iter() {
+                    ui.selectable_value(
+                        &mut internal_memory_map.boot_counter_location,
+                        Some(*sector),
+                        format!("Sector at 0x{:08x}", sector),
+                    );
+                }
+            });
+    });
+    if free_sectors.is_empty() {
+        ui.label("Leave a flash sector free of banks to enable the persistent boot counter.");
+    }
+}
+
+/// Renders the selector for the flash sector that caches the last verified boot image,
+/// offering only the free sectors not taken by the boot counter or the staging rotation.
+fn configure_verification_cache(
+    ui: &mut egui::Ui,
+    internal_memory_map: &mut InternalMemoryMap,
+    port: &Port,
+    security_mode: SecurityMode,
+) {
+    let taken = [
+        internal_memory_map.boot_counter_location,
+        internal_memory_map.staging_rotation.as_ref().map(|r| r.state_location),
+    ];
+    let free_sectors: Vec<u32> = internal_memory_map
+        .free_sectors(&memory::internal_flash_sectors(port))
+        .into_iter()
+        .filter(|sector| !taken.contains(&Some(*sector)))
+        .collect();
+    ui.horizontal_wrapped(|ui| {
+        ui.label("Verification cache:");
+        egui::ComboBox::from_id_source("verification_cache_location")
+            .selected_text(match internal_memory_map.verification_cache_location {
+                Some(location) => format!("Sector at 0x{:08x}", location),
+                None => "Disabled".to_owned(),
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(
+                    &mut internal_memory_map.verification_cache_location,
+                    None,
+                    "Disabled",
+                );
+                for sector in free_sectors.iter() {
+                    ui.selectable_value(
+                        &mut internal_memory_map.verification_cache_location,
+                        Some(*sector),
+                        format!("Sector at 0x{:08x}", sector),
+                    );
+                }
+            });
+    });
+    if !internal_memory_map.verification_cache_allowed(security_mode) {
+        ui.colored_label(
+            Color32::RED,
+            "The cache can't tell signed images from forged ones. Disable it, or switch to \
+            CRC32 mode.",
+        );
+    } else if internal_memory_map.verification_cache_location.is_some() {
+        ui.colored_label(
+            Color32::YELLOW,
+            "Boots faster by trusting the last verified image, only checking its first bytes \
+            and trailer. Changes to the rest of the image go unnoticed.",
+        );
+    }
+}
+
+/// Renders the selector for the bank serial updates are staged in before being promoted
+/// to the bootable bank, offering only banks that are neither bootable nor golden.
+fn configure_staging_bank(
+    ui: &mut egui::Ui,
+    internal_memory_map: &mut InternalMemoryMap,
+    golden_indices: &BTreeSet<usize>,
+) {
+    let regular_banks: Vec<usize> = (0..internal_memory_map.banks.len())
+        .filter(|i| Some(*i) != internal_memory_map.bootable_index && !golden_indices.contains(i))
+        .collect();
+    ui.horizontal_wrapped(|ui| {
+        ui.label("Staging bank:");
+        egui::ComboBox::from_id_source("staging_index")
+            .selected_text(match internal_memory_map.staging_index {
+                Some(index) => format!("Bank {}", index + 1),
+                None => "Disabled".to_owned(),
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut internal_memory_map.staging_index, None, "Disabled");
+                for index in regular_banks.iter() {
+                    ui.selectable_value(
+                        &mut internal_memory_map.staging_index,
+                        Some(*index),
+                        format!("Bank {}", index + 1),
+                    );
+                }
+            });
+        ui.label("Serial updates are received here, then promoted to the bootable bank.");
+    });
+}
+
+/// Renders the selector for the bank the boot image is mirrored into once confirmed
+/// healthy, offering only regular banks that take no part in staging serial updates.
+fn configure_backup_bank(
+    ui: &mut egui::Ui,
+    internal_memory_map: &mut InternalMemoryMap,
+    golden_indices: &BTreeSet<usize>,
+) {
+    let rotation = internal_memory_map.staging_rotation.as_ref();
+    let staged = |i: &usize| {
+        Some(*i) == internal_memory_map.staging_index
+            || rotation.map_or(false, |r| r.indices.contains(i))
+    };
+    let regular_banks: Vec<usize> = (0..internal_memory_map.banks.len())
+        .filter(|i| {
+            Some(*i) != internal_memory_map.bootable_index
+                && !golden_indices.contains(i)
+                && !staged(i)
+        })
+        .collect();
+    ui.horizontal_wrapped(|ui| {
+        ui.label("Backup bank:");
+        egui::ComboBox::from_id_source("backup_index")
+            .selected_text(match internal_memory_map.backup_index {
+                Some(index) => format!("Bank {}", index + 1),
+                None => "Disabled".to_owned(),
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut internal_memory_map.backup_index, None, "Disabled");
+                for index in regular_banks.iter() {
+                    ui.selectable_value(
+                        &mut internal_memory_map.backup_index,
+                        Some(*index),
+                        format!("Bank {}", index + 1),
+                    );
+                }
+            });
+        ui.label("The boot image is mirrored here once the application confirms it healthy.");
+    });
+}
+
+/// Renders the controls to rotate serial updates between the staging bank and other
+/// regular banks, along with the free sector that records whose turn it is.
+fn configure_staging_rotation(
+    ui: &mut egui::Ui,
+    internal_memory_map: &mut InternalMemoryMap,
+    golden_indices: &BTreeSet<usize>,
+    port: &Port,
+) {
+    let candidates: Vec<usize> = (0..internal_memory_map.banks.len())
+        .filter(|i| {
+            Some(*i) != internal_memory_map.bootable_index
+                && Some(*i) != internal_memory_map.staging_index
+                && !golden_indices.contains(i)
+        })
+        .collect();
+    let boot_counter_location = internal_memory_map.boot_counter_location;
+    let free_sectors: Vec<u32> = internal_memory_map
+        .free_sectors(&memory::internal_flash_sectors(port))
+        .into_iter()
+        .filter(|sector| Some(*sector) != boot_counter_location)
+        .collect();
+    let available = internal_memory_map.staging_index.is_some()
+        && !candidates.is_empty()
+        && !free_sectors.is_empty();
+
+    ui.horizontal_wrapped(|ui| {
+        ui.set_enabled(available);
+        let mut enabled = internal_memory_map.staging_rotation.is_some();
+        ui.checkbox(&mut enabled, "Rotate staging banks");
+        match (enabled, internal_memory_map.staging_rotation.is_some()) {
+            (true, false) => {
+                internal_memory_map.staging_rotation = Some(StagingRotation {
+                    indices: candidates.iter().copied().collect(),
+                    state_location: free_sectors[0],
+                })
+            }
+            (false, true) => internal_memory_map.staging_rotation = None,
+            _ => {}
+        }
+        ui.label("Successive serial updates take turns between banks, to wear them evenly.");
+    });
+    if !available {
+        ui.label(
+            "Select a staging bank, and leave another regular bank and a free sector \
+            to rotate staging banks.",
+        );
+    }
+
+    let rotation = match internal_memory_map.staging_rotation.as_mut() {
+        Some(rotation) => rotation,
+        None => return,
+    };
+    ui.horizontal_wrapped(|ui| {
+        ui.label("Rotating with:");
+        for index in candidates.iter() {
+            let mut selected = rotation.indices.contains(index);
+            if ui.checkbox(&mut selected, format!("Bank {}", index + 1)).changed() {
+                if selected {
+                    rotation.indices.insert(*index);
+                } else if rotation.indices.len() > 1 {
+                    rotation.indices.remove(index);
+                }
+            }
+        }
+    });
+    ui.horizontal_wrapped(|ui| {
+        ui.label("Rotation state:");
+        egui::ComboBox::from_id_source("staging_rotation_state")
+            .selected_text(format!("Sector at 0x{:08x}", rotation.state_location))
+            .show_ui(ui, |ui| {
+                for sector in free_sectors.iter() {
+                    ui.selectable_value(
+                        &mut rotation.state_location,
+                        *sector,
+                        format!("Sector at 0x{:08x}", sector),
+                    );
+                }
+            });
+    });
+}
+
 fn configure_internal_banks(
     ui: &mut egui::Ui,
     internal_memory_map: &mut InternalMemoryMap,
     internal_flash: &memory::FlashChip,
-    golden_index: &mut Option<usize>,
+    golden_indices: &mut BTreeSet<usize>,
+    security_mode: SecurityMode,
 ) {
     let InternalMemoryMap { banks, bootable_index, .. } = internal_memory_map;
     let mut to_delete: Option<usize> = None;
@@ -104,8 +443,9 @@ fn configure_internal_banks(
             internal_flash,
             bootable_index,
             i,
-            golden_index,
+            golden_indices,
             &mut to_delete,
+            security_mode,
         );
     }
 
@@ -124,7 +464,7 @@ fn configure_internal_banks(
     ui.horizontal_wrapped(|ui| {
         add_internal_bank(
             ui,
-            golden_index,
+            golden_indices,
             internal_memory_map,
             bank_start_address,
             internal_flash,
@@ -134,17 +474,14 @@ fn configure_internal_banks(
 
 fn add_internal_bank(
     ui: &mut egui::Ui,
-    golden_index: &mut Option<usize>,
+    golden_indices: &mut BTreeSet<usize>,
     internal_memory_map: &mut InternalMemoryMap,
     bank_start_address: u32,
     internal_flash: &FlashChip,
 ) {
     if ui.button("Add bank").clicked() {
-        // Bump the golden index if we added a bank under the golden one
-        match golden_index {
-            Some(index) if *index >= internal_memory_map.banks.len() => *index = *index + 1,
-            _ => (),
-        };
+        // Bump the golden indices of external banks, which now sit one bank further
+        shift_golden_indices(golden_indices, internal_memory_map.banks.len(), 1);
         internal_memory_map.banks.push(Bank {
             start_address: bank_start_address,
             size_kb: internal_flash.region_size / KB!(1),
@@ -152,7 +489,7 @@ fn add_internal_bank(
     };
     ui.label(format!(
         "({}KB available space)",
-        internal_flash.end.saturating_sub(bank_start_address) / KB!(1)
+        internal_flash.available_space(bank_start_address) / KB!(1)
     ));
 }
 
@@ -162,8 +499,9 @@ fn configure_internal_bank(
     internal_flash: &FlashChip,
     bootable_index: &mut Option<usize>,
     i: usize,
-    golden_index: &mut Option<usize>,
+    golden_indices: &mut BTreeSet<usize>,
     to_delete: &mut Option<usize>,
+    security_mode: SecurityMode,
 ) {
     ui.horizontal_wrapped(|ui| {
         ui.add(
@@ -179,38 +517,52 @@ fn configure_internal_bank(
             Label::new(format!("(0x{:x} - 0x{:x})", bank.start_address, bank.end_address()))
                 .text_color(Color32::LIGHT_BLUE),
         );
+        usable_image_size(ui, bank, security_mode, golden_indices.contains(&i));
         ui.radio_value(bootable_index, Some(i), "Bootable");
         ui.scope(|ui| {
             ui.set_enabled(*bootable_index != Some(i));
-            if ui.radio(*golden_index == Some(i), "Golden").on_hover_text(GOLDEN_TOOLTIP).clicked()
-            {
-                *golden_index = match *golden_index {
-                    Some(index) if index == i => None,
-                    _ => Some(i),
-                }
-            };
+            toggle_golden(ui, golden_indices, i);
         });
         if ui.add(Button::new("Delete").text_color(Color32::RED).small()).clicked() {
             *to_delete = Some(i);
-            if let Some(index) = golden_index {
-                if i == *index {
-                    *golden_index = None;
-                } else if i < *index {
-                    *index = *index - 1
-                }
-            }
+            golden_indices.remove(&i);
+            shift_golden_indices(golden_indices, i, -1);
         };
     });
 }
 
+/// Renders the selector for the address at which the external flash is exposed to the
+/// MCU. Bank addresses are shown relative to it.
+fn configure_external_base_address(
+    ui: &mut egui::Ui,
+    external_memory_map: &mut ExternalMemoryMap,
+    port: &Port,
+) {
+    ui.horizontal_wrapped(|ui| {
+        ui.label("Base address:");
+        egui::ComboBox::from_id_source("external_flash_base_address")
+            .selected_text(format!("0x{:08x}", external_memory_map.base_address))
+            .show_ui(ui, |ui| {
+                for base_address in memory::external_flash_base_addresses(port) {
+                    ui.selectable_value(
+                        &mut external_memory_map.base_address,
+                        base_address,
+                        format!("0x{:08x}", base_address),
+                    );
+                }
+            });
+    });
+}
+
 fn configure_external_banks(
     ui: &mut egui::Ui,
     external_memory_map: &mut ExternalMemoryMap,
     internal_memory_map: &InternalMemoryMap,
     external_flash: &memory::FlashChip,
-    golden_index: &mut Option<usize>,
+    golden_indices: &mut BTreeSet<usize>,
+    security_mode: SecurityMode,
 ) {
-    let ExternalMemoryMap { banks: external_banks } = external_memory_map;
+    let ExternalMemoryMap { banks: external_banks, .. } = external_memory_map;
     let InternalMemoryMap { banks: internal_banks, .. } = internal_memory_map;
 
     let mut to_delete: Option<usize> = None;
@@ -221,8 +573,9 @@ fn configure_external_banks(
             ui,
             bank,
             external_flash,
-            golden_index,
+            golden_indices,
             &mut to_delete,
+            security_mode,
         );
     }
 
@@ -253,7 +606,7 @@ fn add_external_bank(
     };
     ui.label(format!(
         "({}KB available space)",
-        external_flash.end.saturating_sub(bank_start_address) / KB!(1)
+        external_flash.available_space(bank_start_address) / KB!(1)
     ));
 }
 
@@ -263,53 +616,74 @@ fn configure_external_bank(
     ui: &mut egui::Ui,
     bank: &mut Bank,
     external_flash: &FlashChip,
-    golden_index: &mut Option<usize>,
+    golden_indices: &mut BTreeSet<usize>,
     to_delete: &mut Option<usize>,
+    security_mode: SecurityMode,
 ) {
     let global_index = i + internal_banks.len();
     ui.horizontal_wrapped(|ui| {
+        let region_kb = external_flash.region_size / KB!(1);
+        let max_size_kb = external_flash.end.saturating_sub(bank.start_address + 1) / KB!(1);
         ui.add(
-            Slider::new(
-                &mut bank.size_kb,
-                1..=external_flash.end.saturating_sub(bank.start_address + 1) / KB!(1),
-            )
-            .clamp_to_range(true)
-            .suffix("KB"),
+            Slider::new(&mut bank.size_kb, region_kb..=max_size_kb - max_size_kb % region_kb)
+                .clamp_to_range(true)
+                .suffix("KB"),
         );
+        // Banks must span whole erasable regions, so erasing one never touches the next.
+        bank.snap_to_erase_multiple(external_flash.region_size);
         ui.label(format!("Bank {}", global_index + 1));
         ui.add(
             Label::new(format!("(0x{:x} - 0x{:x})", bank.start_address, bank.end_address()))
                 .text_color(Color32::LIGHT_BLUE),
         );
-        ui.scope(|ui| {
-            if ui
-                .radio(*golden_index == Some(global_index), "Golden")
-                .on_hover_text(GOLDEN_TOOLTIP)
-                .clicked()
-            {
-                *golden_index = match golden_index {
-                    Some(index) if *index == global_index => None,
-                    _ => Some(global_index),
-                }
-            };
-        });
+        usable_image_size(ui, bank, security_mode, golden_indices.contains(&global_index));
+        toggle_golden(ui, golden_indices, global_index);
         if ui.add(Button::new("Delete").text_color(Color32::RED).small()).clicked() {
             *to_delete = Some(i);
-            if let Some(index) = golden_index {
-                if global_index == *index {
-                    *golden_index = None;
-                } else if global_index < *index {
-                    *index = *index - 1
-                }
-            }
+            golden_indices.remove(&global_index);
+            shift_golden_indices(golden_indices, global_index, -1);
         };
     });
 }
 
+/// Renders the largest image that fits a bank once signed, which is smaller than the
+/// bank itself by the size of the image trailer.
+fn usable_image_size(ui: &mut egui::Ui, bank: &Bank, security_mode: SecurityMode, golden: bool) {
+    ui.label(format!("{} bytes for the image", bank.usable_image_size(security_mode, golden)))
+        .on_hover_text(format!(
+            "The signing tool appends {} bytes to {} images in {:?} mode.",
+            memory::trailer_overhead(security_mode, golden),
+            if golden { "golden" } else { "regular" },
+            security_mode,
+        ));
+}
+
+/// Renders the checkbox that marks a bank as golden, by its global index.
+fn toggle_golden(ui: &mut egui::Ui, golden_indices: &mut BTreeSet<usize>, index: usize) {
+    let mut golden = golden_indices.contains(&index);
+    if ui.checkbox(&mut golden, "Golden").on_hover_text(GOLDEN_TOOLTIP).changed() {
+        if golden {
+            golden_indices.insert(index);
+        } else {
+            golden_indices.remove(&index);
+        }
+    }
+}
+
+/// Moves the golden indices past `from` by `offset` banks, following a bank
+/// being added or removed at that position.
+fn shift_golden_indices(golden_indices: &mut BTreeSet<usize>, from: usize, offset: isize) {
+    *golden_indices = golden_indices
+        .iter()
+        .map(|&i| if i >= from { (i as isize + offset) as usize } else { i })
+        .collect();
+}
+
 fn select_bootloader_length(
     ui: &mut egui::Ui,
     internal_memory_map: &mut InternalMemoryMap,
     internal_flash: &memory::FlashChip,
+    minimum_bootloader_length_kb: u32,
 ) {
     ui.horizontal_wrapped(|ui| {
         ui.add(
@@ -325,11 +699,15 @@ fn select_bootloader_length(
         );
         ui.label("Bootloader allocated length");
     });
-    if internal_memory_map.bootloader_length_kb < 64 {
+    if internal_memory_map.bootloader_length_kb < minimum_bootloader_length_kb {
         ui.colored_label(
             Color32::YELLOW,
-            "You must manually ensure you've allocated enough \
-            bootloader space to hold the final compiled binary.",
+            format!(
+                "The selected features are estimated to need at least {}KB. You must \
+                manually ensure you've allocated enough bootloader space to hold the \
+                final compiled binary.",
+                minimum_bootloader_length_kb
+            ),
         );
     }
 }
@@ -1,3 +1,5 @@
+use std::collections::BTreeSet;
+
 use loadstone_config::{
     memory::{self, ExternalMemoryMap, FlashChip, InternalMemoryMap},
     port::Port,
@@ -14,13 +16,18 @@ pub fn normalize(
     external_memory_map: &mut ExternalMemoryMap,
     internal_flash: &memory::FlashChip,
     external_flash: &mut Option<memory::FlashChip>,
-    golden_index: &mut Option<usize>,
+    golden_indices: &mut BTreeSet<usize>,
     port: &Port,
 ) {
-    enforce_bootable_bank_not_golden(golden_index, internal_memory_map);
+    enforce_bootable_bank_not_golden(golden_indices, internal_memory_map);
     enforce_internal_banks_follow_bootloader(internal_memory_map, internal_flash);
     enforce_internal_banks_are_contiguous(internal_memory_map);
     enforce_internal_bank_ranges_are_maintained(internal_memory_map, internal_flash);
+    enforce_boot_counter_in_free_sector(internal_memory_map, port);
+    enforce_staging_bank_is_regular(internal_memory_map, golden_indices);
+    enforce_staging_rotation_is_valid(internal_memory_map, golden_indices, port);
+    enforce_backup_bank_is_regular(internal_memory_map, golden_indices);
+    enforce_verification_cache_in_free_sector(internal_memory_map, port);
 
     if let Some(chip) = external_flash {
         if memory::external_flash(port).any(|c| c.name == chip.name) {
@@ -33,6 +40,51 @@ pub fn normalize(
     }
 }
 
+fn enforce_boot_counter_in_free_sector(internal_memory_map: &mut InternalMemoryMap, port: &Port) {
+    if !internal_memory_map.boot_counter_placement_valid(&memory::internal_flash_sectors(port)) {
+        internal_memory_map.boot_counter_location = None;
+    }
+}
+
+fn enforce_verification_cache_in_free_sector(
+    internal_memory_map: &mut InternalMemoryMap,
+    port: &Port,
+) {
+    let sectors = memory::internal_flash_sectors(port);
+    if !internal_memory_map.verification_cache_placement_valid(&sectors) {
+        internal_memory_map.verification_cache_location = None;
+    }
+}
+
+fn enforce_staging_bank_is_regular(
+    internal_memory_map: &mut InternalMemoryMap,
+    golden_indices: &BTreeSet<usize>,
+) {
+    if !internal_memory_map.staging_bank_valid(golden_indices) {
+        internal_memory_map.staging_index = None;
+    }
+}
+
+fn enforce_staging_rotation_is_valid(
+    internal_memory_map: &mut InternalMemoryMap,
+    golden_indices: &BTreeSet<usize>,
+    port: &Port,
+) {
+    let sectors = memory::internal_flash_sectors(port);
+    if !internal_memory_map.staging_rotation_valid(golden_indices, &sectors) {
+        internal_memory_map.staging_rotation = None;
+    }
+}
+
+fn enforce_backup_bank_is_regular(
+    internal_memory_map: &mut InternalMemoryMap,
+    golden_indices: &BTreeSet<usize>,
+) {
+    if !internal_memory_map.backup_bank_valid(golden_indices) {
+        internal_memory_map.backup_index = None;
+    }
+}
+
 fn enforce_external_banks_are_contiguous(
     external_memory_map: &mut ExternalMemoryMap,
     chip: &mut FlashChip,
@@ -99,10 +151,10 @@ fn enforce_internal_banks_follow_bootloader(
 }
 
 fn enforce_bootable_bank_not_golden(
-    golden_index: &mut Option<usize>,
+    golden_indices: &mut BTreeSet<usize>,
     internal_memory_map: &mut InternalMemoryMap,
 ) {
-    if *golden_index == internal_memory_map.bootable_index {
-        *golden_index = None;
+    if let Some(bootable_index) = internal_memory_map.bootable_index {
+        golden_indices.remove(&bootable_index);
     }
 }
@@ -1,20 +1,29 @@
 use eframe::egui;
 use enum_iterator::IntoEnumIterator;
 use loadstone_config::{
-    features::{BootMetrics, Greetings},
+    features::{BootMetrics, Greetings, RamClear, StatusLed},
+    memory::ram_size_kb,
+    pins,
     port::Port,
+    Configuration,
 };
 
+pub mod build_command;
 pub mod memory_map;
 pub mod security;
 pub mod generate;
 pub mod update_signal;
 pub mod serial;
+pub mod share;
+pub mod summary;
 
-/// Renders the dropdown menu to select one of the supported
-/// hardware ports.
-pub fn select_port(ui: &mut egui::Ui, port: &mut Port) {
+const MAX_BOOT_DELAY_MS: u32 = 10_000;
+
+/// Renders the dropdown menu to select one of the supported hardware ports, along with
+/// the option to replace the whole configuration with the recommended one for its board.
+pub fn select_port(ui: &mut egui::Ui, configuration: &mut Configuration) {
     ui.horizontal_wrapped(|ui| {
+        let port = &mut configuration.port;
         egui::ComboBox::from_label(format!(
             "Family [{}] - Subfamily [{}]",
             port.family(),
@@ -26,6 +35,13 @@ pub fn select_port(ui: &mut egui::Ui, port: &mut Port) {
                 ui.selectable_value(port, port_choice, port_choice.to_string());
             }
         });
+        if ui
+            .button("Load recommended defaults for this board")
+            .on_hover_text("Replaces the entire configuration with a complete, CRC based preset.")
+            .clicked()
+        {
+            *configuration = Configuration::preset(configuration.port);
+        }
     });
 }
 
@@ -53,6 +69,129 @@ pub fn configure_boot_metrics(ui: &mut egui::Ui, boot_metrics: &mut BootMetrics,
     });
 }
 
+/// Renders the menu to configure the boot delay, a hold period before jumping to the
+/// application during which a keypress over serial diverts into recovery mode.
+pub fn configure_boot_delay(ui: &mut egui::Ui, boot_delay_ms: &mut u32, port: &Port) {
+    ui.horizontal_wrapped(|ui| {
+        ui.set_enabled(BootMetrics::timing_supported(port));
+        ui.add(egui::Slider::new(boot_delay_ms, 0..=MAX_BOOT_DELAY_MS).suffix("ms"));
+        ui.label("Boot delay. Hold before booting, allowing serial input to enter recovery.");
+    });
+}
+
+/// Renders the menu to configure the status LED, which signals the bootloader state
+/// through blink patterns, and the pin that drives it.
+pub fn configure_status_led(ui: &mut egui::Ui, status_led: &mut StatusLed, port: &Port) {
+    let mut status_led_box = status_led.enabled();
+    ui.horizontal_wrapped(|ui| {
+        ui.set_enabled(StatusLed::supported(port));
+        ui.checkbox(&mut status_led_box, "Status LED");
+        match (status_led_box, &status_led) {
+            (true, StatusLed::Disabled) => {
+                *status_led = StatusLed::Enabled { pin: pins::status_led(port).next().unwrap() }
+            }
+            (false, StatusLed::Enabled { .. }) => *status_led = StatusLed::Disabled,
+            _ => {}
+        }
+        ui.label("Blink an LED slowly while scanning, fast during recovery, solid on boot.");
+    });
+    if let StatusLed::Enabled { pin } = status_led {
+        ui.horizontal_wrapped(|ui| {
+            ui.separator();
+            egui::ComboBox::from_label("Status LED pin")
+                .selected_text(pin.to_string())
+                .show_ui(ui, |ui| {
+                    for option in pins::status_led(port) {
+                        ui.selectable_value(pin, option.clone(), option);
+                    }
+                });
+        });
+    }
+}
+
+/// Renders the menu to configure copying the application's vector table to RAM before
+/// booting, for lower interrupt latency.
+pub fn configure_ram_vector_table(ui: &mut egui::Ui, ram_vector_table: &mut bool, port: &Port) {
+    ui.horizontal_wrapped(|ui| {
+        ui.set_enabled(port.vector_table_size().is_some());
+        ui.checkbox(ram_vector_table, "RAM Vector Table");
+        ui.label("Run the application's interrupts from RAM. Reserves the first 1KB of RAM.");
+    });
+}
+
+/// Renders the menu to enable reading back images as they're copied into flash.
+pub fn configure_verify_writes(ui: &mut egui::Ui, verify_writes: &mut bool) {
+    ui.horizontal_wrapped(|ui| {
+        ui.checkbox(verify_writes, "Verify Writes");
+        ui.label("Read back images as they're copied, catching failed flash writes right away.");
+    });
+}
+
+/// Renders the menu to let golden images replace the current image, like regular ones.
+pub fn configure_golden_override(ui: &mut egui::Ui, golden_override: &mut bool, available: bool) {
+    ui.horizontal_wrapped(|ui| {
+        ui.set_enabled(available);
+        ui.checkbox(golden_override, "Golden Override");
+        ui.label("Update from golden images too, if no regular bank holds a valid image.");
+        ui.label("Needs a golden bank.");
+    });
+}
+
+/// Renders the menu to leave the demo app CLI and boot manager out of the build.
+pub fn configure_exclude_cli(ui: &mut egui::Ui, exclude_cli: &mut bool) {
+    ui.horizontal_wrapped(|ui| {
+        ui.checkbox(exclude_cli, "Exclude CLI");
+        ui.label("Leave the demo app CLI out of the build, for size. Serial recovery is kept.");
+    });
+}
+
+/// Renders the menu to enable Loadstone's check of its own flash region on boot.
+pub fn configure_bootloader_self_check(ui: &mut egui::Ui, bootloader_self_check: &mut bool) {
+    ui.horizontal_wrapped(|ui| {
+        ui.checkbox(bootloader_self_check, "Bootloader Self Check");
+        ui.label("Verify a CRC of Loadstone on boot. Build it through the signing tool's");
+        ui.label("`--bootloader` mode to append the CRC.");
+    });
+}
+
+/// Renders the menu to enable sanity checks on an image's entry point before jumping to it.
+pub fn configure_jump_validation(ui: &mut egui::Ui, jump_validation: &mut bool) {
+    ui.horizontal_wrapped(|ui| {
+        ui.checkbox(jump_validation, "Jump Validation");
+        ui.label("Refuse to boot images whose stack pointer or reset handler look invalid.");
+    });
+}
+
+/// Renders the menu to configure zeroing a region of RAM right before jumping to the
+/// application, and the region itself.
+pub fn configure_ram_clear(ui: &mut egui::Ui, ram_clear: &mut RamClear, port: &Port) {
+    let mut ram_clear_box = matches!(ram_clear, RamClear::Enabled { .. });
+    let ram_kb = ram_size_kb(port);
+    ui.horizontal_wrapped(|ui| {
+        ui.checkbox(&mut ram_clear_box, "RAM Clear");
+        match (ram_clear_box, &ram_clear) {
+            (true, RamClear::Disabled) => {
+                *ram_clear = RamClear::Enabled { offset_kb: 0, size_kb: ram_kb }
+            }
+            (false, RamClear::Enabled { .. }) => *ram_clear = RamClear::Disabled,
+            _ => {}
+        }
+        ui.label("Zero RAM before booting, so no secrets linger. Boot info is always kept.");
+    });
+    if let RamClear::Enabled { offset_kb, size_kb } = ram_clear {
+        ui.horizontal_wrapped(|ui| {
+            ui.separator();
+            ui.add(egui::Slider::new(offset_kb, 0..=ram_kb).suffix("KB"));
+            ui.label("Start, from the start of RAM.");
+        });
+        ui.horizontal_wrapped(|ui| {
+            ui.separator();
+            ui.add(egui::Slider::new(size_kb, 0..=ram_kb - *offset_kb).suffix("KB"));
+            ui.label("Size of the cleared region.");
+        });
+    }
+}
+
 /// Configures the custom greetings feature; optional strings that will be printed via
 /// serial by both Loadstone and the companion demo app. When enabled, they default to
 /// a version string containing Git and Cargo information.
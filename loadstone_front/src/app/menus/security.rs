@@ -1,13 +1,18 @@
 use eframe::egui::{self, Button, Color32};
-use loadstone_config::security::SecurityMode;
-use p256::ecdsa::VerifyingKey;
-use std::str::FromStr;
+use loadstone_config::security::{
+    parse_verifying_key, CliAuthentication, CrcAlgorithm, KeyError, SecurityMode, CLI_SALT_SIZE,
+    MINIMAL_VECTOR_TABLE_SIZE,
+};
 
 /// Renders the menu to configure security options (at the moment,
 /// `CRC` and `ECDSA` image verification.
 pub fn configure_security(
     ui: &mut egui::Ui,
     security_mode: &mut SecurityMode,
+    crc_algorithm: &mut CrcAlgorithm,
+    strict_scan: &mut bool,
+    max_scan_bytes: &mut Option<u32>,
+    min_image_size: &mut Option<u32>,
     verifying_key_raw: &mut String,
     verifying_key_text_field: &mut String,
 ) {
@@ -15,9 +20,17 @@ pub fn configure_security(
         ui.radio_value(security_mode, SecurityMode::P256ECDSA, "Enable P256 ECDSA mode.")
             .on_hover_text("Enable P256 ECDSA signature verification.");
         ui.radio_value(security_mode, SecurityMode::Crc, "Enable CRC32 mode.")
-            .on_hover_text("Disable ECDSA verification in favor of IEEE CRC32");
+            .on_hover_text("Disable ECDSA verification in favor of CRC32");
     });
 
+    ui.checkbox(strict_scan, "Strict image scanning").on_hover_text(
+        "ECDSA: scan every bank in full, even if it starts with 0xFF. Slower boot when \
+        banks are empty, but images starting with 0xFF are no longer rejected. CRC: \
+        reject banks starting with 0xFF as empty without scanning them.",
+    );
+    configure_max_scan(ui, max_scan_bytes);
+    configure_min_image_size(ui, min_image_size);
+
     match security_mode {
         SecurityMode::Crc => {
             ui.colored_label(
@@ -25,6 +38,7 @@ pub fn configure_security(
                 "WARNING: Disabling ECDSA Image Verification replaces cryptographic \
                 signatures with insecure CRC. This removes the guarantee of image authenticity.",
             );
+            configure_crc_algorithm(ui, crc_algorithm);
         }
         SecurityMode::P256ECDSA => {
             ui.label("P256 ECDSA Public Key");
@@ -42,15 +56,109 @@ pub fn configure_security(
                     *verifying_key_text_field = verifying_key_text_field
                         .replace("-----BEGIN PUBLIC KEY----- ", "-----BEGIN PUBLIC KEY-----\n")
                         .replace(" -----END PUBLIC KEY-----", "\n-----END PUBLIC KEY-----");
-                    if VerifyingKey::from_str(&verifying_key_text_field).is_ok() {
+                    if parse_verifying_key(&verifying_key_text_field).is_ok() {
                         *verifying_key_raw = verifying_key_text_field.clone();
-                    } else {
-                        *verifying_key_text_field = String::new();
                     }
                 }
 
-                ui.label("Please paste a valid public key in PEM format");
+                match parse_verifying_key(&verifying_key_text_field) {
+                    Err(KeyError::Missing) | Ok(_) => {
+                        ui.label("Please paste a valid public key in PEM format");
+                    }
+                    Err(error) => {
+                        ui.colored_label(Color32::RED, format!("\u{26A0} {}", error));
+                    }
+                }
             }
         }
     }
 }
+
+/// Renders the menu to password protect the demo app CLI's destructive commands.
+pub fn configure_cli_authentication(
+    ui: &mut egui::Ui,
+    cli_authentication: &mut CliAuthentication,
+    cli_password_field: &mut String,
+) {
+    ui.horizontal_wrapped(|ui| match *cli_authentication {
+        CliAuthentication::Enabled { .. } => {
+            ui.colored_label(Color32::GREEN, "\u{1F512} CLI password set");
+            if ui.add(Button::new("Remove").text_color(Color32::RED).small()).clicked() {
+                *cli_authentication = CliAuthentication::Disabled;
+            }
+        }
+        CliAuthentication::Disabled => {
+            ui.label("CLI password:");
+            ui.text_edit_singleline(cli_password_field).on_hover_text(
+                "Locks destructive demo app commands (e.g. `flash`, `format`) until \
+                `login password=...` is run. Letters, digits and underscores only.",
+            );
+            let valid = CliAuthentication::password_valid(cli_password_field);
+            if ui.add(Button::new("Set").enabled(valid)).clicked() {
+                let mut salt = [0u8; CLI_SALT_SIZE];
+                getrandom::getrandom(&mut salt).expect("No source of randomness for the salt.");
+                *cli_authentication = CliAuthentication::with_password(cli_password_field, salt);
+                cli_password_field.clear();
+            }
+        }
+    });
+}
+
+/// Largest scan limit offered, in KB.
+const MAX_SCAN_KB: u32 = 16 * 1024;
+/// Scan limit set when the limit is first enabled, in KB.
+const DEFAULT_MAX_SCAN_KB: u32 = 512;
+
+fn configure_max_scan(ui: &mut egui::Ui, max_scan_bytes: &mut Option<u32>) {
+    ui.horizontal_wrapped(|ui| {
+        let mut limited = max_scan_bytes.is_some();
+        ui.checkbox(&mut limited, "Limit image scan").on_hover_text(
+            "Consider a bank empty if no image is found within this many bytes. By default, \
+            scanning stops at the size of the bootable bank.",
+        );
+        match (limited, *max_scan_bytes) {
+            (true, None) => *max_scan_bytes = Some(DEFAULT_MAX_SCAN_KB * 1024),
+            (false, Some(_)) => *max_scan_bytes = None,
+            _ => {}
+        }
+        if let Some(bytes) = max_scan_bytes {
+            let mut kb = *bytes / 1024;
+            if ui.add(egui::Slider::new(&mut kb, 1..=MAX_SCAN_KB).suffix("KB")).changed() {
+                *bytes = kb * 1024;
+            }
+        }
+    });
+}
+
+/// Largest minimum image size offered, in bytes.
+const MAX_MIN_IMAGE_SIZE: u32 = 4096;
+
+fn configure_min_image_size(ui: &mut egui::Ui, min_image_size: &mut Option<u32>) {
+    ui.horizontal_wrapped(|ui| {
+        let mut custom = min_image_size.is_some();
+        ui.checkbox(&mut custom, "Custom minimum image size").on_hover_text(
+            "Reject images smaller than this many bytes, even if they verify. By default, \
+            images must at least hold a minimal Cortex-M vector table.",
+        );
+        match (custom, *min_image_size) {
+            (true, None) => *min_image_size = Some(MINIMAL_VECTOR_TABLE_SIZE),
+            (false, Some(_)) => *min_image_size = None,
+            _ => {}
+        }
+        if let Some(bytes) = min_image_size {
+            ui.add(egui::Slider::new(bytes, 0..=MAX_MIN_IMAGE_SIZE).suffix("B"));
+        }
+    });
+}
+
+fn configure_crc_algorithm(ui: &mut egui::Ui, crc_algorithm: &mut CrcAlgorithm) {
+    ui.horizontal_wrapped(|ui| {
+        ui.radio_value(crc_algorithm, CrcAlgorithm::Ieee, "IEEE")
+            .on_hover_text("Standard IEEE 802.3 CRC32 (signing tool default).");
+        ui.radio_value(crc_algorithm, CrcAlgorithm::Castagnoli, "Castagnoli")
+            .on_hover_text("CRC32C. Sign images with `signing_tool --crc castagnoli`.");
+        if let CrcAlgorithm::Custom { polynomial } = crc_algorithm {
+            ui.label(format!("Custom (polynomial 0x{:08x})", polynomial));
+        }
+    });
+}
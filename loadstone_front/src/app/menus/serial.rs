@@ -1,7 +1,11 @@
 use eframe::egui;
+use enum_iterator::IntoEnumIterator;
 use itertools::Itertools;
 use loadstone_config::{
-    features::{self, Serial},
+    features::{
+        self, ActiveLevel, BootMetrics, NoImageFallback, RecoveryPin, RecoveryProtocol,
+        RecoveryTimeout, Serial, SerialLogLevel,
+    },
     pins::{self, Peripheral, PeripheralPin},
     port::Port,
 };
@@ -141,6 +145,203 @@ fn select_recovery_mode(ui: &mut egui::Ui, recovery_enabled: &mut bool, port: &P
         ui.set_enabled(features::Serial::supported(port));
         ui.separator();
         ui.checkbox(recovery_enabled, "Serial Recovery");
-        ui.label("Allow recovering a device by sending a new image via XModem.");
+        ui.label("Allow recovering a device by sending a new image via serial.");
     });
 }
+
+/// Renders the menu to select the most verbose level of bootloader log messages
+/// that will be mirrored over serial, with a level prefix.
+pub fn configure_serial_log_level(
+    ui: &mut egui::Ui,
+    serial_log_level: &mut SerialLogLevel,
+    serial: &Serial,
+) {
+    ui.horizontal_wrapped(|ui| {
+        ui.set_enabled(serial.enabled());
+        egui::ComboBox::from_label("Serial Log Level")
+            .selected_text(format!("{:?}", serial_log_level))
+            .show_ui(ui, |ui| {
+                for level in SerialLogLevel::into_enum_iter() {
+                    ui.selectable_value(serial_log_level, level, format!("{:?}", level));
+                }
+            });
+        ui.label("Mirror bootloader log messages over serial, prefixed by their level.");
+    });
+    if !serial.enabled() {
+        *serial_log_level = SerialLogLevel::Off;
+    }
+}
+
+/// Renders the menu to buffer received serial bytes from the USART interrupt.
+pub fn configure_serial_interrupt_rx(
+    ui: &mut egui::Ui,
+    serial_interrupt_rx: &mut bool,
+    serial: &Serial,
+) {
+    ui.horizontal_wrapped(|ui| {
+        ui.set_enabled(serial.enabled());
+        ui.checkbox(serial_interrupt_rx, "Interrupt Driven Reception");
+        ui.label("Buffer received bytes from the USART interrupt, preventing overruns.");
+    });
+    if !serial.enabled() {
+        *serial_interrupt_rx = false;
+    }
+}
+
+/// Renders the menu to select the protocol used to receive images in serial recovery.
+pub fn configure_recovery_protocol(
+    ui: &mut egui::Ui,
+    recovery_protocol: &mut RecoveryProtocol,
+    serial: &Serial,
+) {
+    ui.horizontal_wrapped(|ui| {
+        ui.set_enabled(matches!(serial, Serial::Enabled { recovery_enabled: true, .. }));
+        egui::ComboBox::from_label("Recovery Protocol")
+            .selected_text(format!("{:?}", recovery_protocol))
+            .show_ui(ui, |ui| {
+                for protocol in RecoveryProtocol::into_enum_iter() {
+                    ui.selectable_value(recovery_protocol, protocol, format!("{:?}", protocol));
+                }
+            });
+        ui.label("YModem rejects images too large for the recovery bank before flashing.");
+    });
+}
+
+/// Longest recovery session timeout offered in the GUI.
+const MAX_RECOVERY_TIMEOUT_S: u32 = 3600;
+
+/// Renders the menu to bound serial recovery sessions, and to choose between rebooting
+/// and starting a new session once one times out. Requires serial recovery and a time
+/// source.
+pub fn configure_recovery_timeout(
+    ui: &mut egui::Ui,
+    recovery_timeout: &mut RecoveryTimeout,
+    serial: &Serial,
+    port: &Port,
+) {
+    let available = matches!(serial, Serial::Enabled { recovery_enabled: true, .. })
+        && BootMetrics::timing_supported(port);
+    ui.horizontal_wrapped(|ui| {
+        ui.set_enabled(available);
+        let was_enabled = matches!(recovery_timeout, RecoveryTimeout::Enabled { .. });
+        let mut enabled = was_enabled;
+        ui.checkbox(&mut enabled, "Recovery Timeout");
+        ui.label("Stop waiting for a recovery transfer after a while.");
+        if enabled != was_enabled {
+            *recovery_timeout = if enabled {
+                RecoveryTimeout::Enabled { timeout_s: 300, reboot: true }
+            } else {
+                RecoveryTimeout::Disabled
+            };
+        }
+    });
+    if let RecoveryTimeout::Enabled { timeout_s, reboot } = recovery_timeout {
+        ui.horizontal_wrapped(|ui| {
+            ui.separator();
+            ui.set_enabled(available);
+            ui.add(egui::Slider::new(timeout_s, 1..=MAX_RECOVERY_TIMEOUT_S).suffix("s"));
+            ui.checkbox(reboot, "Reboot");
+            ui.label("Reboot once the session times out, rather than starting a new one.");
+        });
+    }
+    if !available {
+        *recovery_timeout = RecoveryTimeout::Disabled;
+    }
+}
+
+/// Longest delay between restore retries offered in the GUI.
+const MAX_RETRY_DELAY_MS: u32 = 60_000;
+
+/// Renders the menu to select what Loadstone does when there's no image to boot or
+/// restore and serial recovery is disabled. The diagnostic loop requires serial, and
+/// delays between retries require a time source.
+pub fn configure_no_image_fallback(
+    ui: &mut egui::Ui,
+    no_image_fallback: &mut NoImageFallback,
+    serial: &Serial,
+    port: &Port,
+) {
+    let name = |fallback: &NoImageFallback| match fallback {
+        NoImageFallback::Panic => "Panic",
+        NoImageFallback::Retry { .. } => "Retry restoring",
+        NoImageFallback::DiagnosticLoop => "Diagnostic loop",
+    };
+    let mut options = vec![NoImageFallback::Panic, NoImageFallback::Retry { delay_ms: 1000 }];
+    if serial.enabled() {
+        options.push(NoImageFallback::DiagnosticLoop);
+    }
+    ui.horizontal_wrapped(|ui| {
+        ui.set_enabled(!matches!(serial, Serial::Enabled { recovery_enabled: true, .. }));
+        egui::ComboBox::from_label("No image fallback")
+            .selected_text(name(no_image_fallback))
+            .show_ui(ui, |ui| {
+                for option in options.iter().copied() {
+                    let selected = name(&option) == name(no_image_fallback);
+                    if ui.selectable_label(selected, name(&option)).clicked() && !selected {
+                        *no_image_fallback = option;
+                    }
+                }
+            });
+        ui.label("What to do without an image to boot, when serial recovery is disabled.");
+    });
+    if let NoImageFallback::Retry { delay_ms } = no_image_fallback {
+        ui.horizontal_wrapped(|ui| {
+            ui.separator();
+            ui.set_enabled(BootMetrics::timing_supported(port));
+            ui.add(egui::Slider::new(delay_ms, 0..=MAX_RETRY_DELAY_MS).suffix("ms"));
+            ui.label("Delay between restore attempts.");
+        });
+    }
+    if !serial.enabled() && *no_image_fallback == NoImageFallback::DiagnosticLoop {
+        *no_image_fallback = NoImageFallback::Panic;
+    }
+}
+
+/// Renders the menu to configure the recovery pin, which forces recovery mode when
+/// held at its active level during boot, and the pin and level it uses.
+pub fn configure_recovery_pin(
+    ui: &mut egui::Ui,
+    recovery_pin: &mut RecoveryPin,
+    serial: &Serial,
+    port: &Port,
+) {
+    let recovery_enabled = matches!(serial, Serial::Enabled { recovery_enabled: true, .. });
+    if !recovery_enabled {
+        *recovery_pin = RecoveryPin::Disabled;
+    }
+    let mut recovery_pin_box = recovery_pin.enabled();
+    ui.horizontal_wrapped(|ui| {
+        ui.set_enabled(recovery_enabled && RecoveryPin::supported(port));
+        ui.checkbox(&mut recovery_pin_box, "Recovery Pin");
+        match (recovery_pin_box, &recovery_pin) {
+            (true, RecoveryPin::Disabled) => {
+                *recovery_pin = RecoveryPin::Enabled {
+                    pin: pins::recovery_pin(port).next().unwrap(),
+                    active_level: ActiveLevel::High,
+                }
+            }
+            (false, RecoveryPin::Enabled { .. }) => *recovery_pin = RecoveryPin::Disabled,
+            _ => {}
+        }
+        ui.label("Enter recovery mode if the pin is held at its active level during boot.");
+    });
+    if let RecoveryPin::Enabled { pin, active_level } = recovery_pin {
+        ui.horizontal_wrapped(|ui| {
+            ui.separator();
+            egui::ComboBox::from_label("Recovery trigger pin")
+                .selected_text(pin.to_string())
+                .show_ui(ui, |ui| {
+                    for option in pins::recovery_pin(port) {
+                        ui.selectable_value(pin, option.clone(), option);
+                    }
+                });
+            egui::ComboBox::from_label("Active level")
+                .selected_text(format!("{:?}", active_level))
+                .show_ui(ui, |ui| {
+                    for level in ActiveLevel::into_enum_iter() {
+                        ui.selectable_value(active_level, level, format!("{:?}", level));
+                    }
+                });
+        });
+    }
+}
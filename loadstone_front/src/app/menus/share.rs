@@ -0,0 +1,70 @@
+//! This module manages the `Share` dropdown menu, where the
+//! whole Loadstone configuration can be packed into a link
+//! and sent to a colleague, or unpacked from a link received
+//! from somebody else.
+
+use anyhow::{anyhow, Result};
+use eframe::egui::{Color32, Ui};
+use loadstone_config::{Configuration, RonFormat};
+
+const PUBLISHED_APP_URL: &str = "https://absw.github.io/loadstone/loadstone_front/published_app/";
+const FRAGMENT_PREFIX: &str = "#config=";
+const COMPRESSION_LEVEL: u8 = 9;
+
+/// Packs a configuration into a URL fragment (RON, deflated, then URL-safe base64).
+pub fn encode(configuration: &Configuration) -> Result<String> {
+    let ron = configuration.to_ron(RonFormat::Compact)?;
+    let compressed = miniz_oxide::deflate::compress_to_vec(ron.as_bytes(), COMPRESSION_LEVEL);
+    Ok(format!("{}{}", FRAGMENT_PREFIX, base64::encode_config(compressed, base64::URL_SAFE_NO_PAD)))
+}
+
+/// Unpacks a configuration from a full link or a bare URL fragment.
+pub fn decode(link: &str) -> Result<Configuration> {
+    let fragment = link
+        .find(FRAGMENT_PREFIX)
+        .map(|position| &link[position + FRAGMENT_PREFIX.len()..])
+        .ok_or(anyhow!("Link doesn't contain a Loadstone configuration."))?;
+    let compressed = base64::decode_config(fragment.trim(), base64::URL_SAFE_NO_PAD)?;
+    let ron = miniz_oxide::inflate::decompress_to_vec(&compressed)
+        .map_err(|_| anyhow!("Configuration link is corrupted."))?;
+    Ok(ron::de::from_bytes(&ron)?)
+}
+
+/// Renders the menu to copy the current configuration as a link, or to replace it with
+/// one decoded from a link.
+pub fn share(
+    ui: &mut Ui,
+    configuration: &mut Configuration,
+    configuration_link_field: &mut String,
+    configuration_link_error: &mut Option<String>,
+) {
+    ui.horizontal_wrapped(|ui| {
+        if ui.button("Copy config link").clicked() {
+            match encode(configuration) {
+                Ok(fragment) => {
+                    ui.output().copied_text = format!("{}{}", PUBLISHED_APP_URL, fragment);
+                    *configuration_link_error = None;
+                }
+                Err(e) => *configuration_link_error = Some(e.to_string()),
+            }
+        }
+        ui.label("Copy a link to this exact configuration to the clipboard.");
+    });
+    ui.horizontal_wrapped(|ui| {
+        ui.text_edit_singleline(configuration_link_field);
+        ui.set_enabled(!configuration_link_field.is_empty());
+        if ui.button("Load from link").clicked() {
+            match decode(configuration_link_field) {
+                Ok(decoded) => {
+                    *configuration = decoded;
+                    *configuration_link_error = None;
+                }
+                Err(e) => *configuration_link_error = Some(e.to_string()),
+            }
+            configuration_link_field.clear();
+        }
+    });
+    if let Some(error) = configuration_link_error {
+        ui.colored_label(Color32::RED, format!("Failed to load configuration link: {}", error));
+    }
+}
@@ -0,0 +1,18 @@
+//! This module manages the `Summary` dropdown menu, which shows a human
+//! readable report of the whole configuration, suitable for pasting into
+//! reviews or tickets.
+
+use eframe::egui::{Label, Ui};
+use loadstone_config::Configuration;
+
+/// Renders the configuration summary, which updates as options change.
+pub fn show_summary(ui: &mut Ui, configuration: &Configuration) {
+    let summary = configuration.summary();
+    ui.add(Label::new(&summary).code());
+    ui.horizontal_wrapped(|ui| {
+        if ui.button("Copy summary").clicked() {
+            ui.output().copied_text = summary;
+        }
+        ui.label("Copy the summary to the clipboard.");
+    });
+}
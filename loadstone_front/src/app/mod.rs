@@ -1,13 +1,23 @@
 use std::sync::Arc;
 
 use self::menus::{
-    configure_boot_metrics, memory_map::configure_memory_map, security::configure_security,
-    select_port,
+    configure_boot_delay, configure_boot_metrics, configure_bootloader_self_check,
+    configure_exclude_cli, configure_golden_override,
+    configure_jump_validation,
+    configure_ram_clear, configure_ram_vector_table, configure_status_led,
+    configure_verify_writes,
+    memory_map::{configure_memory_map, configure_ram_reservation},
+    security::{configure_cli_authentication, configure_security}, select_port,
 };
 
 use crate::app::menus::{
-    generate, update_signal::configure_update_signal,
-    serial::configure_serial, configure_custom_greetings
+    build_command, generate, share, summary, update_signal::configure_update_signal,
+    serial::{
+        configure_no_image_fallback, configure_recovery_pin, configure_recovery_protocol,
+        configure_recovery_timeout, configure_serial, configure_serial_interrupt_rx,
+        configure_serial_log_level,
+    },
+    configure_custom_greetings
 };
 
 use eframe::{
@@ -31,9 +41,20 @@ mod utilities;
 pub struct LoadstoneApp {
     configuration: Configuration,
     verifying_key_text_field: String,
+    /// Password typed for the demo app CLI. Never stored, only hashed into the configuration.
+    cli_password_field: String,
     personal_access_token_field: String,
     git_fork_field: String,
     git_ref_field: String,
+    configuration_link_field: String,
+    /// Reason the last configuration link failed to load, if it did.
+    configuration_link_error: Option<String>,
+    /// Whether local generation also removes any previously autogenerated code.
+    force_regenerate: bool,
+    /// Reason the last local generation failed, if it did.
+    local_generation_error: Option<String>,
+    /// Whether generated .ron files are written on a single line instead of pretty printed.
+    compact_output: bool,
     /// This complicated type exists to hold the last response to our outgoing POST
     /// requests to github actions. It must be thread safe as responses are received
     /// in a separate context.
@@ -45,14 +66,31 @@ impl Default for LoadstoneApp {
         Self {
             configuration: Default::default(),
             verifying_key_text_field: Default::default(),
+            cli_password_field: Default::default(),
             personal_access_token_field: Default::default(),
             git_ref_field: "main".into(),
             git_fork_field: "absw".into(),
+            configuration_link_field: Default::default(),
+            configuration_link_error: None,
+            force_regenerate: false,
+            local_generation_error: None,
+            compact_output: false,
             last_request_response: Arc::new(Mutex::new(None)),
         }
     }
 }
 
+impl LoadstoneApp {
+    /// Constructs the app from a shared configuration link. Falls back to the
+    /// default configuration, reporting the error, if the link can't be decoded.
+    pub fn from_link(link: &str) -> Self {
+        match share::decode(link) {
+            Ok(configuration) => Self { configuration, ..Default::default() },
+            Err(e) => Self { configuration_link_error: Some(e.to_string()), ..Default::default() },
+        }
+    }
+}
+
 impl epi::App for LoadstoneApp {
     fn name(&self) -> &str { "Loadstone Builder" }
 
@@ -73,10 +111,16 @@ impl epi::App for LoadstoneApp {
         let LoadstoneApp {
             configuration,
             verifying_key_text_field,
+            cli_password_field,
             personal_access_token_field,
             last_request_response,
             git_ref_field,
             git_fork_field,
+            configuration_link_field,
+            configuration_link_error,
+            force_regenerate,
+            local_generation_error,
+            compact_output,
         } = self;
         configuration.cleanup();
 
@@ -88,7 +132,7 @@ impl epi::App for LoadstoneApp {
                     GIT_VERSION
                 ));
                 ui.separator();
-                select_port(ui, &mut configuration.port);
+                select_port(ui, configuration);
                 ui.separator();
                 ui.collapsing("Features", |ui| {
                     ui.label("Greyed out features are unsupported in the current configuration.");
@@ -103,6 +147,39 @@ impl epi::App for LoadstoneApp {
                             &mut &mut configuration.feature_configuration.serial,
                             &mut configuration.port,
                         );
+                        configure_serial_log_level(
+                            ui,
+                            &mut configuration.feature_configuration.serial_log_level,
+                            &configuration.feature_configuration.serial,
+                        );
+                        configure_serial_interrupt_rx(
+                            ui,
+                            &mut configuration.feature_configuration.serial_interrupt_rx,
+                            &configuration.feature_configuration.serial,
+                        );
+                        configure_recovery_protocol(
+                            ui,
+                            &mut configuration.feature_configuration.recovery_protocol,
+                            &configuration.feature_configuration.serial,
+                        );
+                        configure_recovery_timeout(
+                            ui,
+                            &mut configuration.feature_configuration.recovery_timeout,
+                            &configuration.feature_configuration.serial,
+                            &configuration.port,
+                        );
+                        configure_recovery_pin(
+                            ui,
+                            &mut configuration.feature_configuration.recovery_pin,
+                            &configuration.feature_configuration.serial,
+                            &configuration.port,
+                        );
+                        configure_no_image_fallback(
+                            ui,
+                            &mut configuration.feature_configuration.no_image_fallback,
+                            &configuration.feature_configuration.serial,
+                            &configuration.port,
+                        );
                     });
                     ui.group(|ui| {
                         configure_boot_metrics(
@@ -111,6 +188,67 @@ impl epi::App for LoadstoneApp {
                             &mut configuration.port,
                         );
                     });
+                    ui.group(|ui| {
+                        configure_boot_delay(
+                            ui,
+                            &mut configuration.feature_configuration.boot_delay_ms,
+                            &mut configuration.port,
+                        );
+                    });
+                    ui.group(|ui| {
+                        configure_status_led(
+                            ui,
+                            &mut configuration.feature_configuration.status_led,
+                            &configuration.port,
+                        );
+                    });
+                    ui.group(|ui| {
+                        configure_ram_vector_table(
+                            ui,
+                            &mut configuration.feature_configuration.ram_vector_table,
+                            &configuration.port,
+                        );
+                    });
+                    ui.group(|ui| {
+                        configure_bootloader_self_check(
+                            ui,
+                            &mut configuration.feature_configuration.bootloader_self_check,
+                        );
+                    });
+                    ui.group(|ui| {
+                        configure_jump_validation(
+                            ui,
+                            &mut configuration.feature_configuration.jump_validation,
+                        );
+                    });
+                    ui.group(|ui| {
+                        configure_ram_clear(
+                            ui,
+                            &mut configuration.feature_configuration.ram_clear,
+                            &configuration.port,
+                        );
+                    });
+                    ui.group(|ui| {
+                        configure_verify_writes(
+                            ui,
+                            &mut configuration.feature_configuration.verify_writes,
+                        );
+                    });
+                    ui.group(|ui| {
+                        let available =
+                            !configuration.memory_configuration.golden_banks().is_empty();
+                        configure_golden_override(
+                            ui,
+                            &mut configuration.feature_configuration.golden_override,
+                            available,
+                        );
+                    });
+                    ui.group(|ui| {
+                        configure_exclude_cli(
+                            ui,
+                            &mut configuration.feature_configuration.exclude_cli,
+                        );
+                    });
                     ui.group(|ui| {
                         configure_custom_greetings(
                             ui,
@@ -126,13 +264,24 @@ impl epi::App for LoadstoneApp {
                 });
                 ui.separator();
                 ui.collapsing("Memory Map", |ui| {
+                    let minimum_bootloader_length_kb =
+                        configuration.estimated_minimum_bootloader_length_kb();
                     configure_memory_map(
                         ui,
                         &mut configuration.memory_configuration.internal_memory_map,
                         &mut configuration.memory_configuration.external_memory_map,
                         &mut configuration.memory_configuration.external_flash,
-                        &mut configuration.memory_configuration.golden_index,
+                        &mut configuration.memory_configuration.golden_indices,
                         &configuration.port,
+                        minimum_bootloader_length_kb,
+                        configuration.security_configuration.security_mode,
+                    );
+                    ui.separator();
+                    let ram_budget = configuration.ram_budget();
+                    configure_ram_reservation(
+                        ui,
+                        &mut configuration.memory_configuration.ram_reservation,
+                        ram_budget,
                     );
                 });
                 ui.separator();
@@ -140,9 +289,27 @@ impl epi::App for LoadstoneApp {
                     configure_security(
                         ui,
                         &mut configuration.security_configuration.security_mode,
+                        &mut configuration.security_configuration.crc_algorithm,
+                        &mut configuration.security_configuration.strict_scan,
+                        &mut configuration.security_configuration.max_scan_bytes,
+                        &mut configuration.security_configuration.min_image_size,
                         &mut configuration.security_configuration.verifying_key_raw,
                         verifying_key_text_field,
                     );
+                    configure_cli_authentication(
+                        ui,
+                        &mut configuration.security_configuration.cli_authentication,
+                        cli_password_field,
+                    );
+                });
+                ui.separator();
+                ui.collapsing("Share", |ui| {
+                    share::share(
+                        ui,
+                        configuration,
+                        configuration_link_field,
+                        configuration_link_error,
+                    );
                 });
                 ui.separator();
                 ui.collapsing("Generate", |ui| {
@@ -153,9 +320,20 @@ impl epi::App for LoadstoneApp {
                         git_ref_field,
                         git_fork_field,
                         last_request_response,
+                        force_regenerate,
+                        local_generation_error,
+                        compact_output,
                         &configuration,
                     );
                 });
+                ui.separator();
+                ui.collapsing("Build Command", |ui| {
+                    build_command::show_build_command(ui, configuration);
+                });
+                ui.separator();
+                ui.collapsing("Summary", |ui| {
+                    summary::show_summary(ui, configuration);
+                });
             });
         });
     }
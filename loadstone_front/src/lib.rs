@@ -15,10 +15,16 @@ use eframe::wasm_bindgen::{self, prelude::*};
 
 /// This is the entry-point for all the web-assembly.
 /// This is called once from the HTML.
-/// It loads the app, installs some callbacks, then returns.
+/// It loads the app (from a shared configuration link, if
+/// the URL carries one), installs some callbacks, then returns.
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
 pub fn start(canvas_id: &str) -> Result<(), eframe::wasm_bindgen::JsValue> {
-    let app = LoadstoneApp::default();
+    let fragment = web_sys::window().unwrap().location().hash()?;
+    let app = if fragment.is_empty() {
+        LoadstoneApp::default()
+    } else {
+        LoadstoneApp::from_link(&fragment)
+    };
     eframe::start_web(canvas_id, Box::new(app))
 }
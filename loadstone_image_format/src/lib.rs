@@ -0,0 +1,408 @@
+//! Layout of the decorations Loadstone expects at the end of a firmware image.
+//!
+//! A valid image is laid out as follows:
+//!
+//! ```text
+//! | body | [no auto update string] | [golden string] | !magic string | algorithm | [frame] | digest |
+//! ```
+//!
+//! The digest is a CRC or signature, depending on the algorithm, covering every byte
+//! up to and including the inverted magic string.
+//!
+//! The end of the body is found by scanning for the inverted magic string, so a body that
+//! happens to contain it (e.g. an application that links this crate) would be cut short
+//! there. Framed images guard against that: their algorithm identifier carries the
+//! [`FRAMED_FLAG`], and is followed by a frame recording the size of the body. An
+//! occurrence of the inverted magic string followed by a frame that doesn't match the
+//! body before it, or spanned by the body a later frame records, is part of the body,
+//! whatever identifier follows it, and scanning carries on past it.
+//!
+//! This crate has no dependencies and doesn't require `std`, so the same definitions back
+//! both the verifiers compiled into Loadstone and the host tools that decorate, sign and
+//! inspect images.
+#![no_std]
+
+use core::convert::TryInto;
+
+/// This string precedes the CRC/Signature for golden images only
+pub const GOLDEN_STRING: &str = "XPIcbOUrpG";
+
+/// This string precedes the golden string (if any) for images that must never be
+/// used as an update source, even if they look newer than the current image.
+pub const NO_AUTO_UPDATE_STRING: &str = "nQ8vKsr3Ta";
+
+/// This string, INVERTED BYTEWISE must terminate any valid images, before the algorithm
+/// identifier and CRC/Signature.
+///
+/// Note: Why inverted? Because if we used it as-is, no code that includes this
+/// constant could be used as a firmware image, as it contains the magic string
+/// halfway through.
+pub const MAGIC_STRING: &str = "HSc7c2ptydZH2QkqZWPcJgG3JtnJ6VuA";
+
+/// The [`MAGIC_STRING`], inverted bytewise, as it appears in an image.
+pub const MAGIC_STRING_INVERTED: [u8; MAGIC_STRING.len()] = invert(MAGIC_STRING.as_bytes());
+
+const fn invert(bytes: &[u8]) -> [u8; MAGIC_STRING.len()] {
+    let mut inverted = [0u8; MAGIC_STRING.len()];
+    let mut i = 0;
+    while i < inverted.len() {
+        inverted[i] = !bytes[i];
+        i += 1;
+    }
+    inverted
+}
+
+/// Set in the algorithm identifier of framed images, whose trailer records the body size.
+pub const FRAMED_FLAG: u8 = 0x80;
+
+/// Size in bytes of the frame following the algorithm identifier of framed images: the
+/// size of the body, excluding every decoration, as a little endian `u32`.
+pub const FRAME_SIZE: usize = 4;
+
+/// Scheme an image is verified with. Its identifier is stored as a single byte
+/// right after the magic string, and before the frame (if any) and CRC/Signature.
+///
+/// The identifier is not covered by the CRC/Signature itself. Tampering with it can
+/// only direct an image to another verifier compiled into the same reader, so
+/// readers combining schemes are only as strong as the weakest scheme they accept.
+/// Neither is the frame: tampering with it can only make readers skip a trailer, and
+/// the one they settle on must still hold a valid CRC/Signature.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(u8)]
+pub enum Algorithm {
+    Crc32 = 1,
+    P256 = 2,
+    Ed25519 = 3,
+    Sha256 = 4,
+}
+
+impl Algorithm {
+    /// Size in bytes of the identifier stored in the image trailer.
+    pub const ID_SIZE: usize = 1;
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(Algorithm::Crc32),
+            2 => Some(Algorithm::P256),
+            3 => Some(Algorithm::Ed25519),
+            4 => Some(Algorithm::Sha256),
+            _ => None,
+        }
+    }
+
+    /// Splits an identifier read from an image into the algorithm it names and whether
+    /// the image is framed.
+    pub fn parse_id(id: u8) -> Option<(Self, bool)> {
+        Some((Self::from_id(id & !FRAMED_FLAG)?, id & FRAMED_FLAG != 0))
+    }
+
+    pub fn id(&self) -> u8 { *self as u8 }
+
+    /// Identifier stored in the trailer of framed images.
+    pub fn framed_id(&self) -> u8 { self.id() | FRAMED_FLAG }
+
+    /// Size in bytes of the CRC/Signature that follows the identifier.
+    pub fn digest_size(&self) -> usize {
+        match self {
+            Algorithm::Crc32 => 4,
+            Algorithm::P256 | Algorithm::Ed25519 => 64,
+            Algorithm::Sha256 => 32,
+        }
+    }
+}
+
+/// Reasons a sequence of bytes can't be laid out as a decorated image.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FormatError {
+    /// The inverted magic string doesn't appear anywhere in the bytes.
+    MissingMagicString,
+    /// The identifier after the magic string doesn't name a known algorithm.
+    UnknownAlgorithm(u8),
+    /// The bytes end before the algorithm identifier or the full digest.
+    Truncated,
+    /// The frame doesn't record the size of the body preceding the magic string.
+    FramingMismatch,
+}
+
+/// Body size recorded in the frame of a framed image.
+pub fn recorded_body_size(frame: [u8; FRAME_SIZE]) -> usize { u32::from_le_bytes(frame) as usize }
+
+/// Position of every section of a decorated image, as offsets from its first byte.
+///
+/// Describing the layout doesn't verify the image: the digest is only located, never
+/// checked, so the golden and no-auto-update flags only mean something for images that
+/// go on to verify.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Layout {
+    /// Size of the image body, excluding every decoration.
+    pub body_size: usize,
+    /// Whether the body is followed by the [`NO_AUTO_UPDATE_STRING`].
+    pub no_auto_update: bool,
+    /// Whether the body is followed by the [`GOLDEN_STRING`].
+    pub golden: bool,
+    /// Offset of the inverted magic string. Every byte before it is covered by the digest.
+    pub magic_string_offset: usize,
+    /// Scheme named by the identifier after the magic string.
+    pub algorithm: Algorithm,
+    /// Whether a frame recording the body size follows the identifier.
+    pub framed: bool,
+}
+
+impl Layout {
+    /// Locates the decorations of an image, from the first occurrence of the inverted
+    /// magic string that isn't part of the body (see the [crate](crate) documentation).
+    /// Bytes past the digest (e.g. the rest of an image bank) are ignored.
+    pub fn parse(bytes: &[u8]) -> Result<Self, FormatError> { Self::parse_from(bytes, 0) }
+
+    /// Like [`Layout::parse`], for bytes starting `start` bytes into the image (e.g. just
+    /// its trailer). Offsets are relative to the bytes, but frames are checked against
+    /// the size of the whole body.
+    pub fn parse_from(bytes: &[u8], start: usize) -> Result<Self, FormatError> {
+        let mut skipped = None;
+        for magic_string_offset in occurrences(bytes, 0) {
+            match Self::at(bytes, start, magic_string_offset) {
+                Err(error @ FormatError::FramingMismatch) => skipped = skipped.or(Some(error)),
+                Ok(layout) if layout.framed => return Ok(layout),
+                _ if Self::framed_past(bytes, start, magic_string_offset) => (),
+                result => return result,
+            }
+        }
+        Err(skipped.unwrap_or(FormatError::MissingMagicString))
+    }
+
+    /// Whether a framed image further into the bytes records a body spanning the
+    /// occurrence of the inverted magic string at `magic_string_offset`, which is then
+    /// part of that body whatever follows it.
+    fn framed_past(bytes: &[u8], start: usize, magic_string_offset: usize) -> bool {
+        let spanned_size = magic_string_offset + MAGIC_STRING.len();
+        occurrences(bytes, spanned_size).any(|offset| {
+            matches!(
+                Self::at(bytes, start, offset),
+                Ok(layout) if layout.framed && layout.body_size >= spanned_size
+            )
+        })
+    }
+
+    /// Layout of an image ending at a given occurrence of the inverted magic string.
+    fn at(bytes: &[u8], start: usize, magic_string_offset: usize) -> Result<Self, FormatError> {
+        let id =
+            *bytes.get(magic_string_offset + MAGIC_STRING.len()).ok_or(FormatError::Truncated)?;
+        let (algorithm, framed) =
+            Algorithm::parse_id(id).ok_or(FormatError::UnknownAlgorithm(id))?;
+
+        let (body_size, no_auto_update, golden) = strip_flags(&bytes[..magic_string_offset]);
+        let layout =
+            Layout { body_size, no_auto_update, golden, magic_string_offset, algorithm, framed };
+        if bytes.len() < layout.total_size() {
+            return Err(FormatError::Truncated);
+        }
+        if framed {
+            let frame = bytes[layout.frame_offset()..layout.digest_offset()].try_into().unwrap();
+            if recorded_body_size(frame) != start + body_size {
+                return Err(FormatError::FramingMismatch);
+            }
+        }
+        Ok(layout)
+    }
+
+    /// Offset of the algorithm identifier.
+    pub fn algorithm_offset(&self) -> usize { self.magic_string_offset + MAGIC_STRING.len() }
+
+    /// Offset of the frame, if the image is framed.
+    pub fn frame_offset(&self) -> usize { self.algorithm_offset() + Algorithm::ID_SIZE }
+
+    /// Offset of the CRC/Signature.
+    pub fn digest_offset(&self) -> usize {
+        self.frame_offset() + if self.framed { FRAME_SIZE } else { 0 }
+    }
+
+    /// Size of the image including every decoration, up to the end of the digest.
+    pub fn total_size(&self) -> usize { self.digest_offset() + self.algorithm.digest_size() }
+}
+
+/// Offsets of the occurrences of the inverted magic string in `bytes`, from `from`
+/// onwards, each searched for past the end of the previous one.
+fn occurrences(bytes: &[u8], from: usize) -> impl Iterator<Item = usize> + '_ {
+    let mut search_from = from;
+    core::iter::from_fn(move || {
+        let position = bytes
+            .get(search_from..)?
+            .windows(MAGIC_STRING_INVERTED.len())
+            .position(|window| window == MAGIC_STRING_INVERTED)?;
+        let magic_string_offset = search_from + position;
+        search_from = magic_string_offset + MAGIC_STRING.len();
+        Some(magic_string_offset)
+    })
+}
+
+/// Splits the bytes preceding the inverted magic string into the size of the body and
+/// the flags that follow it, as the golden and no-auto-update strings are detected.
+fn strip_flags(decorated_body: &[u8]) -> (usize, bool, bool) {
+    let body = decorated_body;
+    let golden = body.ends_with(GOLDEN_STRING.as_bytes());
+    let body = &body[..body.len() - if golden { GOLDEN_STRING.len() } else { 0 }];
+    let no_auto_update = body.ends_with(NO_AUTO_UPDATE_STRING.as_bytes());
+    let body = &body[..body.len() - if no_auto_update { NO_AUTO_UPDATE_STRING.len() } else { 0 }];
+    (body.len(), no_auto_update, golden)
+}
+
+/// Whether a body decorated with the given flags reads back with exactly those flags.
+///
+/// The flags are detected by the strings right before the magic string, so a body that
+/// happens to end with one of them is misread: e.g. a body ending with the bytes of the
+/// [`GOLDEN_STRING`] is taken for a golden image, even though it wasn't labelled golden.
+pub fn flags_read_back(body: &[u8], no_auto_update: bool, golden: bool) -> bool {
+    // Only the tail of the body can be mistaken for a flag, so that's all that's checked.
+    const FLAGS_SIZE: usize = NO_AUTO_UPDATE_STRING.len() + GOLDEN_STRING.len();
+    let tail = &body[body.len().saturating_sub(FLAGS_SIZE)..];
+    let no_auto_update_string = if no_auto_update { NO_AUTO_UPDATE_STRING.as_bytes() } else { &[] };
+    let golden_string = if golden { GOLDEN_STRING.as_bytes() } else { &[] };
+
+    let mut decorated_tail = [0u8; 2 * FLAGS_SIZE];
+    let mut length = 0;
+    for byte in tail.iter().chain(no_auto_update_string).chain(golden_string) {
+        decorated_tail[length] = *byte;
+        length += 1;
+    }
+    strip_flags(&decorated_tail[..length]) == (tail.len(), no_auto_update, golden)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decorated(body: &[u8], strings: &[&str], algorithm: Algorithm) -> [u8; 128] {
+        let mut image = [0xFFu8; 128];
+        let mut offset = 0;
+        let mut append = |bytes: &[u8]| {
+            image[offset..offset + bytes.len()].copy_from_slice(bytes);
+            offset += bytes.len();
+        };
+        append(body);
+        strings.iter().for_each(|string| append(string.as_bytes()));
+        append(&MAGIC_STRING_INVERTED);
+        append(&[algorithm.id()]);
+        append(&[0xAA; 64][..algorithm.digest_size()]);
+        image
+    }
+
+    fn framed(body: &[u8], algorithm: Algorithm) -> [u8; 128] {
+        let mut image = [0xFFu8; 128];
+        let trailer = [&MAGIC_STRING_INVERTED[..], &[algorithm.framed_id()]].concat();
+        let frame = (body.len() as u32).to_le_bytes();
+        let sections = [body, &trailer, &frame, &[0xAA; 64][..algorithm.digest_size()]];
+        let mut offset = 0;
+        for section in sections.iter() {
+            image[offset..offset + section.len()].copy_from_slice(section);
+            offset += section.len();
+        }
+        image
+    }
+
+    #[test]
+    fn magic_string_inverted_matches_bytewise_inversion() {
+        assert!(MAGIC_STRING.bytes().zip(MAGIC_STRING_INVERTED.iter()).all(|(a, b)| a == !b));
+    }
+
+    #[test]
+    fn parsing_locates_every_section() {
+        let image = decorated(b"body", &[NO_AUTO_UPDATE_STRING, GOLDEN_STRING], Algorithm::Crc32);
+        let layout = Layout::parse(&image).unwrap();
+        assert_eq!(layout.body_size, 4);
+        assert!(layout.golden && layout.no_auto_update);
+        assert_eq!(layout.magic_string_offset, 24);
+        assert_eq!(layout.algorithm, Algorithm::Crc32);
+        assert_eq!(layout.digest_offset(), 24 + 32 + 1);
+        assert_eq!(layout.total_size(), 24 + 32 + 1 + 4);
+    }
+
+    #[test]
+    fn golden_string_out_of_place_is_part_of_the_body() {
+        let image = decorated(b"body", &[GOLDEN_STRING, NO_AUTO_UPDATE_STRING], Algorithm::P256);
+        let layout = Layout::parse(&image).unwrap();
+        assert!(!layout.golden && layout.no_auto_update);
+        assert_eq!(layout.body_size, 4 + GOLDEN_STRING.len());
+    }
+
+    #[test]
+    fn bodies_ending_like_a_flag_are_misread() {
+        assert!(flags_read_back(b"body", false, false));
+        assert!(flags_read_back(b"body", true, true));
+        assert!(flags_read_back(b"", false, true));
+
+        // A body that happens to end with the golden string would pass for a golden image.
+        let crafted = [&b"firmware"[..], GOLDEN_STRING.as_bytes()].concat();
+        assert!(!flags_read_back(&crafted, false, false));
+        assert!(flags_read_back(&crafted, true, true));
+        let image = decorated(&crafted, &[], Algorithm::Crc32);
+        assert!(Layout::parse(&image).unwrap().golden);
+
+        let crafted = [&b"firmware"[..], NO_AUTO_UPDATE_STRING.as_bytes()].concat();
+        assert!(!flags_read_back(&crafted, false, true));
+        assert!(flags_read_back(&crafted, true, true));
+    }
+
+    #[test]
+    fn malformed_images_are_rejected() {
+        assert_eq!(Err(FormatError::MissingMagicString), Layout::parse(&[0xFFu8; 64]));
+
+        let image = decorated(b"body", &[], Algorithm::Sha256);
+        let layout = Layout::parse(&image).unwrap();
+        assert_eq!(Err(FormatError::Truncated), Layout::parse(&image[..layout.total_size() - 1]));
+        assert_eq!(Err(FormatError::Truncated), Layout::parse(&image[..layout.algorithm_offset()]));
+
+        let mut image = image;
+        image[layout.algorithm_offset()] = 0x7F;
+        assert_eq!(Err(FormatError::UnknownAlgorithm(0x7F)), Layout::parse(&image));
+    }
+
+    #[test]
+    fn framed_images_carry_on_past_magic_strings_in_their_body() {
+        // The body holds the magic string followed by an unknown identifier, as an
+        // application embedding this crate's constants could.
+        let body = [&b"ab"[..], &MAGIC_STRING_INVERTED, &[0x00, 0x00]].concat();
+        let image = framed(&body, Algorithm::Crc32);
+        let layout = Layout::parse(&image).unwrap();
+        assert!(layout.framed);
+        assert_eq!(layout.body_size, body.len());
+        assert_eq!(layout.magic_string_offset, body.len());
+        assert_eq!(layout.digest_offset(), body.len() + 32 + 1 + FRAME_SIZE);
+
+        // Even when the identifier is a known one, as in the trailer of an embedded image.
+        let body = [&b"ab"[..], &MAGIC_STRING_INVERTED, &[Algorithm::Crc32.id(); 5]].concat();
+        let layout = Layout::parse(&framed(&body, Algorithm::Crc32)).unwrap();
+        assert!(layout.framed);
+        assert_eq!(layout.body_size, body.len());
+
+        // A framed identifier with a mismatched frame is skipped as well.
+        let body =
+            [&b"ab"[..], &MAGIC_STRING_INVERTED, &[Algorithm::Crc32.framed_id(), 9, 0, 0, 0]]
+                .concat();
+        let layout = Layout::parse(&framed(&body, Algorithm::Crc32)).unwrap();
+        assert_eq!(layout.body_size, body.len());
+    }
+
+    #[test]
+    fn frames_must_match_the_body() {
+        let mut image = framed(b"body", Algorithm::Sha256);
+        let layout = Layout::parse(&image).unwrap();
+        image[layout.frame_offset()] = 5;
+        assert_eq!(Err(FormatError::FramingMismatch), Layout::parse(&image));
+        assert_eq!(
+            Some((Algorithm::Sha256, true)),
+            Algorithm::parse_id(Algorithm::Sha256.framed_id())
+        );
+        assert_eq!(None, Algorithm::parse_id(FRAMED_FLAG));
+    }
+
+    #[test]
+    fn trailers_parsed_on_their_own_are_checked_against_the_whole_body() {
+        let body = [0x5Au8; 40];
+        let image = framed(&body, Algorithm::Crc32);
+        let start = 30;
+        let layout = Layout::parse_from(&image[start..], start).unwrap();
+        assert_eq!(layout.magic_string_offset, body.len() - start);
+        assert!(layout.framed);
+        assert_eq!(Err(FormatError::FramingMismatch), Layout::parse(&image[start..]));
+    }
+}
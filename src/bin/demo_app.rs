@@ -6,7 +6,7 @@
 use cortex_m_rt::{entry, exception};
 pub const HEAP_SIZE_BYTES: usize = 8192;
 
-#[cfg(all(target_arch = "arm", feature = "stm32f412"))]
+#[cfg(all(target_arch = "arm", feature = "stm32f412", feature = "cli"))]
 #[entry]
 fn main() -> ! {
     let heap_start = cortex_m_rt::heap_start() as usize;
@@ -17,7 +17,7 @@ fn main() -> ! {
     app.run();
 }
 
-#[cfg(all(target_arch = "arm", feature = "wgm160p"))]
+#[cfg(all(target_arch = "arm", any(feature = "wgm160p", not(feature = "cli"))))]
 #[entry]
 fn main() -> ! {
     use loadstone_lib as _;
@@ -0,0 +1,70 @@
+//! Runtime serial baud rate changes.
+//!
+//! Allows the demo application to renegotiate the serial speed with a host
+//! without reflashing. Ports supply the hardware side through [`BaudControl`],
+//! while the divisor calculation is shared.
+
+/// Maximum deviation, in permille, between a requested baud rate and the one
+/// actually achievable with an integer divisor.
+const MAX_RATE_ERROR_PERMILLE: u64 = 25;
+
+/// Smallest divisor supported by a USART with 16x oversampling.
+const MIN_DIVISOR: u64 = 16;
+
+/// Calculates the baud rate register value for a USART with 16x oversampling,
+/// clocked at `clock_hz`. Returns `None` if the rate can't be achieved within
+/// tolerance.
+pub fn usart_divisor(clock_hz: u32, baud: u32) -> Option<u16> {
+    if baud == 0 {
+        return None;
+    }
+    let (clock_hz, baud) = (clock_hz as u64, baud as u64);
+    let divisor = (clock_hz + baud / 2) / baud;
+    if divisor < MIN_DIVISOR || divisor > u16::MAX as u64 {
+        return None;
+    }
+    let achieved = clock_hz / divisor;
+    let error = if achieved > baud { achieved - baud } else { baud - achieved };
+    (error * 1000 <= MAX_RATE_ERROR_PERMILLE * baud).then_some(divisor as u16)
+}
+
+/// Hardware hooks to change the serial baud rate at runtime.
+#[derive(Clone, Copy)]
+pub struct BaudControl {
+    /// Frequency of the clock feeding the serial peripheral.
+    pub clock_hz: u32,
+    /// Writes a new divisor to the serial peripheral's baud rate register.
+    pub write_divisor: fn(u16),
+}
+
+impl BaudControl {
+    /// Divisor for a given baud rate, if achievable.
+    pub fn divisor(&self, baud: u32) -> Option<u16> { usart_divisor(self.clock_hz, baud) }
+
+    /// Switches the serial peripheral to a new divisor. Anything still
+    /// being transmitted at the old rate may be garbled.
+    pub fn apply(&self, divisor: u16) { (self.write_divisor)(divisor) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn divisor_is_rounded_to_nearest_integer() {
+        assert_eq!(usart_divisor(50_000_000, 115_200), Some(434));
+        assert_eq!(usart_divisor(16_000_000, 9_600), Some(1667));
+        assert_eq!(usart_divisor(100_000_000, 115_200), Some(868));
+    }
+
+    #[test]
+    fn unachievable_rates_are_rejected() {
+        // Divisor would be below the 16x oversampling minimum
+        assert_eq!(usart_divisor(16_000_000, 2_000_000), None);
+        // Divisor would overflow the baud rate register
+        assert_eq!(usart_divisor(100_000_000, 1_000), None);
+        // Nearest divisor (17) misses the requested rate by ~3%
+        assert_eq!(usart_divisor(16_500_000, 1_000_000), None);
+        assert_eq!(usart_divisor(16_000_000, 0), None);
+    }
+}
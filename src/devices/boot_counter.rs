@@ -0,0 +1,130 @@
+//! Persistent boot counter.
+//!
+//! Counts boots in a small reserved region of MCU flash. Flash bits can be cleared
+//! without erasing, but only set again by erasing a whole sector, so the count is
+//! kept as a tally to keep erases to a minimum:
+//!
+//! * The first word of the region holds the bitwise inverse of the count at the
+//!   time the region was last erased, so an erased region reads as zero boots.
+//! * It is followed by [`TALLY_WORDS`] tally words. Each boot clears one bit of
+//!   the first tally word that still has bits set; once that word is exhausted,
+//!   counting rotates to the next one.
+//! * Only when every tally word is exhausted is the region rewritten, folding the
+//!   tally into the first word. This happens once every `32 * TALLY_WORDS` boots.
+
+use crate::{devices::traits::Flash, error::Error};
+use core::mem::size_of;
+use nb::block;
+
+/// Number of words counting boots one bit at a time.
+pub const TALLY_WORDS: usize = 8;
+
+const WORDS: usize = 1 + TALLY_WORDS;
+
+/// Size in bytes of the flash region occupied by the counter.
+pub const REGION_SIZE: usize = WORDS * size_of::<u32>();
+
+fn read_words<F: Flash>(flash: &mut F, location: F::Address) -> Result<[u32; WORDS], Error> {
+    let mut bytes = [0u8; REGION_SIZE];
+    block!(flash.read(location, &mut bytes))?;
+    let mut words = [0u32; WORDS];
+    for (word, chunk) in words.iter_mut().zip(bytes.chunks_exact(size_of::<u32>())) {
+        *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    Ok(words)
+}
+
+fn count(words: &[u32; WORDS]) -> u32 {
+    let tally: u32 = words[1..].iter().map(|w| w.count_zeros()).sum();
+    (!words[0]).saturating_add(tally)
+}
+
+/// Reads the number of boots recorded at a location.
+pub fn read<F: Flash>(flash: &mut F, location: F::Address) -> Result<u32, Error> {
+    Ok(count(&read_words(flash, location)?))
+}
+
+/// Records one more boot at a location, returning the updated count.
+pub fn increment<F: Flash>(flash: &mut F, location: F::Address) -> Result<u32, Error> {
+    let words = read_words(flash, location)?;
+    let new_count = count(&words).saturating_add(1);
+    match words[1..].iter().position(|w| *w != 0) {
+        Some(index) => {
+            let tally = words[1 + index];
+            let offset = (1 + index) * size_of::<u32>();
+            block!(flash.write(location + offset, &(tally & (tally - 1)).to_le_bytes()))?;
+        }
+        None => {
+            let mut bytes = [0xFFu8; REGION_SIZE];
+            bytes[..size_of::<u32>()].copy_from_slice(&(!new_count).to_le_bytes());
+            block!(flash.write(location, &bytes))?;
+        }
+    }
+    Ok(new_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blue_hal::hal::{doubles::flash::*, flash::ReadWrite};
+
+    fn erased_flash() -> FakeFlash {
+        let mut flash = FakeFlash::new(Address(0));
+        flash.write(Address(0), &[0xFFu8; REGION_SIZE]).unwrap();
+        flash
+    }
+
+    #[test]
+    fn erased_region_reads_zero_boots() {
+        let mut flash = erased_flash();
+        assert_eq!(Ok(0), read(&mut flash, Address(0)));
+    }
+
+    #[test]
+    fn each_increment_adds_a_single_boot() {
+        let mut flash = erased_flash();
+        for expected in 1..=5 {
+            assert_eq!(Ok(expected), increment(&mut flash, Address(0)));
+            assert_eq!(Ok(expected), read(&mut flash, Address(0)));
+        }
+    }
+
+    #[test]
+    fn counting_rotates_to_next_word_when_one_is_exhausted() {
+        let mut flash = erased_flash();
+        for _ in 0..32 {
+            increment(&mut flash, Address(0)).unwrap();
+        }
+        let words = read_words(&mut flash, Address(0)).unwrap();
+        assert_eq!([0xFFFF_FFFF, 0, 0xFFFF_FFFF], words[..3]);
+
+        increment(&mut flash, Address(0)).unwrap();
+        let words = read_words(&mut flash, Address(0)).unwrap();
+        assert_eq!([0, 0xFFFF_FFFE], words[1..3]);
+        assert_eq!(Ok(33), read(&mut flash, Address(0)));
+    }
+
+    #[test]
+    fn exhausted_tally_is_folded_into_base_word() {
+        let mut flash = erased_flash();
+        let full_tally = 32 * TALLY_WORDS as u32;
+        for _ in 0..full_tally {
+            increment(&mut flash, Address(0)).unwrap();
+        }
+        assert!(read_words(&mut flash, Address(0)).unwrap()[1..].iter().all(|w| *w == 0));
+
+        assert_eq!(Ok(full_tally + 1), increment(&mut flash, Address(0)));
+        let words = read_words(&mut flash, Address(0)).unwrap();
+        assert_eq!(!(full_tally + 1), words[0]);
+        assert!(words[1..].iter().all(|w| *w == 0xFFFF_FFFF));
+        assert_eq!(Ok(full_tally + 2), increment(&mut flash, Address(0)));
+    }
+
+    #[test]
+    fn count_survives_unrelated_writes_outside_region() {
+        let mut flash = erased_flash();
+        increment(&mut flash, Address(0)).unwrap();
+        flash.write(Address(REGION_SIZE as u32), &[0u8; 16]).unwrap();
+        assert_eq!(Ok(1), read(&mut flash, Address(0)));
+    }
+}
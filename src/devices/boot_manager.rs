@@ -17,14 +17,22 @@
 use core::marker::PhantomData;
 
 use super::{
-    boot_metrics::{boot_metrics, BootMetrics},
+    baud::BaudControl,
+    boot_metrics::{boot_info, boot_info_mut, BootMetrics},
+    bootloader::{
+        candidacy, decide_update, in_update_pass, is_staging_bank, mirror_image,
+        store_recovered_image, update_target, write_blocks_within_bank, write_within_bank,
+        Candidacy, UpdateDecision,
+    },
     cli::{Cli, DEFAULT_GREETING},
-    image,
+    image::{self, erase_bank},
+    self_test,
     traits::{Flash, Serial},
-    update_signal::{UpdatePlan, WriteUpdateSignal},
+    update_signal::{prepare_reset, ReadUpdateSignal, ResetMode, UpdatePlan, WriteUpdateSignal},
+    usage::{self, BootloaderRegion, Usage},
 };
 use crate::error::Error;
-use blue_hal::hal::flash;
+use blue_hal::hal::{flash, time};
 use cortex_m::peripheral::SCB;
 
 /// Generic boot manager, composed of a CLI interface to serial and flash
@@ -35,7 +43,8 @@ pub struct BootManager<
     EXTF: Flash,
     SRL: Serial,
     R: image::Reader,
-    WUS: WriteUpdateSignal,
+    WUS: ReadUpdateSignal + WriteUpdateSignal,
+    T: time::Now,
 > {
     pub(crate) external_banks: &'static [image::Bank<<EXTF as flash::ReadWrite>::Address>],
     pub(crate) mcu_banks: &'static [image::Bank<<MCUF as flash::ReadWrite>::Address>],
@@ -44,12 +53,34 @@ pub struct BootManager<
     pub(crate) cli: Option<Cli<SRL>>,
     pub(crate) boot_metrics: Option<BootMetrics>,
     pub(crate) greeting: Option<&'static str>,
+    pub(crate) recovery_enabled: bool,
+    pub(crate) baud_control: Option<BaudControl>,
     pub(crate) _marker: PhantomData<R>,
     pub(crate) update_signal: Option<WUS>,
+    pub(crate) start_time: Option<T::I>,
+    /// Index of the MCU bank confirmed images are mirrored into, if any.
+    pub(crate) backup_bank: Option<u8>,
+    /// Index of the MCU bank Loadstone stages serial updates in, if any.
+    pub(crate) staging_bank: Option<u8>,
+    /// Banks taking turns with the staging bank, if serial updates rotate between them.
+    pub(crate) staging_rotation: &'static [u8],
+    /// Whether Loadstone updates from golden banks when no regular bank settles the update.
+    pub(crate) golden_override: bool,
+    /// MCU flash region reserved for the bootloader.
+    pub(crate) bootloader_region: BootloaderRegion<<MCUF as flash::ReadWrite>::Address>,
+    /// Read back every chunk of an image mirrored into the backup bank, failing the copy
+    /// as soon as one doesn't match.
+    pub(crate) verify_writes: bool,
 }
 
-impl<MCUF: Flash, EXTF: Flash, SRL: Serial, R: image::Reader, WUS: WriteUpdateSignal>
-    BootManager<MCUF, EXTF, SRL, R, WUS>
+impl<
+        MCUF: Flash,
+        EXTF: Flash,
+        SRL: Serial,
+        R: image::Reader,
+        WUS: ReadUpdateSignal + WriteUpdateSignal,
+        T: time::Now,
+    > BootManager<MCUF, EXTF, SRL, R, WUS, T>
 {
     /// Provides an iterator over all external flash banks.
     pub fn external_banks(&self) -> impl Iterator<Item = image::Bank<EXTF::Address>> {
@@ -60,6 +91,15 @@ impl<MCUF: Flash, EXTF: Flash, SRL: Serial, R: image::Reader, WUS: WriteUpdateSi
         self.mcu_banks().find(|b| b.bootable).unwrap()
     }
 
+    /// Whether Loadstone stages serial updates in a bank. Only serial updates write to
+    /// staging banks, and Loadstone never updates from them otherwise.
+    pub fn is_staging_bank(&self, index: u8) -> bool {
+        is_staging_bank(index, self.staging_bank, self.staging_rotation)
+    }
+
+    /// Milliseconds elapsed since the boot manager started, if it has a time source.
+    pub fn uptime_ms(&self) -> Option<u32> { self.start_time.map(|t| (T::now() - t).0) }
+
     /// Returns an iterator of all MCU flash banks.
     pub fn mcu_banks(&self) -> impl Iterator<Item = image::Bank<MCUF::Address>> {
         self.mcu_banks.iter().cloned()
@@ -67,33 +107,304 @@ impl<MCUF: Flash, EXTF: Flash, SRL: Serial, R: image::Reader, WUS: WriteUpdateSi
 
     /// Writes a firmware image to an external flash bank. Takes an iterator over byte
     /// blocks, to easily interface with serial or network protocols like XMODEM or TCP/IP
-    /// where information is received in chunks.
+    /// where information is received in chunks. Images too large for the bank are cut
+    /// short rather than overrunning into the next one.
     pub fn store_image_external<I: Iterator<Item = [u8; N]>, const N: usize>(
         &mut self,
         blocks: I,
         bank: image::Bank<EXTF::Address>,
     ) -> Result<(), Error> {
         let external_flash = self.external_flash.as_mut().ok_or(Error::NoExternalFlash)?;
-        external_flash.write_from_blocks(bank.location, blocks)?;
-        Ok(())
+        write_blocks_within_bank(external_flash, bank, blocks)
     }
 
-    /// Writes a firmware image to a MCU flash bank that is not bootable. Takes an iterator over byte
-    /// blocks, to easily interface with serial or network protocols like XMODEM or TCP/IP
-    /// where information is received in chunks.
+    /// Writes a firmware image to a MCU flash bank that is neither bootable nor a staging bank.
+    /// Takes an iterator over byte blocks, to easily interface with serial or network protocols
+    /// like XMODEM or TCP/IP where information is received in chunks. Images too large for the
+    /// bank are cut short rather than overrunning into the next one.
     pub fn store_image_mcu<I: Iterator<Item = [u8; N]>, const N: usize>(
         &mut self,
         blocks: I,
         bank: image::Bank<MCUF::Address>,
     ) -> Result<(), Error> {
+        if bank.bootable || self.is_staging_bank(bank.index) {
+            Err(Error::BankInvalid)
+        } else {
+            write_blocks_within_bank(&mut self.mcu_flash, bank, blocks)
+        }
+    }
+
+    /// Stores a golden image in the golden bank, exactly as Loadstone's recovery mode
+    /// would. Takes an iterator over byte blocks, normally coming from XMODEM.
+    pub fn recover<I: Iterator<Item = [u8; N]>, const N: usize>(
+        &mut self,
+        blocks: I,
+    ) -> Result<(), Error> {
+        if !self.recovery_enabled {
+            return Err(Error::NoRecoverySupport);
+        }
+
+        if let Some(bank) = self.mcu_banks().find(|b| b.is_golden) {
+            store_recovered_image::<R, _, _, N>(&mut self.mcu_flash, bank, blocks, true)?;
+        } else if let Some(bank) = self.external_banks().find(|b| b.is_golden) {
+            let external_flash = self.external_flash.as_mut().ok_or(Error::NoExternalFlash)?;
+            store_recovered_image::<R, _, _, N>(external_flash, bank, blocks, true)?;
+        } else {
+            return Err(Error::NoGoldenBankSupport);
+        }
+        Ok(())
+    }
+
+    /// Runs Loadstone's update selection over all banks without updating anything,
+    /// as if the update signal allowed updating from any bank. Calls `report` with the
+    /// candidacy of every bank considered, and returns the index of the bank Loadstone
+    /// would update from, if any.
+    pub fn update_candidate<F: FnMut(u8, Candidacy)>(
+        &mut self,
+        report: F,
+    ) -> Result<Option<u8>, Error> {
+        Ok(self.update_decision(None, report)?.bank())
+    }
+
+    /// Previews the update Loadstone would perform on the next boot, following the
+    /// update signal as Loadstone would, without updating anything. Calls `report` with
+    /// the candidacy of every bank considered.
+    pub fn dry_update<F: FnMut(u8, Candidacy)>(
+        &mut self,
+        report: F,
+    ) -> Result<UpdateDecision, Error> {
+        let plan = self.update_signal.as_ref().map(ReadUpdateSignal::read_update_plan);
+        match update_target(plan) {
+            Ok(target_bank) => self.update_decision(target_bank, report),
+            Err(decision) => Ok(decision),
+        }
+    }
+
+    /// Runs Loadstone's update selection over all banks, restricted to `target_bank` if
+    /// set, without updating anything. As in Loadstone, golden banks are only considered
+    /// with the golden override, once no regular bank settled the update.
+    fn update_decision<F: FnMut(u8, Candidacy)>(
+        &mut self,
+        target_bank: Option<u8>,
+        mut report: F,
+    ) -> Result<UpdateDecision, Error> {
+        let decision = self.update_pass_decision(target_bank, false, &mut report)?;
+        if self.golden_override && decision == UpdateDecision::NoCandidates {
+            self.update_pass_decision(target_bank, true, &mut report)
+        } else {
+            Ok(decision)
+        }
+    }
+
+    /// Runs a single pass of Loadstone's update selection, over either the regular or the
+    /// golden banks.
+    fn update_pass_decision<F: FnMut(u8, Candidacy)>(
+        &mut self,
+        target_bank: Option<u8>,
+        golden_pass: bool,
+        mut report: F,
+    ) -> Result<UpdateDecision, Error> {
+        let boot_bank = self.boot_bank();
+        let backup_bank = self.backup_bank;
+        let golden_override = self.golden_override;
+        let current = R::image_at(&mut self.mcu_flash, boot_bank)?.identifier();
+
+        let (staging_bank, staging_rotation) = (self.staging_bank, self.staging_rotation);
+        let mcu_flash = &mut self.mcu_flash;
+        let mcu_candidacies = self
+            .mcu_banks
+            .iter()
+            .filter(|b| {
+                b.index != boot_bank.index
+                    && !is_staging_bank(b.index, staging_bank, staging_rotation)
+                    && in_update_pass(*b, golden_pass, golden_override)
+            })
+            .map(|bank| {
+                let candidacy =
+                    candidacy(bank, target_bank, backup_bank, golden_override, &current, || {
+                        R::image_at(mcu_flash, *bank)
+                            .ok()
+                            .map(|image| (image.identifier(), image.no_auto_update()))
+                    });
+                (bank.index, candidacy)
+            });
+
+        let external_banks = if self.external_flash.is_some() { self.external_banks } else { &[] };
+        let external_flash = &mut self.external_flash;
+        let external_candidacies = external_banks
+            .iter()
+            .filter(|b| in_update_pass(*b, golden_pass, golden_override))
+            .map(|bank| {
+                let candidacy =
+                    candidacy(bank, target_bank, backup_bank, golden_override, &current, || {
+                        let flash = external_flash.as_mut().unwrap();
+                        R::image_at(flash, *bank)
+                            .ok()
+                            .map(|image| (image.identifier(), image.no_auto_update()))
+                    });
+                (bank.index, candidacy)
+            });
+
+        Ok(decide_update(
+            mcu_candidacies
+                .chain(external_candidacies)
+                .inspect(|(index, candidacy)| report(*index, *candidacy)),
+        ))
+    }
+
+    /// Verifies the image in a bank as a test boot target, returning its candidacy as an
+    /// update source for the current image. Only `Newer` images can be test booted, and
+    /// `Current` ones are already booted.
+    pub fn test_boot_candidacy(&mut self, index: u8) -> Result<Candidacy, Error> {
+        let current = R::image_at(&mut self.mcu_flash, self.boot_bank())?.identifier();
+        let (backup_bank, golden_override) = (self.backup_bank, self.golden_override);
+        if let Some(bank) = self.mcu_banks().find(|b| b.index == index) {
+            let flash = &mut self.mcu_flash;
+            Ok(candidacy(&bank, Some(index), backup_bank, golden_override, &current, || {
+                R::image_at(flash, bank).ok().map(|i| (i.identifier(), i.no_auto_update()))
+            }))
+        } else if let Some(bank) = self.external_banks().find(|b| b.index == index) {
+            let flash = self.external_flash.as_mut().ok_or(Error::NoExternalFlash)?;
+            Ok(candidacy(&bank, Some(index), backup_bank, golden_override, &current, || {
+                R::image_at(flash, bank).ok().map(|i| (i.identifier(), i.no_auto_update()))
+            }))
+        } else {
+            Err(Error::BankInvalid)
+        }
+    }
+
+    /// Confirms the running image is healthy, mirroring it into the backup bank so it can
+    /// be restored or rolled back to should a later image misbehave. Returns whether the
+    /// backup was rewritten, as it's left untouched if it already holds the same image.
+    pub fn confirm_healthy(&mut self) -> Result<bool, Error> {
+        let index = self.backup_bank.ok_or(Error::DeviceError("No backup bank is configured."))?;
+        let backup_bank = self.mcu_banks().find(|b| b.index == index).ok_or(Error::BankInvalid)?;
+        let boot_bank = self.boot_bank();
+        mirror_image::<R, _, SRL>(
+            &mut None,
+            &mut self.mcu_flash,
+            boot_bank,
+            backup_bank,
+            self.verify_writes,
+        )
+    }
+
+    /// Makes Loadstone boot from a bank on the next boot only. Its image is copied over
+    /// the boot bank, so the image booted before isn't kept. Loadstone puts back the update
+    /// plan in place before jumping to the image, so later boots follow it again.
+    pub fn schedule_test_boot(&mut self, index: u8) -> Result<(), Error> {
+        let plan = self.update_plan()?;
+        self.set_update_signal(plan.test_boot(index))
+    }
+
+    /// Erases a MCU flash bank that is not bootable.
+    pub fn erase_bank_mcu(&mut self, bank: image::Bank<MCUF::Address>) -> Result<(), Error> {
         if bank.bootable {
             Err(Error::BankInvalid)
         } else {
-            self.mcu_flash.write_from_blocks(bank.location, blocks)?;
-            Ok(())
+            erase_bank(&mut self.mcu_flash, bank)
+        }
+    }
+
+    /// Erases an external flash bank.
+    pub fn erase_bank_external(&mut self, bank: image::Bank<EXTF::Address>) -> Result<(), Error> {
+        let external_flash = self.external_flash.as_mut().ok_or(Error::NoExternalFlash)?;
+        erase_bank(external_flash, bank)
+    }
+
+    /// Writes bytes at an offset into a MCU flash bank that is not bootable.
+    pub fn write_mcu(
+        &mut self,
+        bank: image::Bank<MCUF::Address>,
+        offset: usize,
+        bytes: &[u8],
+    ) -> Result<(), Error> {
+        if bank.bootable {
+            Err(Error::BankInvalid)
+        } else {
+            write_within_bank(&mut self.mcu_flash, bank, offset, bytes)
         }
     }
 
+    /// Writes bytes at an offset into an external flash bank.
+    pub fn write_external(
+        &mut self,
+        bank: image::Bank<EXTF::Address>,
+        offset: usize,
+        bytes: &[u8],
+    ) -> Result<(), Error> {
+        let external_flash = self.external_flash.as_mut().ok_or(Error::NoExternalFlash)?;
+        write_within_bank(external_flash, bank, offset, bytes)
+    }
+
+    /// Runs the flash self test over a non-bootable MCU bank, calling `report` with
+    /// the outcome of every step. Returns whether all steps passed.
+    pub fn self_test_mcu<F: FnMut(self_test::Step, self_test::Outcome)>(
+        &mut self,
+        bank: image::Bank<MCUF::Address>,
+        report: F,
+    ) -> Result<bool, Error> {
+        self_test::run(&mut self.mcu_flash, bank, report)
+    }
+
+    /// Runs the flash self test over an external bank, calling `report` with the
+    /// outcome of every step. Returns whether all steps passed.
+    pub fn self_test_external<F: FnMut(self_test::Step, self_test::Outcome)>(
+        &mut self,
+        bank: image::Bank<EXTF::Address>,
+        report: F,
+    ) -> Result<bool, Error> {
+        let external_flash = self.external_flash.as_mut().ok_or(Error::NoExternalFlash)?;
+        self_test::run(external_flash, bank, report)
+    }
+
+    /// Summarizes the occupancy of all MCU banks. A `quick` summary only checks
+    /// whether each bank is erased, rather than verifying its image.
+    pub fn usage_mcu(&mut self, quick: bool) -> Result<Usage, Error> {
+        usage::summarize::<R, _>(&mut self.mcu_flash, self.mcu_banks.iter().cloned(), quick)
+    }
+
+    /// MCU flash region reserved for the bootloader.
+    pub fn bootloader_region(&self) -> BootloaderRegion<MCUF::Address> { self.bootloader_region }
+
+    /// Number of bytes of the bootloader region taken by its binary, up to the last
+    /// programmed byte.
+    pub fn bootloader_programmed_length(&mut self) -> Result<usize, Error> {
+        self.bootloader_region.programmed_length(&mut self.mcu_flash)
+    }
+
+    /// Summarizes the occupancy of all external banks, if there is external flash.
+    /// A `quick` summary only checks whether each bank is erased.
+    pub fn usage_external(&mut self, quick: bool) -> Result<Usage, Error> {
+        match self.external_flash.as_mut() {
+            Some(flash) => {
+                usage::summarize::<R, _>(flash, self.external_banks.iter().cloned(), quick)
+            }
+            None => Ok(Usage::default()),
+        }
+    }
+
+    /// Wipes all application state: Every bank that is neither bootable nor golden is
+    /// erased, the boot metrics relayed by Loadstone are discarded (both here and in RAM)
+    /// and the update signal (if supported) is reset to disallow updates. Golden images
+    /// are kept, as they're the last resort to recover the device.
+    pub fn factory_reset(&mut self) -> Result<(), Error> {
+        for bank in self.mcu_banks().filter(|b| !b.bootable && !b.is_golden) {
+            self.erase_bank_mcu(bank)?;
+        }
+        if self.external_flash.is_some() {
+            for bank in self.external_banks().filter(|b| !b.is_golden) {
+                self.erase_bank_external(bank)?;
+            }
+        }
+        self.boot_metrics = None;
+        unsafe { boot_info_mut() }.clear();
+        if let Some(us) = self.update_signal.as_mut() {
+            us.write_update_plan(UpdatePlan::None);
+        }
+        Ok(())
+    }
+
     /// Fully erases the external flash bank, ensuring there are no leftover images
     /// and future writes to the external flash are as fast as possible.
     pub fn format_external(&mut self) -> Result<(), Error> {
@@ -105,6 +416,30 @@ impl<MCUF: Flash, EXTF: Flash, SRL: Serial, R: image::Reader, WUS: WriteUpdateSi
     /// Triggers a soft system reset.
     pub fn reset(&mut self) -> ! { SCB::sys_reset(); }
 
+    /// Triggers a soft system reset, first persisting the update plan that makes
+    /// Loadstone behave as the mode requires on the next boot. Without the update signal
+    /// feature, only normal resets are possible.
+    pub fn reset_into(&mut self, mode: ResetMode) -> Result<!, Error> {
+        match self.update_signal.as_mut() {
+            Some(signal) => prepare_reset(signal, mode, |_| SCB::sys_reset()),
+            None if mode == ResetMode::Normal => SCB::sys_reset(),
+            None => Err(Error::DeviceError(
+                "Reset modes other than `normal` are not supported without the update \
+                signal feature enabled.",
+            )),
+        }
+    }
+
+    /// Reads back the update plan Loadstone will follow on the next boot.
+    pub fn update_plan(&self) -> Result<UpdatePlan, Error> {
+        self.update_signal.as_ref().map(ReadUpdateSignal::read_update_plan).ok_or(
+            Error::DeviceError(
+                "Update signal commands are not supported without the update \
+                signal feature enabled.",
+            ),
+        )
+    }
+
     pub fn set_update_signal(&mut self, plan: UpdatePlan) -> Result<(), Error> {
         if let Some(us) = self.update_signal.as_mut() {
             us.write_update_plan(plan);
@@ -120,14 +455,7 @@ impl<MCUF: Flash, EXTF: Flash, SRL: Serial, R: image::Reader, WUS: WriteUpdateSi
     /// Gathers metrics left over in memory by Loadstone, if available, and launches
     /// the command line interface.
     pub fn run(mut self) -> ! {
-        self.boot_metrics = {
-            let metrics = unsafe { boot_metrics().clone() };
-            if metrics.is_valid() {
-                Some(metrics)
-            } else {
-                None
-            }
-        };
+        self.boot_metrics = unsafe { boot_info() }.metrics();
         let mut cli = self.cli.take().unwrap();
         let greeting = self.greeting.take();
         loop {
@@ -135,3 +463,58 @@ impl<MCUF: Flash, EXTF: Flash, SRL: Serial, R: image::Reader, WUS: WriteUpdateSi
         }
     }
 }
+
+#[cfg(test)]
+#[doc(hidden)]
+pub mod doubles {
+    use super::*;
+    use crate::devices::{
+        bootloader::doubles::FakeUpdateSignal, doubles::ScriptedSerial, image::CrcImageReader,
+    };
+    use blue_hal::hal::doubles::{
+        flash::{Address, FakeFlash},
+        time::MockSysTick,
+    };
+    use crc::crc32;
+
+    pub type CrcBootManagerDouble = BootManager<
+        FakeFlash,
+        FakeFlash,
+        ScriptedSerial,
+        CrcImageReader<{ crc32::IEEE }, false>,
+        FakeUpdateSignal,
+        MockSysTick,
+    >;
+
+    impl<R: image::Reader>
+        BootManager<FakeFlash, FakeFlash, ScriptedSerial, R, FakeUpdateSignal, MockSysTick>
+    {
+        /// Boot manager over an MCU flash already holding the contents of its banks.
+        pub fn new(mcu_flash: FakeFlash, mcu_banks: &'static [image::Bank<Address>]) -> Self {
+            Self {
+                external_banks: &[],
+                mcu_banks,
+                mcu_flash,
+                external_flash: None,
+                cli: None,
+                boot_metrics: None,
+                greeting: None,
+                recovery_enabled: false,
+                baud_control: None,
+                _marker: Default::default(),
+                update_signal: None,
+                start_time: None,
+                backup_bank: None,
+                staging_bank: None,
+                staging_rotation: &[],
+                golden_override: false,
+                bootloader_region: BootloaderRegion { location: Address(0), length: 0 },
+                verify_writes: false,
+            }
+        }
+
+        pub fn with_backup_bank(self, index: u8) -> Self {
+            Self { backup_bank: Some(index), ..self }
+        }
+    }
+}
@@ -6,33 +6,66 @@
 //! boot process, or logging. It's important for the application to collect
 //! these metrics immediately, as they exist in an untracked section of
 //! memory where they can be quickly clobbered by stack variables.
+//!
+//! # Boot info ABI
+//!
+//! The metrics are stored as a [`BootInfo`] structure, occupying the last 20 bytes
+//! of RAM. This layout is the stable contract between Loadstone and the application,
+//! and only changes along with [`BOOT_INFO_VERSION`]. Every field is little endian:
+//!
+//! | Offset | Size | Field                                                    |
+//! |--------|------|----------------------------------------------------------|
+//! | 0      | 4    | Magic, always [`BOOT_INFO_MAGIC`]                        |
+//! | 4      | 2    | Version, [`BOOT_INFO_VERSION`]                           |
+//! | 6      | 1    | Boot reason: 0 direct, 1 restored, 2 updated             |
+//! | 7      | 1    | Index of the source bank, when restored or updated       |
+//! | 8      | 4    | Boot time in milliseconds, or [`BOOT_INFO_NONE`]         |
+//! | 12     | 4    | Boot count, or [`BOOT_INFO_NONE`]                        |
+//! | 16     | 4    | CRC32 (IEEE) of bytes 0 to 15                            |
+//!
+//! RAM is not cleared across resets, so the structure may hold anything when the
+//! application didn't come from Loadstone (e.g. it was flashed and started by a
+//! debugger) or when it was clobbered. The application must check the magic, the
+//! version and the checksum before trusting any field. Rust applications can use
+//! [`BootInfo::metrics`], which does so. A C application could read it as follows:
+//!
+//! ```c
+//! typedef struct {
+//!     uint32_t magic;
+//!     uint16_t version;
+//!     uint8_t reason;
+//!     uint8_t source_bank;
+//!     uint32_t boot_time_ms;
+//!     uint32_t boot_count;
+//!     uint32_t checksum;
+//! } boot_info_t;
+//!
+//! bool read_boot_info(boot_info_t *info) {
+//!     *info = *(const volatile boot_info_t *)(RAM_END - sizeof(boot_info_t));
+//!     return info->magic == 0x4C534249 && info->version == 1 && info->reason <= 2
+//!         && info->checksum == crc32_ieee((const uint8_t *)info, 16);
+//! }
+//! ```
+
+use core::ops::Range;
+use crc::crc32;
 
 /// Collection of boot metrics relayed by Loadstone to the booted application.
-#[repr(C)]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct BootMetrics {
-    /// Magic string to ensure the boot metrics' integrity when read. Must
-    /// be equal to [`BOOT_MAGIC_START`] when read to guarantee validity.
-    pub boot_magic_start: u32,
     /// The actions taken by Loadstone that ultimately led to an image being
     /// booted.
     pub boot_path: BootPath,
     /// Time from construction of Loadstone's driver suite to the target image
     /// being booted.
     pub boot_time_ms: Option<u32>,
-    /// Magic string to ensure the boot metrics' integrity when read. Must
-    /// be equal to [`BOOT_MAGIC_END`] when read to guarantee validity.
-    pub boot_magic_end: u32,
+    /// Number of times Loadstone has run on this unit, including this boot,
+    /// if the persistent boot counter is enabled.
+    pub boot_count: Option<u32>,
 }
 
-/// Bit pattern that should mark the start of a valid boot metrics struct.
-pub const BOOT_MAGIC_START: u32 = 0xDEADBEEF;
-/// Bit pattern that should mark the end of a valid boot metrics struct.
-pub const BOOT_MAGIC_END: u32 = 0xCAFEBABE;
-
 /// Actions taken by Loadstone that ultimately led to an image being booted.
-#[repr(C)]
-#[derive(Clone)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum BootPath {
     /// The image was booted directly from the main MCU flash bank, as there
     /// was no newer image to supersede it.
@@ -45,24 +78,108 @@ pub enum BootPath {
 
 impl Default for BootMetrics {
     fn default() -> Self {
-        Self {
-            boot_magic_start: BOOT_MAGIC_START,
-            boot_path: BootPath::Direct,
-            boot_time_ms: None,
-            boot_magic_end: BOOT_MAGIC_END,
-        }
+        Self { boot_path: BootPath::Direct, boot_time_ms: None, boot_count: None }
     }
 }
 
-impl BootMetrics {
-    /// The boot metrics struct is valid. This allows the application to verify that the metrics
-    /// read directly from unstructed RAM has not been clobbered.
+/// Marks the start of a boot info structure written by Loadstone ("LSBI").
+pub const BOOT_INFO_MAGIC: u32 = 0x4C53_4249;
+/// Version of the [`BootInfo`] layout.
+pub const BOOT_INFO_VERSION: u16 = 1;
+/// Stored in place of an optional field that wasn't recorded.
+pub const BOOT_INFO_NONE: u32 = u32::MAX;
+
+/// Size in bytes of the fields covered by the [`BootInfo`] checksum.
+const CHECKSUMMED_SIZE: usize = 16;
+
+const REASON_DIRECT: u8 = 0;
+const REASON_RESTORED: u8 = 1;
+const REASON_UPDATED: u8 = 2;
+
+/// Boot metrics as laid out in RAM. See the [module level](self) documentation for the
+/// layout. Every field is a plain integer, so the structure can be safely read from
+/// memory holding anything, and only interpreted once validated.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BootInfo {
+    pub magic: u32,
+    pub version: u16,
+    pub reason: u8,
+    pub source_bank: u8,
+    pub boot_time_ms: u32,
+    pub boot_count: u32,
+    pub checksum: u32,
+}
+
+impl From<&BootMetrics> for BootInfo {
+    fn from(metrics: &BootMetrics) -> Self {
+        let (reason, source_bank) = match metrics.boot_path {
+            BootPath::Direct => (REASON_DIRECT, 0),
+            BootPath::Restored { bank } => (REASON_RESTORED, bank),
+            BootPath::Updated { bank } => (REASON_UPDATED, bank),
+        };
+        let mut info = BootInfo {
+            magic: BOOT_INFO_MAGIC,
+            version: BOOT_INFO_VERSION,
+            reason,
+            source_bank,
+            boot_time_ms: metrics.boot_time_ms.unwrap_or(BOOT_INFO_NONE),
+            boot_count: metrics.boot_count.unwrap_or(BOOT_INFO_NONE),
+            checksum: 0,
+        };
+        info.checksum = info.expected_checksum();
+        info
+    }
+}
+
+impl BootInfo {
+    /// CRC32 of every field preceding the checksum, as laid out in RAM.
+    fn expected_checksum(&self) -> u32 {
+        let mut bytes = [0u8; CHECKSUMMED_SIZE];
+        bytes[0..4].copy_from_slice(&self.magic.to_le_bytes());
+        bytes[4..6].copy_from_slice(&self.version.to_le_bytes());
+        bytes[6] = self.reason;
+        bytes[7] = self.source_bank;
+        bytes[8..12].copy_from_slice(&self.boot_time_ms.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.boot_count.to_le_bytes());
+        crc32::checksum_ieee(&bytes)
+    }
+
+    /// Whether the structure was written by this version of Loadstone, and hasn't been
+    /// clobbered since.
     pub fn is_valid(&self) -> bool {
-        self.boot_magic_start == BOOT_MAGIC_START && self.boot_magic_end == BOOT_MAGIC_END
+        self.magic == BOOT_INFO_MAGIC
+            && self.version == BOOT_INFO_VERSION
+            && self.checksum == self.expected_checksum()
+    }
+
+    /// Invalidates the structure, so the metrics it held are no longer reported.
+    pub fn clear(&mut self) {
+        self.magic = 0;
+        self.checksum = 0;
+    }
+
+    /// Decodes the boot metrics, or `None` if the structure isn't valid.
+    pub fn metrics(&self) -> Option<BootMetrics> {
+        if !self.is_valid() {
+            return None;
+        }
+        let boot_path = match self.reason {
+            REASON_DIRECT => BootPath::Direct,
+            REASON_RESTORED => BootPath::Restored { bank: self.source_bank },
+            REASON_UPDATED => BootPath::Updated { bank: self.source_bank },
+            _ => return None,
+        };
+        let optional = |value: u32| (value != BOOT_INFO_NONE).then_some(value);
+        Some(BootMetrics {
+            boot_path,
+            boot_time_ms: optional(self.boot_time_ms),
+            boot_count: optional(self.boot_count),
+        })
     }
 }
 
-/// Reinterprets an arbitrary memory range as a mutable boot metrics struct.
+/// Reinterprets an arbitrary memory range as a mutable boot info struct.
 ///
 /// # Safety
 ///
@@ -72,19 +189,106 @@ impl BootMetrics {
 ///
 /// This *will* clobber data so it must only be called immediately before jumping into the target
 /// application.
-pub unsafe fn boot_metrics_mut() -> &'static mut BootMetrics {
+pub unsafe fn boot_info_mut() -> &'static mut BootInfo {
+    let boot_info_raw: *mut BootInfo =
+        core::mem::transmute::<usize, *mut BootInfo>(boot_info_range().start);
+    boot_info_raw.as_mut().unwrap()
+}
+
+/// RAM addresses occupied by the boot info struct, which must survive until the
+/// application reads them.
+pub fn boot_info_range() -> Range<usize> {
     let ram_end = 0x20010000;
-    let boot_metrics_raw: *mut BootMetrics = core::mem::transmute::<usize, *mut BootMetrics>(
-        ram_end - core::mem::size_of::<BootMetrics>(),
-    );
-    boot_metrics_raw.as_mut().unwrap()
+    ram_end - core::mem::size_of::<BootInfo>()..ram_end
 }
 
-/// Reinterprets an arbitrary memory range as an immmutable boot metrics struct.
+/// Reinterprets an arbitrary memory range as an immmutable boot info struct.
 ///
 /// # Safety
 ///
 /// Horrendously unsafe. Simply returns a block at end of RAM reinterpreted as an arbitrary struct.
 /// Only useful right after bootstrapping the app, to retrieve metrics information before having a
-/// chance to clobber it.
-pub unsafe fn boot_metrics() -> &'static BootMetrics { boot_metrics_mut() }
+/// chance to clobber it. The struct must be validated before use (see [`BootInfo::metrics`]).
+pub unsafe fn boot_info() -> &'static BootInfo { boot_info_mut() }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics(boot_path: BootPath) -> BootMetrics {
+        BootMetrics { boot_path, boot_time_ms: Some(120), boot_count: None }
+    }
+
+    #[test]
+    fn boot_info_layout_matches_the_documented_abi() {
+        assert_eq!(core::mem::size_of::<BootInfo>(), 20);
+        let info = BootInfo::from(&BootMetrics::default());
+        let base = &info as *const BootInfo as usize;
+        assert_eq!(&info.version as *const u16 as usize - base, 4);
+        assert_eq!(&info.source_bank as *const u8 as usize - base, 7);
+        assert_eq!(&info.checksum as *const u32 as usize - base, CHECKSUMMED_SIZE);
+    }
+
+    #[test]
+    fn metrics_survive_the_round_trip_through_boot_info() {
+        let paths =
+            [BootPath::Direct, BootPath::Restored { bank: 3 }, BootPath::Updated { bank: 2 }];
+        for path in paths.iter() {
+            let info = BootInfo::from(&metrics(*path));
+            assert!(info.is_valid());
+            assert_eq!(Some(metrics(*path)), info.metrics());
+        }
+    }
+
+    #[test]
+    fn clobbered_boot_info_is_rejected() {
+        let info = BootInfo::from(&metrics(BootPath::Updated { bank: 2 }));
+
+        let mut clobbered = info;
+        clobbered.boot_time_ms = 0;
+        assert_eq!(None, clobbered.metrics());
+
+        let mut clobbered = info;
+        clobbered.checksum ^= 1;
+        assert_eq!(None, clobbered.metrics());
+
+        let mut clobbered = info;
+        clobbered.magic = !BOOT_INFO_MAGIC;
+        clobbered.checksum = clobbered.expected_checksum();
+        assert_eq!(None, clobbered.metrics());
+    }
+
+    #[test]
+    fn cleared_boot_info_is_rejected() {
+        let mut info = BootInfo::from(&metrics(BootPath::Restored { bank: 3 }));
+        info.clear();
+        assert_eq!(None, info.metrics());
+    }
+
+    #[test]
+    fn stale_or_garbage_boot_info_is_rejected() {
+        let mut stale = BootInfo::from(&BootMetrics::default());
+        stale.version = BOOT_INFO_VERSION + 1;
+        stale.checksum = stale.expected_checksum();
+        assert_eq!(None, stale.metrics());
+
+        let mut unknown_reason = BootInfo::from(&BootMetrics::default());
+        unknown_reason.reason = 7;
+        unknown_reason.checksum = unknown_reason.expected_checksum();
+        assert_eq!(None, unknown_reason.metrics());
+
+        for fill in [0x00u32, 0xFFFF_FFFF, 0xDEAD_BEEF].iter() {
+            let garbage = BootInfo {
+                magic: *fill,
+                version: *fill as u16,
+                reason: *fill as u8,
+                source_bank: *fill as u8,
+                boot_time_ms: *fill,
+                boot_count: *fill,
+                checksum: *fill,
+            };
+            assert!(!garbage.is_valid());
+            assert_eq!(None, garbage.metrics());
+        }
+    }
+}
@@ -1,5 +1,8 @@
 use super::*;
-use crate::devices::update_signal::ReadUpdateSignal;
+use crate::devices::{
+    image::compression::{self, Inflater},
+    update_signal::{ReadUpdateSignal, WriteUpdateSignal},
+};
 
 impl<
         EXTF: Flash,
@@ -7,36 +10,51 @@ impl<
         SRL: Serial,
         T: time::Now,
         R: image::Reader,
-        RUS: ReadUpdateSignal,
-    > Bootloader<EXTF, MCUF, SRL, T, R, RUS>
+        RUS: ReadUpdateSignal + WriteUpdateSignal,
+        LED: led::Toggle,
+        PIN: gpio::InputPin,
+    > Bootloader<EXTF, MCUF, SRL, T, R, RUS, LED, PIN>
 {
-    pub fn copy_image_single_flash<F: Flash>(
+    pub fn copy_image<I: Flash, O: Flash>(
         serial: &mut Option<SRL>,
-        flash: &mut F,
-        input_bank: image::Bank<F::Address>,
-        output_bank: image::Bank<F::Address>,
+        input_flash: &mut I,
+        output_flash: &mut O,
+        input_bank: image::Bank<I::Address>,
+        output_bank: image::Bank<O::Address>,
         must_be_golden: bool,
+        verify_writes: bool,
     ) -> Result<(), Error> {
-        if input_bank.index == output_bank.index {
-            return Err(Error::DeviceError("Attempted to copy a bank into itself"));
-        }
-        let input_image = R::image_at(flash, input_bank)?;
+        let input_image = scan_bank::<R, _, _>(serial, input_flash, input_bank)?;
         if must_be_golden && !input_image.is_golden() {
             duprintln!(serial, "Image is not golden.",);
             return Err(Error::DeviceError("Image is not golden"));
         }
+        if must_be_golden {
+            if let Some(decompressed_size) = decompressed_size(input_flash, &input_image)? {
+                return Self::decompress_image(
+                    serial,
+                    input_flash,
+                    output_flash,
+                    input_image,
+                    output_bank,
+                    decompressed_size,
+                    verify_writes,
+                );
+            }
+        }
+        if input_image.total_size() > output_bank.size {
+            duprintln!(serial, "Image is too large for bank {:?}.", output_bank.index);
+            return Err(Error::ImageTooLargeForBank);
+        }
         duprintln!(
             serial,
             "Copying bank {:?} image [Address {:?}, size {:?}]\r\n* Input: [{}]\r\n* Output: [{}]",
             input_bank.index,
             input_image.location().into(),
             input_image.size(),
-            F::label(),
-            F::label(),
+            I::label(),
+            O::label(),
         );
-        let input_image_start_address = input_bank.location;
-        let output_image_start_address = output_bank.location;
-
         // Large transfer buffer ensures that the number of read-write cycles needed
         // to guarantee flash integrity through the process is minimal.
         const TRANSFER_BUFFER_SIZE: usize = KB!(64);
@@ -47,56 +65,290 @@ impl<
 
         while byte_index < total_size {
             let bytes_to_read = min(TRANSFER_BUFFER_SIZE, total_size.saturating_sub(byte_index));
-            block!(
-                flash.read(input_image_start_address + byte_index, &mut buffer[0..bytes_to_read])
-            )?;
-            block!(flash.write(output_image_start_address + byte_index, &buffer[0..bytes_to_read]))?;
+            let input_address = input_bank.address_at(byte_index, bytes_to_read)?;
+            let output_address = output_bank.address_at(byte_index, bytes_to_read)?;
+            block!(input_flash.read(input_address, &mut buffer[0..bytes_to_read]))?;
+            let bytes = &buffer[0..bytes_to_read];
+            write_and_verify(output_flash, output_address, bytes, verify_writes)?;
             byte_index += bytes_to_read;
         }
         Ok(())
     }
 
-    pub fn copy_image<I: Flash, O: Flash>(
+    /// Expands a compressed golden image into the output bank. The decompressed image
+    /// carries its own trailer, so it must be verified again once in place.
+    fn decompress_image<I: Flash, O: Flash>(
         serial: &mut Option<SRL>,
         input_flash: &mut I,
         output_flash: &mut O,
-        input_bank: image::Bank<I::Address>,
+        input_image: Image<I::Address>,
         output_bank: image::Bank<O::Address>,
-        must_be_golden: bool,
+        decompressed_size: usize,
+        verify_writes: bool,
     ) -> Result<(), Error> {
-        let input_image = R::image_at(input_flash, input_bank)?;
-        if must_be_golden && !input_image.is_golden() {
-            duprintln!(serial, "Image is not golden.",);
-            return Err(Error::DeviceError("Image is not golden"));
+        if decompressed_size > output_bank.size {
+            duprintln!(serial, "Image is too large for bank {:?}.", output_bank.index);
+            return Err(Error::ImageTooLargeForBank);
         }
         duprintln!(
             serial,
-            "Copying bank {:?} image [Address {:?}, size {:?}]\r\n* Input: [{}]\r\n* Output: [{}]",
-            input_bank.index,
-            input_image.location().into(),
+            "Decompressing golden image [{:?} -> {:?} bytes]\r\n* Input: [{}]\r\n* Output: [{}]",
             input_image.size(),
+            decompressed_size,
             I::label(),
             O::label(),
         );
-        let input_image_start_address = input_bank.location;
-        let output_image_start_address = output_bank.location;
 
-        // Large transfer buffer ensures that the number of read-write cycles needed
-        // to guarantee flash integrity through the process is minimal.
-        const TRANSFER_BUFFER_SIZE: usize = KB!(64);
-        let mut buffer = [0u8; TRANSFER_BUFFER_SIZE];
-        let mut byte_index = 0usize;
-
-        let total_size = input_image.total_size();
-
-        while byte_index < total_size {
-            let bytes_to_read = min(TRANSFER_BUFFER_SIZE, total_size.saturating_sub(byte_index));
+        let mut buffer = [0u8; DECOMPRESSION_BUFFER_SIZE];
+        let mut inflater = Inflater::<DECOMPRESSION_BUFFER_SIZE>::new(decompressed_size);
+        let mut write = |offset: usize, bytes: &[u8]| -> Result<(), Error> {
+            let address = output_bank.address_at(offset, bytes.len())?;
+            write_and_verify(output_flash, address, bytes, verify_writes)
+        };
+        let mut byte_index = compression::HEADER_SIZE;
+        while byte_index < input_image.size() {
+            let bytes_to_read =
+                min(DECOMPRESSION_BUFFER_SIZE, input_image.size().saturating_sub(byte_index));
             block!(input_flash
-                .read(input_image_start_address + byte_index, &mut buffer[0..bytes_to_read]))?;
-            block!(output_flash
-                .write(output_image_start_address + byte_index, &buffer[0..bytes_to_read]))?;
+                .read(input_image.location() + byte_index, &mut buffer[0..bytes_to_read]))?;
+            inflater.inflate(&buffer[0..bytes_to_read], &mut write)?;
             byte_index += bytes_to_read;
         }
+        inflater.finish(&mut write)
+    }
+}
+
+/// Copies the image in a bank into another bank in the same flash chip. Golden images
+/// are expanded on the way if `must_be_golden` is set and they are stored compressed.
+pub fn copy_image_single_flash<R: image::Reader, F: Flash, SRL: Serial>(
+    serial: &mut Option<SRL>,
+    flash: &mut F,
+    input_bank: image::Bank<F::Address>,
+    output_bank: image::Bank<F::Address>,
+    must_be_golden: bool,
+    verify_writes: bool,
+) -> Result<(), Error> {
+    if input_bank.index == output_bank.index {
+        return Err(Error::DeviceError("Attempted to copy a bank into itself"));
+    }
+    let input_image = scan_bank::<R, _, _>(serial, flash, input_bank)?;
+    if must_be_golden && !input_image.is_golden() {
+        duprintln!(serial, "Image is not golden.",);
+        return Err(Error::DeviceError("Image is not golden"));
+    }
+    if must_be_golden {
+        if let Some(decompressed_size) = decompressed_size(flash, &input_image)? {
+            return decompress_image_single_flash(
+                serial,
+                flash,
+                input_image,
+                output_bank,
+                decompressed_size,
+                verify_writes,
+            );
+        }
+    }
+    if input_image.total_size() > output_bank.size {
+        duprintln!(serial, "Image is too large for bank {:?}.", output_bank.index);
+        return Err(Error::ImageTooLargeForBank);
+    }
+    duprintln!(
+        serial,
+        "Copying bank {:?} image [Address {:?}, size {:?}]\r\n* Input: [{}]\r\n* Output: [{}]",
+        input_bank.index,
+        input_image.location().into(),
+        input_image.size(),
+        F::label(),
+        F::label(),
+    );
+    // Large transfer buffer ensures that the number of read-write cycles needed
+    // to guarantee flash integrity through the process is minimal.
+    const TRANSFER_BUFFER_SIZE: usize = KB!(64);
+    let mut buffer = [0u8; TRANSFER_BUFFER_SIZE];
+    let mut byte_index = 0usize;
+
+    let total_size = input_image.total_size();
+
+    while byte_index < total_size {
+        let bytes_to_read = min(TRANSFER_BUFFER_SIZE, total_size.saturating_sub(byte_index));
+        let input_address = input_bank.address_at(byte_index, bytes_to_read)?;
+        let output_address = output_bank.address_at(byte_index, bytes_to_read)?;
+        block!(flash.read(input_address, &mut buffer[0..bytes_to_read]))?;
+        write_and_verify(flash, output_address, &buffer[0..bytes_to_read], verify_writes)?;
+        byte_index += bytes_to_read;
+    }
+    Ok(())
+}
+
+/// Returns the size a golden image expands to, if it is stored compressed.
+pub(super) fn decompressed_size<F: Flash>(
+    flash: &mut F,
+    image: &Image<F::Address>,
+) -> Result<Option<usize>, Error> {
+    if image.size() < compression::HEADER_SIZE {
+        return Ok(None);
+    }
+    let mut header = [0u8; compression::HEADER_SIZE];
+    block!(flash.read(image.location(), &mut header))?;
+    Ok(compression::decompressed_size(&header))
+}
+
+/// Expands a compressed golden image into the output bank. The decompressed image
+/// carries its own trailer, so it must be verified again once in place.
+fn decompress_image_single_flash<F: Flash, SRL: Serial>(
+    serial: &mut Option<SRL>,
+    flash: &mut F,
+    input_image: Image<F::Address>,
+    output_bank: image::Bank<F::Address>,
+    decompressed_size: usize,
+    verify_writes: bool,
+) -> Result<(), Error> {
+    if decompressed_size > output_bank.size {
+        duprintln!(serial, "Image is too large for bank {:?}.", output_bank.index);
+        return Err(Error::ImageTooLargeForBank);
+    }
+    duprintln!(
+        serial,
+        "Decompressing golden image [{:?} -> {:?} bytes]\r\n* Input: [{}]\r\n* Output: [{}]",
+        input_image.size(),
+        decompressed_size,
+        F::label(),
+        F::label(),
+    );
+
+    let mut buffer = [0u8; DECOMPRESSION_BUFFER_SIZE];
+    let mut inflater = Inflater::<DECOMPRESSION_BUFFER_SIZE>::new(decompressed_size);
+    let mut byte_index = compression::HEADER_SIZE;
+    while byte_index < input_image.size() {
+        let bytes_to_read =
+            min(DECOMPRESSION_BUFFER_SIZE, input_image.size().saturating_sub(byte_index));
+        block!(flash.read(input_image.location() + byte_index, &mut buffer[0..bytes_to_read]))?;
+        inflater.inflate(&buffer[0..bytes_to_read], |offset, bytes| {
+            let address = output_bank.address_at(offset, bytes.len())?;
+            write_and_verify(flash, address, bytes, verify_writes)
+        })?;
+        byte_index += bytes_to_read;
+    }
+    inflater.finish(|offset, bytes| {
+        let address = output_bank.address_at(offset, bytes.len())?;
+        write_and_verify(flash, address, bytes, verify_writes)
+    })
+}
+
+/// Size of the buffers used while decompressing golden images. Both the compressed
+/// input and the decompressed output are staged through a buffer of this size.
+const DECOMPRESSION_BUFFER_SIZE: usize = KB!(4);
+
+/// Size of the chunks written bytes are read back in, when verifying writes.
+const READ_BACK_CHUNK_SIZE: usize = 256;
+
+/// Writes bytes to flash and, if `verify` is set, reads them back to compare them. A
+/// failed program (e.g. a brownout or a worn cell) is then caught as it happens, rather
+/// than when the copied image fails verification.
+pub fn write_and_verify<F: Flash>(
+    flash: &mut F,
+    address: F::Address,
+    bytes: &[u8],
+    verify: bool,
+) -> Result<(), Error> {
+    block!(flash.write(address, bytes))?;
+    if verify {
+        verify_written(flash, address, bytes)?;
+    }
+    Ok(())
+}
+
+/// Reads back bytes just written to flash, failing with [`Error::WriteVerificationFailed`]
+/// if they don't match what was written.
+pub fn verify_written<F: Flash>(
+    flash: &mut F,
+    address: F::Address,
+    expected: &[u8],
+) -> Result<(), Error> {
+    let mut buffer = [0u8; READ_BACK_CHUNK_SIZE];
+    for (index, expected) in expected.chunks(READ_BACK_CHUNK_SIZE).enumerate() {
+        let read_back = &mut buffer[..expected.len()];
+        block!(flash.read(address + index * READ_BACK_CHUNK_SIZE, read_back))?;
+        if read_back != expected {
+            return Err(Error::WriteVerificationFailed);
+        }
+    }
+    Ok(())
+}
+
+/// Writes a stream of byte blocks to a bank, refusing to write past its end. Blocks
+/// that would overrun the bank are not written, and `Error::ImageTooLargeForBank`
+/// is returned instead.
+pub fn write_blocks_within_bank<F, I, const N: usize>(
+    flash: &mut F,
+    bank: Bank<F::Address>,
+    blocks: I,
+) -> Result<(), Error>
+where
+    F: Flash,
+    I: Iterator<Item = [u8; N]>,
+{
+    let mut remaining = bank.size;
+    let mut overflowed = false;
+    let bounded_blocks = blocks.take_while(|_| {
+        overflowed = remaining < N;
+        remaining = remaining.saturating_sub(N);
+        !overflowed
+    });
+    flash.write_from_blocks(bank.location, bounded_blocks)?;
+    if overflowed {
+        Err(Error::ImageTooLargeForBank)
+    } else {
         Ok(())
     }
 }
+
+/// Writes a few bytes at an offset into a bank, leaving the rest of its contents intact
+/// (the flash driver merges them into the surrounding sectors). Writes that would extend
+/// past the end of the bank are refused before anything is written.
+pub fn write_within_bank<F: Flash>(
+    flash: &mut F,
+    bank: Bank<F::Address>,
+    offset: usize,
+    bytes: &[u8],
+) -> Result<(), Error> {
+    let address = bank
+        .address_at(offset, bytes.len())
+        .map_err(|_| Error::DeviceError("Write exceeds the bank size"))?;
+    block!(flash.write(address, bytes))?;
+    Ok(())
+}
+
+/// Copies the verified image in a bank into a backup bank in the same flash, unless the
+/// backup already holds the same image (by identifier). The copy is verified in place.
+/// Returns whether anything was copied.
+pub fn mirror_image<R: image::Reader, F: Flash, SRL: Serial>(
+    serial: &mut Option<SRL>,
+    flash: &mut F,
+    input_bank: Bank<F::Address>,
+    backup_bank: Bank<F::Address>,
+    verify_writes: bool,
+) -> Result<bool, Error> {
+    if input_bank.index == backup_bank.index {
+        return Err(Error::DeviceError("Attempted to copy a bank into itself"));
+    }
+    let image = R::image_at(flash, input_bank)?;
+    if let Ok(backup) = R::image_at(flash, backup_bank) {
+        if backup.identifier() == image.identifier() {
+            return Ok(false);
+        }
+    }
+    copy_image_single_flash::<R, _, _>(
+        serial,
+        flash,
+        input_bank,
+        backup_bank,
+        false,
+        verify_writes,
+    )?;
+
+    match R::image_at(flash, backup_bank) {
+        Ok(backup) if backup.identifier() == image.identifier() => Ok(true),
+        _ => Err(Error::DeviceError("Backup image failed verification")),
+    }
+}
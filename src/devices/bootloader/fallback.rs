@@ -0,0 +1,167 @@
+use super::*;
+use crate::devices::update_signal::{ReadUpdateSignal, WriteUpdateSignal};
+use blue_hal::{hal::serial::Read, utilities::buffer::TryCollectSlice};
+use core::str::from_utf8;
+
+/// What Loadstone does when no bank holds an image to boot or restore, and serial
+/// recovery is disabled.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NoImageFallback {
+    /// Halt with a panic.
+    Panic,
+    /// Retry the restore every `delay_ms` milliseconds until an image shows up (e.g. an
+    /// external flash chip that failed to come up earlier) and boots.
+    Retry { delay_ms: u32 },
+    /// Serve a minimal set of diagnostic commands over serial. Falls back to a panic
+    /// if serial is unavailable.
+    DiagnosticLoop,
+}
+
+/// Commands served by the diagnostic loop.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DiagnosticCommand {
+    /// Lists the commands.
+    Help,
+    /// Scans every bank, reporting whether it holds a valid image.
+    Banks,
+    /// Attempts the restore again, booting the restored image on success.
+    Retry,
+    /// Resets the device.
+    Reboot,
+}
+
+impl DiagnosticCommand {
+    pub fn parse(line: &str) -> Option<Self> {
+        match line.trim() {
+            "help" => Some(DiagnosticCommand::Help),
+            "banks" => Some(DiagnosticCommand::Banks),
+            "retry" => Some(DiagnosticCommand::Retry),
+            "reboot" => Some(DiagnosticCommand::Reboot),
+            _ => None,
+        }
+    }
+}
+
+/// Size of the buffer diagnostic commands are read into.
+const COMMAND_BUFFER_SIZE: usize = 32;
+
+/// Calls `attempt` until it succeeds, waiting `delay_ms` milliseconds as measured by
+/// the `T` time source before every retry.
+pub fn retry_until_ok<T: time::Now, O, F: FnMut() -> Option<O>>(
+    delay_ms: u32,
+    mut attempt: F,
+) -> O {
+    loop {
+        if let Some(output) = attempt() {
+            return output;
+        }
+        let start = T::now();
+        while (T::now() - start).0 < delay_ms {}
+    }
+}
+
+impl<
+        EXTF: Flash,
+        MCUF: Flash,
+        SRL: Serial,
+        T: time::Now,
+        R: image::Reader,
+        RUS: ReadUpdateSignal + WriteUpdateSignal,
+        LED: led::Toggle,
+        PIN: gpio::InputPin,
+    > Bootloader<EXTF, MCUF, SRL, T, R, RUS, LED, PIN>
+{
+    /// Last resort after failing to restore an image without serial recovery, following
+    /// the configured [`NoImageFallback`].
+    pub(super) fn no_image_fallback(&mut self) -> ! {
+        match self.no_image_fallback {
+            NoImageFallback::Retry { delay_ms } => {
+                log!(self, Warn, "No image to boot. Retrying the restore periodically...");
+                retry_until_ok::<T, !, _>(delay_ms, || {
+                    self.tick_status_led();
+                    let image = self.restore().ok()?;
+                    let error = self.boot(image).unwrap_err();
+                    log!(self, Error, "Failed to boot from the restored image.");
+                    defmt_log!(info, "Boot error: {:?}", error);
+                    None
+                })
+            }
+            NoImageFallback::DiagnosticLoop if self.serial.is_some() => self.diagnostic_loop(),
+            _ => panic!("FATAL: Failed to boot, and serial recovery is not supported."),
+        }
+    }
+
+    /// Serves diagnostic commands over serial until an image is restored or the device
+    /// is reset.
+    fn diagnostic_loop(&mut self) -> ! {
+        duprintln!(self.serial, "-- Loadstone Diagnostic Mode --");
+        duprintln!(self.serial, "No image to boot. Type `help` for a list of commands.");
+        self.signal(Pattern::FastBlink);
+        loop {
+            if let Some(serial) = self.serial.as_mut() {
+                uprint!(serial, "\n> ");
+            }
+            let mut buffer = [0u8; COMMAND_BUFFER_SIZE];
+            let command =
+                self.read_command(&mut buffer).and_then(|line| DiagnosticCommand::parse(line));
+            match command {
+                Some(DiagnosticCommand::Help) => {
+                    duprintln!(self.serial, "[help] - Lists the commands.");
+                    duprintln!(self.serial, "[banks] - Reports the image in every bank.");
+                    duprintln!(self.serial, "[retry] - Retries the restore, booting on success.");
+                    duprintln!(self.serial, "[reboot] - Resets the device.");
+                }
+                Some(DiagnosticCommand::Banks) => self.report_banks(),
+                Some(DiagnosticCommand::Retry) => match self.restore() {
+                    Ok(image) => {
+                        self.boot(image).unwrap_err();
+                        duprintln!(self.serial, "Failed to boot from the restored image.");
+                    }
+                    Err(_) => duprintln!(self.serial, "Still no image to restore."),
+                },
+                Some(DiagnosticCommand::Reboot) => self.reboot(),
+                None => duprintln!(self.serial, "Unknown command. Type `help` for a list."),
+            }
+        }
+    }
+
+    /// Reads a line from serial, returning `None` if it's too long or not valid UTF-8.
+    fn read_command<'a>(&mut self, buffer: &'a mut [u8]) -> Option<&'a str> {
+        let serial = self.serial.as_mut()?;
+        let bytes = Read::bytes(serial).take_while(|element| match element {
+            Err(_) => true,
+            Ok(b) => *b != b'\n',
+        });
+        let received = bytes.try_collect_slice(buffer).ok()?;
+        if received >= buffer.len() {
+            return None;
+        }
+        from_utf8(&buffer[..received]).ok()
+    }
+
+    /// Prints whether each bank holds a valid image.
+    fn report_banks(&mut self) {
+        for bank in self.mcu_banks() {
+            let valid = R::image_at(&mut self.mcu_flash, bank).is_ok();
+            duprintln!(
+                self.serial,
+                "[{}] Bank {}: {}",
+                MCUF::label(),
+                bank.index,
+                if valid { "Valid image" } else { "No valid image" }
+            );
+        }
+        if let Some(flash) = self.external_flash.as_mut() {
+            for bank in self.external_banks.iter() {
+                let valid = R::image_at(flash, *bank).is_ok();
+                duprintln!(
+                    self.serial,
+                    "[{}] Bank {}: {}",
+                    EXTF::label(),
+                    bank.index,
+                    if valid { "Valid image" } else { "No valid image" }
+                );
+            }
+        }
+    }
+}
@@ -5,31 +5,195 @@
 //! handled by the `port` module as it depends on board
 //! specific information.
 use super::{
-    boot_metrics::{boot_metrics_mut, BootMetrics, BootPath},
-    image::{self, Bank, Image},
+    boot_counter,
+    boot_metrics::{boot_info_mut, boot_info_range, BootInfo, BootMetrics, BootPath},
+    file_transfer::Protocol,
+    image::{self, Bank, Image, VerificationCache},
+    recovery_pin::RecoveryPin,
+    serial_log,
+    status_led::{Pattern, StatusLed},
     traits::{Flash, Serial},
 };
-use crate::{devices::update_signal::ReadUpdateSignal, error::Error};
+use crate::{
+    devices::update_signal::{ReadUpdateSignal, UpdatePlan, WriteUpdateSignal},
+    error::Error,
+};
 use blue_hal::{
     duprintln,
-    hal::{flash, time},
-    KB,
+    hal::{flash, gpio, led, serial::TimeoutRead, time},
+    uprint, KB,
 };
-use core::{cmp::min, marker::PhantomData, mem::size_of};
+use core::{cmp::min, marker::PhantomData, mem::size_of, ops::Range};
 use cortex_m::peripheral::SCB;
-use defmt::{info, warn};
+use crc::crc32;
 use nb::block;
-use ufmt::uwriteln;
+use ufmt::{uwrite, uwriteln};
+
+/// Logs a message through `defmt`, mirroring it over serial if the configured
+/// serial log level allows it.
+macro_rules! log {
+    ($bootloader:expr, Error, $message:literal) => {{
+        defmt_log!(error, $message);
+        $bootloader.serial_log(serial_log::Level::Error, $message);
+    }};
+    ($bootloader:expr, Warn, $message:literal) => {{
+        defmt_log!(warn, $message);
+        $bootloader.serial_log(serial_log::Level::Warn, $message);
+    }};
+    ($bootloader:expr, Info, $message:literal) => {{
+        defmt_log!(info, $message);
+        $bootloader.serial_log(serial_log::Level::Info, $message);
+    }};
+}
 
 /// Operations related to copying images between flash chips.
 mod copy;
+/// Operations related to the last resort when there's no image and no serial recovery.
+mod fallback;
 /// Operations related to serial recovery when there's no fallback to restore to.
 mod recover;
+/// Decision on which image to boot, reported before acting on it.
+mod report;
 /// Operations related to restoring an image when there's no current one to boot.
 mod restore;
 /// Operations related to updating images with newer ones.
 mod update;
 
+pub use copy::{
+    copy_image_single_flash, mirror_image, verify_written, write_and_verify,
+    write_blocks_within_bank, write_within_bank,
+};
+pub use fallback::{retry_until_ok, DiagnosticCommand, NoImageFallback};
+pub use recover::{store_recovered_image, RecoveryTimeout};
+pub use report::{BootReason, BootReport};
+pub use update::{
+    candidacy, decide_update, in_update_pass, is_staging_bank, next_in_rotation, select_update,
+    update_target, Candidacy, StagingRotation, UpdateDecision, PROMOTION_MARKER,
+    STAGING_ROTATION_REGION_SIZE,
+};
+
+/// RAM region the application's vector table is copied to before booting, so
+/// interrupts are dispatched from RAM rather than flash.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RamVectorTable {
+    /// Start of the region. Must satisfy the `VTOR` alignment requirements.
+    pub address: usize,
+    /// Size in bytes of the full vector table.
+    pub length: usize,
+}
+
+impl RamVectorTable {
+    /// Number of bytes to copy from an image, which may be smaller than the
+    /// full vector table.
+    pub fn copy_length(&self, image_size: usize) -> usize { min(self.length, image_size) }
+}
+
+/// Bounds an image's initial stack pointer and reset handler are checked against before
+/// jumping to it, so a malformed image is rejected instead of hardfaulting.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct JumpValidation {
+    /// Start of the device's RAM.
+    pub ram_start: usize,
+    /// Size in bytes of the device's RAM.
+    pub ram_size: usize,
+}
+
+impl JumpValidation {
+    /// Whether the first two vector table entries of an image spanning `image_size` bytes
+    /// from `image_start` could plausibly belong to it.
+    pub fn is_plausible(
+        &self,
+        stack_pointer: u32,
+        reset_handler: u32,
+        image_start: usize,
+        image_size: usize,
+    ) -> bool {
+        // The stack is full descending, so the initial stack pointer may sit right past the
+        // end of RAM, but never at its very start.
+        let stack_pointer = stack_pointer as usize;
+        let stack_pointer_in_ram =
+            stack_pointer > self.ram_start && stack_pointer <= self.ram_start + self.ram_size;
+        // Cortex-M only runs thumb code, so the lowest bit of the handler address must be set.
+        let thumb = reset_handler & 1 == 1;
+        let reset_handler = (reset_handler & !1) as usize;
+        let reset_handler_in_image =
+            reset_handler >= image_start && reset_handler < image_start + image_size;
+        stack_pointer_in_ram && thumb && reset_handler_in_image
+    }
+}
+
+/// RAM region zeroed right before jumping to an image, so secrets Loadstone handled
+/// (e.g. decryption keys) don't linger in memory the application can read.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RamClear {
+    /// Start of the region.
+    pub start: usize,
+    /// Size in bytes of the region.
+    pub length: usize,
+}
+
+impl RamClear {
+    /// Bytes right below the stack pointer left untouched, as they hold the frames of
+    /// the clearing routine itself.
+    pub const STACK_MARGIN: usize = 256;
+
+    /// Parts of the region that are safe to zero right before jumping, in ascending order.
+    ///
+    /// The stack is full descending, so everything from `STACK_MARGIN` bytes below
+    /// `stack_pointer` up is in use, and the region is cut short there. The `protected`
+    /// ranges (e.g. the boot info the application reads, or the RAM vector table) are
+    /// carved out of it. Up to three ranges remain, some of which may be empty.
+    pub fn bounds(&self, stack_pointer: usize, protected: [Range<usize>; 2]) -> [Range<usize>; 3] {
+        let end = (self.start + self.length).min(stack_pointer.saturating_sub(Self::STACK_MARGIN));
+        let mut protected = protected;
+        protected.sort_unstable_by_key(|range| range.start);
+        let mut cursor = self.start;
+        let mut bounds = [0..0, 0..0, 0..0];
+        for (bound, range) in bounds.iter_mut().zip(protected.iter()) {
+            *bound = cursor..range.start.min(end).max(cursor);
+            cursor = cursor.max(range.end);
+        }
+        bounds[2] = cursor..end.max(cursor);
+        bounds
+    }
+}
+
+/// Flash region occupied by Loadstone itself. The build pads the bootloader binary to
+/// fill the region, and stores a little-endian CRC32 (IEEE) of the preceding bytes in
+/// its last four bytes.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SelfCheck {
+    /// Start of the bootloader region.
+    pub location: usize,
+    /// Size in bytes of the bootloader region, CRC included.
+    pub length: usize,
+}
+
+impl SelfCheck {
+    /// Size in bytes of the CRC stored at the end of the region.
+    pub const CRC_SIZE: usize = size_of::<u32>();
+
+    /// Whether the CRC stored at the end of a region matches the rest of its contents.
+    pub fn region_is_intact(region: &[u8]) -> bool {
+        if region.len() < Self::CRC_SIZE {
+            return false;
+        }
+        let (contents, crc) = region.split_at(region.len() - Self::CRC_SIZE);
+        let mut crc_bytes = [0u8; Self::CRC_SIZE];
+        crc_bytes.copy_from_slice(crc);
+        crc32::checksum_ieee(contents) == u32::from_le_bytes(crc_bytes)
+    }
+
+    /// Recomputes the CRC of the bootloader region, straight from the MCU's memory map.
+    fn bootloader_is_intact(&self) -> bool {
+        // NOTE(Safety): The region is defined by the configuration to cover the bootloader
+        // binary, which is memory mapped flash and never written while Loadstone runs.
+        let region =
+            unsafe { core::slice::from_raw_parts(self.location as *const u8, self.length) };
+        Self::region_is_intact(region)
+    }
+}
+
 /// Main bootloader struct.
 // Members are public for the `ports` layer to be able to construct them freely and easily.
 pub struct Bootloader<
@@ -38,18 +202,52 @@ pub struct Bootloader<
     SRL: Serial,
     T: time::Now,
     R: image::Reader,
-    RUS: ReadUpdateSignal,
+    RUS: ReadUpdateSignal + WriteUpdateSignal,
+    LED: led::Toggle,
+    PIN: gpio::InputPin,
 > {
     pub(crate) mcu_flash: MCUF,
     pub(crate) external_banks: &'static [image::Bank<<EXTF as flash::ReadWrite>::Address>],
     pub(crate) mcu_banks: &'static [image::Bank<<MCUF as flash::ReadWrite>::Address>],
+    pub(crate) mcu_sectors: &'static [image::SectorRegion],
+    /// Index of the MCU bank serial updates are staged in, before promotion to the boot bank.
+    pub(crate) staging_bank: Option<u8>,
+    /// Banks taking turns with the staging bank, if serial updates rotate between them.
+    pub(crate) staging_rotation: Option<StagingRotation<MCUF::Address>>,
+    /// Index of the MCU bank the application mirrors confirmed images into. It is only
+    /// updated from when the update signal targets it.
+    pub(crate) backup_bank: Option<u8>,
+    /// Let golden banks take part in updates, after every regular bank, instead of only
+    /// serving restores.
+    pub(crate) golden_override: bool,
+    /// Size of the smallest erasable region of the external flash.
+    pub(crate) external_erase_size: usize,
     pub(crate) external_flash: Option<EXTF>,
     pub(crate) serial: Option<SRL>,
     pub(crate) boot_metrics: BootMetrics,
     pub(crate) start_time: Option<T::I>,
     pub(crate) recovery_enabled: bool,
+    pub(crate) recovery_protocol: Protocol,
+    /// Bounds recovery sessions, which otherwise wait for a transfer indefinitely.
+    pub(crate) recovery_timeout: Option<RecoveryTimeout>,
+    /// Last resort when there's no image to boot or restore, and no serial recovery.
+    pub(crate) no_image_fallback: NoImageFallback,
+    pub(crate) boot_delay_ms: u32,
     pub(crate) update_signal: Option<RUS>,
     pub(crate) greeting: &'static str,
+    pub(crate) serial_log_level: serial_log::Level,
+    pub(crate) boot_counter: Option<MCUF::Address>,
+    /// Remembers the last verified boot image, to skip verifying it in full on every boot.
+    pub(crate) verification_cache: Option<VerificationCache<MCUF::Address>>,
+    pub(crate) status_led: Option<StatusLed<LED>>,
+    pub(crate) recovery_pin: Option<RecoveryPin<PIN>>,
+    pub(crate) ram_vector_table: Option<RamVectorTable>,
+    pub(crate) self_check: Option<SelfCheck>,
+    pub(crate) jump_validation: Option<JumpValidation>,
+    pub(crate) ram_clear: Option<RamClear>,
+    /// Read back every chunk of an image copied into flash, failing the copy as soon as
+    /// one doesn't match. Slower, but catches failed writes right away.
+    pub(crate) verify_writes: bool,
     pub(crate) _marker: PhantomData<R>,
 }
 
@@ -59,63 +257,216 @@ impl<
         SRL: Serial,
         T: time::Now,
         R: image::Reader,
-        RUS: ReadUpdateSignal,
-    > Bootloader<EXTF, MCUF, SRL, T, R, RUS>
+        RUS: ReadUpdateSignal + WriteUpdateSignal,
+        LED: led::Toggle,
+        PIN: gpio::InputPin,
+    > Bootloader<EXTF, MCUF, SRL, T, R, RUS, LED, PIN>
 {
     /// Main bootloader routine.
     ///
     /// In case the MCU flash's main bank contains a valid image, an update is attempted.
     /// (Any valid image with a different signature in the top occupied external bank is
-    /// considered "newer" for the purposes of updating). The golden image, if available,
-    /// is *never* considered newer than the current MCU image, as it exists only as a final
-    /// resort fallback.
+    /// considered "newer" for the purposes of updating). Golden images, if available,
+    /// are *never* considered newer than the current MCU image, as they exist only as a final
+    /// resort fallback. That is, unless the golden override is enabled: golden images are
+    /// then considered too, but only if no regular bank holds a valid image.
     ///
-    /// After attempting or skipping the update process, the bootloader attempts to boot
-    /// the current MCU image. In case of failure, the following steps are attempted:
+    /// After attempting or skipping the update process, the bootloader holds for the
+    /// configured boot delay (if any), during which a keypress over serial diverts into
+    /// recovery mode. It then attempts to boot the current MCU image. In case of failure,
+    /// the following steps are attempted:
     ///
     /// * Verify each bank in ascending order. If any is found to contain a valid
     /// image, copy it to bootable MCU flash bank and attempt to boot it.
-    /// * Verify golden image. If valid, copy to bootable MCU flash bank and attempt to boot.
-    /// * If golden image not available or invalid, proceed to recovery mode.
+    /// * Verify each golden image, MCU banks first. If one is valid, copy it to bootable
+    /// MCU flash bank and attempt to boot.
+    /// * If no golden image is available or valid, proceed to recovery mode, or to the
+    /// configured [`NoImageFallback`] if recovery is disabled.
+    ///
+    /// Every step up to the jump is taken by [`Self::decide`], whose report is then acted on.
+    ///
+    /// If a status LED is available, it blinks slowly while scanning banks, quickly
+    /// during recovery mode, and stays solid right before jumping to the image.
+    ///
+    /// If the bootloader self check is enabled and Loadstone finds its own flash region
+    /// corrupted, it never jumps to an image; it goes straight to recovery mode instead.
+    /// The same happens if a recovery pin is configured and held asserted at boot.
     pub fn run(mut self) -> ! {
+        if !self.self_check.map_or(true, |c| c.bootloader_is_intact()) {
+            log!(self, Error, "Bootloader region is corrupted. Only recovery is available.");
+            if self.recovery_enabled {
+                self.recover();
+            } else {
+                panic!("FATAL: Bootloader is corrupted, and serial recovery is not supported.");
+            }
+        }
         self.verify_bank_correctness();
+        if self.external_flash.is_none() && self.external_banks().count() > 0 {
+            log!(self, Warn, "External flash unavailable. Continuing with MCU flash only.");
+        }
+        self.count_boot();
         duprintln!(self.serial, "");
         duprintln!(self.serial, "{}", self.greeting);
-        if let Some(image) = self.latest_bootable_image() {
-            duprintln!(self.serial, "Attempting to boot from default bank.");
-            match self.boot(image).unwrap_err() {
-                Error::BankInvalid => {
-                    info!("Attempted to boot from invalid bank. Restoring image...")
-                }
-                Error::BankEmpty => {
-                    info!("Attempted to boot from empty bank. Restoring image...")
+        if self.recovery_pin_asserted() {
+            if self.recovery_enabled {
+                duprintln!(self.serial, "Recovery pin asserted.");
+                self.recover();
+            }
+            log!(self, Warn, "Recovery pin asserted, but serial recovery is not supported.");
+        }
+        if self.recovery_requested() {
+            self.end_one_shot_request();
+            if self.recovery_enabled {
+                duprintln!(self.serial, "Recovery requested by the application.");
+                self.recover();
+            }
+            log!(self, Warn, "Recovery requested, but serial recovery is not supported.");
+        }
+        self.signal(Pattern::SlowBlink);
+        let report = self.decide();
+        let image = match report.image {
+            Some(image) if report.is_restore() => image,
+            Some(image) => {
+                if self.boot_interrupted() {
+                    duprintln!(self.serial, "Boot interrupted by user.");
+                    self.recover();
                 }
-                Error::SignatureInvalid => {
-                    info!("Signature invalid for stored image. Restoring image...")
+                duprintln!(self.serial, "Attempting to boot from default bank.");
+                match self.boot(image).unwrap_err() {
+                    Error::BankInvalid => {
+                        log!(self, Info, "Attempted to boot from invalid bank. Restoring image...")
+                    }
+                    Error::BankEmpty => {
+                        log!(self, Info, "Attempted to boot from empty bank. Restoring image...")
+                    }
+                    Error::SignatureInvalid => {
+                        log!(self, Info, "Signature invalid for stored image. Restoring image...")
+                    }
+                    _ => log!(self, Info, "Unexpected boot error. Restoring image..."),
+                };
+                match self.restore() {
+                    Ok(image) => image,
+                    Err(e) => self.restore_failed(e),
                 }
-                _ => info!("Unexpected boot error. Restoring image..."),
-            };
+            }
+            None => self.restore_failed(Error::NoImageToRestoreFrom),
+        };
+        // Jump validation may still refuse a verified image.
+        let error = self.boot(image).unwrap_err();
+        log!(self, Error, "Failed to boot from the restored image.");
+        self.restore_failed(error)
+    }
+
+    /// Falls back to serial recovery after failing to restore or boot an image, if supported.
+    fn restore_failed(&mut self, e: Error) -> ! {
+        log!(self, Error, "Failed to restore.");
+        defmt_log!(info, "Restore error: {:?}", e);
+
+        if self.recovery_enabled {
+            self.recover();
+        } else {
+            self.no_image_fallback();
         }
+    }
 
-        match self.restore() {
-            Ok(image) => self.boot(image).expect("FATAL: Failed to boot from verified image!"),
-            Err(e) => {
-                info!("Failed to restore. Error: {:?}", e);
+    /// Records this boot in the persistent boot counter, if there is one. Called once
+    /// at the start of [`Self::run`], so the restore and recovery retries that follow
+    /// a failed boot attempt are not counted again.
+    fn count_boot(&mut self) {
+        if let Some(location) = self.boot_counter {
+            match boot_counter::increment(&mut self.mcu_flash, location) {
+                Ok(count) => self.boot_metrics.boot_count = Some(count),
+                Err(_) => log!(self, Warn, "Failed to update the boot counter."),
+            }
+        }
+    }
 
-                if self.recovery_enabled {
-                    self.recover();
-                } else {
-                    panic!("FATAL: Failed to boot, and serial recovery is not supported.");
-                }
+    /// Verifies the image in the boot bank, trusting the verification cache if enabled.
+    fn boot_image(
+        &mut self,
+        boot_bank: Bank<MCUF::Address>,
+    ) -> Result<Image<MCUF::Address>, Error> {
+        match self.verification_cache {
+            Some(cache) => cache.image_at::<R, _>(&mut self.mcu_flash, boot_bank),
+            None => R::image_at(&mut self.mcu_flash, boot_bank),
+        }
+    }
+
+    /// Whether a recovery pin is configured and currently held at its active level.
+    fn recovery_pin_asserted(&self) -> bool {
+        self.recovery_pin.as_ref().map_or(false, |pin| pin.asserted())
+    }
+
+    /// Whether the application reset the device asking for serial recovery.
+    fn recovery_requested(&self) -> bool {
+        matches!(
+            self.update_signal.as_ref().map(ReadUpdateSignal::read_update_plan),
+            Some(UpdatePlan::Recovery { .. })
+        )
+    }
+
+    /// Puts back the update plan a recovery or test boot request replaced, as both are
+    /// one-shot. Done as soon as the request is read, before entering recovery or jumping
+    /// to the image (neither of which returns), and even if the request can't be served,
+    /// so it doesn't carry over to every boot that follows.
+    fn end_one_shot_request(&mut self) {
+        if let Some(signal) = self.update_signal.as_mut() {
+            let plan = signal.read_update_plan();
+            signal.write_update_plan(plan.resumed());
+        }
+    }
+
+    /// Switches the status LED, if there is one, to a new pattern.
+    fn signal(&mut self, pattern: Pattern) {
+        if let Some(status_led) = self.status_led.as_mut() {
+            status_led.set_pattern(pattern);
+        }
+    }
+
+    /// Advances the status LED pattern, if there is one, by one step.
+    fn tick_status_led(&mut self) {
+        if let Some(status_led) = self.status_led.as_mut() {
+            status_led.tick();
+        }
+    }
+
+    /// Holds for the configured boot delay, polling serial for a keypress. Returns
+    /// true if the user interrupted the boot process (only possible when recovery
+    /// mode is enabled).
+    fn boot_interrupted(&mut self) -> bool {
+        if self.boot_delay_ms == 0 {
+            return false;
+        }
+        duprintln!(
+            self.serial,
+            "Booting in {} ms.{}",
+            self.boot_delay_ms,
+            if self.recovery_enabled { " Press any key to enter recovery mode." } else { "" }
+        );
+        let recovery_enabled = self.recovery_enabled;
+        let serial = &mut self.serial;
+        hold_for_keypress::<T, _>(self.boot_delay_ms, || match serial.as_mut() {
+            Some(serial) if recovery_enabled => {
+                TimeoutRead::read(serial, time::Milliseconds(KEYPRESS_POLL_MS)).is_ok()
             }
+            _ => false,
+        })
+    }
+
+    /// Prints a message over serial, tagged with its severity, if the configured serial
+    /// log level allows it. Compiled out entirely without the `serial-log` feature.
+    #[allow(unused_variables)]
+    fn serial_log(&mut self, level: serial_log::Level, message: &str) {
+        #[cfg(feature = "serial-log")]
+        if self.serial_log_level.allows(level) {
+            duprintln!(self.serial, "{} {}", level.tag(), message);
         }
     }
+
     /// Makes several sanity checks on the flash bank configuration.
     pub fn verify_bank_correctness(&self) {
-        // There is at most one golden bank between internal and external flash
-        let total_golden = self.external_banks.iter().filter(|b| b.is_golden).count()
-            + self.mcu_banks.iter().filter(|b| b.is_golden).count();
-        assert!(total_golden <= 1);
+        // The bootable bank can't double as a golden bank
+        assert!(!self.mcu_banks().any(|b| b.bootable && b.is_golden));
 
         // There is only one bootable MCU bank
         assert_eq!(self.mcu_banks().filter(|b| b.bootable).count(), 1);
@@ -128,20 +479,92 @@ impl<
             current
         });
 
-        // Either there's external flash, or there's no external flash and no banks.
+        // MCU banks start at a sector boundary, so erasing one can't clobber its neighbours
+        assert!(
+            self.mcu_banks()
+                .all(|b| self.mcu_sectors.iter().any(|s| s.is_sector_start(b.location.into()))),
+            "MCU flash banks are not aligned to sectors!"
+        );
+
+        // The staging bank, if any, is a regular MCU bank
+        assert!(
+            self.staging_bank.map_or(true, |index| self
+                .mcu_banks()
+                .any(|b| b.index == index && !b.bootable && !b.is_golden)),
+            "The staging bank must be an MCU bank that is neither bootable nor golden!"
+        );
+
+        // So are the banks taking turns with it
+        assert!(
+            self.staging_rotation.map_or(true, |rotation| rotation.banks.iter().all(|index| self
+                .mcu_banks()
+                .any(|b| b.index == *index && !b.bootable && !b.is_golden))),
+            "Staging rotation banks must be MCU banks that are neither bootable nor golden!"
+        );
+
+        // The backup bank, if any, is a regular MCU bank too
+        assert!(
+            self.backup_bank.map_or(true, |index| self
+                .mcu_banks()
+                .any(|b| b.index == index && !b.bootable && !b.is_golden)),
+            "The backup bank must be an MCU bank that is neither bootable nor golden!"
+        );
+
+        // External banks span whole erasable regions, so erasing one can't clobber the next
         assert!(
-            self.external_flash.is_some()
-                || (self.external_flash.is_none() && self.external_banks().count() == 0),
-            "Incorrect external flash configuration"
+            self.external_banks().all(|b| b.size % self.external_erase_size == 0),
+            "External flash bank sizes are not a multiple of the erase size!"
         );
     }
 
     /// Boots into a given memory bank.
+    ///
+    /// Images read from a non-bootable bank are refused with `Error::BankInvalid`. If jump
+    /// validation is enabled, the image's initial stack pointer and reset handler are checked
+    /// next, and `Error::BankInvalid` is returned if they're implausible. If a [`RamClear`]
+    /// region is configured, it's zeroed right before the jump, sparing the boot info.
     pub fn boot(&mut self, image: Image<MCUF::Address>) -> Result<!, Error> {
-        warn!("Jumping to a new firmware image. This will break `defmt`.");
+        if !image.bootable() {
+            log!(self, Error, "Refusing to boot an image from a non-bootable bank.");
+            return Err(Error::BankInvalid);
+        }
         let image_location_raw: usize = image.location().into();
+        let image_size = image.size();
+        if let Some(validation) = self.jump_validation {
+            // NOTE(Safety): Reads the first two words of an image in memory mapped MCU flash.
+            let (stack_pointer, reset_handler) = unsafe {
+                (
+                    *(image_location_raw as *const u32),
+                    *((image_location_raw + size_of::<u32>()) as *const u32),
+                )
+            };
+            if !validation.is_plausible(
+                stack_pointer,
+                reset_handler,
+                image_location_raw,
+                image_size,
+            ) {
+                log!(self, Error, "Image has an implausible stack pointer or reset handler.");
+                return Err(Error::BankInvalid);
+            }
+        }
+
+        log!(self, Warn, "Jumping to a new firmware image. This will break `defmt`.");
+        self.signal(Pattern::Solid);
         let time_ms = self.start_time.and_then(|t| Some((T::now() - t).0));
         self.boot_metrics.boot_time_ms = time_ms;
+        self.hand_over(image_location_raw, image_size)
+    }
+
+    /// Hands control over to the image at `image_location_raw`, past any check. Nothing can
+    /// be jumped to on the host, so test builds unwind with `doubles::Exit::Jump` instead.
+    #[cfg_attr(test, allow(unreachable_code))]
+    fn hand_over(&mut self, image_location_raw: usize, image_size: usize) -> ! {
+        // Releases anything the serial holds on to, such as its reception interrupt.
+        drop(self.serial.take());
+
+        #[cfg(test)]
+        std::panic::panic_any(doubles::Exit::Jump(image_location_raw));
 
         // NOTE(Safety): Thoroughly unsafe operations, for obvious reasons: We are jumping to an
         // entirely different firmware image! We have to assume everything is at the right place,
@@ -151,8 +574,29 @@ impl<
             let reset_handler_pointer =
                 *((image_location_raw + size_of::<u32>()) as *const u32) as *const ();
             let reset_handler = core::mem::transmute::<*const (), fn() -> !>(reset_handler_pointer);
-            (*SCB::ptr()).vtor.write(image_location_raw as u32);
-            *boot_metrics_mut() = self.boot_metrics.clone();
+            let vector_table_location = match self.ram_vector_table {
+                Some(table) => {
+                    core::ptr::copy_nonoverlapping(
+                        image_location_raw as *const u8,
+                        table.address as *mut u8,
+                        table.copy_length(image_size),
+                    );
+                    table.address
+                }
+                None => image_location_raw,
+            };
+            (*SCB::ptr()).vtor.write(vector_table_location as u32);
+            *boot_info_mut() = BootInfo::from(&self.boot_metrics);
+            if let Some(clear) = self.ram_clear {
+                let vector_table = match self.ram_vector_table {
+                    Some(table) => table.address..table.address + table.length,
+                    None => 0..0,
+                };
+                let stack_pointer = cortex_m::register::msp::read() as usize;
+                for range in clear.bounds(stack_pointer, [boot_info_range(), vector_table]).iter() {
+                    core::ptr::write_bytes(range.start as *mut u8, 0, range.len());
+                }
+            }
             #[allow(deprecated)]
             cortex_m::register::msp::write(initial_stack_pointer);
             reset_handler()
@@ -174,86 +618,1550 @@ impl<
     }
 }
 
+/// Scans a bank for a valid image, printing a dot over serial for every
+/// [`image::SCAN_PROGRESS_INTERVAL`] bytes scanned so long scans don't look like a hang.
+pub fn scan_bank<R: image::Reader, F: Flash, SRL: Serial>(
+    serial: &mut Option<SRL>,
+    flash: &mut F,
+    bank: Bank<F::Address>,
+) -> Result<Image<F::Address>, Error> {
+    let mut progress_reported = false;
+    let result = R::image_at_with_progress(flash, bank, |_| {
+        if let Some(serial) = serial.as_mut() {
+            uprint!(serial, ".");
+            progress_reported = true;
+        }
+    });
+    if progress_reported {
+        duprintln!(serial, "");
+    }
+    result
+}
+
+/// Granularity of the serial polling during the boot delay.
+const KEYPRESS_POLL_MS: u32 = 10;
+
+/// Waits for `delay_ms` milliseconds as measured by the `T` time source, returning
+/// early with `true` if `keypress` reports input at any point during the wait.
+fn hold_for_keypress<T: time::Now, F: FnMut() -> bool>(delay_ms: u32, mut keypress: F) -> bool {
+    let start = T::now();
+    while (T::now() - start).0 < delay_ms {
+        if keypress() {
+            return true;
+        }
+    }
+    false
+}
+
+/// Takes the outcome of initializing the external flash chip. If initialization failed
+/// (e.g. the chip is absent, or reports the wrong manufacturer ID) the error is logged
+/// and no external flash is returned, so Loadstone carries on with MCU flash only rather
+/// than panicking. Images in external banks are then out of reach until the next reset.
+pub fn external_flash_or_fallback<F>(initialization: Result<Option<F>, Error>) -> Option<F> {
+    initialization
+        .map_err(|e| defmt_log!(warn, "External flash failed to initialize: {:?}", e))
+        .ok()
+        .flatten()
+}
+
+/// Resets the device. The host can't be reset, so test builds unwind with
+/// `doubles::Exit::Reboot` instead.
+#[cfg_attr(test, allow(unreachable_code))]
+fn reset() -> ! {
+    #[cfg(test)]
+    std::panic::panic_any(doubles::Exit::Reboot);
+
+    SCB::sys_reset()
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(not(feature = "ecdsa-verify"))]
+    use super::doubles::{
+        run_to_exit, CrcBootloaderDouble, Exit, FakeUpdateSignal, FaultyBootloaderDouble,
+        FaultyFlash,
+    };
+    use super::{doubles::BootloaderDouble, *};
+    use crate::devices::image::SectorRegion;
+    #[cfg(not(feature = "ecdsa-verify"))]
+    use crate::devices::image::{
+        compression::tests::compress,
+        image_crc::tests::{golden_test_image, regular_test_image, TEST_IMAGE_WITH_CORRECT_CRC},
+        CrcImageReader,
+    };
+    #[cfg(not(feature = "ecdsa-verify"))]
+    use crate::devices::{recovery_pin::ActiveLevel, status_led::doubles::FakeLed};
+    #[cfg(not(feature = "ecdsa-verify"))]
+    use blue_hal::hal::doubles::serial::SerialStub;
+    use blue_hal::hal::{
+        doubles::{
+            flash::{Address, FakeFlash},
+            time::MockSysTick,
+        },
+        flash::ReadWrite,
+    };
+    #[cfg(not(feature = "ecdsa-verify"))]
+    use crc::crc32;
+    use std::{convert::TryInto, iter};
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn recovered_image_is_stored_and_verified() {
+        let mut flash = FakeFlash::new(Address(0));
+        let bank =
+            Bank { index: 1, size: 512, location: Address(0), bootable: false, is_golden: false };
+        let block: [u8; 49] = TEST_IMAGE_WITH_CORRECT_CRC.try_into().unwrap();
+
+        let image = store_recovered_image::<CrcImageReader<{ crc32::IEEE }, false>, _, _, 49>(
+            &mut flash,
+            bank,
+            iter::once(block),
+            false,
+        )
+        .unwrap();
+        assert_eq!(image.size(), 12usize);
+        assert_eq!(image.location(), bank.location);
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn recovering_non_golden_image_as_golden_fails() {
+        let mut flash = FakeFlash::new(Address(0));
+        let bank =
+            Bank { index: 1, size: 512, location: Address(0), bootable: false, is_golden: true };
+        let block: [u8; 49] = TEST_IMAGE_WITH_CORRECT_CRC.try_into().unwrap();
+
+        assert_eq!(
+            Err(Error::ImageIsNotGolden),
+            store_recovered_image::<CrcImageReader<{ crc32::IEEE }, false>, _, _, 49>(
+                &mut flash,
+                bank,
+                iter::once(block),
+                true
+            )
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn copying_image_into_too_small_bank_fails_cleanly() {
+        let mut flash = FakeFlash::new(Address(0));
+        let input_bank =
+            Bank { index: 1, size: 512, location: Address(0), bootable: false, is_golden: false };
+        let output_bank =
+            Bank { index: 2, size: 16, location: Address(512), bootable: false, is_golden: false };
+        flash.write(Address(0), &TEST_IMAGE_WITH_CORRECT_CRC).unwrap();
+        flash.write(Address(512), &[0x55u8; 16]).unwrap();
+
+        assert_eq!(
+            Err(Error::ImageTooLargeForBank),
+            copy_image_single_flash::<CrcImageReader<{ crc32::IEEE }, false>, _, SerialStub>(
+                &mut None,
+                &mut flash,
+                input_bank,
+                output_bank,
+                false,
+                false
+            )
+        );
+        let mut output = [0u8; 16];
+        flash.read(Address(512), &mut output).unwrap();
+        assert_eq!(output, [0x55u8; 16]);
+    }
+
+    #[test]
+    fn writing_blocks_past_bank_end_fails_without_overrun() {
+        let mut flash = FakeFlash::new(Address(0));
+        let bank =
+            Bank { index: 1, size: 32, location: Address(0), bootable: false, is_golden: false };
+        flash.write(Address(32), &[0x55u8; 16]).unwrap();
+        let blocks = iter::repeat([0xAAu8; 16]).take(3);
+
+        assert_eq!(
+            Err(Error::ImageTooLargeForBank),
+            write_blocks_within_bank(&mut flash, bank, blocks)
+        );
+        let mut overrun = [0u8; 16];
+        flash.read(Address(32), &mut overrun).unwrap();
+        assert_eq!(overrun, [0x55u8; 16]);
+    }
+
+    #[test]
+    fn writing_blocks_that_fit_in_bank_succeeds() {
+        let mut flash = FakeFlash::new(Address(0));
+        let bank =
+            Bank { index: 1, size: 32, location: Address(0), bootable: false, is_golden: false };
+        let blocks = iter::repeat([0xAAu8; 16]).take(2);
+
+        assert_eq!(Ok(()), write_blocks_within_bank(&mut flash, bank, blocks));
+    }
+
+    #[test]
+    fn bytes_written_across_a_sector_boundary_read_back_intact() {
+        let mut flash = FakeFlash::new(Address(0));
+        let bank = Bank {
+            index: 2,
+            size: 0x2000,
+            location: Address(0),
+            bootable: false,
+            is_golden: false,
+        };
+        flash.write(Address(0), &[0x55u8; 0x2000]).unwrap();
+
+        assert_eq!(Ok(()), write_within_bank(&mut flash, bank, 0x0FFF, &[0xDE, 0xAD]));
+        let mut around = [0u8; 4];
+        flash.read(Address(0x0FFE), &mut around).unwrap();
+        assert_eq!(around, [0x55, 0xDE, 0xAD, 0x55]);
+    }
+
+    #[test]
+    fn writes_extending_past_the_bank_end_are_refused() {
+        let mut flash = FakeFlash::new(Address(0));
+        let bank =
+            Bank { index: 2, size: 32, location: Address(0), bootable: false, is_golden: false };
+        flash.write(Address(0), &[0x55u8; 48]).unwrap();
+
+        assert!(write_within_bank(&mut flash, bank, 31, &[0xAA, 0xAA]).is_err());
+        assert!(write_within_bank(&mut flash, bank, usize::MAX, &[0xAA]).is_err());
+        let mut contents = [0u8; 48];
+        flash.read(Address(0), &mut contents).unwrap();
+        assert_eq!(contents, [0x55u8; 48]);
+        assert_eq!(Ok(()), write_within_bank(&mut flash, bank, 31, &[0xAA]));
+    }
+
+    #[test]
+    fn writes_that_fail_to_read_back_are_reported() {
+        let mut flash = FakeFlash::new(Address(0));
+        let written = [0xA5u8; 600];
+        assert_eq!(Ok(()), write_and_verify(&mut flash, Address(0), &written, true));
+
+        // A cell that failed to program, past the first chunk read back.
+        flash.write(Address(300), &[0x00]).unwrap();
+        assert_eq!(
+            Err(Error::WriteVerificationFailed),
+            verify_written(&mut flash, Address(0), &written)
+        );
+    }
+
+    #[rustfmt::skip]
+    static TEST_SECTORS: [SectorRegion; 2] = [
+        SectorRegion { start: 0x0000, sector_size: 0x1000, sector_count: 4 },
+        SectorRegion { start: 0x4000, sector_size: 0x4000, sector_count: 2 },
+    ];
+
+    #[rustfmt::skip]
+    static ALIGNED_BANKS: [Bank<Address>; 2] = [
+        Bank { index: 1, size: 0x3000, location: Address(0x1000), bootable: true, is_golden: false },
+        Bank { index: 2, size: 0x4000, location: Address(0x8000), bootable: false, is_golden: false },
+    ];
+
+    #[rustfmt::skip]
+    static MISALIGNED_BANKS: [Bank<Address>; 2] = [
+        Bank { index: 1, size: 0x3000, location: Address(0x1000), bootable: true, is_golden: false },
+        Bank { index: 2, size: 0x4000, location: Address(0x6000), bootable: false, is_golden: false },
+    ];
+
+    #[test]
+    fn sector_aligned_mcu_banks_pass_verification() {
+        BootloaderDouble::new()
+            .with_mcu_banks(&ALIGNED_BANKS)
+            .with_mcu_sectors(&TEST_SECTORS)
+            .verify_bank_correctness();
+    }
+
+    #[test]
+    #[should_panic(expected = "MCU flash banks are not aligned to sectors!")]
+    fn misaligned_mcu_bank_is_flagged() {
+        BootloaderDouble::new()
+            .with_mcu_banks(&MISALIGNED_BANKS)
+            .with_mcu_sectors(&TEST_SECTORS)
+            .verify_bank_correctness();
+    }
+
+    #[test]
+    #[should_panic(expected = "External flash bank sizes are not a multiple of the erase size!")]
+    fn external_bank_not_spanning_whole_erase_regions_is_flagged() {
+        BootloaderDouble::new()
+            .with_mcu_banks(&ALIGNED_BANKS)
+            .with_mcu_sectors(&TEST_SECTORS)
+            .with_external_banks(&EXTERNAL_BANKS_WITH_GOLDEN)
+            .with_external_erase_size(0x1000)
+            .verify_bank_correctness();
+    }
+
+    #[rustfmt::skip]
+    static MCU_BANKS_WITH_GOLDEN: [Bank<Address>; 2] = [
+        Bank { index: 1, size: 0x200, location: Address(0x000), bootable: true, is_golden: false },
+        Bank { index: 2, size: 0x200, location: Address(0x200), bootable: false, is_golden: true },
+    ];
+
+    #[rustfmt::skip]
+    static EXTERNAL_BANKS_WITH_GOLDEN: [Bank<Address>; 1] = [
+        Bank { index: 3, size: 0x200, location: Address(0x000), bootable: false, is_golden: true },
+    ];
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn booting_an_image_from_a_non_bootable_bank_fails() {
+        let mut bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_GOLDEN)
+            .with_image_at(2, &golden_test_image(b"mcu"));
+        let golden_bank = MCU_BANKS_WITH_GOLDEN[1];
+        assert!(!golden_bank.bootable);
+        let image = scan_bank::<CrcImageReader<{ crc32::IEEE }, false>, _, SerialStub>(
+            &mut None,
+            &mut bootloader.mcu_flash,
+            golden_bank,
+        )
+        .unwrap();
+
+        assert!(!image.bootable());
+        assert!(matches!(bootloader.boot(image), Err(Error::BankInvalid)));
+    }
+
+    #[test]
+    fn multiple_golden_banks_pass_verification() {
+        BootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_GOLDEN)
+            .with_external_banks(&EXTERNAL_BANKS_WITH_GOLDEN)
+            .verify_bank_correctness();
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn restore_prefers_mcu_golden_bank() {
+        let mut bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_GOLDEN)
+            .with_external_banks(&EXTERNAL_BANKS_WITH_GOLDEN)
+            .with_image_at(2, &golden_test_image(b"mcu"))
+            .with_image_at(3, &golden_test_image(b"ext"));
+        let image = bootloader.restore().unwrap();
+        assert!(image.is_golden());
+        assert!(matches!(bootloader.boot_metrics.boot_path, BootPath::Restored { bank: 2 }));
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn restore_falls_back_to_next_golden_bank() {
+        let mut corrupted = golden_test_image(b"mcu");
+        corrupted[0] ^= 0xFF;
+        let mut bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_GOLDEN)
+            .with_external_banks(&EXTERNAL_BANKS_WITH_GOLDEN)
+            .with_image_at(2, &corrupted)
+            .with_image_at(3, &golden_test_image(b"ext"));
+        let image = bootloader.restore().unwrap();
+        assert!(image.is_golden());
+        assert!(matches!(bootloader.boot_metrics.boot_path, BootPath::Restored { bank: 3 }));
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn failed_external_flash_initialization_falls_back_to_mcu_flash() {
+        let mut bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_GOLDEN)
+            .with_external_banks(&EXTERNAL_BANKS_WITH_GOLDEN)
+            .with_image_at(2, &golden_test_image(b"mcu"))
+            .with_image_at(3, &golden_test_image(b"ext"));
+        let wrong_chip = Err(Error::DriverError("[External Flash] Wrong manufacturer ID"));
+        bootloader.external_flash = external_flash_or_fallback(wrong_chip);
+        assert!(bootloader.external_flash.is_none());
+
+        bootloader.verify_bank_correctness();
+        let image = bootloader.restore().unwrap();
+        assert!(image.is_golden());
+        assert!(matches!(bootloader.boot_metrics.boot_path, BootPath::Restored { bank: 2 }));
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn failed_external_flash_initialization_leaves_external_golden_out_of_reach() {
+        let mut corrupted = golden_test_image(b"mcu");
+        corrupted[0] ^= 0xFF;
+        let mut bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_GOLDEN)
+            .with_external_banks(&EXTERNAL_BANKS_WITH_GOLDEN)
+            .with_image_at(2, &corrupted)
+            .with_image_at(3, &golden_test_image(b"ext"));
+        bootloader.external_flash = external_flash_or_fallback(Err(Error::DriverError("No chip")));
+        assert_eq!(Err(Error::NoImageToRestoreFrom), bootloader.restore().map(|_| ()));
+    }
+
+    #[rustfmt::skip]
+    static MCU_BANKS_WITH_OVERSIZED_IMAGE: [Bank<Address>; 3] = [
+        Bank { index: 1, size: 0x100, location: Address(0x000), bootable: true, is_golden: false },
+        Bank { index: 2, size: 0x400, location: Address(0x100), bootable: false, is_golden: false },
+        Bank { index: 3, size: 0x100, location: Address(0x500), bootable: false, is_golden: false },
+    ];
+
+    /// Image too large to copy into the boot bank of [`MCU_BANKS_WITH_OVERSIZED_IMAGE`].
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn oversized_image() -> Vec<u8> { regular_test_image(&[0xAB; 0x200]) }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn update_moves_on_when_a_bank_fails_to_copy() {
+        // Bank 2 verifies, but its first bytes can't be read in blocks to copy them.
+        let mut bootloader = FaultyBootloaderDouble::over_mcu_flash(FaultyFlash::new(0x200..0x210))
+            .with_mcu_banks(&MCU_BANKS_WITH_ROTATION)
+            .with_image_at(1, &regular_test_image(b"old"))
+            .with_image_at(2, &regular_test_image(b"newest"))
+            .with_image_at(3, &regular_test_image(b"new"));
+        assert!(bootloader.latest_bootable_image().is_some());
+        assert!(matches!(bootloader.boot_metrics.boot_path, BootPath::Updated { bank: 3 }));
+
+        let expected = regular_test_image(b"new");
+        let mut boot_bank = vec![0u8; expected.len()];
+        bootloader.mcu_flash.read(Address(0x000), &mut boot_bank).unwrap();
+        assert_eq!(boot_bank, expected);
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn restore_moves_on_when_a_bank_fails_to_copy() {
+        let mut bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_OVERSIZED_IMAGE)
+            .with_image_at(2, &oversized_image())
+            .with_image_at(3, &regular_test_image(b"new"));
+        let image = bootloader.restore().unwrap();
+        assert!(!image.is_golden());
+        assert!(matches!(bootloader.boot_metrics.boot_path, BootPath::Restored { bank: 3 }));
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn test_boot_only_proceeds_with_a_verified_image() {
+        use crate::devices::update_signal::UpdatePlan;
+        let test_boot = UpdatePlan::Any.test_boot(3);
+        let mut corrupted = regular_test_image(b"new");
+        corrupted[0] ^= 0xFF;
+
+        let mut bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_OVERSIZED_IMAGE)
+            .with_image_at(1, &regular_test_image(b"old"))
+            .with_image_at(2, &oversized_image())
+            .with_image_at(3, &corrupted)
+            .with_update_plan(test_boot);
+        let image = bootloader.latest_bootable_image().unwrap();
+        assert_eq!(image.location(), Address(0x000));
+        assert!(matches!(bootloader.boot_metrics.boot_path, BootPath::Direct));
+        let mut boot_bank = [0u8; 3];
+        bootloader.mcu_flash.read(Address(0x000), &mut boot_bank).unwrap();
+        assert_eq!(&boot_bank, b"old");
+
+        let signal = FakeUpdateSignal::new(test_boot);
+        let mut bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_OVERSIZED_IMAGE)
+            .with_image_at(1, &regular_test_image(b"old"))
+            .with_image_at(2, &oversized_image())
+            .with_image_at(3, &regular_test_image(b"new"))
+            .with_update_signal(signal.clone());
+        assert!(bootloader.latest_bootable_image().unwrap().bootable());
+        assert!(matches!(bootloader.boot_metrics.boot_path, BootPath::Updated { bank: 3 }));
+
+        // The test boot is one-shot, so later boots follow the previous plan again.
+        assert_eq!(UpdatePlan::Any, signal.read_update_plan());
+    }
+
+    #[rustfmt::skip]
+    static MCU_BANKS_WITH_STAGING: [Bank<Address>; 3] = [
+        Bank { index: 1, size: 0x200, location: Address(0x000), bootable: true, is_golden: false },
+        Bank { index: 2, size: 0x200, location: Address(0x200), bootable: false, is_golden: false },
+        Bank { index: 3, size: 0x200, location: Address(0x400), bootable: false, is_golden: true },
+    ];
+
+    #[test]
+    #[should_panic(
+        expected = "The staging bank must be an MCU bank that is neither bootable nor golden!"
+    )]
+    fn golden_staging_bank_is_flagged() {
+        BootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_STAGING)
+            .with_staging_bank(3)
+            .verify_bank_correctness();
+    }
+
+    /// Marks whatever the staging bank of [`MCU_BANKS_WITH_STAGING`] holds for promotion.
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn mark_for_promotion(bootloader: &mut CrcBootloaderDouble) {
+        let marker_location = Address(0x400 - PROMOTION_MARKER.len() as u32);
+        bootloader.mcu_flash.write(marker_location, &PROMOTION_MARKER).unwrap();
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn power_loss_at_any_promotion_stage_leaves_a_bootable_image() {
+        let (old, new) = (regular_test_image(b"old"), regular_test_image(b"new"));
+        let half = new.len() / 2;
+        let torn_copy = [&new[..half], &old[half..]].concat();
+        let torn_erase = [&[0xFFu8; 4][..], &new[4..]].concat();
+
+        // Contents of the boot and staging banks when power is lost, whether the staged
+        // image is marked for promotion, and the image expected in the boot bank on the
+        // next boot.
+        #[rustfmt::skip]
+        let stages: [(&[u8], &[u8], bool, &[u8]); 6] = [
+            // While receiving the update into the staging bank.
+            (&old, &new[..half], false, &old),
+            // After verifying the staged image, before marking it.
+            (&old, &new, false, &old),
+            // After marking the staged image, before copying it.
+            (&old, &new, true, &new),
+            // While copying the staged image into the boot bank.
+            (&torn_copy, &new, true, &new),
+            // After copying, before clearing the staging bank.
+            (&new, &new, true, &new),
+            // While clearing the staging bank.
+            (&new, &torn_erase, true, &new),
+        ];
+
+        for (boot_contents, staging_contents, marked, expected) in stages.iter() {
+            let mut bootloader = CrcBootloaderDouble::new()
+                .with_mcu_banks(&MCU_BANKS_WITH_STAGING)
+                .with_staging_bank(2)
+                .with_image_at(1, boot_contents)
+                .with_image_at(2, staging_contents);
+            if *marked {
+                mark_for_promotion(&mut bootloader);
+            }
+            bootloader.verify_bank_correctness();
+            assert!(bootloader.latest_bootable_image().is_some());
+            let mut boot_bank = vec![0u8; expected.len()];
+            bootloader.mcu_flash.read(Address(0x000), &mut boot_bank).unwrap();
+            assert_eq!(&boot_bank[..], *expected);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn promotion_clears_the_staging_bank() {
+        let (old, new) = (regular_test_image(b"old"), regular_test_image(b"new"));
+        let mut bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_STAGING)
+            .with_staging_bank(2)
+            .with_image_at(1, &old)
+            .with_image_at(2, &new);
+        mark_for_promotion(&mut bootloader);
+
+        assert!(bootloader.latest_bootable_image().is_some());
+        assert!(matches!(bootloader.boot_metrics.boot_path, BootPath::Updated { bank: 2 }));
+        let mut staging_bank = [0u8; 0x200];
+        bootloader.mcu_flash.read(Address(0x200), &mut staging_bank).unwrap();
+        assert!(staging_bank.iter().all(|b| *b == 0xFF));
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn images_not_staged_by_a_serial_update_are_never_updated_from() {
+        use crate::devices::update_signal::UpdatePlan;
+        let (old, new) = (regular_test_image(b"old"), regular_test_image(b"new"));
+
+        // Neither promoted, nor picked up by the update scan, whatever the plan.
+        for plan in [UpdatePlan::None, UpdatePlan::Any, UpdatePlan::Index(2)].iter() {
+            let mut bootloader = CrcBootloaderDouble::new()
+                .with_mcu_banks(&MCU_BANKS_WITH_STAGING)
+                .with_staging_bank(2)
+                .with_image_at(1, &old)
+                .with_image_at(2, &new)
+                .with_update_plan(*plan);
+            assert_eq!(BootReason::UpToDate, bootloader.decide().reason);
+            let mut boot_bank = vec![0u8; old.len()];
+            bootloader.mcu_flash.read(Address(0x000), &mut boot_bank).unwrap();
+            assert_eq!(old, boot_bank);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn serial_updates_are_staged_promoted_and_served_once() {
+        use crate::devices::file_transfer::{XModemSession, BLOCK_SIZE};
+        use blue_hal::utilities::xmodem;
+        let (old, new) = (regular_test_image(b"old"), regular_test_image(b"new"));
+        let mut block = [0xFFu8; BLOCK_SIZE];
+        block[..new.len()].copy_from_slice(&new);
+        let mut transfer = XModemSession::new().packet(&block).to_vec();
+        transfer.push(xmodem::EOT);
+
+        let signal = FakeUpdateSignal::new(UpdatePlan::Serial);
+        let mut bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_STAGING)
+            .with_staging_bank(2)
+            .with_image_at(1, &old)
+            .with_update_signal(signal.clone())
+            .with_serial_input(&transfer);
+        assert!(bootloader.latest_bootable_image().unwrap().bootable());
+        assert!(matches!(bootloader.boot_metrics.boot_path, BootPath::Updated { bank: 2 }));
+        let mut boot_bank = vec![0u8; new.len()];
+        bootloader.mcu_flash.read(Address(0x000), &mut boot_bank).unwrap();
+        assert_eq!(new, boot_bank);
+
+        // The request is dropped once served, so later boots don't wait for an image.
+        assert_eq!(UpdatePlan::Any, signal.read_update_plan());
+    }
+
+    #[rustfmt::skip]
+    static MCU_BANKS_WITH_ROTATION: [Bank<Address>; 3] = [
+        Bank { index: 1, size: 0x200, location: Address(0x000), bootable: true, is_golden: false },
+        Bank { index: 2, size: 0x200, location: Address(0x200), bootable: false, is_golden: false },
+        Bank { index: 3, size: 0x200, location: Address(0x400), bootable: false, is_golden: false },
+    ];
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn serial_updates_rotate_between_staging_banks_across_resets() {
+        let mut flash = FakeFlash::new(Address(0));
+        flash.write(Address(0x000), &[0xFFu8; 0x600 + STAGING_ROTATION_REGION_SIZE]).unwrap();
+        flash.write(Address(0x000), &regular_test_image(b"v0")).unwrap();
+
+        let booted_over = |flash: FakeFlash| {
+            // Every boot runs on a freshly started bootloader, over the same flash.
+            let mut bootloader = CrcBootloaderDouble::new()
+                .with_mcu_banks(&MCU_BANKS_WITH_ROTATION)
+                .with_staging_rotation(&[2, 3], Address(0x600));
+            bootloader.mcu_flash = flash;
+            bootloader.verify_bank_correctness();
+            bootloader
+        };
+
+        for (version, expected_bank) in [(1u8, 2u8), (2, 3), (3, 2), (4, 3)].iter() {
+            // A serial update that never receives an image keeps the turn where it was.
+            let mut bootloader = booted_over(flash).with_update_plan(UpdatePlan::Serial);
+            assert!(bootloader.latest_bootable_image().is_some());
+            let mut bootloader = booted_over(bootloader.mcu_flash);
+
+            // A reset right after staging, before the rotation is recorded, still
+            // promotes the staged image.
+            let staging_bank = bootloader.next_staging_bank().unwrap();
+            assert_eq!(*expected_bank, staging_bank.index);
+            let image = regular_test_image(&[b'v', b'0' + version]);
+            let mut block = [0xFFu8; 64];
+            block[..image.len()].copy_from_slice(&image);
+            update::stage_update::<CrcImageReader<{ crc32::IEEE }, false>, _, _, 64>(
+                &mut bootloader.mcu_flash,
+                staging_bank,
+                iter::once(block),
+            )
+            .unwrap();
+
+            assert!(bootloader.latest_bootable_image().is_some());
+            let boot_path = bootloader.boot_metrics.boot_path;
+            assert!(matches!(boot_path, BootPath::Updated { bank } if bank == *expected_bank));
+            let mut boot_bank = vec![0u8; image.len()];
+            bootloader.mcu_flash.read(Address(0x000), &mut boot_bank).unwrap();
+            assert_eq!(boot_bank, image);
+            flash = bootloader.mcu_flash;
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn boot_images_are_cached_once_verified() {
+        let mut bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_ROTATION)
+            .with_image_at(1, &regular_test_image(b"cached"))
+            .with_verification_cache(Address(0x600));
+        let cache = bootloader.verification_cache.unwrap();
+        let boot_bank = bootloader.boot_bank();
+        let erased = [0xFFu8; image::verification_cache::REGION_SIZE];
+        bootloader.mcu_flash.write(Address(0x600), &erased).unwrap();
+        assert_eq!(Ok(None), cache.cached_image(&mut bootloader.mcu_flash, boot_bank));
+
+        let booted = bootloader.latest_bootable_image().unwrap();
+        assert_eq!(Ok(Some(booted)), cache.cached_image(&mut bootloader.mcu_flash, boot_bank));
+        assert_eq!(Some(booted), bootloader.latest_bootable_image());
+    }
+
+    #[rustfmt::skip]
+    static MCU_BANKS_WITH_SMALL_GOLDEN: [Bank<Address>; 2] = [
+        Bank { index: 1, size: 0x400, location: Address(0x000), bootable: true, is_golden: false },
+        Bank { index: 2, size: 0x100, location: Address(0x400), bootable: false, is_golden: true },
+    ];
+
+    #[rustfmt::skip]
+    static EXTERNAL_BANKS_WITH_SMALL_GOLDEN: [Bank<Address>; 1] = [
+        Bank { index: 3, size: 0x100, location: Address(0x000), bootable: false, is_golden: true },
+    ];
+
+    #[rustfmt::skip]
+    static MCU_BANKS_FOR_REPORTS: [Bank<Address>; 3] = [
+        Bank { index: 1, size: 0x200, location: Address(0x000), bootable: true, is_golden: false },
+        Bank { index: 2, size: 0x200, location: Address(0x200), bootable: false, is_golden: false },
+        Bank { index: 3, size: 0x200, location: Address(0x400), bootable: false, is_golden: true },
+    ];
+
+    /// Decision taken with the given contents in the boot, regular and golden banks.
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn report_for(boot: &[u8], regular: &[u8], golden: &[u8]) -> BootReport<Address> {
+        CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_FOR_REPORTS)
+            .with_image_at(1, boot)
+            .with_image_at(2, regular)
+            .with_image_at(3, golden)
+            .decide()
+    }
+
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn corrupted(mut image: Vec<u8>) -> Vec<u8> {
+        image[0] ^= 0xFF;
+        image
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn valid_current_image_is_booted_directly() {
+        let current = regular_test_image(b"current");
+        for regular in [vec![], corrupted(regular_test_image(b"new")), current.clone()].iter() {
+            for golden in [vec![], golden_test_image(b"golden")].iter() {
+                let report = report_for(&current, regular, golden);
+                assert_eq!(BootReason::UpToDate, report.reason);
+                assert_eq!(Some(1), report.chosen_bank);
+                assert_eq!(Some(BootPath::Direct), report.path);
+                assert_eq!(0, report.fallbacks_tried);
+                assert!(!report.image.unwrap().is_golden());
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn newer_image_is_reported_as_an_update() {
+        let current = regular_test_image(b"current");
+        for golden in [vec![], golden_test_image(b"golden")].iter() {
+            let report = report_for(&current, &regular_test_image(b"new"), golden);
+            assert_eq!(BootReason::UpdateFound, report.reason);
+            assert_eq!(Some(2), report.chosen_bank);
+            assert_eq!(Some(BootPath::Updated { bank: 2 }), report.path);
+            assert_eq!(0, report.fallbacks_tried);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn missing_current_image_is_restored_from_a_regular_bank_first() {
+        let regular = regular_test_image(b"new");
+        for current in [vec![], corrupted(regular_test_image(b"current"))].iter() {
+            let report = report_for(current, &regular, &golden_test_image(b"golden"));
+            assert_eq!(BootReason::NoCurrentImage, report.reason);
+            assert_eq!(Some(2), report.chosen_bank);
+            assert_eq!(Some(BootPath::Restored { bank: 2 }), report.path);
+            assert_eq!(1, report.fallbacks_tried);
+            assert!(!report.image.unwrap().is_golden());
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn golden_image_is_the_last_fallback() {
+        let golden = golden_test_image(b"golden");
+        for regular in [vec![], corrupted(regular_test_image(b"new"))].iter() {
+            let report = report_for(&[], regular, &golden);
+            assert_eq!(BootReason::GoldenFallback, report.reason);
+            assert_eq!(Some(3), report.chosen_bank);
+            assert_eq!(Some(BootPath::Restored { bank: 3 }), report.path);
+            assert_eq!(2, report.fallbacks_tried);
+            assert!(report.image.unwrap().is_golden());
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn no_valid_image_anywhere_leaves_only_recovery() {
+        let corrupted_golden = corrupted(golden_test_image(b"golden"));
+        for golden in [vec![], corrupted_golden].iter() {
+            let report = report_for(&[], &corrupted(regular_test_image(b"new")), golden);
+            assert_eq!(BootReason::NoImage, report.reason);
+            assert_eq!(None, report.image);
+            assert_eq!(None, report.chosen_bank);
+            assert_eq!(None, report.path);
+            assert_eq!(2, report.fallbacks_tried);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn requested_recovery_holds_back_updates() {
+        let mut bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_FOR_REPORTS)
+            .with_image_at(1, &regular_test_image(b"current"))
+            .with_image_at(2, &regular_test_image(b"new"))
+            .with_update_plan(UpdatePlan::Any.recovery());
+        assert!(bootloader.recovery_requested());
+        assert_eq!(BootReason::UpToDate, bootloader.decide().reason);
+
+        let bootloader = CrcBootloaderDouble::new().with_update_plan(UpdatePlan::Any);
+        assert!(!bootloader.recovery_requested());
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn recovery_requests_are_dropped_before_entering_recovery() {
+        let (current, new) = (regular_test_image(b"current"), regular_test_image(b"new"));
+        let signal = FakeUpdateSignal::new(UpdatePlan::Any.recovery());
+        let bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_FOR_REPORTS)
+            .with_image_at(1, &current)
+            .with_image_at(2, &new)
+            .with_recovery()
+            .with_recovery_timeout(0, true)
+            .with_update_signal(signal.clone());
+        let transcript = bootloader.transcript();
+        assert_eq!(Exit::Reboot, run_to_exit(bootloader));
+        assert!(transcript.contains("-- Loadstone Recovery Mode --"));
+        assert_eq!(UpdatePlan::Any, signal.read_update_plan());
+
+        // Without recovery support, the request is dropped all the same, so updates go on.
+        let signal = FakeUpdateSignal::new(UpdatePlan::Any.recovery());
+        let bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_FOR_REPORTS)
+            .with_image_at(1, &current)
+            .with_image_at(2, &new)
+            .with_update_signal(signal.clone());
+        let transcript = bootloader.transcript();
+        assert_eq!(Exit::Jump(0x000), run_to_exit(bootloader));
+        assert!(transcript.contains("Replaced image with bank 2"));
+        assert_eq!(UpdatePlan::Any, signal.read_update_plan());
+    }
+
+    #[rustfmt::skip]
+    static MCU_BANKS_WITH_BACKUP: [Bank<Address>; 3] = [
+        Bank { index: 1, size: 0x200, location: Address(0x000), bootable: true, is_golden: false },
+        Bank { index: 2, size: 0x200, location: Address(0x200), bootable: false, is_golden: false },
+        Bank { index: 3, size: 0x200, location: Address(0x400), bootable: false, is_golden: false },
+    ];
+
+    #[rustfmt::skip]
+    static MCU_BANKS_WITH_GOLDEN_FIRST: [Bank<Address>; 3] = [
+        Bank { index: 1, size: 0x200, location: Address(0x000), bootable: true, is_golden: false },
+        Bank { index: 2, size: 0x200, location: Address(0x200), bootable: false, is_golden: true },
+        Bank { index: 3, size: 0x200, location: Address(0x400), bootable: false, is_golden: false },
+    ];
+
+    /// Decides the boot on a freshly started bootloader with the golden override, over the
+    /// flash left by `bootloader`.
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn next_boot_with_golden_override(bootloader: CrcBootloaderDouble) -> BootReport<Address> {
+        let mut next_boot = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_GOLDEN_FIRST)
+            .with_golden_override();
+        next_boot.mcu_flash = bootloader.mcu_flash;
+        next_boot.decide()
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn golden_images_are_never_updated_from_by_default() {
+        let (current, golden) = (regular_test_image(b"v1"), golden_test_image(b"v2"));
+        let mut bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_GOLDEN_FIRST)
+            .with_image_at(1, &current)
+            .with_image_at(2, &golden);
+        let report = bootloader.decide();
+        assert_eq!(BootReason::UpToDate, report.reason);
+        assert_eq!(Some(1), report.chosen_bank);
+        assert!(!report.image.unwrap().is_golden());
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn golden_override_updates_from_a_golden_image_newer_than_the_current_one() {
+        let mut bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_GOLDEN_FIRST)
+            .with_golden_override()
+            .with_image_at(1, &regular_test_image(b"v1"))
+            .with_image_at(2, &golden_test_image(b"v2"))
+            .with_image_at(3, &corrupted(regular_test_image(b"v3")));
+        let report = bootloader.decide();
+        assert_eq!(BootReason::UpdateFound, report.reason);
+        assert_eq!(Some(2), report.chosen_bank);
+        assert!(report.image.unwrap().is_golden());
+
+        // The golden bank now holds the current image, so it isn't copied again.
+        let report = next_boot_with_golden_override(bootloader);
+        assert_eq!(BootReason::UpToDate, report.reason);
+        assert!(report.image.unwrap().is_golden());
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn regular_images_take_precedence_over_golden_ones_with_the_golden_override() {
+        let mut bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_GOLDEN_FIRST)
+            .with_golden_override()
+            .with_image_at(1, &regular_test_image(b"v1"))
+            .with_image_at(2, &golden_test_image(b"v2"))
+            .with_image_at(3, &regular_test_image(b"v3"));
+        let report = bootloader.decide();
+        assert_eq!(BootReason::UpdateFound, report.reason);
+        assert_eq!(Some(3), report.chosen_bank);
+
+        // The regular bank holding the current image settles the update before the
+        // golden bank, despite the golden image being different.
+        let report = next_boot_with_golden_override(bootloader);
+        assert_eq!(BootReason::UpToDate, report.reason);
+        assert!(!report.image.unwrap().is_golden());
+    }
+
+    #[test]
+    #[cfg(all(feature = "cli", not(feature = "ecdsa-verify")))]
+    fn boot_image_is_only_mirrored_into_the_backup_bank_once_confirmed() {
+        use crate::devices::boot_manager::doubles::CrcBootManagerDouble;
+        use image::Reader as _;
+        type Reader = CrcImageReader<{ crc32::IEEE }, false>;
+        let backup_bank = MCU_BANKS_WITH_BACKUP[1];
+        let current = regular_test_image(b"current");
+        let mut bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_BACKUP)
+            .with_backup_bank(2)
+            .with_image_at(1, &current)
+            .with_image_at(2, &regular_test_image(b"old"));
+
+        let booted = bootloader.decide().image.unwrap().identifier();
+        let backup = Reader::image_at(&mut bootloader.mcu_flash, backup_bank).unwrap();
+        assert_ne!(booted, backup.identifier());
+
+        let mut boot_manager =
+            CrcBootManagerDouble::new(bootloader.mcu_flash, &MCU_BANKS_WITH_BACKUP)
+                .with_backup_bank(2);
+        assert_eq!(Ok(true), boot_manager.confirm_healthy());
+        let backup = Reader::image_at(&mut boot_manager.mcu_flash, backup_bank).unwrap();
+        assert_eq!(booted, backup.identifier());
+    }
+
+    #[test]
+    #[cfg(all(feature = "cli", not(feature = "ecdsa-verify")))]
+    fn mirroring_is_skipped_when_the_backup_already_matches() {
+        use crate::devices::boot_manager::doubles::CrcBootManagerDouble;
+        let current = regular_test_image(b"current");
+        let bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_BACKUP)
+            .with_backup_bank(2)
+            .with_image_at(1, &current)
+            .with_image_at(2, &current);
+        let mut boot_manager =
+            CrcBootManagerDouble::new(bootloader.mcu_flash, &MCU_BANKS_WITH_BACKUP)
+                .with_backup_bank(2);
+        assert_eq!(Ok(false), boot_manager.confirm_healthy());
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn backup_of_the_current_image_does_not_hold_back_updates() {
+        let current = regular_test_image(b"current");
+        let mut bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_BACKUP)
+            .with_backup_bank(2)
+            .with_image_at(1, &current)
+            .with_image_at(2, &current)
+            .with_image_at(3, &regular_test_image(b"new"));
+        let report = bootloader.decide();
+        assert_eq!(BootReason::UpdateFound, report.reason);
+        assert_eq!(Some(3), report.chosen_bank);
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn lost_boot_image_is_restored_from_the_backup_bank() {
+        let mut bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_BACKUP)
+            .with_backup_bank(2)
+            .with_image_at(2, &regular_test_image(b"current"));
+        let report = bootloader.decide();
+        assert_eq!(BootReason::NoCurrentImage, report.reason);
+        assert_eq!(Some(2), report.chosen_bank);
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn restore_is_retried_until_an_image_shows_up() {
+        let mut bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_GOLDEN)
+            .with_external_banks(&EXTERNAL_BANKS_WITH_GOLDEN);
+        assert_eq!(Err(Error::NoImageToRestoreFrom), bootloader.restore().map(|_| ()));
+
+        let mut attempts = 0;
+        let image = retry_until_ok::<MockSysTick, _, _>(0, || {
+            attempts += 1;
+            if attempts == 3 {
+                bootloader.mcu_flash.write(Address(0x200), &golden_test_image(b"late")).unwrap();
+            }
+            bootloader.restore().ok()
+        });
+        assert_eq!(3, attempts);
+        assert!(image.is_golden());
+        assert!(matches!(bootloader.boot_metrics.boot_path, BootPath::Restored { bank: 2 }));
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn recovery_session_gives_up_after_the_deadline_without_a_transfer() {
+        let mut bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_GOLDEN)
+            .with_image_at(2, &golden_test_image(b"mcu"))
+            .with_recovery_timeout(0, true);
+        let result = bootloader.recovery_session();
+        assert_eq!(Err(Error::RecoveryTimedOut), result);
+        assert!(!bootloader.starts_over(result));
+
+        // Nothing was written, so the golden image is still there to restore from.
+        let golden_bank = MCU_BANKS_WITH_GOLDEN[1];
+        let image = scan_bank::<CrcImageReader<{ crc32::IEEE }, false>, _, SerialStub>(
+            &mut None,
+            &mut bootloader.mcu_flash,
+            golden_bank,
+        );
+        assert!(image.unwrap().is_golden());
+
+        let bootloader = bootloader.with_recovery_timeout(0, false);
+        assert!(bootloader.starts_over(result));
+    }
+
+    #[test]
+    fn diagnostic_commands_are_parsed_leniently() {
+        assert_eq!(Some(DiagnosticCommand::Banks), DiagnosticCommand::parse("banks"));
+        assert_eq!(Some(DiagnosticCommand::Retry), DiagnosticCommand::parse(" retry\r"));
+        assert_eq!(None, DiagnosticCommand::parse("flash"));
+    }
+
+    /// Returns a golden image too large for the small golden banks, along with its
+    /// compressed golden form, which fits.
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn compressed_golden_image() -> (Vec<u8>, Vec<u8>) {
+        let mut firmware = vec![0xAAu8; 0x300];
+        firmware.extend_from_slice(b"firmware");
+        let image = golden_test_image(&firmware);
+        let compressed = golden_test_image(&compress(&image));
+        assert!(image.len() > 0x100 && compressed.len() <= 0x100);
+        (image, compressed)
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn restore_decompresses_compressed_golden_image() {
+        let (image, compressed) = compressed_golden_image();
+        let mut bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_SMALL_GOLDEN)
+            .with_image_at(2, &compressed);
+
+        let restored = bootloader.restore().unwrap();
+        assert!(restored.is_golden());
+        assert_eq!(restored.total_size(), image.len());
+        let mut boot_bank = vec![0u8; image.len()];
+        bootloader.mcu_flash.read(Address(0x000), &mut boot_bank).unwrap();
+        assert_eq!(boot_bank, image);
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn restore_decompresses_compressed_golden_image_from_external_flash() {
+        let (image, compressed) = compressed_golden_image();
+        let mut bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_WITH_SMALL_GOLDEN)
+            .with_external_banks(&EXTERNAL_BANKS_WITH_SMALL_GOLDEN)
+            .with_image_at(3, &compressed);
+
+        let restored = bootloader.restore().unwrap();
+        assert_eq!(restored.total_size(), image.len());
+        assert!(matches!(bootloader.boot_metrics.boot_path, BootPath::Restored { bank: 3 }));
+    }
+
+    #[test]
+    fn plausible_jump_targets_pass_validation() {
+        let validation = JumpValidation { ram_start: 0x2000_0000, ram_size: KB!(256) };
+        let (image_start, image_size) = (0x0802_0000, KB!(64));
+        assert!(validation.is_plausible(0x2004_0000, 0x0802_0401, image_start, image_size));
+        assert!(validation.is_plausible(0x2000_1000, 0x0802_0001, image_start, image_size));
+    }
+
+    #[test]
+    fn implausible_jump_targets_fail_validation() {
+        let validation = JumpValidation { ram_start: 0x2000_0000, ram_size: KB!(256) };
+        let (image_start, image_size) = (0x0802_0000, KB!(64));
+        // Stack pointer outside RAM
+        assert!(!validation.is_plausible(0x2004_0004, 0x0802_0401, image_start, image_size));
+        assert!(!validation.is_plausible(0x2000_0000, 0x0802_0401, image_start, image_size));
+        assert!(!validation.is_plausible(0xFFFF_FFFF, 0x0802_0401, image_start, image_size));
+        // Reset handler without the thumb bit
+        assert!(!validation.is_plausible(0x2004_0000, 0x0802_0400, image_start, image_size));
+        // Reset handler outside the image
+        assert!(!validation.is_plausible(0x2004_0000, 0x0801_FFFF, image_start, image_size));
+        assert!(!validation.is_plausible(0x2004_0000, 0x0803_0001, image_start, image_size));
+        assert!(!validation.is_plausible(0xFFFF_FFFF, 0xFFFF_FFFF, image_start, image_size));
+    }
+
+    #[test]
+    fn ram_vector_table_copy_is_limited_by_image_size() {
+        let table = RamVectorTable { address: 0x2000_0000, length: 452 };
+        assert_eq!(452, table.copy_length(KB!(64)));
+        assert_eq!(452, table.copy_length(452));
+        assert_eq!(100, table.copy_length(100));
+    }
+
+    #[test]
+    fn ram_clear_spares_the_stack_and_protected_ranges() {
+        let clear = RamClear { start: 0x2000_0000, length: KB!(64) };
+        let boot_info = 0x2000_FFEC..0x2001_0000;
+        let vector_table = 0x2000_0000..0x2000_01C4;
+        let stack_pointer = 0x2003_F000;
+        assert_eq!(
+            [0x2000_0000..0x2000_0000, 0x2000_01C4..0x2000_FFEC, 0x2001_0000..0x2001_0000],
+            clear.bounds(stack_pointer, [boot_info.clone(), vector_table.clone()])
+        );
+
+        // The stack cuts the region short, margin included.
+        let stack_pointer = 0x2000_8000;
+        let end = stack_pointer - RamClear::STACK_MARGIN;
+        assert_eq!(
+            [0x2000_0000..0x2000_0000, 0x2000_01C4..end, 0x2001_0000..0x2001_0000],
+            clear.bounds(stack_pointer, [boot_info, vector_table])
+        );
+
+        // Without protected ranges, the whole region is cleared.
+        let bounds = clear.bounds(0x2004_0000, [0..0, 0..0]);
+        assert_eq!(0x2000_0000..0x2001_0000, bounds[2]);
+        assert_eq!(KB!(64), bounds.iter().map(|r| r.len()).sum::<usize>());
+    }
+
+    #[test]
+    fn self_check_detects_corrupted_bootloader_region() {
+        // The CRC32 (IEEE) check value of "123456789" is 0xCBF43926.
+        let mut region = b"123456789".to_vec();
+        region.extend_from_slice(&0xCBF43926u32.to_le_bytes());
+        assert!(SelfCheck::region_is_intact(&region));
+
+        region[4] ^= 0x01;
+        assert!(!SelfCheck::region_is_intact(&region));
+        assert!(!SelfCheck::region_is_intact(&[0xCB, 0xF4, 0x39]));
+    }
+
+    /// Bootloader holding a valid current image, with serial recovery enabled. Recovery
+    /// sessions reboot as soon as they time out, as no host is ever attached.
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn recoverable_bootloader() -> CrcBootloaderDouble {
+        CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_FOR_REPORTS)
+            .with_image_at(1, &regular_test_image(b"current"))
+            .with_recovery()
+            .with_recovery_timeout(0, true)
+    }
+
+    /// Runs a bootloader until it gives up control, reporting whether it went through
+    /// `recover()` (and so rebooted) rather than booting the current image.
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn entered_recovery(bootloader: CrcBootloaderDouble) -> bool {
+        let transcript = bootloader.transcript();
+        let exit = run_to_exit(bootloader);
+        let recovered = transcript.contains("-- Loadstone Recovery Mode --");
+        assert_eq!(if recovered { Exit::Reboot } else { Exit::Jump(0x000) }, exit);
+        recovered
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn keypress_during_boot_delay_interrupts_boot() {
+        assert!(entered_recovery(
+            recoverable_bootloader().with_boot_delay(1000).with_serial_input(b"x")
+        ));
+        // Without a boot delay, there is no window to interrupt the boot in.
+        assert!(!entered_recovery(recoverable_bootloader().with_serial_input(b"x")));
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn status_led_pattern_transitions_follow_bootloader_phases() {
+        use crate::devices::file_transfer::{XModemSession, BLOCK_SIZE};
+        use blue_hal::utilities::xmodem;
+        // Lit as scanning starts, and kept lit (solid) through the jump.
+        let led = FakeLed::default();
+        assert!(!entered_recovery(recoverable_bootloader().with_status_led(led.clone())));
+        assert_eq!(vec![false, true, true], led.history());
+
+        // Recovery skips scanning, and blinks fast with every block received.
+        let golden = golden_test_image(b"golden");
+        let mut block = [0xFFu8; BLOCK_SIZE];
+        block[..golden.len()].copy_from_slice(&golden);
+        let mut transfer = XModemSession::new().packet(&block).to_vec();
+        transfer.push(xmodem::EOT);
+        let led = FakeLed::default();
+        let bootloader = CrcBootloaderDouble::new()
+            .with_mcu_banks(&MCU_BANKS_FOR_REPORTS)
+            .with_recovery()
+            .with_recovery_pin(false, ActiveLevel::Low)
+            .with_status_led(led.clone())
+            .with_serial_input(&transfer);
+        assert!(entered_recovery(bootloader));
+        assert_eq!(vec![false, true, false], led.history());
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn asserted_recovery_pin_forces_recovery_mode() {
+        assert!(!entered_recovery(recoverable_bootloader()));
+        let pin_low =
+            |active_level| recoverable_bootloader().with_recovery_pin(false, active_level);
+        assert!(entered_recovery(pin_low(ActiveLevel::Low)));
+        assert!(!entered_recovery(pin_low(ActiveLevel::High)));
+    }
+}
+
 #[cfg(test)]
 #[doc(hidden)]
 pub mod doubles {
-    use crate::devices::update_signal::{ReadUpdateSignal, UpdatePlan};
+    use crate::devices::{
+        doubles::{ScriptedSerial, Transcript},
+        recovery_pin::{doubles::FakePin, ActiveLevel, RecoveryPin},
+        status_led::{doubles::FakeLed, StatusLed},
+        update_signal::{ReadUpdateSignal, UpdatePlan, WriteUpdateSignal},
+    };
     use blue_hal::{
         hal::{
             doubles::{
                 error::FakeError,
                 flash::{Address, FakeFlash},
-                serial::SerialStub,
+                serial::SerialStubError,
                 time::MockSysTick,
             },
+            flash::ReadWrite,
             null::NullFlash,
         },
         utilities::memory::doubles::FakeAddress,
     };
+    use std::{
+        cell::Cell,
+        ops::Range,
+        panic::{self, AssertUnwindSafe},
+        rc::Rc,
+    };
+
+    /// How [`super::Bootloader::run`] gave up control. Test builds can neither jump nor
+    /// reset, so they unwind with one of these instead.
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum Exit {
+        /// Jump to the image at this address.
+        Jump(usize),
+        Reboot,
+    }
+
+    /// Runs the bootloader until it gives up control, and reports how it did.
+    pub fn run_to_exit<R: Reader>(
+        bootloader: super::Bootloader<
+            FakeFlash,
+            FakeFlash,
+            ScriptedSerial,
+            MockSysTick,
+            R,
+            FakeUpdateSignal,
+            FakeLed,
+            FakePin,
+        >,
+    ) -> Exit {
+        let payload = panic::catch_unwind(AssertUnwindSafe(move || bootloader.run())).unwrap_err();
+        match payload.downcast::<Exit>() {
+            Ok(exit) => *exit,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
 
     pub struct FakeReader;
 
     impl Reader for FakeReader {
-        fn image_at<A, F>(_flash: &mut F, _bank: Bank<A>) -> Result<Image<A>, error::Error>
+        fn image_at_with_progress<A, F, P>(
+            _flash: &mut F,
+            _bank: Bank<A>,
+            _progress: P,
+        ) -> Result<Image<A>, error::Error>
         where
             A: blue_hal::utilities::memory::Address,
             F: blue_hal::hal::flash::ReadWrite<Address = A>,
+            P: FnMut(usize),
             error::Error: From<F::Error>,
         {
             unimplemented!()
         }
     }
 
-    pub struct FakeUpdateSignal;
+    #[derive(Copy, Clone, Debug)]
+    pub struct FlashFault;
+
+    /// Flash double over a [`FakeFlash`], whose reads of more than a byte fail where they
+    /// overlap the `faulty` range, as block transfers out of a damaged region would.
+    /// Single byte reads, such as those scanning for an image, still go through.
+    pub struct FaultyFlash {
+        pub flash: FakeFlash,
+        pub faulty: Range<usize>,
+    }
+
+    impl FaultyFlash {
+        pub fn new(faulty: Range<usize>) -> Self {
+            Self { flash: FakeFlash::new(Address(0)), faulty }
+        }
+    }
+
+    impl ReadWrite for FaultyFlash {
+        type Error = FlashFault;
+        type Address = Address;
+
+        fn label() -> &'static str { "Faulty flash" }
+
+        fn read(&mut self, address: Address, bytes: &mut [u8]) -> nb::Result<(), Self::Error> {
+            let start: usize = address.into();
+            if bytes.len() > 1 && start < self.faulty.end && self.faulty.start < start + bytes.len()
+            {
+                return Err(nb::Error::Other(FlashFault));
+            }
+            self.flash.read(address, bytes).map_err(|e| e.map(|_| FlashFault))
+        }
+
+        fn write(&mut self, address: Address, bytes: &[u8]) -> nb::Result<(), Self::Error> {
+            self.flash.write(address, bytes).map_err(|e| e.map(|_| FlashFault))
+        }
+
+        fn write_from_blocks<I: Iterator<Item = [u8; N]>, const N: usize>(
+            &mut self,
+            address: Address,
+            blocks: I,
+        ) -> Result<(), Self::Error> {
+            self.flash.write_from_blocks(address, blocks).map_err(|_| FlashFault)
+        }
+
+        fn range(&self) -> (Address, Address) { self.flash.range() }
+
+        fn erase(&mut self) -> nb::Result<(), Self::Error> {
+            self.flash.erase().map_err(|e| e.map(|_| FlashFault))
+        }
+    }
+
+    /// Update signal double, shared with the test so the plan left behind can be read
+    /// once the bootloader is done with it.
+    #[derive(Clone)]
+    pub struct FakeUpdateSignal(Rc<Cell<UpdatePlan>>);
+
+    impl FakeUpdateSignal {
+        pub fn new(plan: UpdatePlan) -> Self { Self(Rc::new(Cell::new(plan))) }
+    }
+
     impl ReadUpdateSignal for FakeUpdateSignal {
-        fn read_update_plan(&self) -> UpdatePlan { UpdatePlan::Any }
+        fn read_update_plan(&self) -> UpdatePlan { self.0.get() }
+    }
+
+    impl WriteUpdateSignal for FakeUpdateSignal {
+        fn write_update_plan(&mut self, plan: UpdatePlan) { self.0.set(plan) }
     }
 
     pub type BootloaderDouble = super::Bootloader<
         FakeFlash,
         FakeFlash,
-        SerialStub,
+        ScriptedSerial,
         MockSysTick,
         FakeReader,
         FakeUpdateSignal,
+        FakeLed,
+        FakePin,
+    >;
+
+    pub type CrcBootloaderDouble = super::Bootloader<
+        FakeFlash,
+        FakeFlash,
+        ScriptedSerial,
+        MockSysTick,
+        CrcImageReader<{ crc32::IEEE }, false>,
+        FakeUpdateSignal,
+        FakeLed,
+        FakePin,
     >;
 
-    impl BootloaderDouble {
-        pub fn new() -> Self {
-            BootloaderDouble {
-                mcu_flash: FakeFlash::new(Address(0)),
+    /// Bootloader double whose MCU flash fails some reads (see [`FaultyFlash`]).
+    pub type FaultyBootloaderDouble = super::Bootloader<
+        FakeFlash,
+        FaultyFlash,
+        ScriptedSerial,
+        MockSysTick,
+        CrcImageReader<{ crc32::IEEE }, false>,
+        FakeUpdateSignal,
+        FakeLed,
+        FakePin,
+    >;
+
+    impl<R: Reader>
+        super::Bootloader<
+            FakeFlash,
+            FakeFlash,
+            ScriptedSerial,
+            MockSysTick,
+            R,
+            FakeUpdateSignal,
+            FakeLed,
+            FakePin,
+        >
+    {
+        pub fn new() -> Self { Self::over_mcu_flash(FakeFlash::new(Address(0))) }
+    }
+
+    impl<R: Reader, MCUF: Flash + ReadWrite<Address = Address>>
+        super::Bootloader<
+            FakeFlash,
+            MCUF,
+            ScriptedSerial,
+            MockSysTick,
+            R,
+            FakeUpdateSignal,
+            FakeLed,
+            FakePin,
+        >
+    {
+        pub fn over_mcu_flash(mcu_flash: MCUF) -> Self {
+            Self {
+                mcu_flash,
                 external_banks: &[],
                 mcu_banks: &[],
+                mcu_sectors: &[],
+                staging_bank: None,
+                staging_rotation: None,
+                backup_bank: None,
+                golden_override: false,
+                external_erase_size: 1,
                 external_flash: Some(FakeFlash::new(Address(0))),
-                serial: Some(SerialStub),
+                serial: Some(ScriptedSerial::default()),
                 boot_metrics: BootMetrics::default(),
                 start_time: None,
                 recovery_enabled: false,
+                recovery_protocol: Protocol::XModem,
+                recovery_timeout: None,
+                no_image_fallback: NoImageFallback::Panic,
+                boot_delay_ms: 0,
                 greeting: "I'm a fake bootloader!",
+                serial_log_level: crate::devices::serial_log::Level::Off,
+                boot_counter: None,
+                verification_cache: None,
+                status_led: None,
+                recovery_pin: None,
+                ram_vector_table: None,
+                self_check: None,
+                jump_validation: None,
+                ram_clear: None,
+                verify_writes: false,
                 _marker: Default::default(),
                 update_signal: None,
             }
         }
 
-        pub fn with_mcu_banks(self, mcu_banks: &'static [Bank<Address>]) -> Self {
+        pub fn with_status_led(self, led: FakeLed) -> Self {
+            Self { status_led: Some(StatusLed::new(led)), ..self }
+        }
+
+        pub fn with_recovery_pin(self, high: bool, active_level: ActiveLevel) -> Self {
+            Self { recovery_pin: Some(RecoveryPin::new(FakePin { high }, active_level)), ..self }
+        }
+
+        /// Lays out the MCU banks, erasing them.
+        pub fn with_mcu_banks(mut self, mcu_banks: &'static [Bank<Address>]) -> Self {
+            mcu_banks.iter().for_each(|bank| write_bank(&mut self.mcu_flash, bank, &[]));
             Self { mcu_banks, ..self }
         }
 
-        pub fn with_external_banks(self, external_banks: &'static [Bank<Address>]) -> Self {
+        pub fn with_mcu_sectors(self, mcu_sectors: &'static [SectorRegion]) -> Self {
+            Self { mcu_sectors, ..self }
+        }
+
+        /// Lays out the external banks, erasing them.
+        pub fn with_external_banks(mut self, external_banks: &'static [Bank<Address>]) -> Self {
+            let flash = self.external_flash.as_mut().unwrap();
+            external_banks.iter().for_each(|bank| write_bank(flash, bank, &[]));
             Self { external_banks, ..self }
         }
+
+        /// Replaces the contents of a bank already laid out with `bytes`, followed by
+        /// erased flash up to the end of the bank.
+        pub fn with_image_at(mut self, index: u8, bytes: &[u8]) -> Self {
+            let find = |banks: &[Bank<Address>]| banks.iter().find(|b| b.index == index).copied();
+            if let Some(bank) = find(self.mcu_banks) {
+                write_bank(&mut self.mcu_flash, &bank, bytes);
+            } else {
+                let bank = find(self.external_banks).expect("No bank with that index");
+                write_bank(self.external_flash.as_mut().unwrap(), &bank, bytes);
+            }
+            self
+        }
+
+        pub fn with_external_erase_size(self, external_erase_size: usize) -> Self {
+            Self { external_erase_size, ..self }
+        }
+
+        pub fn with_staging_bank(self, index: u8) -> Self {
+            Self { staging_bank: Some(index), ..self }
+        }
+
+        pub fn with_staging_rotation(self, banks: &'static [u8], state: Address) -> Self {
+            let rotation = StagingRotation { banks, state };
+            Self { staging_bank: banks.first().copied(), staging_rotation: Some(rotation), ..self }
+        }
+
+        pub fn with_backup_bank(self, index: u8) -> Self {
+            Self { backup_bank: Some(index), ..self }
+        }
+
+        pub fn with_golden_override(self) -> Self { Self { golden_override: true, ..self } }
+
+        pub fn with_recovery_timeout(self, timeout_ms: u32, reboot: bool) -> Self {
+            Self { recovery_timeout: Some(RecoveryTimeout { timeout_ms, reboot }), ..self }
+        }
+
+        pub fn with_update_plan(self, plan: UpdatePlan) -> Self {
+            self.with_update_signal(FakeUpdateSignal::new(plan))
+        }
+
+        pub fn with_update_signal(self, update_signal: FakeUpdateSignal) -> Self {
+            Self { update_signal: Some(update_signal), ..self }
+        }
+
+        pub fn with_recovery(self) -> Self { Self { recovery_enabled: true, ..self } }
+
+        pub fn with_boot_delay(self, boot_delay_ms: u32) -> Self { Self { boot_delay_ms, ..self } }
+
+        pub fn with_serial_input(self, input: &[u8]) -> Self {
+            let serial =
+                ScriptedSerial { incoming: input.iter().copied().collect(), ..Default::default() };
+            Self { serial: Some(serial), ..self }
+        }
+
+        /// Everything the bootloader writes to serial from now on.
+        pub fn transcript(&self) -> Transcript { self.serial.as_ref().unwrap().transcript.clone() }
+
+        pub fn with_verification_cache(self, location: Address) -> Self {
+            Self { verification_cache: Some(VerificationCache::new(location)), ..self }
+        }
+    }
+
+    /// Erases a bank, then writes `bytes` at its start.
+    fn write_bank<F: ReadWrite<Address = Address>>(
+        flash: &mut F,
+        bank: &Bank<Address>,
+        bytes: &[u8],
+    ) {
+        assert!(bytes.len() <= bank.size, "Image doesn't fit in bank {}", bank.index);
+        let erased = flash.write(bank.location, &vec![0xFFu8; bank.size]);
+        assert!(erased.is_ok() && flash.write(bank.location, bytes).is_ok());
     }
 
+    use super::{NoImageFallback, RecoveryTimeout, StagingRotation};
     use crate::{
         devices::{
             boot_metrics::BootMetrics,
-            image::{Bank, Image, Reader},
+            file_transfer::Protocol,
+            image::{Bank, CrcImageReader, Image, Reader, SectorRegion, VerificationCache},
+            traits::Flash,
         },
         error,
     };
+    use crc::crc32;
     impl error::Convertible for FakeError {
         fn into(self) -> error::Error {
             error::Error::DeviceError("Something fake happened (test error)")
         }
     }
+    impl error::Convertible for SerialStubError {
+        fn into(self) -> error::Error { error::Error::DeviceError("Serial stub failed") }
+    }
+    impl error::Convertible for FlashFault {
+        fn into(self) -> error::Error { error::Error::DeviceError("Faulty flash failed") }
+    }
 }
@@ -1,4 +1,10 @@
-use crate::devices::{cli::file_transfer::FileTransfer, update_signal::ReadUpdateSignal};
+use crate::devices::{
+    file_transfer::{FileTransfer, Protocol, BLOCK_SIZE},
+    update_signal::{ReadUpdateSignal, WriteUpdateSignal},
+    ymodem::YModemTransfer,
+};
+use blue_hal::{hal::serial, utilities::memory::Address};
+use core::cell::Cell;
 
 use super::*;
 
@@ -8,76 +14,75 @@ impl<
         SRL: Serial,
         T: time::Now,
         R: image::Reader,
-        RUS: ReadUpdateSignal,
-    > Bootloader<EXTF, MCUF, SRL, T, R, RUS>
+        RUS: ReadUpdateSignal + WriteUpdateSignal,
+        LED: led::Toggle,
+        PIN: gpio::InputPin,
+    > Bootloader<EXTF, MCUF, SRL, T, R, RUS, LED, PIN>
 {
     /// Enters recovery mode, which requests a golden image to be transferred via serial through
-    /// the XMODEM protocol, then reboot. If Loadstone has no golden image support, recovery
-    /// mode will allow flashing the bootable bank directly.
+    /// the configured protocol (XMODEM or YMODEM), then reboot. If Loadstone has no golden image support, recovery
+    /// mode will allow flashing the bootable bank directly. With a [`RecoveryTimeout`], sessions
+    /// in which no transfer starts before the deadline either reboot or start over, as configured.
     pub fn recover(&mut self) -> ! {
         duprintln!(self.serial, "-- Loadstone Recovery Mode --");
+        self.signal(Pattern::FastBlink);
 
-        let mcu_golden_bank_exists = self.mcu_banks().any(|b| b.is_golden);
-        let external_golden_bank_exists = self.external_banks().any(|b| b.is_golden);
-        let no_golden_bank_support = !mcu_golden_bank_exists && !external_golden_bank_exists;
-
-        if mcu_golden_bank_exists {
-            duprintln!(self.serial, "Attempting golden image recovery to MCU flash...");
-            match self.recover_internal(true) {
-                Ok(_) => {
-                    duprintln!(self.serial, "Finished flashing golden image.");
-                    self.reboot();
-                }
-                Err(e) => {
-                    duprintln!(self.serial, "FATAL: Image did not flash correctly.");
-                    if let Some(serial) = self.serial.as_mut() {
-                        e.report(serial);
-                    }
-                    self.reboot();
-                }
+        loop {
+            let result = self.recovery_session();
+            if !self.starts_over(result) {
+                self.reboot();
             }
         }
+    }
 
-        if self.external_flash.is_some() && external_golden_bank_exists {
-            duprintln!(self.serial, "Attempting golden image recovery to external flash...");
-            match self.recover_external(true) {
-                Ok(_) => {
-                    duprintln!(self.serial, "Finished flashing golden image.");
-                    self.reboot();
-                }
-                Err(e) => {
-                    duprintln!(self.serial, "FATAL: Image did not flash correctly.");
-                    if let Some(serial) = self.serial.as_mut() {
-                        e.report(serial);
-                    }
-                    self.reboot();
-                }
-            }
-        }
+    /// Whether recovery carries on with a new session after one ended with `result`,
+    /// rather than rebooting.
+    pub(super) fn starts_over(&self, result: Result<(), Error>) -> bool {
+        result == Err(Error::RecoveryTimedOut)
+            && self.recovery_timeout.map_or(false, |timeout| !timeout.reboot)
+    }
 
-        if no_golden_bank_support {
+    /// Requests and stores a single image, reporting the outcome over serial.
+    pub(super) fn recovery_session(&mut self) -> Result<(), Error> {
+        let mcu_golden_bank_exists = self.mcu_banks().any(|b| b.is_golden);
+        let external_golden_bank_exists =
+            self.external_flash.is_some() && self.external_banks().any(|b| b.is_golden);
+
+        let (golden, result) = if mcu_golden_bank_exists {
+            duprintln!(self.serial, "Attempting golden image recovery to MCU flash...");
+            (true, self.recover_internal(true))
+        } else if external_golden_bank_exists {
+            duprintln!(self.serial, "Attempting golden image recovery to external flash...");
+            (true, self.recover_external(true))
+        } else {
             duprintln!(self.serial, "Attempting image recovery to MCU flash...");
-            match self.recover_internal(false) {
-                Ok(_) => {
-                    duprintln!(self.serial, "Finished flashing image.");
-                    self.reboot();
-                }
-                Err(e) => {
-                    duprintln!(self.serial, "FATAL: Image did not flash correctly.");
-                    if let Some(serial) = self.serial.as_mut() {
-                        e.report(serial);
-                    }
-                    self.reboot();
+            (false, self.recover_internal(false))
+        };
+
+        match result {
+            Ok(()) => {
+                duprintln!(
+                    self.serial,
+                    "Finished flashing{} image.",
+                    if golden { " golden" } else { "" }
+                );
+            }
+            Err(Error::RecoveryTimedOut) => {
+                duprintln!(self.serial, "Recovery session timed out.");
+            }
+            Err(e) => {
+                duprintln!(self.serial, "FATAL: Image did not flash correctly.");
+                if let Some(serial) = self.serial.as_mut() {
+                    e.report(serial);
                 }
             }
         }
-
-        self.reboot();
+        result
     }
 
     fn reboot(&mut self) -> ! {
         duprintln!(self.serial, "Rebooting...");
-        SCB::sys_reset();
+        reset()
     }
 
     fn recover_internal(&mut self, golden: bool) -> Result<(), Error> {
@@ -88,26 +93,20 @@ impl<
         if let Some(bank) = self.mcu_banks.iter().find(|b| b.is_golden == golden) {
             duprintln!(
                 self.serial,
-                "Please send{} firmware image via XMODEM.",
-                if golden { " golden" } else { "" }
+                "Please send{} firmware image via {}.",
+                if golden { " golden" } else { "" },
+                self.recovery_protocol.name()
             );
-            let blocks = self.serial.as_mut().unwrap().blocks(None);
-            if self.mcu_flash.write_from_blocks(bank.location, blocks).is_err() {
-                duprintln!(
-                    self.serial,
-                    "FATAL: Failed to flash{} image during recovery mode.",
-                    if golden { " golden" } else { "" },
-                );
-                panic!();
-            }
-            match R::image_at(&mut self.mcu_flash, *bank) {
-                Ok(image) if golden && !image.is_golden() => {
-                    duprintln!(self.serial, "FATAL: Flashed image is not a golden image.");
-                    Err(Error::ImageIsNotGolden)
-                }
-                Err(e) => Err(e),
-                _ => Ok(()),
-            }
+            let result = receive_recovered_image::<R, T, _, _, _>(
+                self.serial.as_mut().unwrap(),
+                &mut self.status_led,
+                self.recovery_protocol,
+                self.recovery_timeout,
+                &mut self.mcu_flash,
+                *bank,
+                golden,
+            );
+            self.report_recovered_image(result)
         } else {
             Err(Error::NoGoldenBankSupport)
         }
@@ -121,34 +120,198 @@ impl<
         if let Some(bank) = self.external_banks.iter().find(|b| b.is_golden == golden) {
             duprintln!(
                 self.serial,
-                "Please send{} firmware image via XMODEM.",
-                if golden { " golden" } else { "" }
+                "Please send{} firmware image via {}.",
+                if golden { " golden" } else { "" },
+                self.recovery_protocol.name()
             );
-            let blocks = self.serial.as_mut().unwrap().blocks(None);
-            if self
-                .external_flash
-                .as_mut()
-                .unwrap()
-                .write_from_blocks(bank.location, blocks)
-                .is_err()
-            {
-                duprintln!(
-                    self.serial,
-                    "FATAL: Failed to flash{} image during recovery mode.",
-                    if golden { " golden" } else { "" },
-                );
-                panic!();
+            let result = receive_recovered_image::<R, T, _, _, _>(
+                self.serial.as_mut().unwrap(),
+                &mut self.status_led,
+                self.recovery_protocol,
+                self.recovery_timeout,
+                self.external_flash.as_mut().unwrap(),
+                bank,
+                golden,
+            );
+            self.report_recovered_image(result)
+        } else {
+            Err(Error::NoGoldenBankSupport)
+        }
+    }
+
+    fn report_recovered_image<A: Address>(
+        &mut self,
+        result: Result<Image<A>, Error>,
+    ) -> Result<(), Error> {
+        match result {
+            Err(Error::ImageIsNotGolden) => {
+                duprintln!(self.serial, "FATAL: Flashed image is not a golden image.");
+                Err(Error::ImageIsNotGolden)
             }
-            match R::image_at(self.external_flash.as_mut().unwrap(), *bank) {
-                Ok(image) if golden && !image.is_golden() => {
-                    duprintln!(self.serial, "FATAL: Flashed image is not a golden image.");
-                    Err(Error::ImageIsNotGolden)
+            Err(e) => Err(e),
+            Ok(_) => Ok(()),
+        }
+    }
+}
+
+/// Bounds a recovery session, so a device left in recovery with no host attached
+/// doesn't wait for a transfer forever.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RecoveryTimeout {
+    /// Time in milliseconds, from the start of the session, after which no transfer
+    /// is waited on any longer and the session times out.
+    pub timeout_ms: u32,
+    /// Reboot once the session times out, to retry a normal boot in case the situation
+    /// changed (e.g. a transient flash fault cleared). Otherwise, a new session starts.
+    pub reboot: bool,
+}
+
+/// Retries allowed per block within a session bounded by a [`RecoveryTimeout`]. The
+/// transfer is requested again until the deadline if it doesn't start within them.
+const RECOVERY_RETRIES: u32 = 10;
+
+/// Receives an image through the given protocol, ticking the status LED (if any) with
+/// every block, and stores it in a bank. YMODEM transfers announcing an image larger
+/// than the bank are refused before anything is written. With a `timeout`, fails with
+/// [`Error::RecoveryTimedOut`] if no transfer started before the deadline.
+fn receive_recovered_image<R, T, F, S, LED>(
+    serial: &mut S,
+    status_led: &mut Option<StatusLed<LED>>,
+    protocol: Protocol,
+    timeout: Option<RecoveryTimeout>,
+    flash: &mut F,
+    bank: Bank<F::Address>,
+    golden: bool,
+) -> Result<Image<F::Address>, Error>
+where
+    R: image::Reader,
+    T: time::Now,
+    F: Flash,
+    S: Serial,
+    LED: led::Toggle,
+{
+    let transfer_started = Cell::new(false);
+    let mut tick = |_: &[u8; BLOCK_SIZE]| {
+        transfer_started.set(true);
+        if let Some(status_led) = status_led.as_mut() {
+            status_led.tick();
+        }
+    };
+    let mut serial =
+        SessionSerial::<_, T>::new(serial, timeout.map(|t| t.timeout_ms), &transfer_started);
+    // Without a timeout, the transfer is waited on indefinitely.
+    let max_retries = timeout.map(|_| RECOVERY_RETRIES);
+    loop {
+        match protocol {
+            Protocol::XModem => {
+                let mut blocks = serial.blocks(max_retries).inspect(&mut tick).peekable();
+                if max_retries.is_none() || blocks.peek().is_some() {
+                    return store_recovered_image::<R, _, _, BLOCK_SIZE>(
+                        flash, bank, blocks, golden,
+                    );
                 }
-                Err(e) => Err(e),
-                _ => Ok(()),
             }
-        } else {
-            Err(Error::NoGoldenBankSupport)
+            Protocol::YModem => match serial.ymodem(max_retries, bank.size) {
+                Ok(blocks) => {
+                    transfer_started.set(true);
+                    let blocks = blocks.inspect(&mut tick);
+                    return store_recovered_image::<R, _, _, BLOCK_SIZE>(
+                        flash, bank, blocks, golden,
+                    );
+                }
+                Err(e) if max_retries.is_none() || e == Error::ImageTooLargeForBank => {
+                    return Err(e)
+                }
+                Err(_) => {}
+            },
+        }
+        if serial.expired() {
+            return Err(Error::RecoveryTimedOut);
+        }
+    }
+}
+
+/// Serial that stops delivering bytes once its session times out, so a session doesn't
+/// wait for a transfer past its deadline. The deadline only bounds the wait for the first
+/// block: once `transfer_started` is set, the transfer is never cut short.
+struct SessionSerial<'a, S, T: time::Now> {
+    serial: &'a mut S,
+    start: T::I,
+    timeout_ms: Option<u32>,
+    transfer_started: &'a Cell<bool>,
+}
+
+impl<'a, S, T: time::Now> SessionSerial<'a, S, T> {
+    fn new(serial: &'a mut S, timeout_ms: Option<u32>, transfer_started: &'a Cell<bool>) -> Self {
+        Self { serial, start: T::now(), timeout_ms, transfer_started }
+    }
+
+    fn expired(&self) -> bool {
+        !self.transfer_started.get()
+            && self.timeout_ms.map_or(false, |timeout_ms| (T::now() - self.start).0 >= timeout_ms)
+    }
+}
+
+impl<'a, S: Serial, T: time::Now> serial::TimeoutRead for SessionSerial<'a, S, T> {
+    type Error = Error;
+
+    fn read<U: Copy + Into<time::Milliseconds>>(&mut self, timeout: U) -> Result<u8, Self::Error> {
+        if self.expired() {
+            return Err(Error::RecoveryTimedOut);
         }
+        Ok(serial::TimeoutRead::read(self.serial, timeout)?)
+    }
+}
+
+impl<'a, S: Serial, T: time::Now> serial::Write for SessionSerial<'a, S, T> {
+    type Error = <S as serial::Write>::Error;
+    fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
+        serial::Write::write_str(self.serial, s)
+    }
+    fn write_char(&mut self, c: char) -> Result<(), Self::Error> {
+        serial::Write::write_char(self.serial, c)
+    }
+}
+
+/// Writes an image received in blocks (e.g. through XMODEM or YMODEM) to a bank, then verifies
+/// it, requiring it to be golden if `golden` is set. This is shared between Loadstone's
+/// automatic recovery mode and the boot manager's on-demand `recover` command.
+pub fn store_recovered_image<R, F, I, const N: usize>(
+    flash: &mut F,
+    bank: Bank<F::Address>,
+    blocks: I,
+    golden: bool,
+) -> Result<Image<F::Address>, Error>
+where
+    R: image::Reader,
+    F: Flash,
+    I: Iterator<Item = [u8; N]>,
+{
+    write_blocks_within_bank(flash, bank, blocks)?;
+    match R::image_at(flash, bank) {
+        Ok(image) if golden && !image.is_golden() => Err(Error::ImageIsNotGolden),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::doubles::ScriptedSerial;
+    use blue_hal::hal::{doubles::time::MockSysTick, serial::TimeoutRead};
+
+    #[test]
+    fn session_deadline_stops_applying_once_a_transfer_started() {
+        let mut serial = ScriptedSerial { incoming: vec![0x01, 0x02].into(), ..Default::default() };
+        let transfer_started = Cell::new(false);
+        let mut session =
+            SessionSerial::<_, MockSysTick>::new(&mut serial, Some(0), &transfer_started);
+        assert!(session.expired());
+        assert_eq!(Err(Error::RecoveryTimedOut), session.read(time::Milliseconds(10)));
+
+        // Past the deadline, a transfer already underway carries on.
+        transfer_started.set(true);
+        assert!(!session.expired());
+        assert_eq!(Ok(0x01), session.read(time::Milliseconds(10)));
     }
 }
@@ -0,0 +1,100 @@
+use super::*;
+use crate::devices::update_signal::{ReadUpdateSignal, WriteUpdateSignal};
+use blue_hal::utilities::memory::Address;
+
+/// Why the bootloader settled on the image it is about to boot, or on recovery.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BootReason {
+    /// The boot bank holds a valid image, and no bank holds a newer one.
+    UpToDate,
+    /// A bank held a newer image, which replaced the one in the boot bank.
+    UpdateFound,
+    /// The boot bank held no valid image, so one was restored from a regular bank.
+    NoCurrentImage,
+    /// No regular bank held a valid image either, so a golden image was restored.
+    GoldenFallback,
+    /// No bank held a valid image, leaving serial recovery as the only way forward.
+    NoImage,
+}
+
+/// Outcome of the bootloader's decision process, before any attempt to boot.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BootReport<A: Address> {
+    /// Image left in the boot bank, ready to boot, if any.
+    pub image: Option<Image<A>>,
+    /// Index of the bank the image came from. This is the boot bank itself unless the
+    /// image was updated or restored from another bank.
+    pub chosen_bank: Option<u8>,
+    /// How the image ended up in the boot bank, if there is one.
+    pub path: Option<BootPath>,
+    /// Why this image, or recovery, was settled on.
+    pub reason: BootReason,
+    /// Number of banks a restore was attempted from, including the one that succeeded.
+    pub fallbacks_tried: u8,
+}
+
+impl<A: Address> BootReport<A> {
+    /// Whether the image had to be restored, as the boot bank held no valid one.
+    pub fn is_restore(&self) -> bool { matches!(self.path, Some(BootPath::Restored { .. })) }
+}
+
+impl<
+        EXTF: Flash,
+        MCUF: Flash,
+        SRL: Serial,
+        T: time::Now,
+        R: image::Reader,
+        RUS: ReadUpdateSignal + WriteUpdateSignal,
+        LED: led::Toggle,
+        PIN: gpio::InputPin,
+    > Bootloader<EXTF, MCUF, SRL, T, R, RUS, LED, PIN>
+{
+    /// Decides which image to boot, updating or restoring the boot bank as needed, but
+    /// stops short of booting it. [`Self::run`] acts on the report.
+    pub fn decide(&mut self) -> BootReport<MCUF::Address> {
+        let boot_bank = self.boot_bank();
+        if let Some(image) = self.latest_bootable_image() {
+            let path = self.boot_metrics.boot_path;
+            let (chosen_bank, reason) = match path {
+                BootPath::Updated { bank } => (bank, BootReason::UpdateFound),
+                _ => (boot_bank.index, BootReason::UpToDate),
+            };
+            return BootReport {
+                image: Some(image),
+                chosen_bank: Some(chosen_bank),
+                path: Some(path),
+                reason,
+                fallbacks_tried: 0,
+            };
+        }
+
+        let mut fallbacks_tried = 0;
+        match self.restore_counting(&mut fallbacks_tried) {
+            Ok(image) => {
+                let path = self.boot_metrics.boot_path;
+                let chosen_bank = match path {
+                    BootPath::Restored { bank } => bank,
+                    _ => boot_bank.index,
+                };
+                BootReport {
+                    image: Some(image),
+                    chosen_bank: Some(chosen_bank),
+                    path: Some(path),
+                    reason: if image.is_golden() {
+                        BootReason::GoldenFallback
+                    } else {
+                        BootReason::NoCurrentImage
+                    },
+                    fallbacks_tried,
+                }
+            }
+            Err(_) => BootReport {
+                image: None,
+                chosen_bank: None,
+                path: None,
+                reason: BootReason::NoImage,
+                fallbacks_tried,
+            },
+        }
+    }
+}
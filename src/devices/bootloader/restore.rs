@@ -1,5 +1,5 @@
 use super::*;
-use crate::devices::update_signal::ReadUpdateSignal;
+use crate::devices::update_signal::{ReadUpdateSignal, WriteUpdateSignal};
 
 impl<
         EXTF: Flash,
@@ -7,22 +7,37 @@ impl<
         SRL: Serial,
         T: time::Now,
         R: image::Reader,
-        RUS: ReadUpdateSignal,
-    > Bootloader<EXTF, MCUF, SRL, T, R, RUS>
+        RUS: ReadUpdateSignal + WriteUpdateSignal,
+        LED: led::Toggle,
+        PIN: gpio::InputPin,
+    > Bootloader<EXTF, MCUF, SRL, T, R, RUS, LED, PIN>
 {
     /// Restores the first image available in all banks, attempting to restore
-    /// from the golden image as a last resort.
+    /// from golden images as a last resort. Golden banks are tried in order, MCU
+    /// banks first and external banks after, until one holds a valid golden image.
+    /// A bank that fails to read, copy or verify is skipped in favour of the next.
     pub fn restore(&mut self) -> Result<Image<MCUF::Address>, Error> {
-        self.restore_internal(false)
-            .or_else(|| self.restore_external(false))
-            .or_else(|| self.restore_internal(true))
-            .or_else(|| self.restore_external(true))
+        self.restore_counting(&mut 0)
+    }
+
+    /// Restores like [`Self::restore`], counting the banks a restore is attempted from.
+    pub(super) fn restore_counting(
+        &mut self,
+        tried: &mut u8,
+    ) -> Result<Image<MCUF::Address>, Error> {
+        self.restore_internal(false, tried)
+            .or_else(|| self.restore_external(false, tried))
+            .or_else(|| self.restore_internal(true, tried))
+            .or_else(|| self.restore_external(true, tried))
             .ok_or(Error::NoImageToRestoreFrom)
     }
 
-    fn restore_external(&mut self, golden: bool) -> Option<Image<MCUF::Address>> {
+    fn restore_external(&mut self, golden: bool, tried: &mut u8) -> Option<Image<MCUF::Address>> {
         let output = self.boot_bank();
+        self.external_flash.as_ref()?;
         for input_bank in self.external_banks.iter().filter(|b| b.is_golden == golden) {
+            self.tick_status_led();
+            *tried += 1;
             duprintln!(
                 self.serial,
                 "Attempting to restore from{} bank {:?}.",
@@ -36,6 +51,7 @@ impl<
                 *input_bank,
                 output,
                 golden,
+                self.verify_writes,
             )
             .is_err()
             {
@@ -49,29 +65,35 @@ impl<
                 EXTF::label()
             );
             duprintln!(self.serial, "Verifying the image again in the boot bank...");
-            self.boot_metrics.boot_path = BootPath::Restored { bank: input_bank.index };
-            return R::image_at(&mut self.mcu_flash, output).ok();
+            if let Ok(image) = R::image_at(&mut self.mcu_flash, output) {
+                self.boot_metrics.boot_path = BootPath::Restored { bank: input_bank.index };
+                return Some(image);
+            }
+            log!(self, Warn, "Restored image failed verification. Trying the next bank...");
         }
         None
     }
 
-    fn restore_internal(&mut self, golden: bool) -> Option<Image<MCUF::Address>> {
+    fn restore_internal(&mut self, golden: bool, tried: &mut u8) -> Option<Image<MCUF::Address>> {
         let output = self.boot_bank();
         for input_bank in
             self.mcu_banks.iter().filter(|b| b.is_golden == golden && b.index != output.index)
         {
+            self.tick_status_led();
+            *tried += 1;
             duprintln!(
                 self.serial,
                 "Attempting to restore from{} bank {:?}.",
                 if golden { " golden" } else { "" },
                 input_bank.index
             );
-            if Self::copy_image_single_flash(
+            if copy_image_single_flash::<R, _, _>(
                 &mut self.serial,
                 &mut self.mcu_flash,
                 *input_bank,
                 output,
                 golden,
+                self.verify_writes,
             )
             .is_err()
             {
@@ -85,8 +107,11 @@ impl<
                 MCUF::label()
             );
             duprintln!(self.serial, "Verifying the image again in the boot bank...");
-            self.boot_metrics.boot_path = BootPath::Restored { bank: input_bank.index };
-            return R::image_at(&mut self.mcu_flash, output).ok();
+            if let Ok(image) = R::image_at(&mut self.mcu_flash, output) {
+                self.boot_metrics.boot_path = BootPath::Restored { bank: input_bank.index };
+                return Some(image);
+            }
+            log!(self, Warn, "Restored image failed verification. Trying the next bank...");
         }
         None
     }
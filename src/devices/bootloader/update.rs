@@ -1,5 +1,263 @@
 use super::*;
-use crate::devices::update_signal::{ReadUpdateSignal, UpdatePlan};
+use crate::devices::{
+    file_transfer::{FileTransfer, BLOCK_SIZE},
+    image::erase_bank,
+    update_signal::{ReadUpdateSignal, UpdatePlan, WriteUpdateSignal},
+    wear_leveling::{self, Ring},
+};
+use blue_hal::utilities::memory::Address;
+
+/// Retries allowed per block while receiving a serial update, so a missing sender
+/// doesn't hold the boot for long.
+pub const SERIAL_UPDATE_MAX_RETRIES: u32 = 10;
+
+/// Standing of a bank as a source of updates for the current bootable image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Candidacy {
+    /// Golden banks are never updated from, unless the golden override is enabled.
+    Golden,
+    /// The update signal restricts updates to a different bank.
+    NotTargeted,
+    /// The bank backs up a previously confirmed image, and is only updated from when
+    /// the update signal targets it.
+    Backup,
+    /// The bank holds no valid image.
+    NoImage,
+    /// The bank holds a valid image flagged to never be updated from.
+    NoAutoUpdate,
+    /// The bank holds the same image as the bootable bank.
+    Current,
+    /// The bank holds a valid image different from the one in the bootable bank.
+    Newer,
+}
+
+impl Candidacy {
+    /// Whether this bank ends the search for an update. Banks are considered in
+    /// order, and the first one holding a valid image decides the outcome.
+    pub fn is_decisive(&self) -> bool { matches!(self, Candidacy::Current | Candidacy::Newer) }
+
+    /// Human readable explanation of the candidacy.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Candidacy::Golden => "Golden bank (golden banks can't be updated from).",
+            Candidacy::NotTargeted => "Skipped (update signal was set to a different bank).",
+            Candidacy::Backup => "Backup bank (only updated from when explicitly targeted).",
+            Candidacy::NoImage => "No valid image.",
+            Candidacy::NoAutoUpdate => "Image is flagged to never be updated from.",
+            Candidacy::Current => "Same image as the bootable bank, no update needed.",
+            Candidacy::Newer => "Valid image different from the bootable one.",
+        }
+    }
+}
+
+/// Decides the candidacy of a bank as an update source. `scan` retrieves the identifier
+/// of the image in the bank, if valid, along with whether the image is flagged as
+/// `no_auto_update`. It is only invoked when the bank is eligible. With `golden_override`,
+/// golden banks are eligible like any other.
+pub fn candidacy<A: Address, I: PartialEq, S: FnOnce() -> Option<(I, bool)>>(
+    bank: &Bank<A>,
+    target_bank: Option<u8>,
+    backup_bank: Option<u8>,
+    golden_override: bool,
+    current: &I,
+    scan: S,
+) -> Candidacy {
+    if bank.is_golden && !golden_override {
+        Candidacy::Golden
+    } else if target_bank.map(|t| t != bank.index).unwrap_or(false) {
+        Candidacy::NotTargeted
+    } else if target_bank.is_none() && backup_bank == Some(bank.index) {
+        Candidacy::Backup
+    } else {
+        match scan() {
+            None => Candidacy::NoImage,
+            Some((identifier, _)) if identifier == *current => Candidacy::Current,
+            Some((_, true)) => Candidacy::NoAutoUpdate,
+            Some((_, false)) => Candidacy::Newer,
+        }
+    }
+}
+
+/// Picks the bank to update from, given bank candidacies in scan order. The first bank
+/// holding a valid image wins, so later banks are never considered (or scanned) after
+/// a bank holding the current image.
+pub fn select_update<I: Iterator<Item = (u8, Candidacy)>>(candidacies: I) -> Option<u8> {
+    decide_update(candidacies).bank()
+}
+
+/// Whether a bank is considered in the golden update pass, or in the regular one. With
+/// the golden override, golden banks get a pass of their own after every regular bank,
+/// so they only replace the current image when no regular bank holds a valid one ahead
+/// of them. Otherwise, there is no golden pass, and golden banks show up in the regular
+/// pass only to be skipped.
+pub fn in_update_pass<A: Address>(
+    bank: &Bank<A>,
+    golden_pass: bool,
+    golden_override: bool,
+) -> bool {
+    if golden_override {
+        bank.is_golden == golden_pass
+    } else {
+        !golden_pass
+    }
+}
+
+/// Outcome of Loadstone's update selection, telling apart why no update is performed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UpdateDecision {
+    /// The update signal forbids updating, or requests recovery instead.
+    Refused,
+    /// The update signal requests an update over serial, received while booting.
+    Serial,
+    /// The boot bank is updated from the bank with this index.
+    UpdateFrom(u8),
+    /// A bank holding the current image was found before any newer one.
+    UpToDate,
+    /// No bank holds an image to update from.
+    NoCandidates,
+}
+
+impl UpdateDecision {
+    /// Index of the bank to update from, if an update is performed.
+    pub fn bank(&self) -> Option<u8> {
+        match self {
+            UpdateDecision::UpdateFrom(index) => Some(*index),
+            _ => None,
+        }
+    }
+
+    /// Human readable explanation of the decision.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            UpdateDecision::Refused => "The update signal forbids updating.",
+            UpdateDecision::Serial => "The update signal requests an update over serial.",
+            UpdateDecision::UpdateFrom(_) => "A bank holds a newer image.",
+            UpdateDecision::UpToDate => "A bank holds the current image ahead of any newer one.",
+            UpdateDecision::NoCandidates => "No bank holds an image to update from.",
+        }
+    }
+}
+
+/// Bank an update plan restricts updates to (`None` meaning any bank), or the decision
+/// the plan settles on its own, before any bank is scanned.
+pub fn update_target(plan: Option<UpdatePlan>) -> Result<Option<u8>, UpdateDecision> {
+    match plan {
+        None | Some(UpdatePlan::Any) => Ok(None),
+        Some(UpdatePlan::Index(bank)) | Some(UpdatePlan::TestBoot { bank, .. }) => Ok(Some(bank)),
+        Some(UpdatePlan::Serial) => Err(UpdateDecision::Serial),
+        Some(UpdatePlan::None) | Some(UpdatePlan::Recovery { .. }) => Err(UpdateDecision::Refused),
+    }
+}
+
+/// Decides the update given bank candidacies in scan order, like [`select_update`].
+pub fn decide_update<I: Iterator<Item = (u8, Candidacy)>>(mut candidacies: I) -> UpdateDecision {
+    match candidacies.find(|(_, candidacy)| candidacy.is_decisive()) {
+        Some((index, Candidacy::Newer)) => UpdateDecision::UpdateFrom(index),
+        Some(_) => UpdateDecision::UpToDate,
+        None => UpdateDecision::NoCandidates,
+    }
+}
+
+/// Receives an update in blocks (e.g. through XMODEM) into a staging bank, verifies it
+/// and marks it for promotion. The staging bank is erased first, so no marker is left over
+/// from an earlier update, and again if the image is rejected.
+pub fn stage_update<R, F, I, const N: usize>(
+    flash: &mut F,
+    staging_bank: Bank<F::Address>,
+    blocks: I,
+) -> Result<Image<F::Address>, Error>
+where
+    R: image::Reader,
+    F: Flash,
+    I: Iterator<Item = [u8; N]>,
+{
+    erase_bank(flash, staging_bank)?;
+    store_recovered_image::<R, _, _, N>(flash, staging_bank, blocks, false)
+        .and_then(|image| mark_for_promotion(flash, staging_bank).map(|_| image))
+        .or_else(|e| {
+            erase_bank(flash, staging_bank)?;
+            Err(e)
+        })
+}
+
+/// Copy-in-progress marker, written over the last bytes of a staging bank once the image
+/// staged in it is verified. It's erased along with the image once promoted, so only images
+/// staged by a serial update are ever promoted.
+pub const PROMOTION_MARKER: [u8; 4] = *b"PRMT";
+
+/// Marks the verified image in a staging bank for promotion. Images reaching into the
+/// marker's bytes are refused.
+fn mark_for_promotion<F: Flash>(flash: &mut F, bank: Bank<F::Address>) -> Result<(), Error> {
+    let offset = bank.size - PROMOTION_MARKER.len();
+    if read_marker(flash, bank)?.iter().any(|byte| *byte != 0xFF) {
+        return Err(Error::ImageTooLargeForBank);
+    }
+    write_within_bank(flash, bank, offset, &PROMOTION_MARKER)
+}
+
+/// Whether the image in a staging bank was marked for promotion.
+fn marked_for_promotion<F: Flash>(flash: &mut F, bank: Bank<F::Address>) -> bool {
+    matches!(read_marker(flash, bank), Ok(marker) if marker == PROMOTION_MARKER)
+}
+
+fn read_marker<F: Flash>(
+    flash: &mut F,
+    bank: Bank<F::Address>,
+) -> Result<[u8; PROMOTION_MARKER.len()], Error> {
+    let mut marker = [0u8; PROMOTION_MARKER.len()];
+    block!(flash.read(bank.location + (bank.size - marker.len()), &mut marker))?;
+    Ok(marker)
+}
+
+/// Whether serial updates are staged in a bank, either as the staging bank or as one
+/// taking turns with it. Staged images only reach the boot bank through promotion, so
+/// staging banks are never scanned for updates, nor written by the application.
+pub fn is_staging_bank(index: u8, staging_bank: Option<u8>, staging_rotation: &[u8]) -> bool {
+    staging_bank == Some(index) || staging_rotation.contains(&index)
+}
+
+/// Size in bytes of the wear leveled region recording the staging rotation.
+pub const STAGING_ROTATION_REGION_SIZE: usize = 32 * wear_leveling::ENTRY_SIZE;
+
+/// MCU banks taking turns to receive serial updates, so frequent updates don't wear out
+/// a single staging bank. The bank each update is staged in is recorded in a wear leveled
+/// region, so the rotation carries on across resets.
+#[derive(Copy, Clone, Debug)]
+pub struct StagingRotation<A: Address> {
+    /// Indices of the banks in rotation, in turn order.
+    pub banks: &'static [u8],
+    /// Start of the region recording the last bank an update was staged in.
+    pub state: A,
+}
+
+impl<A: Address> StagingRotation<A> {
+    fn ring(&self) -> Ring<A, STAGING_ROTATION_REGION_SIZE> { Ring::new(self.state) }
+
+    /// Index of the bank the last update was staged in, if any was recorded.
+    pub fn current<F: Flash<Address = A>>(&self, flash: &mut F) -> Result<Option<u8>, Error> {
+        let recorded = self.ring().read(flash)?;
+        Ok(recorded.map(|index| index as u8).filter(|index| self.banks.contains(index)))
+    }
+
+    /// Index of the bank whose turn it is, which is the least recently staged in.
+    pub fn next<F: Flash<Address = A>>(&self, flash: &mut F) -> Result<u8, Error> {
+        next_in_rotation(self.banks, self.current(flash)?)
+            .ok_or(Error::DeviceError("Staging rotation holds no banks"))
+    }
+
+    /// Records an update staged in a bank, passing the turn to the one after it.
+    pub fn record<F: Flash<Address = A>>(&self, flash: &mut F, index: u8) -> Result<(), Error> {
+        self.ring().write(flash, index as u32)
+    }
+}
+
+/// Bank following `last` in a rotation, wrapping around after the last one. The rotation
+/// starts from the first bank if there is no record of the last one.
+pub fn next_in_rotation(banks: &[u8], last: Option<u8>) -> Option<u8> {
+    let position = last.and_then(|last| banks.iter().position(|b| *b == last));
+    let next = position.map_or(0, |p| (p + 1) % banks.len());
+    banks.get(next).copied()
+}
 
 enum UpdateResult<MCUF: Flash> {
     AlreadyUpToDate(Image<MCUF::Address>),
@@ -14,59 +272,84 @@ impl<
         SRL: Serial,
         T: time::Now,
         R: image::Reader,
-        RUS: ReadUpdateSignal,
-    > Bootloader<EXTF, MCUF, SRL, T, R, RUS>
+        RUS: ReadUpdateSignal + WriteUpdateSignal,
+        LED: led::Toggle,
+        PIN: gpio::InputPin,
+    > Bootloader<EXTF, MCUF, SRL, T, R, RUS, LED, PIN>
 {
     /// If the current bootable (MCU flash) image is different from the top
     /// non-golden image, attempts to replace it. On failure, this process
-    /// is repeated for all non-golden banks. Returns the current
-    /// bootable image after the process, if available.
+    /// is repeated for all non-golden banks. With the golden override, golden
+    /// banks are then tried the same way, if no regular bank settled the update.
+    /// Returns the current bootable image after the process, if available.
     pub fn latest_bootable_image(&mut self) -> Option<Image<MCUF::Address>> {
         let boot_bank = self.boot_bank();
-        let current_image = if let Ok(image) = R::image_at(&mut self.mcu_flash, boot_bank) {
+        if let Some(promoted_image) = self.complete_pending_promotion(boot_bank) {
+            return Some(promoted_image);
+        }
+        let current_image = if let Ok(image) = self.boot_image(boot_bank) {
             image
         } else {
             duprintln!(self.serial, "No current image.");
             return None;
         };
 
-        let bank: Option<u8> = match self
-            .update_signal
-            .as_ref()
-            .map(ReadUpdateSignal::read_update_plan)
-        {
-            None => None,
-            Some(UpdatePlan::None) => {
-                duprintln!(self.serial, "Update signal set to None, refusing to update.");
-                return Some(current_image);
-            }
-            Some(UpdatePlan::Any) => {
-                duprintln!(self.serial, "Update signal set to Any, checking for image updates.");
-                None
-            }
-            Some(UpdatePlan::Index(i)) => {
-                duprintln!(
-                    self.serial,
-                    "Update signal set to Index({}), checking for update in \
-                    that bank.",
-                    i
-                );
-                Some(i)
+        let plan = self.update_signal.as_ref().map(ReadUpdateSignal::read_update_plan);
+        if let Some(plan) = plan {
+            self.report_update_plan(plan);
+        }
+        if let Some(UpdatePlan::TestBoot { .. }) = plan {
+            self.end_one_shot_request();
+        }
+        let bank = match update_target(plan) {
+            Ok(bank) => bank,
+            Err(UpdateDecision::Serial) => {
+                return self.attempt_serial_update(boot_bank, current_image);
             }
+            Err(_) => return Some(current_image),
         };
 
-        let current_image = match self.update_internal(boot_bank, current_image, bank) {
-            UpdateResult::NotUpdated(current_image) => current_image,
-            UpdateResult::AlreadyUpToDate(current_image) => return Some(current_image),
-            UpdateResult::UpdatedTo(new_image) => return Some(new_image),
-            UpdateResult::UpdateError => return None,
-        };
+        let passes: &[bool] = if self.golden_override { &[false, true] } else { &[false] };
+        let mut current_image = current_image;
+        for golden in passes.iter().copied() {
+            current_image = match self.update_internal(boot_bank, current_image, bank, golden) {
+                UpdateResult::NotUpdated(current_image) => current_image,
+                UpdateResult::AlreadyUpToDate(current_image) => return Some(current_image),
+                UpdateResult::UpdatedTo(new_image) => return Some(new_image),
+                UpdateResult::UpdateError => return None,
+            };
 
-        match self.update_external(boot_bank, current_image, bank) {
-            UpdateResult::NotUpdated(current_image) => Some(current_image),
-            UpdateResult::AlreadyUpToDate(current_image) => Some(current_image),
-            UpdateResult::UpdatedTo(new_image) => Some(new_image),
-            UpdateResult::UpdateError => None,
+            current_image = match self.update_external(boot_bank, current_image, bank, golden) {
+                UpdateResult::NotUpdated(current_image) => current_image,
+                UpdateResult::AlreadyUpToDate(current_image) => return Some(current_image),
+                UpdateResult::UpdatedTo(new_image) => return Some(new_image),
+                UpdateResult::UpdateError => return None,
+            };
+        }
+        Some(current_image)
+    }
+
+    fn report_update_plan(&mut self, plan: UpdatePlan) {
+        match plan {
+            UpdatePlan::None => {
+                duprintln!(self.serial, "Update signal set to None, refusing to update.")
+            }
+            UpdatePlan::Any => {
+                duprintln!(self.serial, "Update signal set to Any, checking for image updates.")
+            }
+            UpdatePlan::Serial => (),
+            UpdatePlan::Index(i) => duprintln!(
+                self.serial,
+                "Update signal set to Index({}), checking for update in \
+                that bank.",
+                i
+            ),
+            UpdatePlan::TestBoot { bank, .. } => {
+                duprintln!(self.serial, "Test boot requested from bank {}.", bank)
+            }
+            UpdatePlan::Recovery { .. } => {
+                duprintln!(self.serial, "Recovery requested, refusing to update.")
+            }
         }
     }
 
@@ -75,49 +358,82 @@ impl<
         boot_bank: Bank<MCUF::Address>,
         current_image: Image<MCUF::Address>,
         target_bank: Option<u8>,
+        golden_pass: bool,
     ) -> UpdateResult<MCUF> {
-        for bank in self.mcu_banks().filter(|b| b.index != boot_bank.index) {
-            if bank.is_golden {
-                duprintln!(
+        let mut replacement_failed = false;
+        let golden_override = self.golden_override;
+        let staging_rotation = self.staging_rotation.map_or(&[][..], |rotation| rotation.banks);
+        let staging_bank = self.staging_bank;
+        for bank in self.mcu_banks().filter(|b| {
+            b.index != boot_bank.index
+                && !is_staging_bank(b.index, staging_bank, staging_rotation)
+                && in_update_pass(b, golden_pass, golden_override)
+        }) {
+            self.tick_status_led();
+            let (serial, flash) = (&mut self.serial, &mut self.mcu_flash);
+            let candidacy = candidacy(
+                &bank,
+                target_bank,
+                self.backup_bank,
+                golden_override,
+                &current_image.identifier(),
+                || {
+                    duprintln!(
+                        serial,
+                        "[{}] Scanning bank {:?} for a newer image...",
+                        MCUF::label(),
+                        bank.index
+                    );
+                    scan_bank::<R, _, _>(serial, flash, bank)
+                        .ok()
+                        .filter(|image| Self::replaceable_by(flash, image))
+                        .map(|image| (image.identifier(), image.no_auto_update()))
+                },
+            );
+
+            match candidacy {
+                Candidacy::Golden => duprintln!(
                     self.serial,
                     "[{}] Skipping golden bank {:?} (Golden banks can't be updated from)...",
                     MCUF::label(),
                     bank.index
-                );
-                continue;
-            }
-
-            let skip_nontarget_bank = target_bank.map(|t| t != bank.index).unwrap_or(false);
-            if skip_nontarget_bank {
-                duprintln!(
+                ),
+                Candidacy::NotTargeted => duprintln!(
                     self.serial,
                     "[{}] Skipping bank {:?} (Update signal was set to a bank index)...",
                     MCUF::label(),
                     bank.index
-                );
-                continue;
-            }
-
-            duprintln!(
-                self.serial,
-                "[{}] Scanning bank {:?} for a newer image...",
-                MCUF::label(),
-                bank.index
-            );
-            match R::image_at(&mut self.mcu_flash, bank) {
-                Ok(image) if image.identifier() != current_image.identifier() => {
+                ),
+                Candidacy::Backup => duprintln!(
+                    self.serial,
+                    "[{}] Skipping backup bank {:?} (Update signal doesn't target it)...",
+                    MCUF::label(),
+                    bank.index
+                ),
+                Candidacy::NoImage => (),
+                Candidacy::NoAutoUpdate => duprintln!(
+                    self.serial,
+                    "[{}] Skipping bank {:?} (Image is flagged to never be updated from)...",
+                    MCUF::label(),
+                    bank.index
+                ),
+                Candidacy::Current => {
+                    return self
+                        .recheck_boot_bank(boot_bank, current_image, replacement_failed)
+                        .map_or(UpdateResult::UpdateError, UpdateResult::AlreadyUpToDate);
+                }
+                Candidacy::Newer => {
                     if let Some(updated_image) = self.replace_image_internal(bank, boot_bank) {
                         self.boot_metrics.boot_path = BootPath::Updated { bank: bank.index };
                         return UpdateResult::UpdatedTo(updated_image);
-                    } else {
-                        return UpdateResult::UpdateError;
                     }
+                    log!(self, Warn, "Failed to update from bank. Trying the next one...");
+                    replacement_failed = true;
                 }
-                Ok(_image) => return UpdateResult::AlreadyUpToDate(current_image),
-                _ => (),
             }
         }
-        return UpdateResult::NotUpdated(current_image);
+        self.recheck_boot_bank(boot_bank, current_image, replacement_failed)
+            .map_or(UpdateResult::UpdateError, UpdateResult::NotUpdated)
     }
 
     fn update_external(
@@ -125,51 +441,233 @@ impl<
         boot_bank: Bank<MCUF::Address>,
         current_image: Image<MCUF::Address>,
         target_bank: Option<u8>,
+        golden_pass: bool,
     ) -> UpdateResult<MCUF> {
-        if self.external_flash.is_some() {
-            for bank in self.external_banks() {
-                if bank.is_golden {
-                    duprintln!(
-                        self.serial,
-                        "[{}] Skipping golden bank {:?} (Golden banks can't be updated from)...",
-                        MCUF::label(),
-                        bank.index
-                    );
-                    continue;
-                }
-
-                let skip_nontarget_bank = target_bank.map(|t| t != bank.index).unwrap_or(false);
-                if skip_nontarget_bank {
+        let mut replacement_failed = false;
+        let golden_override = self.golden_override;
+        for bank in
+            self.external_banks().filter(|b| in_update_pass(b, golden_pass, golden_override))
+        {
+            if self.external_flash.is_none() {
+                break;
+            }
+            self.tick_status_led();
+            let (serial, flash) = (&mut self.serial, self.external_flash.as_mut().unwrap());
+            let candidacy = candidacy(
+                &bank,
+                target_bank,
+                self.backup_bank,
+                golden_override,
+                &current_image.identifier(),
+                || {
                     duprintln!(
-                        self.serial,
-                        "[{}] Skipping bank {:?} (Update signal was set to a bank index)...",
-                        MCUF::label(),
+                        serial,
+                        "[{}] Scanning bank {:?} for a newer image...",
+                        EXTF::label(),
                         bank.index
                     );
-                    continue;
-                }
+                    scan_bank::<R, _, _>(serial, flash, bank)
+                        .ok()
+                        .filter(|image| Self::replaceable_by(flash, image))
+                        .map(|image| (image.identifier(), image.no_auto_update()))
+                },
+            );
 
-                duprintln!(
+            match candidacy {
+                Candidacy::Golden => duprintln!(
+                    self.serial,
+                    "[{}] Skipping golden bank {:?} (Golden banks can't be updated from)...",
+                    MCUF::label(),
+                    bank.index
+                ),
+                Candidacy::NotTargeted => duprintln!(
                     self.serial,
-                    "[{}] Scanning bank {:?} for a newer image...",
+                    "[{}] Skipping bank {:?} (Update signal was set to a bank index)...",
+                    MCUF::label(),
+                    bank.index
+                ),
+                Candidacy::Backup => duprintln!(
+                    self.serial,
+                    "[{}] Skipping backup bank {:?} (Update signal doesn't target it)...",
+                    EXTF::label(),
+                    bank.index
+                ),
+                Candidacy::NoImage => (),
+                Candidacy::NoAutoUpdate => duprintln!(
+                    self.serial,
+                    "[{}] Skipping bank {:?} (Image is flagged to never be updated from)...",
                     EXTF::label(),
                     bank.index
-                );
-                match R::image_at(self.external_flash.as_mut().unwrap(), bank) {
-                    Ok(image) if image.identifier() != current_image.identifier() => {
-                        if let Some(updated_image) = self.replace_image_external(bank, boot_bank) {
-                            self.boot_metrics.boot_path = BootPath::Updated { bank: bank.index };
-                            return UpdateResult::UpdatedTo(updated_image);
-                        } else {
-                            return UpdateResult::UpdateError;
-                        }
+                ),
+                Candidacy::Current => {
+                    return self
+                        .recheck_boot_bank(boot_bank, current_image, replacement_failed)
+                        .map_or(UpdateResult::UpdateError, UpdateResult::AlreadyUpToDate);
+                }
+                Candidacy::Newer => {
+                    if let Some(updated_image) = self.replace_image_external(bank, boot_bank) {
+                        self.boot_metrics.boot_path = BootPath::Updated { bank: bank.index };
+                        return UpdateResult::UpdatedTo(updated_image);
                     }
-                    Ok(_image) => return UpdateResult::AlreadyUpToDate(current_image),
-                    _ => (),
+                    log!(self, Warn, "Failed to update from bank. Trying the next one...");
+                    replacement_failed = true;
                 }
             }
         }
-        return UpdateResult::NotUpdated(current_image);
+        self.recheck_boot_bank(boot_bank, current_image, replacement_failed)
+            .map_or(UpdateResult::UpdateError, UpdateResult::NotUpdated)
+    }
+
+    /// Whether an image can be copied over the boot image as is. Compressed golden images
+    /// only expand when restored from, so they are never updated from, even with the
+    /// golden override: the expanded copy would never match them, and be replaced again
+    /// on every boot.
+    fn replaceable_by<F: Flash>(flash: &mut F, image: &Image<F::Address>) -> bool {
+        !image.is_golden() || matches!(super::copy::decompressed_size(flash, image), Ok(None))
+    }
+
+    /// Returns the current image, verifying the boot bank again first if a failed
+    /// replacement may have left it partially overwritten.
+    fn recheck_boot_bank(
+        &mut self,
+        boot_bank: Bank<MCUF::Address>,
+        current_image: Image<MCUF::Address>,
+        replacement_failed: bool,
+    ) -> Option<Image<MCUF::Address>> {
+        if replacement_failed {
+            R::image_at(&mut self.mcu_flash, boot_bank).ok()
+        } else {
+            Some(current_image)
+        }
+    }
+
+    /// Receives an image over serial into the staging bank, verifies it, and promotes it
+    /// to the boot bank. Returns the bootable image after the process, which is the current
+    /// one if the transfer or verification fail. Serial updates are refused if no staging
+    /// bank is configured, as receiving straight into the boot bank would leave the device
+    /// unbootable after an interrupted transfer. The request is one-shot: the update signal
+    /// is reset to [`UpdatePlan::Any`] before waiting for the image.
+    fn attempt_serial_update(
+        &mut self,
+        boot_bank: Bank<MCUF::Address>,
+        current_image: Image<MCUF::Address>,
+    ) -> Option<Image<MCUF::Address>> {
+        if self.serial.is_none() {
+            log!(self, Warn, "Update signal set to Serial, but serial is unavailable.");
+            return Some(current_image);
+        }
+        let staging_bank = if let Some(bank) = self.next_staging_bank() {
+            bank
+        } else {
+            log!(self, Error, "Serial updates require a staging bank, but none is configured.");
+            return Some(current_image);
+        };
+        if let Some(signal) = self.update_signal.as_mut() {
+            signal.write_update_plan(UpdatePlan::Any);
+        }
+
+        duprintln!(self.serial, "Update signal set to Serial. Please send image via XMODEM.");
+        let serial = self.serial.as_mut().unwrap();
+        let blocks = serial.blocks(Some(SERIAL_UPDATE_MAX_RETRIES));
+        if let Err(e) =
+            stage_update::<R, _, _, BLOCK_SIZE>(&mut self.mcu_flash, staging_bank, blocks)
+        {
+            log!(self, Error, "Serial update failed. Keeping the current image.");
+            if let Some(serial) = self.serial.as_mut() {
+                e.report(serial);
+            }
+            return Some(current_image);
+        }
+
+        self.record_staging_bank(staging_bank);
+        match self.promote_staged_image(staging_bank, boot_bank) {
+            Some(image) => Some(image),
+            None => self.recheck_boot_bank(boot_bank, current_image, true),
+        }
+    }
+
+    /// Bank the last serial update was staged in. Without a staging rotation, this is
+    /// always the configured staging bank, if any.
+    pub(super) fn staging_bank(&mut self) -> Option<Bank<MCUF::Address>> {
+        let index = match self.staging_rotation {
+            Some(rotation) => rotation.current(&mut self.mcu_flash).ok()??,
+            None => self.staging_bank?,
+        };
+        self.mcu_banks().find(|b| b.index == index)
+    }
+
+    /// Bank the next serial update is staged in. With a staging rotation, this is the bank
+    /// following the last one recorded; the turn only passes on once an update is staged
+    /// (see [`Self::record_staging_bank`]). Falls back to the configured staging bank if
+    /// the rotation can't be read.
+    pub(super) fn next_staging_bank(&mut self) -> Option<Bank<MCUF::Address>> {
+        let index = match self.staging_rotation {
+            Some(rotation) => rotation.next(&mut self.mcu_flash).ok().or(self.staging_bank)?,
+            None => self.staging_bank?,
+        };
+        self.mcu_banks().find(|b| b.index == index)
+    }
+
+    /// Records a staged update in the staging rotation, if any, so the next update is
+    /// staged in the following bank. Transfers that fail leave the rotation untouched.
+    fn record_staging_bank(&mut self, bank: Bank<MCUF::Address>) {
+        let rotation = match self.staging_rotation {
+            Some(rotation) => rotation,
+            None => return,
+        };
+        if rotation.current(&mut self.mcu_flash).ok().flatten() == Some(bank.index) {
+            return;
+        }
+        if rotation.record(&mut self.mcu_flash, bank.index).is_err() {
+            log!(self, Warn, "Failed to record the staging rotation.");
+        }
+    }
+
+    /// Finishes promoting a staged image, if a reset interrupted the process. Only images
+    /// carrying the [`PROMOTION_MARKER`] are promoted, and the marker is only erased once
+    /// the boot bank holds a verified copy of the image. A marker left without a valid
+    /// image (e.g. by a reset while clearing the staging bank) is cleared. Returns the
+    /// promoted image, if any.
+    fn complete_pending_promotion(
+        &mut self,
+        boot_bank: Bank<MCUF::Address>,
+    ) -> Option<Image<MCUF::Address>> {
+        // A reset between staging an update and recording it leaves the marked image in
+        // the bank whose turn it is, rather than in the last one recorded.
+        let candidates = [self.next_staging_bank(), self.staging_bank()];
+        let staging_bank = candidates
+            .iter()
+            .flatten()
+            .copied()
+            .find(|bank| marked_for_promotion(&mut self.mcu_flash, *bank))?;
+        if R::image_at(&mut self.mcu_flash, staging_bank).is_err() {
+            erase_bank(&mut self.mcu_flash, staging_bank).ok();
+            return None;
+        }
+        self.record_staging_bank(staging_bank);
+        log!(self, Info, "Found an image pending promotion in the staging bank. Promoting it...");
+        self.promote_staged_image(staging_bank, boot_bank)
+    }
+
+    /// Copies a verified image from the staging bank to the boot bank, and clears the
+    /// staging bank once the copy is verified. A reset at any point leaves either a valid
+    /// image in the boot bank, or the staged image in place to resume from.
+    fn promote_staged_image(
+        &mut self,
+        staging_bank: Bank<MCUF::Address>,
+        boot_bank: Bank<MCUF::Address>,
+    ) -> Option<Image<MCUF::Address>> {
+        let staged = R::image_at(&mut self.mcu_flash, staging_bank).ok()?.identifier();
+        // A previous promotion may have completed the copy, but not cleared the marker.
+        let image = match R::image_at(&mut self.mcu_flash, boot_bank) {
+            Ok(image) if image.identifier() == staged => image,
+            _ => self.replace_image_internal(staging_bank, boot_bank)?,
+        };
+        self.boot_metrics.boot_path = BootPath::Updated { bank: staging_bank.index };
+        if erase_bank(&mut self.mcu_flash, staging_bank).is_err() {
+            log!(self, Warn, "Failed to clear the staging bank after promoting its image.");
+        }
+        Some(image)
     }
 
     fn replace_image_internal(
@@ -178,18 +676,20 @@ impl<
         boot_bank: Bank<MCUF::Address>,
     ) -> Option<Image<MCUF::Address>> {
         duprintln!(self.serial, "Replacing current image with bank {:?}.", bank.index,);
-        Self::copy_image_single_flash(
+        if let Err(e) = copy_image_single_flash::<R, _, _>(
             &mut self.serial,
             &mut self.mcu_flash,
             bank,
             boot_bank,
             false,
-        )
-        .expect("Failed to copy a valid image!");
+            self.verify_writes,
+        ) {
+            log!(self, Warn, "Failed to copy the image into the boot bank.");
+            defmt_log!(warn, "Copy error: {:?}", e);
+            return None;
+        }
         duprintln!(self.serial, "Replaced image with bank {:?} [{}]", bank.index, MCUF::label(),);
-        let image = R::image_at(&mut self.mcu_flash, boot_bank)
-            .expect("Failed to verify an image after copy!");
-        Some(image)
+        R::image_at(&mut self.mcu_flash, boot_bank).ok()
     }
 
     fn replace_image_external(
@@ -198,18 +698,183 @@ impl<
         boot_bank: Bank<MCUF::Address>,
     ) -> Option<Image<MCUF::Address>> {
         duprintln!(self.serial, "Replacing current image with bank {:?}.", bank.index,);
-        Self::copy_image(
+        if let Err(e) = Self::copy_image(
             &mut self.serial,
-            self.external_flash.as_mut().unwrap(),
+            self.external_flash.as_mut()?,
             &mut self.mcu_flash,
             bank,
             boot_bank,
             false,
-        )
-        .expect("Failed to copy a valid image!");
+            self.verify_writes,
+        ) {
+            log!(self, Warn, "Failed to copy the image into the boot bank.");
+            defmt_log!(warn, "Copy error: {:?}", e);
+            return None;
+        }
         duprintln!(self.serial, "Replaced image with bank {:?} [{}]", bank.index, MCUF::label(),);
-        let image = R::image_at(&mut self.mcu_flash, boot_bank)
-            .expect("Failed to verify an image after copy!");
-        Some(image)
+        R::image_at(&mut self.mcu_flash, boot_bank).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::image::{image_crc::tests::TEST_IMAGE_WITH_CORRECT_CRC, CrcImageReader};
+    use blue_hal::hal::{
+        doubles::flash::{Address, FakeFlash},
+        flash::ReadWrite,
+    };
+    use core::{convert::TryInto, iter};
+    use crc::crc32;
+
+    #[test]
+    fn golden_and_nontarget_banks_are_never_scanned() {
+        let golden = Bank::golden(2, 512, Address(0));
+        let regular = Bank::regular(3, 512, Address(512));
+        let scan = || -> Option<(u32, bool)> { panic!("Bank should not be scanned") };
+
+        assert_eq!(candidacy(&golden, None, None, false, &0u32, scan), Candidacy::Golden);
+        assert_eq!(candidacy(&regular, Some(4), None, false, &0u32, scan), Candidacy::NotTargeted);
+    }
+
+    #[test]
+    fn golden_banks_are_only_candidates_with_the_golden_override() {
+        let golden = Bank::golden(2, 512, Address(0));
+        assert_eq!(
+            candidacy(&golden, None, None, true, &1u32, || Some((1u32, false))),
+            Candidacy::Current
+        );
+        assert_eq!(
+            candidacy(&golden, None, None, true, &1u32, || Some((2u32, false))),
+            Candidacy::Newer
+        );
+
+        // Golden banks are left for a pass of their own, after every regular bank.
+        let regular = Bank::regular(3, 512, Address(512));
+        assert!(in_update_pass(&regular, false, true) && !in_update_pass(&regular, true, true));
+        assert!(in_update_pass(&golden, true, true) && !in_update_pass(&golden, false, true));
+        assert!(in_update_pass(&golden, false, false) && !in_update_pass(&golden, true, false));
+    }
+
+    #[test]
+    fn scanned_banks_are_compared_with_current_image() {
+        let bank = Bank::regular(2, 512, Address(0));
+        assert_eq!(candidacy(&bank, None, None, false, &1u32, || None), Candidacy::NoImage);
+        assert_eq!(
+            candidacy(&bank, Some(2), None, false, &1u32, || Some((1u32, false))),
+            Candidacy::Current
+        );
+        assert_eq!(
+            candidacy(&bank, Some(2), None, false, &1u32, || Some((2u32, false))),
+            Candidacy::Newer
+        );
+    }
+
+    #[test]
+    fn backup_banks_are_only_scanned_when_targeted() {
+        let backup = Bank::regular(2, 512, Address(0));
+        let scan = || -> Option<(u32, bool)> { panic!("Bank should not be scanned") };
+        let untargeted = candidacy(&backup, None, Some(2), false, &1u32, scan);
+        assert_eq!(untargeted, Candidacy::Backup);
+        assert!(!untargeted.is_decisive());
+        assert_eq!(
+            candidacy(&backup, Some(2), Some(2), false, &1u32, || Some((2u32, false))),
+            Candidacy::Newer
+        );
+    }
+
+    #[test]
+    fn images_flagged_no_auto_update_are_never_update_sources() {
+        let bank = Bank::regular(2, 512, Address(0));
+        let flagged = candidacy(&bank, None, None, false, &1u32, || Some((2u32, true)));
+        assert_eq!(flagged, Candidacy::NoAutoUpdate);
+        assert!(!flagged.is_decisive());
+
+        let candidacies = [(2, flagged), (3, Candidacy::Newer)];
+        assert_eq!(select_update(candidacies.iter().cloned()), Some(3));
+        assert_eq!(select_update(iter::once((2, flagged))), None);
+    }
+
+    #[test]
+    fn first_bank_with_different_image_wins_tie() {
+        let candidacies = [(2, Candidacy::NoImage), (3, Candidacy::Newer), (4, Candidacy::Newer)];
+        assert_eq!(select_update(candidacies.iter().cloned()), Some(3));
+    }
+
+    #[test]
+    fn bank_holding_current_image_stops_the_search() {
+        let candidacies = [(2, Candidacy::Golden), (3, Candidacy::Current), (4, Candidacy::Newer)];
+        assert_eq!(select_update(candidacies.iter().cloned()), None);
+    }
+
+    #[test]
+    fn no_valid_images_means_no_update() {
+        let candidacies = [(2, Candidacy::NoImage), (3, Candidacy::NotTargeted)];
+        assert_eq!(select_update(candidacies.iter().cloned()), None);
+    }
+
+    #[test]
+    fn decision_names_the_bank_a_newer_image_would_be_updated_from() {
+        let candidacies = [(2, Candidacy::Golden), (3, Candidacy::NoImage), (4, Candidacy::Newer)];
+        let decision = decide_update(candidacies.iter().cloned());
+        assert_eq!(decision, UpdateDecision::UpdateFrom(4));
+        assert_eq!(decision.bank(), Some(4));
+    }
+
+    #[test]
+    fn decision_tells_up_to_date_apart_from_no_candidates() {
+        let candidacies = [(2, Candidacy::NoImage), (3, Candidacy::Current), (4, Candidacy::Newer)];
+        assert_eq!(decide_update(candidacies.iter().cloned()), UpdateDecision::UpToDate);
+
+        let candidacies = [(2, Candidacy::NoImage), (3, Candidacy::NoAutoUpdate)];
+        assert_eq!(decide_update(candidacies.iter().cloned()), UpdateDecision::NoCandidates);
+        assert_eq!(decide_update(iter::empty()), UpdateDecision::NoCandidates);
+        assert_eq!(UpdateDecision::UpToDate.bank(), None);
+    }
+
+    #[test]
+    fn update_plans_settle_the_target_or_the_decision() {
+        assert_eq!(update_target(None), Ok(None));
+        assert_eq!(update_target(Some(UpdatePlan::Any)), Ok(None));
+        assert_eq!(update_target(Some(UpdatePlan::Index(3))), Ok(Some(3)));
+        assert_eq!(update_target(Some(UpdatePlan::Any.test_boot(4))), Ok(Some(4)));
+        assert_eq!(update_target(Some(UpdatePlan::Serial)), Err(UpdateDecision::Serial));
+        assert_eq!(update_target(Some(UpdatePlan::None)), Err(UpdateDecision::Refused));
+        assert_eq!(update_target(Some(UpdatePlan::Any.recovery())), Err(UpdateDecision::Refused));
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn serial_update_is_staged_and_verified() {
+        let mut flash = FakeFlash::new(Address(0));
+        let bank = Bank::regular(2, 512, Address(0));
+        let block: [u8; 49] = TEST_IMAGE_WITH_CORRECT_CRC.try_into().unwrap();
+
+        let image = stage_update::<CrcImageReader<{ crc32::IEEE }, false>, _, _, 49>(
+            &mut flash,
+            bank,
+            iter::once(block),
+        )
+        .unwrap();
+        assert_eq!(image.location(), bank.location);
+    }
+
+    #[test]
+    #[cfg(not(feature = "ecdsa-verify"))]
+    fn rejected_serial_update_leaves_staging_bank_erased() {
+        let mut flash = FakeFlash::new(Address(0));
+        let bank = Bank::regular(2, 512, Address(0));
+        let mut block: [u8; 49] = TEST_IMAGE_WITH_CORRECT_CRC.try_into().unwrap();
+        block[0] ^= 0xFF;
+
+        assert!(stage_update::<CrcImageReader<{ crc32::IEEE }, false>, _, _, 49>(
+            &mut flash,
+            bank,
+            iter::once(block),
+        )
+        .is_err());
+        let mut contents = [0u8; 49];
+        flash.read(bank.location, &mut contents).unwrap();
+        assert!(contents.iter().all(|&b| b == 0xFF));
     }
 }
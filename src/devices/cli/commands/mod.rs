@@ -2,15 +2,21 @@ use crate::{
     devices::{
         boot_manager::BootManager,
         boot_metrics::BootPath,
-        cli::{file_transfer::FileTransfer, ArgumentIterator, Cli, Error, Name, RetrieveArgument},
-        image::{self, MAGIC_STRING},
+        bootloader::{Candidacy, UpdateDecision},
+        cli::{
+            Access, ArgumentIterator, BankRef, Cli, Error, Hex, HexDigest, HexPatch,
+            InterruptedTransfer, Name, ResolvedBank, RetrieveArgument, RightAligned, BUFFER_SIZE,
+        },
+        file_transfer::{BlockIterator, FileTransfer, XModemSender, BLOCK_SIZE},
+        image, image_digest, self_test,
         traits::{Flash, Serial},
-        update_signal::{UpdatePlan, WriteUpdateSignal},
+        update_signal::{ResetMode, UpdatePlan, WriteUpdateSignal},
+        usage::Usage,
     },
     error::Error as ApplicationError,
 };
-use blue_hal::uprintln;
-use ufmt::uwriteln;
+use blue_hal::{hal::serial::Write, uprintln, utilities::memory::Address};
+use ufmt::{uwrite, uwriteln};
 
 commands!( cli, boot_manager, names, helpstrings [
 
@@ -18,6 +24,26 @@ commands!( cli, boot_manager, names, helpstrings [
         cli.print_help(names, helpstrings, command)
     },
 
+    login ["Unlocks privileged commands (e.g. `flash`, `format`) for this session."] (
+        password: &str ["Password set in the Loadstone configuration."],
+        )
+    {
+        if cli.credentials.is_none() {
+            uprintln!(cli.serial, "Authentication is disabled. Every command is available.");
+            return Ok(());
+        }
+        cli.login(password)?;
+        uprintln!(cli.serial, "Privileged commands unlocked.");
+    },
+
+    logout ["Locks privileged commands again."] ( )
+    {
+        cli.logged_in = false;
+        if cli.credentials.is_some() {
+            uprintln!(cli.serial, "Privileged commands locked.");
+        }
+    },
+
     banks ["Displays bank information"] (){
         uprintln!(cli.serial, "[{}] Banks:", MCUF::label());
         for bank in boot_manager.mcu_banks() {
@@ -40,64 +66,166 @@ commands!( cli, boot_manager, names, helpstrings [
         }
     },
 
-    images ["Displays image information (WARNING: Slow)"] (){
+    memmap ["Displays the flash address layout of every bank, including unused gaps."] (){
+        uprintln!(cli.serial, "Bank  Start       End          Size (KB)  Flash  Flags");
+        print_memory_map(&mut cli.serial, MCUF::label(), boot_manager.mcu_banks());
+        print_memory_map(&mut cli.serial, EXTF::label(), boot_manager.external_banks());
+    },
+
+    bootloader_info ["Displays the flash region reserved for Loadstone, and how much of it is used."] (){
+        let region = boot_manager.bootloader_region();
+        let start: usize = region.location.into();
+        uprintln!(cli.serial, "[{}] Bootloader region: {} - {} ({}KB)",
+            MCUF::label(), Hex(start), Hex(start + region.length), region.length / 1024);
+        let used = boot_manager.bootloader_programmed_length()?;
+        uprintln!(cli.serial, "* Programmed: {}b - Headroom: {}b", used, region.length - used);
+        if used == region.length {
+            uprintln!(cli.serial, "* The binary fills the region (e.g. padded for the self check).");
+        }
+    },
+
+    images ["Displays image information, telling empty banks from invalid images (WARNING: Slow)"] (){
         uprintln!(cli.serial, "[{}] Images:", MCUF::label());
         for bank in boot_manager.mcu_banks() {
-            if let Ok(image) = R::image_at(&mut boot_manager.mcu_flash, bank) {
-                uwriteln!(cli.serial, "Bank {} - [IMAGE] - Size: {}b - {}",
-                    bank.index,
-                    image.size(),
-                    if image.is_golden() { " - GOLDEN" } else { "" }).ok().unwrap();
-            }
+            let contents = R::contents_at(&mut boot_manager.mcu_flash, bank);
+            print_bank_contents(&mut cli.serial, bank.index, contents);
         }
         if let Some(ref mut external_flash) = boot_manager.external_flash {
             uprintln!(cli.serial, "[{}] Images:", EXTF::label());
             for bank in boot_manager.external_banks.iter().cloned() {
-                if let Ok(image) = R::image_at(external_flash, bank) {
-                    uwriteln!(cli.serial, "Bank {} - [IMAGE] - Size: {}b - {}",
-                        bank.index,
-                        image.size(),
-                        if image.is_golden() { " - GOLDEN" } else { "" }).ok().unwrap();
-                }
+                let contents = R::contents_at(external_flash, bank);
+                print_bank_contents(&mut cli.serial, bank.index, contents);
             }
         }
     },
 
-    flash ["Stores a FW image in a non-bootable bank."] (
-        bank: u8 ["Bank index."],
+    usage ["Displays used and free space across all banks (WARNING: Slow without `quick`)"] (
+        quick: bool ["Only check whether banks are erased, counting occupied ones as full."],
         )
     {
-        if let Some(bank) = boot_manager.external_banks().find(|b| b.index == bank) {
-            uprintln!(cli.serial, "Starting XMODEM mode! Send file with your XMODEM client.");
-            boot_manager.store_image_external(cli.serial.blocks(None), bank)?;
-            uprintln!(cli.serial, "Image transfer complete!");
-        } else if let Some(bank) = boot_manager.mcu_banks().find(|b| b.index == bank) {
-            if bank.bootable {
-                uprintln!(cli.serial, "You can't erase the bootable image, it's what you are");
-                uprintln!(cli.serial, "currently running! You can still corrupt its signature");
-                uprintln!(cli.serial, "to force it to be invalid.");
-                return Err(Error::ApplicationError(ApplicationError::BankInvalid));
+        let mcu_usage = boot_manager.usage_mcu(quick)?;
+        let external_usage = boot_manager.usage_external(quick)?;
+        print_usage(&mut cli.serial, MCUF::label(), mcu_usage);
+        if external_usage.banks > 0 {
+            print_usage(&mut cli.serial, EXTF::label(), external_usage);
+        }
+        print_usage(&mut cli.serial, "Overall", mcu_usage + external_usage);
+        if quick {
+            uprintln!(cli.serial, "Quick mode: occupied banks are counted as entirely used.");
+        }
+    },
+
+    flash ["Stores a FW image in a bank that is neither bootable nor a staging bank."] Privileged (
+        bank: BankRef ["Bank index."],
+        resume_from: Option<u32> ["Block to resume an interrupted transfer from (see `resume_info`)."],
+        )
+    {
+        let resume_from = resume_from.unwrap_or(0);
+        let start = InterruptedTransfer { bank: bank.0, received_blocks: resume_from };
+        let offset = resume_from as usize * BLOCK_SIZE;
+        match cli.resolve_bank(boot_manager, bank)? {
+            ResolvedBank::External(bank) => {
+                let external_flash = boot_manager.external_flash.as_mut()
+                    .ok_or(Error::ApplicationError(ApplicationError::NoExternalFlash))?;
+                start_transfer(cli, external_flash, bank, start)?;
+                let remaining = skip_into_bank(bank, offset);
+                let interrupted = receive_image(cli, start, |blocks| boot_manager.store_image_external(blocks, remaining))?;
+                if let Some(transfer) = interrupted {
+                    let external_flash = boot_manager.external_flash.as_mut()
+                        .ok_or(Error::ApplicationError(ApplicationError::NoExternalFlash))?;
+                    record_interrupted_transfer(cli, external_flash, bank, transfer)?;
+                }
+            }
+            ResolvedBank::Mcu(bank) => {
+                if bank.bootable {
+                    uprintln!(cli.serial, "You can't erase the bootable image, it's what you are");
+                    uprintln!(cli.serial, "currently running! You can still corrupt its signature");
+                    uprintln!(cli.serial, "to force it to be invalid.");
+                    return Err(Error::ApplicationError(ApplicationError::BankInvalid));
+                }
+                if boot_manager.is_staging_bank(bank.index) {
+                    uprintln!(cli.serial, "Loadstone stages serial updates in this bank, and only");
+                    uprintln!(cli.serial, "promotes images it staged itself. Request a serial");
+                    uprintln!(cli.serial, "update instead (`update_signal_serial`).");
+                    return Err(Error::ApplicationError(ApplicationError::BankInvalid));
+                }
+                start_transfer(cli, &mut boot_manager.mcu_flash, bank, start)?;
+                let remaining = skip_into_bank(bank, offset);
+                let interrupted = receive_image(cli, start, |blocks| boot_manager.store_image_mcu(blocks, remaining))?;
+                if let Some(transfer) = interrupted {
+                    record_interrupted_transfer(cli, &mut boot_manager.mcu_flash, bank, transfer)?;
+                }
             }
-            uprintln!(cli.serial, "Starting XMODEM mode! Send file with your XMODEM client.");
-            boot_manager.store_image_mcu(cli.serial.blocks(None), bank)?;
-            uprintln!(cli.serial, "Image transfer complete!");
-        } else {
-            uprintln!(cli.serial, "Index supplied does not correspond to any bank.");
         }
+    },
 
+    hash ["Displays the SHA-256 of an image, up to its magic string, to compare with the host."] (
+        bank: BankRef ["Bank index."],
+        length: Option<u32> ["Hash this many bytes from the start of the bank instead."],
+        )
+    {
+        let length = length.map(|l| l as usize);
+        let digest = match cli.resolve_bank(boot_manager, bank)? {
+            ResolvedBank::External(bank) => {
+                let external_flash = boot_manager.external_flash.as_mut()
+                    .ok_or(Error::ApplicationError(ApplicationError::NoExternalFlash))?;
+                image_digest::image_digest::<R, _>(external_flash, bank, length)
+            }
+            ResolvedBank::Mcu(bank) => {
+                image_digest::image_digest::<R, _>(&mut boot_manager.mcu_flash, bank, length)
+            }
+        }.map_err(Error::ApplicationError)?;
+        uprintln!(cli.serial, "sha256={}", HexDigest(&digest));
     },
 
-    corrupt_signature ["Corrupts the ECDSA signature of a specified image."] (
-        bank: u8 ["Bank index."],
+    export ["Sends a FW image, trailer included, to the host via XMODEM (e.g. for analysis)."] Privileged (
+        bank: BankRef ["Bank index."],
         )
     {
+        match cli.resolve_bank(boot_manager, bank)? {
+            ResolvedBank::External(bank) => {
+                let external_flash = boot_manager.external_flash.as_mut()
+                    .ok_or(Error::ApplicationError(ApplicationError::NoExternalFlash))?;
+                let image = R::image_at(external_flash, bank).map_err(Error::ApplicationError)?;
+                export_image(cli, external_flash, image)?;
+            }
+            ResolvedBank::Mcu(bank) => {
+                let image = R::image_at(&mut boot_manager.mcu_flash, bank)
+                    .map_err(Error::ApplicationError)?;
+                export_image(cli, &mut boot_manager.mcu_flash, image)?;
+            }
+        }
+    },
 
+    resume_info ["Reports the block an interrupted `flash` transfer can resume from."] (
+        bank: BankRef ["Bank index."],
+        )
+    {
+        let transfer = match cli.resolve_bank(boot_manager, bank)? {
+            ResolvedBank::External(bank) => {
+                let external_flash = boot_manager.external_flash.as_mut()
+                    .ok_or(Error::ApplicationError(ApplicationError::NoExternalFlash))?;
+                InterruptedTransfer::recorded(external_flash, bank)
+            }
+            ResolvedBank::Mcu(bank) => InterruptedTransfer::recorded(&mut boot_manager.mcu_flash, bank),
+        }.map_err(Error::ApplicationError)?;
+        match transfer {
+            Some(transfer) => uprintln!(cli.serial, "resume_from={}", transfer.received_blocks),
+            None => uprintln!(cli.serial, "No interrupted transfer into bank {}.", bank.0),
+        }
+    },
 
-        if let Some(ref mut external_flash) = boot_manager.external_flash {
-            if let Some(bank) = boot_manager.external_banks.iter().cloned().find(|b| b.index == bank) {
+    corrupt_signature ["Corrupts the ECDSA signature of a specified image."] Privileged (
+        bank: BankRef ["Bank index."],
+        )
+    {
+        match cli.resolve_bank(boot_manager, bank)? {
+            ResolvedBank::External(bank) => {
+                let external_flash = boot_manager.external_flash.as_mut()
+                    .ok_or(Error::ApplicationError(ApplicationError::NoExternalFlash))?;
                 let image = R::image_at(external_flash, bank)
-                    .map_err(|_| Error::ApplicationError(ApplicationError::BankEmpty))?;
-                let signature_location = image.location() + image.size() + MAGIC_STRING.len();
+                    .map_err(Error::ApplicationError)?;
+                let signature_location = image.digest_location();
                 let mut signature_bytes = [0u8; 64usize];
                 nb::block!(external_flash.read(signature_location, &mut signature_bytes))
                     .map_err(|e| Error::ApplicationError(e.into()))?;
@@ -106,41 +234,39 @@ commands!( cli, boot_manager, names, helpstrings [
                     .map_err(|e| Error::ApplicationError(e.into()))?;
                 uprintln!(cli.serial, "Flipped the first signature byte from {} to {}.", !signature_bytes[0], signature_bytes[0]);
             }
-        } else if let Some(bank) = boot_manager.mcu_banks().find(|b| b.index == bank) {
-            uprintln!(cli.serial, "Warning: Corrupting a signature in the MCU flash should work, but it might cause");
-            uprintln!(cli.serial, "the application to crash.");
-            let image = R::image_at(&mut boot_manager.mcu_flash, bank)
-                .map_err(|_| Error::ApplicationError(ApplicationError::BankEmpty))?;
-            let signature_location = image.location() + image.size() + MAGIC_STRING.len();
-            let mut signature_bytes = [0u8; 64usize];
-            nb::block!(boot_manager.mcu_flash.read(signature_location, &mut signature_bytes))
-                .map_err(|e| Error::ApplicationError(e.into()))?;
-            signature_bytes[0] = !signature_bytes[0];
-            nb::block!(boot_manager.mcu_flash.write(signature_location, &mut signature_bytes))
-                .map_err(|e| Error::ApplicationError(e.into()))?;
-            uprintln!(cli.serial, "Flipped the first signature byte from {} to {}.", !signature_bytes[0], signature_bytes[0]);
-        } else {
-            uprintln!(cli.serial, "Index supplied does not correspond to any bank.");
-            return Ok(());
-        };
+            ResolvedBank::Mcu(bank) => {
+                uprintln!(cli.serial, "Warning: Corrupting a signature in the MCU flash should work, but it might cause");
+                uprintln!(cli.serial, "the application to crash.");
+                let image = R::image_at(&mut boot_manager.mcu_flash, bank)
+                    .map_err(Error::ApplicationError)?;
+                let signature_location = image.digest_location();
+                let mut signature_bytes = [0u8; 64usize];
+                nb::block!(boot_manager.mcu_flash.read(signature_location, &mut signature_bytes))
+                    .map_err(|e| Error::ApplicationError(e.into()))?;
+                signature_bytes[0] = !signature_bytes[0];
+                nb::block!(boot_manager.mcu_flash.write(signature_location, &mut signature_bytes))
+                    .map_err(|e| Error::ApplicationError(e.into()))?;
+                uprintln!(cli.serial, "Flipped the first signature byte from {} to {}.", !signature_bytes[0], signature_bytes[0]);
+            }
+        }
     },
 
-    corrupt_body ["Corrupts a byte inside a specified external image."] (
-        bank: u8 ["External bank index."],
+    corrupt_body ["Corrupts a byte inside a specified external image."] Privileged (
+        bank: BankRef ["External bank index."],
         )
     {
+        let bank = match cli.resolve_bank(boot_manager, bank)? {
+            ResolvedBank::External(bank) => bank,
+            ResolvedBank::Mcu(_) => {
+                uprintln!(cli.serial, "Index supplied does not correspond to an external bank.");
+                return Err(Error::ArgumentOutOfRange);
+            }
+        };
         let external_flash = boot_manager.external_flash.as_mut()
             .ok_or(Error::ApplicationError(ApplicationError::NoExternalFlash))?;
 
-        let bank = if let Some(bank) = boot_manager.external_banks.iter().cloned().find(|b| b.index == bank) {
-            bank
-        } else {
-            uprintln!(cli.serial, "Index supplied does not correspond to an external bank.");
-            return Ok(());
-        };
-
         let image = R::image_at(external_flash, bank)
-            .map_err(|_| Error::ApplicationError(ApplicationError::BankEmpty))?;
+            .map_err(Error::ApplicationError)?;
 
         let byte_location = image.location() + 1;
         let mut byte_buffer = [0u8];
@@ -150,7 +276,118 @@ commands!( cli, boot_manager, names, helpstrings [
         uprintln!(cli.serial, "Flipped an application byte byte from {} to {}.", !byte_buffer[0], byte_buffer[0]);
     },
 
-    format ["Formats external flash."] ()
+    write ["Writes bytes at an offset into a non-bootable bank, e.g. to test corruption handling."] Privileged (
+        byte: HexPatch ["Hexadecimal bytes to write (e.g. `A5` or `DEADBEEF`, 16 bytes at most)."],
+        bank: BankRef ["Non-bootable bank index."],
+        offset: usize ["Offset from the start of the bank, in bytes."],
+        )
+    {
+        match cli.resolve_bank(boot_manager, bank)? {
+            ResolvedBank::External(bank) => boot_manager.write_external(bank, offset, byte.bytes()),
+            ResolvedBank::Mcu(bank) if bank.bootable => {
+                uprintln!(cli.serial, "Refusing to write to the bootable bank.");
+                return Err(Error::ApplicationError(ApplicationError::BankInvalid));
+            }
+            ResolvedBank::Mcu(bank) => boot_manager.write_mcu(bank, offset, byte.bytes()),
+        }.map_err(Error::ApplicationError)?;
+        uprintln!(cli.serial, "Wrote {} bytes to bank {} at offset {}.", byte.bytes().len(), bank.0, offset);
+    },
+
+    self_test ["Validates flash read, write and erase on a scratch bank (DESTROYS its contents)."] Privileged (
+        bank: BankRef ["Non-bootable bank index."],
+        )
+    {
+        let bank = cli.resolve_bank(boot_manager, bank)?;
+        if matches!(bank, ResolvedBank::Mcu(bank) if bank.bootable) {
+            uprintln!(cli.serial, "Refusing to self test the bootable bank.");
+            return Err(Error::ApplicationError(ApplicationError::BankInvalid));
+        }
+        let serial = &mut cli.serial;
+        let report = |step: self_test::Step, outcome: self_test::Outcome| {
+            uprintln!(serial, "   - {}: {}", step.name(), outcome.name());
+        };
+        let passed = match bank {
+            ResolvedBank::External(bank) => boot_manager.self_test_external(bank, report),
+            ResolvedBank::Mcu(bank) => boot_manager.self_test_mcu(bank, report),
+        }.map_err(Error::ApplicationError)?;
+        uprintln!(cli.serial, "Self test {}.", if passed { "passed" } else { "FAILED" });
+    },
+
+    recover ["Receives a golden image via XMODEM, as Loadstone's recovery mode would."] Privileged ( )
+    {
+        if !boot_manager.recovery_enabled {
+            uprintln!(cli.serial, "Recovery is disabled in this Loadstone configuration.");
+            return Err(Error::ApplicationError(ApplicationError::NoRecoverySupport));
+        }
+        uprintln!(cli.serial, "Starting XMODEM mode! Send golden image with your XMODEM client.");
+        boot_manager.recover(cli.serial.blocks(None))?;
+        uprintln!(cli.serial, "Golden image recovered! Use `boot` to restart.");
+    },
+
+    scan ["Reports which bank Loadstone would update from, without rebooting."] ( )
+    {
+        uprintln!(cli.serial, "Scanning banks in update order (as if any bank is allowed)...");
+        let serial = &mut cli.serial;
+        let candidate = boot_manager.update_candidate(|index, candidacy| {
+            uprintln!(serial, "   - [{}] {}", index, candidacy.reason());
+        })?;
+        match candidate {
+            Some(index) => uprintln!(cli.serial, "Loadstone would update from bank {}.", index),
+            None => uprintln!(cli.serial, "Loadstone would boot the current image without updating."),
+        }
+    },
+
+    dry_update ["Previews the update Loadstone would perform on the next boot, without performing it."] ( )
+    {
+        uprintln!(cli.serial, "Scanning banks in update order, following the update signal...");
+        let serial = &mut cli.serial;
+        let decision = boot_manager.dry_update(|index, candidacy| {
+            uprintln!(serial, "   - [{}] {}", index, candidacy.reason());
+        })?;
+        match decision {
+            UpdateDecision::UpdateFrom(index) => {
+                uprintln!(cli.serial, "Loadstone would update from bank {}.", index)
+            }
+            decision => uprintln!(cli.serial, "Loadstone wouldn't update: {}", decision.reason()),
+        }
+    },
+
+    baud ["Changes the serial baud rate. Reconnect at the new rate afterwards."] Privileged (
+        rate: u32 ["New baud rate, in bits per second."],
+        )
+    {
+        let control = boot_manager.baud_control.ok_or(Error::ApplicationError(
+            ApplicationError::DeviceError("Changing the baud rate is not supported on this port.")))?;
+        let divisor = control.divisor(rate).ok_or(Error::ArgumentOutOfRange)?;
+        uprintln!(cli.serial, "Switching to {} bps. Please reconnect at the new rate.", rate);
+        control.apply(divisor);
+    },
+
+    echo ["Writes the next line received back verbatim, to check the serial link."] ( ) {
+        uprintln!(cli.serial, "Send a line to echo back.");
+        let mut buffer = [0u8; BUFFER_SIZE];
+        let received = nb::block!(cli.read_line(&mut buffer))?;
+        for byte in &buffer[..received] {
+            cli.serial.write_char(*byte as char).ok().unwrap();
+        }
+        uprintln!(cli.serial, "");
+        uprintln!(cli.serial, "Received {} bytes.", received);
+    },
+
+    factory_reset ["Erases all banks but the bootable and golden ones, boot metrics and the update signal."] Privileged (
+        confirm: Option<&str> ["Must be `yes` to proceed."],
+        )
+    {
+        if confirm != Some("yes") {
+            uprintln!(cli.serial, "This wipes all application state! Use `factory_reset confirm=yes` to proceed.");
+            return Err(Error::MissingArgument);
+        }
+        uprintln!(cli.serial, "Erasing all non-bootable, non-golden banks...");
+        boot_manager.factory_reset()?;
+        uprintln!(cli.serial, "Factory reset complete!");
+    },
+
+    format ["Formats external flash."] Privileged ()
     {
         uprintln!(cli.serial, "Formatting external flash...");
         boot_manager.format_external()?;
@@ -160,26 +397,160 @@ commands!( cli, boot_manager, names, helpstrings [
     boot ["Restart, attempting to boot into a valid image if available."] ( )
     {
         uprintln!(cli.serial, "Restarting...");
-        boot_manager.reset();
+        boot_manager.reset_into(ResetMode::Normal)?;
     },
 
-    update_signal_bank ["Only allow loadstone to update from a specific bank."] (
-        bank: u8 ["Updatable bank index."],
+    reset ["Resets the device, choosing how Loadstone behaves on the next boot."] Privileged (
+        mode: &str ["One of `normal`, `recovery` (serial recovery) or `bank` (boots once from `bank`)."],
+        bank: Option<BankRef> ["Bank to boot once from (only for `bank`)."],
     ) {
-        return boot_manager.set_update_signal(UpdatePlan::Index(bank))
+        let name = mode;
+        let mode = ResetMode::from_name(name, bank.map(|b| b.0)).ok_or(Error::MalformedArguments)?;
+        if let ResetMode::Bank(index) = mode {
+            cli.resolve_bank(boot_manager, BankRef(index))?;
+            let candidacy = boot_manager.test_boot_candidacy(index)?;
+            if candidacy != Candidacy::Newer {
+                uprintln!(cli.serial, "Refusing to boot once from bank {}: {}", index, candidacy.reason());
+                return Err(Error::ApplicationError(ApplicationError::BankInvalid));
+            }
+        }
+        uprintln!(cli.serial, "Resetting into {} mode...", name);
+        boot_manager.reset_into(mode)?;
+    },
+
+    test_boot ["Copies a verified image over the boot bank and boots it, without changing the update plan."] Privileged (
+        bank: BankRef ["Bank index."],
+    ) {
+        cli.resolve_bank(boot_manager, bank)?;
+        match boot_manager.test_boot_candidacy(bank.0)? {
+            Candidacy::Current => {
+                uprintln!(cli.serial, "Bank {} holds the current image. Restarting...", bank.0);
+                boot_manager.reset_into(ResetMode::Normal)?;
+            }
+            Candidacy::Newer => {
+                uprintln!(cli.serial, "Image verified. Restarting to boot once from bank {}...", bank.0);
+                boot_manager.reset_into(ResetMode::Bank(bank.0))?;
+            }
+            refused => {
+                uprintln!(cli.serial, "Refusing to test boot bank {}: {}", bank.0, refused.reason());
+                return Err(Error::ApplicationError(ApplicationError::BankInvalid));
+            }
+        }
+    },
+
+    confirm_healthy ["Confirms the running image is healthy, mirroring it into the backup bank."] Privileged ( )
+    {
+        uprintln!(cli.serial, "Mirroring the running image into the backup bank...");
+        if boot_manager.confirm_healthy()? {
+            uprintln!(cli.serial, "Backup updated!");
+        } else {
+            uprintln!(cli.serial, "The backup bank already holds this image.");
+        }
+    },
+
+    update_signal_bank ["Only allow loadstone to update from a specific bank."] Privileged (
+        bank: BankRef ["Updatable bank index."],
+    ) {
+        cli.resolve_bank(boot_manager, bank)?;
+        return boot_manager.set_update_signal(UpdatePlan::Index(bank.0))
             .map_err(|e| Error::ApplicationError(e));
     },
 
-    update_signal_none ["Disallow loadstone from updating."] ( ) {
+    update_signal_none ["Disallow loadstone from updating."] Privileged ( ) {
         return boot_manager.set_update_signal(UpdatePlan::None)
             .map_err(|e| Error::ApplicationError(e));
     },
 
-    update_signal_any ["Allow loadstone to update from any bank."] ( ) {
+    update_signal_any ["Allow loadstone to update from any bank."] Privileged ( ) {
         return boot_manager.set_update_signal(UpdatePlan::Any)
             .map_err(|e| Error::ApplicationError(e));
     },
 
+    update_signal_serial ["Make loadstone wait for an update over serial (XMODEM) on the next boot."] Privileged ( ) {
+        return boot_manager.set_update_signal(UpdatePlan::Serial)
+            .map_err(|e| Error::ApplicationError(e));
+    },
+
+    update_plan ["Displays the update plan Loadstone will follow on the next boot."] ( ) {
+        let plan = boot_manager.update_plan().map_err(Error::ApplicationError)?;
+        print_update_plan(&mut cli.serial, plan);
+    },
+
+    set_update_plan ["Sets the update plan Loadstone will follow on the next boot."] Privileged (
+        plan: &str ["One of `none`, `any`, `index` or `serial`."],
+        bank: Option<BankRef> ["Bank index to update from (only for `index`)."],
+    ) {
+        if let Some(bank) = bank {
+            cli.resolve_bank(boot_manager, bank)?;
+        }
+        let plan = UpdatePlan::from_name(plan, bank.map(|b| b.0)).ok_or(Error::MalformedArguments)?;
+        boot_manager.set_update_signal(plan).map_err(Error::ApplicationError)?;
+        let stored = boot_manager.update_plan().map_err(Error::ApplicationError)?;
+        print_update_plan(&mut cli.serial, stored);
+        if stored != plan {
+            uprintln!(cli.serial, "Warning: the stored plan doesn't match the requested one.");
+            return Err(Error::ApplicationError(ApplicationError::DeviceError(
+                "Update plan was not persisted.")));
+        }
+    },
+
+    time ["Displays milliseconds elapsed since the boot manager started."] ( )
+    {
+        match boot_manager.uptime_ms() {
+            Some(uptime_ms) => uprintln!(cli.serial, "Uptime: {} milliseconds.", uptime_ms),
+            None => uprintln!(cli.serial, "Timing unavailable."),
+        }
+    },
+
+    version ["Displays the version, port and security mode Loadstone was built with."] ( )
+    {
+        uprintln!(cli.serial, "Loadstone {} ({})", env!("CARGO_PKG_VERSION"), GIT_VERSION);
+        uprintln!(cli.serial, "* Port: {}", option_env!("LOADSTONE_PORT").unwrap_or("Unknown"));
+        uprintln!(cli.serial, "* Security mode: {}",
+            option_env!("LOADSTONE_SECURITY_MODE").unwrap_or("Unknown"));
+    },
+
+    key_fingerprint ["Displays a fingerprint of the key image signatures are verified against."] ( )
+    {
+        #[cfg(feature = "ecdsa-verify")]
+        {
+            use crate::devices::{
+                cli::HexBytes,
+                image::{key_source::fingerprint, EmbeddedKey, KeySource},
+            };
+            let key = EmbeddedKey::verifying_key().map_err(Error::ApplicationError)?;
+            uprintln!(cli.serial, "Verifying key fingerprint (truncated SHA-256): {}",
+                HexBytes(&fingerprint(&key)));
+        }
+        #[cfg(not(feature = "ecdsa-verify"))]
+        uprintln!(cli.serial, "Images are verified by CRC, so there is no verifying key.");
+    },
+
+    security ["Displays the image verification scheme, recovery policy and key this build enforces."] ( )
+    {
+        uprintln!(cli.serial, "[Security]");
+        uprintln!(cli.serial, "* Image verification: {}", algorithm_name(image::enforced_algorithm()));
+        let golden_bank_exists = boot_manager.mcu_banks().any(|b| b.is_golden)
+            || boot_manager.external_banks().any(|b| b.is_golden);
+        uprintln!(cli.serial, "* Serial recovery: {}", match boot_manager.recovery_enabled {
+            false => "Disabled",
+            true if golden_bank_exists => "Golden images only",
+            true => "Any verified image",
+        });
+        #[cfg(feature = "ecdsa-verify")]
+        {
+            use crate::devices::{
+                cli::HexBytes,
+                image::{key_source::fingerprint, EmbeddedKey, KeySource},
+            };
+            let key = EmbeddedKey::verifying_key().map_err(Error::ApplicationError)?;
+            uprintln!(cli.serial, "* Key fingerprint (truncated SHA-256): {}",
+                HexBytes(&fingerprint(&key)));
+        }
+        #[cfg(not(feature = "ecdsa-verify"))]
+        uprintln!(cli.serial, "* Key fingerprint: None (images are not signed)");
+    },
+
     metrics ["Displays boot process metrics relayed by Loadstone."] ( )
     {
         if let Some(metrics) = &boot_manager.boot_metrics {
@@ -228,9 +599,214 @@ commands!( cli, boot_manager, names, helpstrings [
             if let Some(boot_time_ms) = metrics.boot_time_ms {
                 uprintln!(cli.serial, "* Boot process took {} milliseconds.", boot_time_ms);
             }
+            if let Some(boot_count) = metrics.boot_count {
+                uprintln!(cli.serial, "* Unit has booted {} times.", boot_count);
+            }
         } else {
             uprintln!(cli.serial, "Loadstone did not relay any boot metrics, or the boot metrics were corrupted.");
         }
     },
 
 ]);
+
+/// Git version of the source tree Loadstone was built from.
+const GIT_VERSION: &str = git_version::git_version!(fallback = "unknown");
+
+/// Prints a row per bank of a flash chip, in address order, with a row for every
+/// unused gap between consecutive banks.
+fn print_memory_map<S: Serial, A: Address>(
+    serial: &mut S,
+    label: &str,
+    banks: impl Iterator<Item = image::Bank<A>>,
+) {
+    let mut previous_end: Option<usize> = None;
+    for bank in banks {
+        let start: usize = bank.location.into();
+        let end = start + bank.size;
+        if let Some(gap_start) = previous_end.filter(|e| *e < start) {
+            uprintln!(
+                serial,
+                " --   {} - {} {}  {}  (Unused)",
+                Hex(gap_start),
+                Hex(start),
+                RightAligned((start - gap_start) / 1024, 9),
+                label
+            );
+        }
+        uprintln!(
+            serial,
+            "[{}] {} - {} {}  {}  {}{}",
+            RightAligned(bank.index as usize, 2),
+            Hex(start),
+            Hex(end),
+            RightAligned(bank.size / 1024, 9),
+            label,
+            if bank.bootable { "Bootable" } else { "Non-Bootable" },
+            if bank.is_golden { " - GOLDEN" } else { "" }
+        );
+        previous_end = Some(end);
+    }
+}
+
+/// Human readable name of an image verification scheme.
+fn algorithm_name(algorithm: image::Algorithm) -> &'static str {
+    match algorithm {
+        image::Algorithm::Crc32 => "CRC32 (integrity only)",
+        image::Algorithm::P256 => "ECDSA P-256 signatures",
+        image::Algorithm::Ed25519 => "Ed25519 signatures",
+        image::Algorithm::Sha256 => "SHA-256 digests (integrity only)",
+    }
+}
+
+/// Prints an update plan, along with its target bank if it has one.
+fn print_update_plan<S: Serial>(serial: &mut S, plan: UpdatePlan) {
+    match plan {
+        UpdatePlan::Index(bank) | UpdatePlan::TestBoot { bank, .. } => {
+            uprintln!(serial, "Update plan: {} (bank {})", plan.name(), bank)
+        }
+        _ => uprintln!(serial, "Update plan: {}", plan.name()),
+    }
+}
+
+/// Prints a single line summary of bank occupancy.
+fn print_usage<S: Serial>(serial: &mut S, label: &str, usage: Usage) {
+    uprintln!(
+        serial,
+        "[{}] {}/{} banks occupied - Used: {}b - Free: {}b - Total: {}b",
+        label,
+        usage.occupied_banks,
+        usage.banks,
+        usage.used,
+        usage.free(),
+        usage.capacity
+    );
+}
+
+/// Prints a single line describing what a bank holds: a valid image, nothing at all, or
+/// data that doesn't verify, along with the reason (e.g. a bad signature).
+fn print_bank_contents<S: Serial, A: Address>(
+    serial: &mut S,
+    index: u8,
+    contents: image::BankContents<A>,
+) {
+    match contents {
+        image::BankContents::Image(image) => uprintln!(
+            serial,
+            "Bank {} - [IMAGE] - Size: {}b - {}",
+            index,
+            image.size(),
+            if image.is_golden() { " - GOLDEN" } else { "" }
+        ),
+        image::BankContents::Empty => uprintln!(serial, "Bank {} - [EMPTY]", index),
+        image::BankContents::Unverified(error) => {
+            uwrite!(serial, "Bank {} - [INVALID] ", index).ok().unwrap();
+            error.report(serial);
+        }
+    }
+}
+
+/// Retries allowed per block while flashing or exporting, so a dropped link ends the
+/// transfer (leaving it resumable) rather than waiting forever. Each retry waits up to
+/// [`xmodem::DEFAULT_TIMEOUT`](blue_hal::utilities::xmodem::DEFAULT_TIMEOUT) for the
+/// other end, so a silent link is given up on after that many timeouts in a row.
+const FLASH_MAX_RETRIES: u32 = 60;
+
+/// Narrows a bank to the region past its first `offset` bytes, where a resumed
+/// transfer continues writing.
+fn skip_into_bank<A: Address>(bank: image::Bank<A>, offset: usize) -> image::Bank<A> {
+    image::Bank { location: bank.location + offset, size: bank.size.saturating_sub(offset), ..bank }
+}
+
+/// Sends an image over XMODEM, from its start to the end of its trailer. The last block
+/// is padded with `0xFF`, like erased flash.
+fn export_image<SRL: Serial, F: Flash>(
+    cli: &mut Cli<SRL>,
+    flash: &mut F,
+    image: image::Image<F::Address>,
+) -> Result<(), Error> {
+    uprintln!(cli.serial, "Starting XMODEM mode! Receive the image with your XMODEM client.");
+    let mut sender = XModemSender::start(&mut cli.serial, FLASH_MAX_RETRIES)?;
+    let total_size = image.total_size();
+    for offset in (0..total_size).step_by(BLOCK_SIZE) {
+        let mut block = [0xFFu8; BLOCK_SIZE];
+        let length = BLOCK_SIZE.min(total_size - offset);
+        if let Err(e) = nb::block!(flash.read(image.location() + offset, &mut block[..length])) {
+            sender.cancel();
+            return Err(Error::ApplicationError(e.into()));
+        }
+        sender.send(&block)?;
+    }
+    let sent_blocks = sender.finish()?;
+    uprintln!(cli.serial, "Image export complete! Sent {} blocks.", sent_blocks);
+    Ok(())
+}
+
+/// Checks that a `flash` transfer can start where requested: resuming requires the
+/// bank to record an interrupted transfer that stopped at the same block. The record is
+/// then cleared, as the transfer writes over it.
+fn start_transfer<SRL: Serial, F: Flash>(
+    cli: &mut Cli<SRL>,
+    flash: &mut F,
+    bank: image::Bank<F::Address>,
+    start: InterruptedTransfer,
+) -> Result<(), Error> {
+    let recorded = InterruptedTransfer::recorded(flash, bank).map_err(Error::ApplicationError)?;
+    if start.received_blocks > 0 && recorded != Some(start) {
+        uprintln!(cli.serial, "There is no interrupted transfer to resume from that block.");
+        return Err(Error::ArgumentOutOfRange);
+    }
+    InterruptedTransfer::clear(flash, bank).map_err(Error::ApplicationError)
+}
+
+/// Receives an image over XMODEM, handing the blocks to `store`. If the sender
+/// goes silent before finishing, returns the progress so far, so the transfer can
+/// be resumed later.
+fn receive_image<SRL: Serial, F>(
+    cli: &mut Cli<SRL>,
+    start: InterruptedTransfer,
+    store: F,
+) -> Result<Option<InterruptedTransfer>, Error>
+where
+    F: FnOnce(&mut BlockIterator<SRL>) -> Result<(), ApplicationError>,
+{
+    uprintln!(cli.serial, "Starting XMODEM mode! Send file with your XMODEM client.");
+    let mut blocks =
+        cli.serial.blocks_resuming_from(Some(FLASH_MAX_RETRIES), start.received_blocks);
+    let result = store(&mut blocks);
+    let (completed, received_blocks) = (blocks.completed(), blocks.block_count());
+    drop(blocks);
+    result?;
+
+    if completed {
+        uprintln!(cli.serial, "Image transfer complete!");
+        Ok(None)
+    } else {
+        Ok(Some(InterruptedTransfer { received_blocks, ..start }))
+    }
+}
+
+/// Records an interrupted transfer in its bank, so it can be resumed even after a reset.
+fn record_interrupted_transfer<SRL: Serial, F: Flash>(
+    cli: &mut Cli<SRL>,
+    flash: &mut F,
+    bank: image::Bank<F::Address>,
+    transfer: InterruptedTransfer,
+) -> Result<(), Error> {
+    let (bank_index, received_blocks) = (transfer.bank, transfer.received_blocks);
+    uprintln!(cli.serial, "Transfer interrupted after {} blocks.", received_blocks);
+    match transfer.record(flash, bank) {
+        Ok(()) => {
+            uprintln!(
+                cli.serial,
+                "Resume with `flash bank={} resume_from={}`.",
+                bank_index,
+                received_blocks
+            );
+        }
+        Err(ApplicationError::ImageTooLargeForBank) => {
+            uprintln!(cli.serial, "It reached the end of the bank, so it can't be resumed.");
+        }
+        Err(e) => return Err(Error::ApplicationError(e)),
+    }
+    Ok(())
+}
@@ -7,23 +7,30 @@
 #![macro_use]
 use crate::error::Error as ApplicationError;
 use blue_hal::{
-    hal::serial::{self, Read},
+    hal::{
+        serial::{self, Read},
+        time,
+    },
     uprint, uprintln,
-    utilities::{buffer::TryCollectSlice, iterator::Unique},
+    utilities::{buffer::TryCollectSlice, iterator::Unique, memory::Address},
+};
+use core::{
+    convert::TryInto,
+    str::{from_utf8, SplitWhitespace},
 };
-use core::str::{from_utf8, SplitWhitespace};
 use nb::block;
-use ufmt::{uwrite, uwriteln};
+use sha2::{Digest, Sha256};
+use ufmt::{uDisplay, uWrite, uwrite, uwriteln, Formatter};
 
 use super::{
     boot_manager::BootManager,
+    bootloader::write_within_bank,
+    file_transfer::BLOCK_SIZE,
     image,
     traits::{Flash, Serial},
-    update_signal::WriteUpdateSignal,
+    update_signal::{ReadUpdateSignal, WriteUpdateSignal},
 };
 
-pub mod file_transfer;
-
 const PROMPT: &str = "\n> ";
 const BUFFER_SIZE: usize = 256;
 
@@ -36,10 +43,15 @@ pub enum Error {
     MalformedArguments,
     MissingArgument,
     CharactersNotAllowed,
-    BadCommandEncoding,
+    /// The command is not valid UTF-8, starting at the byte at this position.
+    BadCommandEncoding {
+        position: usize,
+    },
     DuplicateArguments,
     SerialBufferOverflow,
     SerialReadError,
+    CommandLocked,
+    WrongPassword,
     ApplicationError(ApplicationError),
 }
 
@@ -47,6 +59,93 @@ impl From<ApplicationError> for Error {
     fn from(e: ApplicationError) -> Self { Error::ApplicationError(e) }
 }
 
+/// Displays a number as `0x` prefixed, zero padded 32 bit hexadecimal.
+pub struct Hex(pub usize);
+
+impl uDisplay for Hex {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error> {
+        const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+        f.write_str("0x")?;
+        for nibble in (0..8).rev() {
+            f.write_char(DIGITS[(self.0 >> (nibble * 4)) & 0xF] as char)?;
+        }
+        Ok(())
+    }
+}
+
+/// Displays bytes as contiguous lowercase hexadecimal digits, the way host tools such
+/// as `sha256sum` print digests.
+pub struct HexDigest<'a>(pub &'a [u8]);
+
+impl<'a> uDisplay for HexDigest<'a> {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error> {
+        const DIGITS: &[u8; 16] = b"0123456789abcdef";
+        for byte in self.0 {
+            f.write_char(DIGITS[(byte >> 4) as usize] as char)?;
+            f.write_char(DIGITS[(byte & 0xF) as usize] as char)?;
+        }
+        Ok(())
+    }
+}
+
+/// Displays bytes as colon separated pairs of hexadecimal digits.
+pub struct HexBytes<'a>(pub &'a [u8]);
+
+impl<'a> uDisplay for HexBytes<'a> {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error> {
+        const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+        for (index, byte) in self.0.iter().enumerate() {
+            if index > 0 {
+                f.write_char(':')?;
+            }
+            f.write_char(DIGITS[(byte >> 4) as usize] as char)?;
+            f.write_char(DIGITS[(byte & 0xF) as usize] as char)?;
+        }
+        Ok(())
+    }
+}
+
+/// Displays a number right aligned in a column of the given width.
+pub struct RightAligned(pub usize, pub usize);
+
+impl uDisplay for RightAligned {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error> {
+        let RightAligned(value, width) = *self;
+        let digits = core::iter::successors(Some(value), |v| (*v >= 10).then_some(v / 10)).count();
+        for _ in digits..width {
+            f.write_char(' ')?;
+        }
+        uwrite!(f, "{}", value)
+    }
+}
+
+/// Salted SHA-256 hash of the password that unlocks privileged commands.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Credentials {
+    pub salt: [u8; 16],
+    pub hash: [u8; 32],
+}
+
+impl Credentials {
+    /// Whether the password hashes to the stored value. Every byte of the hash is
+    /// compared, so the time taken doesn't reveal how much of it matched.
+    pub fn matches(&self, password: &str) -> bool {
+        let mut hasher = Sha256::new();
+        hasher.update(&self.salt);
+        hasher.update(password.as_bytes());
+        let hash = hasher.finalize();
+        hash.iter().zip(self.hash.iter()).fold(0u8, |difference, (a, b)| difference | (a ^ b)) == 0
+    }
+}
+
+/// Whether a command can run before logging in. Only relevant when the CLI was
+/// given [`Credentials`]; otherwise every command is open.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Access {
+    Open,
+    Privileged,
+}
+
 pub const DEFAULT_GREETING: &str = "--=Loadstone demo app CLI + Boot Manager=--";
 
 /// Command line interface struct, generic over a serial driver. Offers a collection of commands
@@ -55,6 +154,69 @@ pub struct Cli<S: serial::ReadWrite> {
     serial: S,
     greeted: bool,
     needs_prompt: bool,
+    credentials: Option<Credentials>,
+    logged_in: bool,
+}
+
+/// Record of a `flash` transfer that stopped before the sender closed it, kept so the
+/// host can resume it instead of starting over. It's written over the last bytes of the
+/// destination bank, so it survives a reset of the device as well.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct InterruptedTransfer {
+    /// Index of the destination bank.
+    pub bank: u8,
+    /// Number of blocks received and written before the interruption.
+    pub received_blocks: u32,
+}
+
+impl InterruptedTransfer {
+    /// Precedes the number of received blocks in a recorded transfer.
+    const MARKER: [u8; 4] = *b"RSME";
+    const RECORD_SIZE: usize = Self::MARKER.len() + core::mem::size_of::<u32>();
+
+    fn record_offset<A: Address>(bank: image::Bank<A>) -> Result<usize, ApplicationError> {
+        bank.size.checked_sub(Self::RECORD_SIZE).ok_or(ApplicationError::BankInvalid)
+    }
+
+    /// Reads the interrupted transfer recorded in a bank, if any.
+    pub fn recorded<F: Flash>(
+        flash: &mut F,
+        bank: image::Bank<F::Address>,
+    ) -> Result<Option<Self>, ApplicationError> {
+        let mut record = [0u8; Self::RECORD_SIZE];
+        block!(flash.read(bank.location + Self::record_offset(bank)?, &mut record))?;
+        let (marker, received_blocks) = record.split_at(Self::MARKER.len());
+        let received_blocks = u32::from_le_bytes(received_blocks.try_into().unwrap());
+        Ok((marker == Self::MARKER).then_some(Self { bank: bank.index, received_blocks }))
+    }
+
+    /// Records the transfer in its bank. Refused if the received blocks reach into the
+    /// record, as resuming would overwrite it.
+    pub fn record<F: Flash>(
+        &self,
+        flash: &mut F,
+        bank: image::Bank<F::Address>,
+    ) -> Result<(), ApplicationError> {
+        let offset = Self::record_offset(bank)?;
+        if self.received_blocks as usize * BLOCK_SIZE > offset {
+            return Err(ApplicationError::ImageTooLargeForBank);
+        }
+        let mut record = [0u8; Self::RECORD_SIZE];
+        record[..Self::MARKER.len()].copy_from_slice(&Self::MARKER);
+        record[Self::MARKER.len()..].copy_from_slice(&self.received_blocks.to_le_bytes());
+        write_within_bank(flash, bank, offset, &record)
+    }
+
+    /// Clears the transfer recorded in a bank, if any.
+    pub fn clear<F: Flash>(
+        flash: &mut F,
+        bank: image::Bank<F::Address>,
+    ) -> Result<(), ApplicationError> {
+        if Self::recorded(flash, bank)?.is_none() {
+            return Ok(());
+        }
+        write_within_bank(flash, bank, Self::record_offset(bank)?, &[0xFF; Self::RECORD_SIZE])
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -121,6 +283,73 @@ impl<'a> Parsable<'a> for &'a str {
     fn parse(text: &'a str) -> Result<Self, Error> { Ok(text) }
 }
 
+/// Most bytes written by a single `write` command.
+pub const MAX_PATCH_SIZE: usize = 16;
+
+/// Bytes given as contiguous hexadecimal digits, optionally `0x` prefixed (e.g. `A5`
+/// or `0xDEADBEEF`), up to [`MAX_PATCH_SIZE`] of them.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HexPatch {
+    bytes: [u8; MAX_PATCH_SIZE],
+    length: usize,
+}
+
+impl HexPatch {
+    pub fn bytes(&self) -> &[u8] { &self.bytes[..self.length] }
+}
+
+impl<'a> Parsable<'a> for HexPatch {
+    fn parse(text: &'a str) -> Result<Self, Error> {
+        let digits = text.strip_prefix("0x").unwrap_or(text);
+        if digits.is_empty() || digits.len() % 2 != 0 {
+            return Err(Error::MalformedArguments);
+        }
+        let length = digits.len() / 2;
+        if length > MAX_PATCH_SIZE {
+            return Err(Error::ArgumentOutOfRange);
+        }
+        let mut bytes = [0u8; MAX_PATCH_SIZE];
+        for (byte, pair) in bytes.iter_mut().zip(digits.as_bytes().chunks(2)) {
+            let pair = from_utf8(pair).map_err(|_| Error::MalformedArguments)?;
+            *byte = u8::from_str_radix(pair, 16).map_err(|_| Error::MalformedArguments)?;
+        }
+        Ok(HexPatch { bytes, length })
+    }
+}
+
+/// Bank index argument. Parsing only checks that the index is numeric; it is
+/// matched to an actual bank on either flash chip through [`BankRef::resolve`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BankRef(pub u8);
+
+/// A bank referred to by a [`BankRef`], tagged with the flash chip it lives in.
+#[derive(Debug, Copy, Clone)]
+pub enum ResolvedBank<MCUA: Address, EXTA: Address> {
+    Mcu(image::Bank<MCUA>),
+    External(image::Bank<EXTA>),
+}
+
+impl<'a> Parsable<'a> for BankRef {
+    fn parse(text: &'a str) -> Result<Self, Error> { u8::parse(text).map(BankRef) }
+}
+
+impl BankRef {
+    /// Finds the referred bank among the MCU and external banks, in that order.
+    pub fn resolve<MCUA: Address, EXTA: Address>(
+        self,
+        mut mcu_banks: impl Iterator<Item = image::Bank<MCUA>>,
+        mut external_banks: impl Iterator<Item = image::Bank<EXTA>>,
+    ) -> Result<ResolvedBank<MCUA, EXTA>, Error> {
+        if let Some(bank) = mcu_banks.find(|b| b.index == self.0) {
+            Ok(ResolvedBank::Mcu(bank))
+        } else if let Some(bank) = external_banks.find(|b| b.index == self.0) {
+            Ok(ResolvedBank::External(bank))
+        } else {
+            Err(Error::ArgumentOutOfRange)
+        }
+    }
+}
+
 trait RetrieveArgument<T> {
     fn retrieve(&self, name: &str) -> Result<T, Error>;
 }
@@ -169,15 +398,21 @@ impl<'a, T: Parsable<'a>> RetrieveArgument<Option<T>> for ArgumentIterator<'a> {
     }
 }
 
+/// Interprets the bytes of a received line as text, pointing at the first byte that
+/// isn't valid UTF-8 (e.g. line noise) if there is one.
+fn decode_line(line: &[u8]) -> Result<&str, Error> {
+    from_utf8(line).map_err(|e| Error::BadCommandEncoding { position: e.valid_up_to() })
+}
+
 const ARGUMENT_SEPARATOR: char = '=';
 const ALLOWED_TOKENS: &str = " =_";
 const LINE_TERMINATOR: char = '\n';
 
 impl<SRL: Serial> Cli<SRL> {
     /// Reads a line, parses it as a command and attempts to execute it.
-    pub fn run<MCUF: Flash, EXTF: Flash, R: image::Reader, WUS: WriteUpdateSignal>(
+    pub fn run<MCUF: Flash, EXTF: Flash, R: image::Reader, WUS: ReadUpdateSignal + WriteUpdateSignal, T: time::Now>(
         &mut self,
-        boot_manager: &mut BootManager<MCUF, EXTF, SRL, R, WUS>,
+        boot_manager: &mut BootManager<MCUF, EXTF, SRL, R, WUS, T>,
         greeting: &'static str,
     ) {
         if !self.greeted {
@@ -192,16 +427,18 @@ impl<SRL: Serial> Cli<SRL> {
         }
         let mut execute_command = || -> Result<(), Error> {
             let mut buffer = [0u8; BUFFER_SIZE];
-            block!(self.read_line(&mut buffer))?;
-            let text = from_utf8(&buffer).map_err(|_| Error::BadCommandEncoding)?;
+            let received = block!(self.read_line(&mut buffer))?;
+            let text = decode_line(&buffer[..received])?;
             let (name, arguments) = Self::parse(text)?;
             commands::run(self, boot_manager, name, arguments)?;
             Ok(())
         };
         match execute_command() {
-            Err(Error::BadCommandEncoding) => {
-                uwriteln!(self.serial, "[CLI Error] Bad command encoding")
-            }
+            Err(Error::BadCommandEncoding { position }) => uwriteln!(
+                self.serial,
+                "[CLI Error] Bad command encoding (invalid byte at column {})",
+                position + 1
+            ),
             Err(Error::CharactersNotAllowed) => {
                 uwriteln!(self.serial, "[CLI Error] Illegal characters in command")
             }
@@ -229,6 +466,10 @@ impl<SRL: Serial> Cli<SRL> {
                 uwriteln!(self.serial, "[CLI Error] Argument is out of valid range")
             }
             Err(Error::SerialReadError) => uwriteln!(self.serial, "[CLI Error] Serial read failed"),
+            Err(Error::CommandLocked) => {
+                uwriteln!(self.serial, "[CLI Error] Command is locked. Run `login` first")
+            }
+            Err(Error::WrongPassword) => uwriteln!(self.serial, "[CLI Error] Wrong password"),
             Err(Error::CommandUnknown) => uwriteln!(self.serial, "Unknown command"),
             Err(Error::CommandEmpty) => Ok(()),
             Ok(_) => Ok(()),
@@ -238,6 +479,33 @@ impl<SRL: Serial> Cli<SRL> {
         self.needs_prompt = true;
     }
 
+    /// Resolves a bank argument against the boot manager's banks, listing the valid
+    /// bank indices over serial if it doesn't correspond to any of them.
+    fn resolve_bank<
+        MCUF: Flash,
+        EXTF: Flash,
+        R: image::Reader,
+        WUS: ReadUpdateSignal + WriteUpdateSignal,
+        T: time::Now,
+    >(
+        &mut self,
+        boot_manager: &BootManager<MCUF, EXTF, SRL, R, WUS, T>,
+        bank: BankRef,
+    ) -> Result<ResolvedBank<MCUF::Address, EXTF::Address>, Error> {
+        let result = bank.resolve(boot_manager.mcu_banks(), boot_manager.external_banks());
+        if result.is_err() {
+            uprint!(self.serial, "Index supplied does not correspond to any bank. Valid indices:");
+            for bank in boot_manager.mcu_banks() {
+                uprint!(self.serial, " {}", bank.index);
+            }
+            for bank in boot_manager.external_banks() {
+                uprint!(self.serial, " {}", bank.index);
+            }
+            uprintln!(self.serial, "");
+        }
+        result
+    }
+
     /// Returns the serial driver the CLI is using.
     pub fn serial(&mut self) -> &mut SRL { &mut self.serial }
 
@@ -281,16 +549,43 @@ impl<SRL: Serial> Cli<SRL> {
 
     /// Creates a new CLI using the given serial.
     pub fn new(serial: SRL) -> Result<Self, Error> {
-        Ok(Cli { serial, greeted: false, needs_prompt: true })
+        Ok(Cli { serial, greeted: false, needs_prompt: true, credentials: None, logged_in: false })
+    }
+
+    /// Locks privileged commands behind a `login` with the password matching the
+    /// credentials, if any.
+    pub fn with_credentials(self, credentials: Option<Credentials>) -> Self {
+        Self { credentials, ..self }
+    }
+
+    /// Fails with [`Error::CommandLocked`] if a command with the given access level
+    /// can't run until the session is logged in.
+    fn check_access(&self, access: Access) -> Result<(), Error> {
+        match (access, self.credentials) {
+            (Access::Privileged, Some(_)) if !self.logged_in => Err(Error::CommandLocked),
+            _ => Ok(()),
+        }
+    }
+
+    /// Unlocks privileged commands for the rest of the session if the password matches.
+    fn login(&mut self, password: &str) -> Result<(), Error> {
+        self.logged_in = match self.credentials {
+            Some(credentials) => credentials.matches(password),
+            None => true,
+        };
+        self.logged_in.then_some(()).ok_or(Error::WrongPassword)
     }
 
-    fn read_line(&mut self, buffer: &mut [u8]) -> nb::Result<(), Error> {
+    /// Reads a line into a buffer, returning the number of bytes received (excluding
+    /// the line terminator).
+    fn read_line(&mut self, buffer: &mut [u8]) -> nb::Result<usize, Error> {
         let mut bytes = Read::bytes(&mut self.serial).take_while(|element| match element {
             Err(_) => true,
             Ok(b) => *b as char != LINE_TERMINATOR,
         });
-        if bytes.try_collect_slice(buffer).map_err(|_| Error::SerialReadError)? < buffer.len() {
-            Ok(())
+        let received = bytes.try_collect_slice(buffer).map_err(|_| Error::SerialReadError)?;
+        if received < buffer.len() {
+            Ok(received)
         } else {
             Err(nb::Error::Other(Error::SerialBufferOverflow))
         }
@@ -326,11 +621,24 @@ impl<SRL: Serial> Cli<SRL> {
     }
 }
 
+/// Access level of a command, `Open` unless it is tagged otherwise.
+macro_rules! command_access {
+    () => {
+        Access::Open
+    };
+    ($access:ident) => {
+        Access::$access
+    };
+}
+
+/// Defines the CLI commands. Each command is listed with its help string, an optional
+/// [`Access`] tag (e.g. `Privileged`), its arguments with their help strings, and the
+/// block that runs it.
 macro_rules! commands {
     (
         $cli:ident, $boot_manager:ident, $names:ident, $helpstrings:ident [
             $(
-                $c:ident[$h:expr]($($a:ident: $t:ty [$r:expr],)*) $command:block,
+                $c:ident[$h:expr] $($access:ident)? ($($a:ident: $t:ty [$r:expr],)*) $command:block,
             )+
         ]
     ) => {
@@ -349,15 +657,26 @@ macro_rules! commands {
             )+
         ];
 
+        /// Access level of a command, or `None` if there is no such command.
+        pub(super) fn access(name: Name) -> Option<Access> {
+            match name {
+                $(
+                    stringify!($c) => Some(command_access!($($access)?)),
+                )+
+                _ => None,
+            }
+        }
+
         #[allow(unreachable_code)]
-        pub(super) fn run<MCUF: Flash, EXTF: Flash, SRL: Serial, R: image::Reader, WUS: WriteUpdateSignal>(
+        pub(super) fn run<MCUF: Flash, EXTF: Flash, SRL: Serial, R: image::Reader, WUS: ReadUpdateSignal + WriteUpdateSignal, T: blue_hal::hal::time::Now>(
             $cli: &mut Cli<SRL>,
-            $boot_manager: &mut BootManager<MCUF, EXTF, SRL, R, WUS>,
+            $boot_manager: &mut BootManager<MCUF, EXTF, SRL, R, WUS, T>,
             name: Name, arguments: ArgumentIterator) -> Result<(), Error>
         {
             match name {
                 $(
                     stringify!($c) => {
+                        $cli.check_access(command_access!($($access)?))?;
                         if arguments.clone().any(|_a| true $(&& _a.name() != stringify!($a))*) {
                             return Err(Error::UnexpectedArguments);
                         }
@@ -380,14 +699,9 @@ mod commands;
 
 #[cfg(test)]
 mod test {
-    use crate::error::Convertible;
-
     use super::*;
-    use blue_hal::hal::doubles::serial::*;
-
-    impl Convertible for SerialStubError {
-        fn into(self) -> ApplicationError { ApplicationError::DeviceError("Serial stub failed") }
-    }
+    use blue_hal::hal::doubles::{flash::Address as FlashAddress, serial::*};
+    use core::iter;
 
     #[test]
     fn basic_command_parsing() {
@@ -425,4 +739,153 @@ mod test {
             Cli::<SerialStub>::parse(bad_command_characters_not_allowed).err().unwrap()
         );
     }
+
+    #[test]
+    fn only_the_received_part_of_the_line_buffer_is_decoded() {
+        let mut buffer = [0u8; BUFFER_SIZE];
+        let line = b"banks";
+        buffer[..line.len()].copy_from_slice(line);
+        buffer[line.len() + 1] = 0xFF;
+        assert_eq!(Ok("banks"), decode_line(&buffer[..line.len()]));
+        let (name, mut arguments) =
+            Cli::<SerialStub>::parse(decode_line(&buffer[..line.len()]).unwrap()).unwrap();
+        assert_eq!("banks", name);
+        assert_eq!(None, arguments.next());
+    }
+
+    #[test]
+    fn invalid_bytes_are_reported_at_their_position() {
+        let line = b"flash ba\xFFnk=2";
+        assert_eq!(Err(Error::BadCommandEncoding { position: 8 }), decode_line(line));
+        // Multi-byte characters are valid encoding, even if not allowed in commands.
+        let line = "flash bank=\u{e9}".as_bytes();
+        let parsed = Cli::<SerialStub>::parse(decode_line(line).unwrap());
+        assert_eq!(Some(Error::CharactersNotAllowed), parsed.err());
+        // A truncated multi-byte character points at its first byte.
+        assert_eq!(Err(Error::BadCommandEncoding { position: 11 }), decode_line(&line[..12]));
+    }
+
+    #[rustfmt::skip]
+    static MCU_BANKS: [image::Bank<FlashAddress>; 2] = [
+        image::Bank { index: 1, size: 0x1000, location: FlashAddress(0x0000), bootable: true, is_golden: false },
+        image::Bank { index: 2, size: 0x1000, location: FlashAddress(0x1000), bootable: false, is_golden: false },
+    ];
+
+    #[rustfmt::skip]
+    static EXTERNAL_BANKS: [image::Bank<FlashAddress>; 1] = [
+        image::Bank { index: 3, size: 0x1000, location: FlashAddress(0x0000), bootable: false, is_golden: true },
+    ];
+
+    #[test]
+    fn bank_arguments_resolve_across_flash_chips() {
+        let (command, arguments) = Cli::<SerialStub>::parse("flash bank=2").unwrap();
+        assert_eq!("flash", command);
+        let bank: BankRef = arguments.retrieve("bank").unwrap();
+        assert!(matches!(
+            bank.resolve(MCU_BANKS.iter().cloned(), EXTERNAL_BANKS.iter().cloned()),
+            Ok(ResolvedBank::Mcu(image::Bank { index: 2, .. }))
+        ));
+        assert!(matches!(
+            BankRef(3).resolve(MCU_BANKS.iter().cloned(), EXTERNAL_BANKS.iter().cloned()),
+            Ok(ResolvedBank::External(image::Bank { index: 3, .. }))
+        ));
+    }
+
+    #[test]
+    fn out_of_range_bank_arguments_fail_to_resolve() {
+        assert!(matches!(
+            BankRef(4).resolve(MCU_BANKS.iter().cloned(), EXTERNAL_BANKS.iter().cloned()),
+            Err(Error::ArgumentOutOfRange)
+        ));
+        assert!(matches!(
+            BankRef(0)
+                .resolve(MCU_BANKS.iter().cloned(), iter::empty::<image::Bank<FlashAddress>>()),
+            Err(Error::ArgumentOutOfRange)
+        ));
+    }
+
+    #[test]
+    fn interrupted_transfers_are_recorded_in_their_bank() {
+        use blue_hal::hal::{doubles::flash::FakeFlash, flash::ReadWrite};
+        let mut flash = FakeFlash::new(FlashAddress(0));
+        let bank = MCU_BANKS[1];
+        flash.write(bank.location, &[0xFFu8; 0x1000]).unwrap();
+        assert_eq!(Ok(None), InterruptedTransfer::recorded(&mut flash, bank));
+
+        let transfer = InterruptedTransfer { bank: 2, received_blocks: 7 };
+        transfer.record(&mut flash, bank).unwrap();
+        assert_eq!(Ok(Some(transfer)), InterruptedTransfer::recorded(&mut flash, bank));
+        InterruptedTransfer::clear(&mut flash, bank).unwrap();
+        assert_eq!(Ok(None), InterruptedTransfer::recorded(&mut flash, bank));
+
+        // A transfer that already reached the last bytes of the bank can't be recorded.
+        let received_blocks = (0x1000 / BLOCK_SIZE) as u32;
+        let transfer = InterruptedTransfer { bank: 2, received_blocks };
+        assert_eq!(Err(ApplicationError::ImageTooLargeForBank), transfer.record(&mut flash, bank));
+    }
+
+    #[test]
+    fn hex_patches_parse_whole_bytes_within_the_size_limit() {
+        let (_, arguments) = Cli::<SerialStub>::parse("write byte=0xdeAD01 bank=2").unwrap();
+        let patch: HexPatch = arguments.retrieve("byte").unwrap();
+        assert_eq!(&[0xDE, 0xAD, 0x01], patch.bytes());
+        assert_eq!(&[0x0F], HexPatch::parse("0f").unwrap().bytes());
+
+        assert_eq!(Err(Error::MalformedArguments), HexPatch::parse("ABC"));
+        assert_eq!(Err(Error::MalformedArguments), HexPatch::parse("0x"));
+        assert_eq!(Err(Error::MalformedArguments), HexPatch::parse("GG"));
+        assert_eq!(Ok(MAX_PATCH_SIZE), HexPatch::parse(&"AB".repeat(16)).map(|p| p.length));
+        assert_eq!(Err(Error::ArgumentOutOfRange), HexPatch::parse(&"AB".repeat(17)));
+    }
+
+    fn credentials(password: &str) -> Credentials {
+        let salt = [0x5Au8; 16];
+        let mut hasher = Sha256::new();
+        hasher.update(&salt);
+        hasher.update(password.as_bytes());
+        Credentials { salt, hash: hasher.finalize().into() }
+    }
+
+    #[test]
+    fn commands_are_tagged_with_their_access_level() {
+        for open in ["help", "banks", "metrics", "login", "logout"].iter() {
+            assert_eq!(Some(Access::Open), commands::access(open), "{}", open);
+        }
+        for privileged in ["flash", "export", "format", "corrupt_body", "factory_reset"].iter() {
+            assert_eq!(Some(Access::Privileged), commands::access(privileged), "{}", privileged);
+        }
+        assert_eq!(None, commands::access("nonexistent"));
+    }
+
+    #[test]
+    fn privileged_commands_are_locked_until_login() {
+        let mut cli =
+            Cli::new(SerialStub).unwrap().with_credentials(Some(credentials("open_sesame")));
+        assert_eq!(Ok(()), cli.check_access(Access::Open));
+        assert_eq!(Err(Error::CommandLocked), cli.check_access(Access::Privileged));
+
+        assert_eq!(Err(Error::WrongPassword), cli.login("open_sesam"));
+        assert_eq!(Err(Error::CommandLocked), cli.check_access(Access::Privileged));
+
+        assert_eq!(Ok(()), cli.login("open_sesame"));
+        assert_eq!(Ok(()), cli.check_access(Access::Privileged));
+
+        // A failed attempt after logging in locks the session again.
+        assert_eq!(Err(Error::WrongPassword), cli.login("guess"));
+        assert_eq!(Err(Error::CommandLocked), cli.check_access(Access::Privileged));
+    }
+
+    #[test]
+    fn every_command_is_open_without_credentials() {
+        let cli = Cli::new(SerialStub).unwrap();
+        assert_eq!(Ok(()), cli.check_access(Access::Privileged));
+    }
+
+    #[test]
+    fn non_numeric_bank_arguments_are_malformed() {
+        let (_, arguments) = Cli::<SerialStub>::parse("flash bank=golden").unwrap();
+        let bank: Result<BankRef, _> = arguments.retrieve("bank");
+        assert_eq!(Err(Error::MalformedArguments), bank);
+        assert_eq!(Err(Error::MalformedArguments), BankRef::parse("300"));
+    }
 }
@@ -0,0 +1,60 @@
+//! Test doubles shared by the devices that talk over serial.
+
+use crate::error;
+use blue_hal::hal::{serial, time};
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+#[derive(Copy, Clone, Debug)]
+pub struct LinkSilent;
+
+/// Serial double that plays back the bytes a host would send, then goes silent.
+/// Everything written to it is kept in a [`Transcript`].
+#[derive(Default)]
+pub struct ScriptedSerial {
+    pub incoming: VecDeque<u8>,
+    pub transcript: Transcript,
+}
+
+/// Record of everything written to a [`ScriptedSerial`], shared with the test so it
+/// can be read once the device under test is done with the serial.
+#[derive(Clone, Default)]
+pub struct Transcript(Rc<RefCell<Vec<u8>>>);
+
+impl Transcript {
+    pub fn contains(&self, text: &str) -> bool {
+        String::from_utf8_lossy(&self.0.borrow()).contains(text)
+    }
+
+    pub fn bytes(&self) -> Vec<u8> { self.0.borrow().clone() }
+}
+
+impl serial::Read for ScriptedSerial {
+    type Error = LinkSilent;
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        self.incoming.pop_front().ok_or(nb::Error::WouldBlock)
+    }
+}
+
+impl serial::TimeoutRead for ScriptedSerial {
+    type Error = LinkSilent;
+    fn read<T: Copy + Into<time::Milliseconds>>(&mut self, _: T) -> Result<u8, Self::Error> {
+        self.incoming.pop_front().ok_or(LinkSilent)
+    }
+}
+
+impl serial::Write for ScriptedSerial {
+    type Error = LinkSilent;
+    fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
+        self.transcript.0.borrow_mut().extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+    fn write_char(&mut self, c: char) -> Result<(), Self::Error> {
+        assert!((c as u32) <= 0xFF, "{:?} doesn't fit in a byte", c);
+        self.transcript.0.borrow_mut().push(c as u8);
+        Ok(())
+    }
+}
+
+impl error::Convertible for LinkSilent {
+    fn into(self) -> error::Error { error::Error::DeviceError("Scripted serial went silent") }
+}
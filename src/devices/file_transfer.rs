@@ -0,0 +1,415 @@
+//! XMODEM file transfer implementation.
+//!
+//! Provides methods to receive arbitrary byte streams through serial
+//! via the XMODEM protocol.
+//!
+//! XMODEM has no native way to resume an interrupted transfer, so Loadstone
+//! layers a small handshake on top of it:
+//!
+//! * The receiver keeps count of the blocks it has received. When a transfer
+//!   ends without an end of transmission message (e.g. the link dropped), that
+//!   count is kept as the resume point for the destination bank.
+//! * The host queries the resume point through the `resume_info` command, then
+//!   restarts the transfer with `flash bank=<index> resume_from=<block>`.
+//! * Both sides skip ahead: the receiver expects block number `block + 1` and
+//!   writes from byte `block * BLOCK_SIZE` of the bank, and the sender
+//!   ([`XModemSession::resume_from`]) starts numbering from the same block.
+//!
+//! Transfers also run the other way, from the device to the host, through
+//! [`XModemSender`]: the host's XMODEM client receives, and Loadstone sends.
+
+use super::ymodem::CAN;
+use crate::error::Error;
+use blue_hal::{
+    hal::serial::{TimeoutRead, Write},
+    utilities::xmodem,
+};
+
+/// The size of a single byte block retrieved from an XMODEM stream.
+pub const BLOCK_SIZE: usize = xmodem::PAYLOAD_SIZE;
+
+const SEND_TIMED_OUT: Error = Error::DeviceError("XMODEM send timed out");
+const SEND_CANCELLED: Error = Error::DeviceError("XMODEM send cancelled by the receiver");
+
+/// Serial protocol used to receive images in recovery mode.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Protocol {
+    XModem,
+    /// See [`ymodem`](super::ymodem). Rejects images larger than the destination bank
+    /// before writing anything.
+    YModem,
+}
+
+impl Protocol {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Protocol::XModem => "XMODEM",
+            Protocol::YModem => "YMODEM",
+        }
+    }
+}
+
+/// Generic file transfer iterator trait, returning an iterator over byte blocks.
+pub trait FileTransfer: TimeoutRead + Write {
+    fn blocks(&mut self, max_retries: Option<u32>) -> BlockIterator<Self> {
+        self.blocks_resuming_from(max_retries, 0)
+    }
+
+    /// Returns an iterator over the blocks of a transfer that was interrupted after
+    /// `received_blocks` blocks, expecting the sender to continue from the next one.
+    fn blocks_resuming_from(
+        &mut self,
+        max_retries: Option<u32>,
+        received_blocks: u32,
+    ) -> BlockIterator<Self> {
+        BlockIterator {
+            serial: self,
+            received_block: false,
+            finished: false,
+            completed: false,
+            block_number: received_blocks as u8,
+            block_count: received_blocks,
+            max_retries,
+        }
+    }
+}
+
+impl<T: TimeoutRead + Write> FileTransfer for T {}
+
+/// Generic iterator over byte blocks.
+pub struct BlockIterator<'a, S: TimeoutRead + Write + ?Sized> {
+    serial: &'a mut S,
+    received_block: bool,
+    finished: bool,
+    completed: bool,
+    block_number: u8,
+    block_count: u32,
+    max_retries: Option<u32>,
+}
+
+impl<'a, S: TimeoutRead + Write + ?Sized> Iterator for BlockIterator<'a, S> {
+    type Item = [u8; BLOCK_SIZE];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let mut retries = 0;
+        let mut buffer = [0u8; xmodem::MAX_PACKET_SIZE];
+
+        'block_loop: while self.max_retries.is_none() || retries < self.max_retries.unwrap() {
+            let mut buffer_index = 0usize;
+
+            let message = if self.received_block { xmodem::ACK } else { xmodem::NAK };
+            if self.serial.write_char(message as char).is_err() {
+                retries += 1;
+                continue 'block_loop;
+            }
+            self.received_block = false;
+
+            loop {
+                buffer[buffer_index] = match self.serial.read(xmodem::DEFAULT_TIMEOUT) {
+                    Ok(byte) => byte,
+                    Err(_) => {
+                        retries += 1;
+                        continue 'block_loop;
+                    }
+                };
+
+                if buffer_index == 0 || buffer_index == (xmodem::MAX_PACKET_SIZE - 1) {
+                    if let Some(block) = self.process_message(&buffer) {
+                        self.received_block = true;
+                        return Some(block);
+                    }
+
+                    if self.finished {
+                        return None;
+                    }
+                }
+                buffer_index += 1;
+                if buffer_index == xmodem::MAX_PACKET_SIZE {
+                    continue 'block_loop;
+                }
+            }
+        }
+
+        // Fully timed out
+        self.finished = true;
+        None
+    }
+}
+
+impl<'a, S: TimeoutRead + Write + ?Sized> BlockIterator<'a, S> {
+    /// Total blocks received so far, including those received before resuming.
+    pub fn block_count(&self) -> u32 { self.block_count }
+
+    /// Whether the sender closed the transfer cleanly, as opposed to the transfer
+    /// being abandoned after too many timeouts or errors.
+    pub fn completed(&self) -> bool { self.completed }
+
+    fn process_message(&mut self, buffer: &[u8]) -> Option<[u8; BLOCK_SIZE]> {
+        match xmodem::parse_message(&buffer) {
+            Ok((_, xmodem::Message::EndOfTransmission)) => {
+                self.end_transmission();
+                None
+            }
+            Ok((_, xmodem::Message::Chunk(chunk))) => {
+                if let Some(block) = self.process_chunk(chunk) {
+                    self.block_number = self.block_number.wrapping_add(1);
+                    self.block_count += 1;
+                    Some(block)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn process_chunk(&self, chunk: xmodem::Chunk) -> Option<[u8; BLOCK_SIZE]> {
+        let next_block = self.block_number.wrapping_add(1);
+        (chunk.block_number == next_block).then_some(chunk.payload)
+    }
+
+    fn end_transmission(&mut self) {
+        self.finished = true;
+        self.completed = true;
+        if self.serial.write_char(xmodem::ACK as char).is_err() {
+            return;
+        }
+        if let Ok(xmodem::ETB) = self.serial.read(xmodem::DEFAULT_TIMEOUT) {
+            // We don't care about this being received, as there's no
+            // recovering from a failure here.
+            let _ = self.serial.write_char(xmodem::ACK as char);
+        }
+    }
+}
+
+impl<'a, S: TimeoutRead + Write + ?Sized> Drop for BlockIterator<'a, S> {
+    // Must fully consume the iterator on drop
+    // to close the xmodem communication cleanly
+    fn drop(&mut self) { self.for_each(drop); }
+}
+
+/// Sending side of an XMODEM transfer, for host tooling talking to Loadstone.
+/// Wraps payload blocks into numbered, checksummed XMODEM packets.
+pub struct XModemSession {
+    block_number: u8,
+}
+
+impl Default for XModemSession {
+    fn default() -> Self { Self::new() }
+}
+
+impl XModemSession {
+    pub fn new() -> Self { Self { block_number: 0 } }
+
+    /// Skips ahead past the first `block` blocks of a transfer, as reported by the
+    /// receiver's `resume_info` command. The next packet will carry block `block + 1`.
+    pub fn resume_from(&mut self, block: u32) { self.block_number = block as u8; }
+
+    /// Wraps the next block of the file into an XMODEM packet.
+    pub fn packet(&mut self, payload: &[u8; BLOCK_SIZE]) -> [u8; xmodem::MAX_PACKET_SIZE] {
+        self.block_number = self.block_number.wrapping_add(1);
+        let mut packet = [0u8; xmodem::MAX_PACKET_SIZE];
+        packet[0] = xmodem::SOH;
+        packet[1] = self.block_number;
+        packet[2] = !self.block_number;
+        packet[3..3 + BLOCK_SIZE].copy_from_slice(payload);
+        packet[3 + BLOCK_SIZE] = payload.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        packet
+    }
+}
+
+/// Sending side of an XMODEM transfer run by the device, the inverse of [`BlockIterator`].
+/// Blocks are sent one at a time, so they can be read from flash as the transfer goes.
+///
+/// Packets go out one `char` per byte, which Loadstone's serial drivers transmit as
+/// that single byte.
+pub struct XModemSender<'a, S: TimeoutRead + Write + ?Sized> {
+    serial: &'a mut S,
+    session: XModemSession,
+    max_retries: u32,
+    block_count: u32,
+}
+
+impl<'a, S: TimeoutRead + Write + ?Sized> XModemSender<'a, S> {
+    /// Waits for the receiver to open the transfer with a `NAK`, for up to `max_retries`
+    /// read timeouts. The same number of attempts is allowed for every packet after it.
+    pub fn start(serial: &'a mut S, max_retries: u32) -> Result<Self, Error> {
+        for _ in 0..max_retries {
+            match serial.read(xmodem::DEFAULT_TIMEOUT) {
+                Ok(xmodem::NAK) => {
+                    return Ok(Self {
+                        serial,
+                        session: XModemSession::new(),
+                        max_retries,
+                        block_count: 0,
+                    })
+                }
+                Ok(CAN) => return Err(SEND_CANCELLED),
+                _ => {}
+            }
+        }
+        Err(SEND_TIMED_OUT)
+    }
+
+    /// Sends the next block, resending it until the receiver acknowledges it.
+    pub fn send(&mut self, block: &[u8; BLOCK_SIZE]) -> Result<(), Error> {
+        let packet = self.session.packet(block);
+        self.transmit(&packet)?;
+        self.block_count += 1;
+        Ok(())
+    }
+
+    /// Closes the transfer with an end of transmission. Returns the number of blocks sent.
+    pub fn finish(mut self) -> Result<u32, Error> {
+        self.transmit(&[xmodem::EOT])?;
+        Ok(self.block_count)
+    }
+
+    /// Abandons the transfer, so the receiver stops waiting for more blocks.
+    pub fn cancel(self) {
+        // There's no recovering from a failure here.
+        let _ = self.serial.write_char(CAN as char);
+        let _ = self.serial.write_char(CAN as char);
+    }
+
+    /// Blocks acknowledged by the receiver so far.
+    pub fn block_count(&self) -> u32 { self.block_count }
+
+    /// Writes a packet until the receiver acknowledges it. Anything other than an `ACK`
+    /// or a `CAN` (e.g. a `NAK`, or no answer at all) is met by writing it again.
+    fn transmit(&mut self, packet: &[u8]) -> Result<(), Error> {
+        for _ in 0..self.max_retries {
+            if packet.iter().any(|byte| self.serial.write_char(*byte as char).is_err()) {
+                continue;
+            }
+            match self.serial.read(xmodem::DEFAULT_TIMEOUT) {
+                Ok(xmodem::ACK) => return Ok(()),
+                Ok(CAN) => return Err(SEND_CANCELLED),
+                _ => {}
+            }
+        }
+        Err(SEND_TIMED_OUT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::doubles::ScriptedSerial;
+    use std::collections::VecDeque;
+
+    fn sample_image() -> Vec<[u8; BLOCK_SIZE]> {
+        (0..5u8).map(|i| [i.wrapping_mul(37).wrapping_add(1); BLOCK_SIZE]).collect()
+    }
+
+    fn transmit(
+        session: &mut XModemSession,
+        blocks: &[[u8; BLOCK_SIZE]],
+        finish: bool,
+    ) -> ScriptedSerial {
+        let mut incoming: VecDeque<u8> =
+            blocks.iter().flat_map(|block| session.packet(block)).collect();
+        if finish {
+            incoming.push_back(xmodem::EOT);
+        }
+        ScriptedSerial { incoming, ..Default::default() }
+    }
+
+    /// Host that opens the transfer, then answers every packet with the given replies.
+    fn host(replies: &[u8]) -> ScriptedSerial {
+        let incoming = core::iter::once(xmodem::NAK).chain(replies.iter().copied()).collect();
+        ScriptedSerial { incoming, ..Default::default() }
+    }
+
+    #[test]
+    fn interrupted_transfer_resumes_into_full_image() {
+        let image = sample_image();
+        let mut received = Vec::new();
+
+        // The link drops after the first three blocks
+        let mut session = XModemSession::new();
+        let mut serial = transmit(&mut session, &image[..3], false);
+        let mut blocks = serial.blocks(Some(2));
+        received.extend(blocks.by_ref());
+        assert!(!blocks.completed());
+        let resume_point = blocks.block_count();
+        drop(blocks);
+        assert_eq!(3, resume_point);
+
+        // A new session picks up where the receiver left off
+        let mut session = XModemSession::new();
+        session.resume_from(resume_point);
+        let mut serial = transmit(&mut session, &image[resume_point as usize..], true);
+        let mut blocks = serial.blocks_resuming_from(Some(2), resume_point);
+        received.extend(blocks.by_ref());
+        assert!(blocks.completed());
+        assert_eq!(5, blocks.block_count());
+        drop(blocks);
+
+        assert_eq!(image, received);
+    }
+
+    #[test]
+    fn resumed_blocks_are_rejected_by_a_fresh_transfer() {
+        let image = sample_image();
+        let mut session = XModemSession::new();
+        session.resume_from(3);
+        let mut serial = transmit(&mut session, &image[3..], true);
+        assert_eq!(0, serial.blocks(Some(2)).count());
+    }
+
+    #[test]
+    fn sent_blocks_are_received_in_full_by_the_host() {
+        let image = sample_image();
+        let mut serial = host(&[xmodem::ACK; 6]);
+        let mut sender = XModemSender::start(&mut serial, 2).unwrap();
+        image.iter().for_each(|block| sender.send(block).unwrap());
+        assert_eq!(Ok(5), sender.finish());
+
+        // The host saw the same packets a host side session would have produced.
+        let mut session = XModemSession::new();
+        let mut expected: Vec<u8> = image.iter().flat_map(|block| session.packet(block)).collect();
+        expected.push(xmodem::EOT);
+        assert_eq!(expected, serial.transcript.bytes());
+
+        // So feeding them to a receiver yields the original blocks.
+        let mut receiver =
+            ScriptedSerial { incoming: serial.transcript.bytes().into(), ..Default::default() };
+        let received: Vec<_> = receiver.blocks(Some(2)).collect();
+        assert_eq!(image, received);
+    }
+
+    #[test]
+    fn rejected_blocks_are_sent_again() {
+        let image = sample_image();
+        let mut serial = host(&[xmodem::NAK, xmodem::ACK, xmodem::ACK]);
+        let mut sender = XModemSender::start(&mut serial, 2).unwrap();
+        sender.send(&image[0]).unwrap();
+        assert_eq!(1, sender.block_count());
+        assert_eq!(Ok(1), sender.finish());
+
+        let packet = XModemSession::new().packet(&image[0]);
+        assert_eq!([&packet[..], &packet[..], &[xmodem::EOT]].concat(), serial.transcript.bytes());
+    }
+
+    #[test]
+    fn silent_or_cancelling_hosts_abort_the_send() {
+        let image = sample_image();
+        let mut serial = ScriptedSerial::default();
+        assert_eq!(Some(SEND_TIMED_OUT), XModemSender::start(&mut serial, 2).err());
+
+        // The host stops answering after the first block.
+        let mut serial = host(&[xmodem::ACK]);
+        let mut sender = XModemSender::start(&mut serial, 2).unwrap();
+        sender.send(&image[0]).unwrap();
+        assert_eq!(Err(SEND_TIMED_OUT), sender.send(&image[1]));
+
+        let mut serial = host(&[CAN]);
+        let mut sender = XModemSender::start(&mut serial, 2).unwrap();
+        assert_eq!(Err(SEND_CANCELLED), sender.send(&image[0]));
+    }
+}
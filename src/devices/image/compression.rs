@@ -0,0 +1,230 @@
+//! Decompression of golden images stored in compressed form.
+//!
+//! A golden bank may hold a smaller, compressed version of a complete firmware image,
+//! which is expanded into the boot bank when restoring from it. A compressed golden
+//! image is laid out as follows:
+//!
+//! * A [`HEADER_SIZE`] byte header: the [`COMPRESSION_MAGIC`] followed by the size of
+//!   the decompressed image, as a little endian `u32`.
+//! * The compressed stream, containing a complete, decorated and signed image.
+//! * The usual golden string, magic string and CRC/Signature, covering the two above.
+//!
+//! The outer trailer is untouched by compression, so the golden bank is scanned and
+//! verified like any other, and golden detection still relies on [`GOLDEN_STRING`].
+//! The decompressed image carries its own trailer, and is verified again once in the
+//! boot bank.
+//!
+//! The stream uses a PackBits style run length encoding. Each run starts with a
+//! control byte `n`:
+//!
+//! * `0..=127`: the next `n + 1` bytes are copied literally.
+//! * `129..=255`: the next byte is repeated `257 - n` times.
+//! * `128`: no operation.
+//!
+//! The signing tool never emits literal runs longer than [`MAX_LITERAL_RUN`] bytes.
+//! Control bytes then break up the embedded image's magic string, which could
+//! otherwise appear intact in the stream and truncate the outer image.
+//!
+//! [`GOLDEN_STRING`]: super::GOLDEN_STRING
+
+use crate::error::Error;
+use core::mem::size_of;
+
+/// Identifies a golden image body as compressed.
+pub const COMPRESSION_MAGIC: [u8; 4] = *b"LSZ1";
+
+/// Size of the header preceding the compressed stream.
+pub const HEADER_SIZE: usize = COMPRESSION_MAGIC.len() + size_of::<u32>();
+
+/// Longest literal run the signing tool emits.
+pub const MAX_LITERAL_RUN: usize = 16;
+
+/// Returns the decompressed size announced by a header, or `None` if the
+/// bytes don't belong to a compressed image.
+pub fn decompressed_size(header: &[u8; HEADER_SIZE]) -> Option<usize> {
+    let (magic, size) = header.split_at(COMPRESSION_MAGIC.len());
+    if magic != COMPRESSION_MAGIC {
+        return None;
+    }
+    let mut size_bytes = [0u8; size_of::<u32>()];
+    size_bytes.copy_from_slice(size);
+    Some(u32::from_le_bytes(size_bytes) as usize)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum State {
+    Control,
+    Literal(usize),
+    Repeat(usize),
+}
+
+/// Expands a compressed stream fed to it in arbitrary chunks, handing the output to a
+/// writer `N` bytes at a time.
+pub struct Inflater<const N: usize> {
+    state: State,
+    buffer: [u8; N],
+    pending: usize,
+    written: usize,
+    expected: usize,
+}
+
+impl<const N: usize> Inflater<N> {
+    /// Creates an inflater for a stream that must expand to exactly `expected` bytes.
+    pub fn new(expected: usize) -> Self {
+        Self { state: State::Control, buffer: [0u8; N], pending: 0, written: 0, expected }
+    }
+
+    /// Expands the next chunk of the compressed stream. `write` receives full
+    /// buffers along with their offset into the decompressed image.
+    pub fn inflate<W>(&mut self, compressed: &[u8], mut write: W) -> Result<(), Error>
+    where
+        W: FnMut(usize, &[u8]) -> Result<(), Error>,
+    {
+        for &byte in compressed {
+            let (value, count) = match self.state {
+                State::Control => {
+                    self.state = match byte {
+                        0..=127 => State::Literal(byte as usize + 1),
+                        128 => State::Control,
+                        _ => State::Repeat(257 - byte as usize),
+                    };
+                    continue;
+                }
+                State::Literal(remaining) => {
+                    self.state =
+                        if remaining > 1 { State::Literal(remaining - 1) } else { State::Control };
+                    (byte, 1)
+                }
+                State::Repeat(count) => {
+                    self.state = State::Control;
+                    (byte, count)
+                }
+            };
+            for _ in 0..count {
+                self.push(value, &mut write)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes any remaining output, and checks the stream expanded to the expected size.
+    pub fn finish<W>(mut self, mut write: W) -> Result<(), Error>
+    where
+        W: FnMut(usize, &[u8]) -> Result<(), Error>,
+    {
+        if self.pending > 0 {
+            write(self.written, &self.buffer[..self.pending])?;
+            self.written += self.pending;
+        }
+        if self.state == State::Control && self.written == self.expected {
+            Ok(())
+        } else {
+            Err(Error::DecompressionFailed)
+        }
+    }
+
+    fn push<W>(&mut self, byte: u8, write: &mut W) -> Result<(), Error>
+    where
+        W: FnMut(usize, &[u8]) -> Result<(), Error>,
+    {
+        if self.written + self.pending == self.expected {
+            return Err(Error::DecompressionFailed);
+        }
+        self.buffer[self.pending] = byte;
+        self.pending += 1;
+        if self.pending == N {
+            write(self.written, &self.buffer)?;
+            self.written += N;
+            self.pending = 0;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Compresses data the way the signing tool does, header included.
+    pub(crate) fn compress(data: &[u8]) -> Vec<u8> {
+        let mut output = COMPRESSION_MAGIC.to_vec();
+        output.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        let mut literals: Vec<u8> = vec![];
+        let flush = |literals: &mut Vec<u8>, output: &mut Vec<u8>| {
+            if !literals.is_empty() {
+                output.push(literals.len() as u8 - 1);
+                output.append(literals);
+            }
+        };
+        let mut index = 0;
+        while index < data.len() {
+            let run = data[index..].iter().take(128).take_while(|b| **b == data[index]).count();
+            if run > 1 {
+                flush(&mut literals, &mut output);
+                output.extend_from_slice(&[(257 - run) as u8, data[index]]);
+            } else {
+                literals.push(data[index]);
+                if literals.len() == MAX_LITERAL_RUN {
+                    flush(&mut literals, &mut output);
+                }
+            }
+            index += run;
+        }
+        flush(&mut literals, &mut output);
+        output
+    }
+
+    fn decompress<const N: usize>(compressed: &[u8], chunk: usize) -> Result<Vec<u8>, Error> {
+        let mut header = [0u8; HEADER_SIZE];
+        header.copy_from_slice(&compressed[..HEADER_SIZE]);
+        let mut inflater = Inflater::<N>::new(decompressed_size(&header).unwrap());
+        let mut output = vec![];
+        let mut write = |offset: usize, bytes: &[u8]| -> Result<(), Error> {
+            assert_eq!(offset, output.len());
+            output.extend_from_slice(bytes);
+            Ok(())
+        };
+        for piece in compressed[HEADER_SIZE..].chunks(chunk) {
+            inflater.inflate(piece, &mut write)?;
+        }
+        inflater.finish(&mut write)?;
+        Ok(output)
+    }
+
+    #[test]
+    fn known_stream_decompresses_correctly() {
+        let mut compressed = COMPRESSION_MAGIC.to_vec();
+        compressed.extend_from_slice(&9u32.to_le_bytes());
+        compressed.extend_from_slice(&[0x02, b'a', b'b', b'c', 0xFD, 0xFF, 128, 0x01, b'd', b'e']);
+        assert_eq!(decompress::<4>(&compressed, 3).unwrap(), b"abc\xFF\xFF\xFF\xFFde");
+    }
+
+    #[test]
+    fn compressed_data_round_trips_across_chunk_sizes() {
+        let mut data: Vec<u8> = (0..1000u32).map(|i| (i * 31 % 7) as u8).collect();
+        data.extend_from_slice(&[0xFF; 700]);
+        data.extend_from_slice(b"tail");
+        let compressed = compress(&data);
+        assert!(compressed.len() < data.len());
+        for chunk in [1, 7, 64, compressed.len()].iter() {
+            assert_eq!(decompress::<64>(&compressed, *chunk).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn stream_expanding_to_the_wrong_size_is_rejected() {
+        let mut compressed = compress(b"some firmware");
+        compressed[COMPRESSION_MAGIC.len()] += 1;
+        assert_eq!(decompress::<8>(&compressed, 4), Err(Error::DecompressionFailed));
+
+        let mut compressed = compress(b"some firmware");
+        compressed[COMPRESSION_MAGIC.len()] -= 1;
+        assert_eq!(decompress::<8>(&compressed, 4), Err(Error::DecompressionFailed));
+    }
+
+    #[test]
+    fn uncompressed_header_is_not_recognised() {
+        assert_eq!(decompressed_size(b"LSZ1\x10\x00\x00\x00"), Some(16));
+        assert_eq!(decompressed_size(b"\x00\x01\x02\x03\x10\x00\x00\x00"), None);
+    }
+}
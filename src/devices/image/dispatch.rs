@@ -0,0 +1,113 @@
+//! Runtime selection between image verification schemes.
+//!
+//! Every reader rejects images whose [algorithm identifier](`super::Algorithm`)
+//! doesn't match its own scheme with [`Error::UnsupportedAlgorithm`]. This module
+//! builds on that to compose readers, so a single build can accept images
+//! verified with any of several schemes (e.g. while a fleet migrates from CRC
+//! to ECDSA signed images).
+
+use super::*;
+use crate::error::Error;
+use core::marker::PhantomData;
+
+/// Reader that verifies images with `First` or `Second`, whichever matches the
+/// algorithm identifier stored in the image. Readers can be nested to accept
+/// more than two schemes.
+///
+/// NOTE: A dispatching reader is only as strong as the weakest scheme it accepts.
+/// Combining a CRC reader with a signature reader means unsigned images will boot.
+pub struct DispatchingReader<First: Reader, Second: Reader>(PhantomData<(First, Second)>);
+
+impl<First: Reader, Second: Reader> Reader for DispatchingReader<First, Second> {
+    fn image_at_with_progress<A, F, P>(
+        flash: &mut F,
+        bank: Bank<A>,
+        mut progress: P,
+    ) -> Result<Image<A>, error::Error>
+    where
+        A: Address,
+        F: flash::ReadWrite<Address = A>,
+        P: FnMut(usize),
+        error::Error: From<F::Error>,
+    {
+        match First::image_at_with_progress(flash, bank, &mut progress) {
+            Err(Error::UnsupportedAlgorithm) => {
+                Second::image_at_with_progress(flash, bank, &mut progress)
+            }
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::image::image_crc::tests::TEST_IMAGE_WITH_CORRECT_CRC;
+    use blue_hal::hal::{
+        doubles::flash::{Address, FakeFlash},
+        flash::ReadWrite,
+    };
+    use crc::crc32;
+
+    type Ieee = CrcImageReader<{ crc32::IEEE }, false>;
+    type Castagnoli = CrcImageReader<{ crc32::CASTAGNOLI }, false>;
+
+    fn bank(index: u8, location: u32) -> Bank<Address> {
+        Bank { index, size: 512, location: Address(location), bootable: false, is_golden: false }
+    }
+
+    fn image_with_algorithm_id(id: u8) -> Vec<u8> {
+        let mut image = TEST_IMAGE_WITH_CORRECT_CRC.to_vec();
+        image[12 + MAGIC_STRING.len()] = id;
+        image
+    }
+
+    #[test]
+    fn images_with_unknown_algorithm_are_rejected() {
+        let mut flash = FakeFlash::new(Address(0));
+        flash.write(Address(0), &image_with_algorithm_id(Algorithm::Ed25519.id())).unwrap();
+        assert_eq!(Err(Error::UnsupportedAlgorithm), Ieee::image_at(&mut flash, bank(1, 0)));
+        assert_eq!(
+            Err(Error::UnsupportedAlgorithm),
+            DispatchingReader::<Castagnoli, Ieee>::image_at(&mut flash, bank(1, 0))
+        );
+
+        flash.write(Address(0), &image_with_algorithm_id(0xFF)).unwrap();
+        assert_eq!(Err(Error::UnsupportedAlgorithm), Ieee::image_at(&mut flash, bank(1, 0)));
+    }
+
+    #[test]
+    fn verification_errors_are_not_masked_by_dispatch() {
+        let mut flash = FakeFlash::new(Address(0));
+        flash.write(Address(0), &TEST_IMAGE_WITH_CORRECT_CRC).unwrap();
+        assert_eq!(
+            Err(Error::CrcInvalid),
+            DispatchingReader::<Castagnoli, Ieee>::image_at(&mut flash, bank(1, 0))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "ecdsa-verify")]
+    fn dual_capable_reader_accepts_crc_and_p256_images() {
+        use crate::devices::image::{
+            image_ecdsa::tests::TEST_SIGNED_IMAGE, key_source::EmbeddedKey, EcdsaImageReader,
+        };
+        type Ecdsa = EcdsaImageReader<EmbeddedKey, false>;
+        type Dual = DispatchingReader<Ieee, Ecdsa>;
+
+        let mut flash = FakeFlash::new(Address(0));
+        flash.write(Address(0), &TEST_IMAGE_WITH_CORRECT_CRC).unwrap();
+        flash.write(Address(512), &TEST_SIGNED_IMAGE).unwrap();
+
+        let crc_image = Dual::image_at(&mut flash, bank(1, 0)).unwrap();
+        assert_eq!(Algorithm::Crc32, crc_image.algorithm());
+        assert_eq!(12usize, crc_image.size());
+
+        let p256_image = Dual::image_at(&mut flash, bank(2, 512)).unwrap();
+        assert_eq!(Algorithm::P256, p256_image.algorithm());
+        assert_eq!(2usize, p256_image.size());
+
+        assert_eq!(Err(Error::UnsupportedAlgorithm), Ieee::image_at(&mut flash, bank(2, 512)));
+        assert_eq!(Err(Error::UnsupportedAlgorithm), Ecdsa::image_at(&mut flash, bank(1, 0)));
+    }
+}
@@ -9,69 +9,182 @@ use blue_hal::{
 use crc::{crc32, Hasher32};
 use nb::block;
 
-pub struct CrcImageReader;
+/// Verifies images through a reflected CRC32 with the given polynomial
+/// (e.g. `crc32::IEEE` or `crc32::CASTAGNOLI`).
+///
+/// Images are verified in a single sequential pass: every byte up to the magic string is
+/// fed to the digest exactly once, in order, and the only reads past that point are the
+/// algorithm identifier, the frame (for framed images) and the stored CRC, which untagged
+/// images (see [`Image::tagged`]) carry right after the magic string. This access
+/// pattern maps directly onto a streaming hardware CRC unit. The golden and no-auto-update
+/// strings are detected from the last bytes of that same pass, rather than read from flash
+/// again. Occurrences of the magic string within the body of a framed image are digested
+/// like any other bytes, and the pass carries on past them. Only when an occurrence
+/// fails to verify is the rest of the bank looked over, for a framed trailer whose body
+/// spans it.
+///
+/// Banks are scanned in full by default, so an image starting with 0xFF verifies like
+/// any other. Under `STRICT_SCAN`, banks whose first byte is 0xFF are rejected as empty
+/// without being scanned, which speeds up booting when some banks are erased.
+///
+/// `MAX_SCAN` and `MIN_SIZE` bound the scan as described on [`Reader`].
+pub struct CrcImageReader<
+    const POLYNOMIAL: u32,
+    const STRICT_SCAN: bool,
+    const MAX_SCAN: usize = { usize::MAX },
+    const MIN_SIZE: usize = 0,
+>;
 
-impl super::Reader for CrcImageReader {
-    fn image_at<A, F>(flash: &mut F, bank: Bank<A>) -> Result<Image<A>, error::Error>
+impl<
+        const POLYNOMIAL: u32,
+        const STRICT_SCAN: bool,
+        const MAX_SCAN: usize,
+        const MIN_SIZE: usize,
+    > super::Reader for CrcImageReader<POLYNOMIAL, STRICT_SCAN, MAX_SCAN, MIN_SIZE>
+{
+    fn image_at_with_progress<A, F, P>(
+        flash: &mut F,
+        bank: Bank<A>,
+        mut progress: P,
+    ) -> Result<Image<A>, error::Error>
     where
         A: Address,
         F: flash::ReadWrite<Address = A>,
+        P: FnMut(usize),
         error::Error: From<F::Error>,
     {
-        // Generic buffer to hold temporary slices read from flash memory.
-        const BUFFER_SIZE: usize = 256;
-        let mut buffer = [0u8; BUFFER_SIZE];
-
-        let (mut digest, mut image_size) = flash
-            .bytes(bank.location)
-            .take(bank.size)
-            .until_sequence(&magic_string_inverted())
-            .fold(
-                (crc32::Digest::new(crc32::IEEE), 0usize),
-                |(mut digest, mut byte_count), byte| {
+        if STRICT_SCAN && flash.bytes(bank.location).next().ok_or(Error::BankInvalid)? == 0xFF {
+            return Err(Error::BankEmpty);
+        }
+
+        let scanned_size = scan_limit(bank, MAX_SCAN);
+        let mut trailing_bytes = TrailingBytes::new();
+        let mut digest = crc32::Digest::new(POLYNOMIAL);
+        let mut magic_string_offset = 0usize;
+        let mut skipped = None;
+        let mut occupied = false;
+        loop {
+            magic_string_offset = flash
+                .bytes(bank.location + magic_string_offset)
+                .take(scanned_size - magic_string_offset)
+                .until_sequence(&magic_string_inverted())
+                .fold(magic_string_offset, |mut byte_count, byte| {
                     digest.write(&[byte]);
+                    trailing_bytes.push(byte);
+                    occupied |= byte != 0xFF;
                     byte_count += 1;
-                    (digest, byte_count)
-                },
-            );
+                    if byte_count % SCAN_PROGRESS_INTERVAL == 0 {
+                        progress(byte_count);
+                    }
+                    byte_count
+                });
 
-        if image_size == bank.size {
-            return Err(Error::BankEmpty);
-        }
+            if magic_string_offset == scanned_size {
+                let missing = if occupied { Error::ImageUndecorated } else { Error::BankEmpty };
+                return Err(skipped.unwrap_or(missing));
+            }
+
+            let golden = trailing_bytes.ends_with(GOLDEN_STRING.as_bytes(), 0);
+            let skipped_flags = if golden { GOLDEN_STRING.len() } else { 0 };
+            let no_auto_update =
+                trailing_bytes.ends_with(NO_AUTO_UPDATE_STRING.as_bytes(), skipped_flags);
+            let flags_size =
+                skipped_flags + if no_auto_update { NO_AUTO_UPDATE_STRING.len() } else { 0 };
+            let image_size = magic_string_offset.saturating_sub(flags_size);
+            let boundary =
+                read_boundary(flash, bank, magic_string_offset, image_size, Algorithm::Crc32)?;
 
-        // Magic string is part of the digest
-        digest.write(&magic_string_inverted());
-        let digest_position = bank.location + image_size + MAGIC_STRING.len();
-        let mut digest_bytes = [0; size_of::<u32>()];
-        block!(flash.read(digest_position, &mut digest_bytes))?;
+            // Magic string is part of the digest, and of the body if scanning carries on.
+            digest.write(&magic_string_inverted());
+            magic_string_inverted().iter().for_each(|byte| trailing_bytes.push(*byte));
+            let calculated_crc = digest.sum32();
+            let image = |tagged, framed| Image {
+                size: image_size,
+                location: bank.location,
+                bootable: bank.bootable,
+                golden,
+                no_auto_update,
+                algorithm: Algorithm::Crc32,
+                tagged,
+                framed,
+                identifier: Identifier::Crc(calculated_crc),
+            };
 
-        let retrieved_crc = u32::from_le_bytes(digest_bytes);
-        let calculated_crc = digest.sum32();
-        if retrieved_crc != calculated_crc {
-            return Err(Error::CrcInvalid);
+            let trailer_offset = magic_string_offset + MAGIC_STRING.len();
+            if let Boundary::Trailer { framed } = boundary {
+                if image_size < MIN_SIZE {
+                    return Err(Error::ImageTooSmall);
+                }
+                let crc_offset =
+                    trailer_offset + Algorithm::ID_SIZE + if framed { FRAME_SIZE } else { 0 };
+                if read_crc(flash, bank, crc_offset)? == calculated_crc {
+                    return Ok(image(true, framed));
+                }
+            }
+            if image_size >= MIN_SIZE
+                && matches!(read_crc(flash, bank, trailer_offset), Ok(crc) if crc == calculated_crc)
+            {
+                return Ok(image(false, false));
+            }
+            match boundary {
+                Boundary::Skip(error) => skipped = skipped.or(Some(error)),
+                _ if framed_past(flash, bank, magic_string_offset, scanned_size)? => (),
+                Boundary::Trailer { .. } => return Err(Error::CrcInvalid),
+                Boundary::Foreign => return Err(Error::UnsupportedAlgorithm),
+            }
+            magic_string_offset += MAGIC_STRING.len();
         }
+    }
+}
 
-        let golden_string_position = bank.location + image_size.saturating_sub(GOLDEN_STRING.len());
-        let golden_bytes = &mut buffer[0..GOLDEN_STRING.len()];
-        block!(flash.read(golden_string_position, golden_bytes))?;
-        let golden = golden_bytes == GOLDEN_STRING.as_bytes();
+/// Reads the CRC stored `offset` bytes into a bank.
+fn read_crc<A, F>(flash: &mut F, bank: Bank<A>, offset: usize) -> Result<u32, error::Error>
+where
+    A: Address,
+    F: flash::ReadWrite<Address = A>,
+    error::Error: From<F::Error>,
+{
+    let mut crc_bytes = [0; size_of::<u32>()];
+    block!(flash.read(bank.address_at(offset, crc_bytes.len())?, &mut crc_bytes))?;
+    Ok(u32::from_le_bytes(crc_bytes))
+}
 
-        if golden {
-            image_size = image_size.saturating_sub(GOLDEN_STRING.len());
-        }
+/// Enough trailing image bytes to hold both the golden and no-auto-update strings.
+const TRAILING_WINDOW: usize = GOLDEN_STRING.len() + NO_AUTO_UPDATE_STRING.len();
+
+/// Ring buffer over the last bytes fed to it, so the strings preceding the magic
+/// string can be recognised at the end of a scan without going back to flash.
+struct TrailingBytes {
+    buffer: [u8; TRAILING_WINDOW],
+    head: usize,
+    count: usize,
+}
+
+impl TrailingBytes {
+    fn new() -> Self { Self { buffer: [0u8; TRAILING_WINDOW], head: 0, count: 0 } }
+
+    fn push(&mut self, byte: u8) {
+        self.buffer[self.head] = byte;
+        self.head = if self.head + 1 == TRAILING_WINDOW { 0 } else { self.head + 1 };
+        self.count += 1;
+    }
 
-        Ok(Image {
-            size: image_size,
-            location: bank.location,
-            bootable: bank.bootable,
-            golden,
-            crc: calculated_crc,
-        })
+    /// Whether the bytes pushed so far, ignoring the last `skip` of them, end in `suffix`.
+    fn ends_with(&self, suffix: &[u8], skip: usize) -> bool {
+        if suffix.len() + skip > TRAILING_WINDOW || suffix.len() + skip > self.count {
+            return false;
+        }
+        // Position of the oldest byte of the suffix, relative to the next write.
+        let start = TRAILING_WINDOW - suffix.len() - skip;
+        suffix
+            .iter()
+            .enumerate()
+            .all(|(i, byte)| self.buffer[(self.head + start + i) % TRAILING_WINDOW] == *byte)
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use std::convert::TryInto;
 
     use super::*;
@@ -84,7 +197,7 @@ mod tests {
     };
 
     #[rustfmt::skip]
-    const TEST_IMAGE_WITH_CORRECT_CRC: &[u8] = &[
+    pub(crate) const TEST_IMAGE_WITH_CORRECT_CRC: &[u8] = &[
         // Image
         0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x20, 0x77, 0x6f, 0x72, 0x6c, 0x64, 0x0a,
         // Magic string inverted
@@ -92,10 +205,42 @@ mod tests {
         0x86, 0x9b, 0xa5, 0xb7, 0xcd, 0xae, 0x94, 0x8e, 0xa5, 0xa8,
         0xaf, 0x9c, 0xb5, 0x98, 0xb8, 0xcc, 0xb5, 0x8b, 0x91, 0xb5,
         0xc9, 0xa9, 0x8a, 0xbe,
+        // Algorithm
+        0x01,
         // CRC
         0xf0, 0xc9, 0x42, 0xad
     ];
 
+    /// Builds a golden image with a valid IEEE CRC around an arbitrary payload.
+    pub(crate) fn golden_test_image(payload: &[u8]) -> Vec<u8> {
+        let mut payload = payload.to_vec();
+        payload.extend_from_slice(GOLDEN_STRING.as_bytes());
+        regular_test_image(&payload)
+    }
+
+    /// Builds a regular image with a valid IEEE CRC around an arbitrary payload.
+    pub(crate) fn regular_test_image(payload: &[u8]) -> Vec<u8> {
+        let mut image = payload.to_vec();
+        image.extend_from_slice(&magic_string_inverted());
+        let mut digest = crc32::Digest::new(crc32::IEEE);
+        digest.write(&image);
+        image.push(Algorithm::Crc32.id());
+        image.extend_from_slice(&digest.sum32().to_le_bytes());
+        image
+    }
+
+    /// Builds a framed regular image with a valid IEEE CRC around an arbitrary payload.
+    pub(crate) fn framed_test_image(payload: &[u8]) -> Vec<u8> {
+        let mut image = payload.to_vec();
+        image.extend_from_slice(&magic_string_inverted());
+        let mut digest = crc32::Digest::new(crc32::IEEE);
+        digest.write(&image);
+        image.push(Algorithm::Crc32.framed_id());
+        image.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        image.extend_from_slice(&digest.sum32().to_le_bytes());
+        image
+    }
+
     #[rustfmt::skip]
     const TEST_IMAGE_WITH_BAD_CRC: &[u8] = &[
         // Image
@@ -105,10 +250,27 @@ mod tests {
         0x86, 0x9b, 0xa5, 0xb7, 0xcd, 0xae, 0x94, 0x8e, 0xa5, 0xa8,
         0xaf, 0x9c, 0xb5, 0x98, 0xb8, 0xcc, 0xb5, 0x8b, 0x91, 0xb5,
         0xc9, 0xa9, 0x8a, 0xbe,
+        // Algorithm
+        0x01,
         // CRC (first byte invalid)
         0x77, 0xc9, 0x42, 0xad
     ];
 
+    #[rustfmt::skip]
+    const TEST_IMAGE_WITH_CORRECT_CRC32C: &[u8] = &[
+        // Image
+        0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x20, 0x77, 0x6f, 0x72, 0x6c, 0x64, 0x0a,
+        // Magic string inverted
+        0xb7, 0xac, 0x9c, 0xc8, 0x9c, 0xcd, 0x8f, 0x8b,
+        0x86, 0x9b, 0xa5, 0xb7, 0xcd, 0xae, 0x94, 0x8e, 0xa5, 0xa8,
+        0xaf, 0x9c, 0xb5, 0x98, 0xb8, 0xcc, 0xb5, 0x8b, 0x91, 0xb5,
+        0xc9, 0xa9, 0x8a, 0xbe,
+        // Algorithm
+        0x01,
+        // CRC32C
+        0x4d, 0x61, 0x5a, 0x6d
+    ];
+
     #[test]
     fn retrieving_image_with_correct_crc_succeeds() {
         let mut flash = FakeFlash::new(Address(0));
@@ -116,11 +278,12 @@ mod tests {
             Bank { index: 1, size: 512, location: Address(0), bootable: false, is_golden: false };
         flash.write(Address(0), &TEST_IMAGE_WITH_CORRECT_CRC).unwrap();
 
-        let image = CrcImageReader::image_at(&mut flash, bank).unwrap();
+        let image = CrcImageReader::<{ crc32::IEEE }, false>::image_at(&mut flash, bank).unwrap();
         assert_eq!(image.size, 12usize);
         assert_eq!(image.location, bank.location);
         assert_eq!(image.bootable, false);
         assert_eq!(image.is_golden(), false);
+        assert_eq!(image.no_auto_update(), false);
     }
 
     #[test]
@@ -130,6 +293,407 @@ mod tests {
             Bank { index: 1, size: 512, location: Address(0), bootable: false, is_golden: false };
 
         flash.write(Address(0), &TEST_IMAGE_WITH_BAD_CRC).unwrap();
-        assert_eq!(Err(Error::CrcInvalid), CrcImageReader::image_at(&mut flash, bank));
+        assert_eq!(
+            Err(Error::CrcInvalid),
+            CrcImageReader::<{ crc32::IEEE }, false>::image_at(&mut flash, bank)
+        );
+    }
+
+    #[test]
+    fn retrieving_image_with_correct_crc32c_succeeds_under_crc32c() {
+        let mut flash = FakeFlash::new(Address(0));
+        let bank =
+            Bank { index: 1, size: 512, location: Address(0), bootable: false, is_golden: false };
+        flash.write(Address(0), &TEST_IMAGE_WITH_CORRECT_CRC32C).unwrap();
+
+        let image =
+            CrcImageReader::<{ crc32::CASTAGNOLI }, false>::image_at(&mut flash, bank).unwrap();
+        assert_eq!(image.size, 12usize);
+    }
+
+    #[test]
+    fn retrieving_image_with_ieee_crc_fails_under_crc32c() {
+        let mut flash = FakeFlash::new(Address(0));
+        let bank =
+            Bank { index: 1, size: 512, location: Address(0), bootable: false, is_golden: false };
+
+        flash.write(Address(0), &TEST_IMAGE_WITH_CORRECT_CRC).unwrap();
+        assert_eq!(
+            Err(Error::CrcInvalid),
+            CrcImageReader::<{ crc32::CASTAGNOLI }, false>::image_at(&mut flash, bank)
+        );
+    }
+
+    #[test]
+    fn framed_images_are_read_past_a_magic_string_in_their_body() {
+        let bank = Bank::regular(1, 512, Address(0));
+        // Whatever identifier follows the magic string in the body.
+        for id in [0x00, Algorithm::Crc32.id(), Algorithm::P256.id()].iter() {
+            let body = [&[0x5Au8; 16][..], &magic_string_inverted(), &[*id], &[0xA5; 16]].concat();
+            let image = framed_test_image(&body);
+            let mut flash = FakeFlash::new(Address(0));
+            flash.write(Address(0), &image).unwrap();
+
+            let read =
+                CrcImageReader::<{ crc32::IEEE }, false>::image_at(&mut flash, bank).unwrap();
+            assert!(read.framed());
+            assert_eq!(read.size(), body.len());
+            assert_eq!(read.total_size(), image.len());
+            assert_eq!(read.signed_size(), body.len() + MAGIC_STRING.len());
+            let layout = loadstone_image_format::Layout::parse(&image).unwrap();
+            assert_eq!(layout.body_size, read.size());
+        }
+    }
+
+    #[test]
+    fn untagged_images_are_verified_with_the_build_algorithm() {
+        let bank = Bank::regular(1, 512, Address(0));
+        // Whatever the first CRC byte, which takes the place of the identifier.
+        for first_crc_byte in [0xFF, Algorithm::Crc32.id(), Algorithm::P256.id(), 0x81].iter() {
+            let untagged = (0u32..)
+                .map(|seed| {
+                    let mut image = seed.to_le_bytes().to_vec();
+                    image.extend_from_slice(&magic_string_inverted());
+                    let mut digest = crc32::Digest::new(crc32::IEEE);
+                    digest.write(&image);
+                    image.extend_from_slice(&digest.sum32().to_le_bytes());
+                    image
+                })
+                .find(|image| image[4 + MAGIC_STRING.len()] == *first_crc_byte)
+                .unwrap();
+            let mut flash = FakeFlash::new(Address(0));
+            flash.write(Address(0), &[0xFF; 512]).unwrap();
+            flash.write(Address(0), &untagged).unwrap();
+
+            let image =
+                CrcImageReader::<{ crc32::IEEE }, false>::image_at(&mut flash, bank).unwrap();
+            assert!(!image.tagged());
+            assert_eq!(4, image.size());
+            assert_eq!(untagged.len(), image.total_size());
+        }
+    }
+
+    #[test]
+    fn framed_images_with_a_mismatched_frame_are_not_found() {
+        let mut flash = FakeFlash::new(Address(0));
+        let bank = Bank::regular(1, 512, Address(0));
+        let mut image = framed_test_image(&[0x5Au8; 16]);
+        image[16 + MAGIC_STRING.len() + Algorithm::ID_SIZE] = 15;
+        flash.write(Address(0), &[0xFF; 512]).unwrap();
+        flash.write(Address(0), &image).unwrap();
+        assert_eq!(
+            Err(Error::FramingMismatch),
+            CrcImageReader::<{ crc32::IEEE }, false>::image_at(&mut flash, bank)
+        );
+    }
+
+    #[test]
+    fn scanning_large_image_reports_progress() {
+        let mut flash = FakeFlash::new(Address(0));
+        let bank = Bank {
+            index: 1,
+            size: 3 * SCAN_PROGRESS_INTERVAL,
+            location: Address(0),
+            bootable: false,
+            is_golden: false,
+        };
+        let mut image = vec![0xAAu8; 2 * SCAN_PROGRESS_INTERVAL + 1];
+        image.extend_from_slice(&magic_string_inverted());
+        let mut digest = crc32::Digest::new(crc32::IEEE);
+        digest.write(&image);
+        image.push(Algorithm::Crc32.id());
+        image.extend_from_slice(&digest.sum32().to_le_bytes());
+        flash.write(Address(0), &image).unwrap();
+
+        let mut reports = vec![];
+        CrcImageReader::<{ crc32::IEEE }, false>::image_at_with_progress(
+            &mut flash,
+            bank,
+            |scanned| reports.push(scanned),
+        )
+        .unwrap();
+        assert_eq!(reports, vec![SCAN_PROGRESS_INTERVAL, 2 * SCAN_PROGRESS_INTERVAL]);
+    }
+
+    #[test]
+    fn image_flagged_no_auto_update_is_bootable_in_place() {
+        let mut flash = FakeFlash::new(Address(0));
+        let bank = Bank::bootable(1, 512, Address(0));
+        let mut image = vec![0x01u8, 0x02, 0x03, 0x04];
+        image.extend_from_slice(NO_AUTO_UPDATE_STRING.as_bytes());
+        image.extend_from_slice(GOLDEN_STRING.as_bytes());
+        image.extend_from_slice(&magic_string_inverted());
+        let mut digest = crc32::Digest::new(crc32::IEEE);
+        digest.write(&image);
+        image.push(Algorithm::Crc32.id());
+        image.extend_from_slice(&digest.sum32().to_le_bytes());
+        flash.write(Address(0), &image).unwrap();
+
+        let image = CrcImageReader::<{ crc32::IEEE }, false>::image_at(&mut flash, bank).unwrap();
+        assert!(image.no_auto_update());
+        assert!(image.is_golden());
+        assert!(image.bootable);
+        assert_eq!(image.size(), 4usize);
+        assert_eq!(image.total_size(), 4 + NO_AUTO_UPDATE_STRING.len() + GOLDEN_STRING.len() + 37);
+    }
+
+    /// Trailing string detection as done before the single pass scan, reading the strings
+    /// back from flash once the position of the magic string is known.
+    fn trailing_strings_from_flash(flash: &mut FakeFlash, raw_size: usize) -> (bool, bool, usize) {
+        let mut size = raw_size;
+        let mut golden_bytes = [0u8; GOLDEN_STRING.len()];
+        flash
+            .read(Address(size.saturating_sub(GOLDEN_STRING.len()) as u32), &mut golden_bytes)
+            .unwrap();
+        let golden = golden_bytes == GOLDEN_STRING.as_bytes();
+        if golden {
+            size = size.saturating_sub(GOLDEN_STRING.len());
+        }
+        let mut no_auto_update_bytes = [0u8; NO_AUTO_UPDATE_STRING.len()];
+        flash
+            .read(
+                Address(size.saturating_sub(NO_AUTO_UPDATE_STRING.len()) as u32),
+                &mut no_auto_update_bytes,
+            )
+            .unwrap();
+        let no_auto_update = no_auto_update_bytes == NO_AUTO_UPDATE_STRING.as_bytes();
+        if no_auto_update {
+            size = size.saturating_sub(NO_AUTO_UPDATE_STRING.len());
+        }
+        (golden, no_auto_update, size)
+    }
+
+    #[test]
+    fn single_pass_scan_matches_two_pass_trailing_string_detection() {
+        let golden = GOLDEN_STRING.as_bytes();
+        let no_auto_update = NO_AUTO_UPDATE_STRING.as_bytes();
+        let payloads: Vec<Vec<u8>> = vec![
+            b"plain firmware payload".to_vec(),
+            [&b"golden payload"[..], golden].concat(),
+            [&b"pinned payload"[..], no_auto_update].concat(),
+            [&b"pinned golden payload"[..], no_auto_update, golden].concat(),
+            // Strings out of order are only recognised where the format expects them
+            [&b"misordered payload"[..], golden, no_auto_update].concat(),
+            // Partial strings and payloads no longer than the strings themselves
+            golden[1..].to_vec(),
+            golden.to_vec(),
+            [no_auto_update, golden].concat(),
+        ];
+
+        for payload in payloads {
+            let mut image = payload.clone();
+            image.extend_from_slice(&magic_string_inverted());
+            let mut digest = crc32::Digest::new(crc32::IEEE);
+            digest.write(&image);
+            image.push(Algorithm::Crc32.id());
+            image.extend_from_slice(&digest.sum32().to_le_bytes());
+
+            let mut flash = FakeFlash::new(Address(0));
+            let bank = Bank::bootable(1, 512, Address(0));
+            flash.write(Address(0), &image).unwrap();
+
+            let scanned =
+                CrcImageReader::<{ crc32::IEEE }, false>::image_at(&mut flash, bank).unwrap();
+            let (golden, no_auto_update, size) =
+                trailing_strings_from_flash(&mut flash, payload.len());
+            assert_eq!(
+                (scanned.is_golden(), scanned.no_auto_update(), scanned.size()),
+                (golden, no_auto_update, size)
+            );
+        }
+    }
+
+    #[test]
+    fn shared_layout_parser_matches_device_scan() {
+        let golden = GOLDEN_STRING.as_bytes();
+        let no_auto_update = NO_AUTO_UPDATE_STRING.as_bytes();
+        let payloads: Vec<Vec<u8>> = vec![
+            b"plain firmware payload".to_vec(),
+            [&b"golden payload"[..], golden].concat(),
+            [&b"pinned payload"[..], no_auto_update].concat(),
+            [&b"pinned golden payload"[..], no_auto_update, golden].concat(),
+            [&b"misordered payload"[..], golden, no_auto_update].concat(),
+            golden.to_vec(),
+        ];
+
+        for payload in payloads {
+            let mut image = payload.clone();
+            image.extend_from_slice(&MAGIC_STRING_INVERTED);
+            let mut digest = crc32::Digest::new(crc32::IEEE);
+            digest.write(&image);
+            image.push(Algorithm::Crc32.id());
+            image.extend_from_slice(&digest.sum32().to_le_bytes());
+
+            let mut flash = FakeFlash::new(Address(0));
+            let bank = Bank::bootable(1, 512, Address(0));
+            flash.write(Address(0), &image).unwrap();
+            let scanned =
+                CrcImageReader::<{ crc32::IEEE }, false>::image_at(&mut flash, bank).unwrap();
+            let layout = loadstone_image_format::Layout::parse(&image).unwrap();
+            assert_eq!(
+                (scanned.size(), scanned.total_size(), scanned.is_golden()),
+                (layout.body_size, layout.total_size(), layout.golden)
+            );
+            assert_eq!(
+                (scanned.no_auto_update(), scanned.algorithm()),
+                (layout.no_auto_update, layout.algorithm)
+            );
+        }
+    }
+
+    #[test]
+    fn image_just_under_the_scan_limit_verifies() {
+        const MAX_SCAN: usize = 128;
+        let bank = Bank::bootable(1, 512, Address(0));
+        let image_with_body = |body_size: usize| {
+            let mut image = vec![0x5Au8; body_size];
+            image.extend_from_slice(&magic_string_inverted());
+            let mut digest = crc32::Digest::new(crc32::IEEE);
+            digest.write(&image);
+            image.push(Algorithm::Crc32.id());
+            image.extend_from_slice(&digest.sum32().to_le_bytes());
+            let mut flash = FakeFlash::new(Address(0));
+            flash.write(Address(0), &image).unwrap();
+            flash
+        };
+
+        // The magic string ends right at the scan limit.
+        let mut flash = image_with_body(MAX_SCAN - MAGIC_STRING.len());
+        let image =
+            CrcImageReader::<{ crc32::IEEE }, false, MAX_SCAN>::image_at(&mut flash, bank).unwrap();
+        assert_eq!(image.size(), MAX_SCAN - MAGIC_STRING.len());
+
+        let mut flash = image_with_body(MAX_SCAN - MAGIC_STRING.len() + 1);
+        assert_eq!(
+            Err(Error::ImageUndecorated),
+            CrcImageReader::<{ crc32::IEEE }, false, MAX_SCAN>::image_at(&mut flash, bank)
+        );
+        assert!(CrcImageReader::<{ crc32::IEEE }, false>::image_at(&mut flash, bank).is_ok());
+    }
+
+    #[test]
+    fn images_under_the_minimum_size_are_refused() {
+        const MIN_SIZE: usize = 64;
+        let bank = Bank::bootable(1, 512, Address(0));
+        let mut flash = FakeFlash::new(Address(0));
+        flash.write(Address(0), &regular_test_image(&[0x5A; MIN_SIZE - 1])).unwrap();
+        assert_eq!(
+            Err(Error::ImageTooSmall),
+            CrcImageReader::<{ crc32::IEEE }, false, { usize::MAX }, MIN_SIZE>::image_at(
+                &mut flash, bank
+            )
+        );
+
+        flash.write(Address(0), &regular_test_image(&[0x5A; MIN_SIZE])).unwrap();
+        let image = CrcImageReader::<{ crc32::IEEE }, false, { usize::MAX }, MIN_SIZE>::image_at(
+            &mut flash, bank,
+        )
+        .unwrap();
+        assert_eq!(MIN_SIZE, image.size());
+    }
+
+    #[test]
+    fn empty_banks_are_told_apart_from_banks_holding_no_valid_image() {
+        type Strict = CrcImageReader<{ crc32::IEEE }, true>;
+        let bank = Bank::regular(1, 512, Address(0));
+        let scan = |bytes: &[u8]| {
+            let mut flash = FakeFlash::new(Address(0));
+            flash.write(Address(0), &[0xFFu8; 512]).unwrap();
+            flash.write(Address(0), bytes).unwrap();
+            Strict::image_at(&mut flash, bank)
+        };
+        let mut corrupted = regular_test_image(b"payload");
+        corrupted[0] ^= 0xFF;
+
+        assert_eq!(Err(Error::BankEmpty), scan(&[]));
+        assert_eq!(Err(Error::ImageUndecorated), scan(&[0x5A; 64]));
+        assert_eq!(Err(Error::CrcInvalid), scan(&corrupted));
+        assert!(scan(&regular_test_image(b"payload")).is_ok());
+
+        let contents = |bytes: &[u8]| BankContents::from(scan(bytes));
+        assert_eq!(BankContents::Empty, contents(&[]));
+        assert_eq!(BankContents::Unverified(Error::ImageUndecorated), contents(&[0x5A; 64]));
+        assert_eq!(BankContents::Unverified(Error::CrcInvalid), contents(&corrupted));
+        assert!(matches!(contents(&regular_test_image(b"payload")), BankContents::Image(_)));
+    }
+
+    #[test]
+    fn bank_without_image_is_only_scanned_up_to_the_limit() {
+        const BANK_SIZE: usize = 4 * SCAN_PROGRESS_INTERVAL;
+        let mut flash = FakeFlash::new(Address(0));
+        let bank = Bank::bootable(1, BANK_SIZE, Address(0));
+        flash.write(Address(0), &vec![0x00u8; BANK_SIZE]).unwrap();
+
+        let mut reports = vec![];
+        assert_eq!(
+            Err(Error::ImageUndecorated),
+            CrcImageReader::<{ crc32::IEEE }, false, SCAN_PROGRESS_INTERVAL>::image_at_with_progress(
+                &mut flash,
+                bank,
+                |scanned| reports.push(scanned),
+            )
+        );
+        assert_eq!(reports, vec![SCAN_PROGRESS_INTERVAL]);
+
+        reports.clear();
+        assert_eq!(
+            Err(Error::ImageUndecorated),
+            CrcImageReader::<{ crc32::IEEE }, false>::image_at_with_progress(
+                &mut flash,
+                bank,
+                |scanned| reports.push(scanned),
+            )
+        );
+        assert_eq!(reports.len(), 4);
+    }
+
+    #[test]
+    fn image_starting_with_erased_byte_is_only_rejected_under_strict_scan() {
+        let mut flash = FakeFlash::new(Address(0));
+        let bank =
+            Bank { index: 1, size: 512, location: Address(0), bootable: false, is_golden: false };
+        let mut image = vec![0xFFu8, 0x01, 0x02, 0x03];
+        image.extend_from_slice(&magic_string_inverted());
+        let mut digest = crc32::Digest::new(crc32::IEEE);
+        digest.write(&image);
+        image.push(Algorithm::Crc32.id());
+        image.extend_from_slice(&digest.sum32().to_le_bytes());
+        flash.write(Address(0), &image).unwrap();
+
+        assert_eq!(
+            Err(Error::BankEmpty),
+            CrcImageReader::<{ crc32::IEEE }, true>::image_at(&mut flash, bank)
+        );
+        let image = CrcImageReader::<{ crc32::IEEE }, false>::image_at(&mut flash, bank).unwrap();
+        assert_eq!(image.size, 4usize);
+    }
+
+    #[test]
+    fn addresses_past_the_bank_or_the_address_space_are_refused() {
+        let bank = Bank::regular(2, 0x200, Address(0xFFFF_FF00));
+        assert_eq!(Ok(Address(0xFFFF_FF00)), bank.address_at(0, 0x100));
+        assert_eq!(Ok(Address(0xFFFF_FFFF)), bank.address_at(0xFF, 1));
+        assert_eq!(Err(Error::AddressOutOfRange), bank.address_at(0xFF, 2));
+        assert_eq!(Err(Error::AddressOutOfRange), bank.address_at(0x100, 0));
+        assert_eq!(Err(Error::AddressOutOfRange), bank.address_at(usize::MAX, 1));
+
+        let bank = Bank::regular(2, 0x100, Address(0x100));
+        assert_eq!(Ok(Address(0x1F0)), bank.address_at(0xF0, 0x10));
+        assert_eq!(Err(Error::AddressOutOfRange), bank.address_at(0xF0, 0x11));
+    }
+
+    #[test]
+    fn image_whose_trailer_overruns_the_bank_is_refused() {
+        // The last byte of the CRC lies just past the end of the bank.
+        let mut flash = FakeFlash::new(Address(0));
+        flash.write(Address(0), &TEST_IMAGE_WITH_CORRECT_CRC).unwrap();
+        let bank = Bank::regular(1, TEST_IMAGE_WITH_CORRECT_CRC.len() - 1, Address(0));
+        assert_eq!(
+            Err(Error::AddressOutOfRange),
+            CrcImageReader::<{ crc32::IEEE }, false>::image_at(&mut flash, bank)
+        );
+
+        let bank = Bank { size: TEST_IMAGE_WITH_CORRECT_CRC.len(), ..bank };
+        assert!(CrcImageReader::<{ crc32::IEEE }, false>::image_at(&mut flash, bank).is_ok());
     }
 }
@@ -6,100 +6,201 @@ use blue_hal::{
     utilities::{iterator::UntilSequence, memory::Address},
 };
 
+use super::key_source::KeySource;
 pub use ::ecdsa::{elliptic_curve::generic_array::typenum::Unsigned, SignatureSize};
+use core::marker::PhantomData;
 pub use ecdsa::signature::Signature as EcdsaSignature;
 use nb::block;
-use p256::EncodedPoint;
 pub use p256::{
     ecdsa::{signature::DigestVerifier, Signature, VerifyingKey},
     NistP256,
 };
 pub use sha2::Digest;
 
-fn retrieve_key() -> VerifyingKey {
-    #[allow(unused)]
-    use core::str::FromStr;
-
-    #[cfg(test)]
-    return VerifyingKey::from_str(include_str!("../assets/test_key.pem"))
-        .expect("Invalic public key supplied on compilation");
-
-    #[cfg(not(test))]
-    return VerifyingKey::from_encoded_point(
-        &EncodedPoint::from_bytes(include_bytes!("../assets/key.sec1"))
-            .expect("Invalic public key supplied on compilation"),
-    )
-    .expect("Invalic public key supplied on compilation");
-}
-
-pub struct EcdsaImageReader;
-
-impl Reader for EcdsaImageReader {
-    fn image_at<A, F>(flash: &mut F, bank: Bank<A>) -> Result<Image<A>, error::Error>
+/// Verifies images through a P256 ECDSA signature, against the public key supplied
+/// by the `K` key source.
+///
+/// Unless `STRICT_SCAN` is set, banks whose first byte is 0xFF are quickly rejected
+/// as empty instead of being scanned in full. This is much faster for erased banks,
+/// but wrongly rejects any valid image that happens to start with 0xFF.
+///
+/// `MAX_SCAN` and `MIN_SIZE` bound the scan as described on [`Reader`].
+pub struct EcdsaImageReader<
+    K: KeySource,
+    const STRICT_SCAN: bool,
+    const MAX_SCAN: usize = { usize::MAX },
+    const MIN_SIZE: usize = 0,
+>(PhantomData<K>);
+
+impl<K: KeySource, const STRICT_SCAN: bool, const MAX_SCAN: usize, const MIN_SIZE: usize> Reader
+    for EcdsaImageReader<K, STRICT_SCAN, MAX_SCAN, MIN_SIZE>
+{
+    fn image_at_with_progress<A, F, P>(
+        flash: &mut F,
+        bank: Bank<A>,
+        mut progress: P,
+    ) -> Result<Image<A>, error::Error>
     where
         A: Address,
         F: flash::ReadWrite<Address = A>,
+        P: FnMut(usize),
         error::Error: From<F::Error>,
     {
-        // Development build shorcut: We're checking that the image does *not* start with 0xFF. This
-        // will not be part of the final Loadstone release build, but it helps speed up the
-        // verification for invalid images during development.
-        if flash.bytes(bank.location).next().ok_or(Error::BankInvalid)? == 0xFF {
+        // Shortcut: We're checking that the image does *not* start with 0xFF. This helps speed
+        // up the verification for empty banks, and can be disabled through `strict_scan`.
+        if !STRICT_SCAN && flash.bytes(bank.location).next().ok_or(Error::BankInvalid)? == 0xFF {
             return Err(Error::BankEmpty);
         }
-        let key = retrieve_key();
+        let key = K::verifying_key()?;
 
         // Generic buffer to hold temporary slices read from flash memory.
         const BUFFER_SIZE: usize = 256;
         let mut buffer = [0u8; BUFFER_SIZE];
 
-        let (mut digest, mut image_size) = flash
-            .bytes(bank.location)
-            .take(bank.size)
-            .until_sequence(&magic_string_inverted())
-            .fold((sha2::Sha256::default(), 0usize), |(mut digest, mut byte_count), byte| {
-                digest.update(&[byte]);
-                byte_count += 1;
-                (digest, byte_count)
-            });
-
-        if image_size == bank.size {
-            return Err(Error::BankEmpty);
+        let scanned_size = scan_limit(bank, MAX_SCAN);
+        let mut digest = sha2::Sha256::default();
+        let mut magic_string_offset = 0usize;
+        let mut skipped = None;
+        let mut occupied = false;
+        loop {
+            magic_string_offset = flash
+                .bytes(bank.location + magic_string_offset)
+                .take(scanned_size - magic_string_offset)
+                .until_sequence(&magic_string_inverted())
+                .fold(magic_string_offset, |mut byte_count, byte| {
+                    digest.update(&[byte]);
+                    occupied |= byte != 0xFF;
+                    byte_count += 1;
+                    if byte_count % SCAN_PROGRESS_INTERVAL == 0 {
+                        progress(byte_count);
+                    }
+                    byte_count
+                });
+
+            if magic_string_offset == scanned_size {
+                let missing = if occupied { Error::ImageUndecorated } else { Error::BankEmpty };
+                return Err(skipped.unwrap_or(missing));
+            }
+
+            let (image_size, golden, no_auto_update) =
+                read_flags(flash, bank, magic_string_offset, &mut buffer)?;
+            let boundary =
+                read_boundary(flash, bank, magic_string_offset, image_size, Algorithm::P256)?;
+
+            // Magic string is part of the digest, and of the body if scanning carries on.
+            digest.update(&magic_string_inverted());
+            let image = |tagged, framed, signature| Image {
+                size: image_size,
+                location: bank.location,
+                bootable: bank.bootable,
+                golden,
+                no_auto_update,
+                algorithm: Algorithm::P256,
+                tagged,
+                framed,
+                identifier: Identifier::Signature(signature),
+            };
+
+            let trailer_offset = magic_string_offset + MAGIC_STRING.len();
+            if let Boundary::Trailer { framed } = boundary {
+                if image_size < MIN_SIZE {
+                    return Err(Error::ImageTooSmall);
+                }
+                let signature_offset =
+                    trailer_offset + Algorithm::ID_SIZE + if framed { FRAME_SIZE } else { 0 };
+                match read_signature(flash, bank, signature_offset, &mut buffer)? {
+                    Some(signature) if verify(&key, &digest, &signature) => {
+                        return Ok(image(true, framed, signature))
+                    }
+                    _ => (),
+                }
+            }
+            if image_size >= MIN_SIZE {
+                match read_signature(flash, bank, trailer_offset, &mut buffer) {
+                    Ok(Some(signature)) if verify(&key, &digest, &signature) => {
+                        return Ok(image(false, false, signature))
+                    }
+                    _ => (),
+                }
+            }
+            match boundary {
+                Boundary::Skip(error) => skipped = skipped.or(Some(error)),
+                _ if framed_past(flash, bank, magic_string_offset, scanned_size)? => (),
+                Boundary::Trailer { .. } => return Err(Error::SignatureInvalid),
+                Boundary::Foreign => return Err(Error::UnsupportedAlgorithm),
+            }
+            magic_string_offset += MAGIC_STRING.len();
         }
+    }
+}
 
-        // Magic string is part of the digest
-        digest.update(&magic_string_inverted());
-
-        let signature_position = bank.location + image_size + MAGIC_STRING.len();
-        let signature_bytes = &mut buffer[0..SignatureSize::<NistP256>::to_usize()];
-        block!(flash.read(signature_position, signature_bytes))?;
+/// Reads the signature stored `offset` bytes into a bank, if the bytes hold one.
+fn read_signature<A, F>(
+    flash: &mut F,
+    bank: Bank<A>,
+    offset: usize,
+    buffer: &mut [u8],
+) -> Result<Option<Signature>, error::Error>
+where
+    A: Address,
+    F: flash::ReadWrite<Address = A>,
+    error::Error: From<F::Error>,
+{
+    let signature_bytes = &mut buffer[0..SignatureSize::<NistP256>::to_usize()];
+    let signature_position = bank.address_at(offset, signature_bytes.len())?;
+    block!(flash.read(signature_position, signature_bytes))?;
+    Ok(Signature::from_bytes(signature_bytes).ok())
+}
 
-        let signature =
-            Signature::from_bytes(signature_bytes).map_err(|_| Error::SignatureInvalid)?;
-        key.verify_digest(digest, &signature).map_err(|_| Error::SignatureInvalid)?;
+/// Whether a signature matches the digest of everything up to the magic string. The
+/// digest is left untouched, as scanning may carry on past the magic string.
+fn verify(key: &VerifyingKey, digest: &sha2::Sha256, signature: &Signature) -> bool {
+    key.verify_digest(digest.clone(), signature).is_ok()
+}
 
-        let golden_string_position = bank.location + image_size.saturating_sub(GOLDEN_STRING.len());
-        let golden_bytes = &mut buffer[0..GOLDEN_STRING.len()];
-        block!(flash.read(golden_string_position, golden_bytes))?;
-        let golden = golden_bytes == GOLDEN_STRING.as_bytes();
+/// Reads the golden and no-auto-update strings preceding the magic string found
+/// `magic_string_offset` bytes into a bank. Returns the size of the body before them,
+/// and whether each is present.
+fn read_flags<A, F>(
+    flash: &mut F,
+    bank: Bank<A>,
+    magic_string_offset: usize,
+    buffer: &mut [u8],
+) -> Result<(usize, bool, bool), error::Error>
+where
+    A: Address,
+    F: flash::ReadWrite<Address = A>,
+    error::Error: From<F::Error>,
+{
+    let mut image_size = magic_string_offset;
+    let golden_bytes = &mut buffer[0..GOLDEN_STRING.len()];
+    let golden_string_position =
+        bank.address_at(image_size.saturating_sub(GOLDEN_STRING.len()), golden_bytes.len())?;
+    block!(flash.read(golden_string_position, golden_bytes))?;
+    let golden = golden_bytes == GOLDEN_STRING.as_bytes();
+
+    if golden {
+        image_size = image_size.saturating_sub(GOLDEN_STRING.len());
+    }
 
-        if golden {
-            image_size = image_size.saturating_sub(GOLDEN_STRING.len());
-        }
+    let no_auto_update_bytes = &mut buffer[0..NO_AUTO_UPDATE_STRING.len()];
+    let no_auto_update_string_position = bank.address_at(
+        image_size.saturating_sub(NO_AUTO_UPDATE_STRING.len()),
+        no_auto_update_bytes.len(),
+    )?;
+    block!(flash.read(no_auto_update_string_position, no_auto_update_bytes))?;
+    let no_auto_update = no_auto_update_bytes == NO_AUTO_UPDATE_STRING.as_bytes();
 
-        Ok(Image {
-            size: image_size,
-            location: bank.location,
-            bootable: bank.bootable,
-            golden,
-            signature,
-        })
+    if no_auto_update {
+        image_size = image_size.saturating_sub(NO_AUTO_UPDATE_STRING.len());
     }
+    Ok((image_size, golden, no_auto_update))
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use crate::devices::image::key_source::EmbeddedKey;
     use blue_hal::hal::{
         doubles::{
             error::FakeError,
@@ -110,15 +211,17 @@ mod tests {
     use std::convert::TryInto;
 
     #[rustfmt::skip]
-    const TEST_SIGNED_IMAGE: &[u8] = &[
+    pub(crate) const TEST_SIGNED_IMAGE: &[u8] = &[
         // Image
         0xaa, 0xbb,
         // Magic string inverted
         0xb7, 0xac, 0x9c, 0xc8, 0x9c, 0xcd, 0x8f, 0x8b,
         0x86, 0x9b, 0xa5, 0xb7, 0xcd, 0xae, 0x94, 0x8e,
-        // Signature
         0xa5, 0xa8, 0xaf, 0x9c, 0xb5, 0x98, 0xb8, 0xcc,
         0xb5, 0x8b, 0x91, 0xb5, 0xc9, 0xa9, 0x8a, 0xbe,
+        // Algorithm
+        0x02,
+        // Signature
         0x49, 0xdb, 0xc3, 0x82, 0x37, 0xff, 0x13, 0x9a,
         0x96, 0xb1, 0xb2, 0x37, 0x4a, 0x41, 0x35, 0x36,
         0xd4, 0xed, 0xc7, 0xdf, 0x00, 0x80, 0x54, 0xde,
@@ -138,9 +241,11 @@ mod tests {
         // Magic String Inverted
         0xb7, 0xac, 0x9c, 0xc8, 0x9c, 0xcd, 0x8f, 0x8b,
         0x86, 0x9b, 0xa5, 0xb7, 0xcd, 0xae, 0x94, 0x8e,
-        // Signature
         0xa5, 0xa8, 0xaf, 0x9c, 0xb5, 0x98, 0xb8, 0xcc,
         0xb5, 0x8b, 0x91, 0xb5, 0xc9, 0xa9, 0x8a, 0xbe,
+        // Algorithm
+        0x02,
+        // Signature
         0x8a, 0xb7, 0xcb, 0x03, 0x03, 0x53, 0xd2, 0xa3,
         0x9d, 0x42, 0x99, 0x3f, 0x94, 0xfc, 0x2d, 0x91,
         0x4b, 0x91, 0x50, 0xfb, 0xdc, 0x28, 0xaa, 0x11,
@@ -159,10 +264,13 @@ mod tests {
         // Magic string inverted
         0xb7, 0xac, 0x9c, 0xc8, 0x9c, 0xcd, 0x8f, 0x8b,
         0x86, 0x9b, 0xa5, 0xb7, 0xcd, 0xae, 0x94, 0x8e,
-
-        // Signature
         0xa5, 0xa8, 0xaf, 0x9c, 0xb5, 0x98, 0xb8, 0xcc,
         0xb5, 0x8b, 0x91, 0xb5, 0xc9, 0xa9, 0x8a, 0xbe,
+
+        // Algorithm
+        0x02,
+
+        // Signature
         0x12, 0x77, 0x26, 0xc9, 0x13, 0x89, 0x38, 0xca,
         0x23, 0xb9, 0x3d, 0xc9, 0xdc, 0xad, 0xbc, 0x8b,
         0x41, 0x99, 0xe0, 0x89, 0x97, 0xf4, 0x7d, 0x88,
@@ -182,9 +290,11 @@ mod tests {
         // Magic string inverted
         0xb7, 0xac, 0x9c, 0xc8, 0x9c, 0xcd, 0x8f, 0x8b,
         0x86, 0x9b, 0xa5, 0xb7, 0xcd, 0xae, 0x94, 0x8e,
-        // Signature
         0xa5, 0xa8, 0xaf, 0x9c, 0xb5, 0x98, 0xb8, 0xcc,
         0xb5, 0x8b, 0x91, 0xb5, 0xc9, 0xa9, 0x8a, 0xbe,
+        // Algorithm
+        0x02,
+        // Signature
         0xcf, 0x71, 0x77, 0x7f, 0x47, 0x4b, 0x3e, 0xd4,
         0x01, 0xaa, 0x65, 0x22, 0x78, 0x4a, 0x0f, 0x4a,
         0x84, 0x11, 0x65, 0xba, 0x7c, 0x85, 0x00, 0x8b,
@@ -202,7 +312,7 @@ mod tests {
             Bank { index: 1, size: 512, location: Address(0), bootable: false, is_golden: false };
         flash.write(Address(0), &TEST_SIGNED_IMAGE).unwrap();
 
-        let image = EcdsaImageReader::image_at(&mut flash, bank).unwrap();
+        let image = EcdsaImageReader::<EmbeddedKey, false>::image_at(&mut flash, bank).unwrap();
         assert_eq!(image.size, 2usize);
         assert_eq!(image.location, bank.location);
         assert_eq!(image.bootable, false);
@@ -216,7 +326,7 @@ mod tests {
             Bank { index: 1, size: 512, location: Address(0), bootable: false, is_golden: false };
         flash.write(Address(0), &TEST_SIGNED_GOLDEN_IMAGE).unwrap();
 
-        let image = EcdsaImageReader::image_at(&mut flash, bank).unwrap();
+        let image = EcdsaImageReader::<EmbeddedKey, false>::image_at(&mut flash, bank).unwrap();
         assert_eq!(image.size, 2usize);
         assert_eq!(image.location, bank.location);
         assert_eq!(image.bootable, false);
@@ -230,10 +340,16 @@ mod tests {
             Bank { index: 1, size: 512, location: Address(0), bootable: false, is_golden: false };
 
         flash.write(Address(0), &TEST_IMAGE_SIGNED_BY_ANOTHER_KEY).unwrap();
-        assert_eq!(Err(Error::SignatureInvalid), EcdsaImageReader::image_at(&mut flash, bank));
+        assert_eq!(
+            Err(Error::SignatureInvalid),
+            EcdsaImageReader::<EmbeddedKey, false>::image_at(&mut flash, bank)
+        );
 
         flash.write(Address(0), &TEST_GOLDEN_IMAGE_SIGNED_BY_ANOTHER_KEY).unwrap();
-        assert_eq!(Err(Error::SignatureInvalid), EcdsaImageReader::image_at(&mut flash, bank));
+        assert_eq!(
+            Err(Error::SignatureInvalid),
+            EcdsaImageReader::<EmbeddedKey, false>::image_at(&mut flash, bank)
+        );
     }
 
     #[test]
@@ -242,19 +358,28 @@ mod tests {
         let bank =
             Bank { index: 1, size: 512, location: Address(0), bootable: false, is_golden: false };
 
-        let mut image: [u8; 98] = TEST_SIGNED_IMAGE.try_into().unwrap();
+        let mut image: [u8; 99] = TEST_SIGNED_IMAGE.try_into().unwrap();
         image[0] = 0xCC; // Corrupted image body;
         flash.write(Address(0), &image).unwrap();
-        assert_eq!(Err(Error::SignatureInvalid), EcdsaImageReader::image_at(&mut flash, bank));
+        assert_eq!(
+            Err(Error::SignatureInvalid),
+            EcdsaImageReader::<EmbeddedKey, false>::image_at(&mut flash, bank)
+        );
 
-        let mut image: [u8; 98] = TEST_SIGNED_IMAGE.try_into().unwrap();
+        let mut image: [u8; 99] = TEST_SIGNED_IMAGE.try_into().unwrap();
         image[3] = 0xCC; // Corrupted magic string
         flash.write(Address(0), &image).unwrap();
-        assert_eq!(Err(Error::BankEmpty), EcdsaImageReader::image_at(&mut flash, bank));
+        assert_eq!(
+            Err(Error::ImageUndecorated),
+            EcdsaImageReader::<EmbeddedKey, false>::image_at(&mut flash, bank)
+        );
 
-        let mut image: [u8; 98] = TEST_SIGNED_IMAGE.try_into().unwrap();
+        let mut image: [u8; 99] = TEST_SIGNED_IMAGE.try_into().unwrap();
         image[96] = 0xCC; // Corrupted signature
         flash.write(Address(0), &image).unwrap();
-        assert_eq!(Err(Error::SignatureInvalid), EcdsaImageReader::image_at(&mut flash, bank));
+        assert_eq!(
+            Err(Error::SignatureInvalid),
+            EcdsaImageReader::<EmbeddedKey, false>::image_at(&mut flash, bank)
+        );
     }
 }
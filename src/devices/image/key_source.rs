@@ -0,0 +1,158 @@
+//! Sources for the public key that ECDSA signed images are verified against.
+//!
+//! By default the key is embedded in the Loadstone binary at compile time
+//! ([`EmbeddedKey`]). High security deployments may instead keep it in an external
+//! secure element, so that key provisioning is decoupled from the firmware binary
+//! ([`SecureElement`]).
+//!
+//! # Secure element protocol
+//!
+//! The secure element is addressed through a single SPI transaction:
+//!
+//! * Loadstone writes the two byte command `[READ_PUBLIC_KEY, slot]`.
+//! * The secure element answers with a status byte, followed by the key as an
+//!   uncompressed SEC1 point (`0x04 || X || Y`, 65 bytes).
+//!
+//! Any status other than [`STATUS_OK`] is treated as the key being unavailable.
+//! This is deliberately minimal, and expected to be adapted to the command set
+//! of the specific secure element in use.
+
+use core::marker::PhantomData;
+
+use crate::error::Error;
+use p256::{ecdsa::VerifyingKey, EncodedPoint};
+use sha2::{Digest, Sha256};
+
+/// Command byte requesting the public key stored in a slot.
+pub const READ_PUBLIC_KEY: u8 = 0x30;
+/// Status byte reported by the secure element on success.
+pub const STATUS_OK: u8 = 0x00;
+/// Length of an uncompressed SEC1 encoded P256 public key.
+pub const SEC1_KEY_LENGTH: usize = 65;
+/// Number of leading SHA-256 digest bytes kept in a key fingerprint.
+pub const FINGERPRINT_LENGTH: usize = 8;
+
+/// Provides the public key that image signatures are verified against.
+pub trait KeySource {
+    fn verifying_key() -> Result<VerifyingKey, Error>;
+}
+
+/// Key embedded in the Loadstone binary at compile time.
+pub struct EmbeddedKey;
+
+impl KeySource for EmbeddedKey {
+    fn verifying_key() -> Result<VerifyingKey, Error> {
+        #[allow(unused)]
+        use core::str::FromStr;
+
+        #[cfg(test)]
+        return VerifyingKey::from_str(include_str!("../assets/test_key.pem"))
+            .map_err(|_| Error::KeyUnavailable);
+
+        #[cfg(not(test))]
+        return VerifyingKey::from_encoded_point(
+            &EncodedPoint::from_bytes(include_bytes!("../assets/key.sec1"))
+                .map_err(|_| Error::KeyUnavailable)?,
+        )
+        .map_err(|_| Error::KeyUnavailable);
+    }
+}
+
+/// Identifies a public key by the truncated SHA-256 digest of its uncompressed SEC1
+/// encoding, so operators can confirm which key a device trusts at a glance.
+pub fn fingerprint(key: &VerifyingKey) -> [u8; FINGERPRINT_LENGTH] {
+    let digest = Sha256::digest(key.to_encoded_point(false).as_bytes());
+    let mut fingerprint = [0u8; FINGERPRINT_LENGTH];
+    fingerprint.copy_from_slice(&digest[..FINGERPRINT_LENGTH]);
+    fingerprint
+}
+
+/// SPI bus connected to a secure element. Key sources are stateless, so ports
+/// implement this by accessing their SPI peripheral directly.
+pub trait SecureElementBus {
+    /// Writes `command`, then reads `response.len()` bytes back.
+    fn transfer(command: &[u8], response: &mut [u8]) -> Result<(), Error>;
+}
+
+/// Key read from a secure element at verification time.
+pub struct SecureElement<B: SecureElementBus, const SLOT: u8>(PhantomData<B>);
+
+impl<B: SecureElementBus, const SLOT: u8> KeySource for SecureElement<B, SLOT> {
+    fn verifying_key() -> Result<VerifyingKey, Error> {
+        let mut response = [0u8; 1 + SEC1_KEY_LENGTH];
+        B::transfer(&[READ_PUBLIC_KEY, SLOT], &mut response)?;
+        let (status, key) = response.split_at(1);
+        if status[0] != STATUS_OK {
+            return Err(Error::KeyUnavailable);
+        }
+        let point = EncodedPoint::from_bytes(key).map_err(|_| Error::KeyUnavailable)?;
+        VerifyingKey::from_encoded_point(&point).map_err(|_| Error::KeyUnavailable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::str::FromStr;
+
+    fn test_key() -> VerifyingKey {
+        VerifyingKey::from_str(include_str!("../assets/test_key.pem")).unwrap()
+    }
+
+    /// Secure element that holds the test key in slot 0, and nothing anywhere else.
+    struct MockBus;
+
+    impl SecureElementBus for MockBus {
+        fn transfer(command: &[u8], response: &mut [u8]) -> Result<(), Error> {
+            match command {
+                [READ_PUBLIC_KEY, 0] => {
+                    response[0] = STATUS_OK;
+                    response[1..].copy_from_slice(test_key().to_encoded_point(false).as_bytes());
+                }
+                _ => response[0] = 0xFF,
+            }
+            Ok(())
+        }
+    }
+
+    struct DisconnectedBus;
+
+    impl SecureElementBus for DisconnectedBus {
+        fn transfer(_: &[u8], _: &mut [u8]) -> Result<(), Error> {
+            Err(Error::DriverError("Secure element not responding"))
+        }
+    }
+
+    #[test]
+    fn embedded_key_is_available() {
+        assert_eq!(Ok(test_key()), EmbeddedKey::verifying_key());
+    }
+
+    #[test]
+    fn fingerprint_is_stable_and_tells_keys_apart() {
+        let key = EmbeddedKey::verifying_key().unwrap();
+        assert_eq!(fingerprint(&key), fingerprint(&test_key()));
+
+        let other_key =
+            VerifyingKey::from(&p256::ecdsa::SigningKey::from_bytes(&[1u8; 32]).unwrap());
+        assert_ne!(fingerprint(&key), fingerprint(&other_key));
+    }
+
+    #[test]
+    fn secure_element_key_is_read_from_slot() {
+        assert_eq!(Ok(test_key()), SecureElement::<MockBus, 0>::verifying_key());
+    }
+
+    #[test]
+    fn secure_element_error_status_makes_key_unavailable() {
+        assert_eq!(Err(Error::KeyUnavailable), SecureElement::<MockBus, 1>::verifying_key());
+    }
+
+    #[test]
+    fn secure_element_bus_errors_are_propagated() {
+        assert_eq!(
+            Err(Error::DriverError("Secure element not responding")),
+            SecureElement::<DisconnectedBus, 0>::verifying_key()
+        );
+    }
+}
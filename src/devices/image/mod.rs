@@ -3,44 +3,172 @@
 //! This module offers tools to partition flash memory spaces
 //! into image banks and scan those banks for valid images.
 
-#[cfg(not(feature = "ecdsa-verify"))]
+pub mod compression;
+pub mod dispatch;
 pub mod image_crc;
 #[cfg(feature = "ecdsa-verify")]
 pub mod image_ecdsa;
+#[cfg(feature = "ecdsa-verify")]
+pub mod key_source;
+pub mod verification_cache;
 
-#[cfg(not(feature = "ecdsa-verify"))]
+pub use dispatch::DispatchingReader;
 pub use image_crc::CrcImageReader;
 #[cfg(feature = "ecdsa-verify")]
 pub use image_ecdsa::EcdsaImageReader;
-
 #[cfg(feature = "ecdsa-verify")]
-use ecdsa::elliptic_curve::generic_array::typenum::Unsigned;
+pub use key_source::{EmbeddedKey, KeySource, SecureElement, SecureElementBus};
+pub use verification_cache::VerificationCache;
+
+pub use loadstone_image_format::{
+    Algorithm, FRAME_SIZE, GOLDEN_STRING, MAGIC_STRING, MAGIC_STRING_INVERTED,
+    NO_AUTO_UPDATE_STRING,
+};
 
 use blue_hal::{
     hal::flash,
-    utilities::{buffer::CollectSlice, memory::Address},
+    utilities::{iterator::UntilSequence, memory::Address},
+    KB,
 };
+use core::{cmp::min, convert::TryInto};
+
+use crate::{devices::traits::Flash, error};
 
-use crate::error;
+/// Number of bytes scanned between calls to the progress callback of
+/// [`Reader::image_at_with_progress`].
+pub const SCAN_PROGRESS_INTERVAL: usize = KB!(64);
 
-/// This string precedes the CRC/Signature for golden images only
-pub const GOLDEN_STRING: &str = "XPIcbOUrpG";
+/// Size in bytes of the address space flash addresses live in.
+const ADDRESS_SPACE_SIZE: u64 = 1 << 32;
 
-/// This string, INVERTED BYTEWISE must terminate any valid images, after CRC/Signature
+/// Number of bytes of a bank scanned for the magic string, given a reader's `max_scan`.
 ///
-/// Note: Why inverted? Because if we used it as-is, no code that includes this
-/// constant could be used as a firmware image, as it contains the magic string
-/// halfway through.
-pub const MAGIC_STRING: &str = "HSc7c2ptydZH2QkqZWPcJgG3JtnJ6VuA";
+/// No valid image is expected to be larger than `max_scan`, so a bank holding no magic
+/// string within that many bytes is considered empty without reading the rest of it.
+/// Images whose magic string doesn't end within the limit are never found.
+pub fn scan_limit<A: Address>(bank: Bank<A>, max_scan: usize) -> usize { bank.size.min(max_scan) }
 
-/// utility function to invert the [`MAGIC_STRING`].
-pub fn magic_string_inverted() -> [u8; MAGIC_STRING.len()] {
-    let mut inverted = [0u8; MAGIC_STRING.len()];
-    let mut bytes = MAGIC_STRING.as_bytes().iter().map(|b| !b);
-    bytes.collect_slice(&mut inverted);
-    inverted
+/// A reader's verdict on an occurrence of the inverted magic string, going by the
+/// identifier that follows it.
+///
+/// Images signed before identifiers were introduced carry their digest right after the
+/// magic string, so whatever the verdict, readers also check the occurrence as the end
+/// of such an untagged image, verified with their own scheme. Readers fail at trailers
+/// that don't verify, unless a framed image further into the bank records a body
+/// spanning the occurrence (see [`framed_past`]).
+pub(crate) enum Boundary {
+    /// The occurrence starts the trailer of an image verified with the expected scheme.
+    Trailer { framed: bool },
+    /// The occurrence starts the trailer of an image verified with another scheme, or
+    /// with one Loadstone doesn't know of, which fails with
+    /// [`error::Error::UnsupportedAlgorithm`].
+    Foreign,
+    /// The occurrence is part of the body, so scanning carries on past it. Holds the
+    /// error to report if no trailer is found after it.
+    Skip(error::Error),
+}
+
+/// Reads the algorithm identifier, and the frame if any, following the inverted magic
+/// string found `magic_string_offset` bytes into a bank, after a body of `body_size`
+/// bytes. Occurrences followed by a mismatched frame are skipped, while those naming a
+/// scheme other than `expected`, or an unknown one, are foreign.
+pub(crate) fn read_boundary<A, F>(
+    flash: &mut F,
+    bank: Bank<A>,
+    magic_string_offset: usize,
+    body_size: usize,
+    expected: Algorithm,
+) -> Result<Boundary, error::Error>
+where
+    A: Address,
+    F: flash::ReadWrite<Address = A>,
+    error::Error: From<F::Error>,
+{
+    let mut id = [0u8; Algorithm::ID_SIZE];
+    let id_offset = magic_string_offset + MAGIC_STRING.len();
+    nb::block!(flash.read(bank.address_at(id_offset, Algorithm::ID_SIZE)?, &mut id))?;
+    let (algorithm, framed) = match Algorithm::parse_id(id[0]) {
+        Some(parsed) => parsed,
+        None => return Ok(Boundary::Foreign),
+    };
+    if framed {
+        let mut frame = [0u8; FRAME_SIZE];
+        let frame_offset = id_offset + Algorithm::ID_SIZE;
+        nb::block!(flash.read(bank.address_at(frame_offset, FRAME_SIZE)?, &mut frame))?;
+        if loadstone_image_format::recorded_body_size(frame) != body_size {
+            return Ok(Boundary::Skip(error::Error::FramingMismatch));
+        }
+    }
+    if algorithm == expected {
+        Ok(Boundary::Trailer { framed })
+    } else {
+        Ok(Boundary::Foreign)
+    }
 }
 
+/// Whether a framed trailer past the inverted magic string found `magic_string_offset`
+/// bytes into a bank, within its first `scanned_size` bytes, records a body spanning
+/// that occurrence. If so, the occurrence is part of the body whatever follows it, and
+/// readers skip past it rather than failing there.
+///
+/// Only the frame is looked at: readers check it exactly, and verify the image, once
+/// their scan gets to that trailer.
+pub(crate) fn framed_past<A, F>(
+    flash: &mut F,
+    bank: Bank<A>,
+    magic_string_offset: usize,
+    scanned_size: usize,
+) -> Result<bool, error::Error>
+where
+    A: Address,
+    F: flash::ReadWrite<Address = A>,
+    error::Error: From<F::Error>,
+{
+    const FLAGS_SIZE: usize = GOLDEN_STRING.len() + NO_AUTO_UPDATE_STRING.len();
+    let spanned_size = magic_string_offset + MAGIC_STRING.len();
+    let mut offset = spanned_size;
+    loop {
+        offset = flash
+            .bytes(bank.location + offset)
+            .take(scanned_size - offset)
+            .until_sequence(&magic_string_inverted())
+            .fold(offset, |byte_count, _| byte_count + 1);
+        if offset == scanned_size {
+            return Ok(false);
+        }
+
+        let trailer_offset = offset + MAGIC_STRING.len();
+        let mut trailer = [0u8; Algorithm::ID_SIZE + FRAME_SIZE];
+        let address = match bank.address_at(trailer_offset, trailer.len()) {
+            Ok(address) => address,
+            // No room left in the bank for a frame.
+            Err(_) => return Ok(false),
+        };
+        nb::block!(flash.read(address, &mut trailer))?;
+        let framed = matches!(Algorithm::parse_id(trailer[0]), Some((_, true)));
+        let recorded = loadstone_image_format::recorded_body_size(
+            trailer[Algorithm::ID_SIZE..].try_into().unwrap(),
+        );
+        if framed && (spanned_size..=offset).contains(&recorded) && offset - recorded <= FLAGS_SIZE
+        {
+            return Ok(true);
+        }
+        offset = trailer_offset;
+    }
+}
+
+/// Verification scheme this build enforces on images, as selected by its features.
+pub const fn enforced_algorithm() -> Algorithm {
+    if cfg!(feature = "ecdsa-verify") {
+        Algorithm::P256
+    } else {
+        Algorithm::Crc32
+    }
+}
+
+/// utility function to invert the [`MAGIC_STRING`].
+pub fn magic_string_inverted() -> [u8; MAGIC_STRING.len()] { MAGIC_STRING_INVERTED }
+
 /// Image bank descriptor.
 ///
 /// A bank represents a section of flash memory that may contain a single signed/crc'd
@@ -78,6 +206,67 @@ impl<A: Address> Bank<A> {
     pub fn regular(index: u8, size: usize, location: A) -> Self {
         Self { index, size, location, bootable: false, is_golden: false }
     }
+
+    /// Address of the `length` bytes found `offset` bytes into the bank.
+    ///
+    /// Fails with [`error::Error::AddressOutOfRange`], rather than wrapping around, if
+    /// those bytes extend past the end of the bank or of the 32 bit address space.
+    pub fn address_at(&self, offset: usize, length: usize) -> Result<A, error::Error> {
+        let start: usize = self.location.into();
+        match offset.checked_add(length) {
+            Some(end) if end <= self.size && start as u64 + end as u64 <= ADDRESS_SPACE_SIZE => {
+                Ok(self.location + offset)
+            }
+            _ => Err(error::Error::AddressOutOfRange),
+        }
+    }
+}
+
+/// Overwrites a whole bank with the erased flash value (0xFF).
+pub(crate) fn erase_bank<F: Flash>(
+    flash: &mut F,
+    bank: Bank<F::Address>,
+) -> Result<(), error::Error> {
+    // Large buffer ensures that the number of read-write cycles needed
+    // to erase the bank is minimal.
+    const ERASE_BUFFER_SIZE: usize = KB!(16);
+    let erased = [0xFFu8; ERASE_BUFFER_SIZE];
+    let mut byte_index = 0usize;
+    while byte_index < bank.size {
+        let bytes_to_write = min(ERASE_BUFFER_SIZE, bank.size - byte_index);
+        nb::block!(flash.write(bank.location + byte_index, &erased[0..bytes_to_write]))?;
+        byte_index += bytes_to_write;
+    }
+    Ok(())
+}
+
+/// Run of contiguous, equally sized erasable sectors in a flash chip.
+#[derive(Clone, Copy, Debug)]
+pub struct SectorRegion {
+    /// Address of the first sector in the region.
+    pub start: usize,
+    /// Size in bytes of each sector in the region.
+    pub sector_size: usize,
+    /// Number of sectors in the region.
+    pub sector_count: usize,
+}
+
+impl SectorRegion {
+    /// Whether an address coincides with the start of a sector in this region.
+    pub fn is_sector_start(&self, address: usize) -> bool {
+        address >= self.start
+            && address < self.start + self.sector_size * self.sector_count
+            && (address - self.start) % self.sector_size == 0
+    }
+}
+
+/// Unique identifier of a firmware image, for the purposes of updating: its CRC
+/// or signature, depending on the algorithm it was verified with.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Identifier {
+    Crc(u32),
+    #[cfg(feature = "ecdsa-verify")]
+    Signature(image_ecdsa::Signature),
 }
 
 /// Image descriptor.
@@ -90,17 +279,83 @@ pub struct Image<A: Address> {
     location: A,
     bootable: bool,
     golden: bool,
-    #[cfg(feature = "ecdsa-verify")]
-    signature: image_ecdsa::Signature,
-    #[cfg(not(feature = "ecdsa-verify"))]
-    crc: u32,
+    no_auto_update: bool,
+    algorithm: Algorithm,
+    tagged: bool,
+    framed: bool,
+    identifier: Identifier,
+}
+
+/// What a bank holds, as far as image verification goes.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BankContents<A: Address> {
+    /// A valid image.
+    Image(Image<A>),
+    /// Nothing: every byte scanned was erased.
+    Empty,
+    /// Data that doesn't verify as an image, for the given reason. This covers images
+    /// with a bad CRC or signature, data with no image trailer at all, and failures to
+    /// read the bank.
+    Unverified(error::Error),
+}
+
+impl<A: Address> From<Result<Image<A>, error::Error>> for BankContents<A> {
+    fn from(result: Result<Image<A>, error::Error>) -> Self {
+        match result {
+            Ok(image) => BankContents::Image(image),
+            Err(error::Error::BankEmpty) => BankContents::Empty,
+            Err(error) => BankContents::Unverified(error),
+        }
+    }
 }
 
+/// Scans banks for images, verifying them under one scheme.
+///
+/// Readers take two const parameters bounding the work they do on a bank. At most
+/// `MAX_SCAN` bytes of it are scanned for the magic string (see [`scan_limit`]), bounding
+/// the time spent on large banks with no image. Images smaller than `MIN_SIZE` bytes are
+/// rejected with [`error::Error::ImageTooSmall`] without checking their digest, so a few
+/// stray bytes that happen to verify can't pass for one.
 pub trait Reader {
+    /// Scans a bank for a valid image.
+    ///
+    /// A bank that holds nothing but erased bytes fails with [`error::Error::BankEmpty`],
+    /// while one holding data without an image trailer fails with
+    /// [`error::Error::ImageUndecorated`]. An image that is found but doesn't verify fails
+    /// with the reason, e.g. [`error::Error::CrcInvalid`] or
+    /// [`error::Error::SignatureInvalid`]. [`BankContents`] sorts these outcomes out.
     fn image_at<A, F>(flash: &mut F, bank: Bank<A>) -> Result<Image<A>, error::Error>
     where
         A: Address,
         F: flash::ReadWrite<Address = A>,
+        error::Error: From<F::Error>,
+    {
+        Self::image_at_with_progress(flash, bank, |_| ())
+    }
+
+    /// Scans a bank like [`Self::image_at`], telling an empty bank apart from one whose
+    /// contents don't verify.
+    fn contents_at<A, F>(flash: &mut F, bank: Bank<A>) -> BankContents<A>
+    where
+        A: Address,
+        F: flash::ReadWrite<Address = A>,
+        error::Error: From<F::Error>,
+    {
+        Self::image_at(flash, bank).into()
+    }
+
+    /// Scans a bank for a valid image, calling `progress` with the number of bytes
+    /// scanned so far every [`SCAN_PROGRESS_INTERVAL`] bytes. Scanning a large bank
+    /// can take a while, so this allows signaling that the scan is still going.
+    fn image_at_with_progress<A, F, P>(
+        flash: &mut F,
+        bank: Bank<A>,
+        progress: P,
+    ) -> Result<Image<A>, error::Error>
+    where
+        A: Address,
+        F: flash::ReadWrite<Address = A>,
+        P: FnMut(usize),
         error::Error: From<F::Error>;
 }
 
@@ -110,32 +365,57 @@ impl<A: Address> Image<A> {
     pub fn location(&self) -> A { self.location }
     /// Size of the firmware image, excluding decoration and signature/crc.
     pub fn size(&self) -> usize { self.size }
-    /// Size of the firmware image, including decoration and signature.
-    #[cfg(feature = "ecdsa-verify")]
+    /// Size of the firmware image, including decoration, algorithm identifier, frame
+    /// and signature/crc.
     pub fn total_size(&self) -> usize {
-        self.size()
-            + image_ecdsa::SignatureSize::<image_ecdsa::NistP256>::to_usize()
-            + MAGIC_STRING.len()
-            + if self.is_golden() { GOLDEN_STRING.len() } else { 0 }
+        self.signed_size()
+            + if self.tagged() { Algorithm::ID_SIZE } else { 0 }
+            + if self.framed() { FRAME_SIZE } else { 0 }
+            + self.algorithm.digest_size()
     }
-    /// Size of the firmware image, including decoration and crc.
-    #[cfg(not(feature = "ecdsa-verify"))]
-    pub fn total_size(&self) -> usize {
+    /// Size of the region covered by the signature/crc: the firmware image and its
+    /// decoration, up to and including the inverted magic string.
+    pub fn signed_size(&self) -> usize {
         self.size()
-            + core::mem::size_of::<u32>()
-            + MAGIC_STRING.len()
+            + if self.no_auto_update() { NO_AUTO_UPDATE_STRING.len() } else { 0 }
             + if self.is_golden() { GOLDEN_STRING.len() } else { 0 }
+            + MAGIC_STRING.len()
+    }
+    /// Address of the signature/crc, at the very end of the image.
+    pub fn digest_location(&self) -> A {
+        self.location + self.total_size() - self.algorithm.digest_size()
     }
+    /// Whether the image was read from a bootable bank, and can therefore be booted.
+    pub fn bootable(&self) -> bool { self.bootable }
     /// Whether the image is verified to be golden (contains a golden string).
     /// A golden image is a high reliability, 'blessed' image able
     /// to be used as a last resort fallback.
     pub fn is_golden(&self) -> bool { self.golden }
-    #[cfg(feature = "ecdsa-verify")]
-    /// ECDSA signature of the firmware image. This is also used as an unique
-    /// identifier for the firmware image for the purposes of updating.
-    pub fn identifier(&self) -> image_ecdsa::Signature { self.signature }
-    #[cfg(not(feature = "ecdsa-verify"))]
-    /// Firmware image CRC. This is also used as an unique
+    /// Whether the image is flagged to never be used as an update source (contains
+    /// a no auto update string). It can still be booted from the bootable bank.
+    pub fn no_auto_update(&self) -> bool { self.no_auto_update }
+    /// Scheme the image was verified with.
+    pub fn algorithm(&self) -> Algorithm { self.algorithm }
+    /// Whether the image's trailer holds the algorithm identifier. Images signed before
+    /// identifiers were introduced don't, and are verified with the build's own scheme.
+    pub fn tagged(&self) -> bool { self.tagged }
+    /// Whether the image's trailer records the size of its body, so it was found even
+    /// if the body contains the inverted magic string.
+    pub fn framed(&self) -> bool { self.framed }
+    /// Firmware image CRC or ECDSA signature. This is also used as an unique
     /// identifier for the firmware image for the purposes of updating.
-    pub fn identifier(&self) -> u32 { self.crc }
+    pub fn identifier(&self) -> Identifier { self.identifier }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enforced_algorithm_matches_the_active_features() {
+        #[cfg(feature = "ecdsa-verify")]
+        assert_eq!(Algorithm::P256, enforced_algorithm());
+        #[cfg(not(feature = "ecdsa-verify"))]
+        assert_eq!(Algorithm::Crc32, enforced_algorithm());
+    }
 }
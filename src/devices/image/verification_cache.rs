@@ -0,0 +1,307 @@
+//! Opt-in cache of the boot image's verification, trading a security margin for boot time.
+//!
+//! Verifying an image means reading and digesting every byte of it, and checking its
+//! signature if it has one, on every boot. With the cache, a successful verification of
+//! the boot bank is remembered in a reserved region of MCU flash, as the position of
+//! the image's magic string and a tag over the image's trailer and first bytes.
+//! On the following boots the trailer and first bytes are read again, and if their tag
+//! still matches, the image is trusted without digesting the rest of it. On any mismatch
+//! (e.g. a new image was written to the boot bank) the image is verified in full, and
+//! the cache is updated if it passes.
+//!
+//! **The cache weakens image verification.** An image whose body changes past its first
+//! [`SAMPLE_SIZE`] bytes while keeping its trailer, whether by corruption or tampering,
+//! is still booted. The tag is a plain CRC, not a keyed MAC, so anyone able to write the
+//! MCU flash can also forge it. Only enable the cache when boot time matters more than
+//! catching those cases, and when the MCU flash is otherwise protected. As a forged tag
+//! would let an unsigned image skip signature verification, `loadstone_config` refuses
+//! to generate a port with the cache enabled alongside ECDSA image verification.
+//!
+//! The cache lives in two wear leveled [`Ring`]s, so it's only rewritten when the boot
+//! image changes. A power loss between updating both leaves a stale tag behind, which
+//! simply fails to match on the next boot.
+
+use super::*;
+use crate::{
+    devices::{
+        traits::Flash,
+        wear_leveling::{self, Ring},
+    },
+    error::Error,
+};
+use core::convert::TryInto;
+use crc::{crc32, Hasher32};
+use loadstone_image_format::Layout;
+use nb::block;
+
+/// Bytes of the image, from its start, covered by the tag. These hold the vector table
+/// that Loadstone jumps through, so a partially erased or rewritten image is caught.
+pub const SAMPLE_SIZE: usize = 512;
+
+/// Size in bytes of each of the two rings holding the cache.
+const RING_SIZE: usize = 16 * wear_leveling::ENTRY_SIZE;
+
+/// Size in bytes of the MCU flash region reserved for the cache.
+pub const REGION_SIZE: usize = 2 * RING_SIZE;
+
+/// Largest trailer read back from flash: the golden and no-auto-update strings, the
+/// inverted magic string, the algorithm identifier, the frame and the largest digest.
+const MAX_TRAILER_SIZE: usize = GOLDEN_STRING.len()
+    + NO_AUTO_UPDATE_STRING.len()
+    + MAGIC_STRING.len()
+    + Algorithm::ID_SIZE
+    + FRAME_SIZE
+    + 64;
+
+/// Remembers the last image verified in the boot bank. See the [module](self) documentation.
+#[derive(Copy, Clone, Debug)]
+pub struct VerificationCache<A: Address> {
+    location: A,
+}
+
+impl<A: Address> VerificationCache<A> {
+    /// Cache in the region starting at a location, which must be reserved for it.
+    pub fn new(location: A) -> Self { Self { location } }
+
+    fn magic_string_offset_ring(&self) -> Ring<A, RING_SIZE> { Ring::new(self.location) }
+    fn tag_ring(&self) -> Ring<A, RING_SIZE> { Ring::new(self.location + RING_SIZE) }
+
+    /// Scans a bank for a valid image like `R::image_at`, but trusts the cached result if
+    /// it still matches the bank. The cache is updated after a full verification.
+    pub fn image_at<R: Reader, F: Flash<Address = A>>(
+        &self,
+        flash: &mut F,
+        bank: Bank<A>,
+    ) -> Result<Image<A>, Error> {
+        if let Ok(Some(image)) = self.cached_image(flash, bank) {
+            return Ok(image);
+        }
+        let image = R::image_at(flash, bank)?;
+        // Failing to cache only costs a full verification on the next boot.
+        self.record(flash, bank, &image).ok();
+        Ok(image)
+    }
+
+    /// The image in a bank, if it still matches the one last recorded.
+    pub fn cached_image<F: Flash<Address = A>>(
+        &self,
+        flash: &mut F,
+        bank: Bank<A>,
+    ) -> Result<Option<Image<A>>, Error> {
+        let (magic_string_offset, cached_tag) =
+            match (self.magic_string_offset_ring().read(flash)?, self.tag_ring().read(flash)?) {
+                (Some(offset), Some(tag)) => (offset as usize, tag),
+                _ => return Ok(None),
+            };
+        let mut trailer = [0u8; MAX_TRAILER_SIZE];
+        let trailer = match read_trailer(flash, bank, magic_string_offset, &mut trailer)? {
+            Some(trailer) => trailer,
+            None => return Ok(None),
+        };
+        if tag(flash, bank, magic_string_offset, trailer)? != cached_tag {
+            return Ok(None);
+        }
+        Ok(image_from_trailer(bank, magic_string_offset, trailer))
+    }
+
+    /// Records an image, freshly verified in a bank, as the cached one. Untagged images
+    /// (see [`Image::tagged`]) are never cached, as their trailer can't be told apart from
+    /// a tagged one without verifying the image again.
+    pub fn record<F: Flash<Address = A>>(
+        &self,
+        flash: &mut F,
+        bank: Bank<A>,
+        image: &Image<A>,
+    ) -> Result<(), Error> {
+        if !image.tagged() {
+            return Ok(());
+        }
+        let magic_string_offset = image.signed_size() - MAGIC_STRING.len();
+        let mut trailer = [0u8; MAX_TRAILER_SIZE];
+        let trailer = read_trailer(flash, bank, magic_string_offset, &mut trailer)?
+            .ok_or(Error::BankInvalid)?;
+        let tag = tag(flash, bank, magic_string_offset, trailer)?;
+        self.magic_string_offset_ring().write(flash, magic_string_offset as u32)?;
+        self.tag_ring().write(flash, tag)
+    }
+}
+
+/// Reads the bytes of a bank from the start of the longest possible string preceding
+/// the magic string, to the end of the digest. Returns `None` if they don't hold a
+/// decorated image trailer.
+fn read_trailer<'a, F: Flash>(
+    flash: &mut F,
+    bank: Bank<F::Address>,
+    magic_string_offset: usize,
+    buffer: &'a mut [u8; MAX_TRAILER_SIZE],
+) -> Result<Option<&'a [u8]>, Error> {
+    if magic_string_offset + MAGIC_STRING.len() + Algorithm::ID_SIZE > bank.size {
+        return Ok(None);
+    }
+    let start = trailer_start(magic_string_offset);
+    let buffer = &mut buffer[..MAX_TRAILER_SIZE.min(bank.size - start)];
+    block!(flash.read(bank.location + start, buffer))?;
+    Ok(match Layout::parse_from(buffer, start) {
+        Ok(layout) if start + layout.magic_string_offset == magic_string_offset => {
+            Some(&buffer[..layout.total_size()])
+        }
+        _ => None,
+    })
+}
+
+/// Offset of the trailer read back from flash: the start of the longest possible string
+/// preceding the magic string.
+fn trailer_start(magic_string_offset: usize) -> usize {
+    magic_string_offset.saturating_sub(GOLDEN_STRING.len() + NO_AUTO_UPDATE_STRING.len())
+}
+
+/// CRC32 of the bank index, the magic string offset, the image trailer and the first
+/// [`SAMPLE_SIZE`] bytes of the image.
+fn tag<F: Flash>(
+    flash: &mut F,
+    bank: Bank<F::Address>,
+    magic_string_offset: usize,
+    trailer: &[u8],
+) -> Result<u32, Error> {
+    let mut sample = [0u8; SAMPLE_SIZE];
+    let sample = &mut sample[..SAMPLE_SIZE.min(magic_string_offset)];
+    block!(flash.read(bank.location, sample))?;
+    let mut digest = crc32::Digest::new(crc32::IEEE);
+    digest.write(&[bank.index]);
+    digest.write(&(magic_string_offset as u32).to_le_bytes());
+    digest.write(trailer);
+    digest.write(sample);
+    Ok(digest.sum32())
+}
+
+/// Image descriptor for a trailer read from a bank, if its algorithm is one Loadstone
+/// can identify images by.
+fn image_from_trailer<A: Address>(
+    bank: Bank<A>,
+    magic_string_offset: usize,
+    trailer: &[u8],
+) -> Option<Image<A>> {
+    let layout = Layout::parse_from(trailer, trailer_start(magic_string_offset)).ok()?;
+    let digest = &trailer[layout.digest_offset()..layout.total_size()];
+    let identifier = match layout.algorithm {
+        Algorithm::Crc32 => Identifier::Crc(u32::from_le_bytes(digest.try_into().ok()?)),
+        #[cfg(feature = "ecdsa-verify")]
+        Algorithm::P256 => {
+            use image_ecdsa::EcdsaSignature;
+            Identifier::Signature(image_ecdsa::Signature::from_bytes(digest).ok()?)
+        }
+        _ => return None,
+    };
+    let flags_size = if layout.golden { GOLDEN_STRING.len() } else { 0 }
+        + if layout.no_auto_update { NO_AUTO_UPDATE_STRING.len() } else { 0 };
+    Some(Image {
+        size: magic_string_offset - flags_size,
+        location: bank.location,
+        bootable: bank.bootable,
+        golden: layout.golden,
+        no_auto_update: layout.no_auto_update,
+        algorithm: layout.algorithm,
+        tagged: true,
+        framed: layout.framed,
+        identifier,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::image::image_crc::tests::{
+        framed_test_image, golden_test_image, regular_test_image,
+    };
+    use blue_hal::hal::{doubles::flash::*, flash::ReadWrite};
+
+    type Reader = CrcImageReader<{ crc32::IEEE }, false>;
+
+    const BANK: Bank<Address> =
+        Bank { index: 1, size: 2048, location: Address(0), bootable: true, is_golden: false };
+    const CACHE_LOCATION: Address = Address(4096);
+
+    fn flash_with(image: &[u8]) -> FakeFlash {
+        let mut flash = FakeFlash::new(Address(0));
+        flash.write(Address(0), &[0xFF; 4096 + REGION_SIZE]).unwrap();
+        flash.write(Address(0), image).unwrap();
+        flash
+    }
+
+    fn body(fill: u8) -> Vec<u8> { vec![fill; 3 * SAMPLE_SIZE / 2] }
+
+    #[test]
+    fn verified_images_are_cached_with_the_same_descriptor() {
+        let image = golden_test_image(&body(0x5A));
+        let mut flash = flash_with(&image);
+        let cache = VerificationCache::new(CACHE_LOCATION);
+        assert_eq!(Ok(None), cache.cached_image(&mut flash, BANK));
+
+        let verified = cache.image_at::<Reader, _>(&mut flash, BANK).unwrap();
+        assert_eq!(Reader::image_at(&mut flash, BANK), Ok(verified));
+        assert_eq!(Ok(Some(verified)), cache.cached_image(&mut flash, BANK));
+    }
+
+    #[test]
+    fn cache_hits_skip_digesting_the_body_past_the_sample() {
+        let mut flash = flash_with(&regular_test_image(&body(0x5A)));
+        let cache = VerificationCache::new(CACHE_LOCATION);
+        let verified = cache.image_at::<Reader, _>(&mut flash, BANK).unwrap();
+
+        // This is the security margin traded away: the CRC no longer matches, but the
+        // change is out of reach of the tag.
+        flash.write(Address(SAMPLE_SIZE as u32 + 1), &[0x00]).unwrap();
+        assert!(Reader::image_at(&mut flash, BANK).is_err());
+        assert_eq!(Ok(verified), cache.image_at::<Reader, _>(&mut flash, BANK));
+    }
+
+    #[test]
+    fn changed_images_invalidate_the_cache_and_are_verified_in_full() {
+        let mut flash = flash_with(&regular_test_image(&body(0x5A)));
+        let cache = VerificationCache::new(CACHE_LOCATION);
+        let old = cache.image_at::<Reader, _>(&mut flash, BANK).unwrap();
+
+        // A new valid image replaces the cached one.
+        let new_image = regular_test_image(&body(0xA5));
+        flash.write(Address(0), &new_image).unwrap();
+        assert_eq!(Ok(None), cache.cached_image(&mut flash, BANK));
+        let new = cache.image_at::<Reader, _>(&mut flash, BANK).unwrap();
+        assert_ne!(old.identifier(), new.identifier());
+        assert_eq!(Ok(Some(new)), cache.cached_image(&mut flash, BANK));
+
+        // A corrupted vector table is caught by the tag, then by the full verification.
+        flash.write(Address(4), &[0x00]).unwrap();
+        assert_eq!(Ok(None), cache.cached_image(&mut flash, BANK));
+        assert!(cache.image_at::<Reader, _>(&mut flash, BANK).is_err());
+
+        // So is an image with a different trailer, even if its first bytes are the same.
+        let mut longer_body = body(0xA5);
+        longer_body.push(0x00);
+        flash.write(Address(0), &regular_test_image(&longer_body)).unwrap();
+        assert_eq!(Ok(None), cache.cached_image(&mut flash, BANK));
+        assert_eq!(
+            longer_body.len(),
+            cache.image_at::<Reader, _>(&mut flash, BANK).unwrap().size()
+        );
+    }
+
+    #[test]
+    fn framed_images_are_cached_with_the_same_descriptor() {
+        let body = [&body(0x5A)[..], &magic_string_inverted(), &[0x00]].concat();
+        let mut flash = flash_with(&framed_test_image(&body));
+        let cache = VerificationCache::new(CACHE_LOCATION);
+
+        let verified = cache.image_at::<Reader, _>(&mut flash, BANK).unwrap();
+        assert!(verified.framed());
+        assert_eq!(Ok(Some(verified)), cache.cached_image(&mut flash, BANK));
+    }
+
+    #[test]
+    fn cache_is_bound_to_its_bank() {
+        let mut flash = flash_with(&regular_test_image(&body(0x5A)));
+        let cache = VerificationCache::new(CACHE_LOCATION);
+        cache.image_at::<Reader, _>(&mut flash, BANK).unwrap();
+        let other_bank = Bank { index: 2, ..BANK };
+        assert_eq!(Ok(None), cache.cached_image(&mut flash, other_bank));
+    }
+}
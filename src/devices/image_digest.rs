@@ -0,0 +1,104 @@
+//! SHA256 digests of flash contents, to check from the host which image a bank holds.
+//!
+//! Unlike the stored CRC/signature, these are computed on demand over what is actually
+//! in flash, so they identify an image regardless of the security mode Loadstone was
+//! built with. By default an image is hashed over the same bytes its CRC/signature
+//! covers: its body and decorations, up to and including the inverted magic string.
+
+use super::{image, traits::Flash};
+use crate::error::Error;
+use nb::block;
+use sha2::{Digest, Sha256};
+
+/// Size in bytes of a SHA256 digest.
+pub const DIGEST_SIZE: usize = 32;
+
+/// Bytes read from flash at a time while hashing.
+const CHUNK_SIZE: usize = 256;
+
+/// SHA256 of the first `length` bytes of a bank, whatever they hold.
+pub fn bank_digest<F: Flash>(
+    flash: &mut F,
+    bank: image::Bank<F::Address>,
+    length: usize,
+) -> Result<[u8; DIGEST_SIZE], Error> {
+    if length > bank.size {
+        return Err(Error::DeviceError("Hashed length exceeds the bank size"));
+    }
+    let mut hasher = Sha256::new();
+    let mut chunk = [0u8; CHUNK_SIZE];
+    for offset in (0..length).step_by(CHUNK_SIZE) {
+        let chunk = &mut chunk[..CHUNK_SIZE.min(length - offset)];
+        block!(flash.read(bank.location + offset, chunk))?;
+        hasher.update(chunk);
+    }
+    Ok(hasher.finalize().into())
+}
+
+/// SHA256 of the image in a bank, over the bytes its CRC/signature covers. If `length` is
+/// given (e.g. the size of the undecorated file on the host), the first `length` bytes of
+/// the bank are hashed instead, without requiring them to hold a valid image.
+pub fn image_digest<R: image::Reader, F: Flash>(
+    flash: &mut F,
+    bank: image::Bank<F::Address>,
+    length: Option<usize>,
+) -> Result<[u8; DIGEST_SIZE], Error> {
+    let length = match length {
+        Some(length) => length,
+        None => R::image_at(flash, bank)?.signed_size(),
+    };
+    bank_digest(flash, bank, length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::image::{
+        image_crc::tests::regular_test_image, CrcImageReader, MAGIC_STRING,
+    };
+    use blue_hal::hal::{doubles::flash::*, flash::ReadWrite};
+    use crc::crc32;
+    use loadstone_image_format::Layout;
+
+    type Reader = CrcImageReader<{ crc32::IEEE }, false>;
+
+    const BANK: image::Bank<Address> = image::Bank {
+        index: 1,
+        size: 2048,
+        location: Address(0),
+        bootable: false,
+        is_golden: false,
+    };
+
+    fn flash_with(contents: &[u8]) -> FakeFlash {
+        let mut flash = FakeFlash::new(Address(0));
+        flash.write(Address(0), &[0xFF; 2048]).unwrap();
+        flash.write(Address(0), contents).unwrap();
+        flash
+    }
+
+    #[test]
+    fn image_digest_covers_everything_up_to_the_magic_string() {
+        // Spans several chunks, with a partial one at the end.
+        let body = [0x5Au8; 3 * CHUNK_SIZE / 2];
+        let image = regular_test_image(&body);
+        let mut flash = flash_with(&image);
+
+        let layout = Layout::parse(&image).unwrap();
+        let signed = &image[..layout.magic_string_offset + MAGIC_STRING.len()];
+        let expected: [u8; DIGEST_SIZE] = Sha256::digest(signed).into();
+        assert_eq!(Ok(expected), image_digest::<Reader, _>(&mut flash, BANK, None));
+
+        let expected: [u8; DIGEST_SIZE] = Sha256::digest(&body).into();
+        assert_eq!(Ok(expected), image_digest::<Reader, _>(&mut flash, BANK, Some(body.len())));
+    }
+
+    #[test]
+    fn explicit_lengths_are_hashed_without_an_image_but_within_the_bank() {
+        let mut flash = flash_with(&[0x00, 0x01, 0x02]);
+        assert!(image_digest::<Reader, _>(&mut flash, BANK, None).is_err());
+        assert!(image_digest::<Reader, _>(&mut flash, BANK, Some(BANK.size + 1)).is_err());
+        let expected: [u8; DIGEST_SIZE] = Sha256::digest(&[0x00, 0x01, 0x02]).into();
+        assert_eq!(Ok(expected), image_digest::<Reader, _>(&mut flash, BANK, Some(3)));
+    }
+}
@@ -3,12 +3,29 @@
 //! generic, while board specifics (pins, board config) are
 //! handled in the `ports` module.
 
+pub mod baud;
+pub mod boot_counter;
+#[cfg(feature = "cli")]
 pub mod boot_manager;
 pub mod boot_metrics;
 pub mod bootloader;
+#[cfg(feature = "cli")]
 pub mod cli;
+#[cfg(test)]
+#[doc(hidden)]
+pub mod doubles;
+pub mod file_transfer;
 pub mod image;
+pub mod image_digest;
+pub mod recovery_pin;
+pub mod self_test;
+pub mod serial_log;
+pub mod serial_rx;
+pub mod status_led;
 pub mod update_signal;
+pub mod usage;
+pub mod wear_leveling;
+pub mod ymodem;
 
 /// General purpose traits that summarize requirements on devices.
 pub mod traits {
@@ -0,0 +1,70 @@
+//! Hardware trigger for recovery mode.
+//!
+//! When a recovery pin is configured, Loadstone samples it once at startup, before
+//! attempting to boot. If the pin is held at its active level, Loadstone goes straight
+//! to recovery mode, even if the current image is perfectly valid. This provides a way
+//! out of an image that boots correctly but misbehaves (e.g. never accepts updates).
+
+use blue_hal::hal::gpio;
+
+/// Level at which the recovery pin is considered asserted.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ActiveLevel {
+    High,
+    Low,
+}
+
+/// Input pin that forces recovery mode while asserted at boot.
+pub struct RecoveryPin<P: gpio::InputPin> {
+    pin: P,
+    active_level: ActiveLevel,
+}
+
+impl<P: gpio::InputPin> RecoveryPin<P> {
+    pub fn new(pin: P, active_level: ActiveLevel) -> Self { Self { pin, active_level } }
+
+    /// Whether the pin is currently held at its active level.
+    pub fn asserted(&self) -> bool {
+        match self.active_level {
+            ActiveLevel::High => self.pin.is_high(),
+            ActiveLevel::Low => self.pin.is_low(),
+        }
+    }
+}
+
+/// Placeholder for ports or configurations without a recovery pin.
+pub struct NullPin;
+
+impl gpio::InputPin for NullPin {
+    fn is_high(&self) -> bool { false }
+    fn is_low(&self) -> bool { false }
+}
+
+#[cfg(test)]
+#[doc(hidden)]
+pub mod doubles {
+    use super::*;
+
+    /// Pin permanently held at a fixed level.
+    pub struct FakePin {
+        pub high: bool,
+    }
+
+    impl gpio::InputPin for FakePin {
+        fn is_high(&self) -> bool { self.high }
+        fn is_low(&self) -> bool { !self.high }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{doubles::FakePin, *};
+
+    #[test]
+    fn assertion_follows_the_active_level() {
+        assert!(RecoveryPin::new(FakePin { high: true }, ActiveLevel::High).asserted());
+        assert!(!RecoveryPin::new(FakePin { high: false }, ActiveLevel::High).asserted());
+        assert!(RecoveryPin::new(FakePin { high: false }, ActiveLevel::Low).asserted());
+        assert!(!RecoveryPin::new(FakePin { high: true }, ActiveLevel::Low).asserted());
+    }
+}
@@ -0,0 +1,182 @@
+//! Flash self test, meant to validate read, write and erase support when
+//! bringing up a new board.
+//!
+//! A known pattern is written straddling a [`SUBSECTOR_SIZE`] boundary near the
+//! start of a scratch bank, so flash drivers that merge partial subsector writes
+//! (such as the Micron external flash) are exercised on both sides of the
+//! boundary. The pattern is then read back, erased, and confirmed to read as
+//! erased flash (0xFF). A step that fails doesn't end the test: steps checking its
+//! result are reported as skipped, and the rest still run.
+
+use super::{
+    image::{self, erase_bank},
+    traits::Flash,
+};
+use crate::error::Error;
+use blue_hal::utilities::memory;
+use core::cmp::min;
+use nb::block;
+
+/// Smallest erasable region of the external flash chips Loadstone supports.
+pub const SUBSECTOR_SIZE: usize = 4 * 1024;
+
+/// Length of the pattern written during the test.
+pub const PATTERN_LENGTH: usize = 256;
+
+/// A stage of the self test, reported as it completes.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Step {
+    Write,
+    ReadBack,
+    Erase,
+    VerifyErased,
+}
+
+/// Outcome of a step of the self test.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Passed,
+    Failed,
+    /// Not attempted, as the step it checks failed.
+    Skipped,
+}
+
+impl Outcome {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Outcome::Passed => "PASS",
+            Outcome::Failed => "FAIL",
+            Outcome::Skipped => "SKIPPED",
+        }
+    }
+
+    fn of(passed: bool) -> Self {
+        if passed {
+            Outcome::Passed
+        } else {
+            Outcome::Failed
+        }
+    }
+}
+
+impl Step {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Step::Write => "Write pattern across subsector boundary",
+            Step::ReadBack => "Read back pattern",
+            Step::Erase => "Erase",
+            Step::VerifyErased => "Confirm erased (0xFF)",
+        }
+    }
+}
+
+fn pattern(index: usize) -> u8 { (index as u8).wrapping_mul(31) ^ 0xA5 }
+
+/// Region of a bank the test writes to: straddling the first subsector boundary
+/// if the bank is large enough, or from the bank start otherwise.
+fn test_region<A: memory::Address>(bank: image::Bank<A>) -> image::Bank<A> {
+    let offset = if bank.size >= SUBSECTOR_SIZE + PATTERN_LENGTH / 2 {
+        SUBSECTOR_SIZE - PATTERN_LENGTH / 2
+    } else {
+        0
+    };
+    image::Bank { location: bank.location + offset, size: min(PATTERN_LENGTH, bank.size), ..bank }
+}
+
+/// Runs the self test over a bank, calling `report` with the outcome of every step.
+/// Returns whether all steps passed. Bootable banks are refused, as the test
+/// destroys the bank contents.
+pub fn run<F: Flash, R: FnMut(Step, Outcome)>(
+    flash: &mut F,
+    bank: image::Bank<F::Address>,
+    mut report: R,
+) -> Result<bool, Error> {
+    if bank.bootable {
+        return Err(Error::BankInvalid);
+    }
+    let region = test_region(bank);
+    let mut buffer = [0u8; PATTERN_LENGTH];
+    let buffer = &mut buffer[..region.size];
+    let mut all_passed = true;
+    let mut outcome = |step: Step, outcome: Outcome| {
+        all_passed &= outcome == Outcome::Passed;
+        report(step, outcome);
+    };
+
+    buffer.iter_mut().enumerate().for_each(|(i, b)| *b = pattern(i));
+    let written = block!(flash.write(region.location, buffer)).is_ok();
+    outcome(Step::Write, Outcome::of(written));
+
+    let read_back = if written {
+        buffer.iter_mut().for_each(|b| *b = 0);
+        let read = block!(flash.read(region.location, buffer)).is_ok();
+        Outcome::of(read && buffer.iter().enumerate().all(|(i, b)| *b == pattern(i)))
+    } else {
+        Outcome::Skipped
+    };
+    outcome(Step::ReadBack, read_back);
+
+    // Attempted even if writing failed, so a partial write isn't left behind.
+    let erased = erase_bank(flash, region).is_ok();
+    outcome(Step::Erase, Outcome::of(erased));
+
+    let verified_erased = if erased {
+        buffer.iter_mut().for_each(|b| *b = 0);
+        let read = block!(flash.read(region.location, buffer)).is_ok();
+        Outcome::of(read && buffer.iter().all(|b| *b == 0xFF))
+    } else {
+        Outcome::Skipped
+    };
+    outcome(Step::VerifyErased, verified_erased);
+
+    Ok(all_passed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blue_hal::{hal::doubles::flash::*, KB};
+
+    fn bank(size: usize, bootable: bool) -> image::Bank<Address> {
+        image::Bank { index: 1, size, location: Address(0), bootable, is_golden: false }
+    }
+
+    #[test]
+    fn self_test_passes_on_working_flash() {
+        let mut flash = FakeFlash::new(Address(0));
+        let mut steps = vec![];
+        let passed = run(&mut flash, bank(KB!(8), false), |step, passed| {
+            steps.push((step, passed));
+        });
+        assert_eq!(Ok(true), passed);
+        assert_eq!(
+            vec![
+                (Step::Write, Outcome::Passed),
+                (Step::ReadBack, Outcome::Passed),
+                (Step::Erase, Outcome::Passed),
+                (Step::VerifyErased, Outcome::Passed)
+            ],
+            steps
+        );
+    }
+
+    #[test]
+    fn pattern_straddles_subsector_boundary() {
+        let region = test_region(bank(KB!(8), false));
+        let start: usize = region.location.into();
+        assert!(start < SUBSECTOR_SIZE && start + region.size > SUBSECTOR_SIZE);
+    }
+
+    #[test]
+    fn small_banks_are_tested_from_the_start() {
+        let region = test_region(bank(128, false));
+        assert_eq!(Address(0), region.location);
+        assert_eq!(128, region.size);
+    }
+
+    #[test]
+    fn bootable_banks_are_refused() {
+        let mut flash = FakeFlash::new(Address(0));
+        assert_eq!(Err(Error::BankInvalid), run(&mut flash, bank(KB!(8), true), |_, _| {}));
+    }
+}
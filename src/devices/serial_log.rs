@@ -0,0 +1,52 @@
+//! Plain serial logging.
+//!
+//! Mirrors the bootloader's `defmt` messages over serial, tagged with their
+//! severity, for setups where a debug probe isn't attached. The mirroring is
+//! only compiled in with the `serial-log` feature; the configured [`Level`]
+//! then filters out less severe messages at runtime.
+
+/// Severity threshold for messages mirrored over serial, from least to most verbose.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Off,
+    Error,
+    Warn,
+    Info,
+}
+
+impl Level {
+    /// Whether a message of the given severity passes this threshold.
+    pub fn allows(&self, severity: Level) -> bool { severity != Level::Off && severity <= *self }
+
+    /// Prefix printed ahead of messages of this severity.
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Level::Off => "",
+            Level::Error => "[ERROR]",
+            Level::Warn => "[WARN]",
+            Level::Info => "[INFO]",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_filtering_suppresses_less_severe_messages() {
+        assert!(Level::Warn.allows(Level::Error));
+        assert!(Level::Warn.allows(Level::Warn));
+        assert!(!Level::Warn.allows(Level::Info));
+        assert!(!Level::Error.allows(Level::Warn));
+        assert!(Level::Info.allows(Level::Info));
+    }
+
+    #[test]
+    fn off_level_suppresses_everything() {
+        for severity in [Level::Off, Level::Error, Level::Warn, Level::Info] {
+            assert!(!Level::Off.allows(severity));
+        }
+        assert!(!Level::Info.allows(Level::Off));
+    }
+}
@@ -0,0 +1,220 @@
+//! Interrupt driven serial reception.
+//!
+//! By default, serial bytes are read by polling the USART, so any byte that arrives
+//! while Loadstone is busy elsewhere (e.g. writing the previous XMODEM block to flash)
+//! is lost to an overrun. With interrupt reception enabled, the port's USART interrupt
+//! pushes every received byte into an [`RxRing`], and a [`BufferedSerial`] drains it
+//! on behalf of the transfer logic.
+//!
+//! The ring is single producer, single consumer: only the interrupt handler pushes,
+//! and only the serial wrapper pops.
+
+use crate::error::{self, Error};
+use blue_hal::hal::{serial, time};
+use core::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+/// Fixed capacity queue of received bytes, filled from an interrupt handler.
+pub struct RxRing<const N: usize> {
+    buffer: UnsafeCell<[u8; N]>,
+    /// Position of the next byte pushed, modulo `2 * N` so a full ring can be told
+    /// apart from an empty one. Only written by the producer.
+    head: AtomicUsize,
+    /// Position of the next byte popped, modulo `2 * N`. Only written by the consumer.
+    tail: AtomicUsize,
+    /// Set when a byte was dropped, either by the ring or by the peripheral.
+    overrun: AtomicBool,
+}
+
+// NOTE(Safety): Each slot of the buffer is written by the producer only while the ring
+// considers it free, and read by the consumer only once the updated `head` is published.
+unsafe impl<const N: usize> Sync for RxRing<N> {}
+
+impl<const N: usize> RxRing<N> {
+    pub const fn new() -> Self {
+        Self {
+            buffer: UnsafeCell::new([0u8; N]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            overrun: AtomicBool::new(false),
+        }
+    }
+
+    /// Queues a received byte. Meant to be called from the interrupt handler only. If
+    /// the ring is full the byte is dropped, and the overrun reported to the consumer.
+    pub fn push(&self, byte: u8) {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        if Self::length(head, tail) == N {
+            self.flag_overrun();
+            return;
+        }
+        // NOTE(Safety): The slot is free, so the consumer won't read it until `head` moves.
+        unsafe { (*self.buffer.get())[head % N] = byte };
+        self.head.store((head + 1) % (2 * N), Ordering::Release);
+    }
+
+    /// Records a byte lost before it reached the ring (e.g. a USART overrun).
+    pub fn flag_overrun(&self) { self.overrun.store(true, Ordering::Relaxed); }
+
+    /// Takes the oldest queued byte, if any.
+    pub fn pop(&self) -> Option<u8> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        // NOTE(Safety): The slot was published by the producer, which won't touch it
+        // again until `tail` moves past it.
+        let byte = unsafe { (*self.buffer.get())[tail % N] };
+        self.tail.store((tail + 1) % (2 * N), Ordering::Release);
+        Some(byte)
+    }
+
+    /// Number of queued bytes.
+    pub fn available(&self) -> usize {
+        Self::length(self.head.load(Ordering::Acquire), self.tail.load(Ordering::Relaxed))
+    }
+
+    /// Whether any byte was dropped since the last call, clearing the flag.
+    pub fn take_overrun(&self) -> bool { self.overrun.swap(false, Ordering::Relaxed) }
+
+    fn length(head: usize, tail: usize) -> usize { (head + 2 * N - tail) % (2 * N) }
+}
+
+/// Reception failures of a [`BufferedSerial`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ReceptionError {
+    /// No byte arrived before the timeout.
+    Timeout,
+    /// Bytes were dropped, because the ring was full or the interrupt was serviced late.
+    Overrun,
+}
+
+impl error::Convertible for ReceptionError {
+    fn into(self) -> Error {
+        match self {
+            ReceptionError::Timeout => Error::DriverError("[Serial] Timeout error"),
+            ReceptionError::Overrun => Error::DriverError("[Serial] Overrun error"),
+        }
+    }
+}
+
+/// Serial that transmits through the wrapped driver, but receives from an [`RxRing`]
+/// filled by the USART interrupt. The `T` time source measures read timeouts.
+/// Dropping it calls the port's `release` hook, so the interrupt is disabled again
+/// before Loadstone jumps to an image.
+pub struct BufferedSerial<S, T: time::Now, const N: usize> {
+    serial: S,
+    ring: &'static RxRing<N>,
+    release: fn(),
+    _marker: PhantomData<T>,
+}
+
+impl<S, T: time::Now, const N: usize> BufferedSerial<S, T, N> {
+    /// Wraps a serial driver. The port must have enabled the reception interrupt, with a
+    /// handler pushing into `ring`, and `release` must undo that.
+    pub fn new(serial: S, ring: &'static RxRing<N>, release: fn()) -> Self {
+        Self { serial, ring, release, _marker: PhantomData }
+    }
+
+    /// Number of received bytes ready to be read without blocking.
+    pub fn available(&self) -> usize { self.ring.available() }
+}
+
+impl<S, T: time::Now, const N: usize> Drop for BufferedSerial<S, T, N> {
+    fn drop(&mut self) { (self.release)() }
+}
+
+impl<S, T: time::Now, const N: usize> serial::Read for BufferedSerial<S, T, N> {
+    type Error = ReceptionError;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        if self.ring.take_overrun() {
+            return Err(nb::Error::Other(ReceptionError::Overrun));
+        }
+        self.ring.pop().ok_or(nb::Error::WouldBlock)
+    }
+}
+
+impl<S, T: time::Now, const N: usize> serial::TimeoutRead for BufferedSerial<S, T, N> {
+    type Error = ReceptionError;
+
+    fn read<U: Copy + Into<time::Milliseconds>>(&mut self, timeout: U) -> Result<u8, Self::Error> {
+        let timeout = timeout.into();
+        let start = T::now();
+        loop {
+            match serial::Read::read(self) {
+                Ok(byte) => return Ok(byte),
+                Err(nb::Error::Other(error)) => return Err(error),
+                Err(nb::Error::WouldBlock) if (T::now() - start).0 >= timeout.0 => {
+                    return Err(ReceptionError::Timeout)
+                }
+                Err(nb::Error::WouldBlock) => {}
+            }
+        }
+    }
+}
+
+impl<S: serial::Write, T: time::Now, const N: usize> serial::Write for BufferedSerial<S, T, N> {
+    type Error = S::Error;
+    fn write_str(&mut self, s: &str) -> Result<(), Self::Error> { self.serial.write_str(s) }
+    fn write_char(&mut self, c: char) -> Result<(), Self::Error> { self.serial.write_char(c) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blue_hal::hal::{
+        doubles::{serial::SerialStub, time::MockSysTick},
+        serial::{Read, TimeoutRead},
+    };
+
+    fn ring<const N: usize>() -> &'static RxRing<N> { Box::leak(Box::new(RxRing::new())) }
+
+    #[test]
+    fn buffered_bytes_are_delivered_in_order() {
+        let ring = ring::<8>();
+        let mut serial = BufferedSerial::<_, MockSysTick, 8>::new(SerialStub, ring, || {});
+        assert!(matches!(Read::read(&mut serial), Err(nb::Error::WouldBlock)));
+
+        // Pushed as the interrupt handler would, wrapping around the ring twice.
+        for chunk in (0..20u8).collect::<Vec<_>>().chunks(5) {
+            chunk.iter().for_each(|byte| ring.push(*byte));
+            assert_eq!(chunk.len(), serial.available());
+            for byte in chunk {
+                assert_eq!(Ok(*byte), TimeoutRead::read(&mut serial, time::Milliseconds(10)));
+            }
+        }
+        assert_eq!(0, serial.available());
+    }
+
+    #[test]
+    fn bytes_pushed_into_a_full_ring_are_dropped_and_reported() {
+        let ring = ring::<4>();
+        let mut serial = BufferedSerial::<_, MockSysTick, 4>::new(SerialStub, ring, || {});
+        (1..=5u8).for_each(|byte| ring.push(byte));
+        assert_eq!(4, serial.available());
+
+        assert!(matches!(Read::read(&mut serial), Err(nb::Error::Other(ReceptionError::Overrun))));
+        let received: Vec<u8> = (0..4)
+            .map(|_| TimeoutRead::read(&mut serial, time::Milliseconds(10)).unwrap())
+            .collect();
+        assert_eq!(vec![1, 2, 3, 4], received);
+        assert!(matches!(Read::read(&mut serial), Err(nb::Error::WouldBlock)));
+    }
+
+    #[test]
+    fn dropping_the_serial_releases_the_interrupt() {
+        static RELEASED: AtomicBool = AtomicBool::new(false);
+        let serial = BufferedSerial::<_, MockSysTick, 4>::new(SerialStub, ring::<4>(), || {
+            RELEASED.store(true, Ordering::Relaxed)
+        });
+        assert!(!RELEASED.load(Ordering::Relaxed));
+        drop(serial);
+        assert!(RELEASED.load(Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,162 @@
+//! Status LED signalling of the bootloader state.
+//!
+//! Loadstone runs without interrupts, so blink patterns don't run on their own:
+//! they advance every time the bootloader calls [`StatusLed::tick`] from its
+//! long running loops (once per bank scanned, once per recovery block received).
+//! Blink rates are therefore only indicative, but the patterns remain easy to
+//! tell apart:
+//!
+//! * [`Pattern::SlowBlink`] while scanning, updating and restoring banks.
+//! * [`Pattern::FastBlink`] during serial recovery.
+//! * [`Pattern::Solid`] right before jumping to the application.
+
+use blue_hal::hal::led;
+
+/// A blink pattern, signalling a bootloader phase.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Pattern {
+    Off,
+    SlowBlink,
+    FastBlink,
+    Solid,
+}
+
+/// Number of ticks between toggles of a slow blink. Fast blinks toggle every tick.
+pub const SLOW_BLINK_TICKS: u32 = 4;
+
+/// LED driven according to a blink pattern.
+pub struct StatusLed<L: led::Toggle> {
+    led: L,
+    pattern: Pattern,
+    ticks: u32,
+}
+
+impl<L: led::Toggle> StatusLed<L> {
+    pub fn new(mut led: L) -> Self {
+        led.off();
+        Self { led, pattern: Pattern::Off, ticks: 0 }
+    }
+
+    pub fn pattern(&self) -> Pattern { self.pattern }
+
+    pub fn led(&self) -> &L { &self.led }
+
+    /// Switches to a new pattern. Blinking patterns start with the LED on, so
+    /// the transition is visible even if no ticks follow.
+    pub fn set_pattern(&mut self, pattern: Pattern) {
+        if pattern == self.pattern {
+            return;
+        }
+        self.pattern = pattern;
+        self.ticks = 0;
+        match pattern {
+            Pattern::Off => self.led.off(),
+            Pattern::SlowBlink | Pattern::FastBlink | Pattern::Solid => self.led.on(),
+        }
+    }
+
+    /// Advances the current pattern by one step.
+    pub fn tick(&mut self) {
+        self.ticks = self.ticks.wrapping_add(1);
+        match self.pattern {
+            Pattern::SlowBlink if self.ticks % SLOW_BLINK_TICKS == 0 => self.led.toggle(),
+            Pattern::FastBlink => self.led.toggle(),
+            _ => (),
+        }
+    }
+}
+
+/// Placeholder for ports or configurations without a status LED.
+pub struct NullLed;
+
+impl led::Toggle for NullLed {
+    fn on(&mut self) {}
+    fn off(&mut self) {}
+    fn toggle(&mut self) {}
+}
+
+#[cfg(test)]
+#[doc(hidden)]
+pub mod doubles {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    /// LED that records its state after every operation. Clones share the record, so it
+    /// can be read once the LED is handed over to a bootloader.
+    #[derive(Clone, Default)]
+    pub struct FakeLed {
+        pub lit: bool,
+        history: Rc<RefCell<Vec<bool>>>,
+    }
+
+    impl FakeLed {
+        /// Every state the LED went through, oldest first.
+        pub fn history(&self) -> Vec<bool> { self.history.borrow().clone() }
+
+        pub fn clear_history(&self) { self.history.borrow_mut().clear() }
+
+        fn record(&mut self, lit: bool) {
+            self.lit = lit;
+            self.history.borrow_mut().push(lit);
+        }
+    }
+
+    impl led::Toggle for FakeLed {
+        fn on(&mut self) { self.record(true) }
+        fn off(&mut self) { self.record(false) }
+        fn toggle(&mut self) { self.record(!self.lit) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{doubles::FakeLed, *};
+
+    fn led_after_ticks(pattern: Pattern, ticks: u32) -> StatusLed<FakeLed> {
+        let mut status_led = StatusLed::new(FakeLed::default());
+        status_led.set_pattern(pattern);
+        status_led.led.clear_history();
+        (0..ticks).for_each(|_| status_led.tick());
+        status_led
+    }
+
+    #[test]
+    fn new_status_led_starts_off() {
+        let status_led = StatusLed::new(FakeLed::default());
+        assert_eq!(Pattern::Off, status_led.pattern());
+        assert_eq!(vec![false], status_led.led.history());
+    }
+
+    #[test]
+    fn slow_blink_toggles_every_few_ticks() {
+        let status_led = led_after_ticks(Pattern::SlowBlink, 2 * SLOW_BLINK_TICKS);
+        assert_eq!(vec![false, true], status_led.led.history());
+    }
+
+    #[test]
+    fn fast_blink_toggles_every_tick() {
+        let status_led = led_after_ticks(Pattern::FastBlink, 3);
+        assert_eq!(vec![false, true, false], status_led.led.history());
+    }
+
+    #[test]
+    fn solid_and_off_are_unaffected_by_ticks() {
+        let status_led = led_after_ticks(Pattern::Solid, 10);
+        assert!(status_led.led.lit);
+        assert!(status_led.led.history().is_empty());
+
+        let status_led = led_after_ticks(Pattern::Off, 10);
+        assert!(!status_led.led.lit);
+        assert!(status_led.led.history().is_empty());
+    }
+
+    #[test]
+    fn changing_pattern_restarts_the_blink_from_lit() {
+        let mut status_led = led_after_ticks(Pattern::FastBlink, 1);
+        assert!(!status_led.led.lit);
+        status_led.set_pattern(Pattern::SlowBlink);
+        assert!(status_led.led.lit);
+        status_led.set_pattern(Pattern::SlowBlink);
+        assert_eq!(vec![false, true], status_led.led.history());
+    }
+}
@@ -1,5 +1,5 @@
 /// Indicates the state of an update signal.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum UpdatePlan {
     /// Do not update.
     None,
@@ -9,6 +9,176 @@ pub enum UpdatePlan {
 
     /// Update from a specific image.
     Index(u8),
+
+    /// Receive an update over serial (XMODEM) before booting, through the configured
+    /// staging bank. Served once: the bootloader resets the plan to [`UpdatePlan::Any`]
+    /// before waiting for the image.
+    Serial,
+
+    /// Boot once from a specific bank, to test its image without promoting it. Handled
+    /// by the bootloader like `Index(bank)`, after which the application restores the
+    /// plan that was in place before (see [`UpdatePlan::resumed`]), kept in compact
+    /// form in `previous`.
+    TestBoot { bank: u8, previous: u8 },
+
+    /// Enter serial recovery on the next boot, as if the recovery pin were held. Once
+    /// the application runs again, it restores the plan kept in compact form in
+    /// `previous`, like after a test boot.
+    Recovery { previous: u8 },
+}
+
+// Raw values of plans persisted in a 32-bit register.
+const NONE_BITS: u32 = 0x0000_0000;
+const ANY_BITS: u32 = 0xFFFF_FFFF;
+const SERIAL_BITS: u32 = 0xFFFF_FFFE;
+// One-shot plans are tagged in the upper half, holding the previous plan (and the bank,
+// for test boots) below.
+const TEST_BOOT_TAG: u32 = 0x7E57_0000;
+const RECOVERY_TAG: u32 = 0x2EC0_0000;
+const TAG_MASK: u32 = 0xFFFF_0000;
+
+// Compact (single byte) form of the plan a test boot resumes. Plans targeting banks
+// 254 and 255 can't be told apart from `serial` and `any` in this form.
+const COMPACT_NONE: u8 = 0x00;
+const COMPACT_ANY: u8 = 0xFF;
+const COMPACT_SERIAL: u8 = 0xFE;
+
+impl UpdatePlan {
+    /// Builds a plan from its name (`none`, `any`, `index` or `serial`), taking
+    /// the bank index for `index` plans. Bank indices start at 1, as index 0 can't
+    /// be told apart from `none` once persisted.
+    pub fn from_name(name: &str, index: Option<u8>) -> Option<Self> {
+        match (name, index) {
+            ("none", None) => Some(UpdatePlan::None),
+            ("any", None) => Some(UpdatePlan::Any),
+            ("serial", None) => Some(UpdatePlan::Serial),
+            ("index", Some(index)) if index > 0 => Some(UpdatePlan::Index(index)),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            UpdatePlan::None => "none",
+            UpdatePlan::Any => "any",
+            UpdatePlan::Index(_) => "index",
+            UpdatePlan::Serial => "serial",
+            UpdatePlan::TestBoot { .. } => "test_boot",
+            UpdatePlan::Recovery { .. } => "recovery",
+        }
+    }
+
+    /// One-shot plan that boots from `bank` once, then resumes this plan.
+    pub fn test_boot(self, bank: u8) -> Self {
+        UpdatePlan::TestBoot { bank, previous: self.resumed().compact() }
+    }
+
+    /// One-shot plan that enters serial recovery on the next boot, then resumes this plan.
+    pub fn recovery(self) -> Self { UpdatePlan::Recovery { previous: self.resumed().compact() } }
+
+    /// Plan to persist once the image this plan booted is running. Test boots and
+    /// recoveries resume the plan they replaced, every other plan stands.
+    pub fn resumed(self) -> Self {
+        match self {
+            UpdatePlan::TestBoot { previous, .. } | UpdatePlan::Recovery { previous } => {
+                match previous {
+                    COMPACT_NONE => UpdatePlan::None,
+                    COMPACT_ANY => UpdatePlan::Any,
+                    COMPACT_SERIAL => UpdatePlan::Serial,
+                    index => UpdatePlan::Index(index),
+                }
+            }
+            plan => plan,
+        }
+    }
+
+    fn compact(self) -> u8 {
+        match self {
+            UpdatePlan::None => COMPACT_NONE,
+            UpdatePlan::Any | UpdatePlan::TestBoot { .. } | UpdatePlan::Recovery { .. } => {
+                COMPACT_ANY
+            }
+            UpdatePlan::Serial => COMPACT_SERIAL,
+            UpdatePlan::Index(index) => index,
+        }
+    }
+}
+
+/// Decodes a plan persisted in a 32-bit register (e.g. an RTC backup register).
+impl From<u32> for UpdatePlan {
+    fn from(bits: u32) -> Self {
+        match bits {
+            NONE_BITS => UpdatePlan::None,
+            ANY_BITS => UpdatePlan::Any,
+            SERIAL_BITS => UpdatePlan::Serial,
+            x if x & TAG_MASK == TEST_BOOT_TAG => {
+                UpdatePlan::TestBoot { bank: x as u8, previous: (x >> 8) as u8 }
+            }
+            x if x & TAG_MASK == RECOVERY_TAG => UpdatePlan::Recovery { previous: x as u8 },
+            x => UpdatePlan::Index(x as u8),
+        }
+    }
+}
+
+/// Encodes a plan to be persisted in a 32-bit register (e.g. an RTC backup register).
+impl From<UpdatePlan> for u32 {
+    fn from(plan: UpdatePlan) -> Self {
+        match plan {
+            UpdatePlan::None => NONE_BITS,
+            UpdatePlan::Any => ANY_BITS,
+            UpdatePlan::Serial => SERIAL_BITS,
+            UpdatePlan::Index(x) => x as u32,
+            UpdatePlan::TestBoot { bank, previous } => {
+                TEST_BOOT_TAG | (previous as u32) << 8 | bank as u32
+            }
+            UpdatePlan::Recovery { previous } => RECOVERY_TAG | previous as u32,
+        }
+    }
+}
+
+/// What a controlled reset asks of Loadstone on the next boot.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ResetMode {
+    /// Plain reset. A pending test boot or recovery is dropped, resuming the plan it
+    /// replaced.
+    Normal,
+    /// Serial recovery on the next boot.
+    Recovery,
+    /// A single boot from a bank (see [`UpdatePlan::TestBoot`]).
+    Bank(u8),
+}
+
+impl ResetMode {
+    /// Builds a mode from its name (`normal`, `recovery` or `bank`), taking the bank
+    /// index for `bank` resets.
+    pub fn from_name(name: &str, bank: Option<u8>) -> Option<Self> {
+        match (name, bank) {
+            ("normal", None) => Some(ResetMode::Normal),
+            ("recovery", None) => Some(ResetMode::Recovery),
+            ("bank", Some(bank)) if bank > 0 => Some(ResetMode::Bank(bank)),
+            _ => None,
+        }
+    }
+
+    /// Plan to persist before resetting in this mode, given the plan in place.
+    pub fn plan(self, current: UpdatePlan) -> UpdatePlan {
+        match self {
+            ResetMode::Normal => current.resumed(),
+            ResetMode::Recovery => current.recovery(),
+            ResetMode::Bank(bank) => current.test_boot(bank),
+        }
+    }
+}
+
+/// Persists the plan a reset mode requires, then hands the signal over to `reset`,
+/// which is expected to reset the device.
+pub fn prepare_reset<S, O, F>(signal: &mut S, mode: ResetMode, reset: F) -> O
+where
+    S: ReadUpdateSignal + WriteUpdateSignal,
+    F: FnOnce(&S) -> O,
+{
+    signal.write_update_plan(mode.plan(signal.read_update_plan()));
+    reset(signal)
 }
 
 pub trait ReadUpdateSignal {
@@ -18,3 +188,110 @@ pub trait ReadUpdateSignal {
 pub trait WriteUpdateSignal {
     fn write_update_plan(&mut self, plan: UpdatePlan);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stand-in for a signal persisted in a 32-bit register.
+    #[derive(Default)]
+    struct RegisterStore(u32);
+
+    impl ReadUpdateSignal for RegisterStore {
+        fn read_update_plan(&self) -> UpdatePlan { self.0.into() }
+    }
+
+    impl WriteUpdateSignal for RegisterStore {
+        fn write_update_plan(&mut self, plan: UpdatePlan) { self.0 = plan.into(); }
+    }
+
+    const PLANS: [UpdatePlan; 5] = [
+        UpdatePlan::None,
+        UpdatePlan::Any,
+        UpdatePlan::Serial,
+        UpdatePlan::Index(1),
+        UpdatePlan::Index(7),
+    ];
+
+    #[test]
+    fn every_plan_round_trips_through_the_store() {
+        let mut store = RegisterStore::default();
+        for plan in PLANS.iter().copied() {
+            store.write_update_plan(plan);
+            assert_eq!(plan, store.read_update_plan());
+        }
+    }
+
+    #[test]
+    fn test_boots_round_trip_and_resume_the_previous_plan() {
+        let mut store = RegisterStore::default();
+        for plan in PLANS.iter().copied() {
+            let test_boot = plan.test_boot(3);
+            store.write_update_plan(test_boot);
+            assert_eq!(test_boot, store.read_update_plan());
+            assert!(matches!(test_boot, UpdatePlan::TestBoot { bank: 3, .. }));
+            assert_eq!(plan, test_boot.resumed());
+            assert_eq!(plan, plan.resumed());
+        }
+        // Test boots don't nest, the original plan is resumed.
+        assert_eq!(UpdatePlan::None, UpdatePlan::None.test_boot(2).test_boot(3).resumed());
+    }
+
+    #[test]
+    fn recoveries_round_trip_and_resume_the_previous_plan() {
+        let mut store = RegisterStore::default();
+        for plan in PLANS.iter().copied() {
+            let recovery = plan.recovery();
+            store.write_update_plan(recovery);
+            assert_eq!(recovery, store.read_update_plan());
+            assert_eq!(plan, recovery.resumed());
+        }
+        assert_eq!(UpdatePlan::Any, UpdatePlan::Any.test_boot(2).recovery().resumed());
+    }
+
+    #[test]
+    fn each_reset_mode_persists_its_plan_before_resetting() {
+        let modes = [
+            (ResetMode::Normal, UpdatePlan::Index(2)),
+            (ResetMode::Recovery, UpdatePlan::Recovery { previous: 2 }),
+            (ResetMode::Bank(3), UpdatePlan::TestBoot { bank: 3, previous: 2 }),
+        ];
+        for (mode, expected) in modes.iter().copied() {
+            let mut store = RegisterStore::default();
+            store.write_update_plan(UpdatePlan::Index(2).test_boot(4));
+            // Stands in for the reset, capturing the plan the next boot would find.
+            let persisted = prepare_reset(&mut store, mode, |store| store.read_update_plan());
+            assert_eq!(expected, persisted);
+        }
+    }
+
+    #[test]
+    fn reset_modes_are_parsed_with_a_bank_only_when_needed() {
+        assert_eq!(Some(ResetMode::Normal), ResetMode::from_name("normal", None));
+        assert_eq!(Some(ResetMode::Recovery), ResetMode::from_name("recovery", None));
+        assert_eq!(Some(ResetMode::Bank(2)), ResetMode::from_name("bank", Some(2)));
+        assert_eq!(None, ResetMode::from_name("bank", None));
+        assert_eq!(None, ResetMode::from_name("bank", Some(0)));
+        assert_eq!(None, ResetMode::from_name("recovery", Some(2)));
+        assert_eq!(None, ResetMode::from_name("hard", None));
+    }
+
+    #[test]
+    fn every_plan_round_trips_through_its_name() {
+        for plan in PLANS.iter().copied() {
+            let index = match plan {
+                UpdatePlan::Index(index) => Some(index),
+                _ => None,
+            };
+            assert_eq!(Some(plan), UpdatePlan::from_name(plan.name(), index));
+        }
+    }
+
+    #[test]
+    fn bank_index_is_required_only_for_index_plans() {
+        assert_eq!(None, UpdatePlan::from_name("index", None));
+        assert_eq!(None, UpdatePlan::from_name("index", Some(0)));
+        assert_eq!(None, UpdatePlan::from_name("any", Some(2)));
+        assert_eq!(None, UpdatePlan::from_name("sometimes", None));
+    }
+}
@@ -0,0 +1,190 @@
+//! Flash utilization summaries, for planning deployments.
+//!
+//! A full scan verifies every bank to find which hold images and how large they
+//! are. As that is slow, a quick scan is also offered: it only checks whether the
+//! first byte of each bank is erased, and counts occupied banks as entirely used.
+//!
+//! The flash region reserved for the bootloader itself can also be inspected, to
+//! tell how much headroom its binary leaves.
+
+use super::{image, traits::Flash};
+use crate::error::Error;
+use blue_hal::utilities::memory::Address;
+use core::ops::Add;
+use nb::block;
+
+/// Value of erased flash.
+const ERASED: u8 = 0xFF;
+
+/// Aggregated bank occupancy over one or several flash chips.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Usage {
+    pub banks: usize,
+    pub occupied_banks: usize,
+    pub capacity: usize,
+    pub used: usize,
+}
+
+impl Usage {
+    /// Records a bank of `size` bytes, holding `occupied` bytes if any.
+    pub fn record(&mut self, size: usize, occupied: Option<usize>) {
+        self.banks += 1;
+        self.capacity += size;
+        if let Some(used) = occupied {
+            self.occupied_banks += 1;
+            self.used += used;
+        }
+    }
+
+    pub fn free(&self) -> usize { self.capacity.saturating_sub(self.used) }
+}
+
+impl Add for Usage {
+    type Output = Usage;
+
+    fn add(self, other: Usage) -> Usage {
+        Usage {
+            banks: self.banks + other.banks,
+            occupied_banks: self.occupied_banks + other.occupied_banks,
+            capacity: self.capacity + other.capacity,
+            used: self.used + other.used,
+        }
+    }
+}
+
+/// Returns the number of bytes occupied in a bank, or `None` if it holds no image.
+/// A quick check only looks at the first byte of the bank, reporting the whole
+/// bank as occupied if it isn't erased.
+pub fn occupancy<R: image::Reader, F: Flash>(
+    flash: &mut F,
+    bank: image::Bank<F::Address>,
+    quick: bool,
+) -> Result<Option<usize>, Error> {
+    if quick {
+        let mut first_byte = [0u8];
+        block!(flash.read(bank.location, &mut first_byte))?;
+        Ok((first_byte[0] != ERASED).then_some(bank.size))
+    } else {
+        Ok(R::image_at(flash, bank).ok().map(|image| image.total_size()))
+    }
+}
+
+/// Summarizes the occupancy of a set of banks within a flash chip.
+pub fn summarize<R: image::Reader, F: Flash>(
+    flash: &mut F,
+    banks: impl Iterator<Item = image::Bank<F::Address>>,
+    quick: bool,
+) -> Result<Usage, Error> {
+    let mut usage = Usage::default();
+    for bank in banks {
+        usage.record(bank.size, occupancy::<R, F>(flash, bank, quick)?);
+    }
+    Ok(usage)
+}
+
+/// MCU flash region reserved for the bootloader binary.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BootloaderRegion<A: Address> {
+    /// Start of the region.
+    pub location: A,
+    /// Size in bytes of the region.
+    pub length: usize,
+}
+
+impl<A: Address> BootloaderRegion<A> {
+    /// Returns the number of bytes from the start of the region up to its last
+    /// programmed (not erased) byte. A binary padded to fill the region, as needed by
+    /// the bootloader self check, takes all of it.
+    pub fn programmed_length<F: Flash<Address = A>>(&self, flash: &mut F) -> Result<usize, Error> {
+        const CHUNK_SIZE: usize = 256;
+        let mut buffer = [0u8; CHUNK_SIZE];
+        let mut end = self.length;
+        while end > 0 {
+            let start = end.saturating_sub(CHUNK_SIZE);
+            let chunk = &mut buffer[..end - start];
+            block!(flash.read(self.location + start, chunk))?;
+            if let Some(last) = chunk.iter().rposition(|byte| *byte != ERASED) {
+                return Ok(start + last + 1);
+            }
+            end = start;
+        }
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::image::{image_crc::tests::TEST_IMAGE_WITH_CORRECT_CRC, CrcImageReader};
+    use blue_hal::hal::{doubles::flash::*, flash::ReadWrite};
+    use crc::crc32;
+
+    type Reader = CrcImageReader<{ crc32::IEEE }, false>;
+
+    fn banks() -> impl Iterator<Item = image::Bank<Address>> {
+        (0..2u8).map(|i| image::Bank {
+            index: i + 1,
+            size: 512,
+            location: Address(i as u32 * 512),
+            bootable: false,
+            is_golden: false,
+        })
+    }
+
+    fn flash_with_first_bank(contents: &[u8]) -> FakeFlash {
+        let mut flash = FakeFlash::new(Address(0));
+        flash.write(Address(0), &[ERASED; 1024]).unwrap();
+        flash.write(Address(0), contents).unwrap();
+        flash
+    }
+
+    #[test]
+    fn quick_scan_counts_non_erased_banks_as_fully_used() {
+        let mut flash = flash_with_first_bank(&[0x00, 0x01, 0x02]);
+        let usage = summarize::<Reader, _>(&mut flash, banks(), true).unwrap();
+        assert_eq!(Usage { banks: 2, occupied_banks: 1, capacity: 1024, used: 512 }, usage);
+        assert_eq!(512, usage.free());
+    }
+
+    #[test]
+    fn full_scan_ignores_banks_without_a_valid_image() {
+        let mut flash = flash_with_first_bank(&[0x00, 0x01, 0x02]);
+        let usage = summarize::<Reader, _>(&mut flash, banks(), false).unwrap();
+        assert_eq!(Usage { banks: 2, occupied_banks: 0, capacity: 1024, used: 0 }, usage);
+    }
+
+    #[test]
+    fn full_scan_reports_image_sizes() {
+        let mut flash = flash_with_first_bank(&TEST_IMAGE_WITH_CORRECT_CRC);
+        let usage = summarize::<Reader, _>(&mut flash, banks(), false).unwrap();
+        assert_eq!(1, usage.occupied_banks);
+        assert_eq!(TEST_IMAGE_WITH_CORRECT_CRC.len(), usage.used);
+    }
+
+    #[test]
+    fn usage_across_chips_adds_up() {
+        let mut mcu = Usage::default();
+        mcu.record(512, Some(100));
+        let mut external = Usage::default();
+        external.record(1024, None);
+        assert_eq!(
+            Usage { banks: 2, occupied_banks: 1, capacity: 1536, used: 100 },
+            mcu + external
+        );
+    }
+
+    #[test]
+    fn programmed_length_ends_at_the_last_non_erased_byte() {
+        let region = BootloaderRegion { location: Address(512), length: 512 };
+        let mut flash = flash_with_first_bank(&[]);
+        assert_eq!(Ok(0), region.programmed_length(&mut flash));
+
+        // Erased bytes within the binary don't cut it short.
+        flash.write(Address(512), &[0x00, ERASED, ERASED, 0x01]).unwrap();
+        flash.write(Address(512 + 300), &[0x02]).unwrap();
+        assert_eq!(Ok(301), region.programmed_length(&mut flash));
+
+        flash.write(Address(1023), &[0x03]).unwrap();
+        assert_eq!(Ok(512), region.programmed_length(&mut flash));
+    }
+}
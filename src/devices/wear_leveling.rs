@@ -0,0 +1,191 @@
+//! Wear leveled storage for small values persisted in MCU flash.
+//!
+//! Flash bits can be cleared without erasing, but only set again by erasing a whole
+//! sector, so rewriting the same word on every boot would quickly wear it out. A
+//! [`Ring`] instead spreads writes over a reserved region, split into [`ENTRY_SIZE`]
+//! byte slots:
+//!
+//! * Each write fills the erased slot following the latest entry, with the value,
+//!   a sequence number one higher than the latest entry's, and a CRC of both.
+//! * Reading scans every slot and returns the valid entry with the highest sequence
+//!   number. Erased slots are skipped, and so are slots whose CRC doesn't match (e.g.
+//!   a write torn by a power loss), in which case the previous entry is recovered.
+//! * Once the slot after the latest entry is no longer erased (normally because the
+//!   last slot has been used) the region is rewritten, erased except for the new entry
+//!   in its first slot. This is the only erase, once every `REGION_SIZE / ENTRY_SIZE`
+//!   writes. A power loss during this rewrite may lose the stored value.
+//!
+//! Counters that only ever grow by one are better served by a tally, as in
+//! [`boot_counter`](super::boot_counter).
+
+use crate::{devices::traits::Flash, error::Error};
+use blue_hal::utilities::memory::Address;
+use core::mem::size_of;
+use crc::crc32;
+use nb::block;
+
+/// Size in bytes of a single entry: sequence number, value, and CRC.
+pub const ENTRY_SIZE: usize = 3 * size_of::<u32>();
+
+/// Sequence number of an erased slot, never assigned to a written entry.
+const ERASED_SEQUENCE: u32 = u32::MAX;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Entry {
+    slot: usize,
+    sequence: u32,
+    value: u32,
+}
+
+fn encode(sequence: u32, value: u32) -> [u8; ENTRY_SIZE] {
+    let mut bytes = [0u8; ENTRY_SIZE];
+    bytes[..4].copy_from_slice(&sequence.to_le_bytes());
+    bytes[4..8].copy_from_slice(&value.to_le_bytes());
+    let crc = crc32::checksum_ieee(&bytes[..8]);
+    bytes[8..].copy_from_slice(&crc.to_le_bytes());
+    bytes
+}
+
+fn decode(bytes: &[u8]) -> Option<(u32, u32)> {
+    let word = |index: usize| {
+        let offset = index * size_of::<u32>();
+        u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+    };
+    let (sequence, value, crc) = (word(0), word(1), word(2));
+    (sequence != ERASED_SEQUENCE && crc == crc32::checksum_ieee(&bytes[..8]))
+        .then_some((sequence, value))
+}
+
+/// A wear leveled `u32`, stored in a region of `REGION_SIZE` bytes of MCU flash.
+pub struct Ring<A: Address, const REGION_SIZE: usize> {
+    location: A,
+}
+
+impl<A: Address, const REGION_SIZE: usize> Ring<A, REGION_SIZE> {
+    /// Number of entries that fit in the region.
+    pub const SLOTS: usize = REGION_SIZE / ENTRY_SIZE;
+
+    /// Creates a ring over the region starting at a location. The region must be
+    /// reserved for the ring, and start erased.
+    pub fn new(location: A) -> Self {
+        assert!(Self::SLOTS > 0, "Wear leveling region is too small for a single entry.");
+        Self { location }
+    }
+
+    /// Reads the latest value written, or `None` if the region holds no valid entry.
+    pub fn read<F: Flash<Address = A>>(&self, flash: &mut F) -> Result<Option<u32>, Error> {
+        let mut region = [0u8; REGION_SIZE];
+        Ok(self.latest(flash, &mut region)?.map(|entry| entry.value))
+    }
+
+    /// Persists a new value, superseding the previous one.
+    pub fn write<F: Flash<Address = A>>(&self, flash: &mut F, value: u32) -> Result<(), Error> {
+        let mut region = [0u8; REGION_SIZE];
+        let latest = self.latest(flash, &mut region)?;
+        let entry = encode(latest.map_or(0, |e| e.sequence + 1), value);
+        let next_slot = latest.map_or(0, |e| e.slot + 1);
+
+        let next_slot_erased = next_slot < Self::SLOTS
+            && region[next_slot * ENTRY_SIZE..][..ENTRY_SIZE].iter().all(|b| *b == 0xFF);
+        if next_slot_erased {
+            block!(flash.write(self.location + next_slot * ENTRY_SIZE, &entry))?;
+        } else {
+            region.iter_mut().for_each(|b| *b = 0xFF);
+            region[..ENTRY_SIZE].copy_from_slice(&entry);
+            block!(flash.write(self.location, &region))?;
+        }
+        Ok(())
+    }
+
+    /// Reads the whole region into a buffer, and finds the valid entry with the
+    /// highest sequence number.
+    fn latest<F: Flash<Address = A>>(
+        &self,
+        flash: &mut F,
+        region: &mut [u8; REGION_SIZE],
+    ) -> Result<Option<Entry>, Error> {
+        block!(flash.read(self.location, region))?;
+        Ok(region
+            .chunks_exact(ENTRY_SIZE)
+            .enumerate()
+            .filter_map(|(slot, bytes)| {
+                decode(bytes).map(|(sequence, value)| Entry { slot, sequence, value })
+            })
+            .max_by_key(|entry| entry.sequence))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blue_hal::hal::{doubles::flash::*, flash::ReadWrite};
+
+    const SLOTS: usize = 4;
+    type TestRing = Ring<Address, { SLOTS * ENTRY_SIZE }>;
+
+    fn erased_flash() -> FakeFlash {
+        let mut flash = FakeFlash::new(Address(0));
+        flash.write(Address(0), &[0xFFu8; SLOTS * ENTRY_SIZE]).unwrap();
+        flash
+    }
+
+    fn slot(flash: &mut FakeFlash, slot: usize) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0u8; ENTRY_SIZE];
+        flash.read(Address((slot * ENTRY_SIZE) as u32), &mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn erased_region_holds_no_value() {
+        let mut flash = erased_flash();
+        assert_eq!(Ok(None), TestRing::new(Address(0)).read(&mut flash));
+    }
+
+    #[test]
+    fn writes_rotate_through_slots() {
+        let mut flash = erased_flash();
+        let ring = TestRing::new(Address(0));
+        for (index, value) in [10u32, 20, 30].iter().enumerate() {
+            ring.write(&mut flash, *value).unwrap();
+            assert_eq!(Ok(Some(*value)), ring.read(&mut flash));
+            assert_eq!(encode(index as u32, *value), slot(&mut flash, index));
+        }
+        assert_eq!([0xFFu8; ENTRY_SIZE], slot(&mut flash, 3));
+    }
+
+    #[test]
+    fn full_region_wraps_around_to_first_slot() {
+        let mut flash = erased_flash();
+        let ring = TestRing::new(Address(0));
+        for value in 0..SLOTS as u32 {
+            ring.write(&mut flash, value).unwrap();
+        }
+
+        ring.write(&mut flash, 42).unwrap();
+        assert_eq!(Ok(Some(42)), ring.read(&mut flash));
+        assert_eq!(encode(SLOTS as u32, 42), slot(&mut flash, 0));
+        assert!((1..SLOTS).all(|index| slot(&mut flash, index) == [0xFFu8; ENTRY_SIZE]));
+
+        ring.write(&mut flash, 43).unwrap();
+        assert_eq!(encode(SLOTS as u32 + 1, 43), slot(&mut flash, 1));
+        assert_eq!(Ok(Some(43)), ring.read(&mut flash));
+    }
+
+    #[test]
+    fn torn_write_recovers_previous_entry() {
+        let mut flash = erased_flash();
+        let ring = TestRing::new(Address(0));
+        ring.write(&mut flash, 1).unwrap();
+
+        // Power is lost after the sequence number of the next entry is written.
+        let torn = encode(1, 2);
+        flash.write(Address(ENTRY_SIZE as u32), &torn[..4]).unwrap();
+        assert_eq!(Ok(Some(1)), ring.read(&mut flash));
+
+        // The torn slot can't be written over, so the next write starts the region afresh.
+        ring.write(&mut flash, 3).unwrap();
+        assert_eq!(Ok(Some(3)), ring.read(&mut flash));
+        assert_eq!(encode(1, 3), slot(&mut flash, 0));
+        assert_eq!([0xFFu8; ENTRY_SIZE], slot(&mut flash, 1));
+    }
+}
@@ -0,0 +1,441 @@
+//! YMODEM file transfer implementation.
+//!
+//! An alternative to XMODEM for serial recovery. YMODEM opens every transfer with
+//! a header block carrying the file name and size, so the receiver can reject a
+//! file too large for its destination before any of it is written. Blocks are
+//! protected by a CRC-16 instead of XMODEM's checksum, and data blocks may carry
+//! 128 or 1024 bytes.
+//!
+//! Loadstone receives a single file per session:
+//!
+//! * The receiver requests the header block by sending `C`. A header announcing a
+//!   file larger than the destination is answered with two `CAN` bytes, aborting
+//!   the transfer. Otherwise, the header is acknowledged.
+//! * The receiver sends `C` again to request the data blocks, numbered from 1, and
+//!   acknowledges each one. Data is handed on in [`BLOCK_SIZE`] byte blocks, just
+//!   like XMODEM, with any padding past the announced file size replaced by `0xFF`.
+//! * The first end of transmission is answered with `NAK` and the second with
+//!   `ACK`. The receiver then requests and acknowledges the empty header block that
+//!   closes the batch.
+
+use super::file_transfer::BLOCK_SIZE;
+use crate::error::Error;
+use blue_hal::{
+    hal::serial::{TimeoutRead, Write},
+    utilities::xmodem::DEFAULT_TIMEOUT,
+};
+
+pub const SOH: u8 = 0x01;
+pub const STX: u8 = 0x02;
+pub const EOT: u8 = 0x04;
+pub const ACK: u8 = 0x06;
+pub const NAK: u8 = 0x15;
+pub const CAN: u8 = 0x18;
+/// Sent by the receiver to request a block protected by a CRC-16.
+pub const CRC_REQUEST: u8 = b'C';
+
+/// Payload size of the header block, and of short data blocks.
+pub const SHORT_PAYLOAD_SIZE: usize = 128;
+/// Payload size of long data blocks.
+pub const LONG_PAYLOAD_SIZE: usize = 1024;
+/// Start byte, block number and its complement.
+const PACKET_HEADER_SIZE: usize = 3;
+const CRC_SIZE: usize = 2;
+pub const SHORT_PACKET_SIZE: usize = PACKET_HEADER_SIZE + SHORT_PAYLOAD_SIZE + CRC_SIZE;
+pub const LONG_PACKET_SIZE: usize = PACKET_HEADER_SIZE + LONG_PAYLOAD_SIZE + CRC_SIZE;
+
+const TRANSFER_FAILED: Error = Error::DeviceError("YMODEM transfer failed");
+
+/// File information carried by the header block.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Header {
+    /// Size of the file in bytes.
+    pub size: usize,
+}
+
+/// Parses the payload of a header block: a null terminated file name, followed by
+/// the file size in decimal. Returns `None` for the empty header closing a batch,
+/// or if the size is missing.
+pub fn parse_header(payload: &[u8]) -> Option<Header> {
+    let name_length = payload.iter().position(|b| *b == 0).filter(|l| *l > 0)?;
+    let size_field = &payload[name_length + 1..];
+    let digits = size_field.iter().take_while(|b| b.is_ascii_digit()).count();
+    if digits == 0 {
+        return None;
+    }
+    let size = size_field[..digits].iter().try_fold(0usize, |size, digit| {
+        size.checked_mul(10)?.checked_add((digit - b'0') as usize)
+    })?;
+    Some(Header { size })
+}
+
+/// CRC-16/XMODEM, protecting every YMODEM block.
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, byte| {
+        (0..8).fold(crc ^ ((*byte as u16) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+enum Packet {
+    /// A block whose payload was read into the start of the buffer.
+    Block {
+        number: u8,
+        length: usize,
+    },
+    EndOfTransmission,
+    Cancelled,
+}
+
+/// Reads a single packet. Returns `None` on timeouts, unexpected bytes, or blocks
+/// that fail their integrity checks.
+fn read_packet<S: TimeoutRead + ?Sized>(
+    serial: &mut S,
+    buffer: &mut [u8; LONG_PAYLOAD_SIZE],
+) -> Option<Packet> {
+    let mut read = || serial.read(DEFAULT_TIMEOUT).ok();
+    let length = match read()? {
+        SOH => SHORT_PAYLOAD_SIZE,
+        STX => LONG_PAYLOAD_SIZE,
+        EOT => return Some(Packet::EndOfTransmission),
+        CAN => return Some(Packet::Cancelled),
+        _ => return None,
+    };
+    let (number, complement) = (read()?, read()?);
+    for byte in buffer[..length].iter_mut() {
+        *byte = read()?;
+    }
+    let crc = u16::from_be_bytes([read()?, read()?]);
+    (number == !complement && crc == crc16(&buffer[..length]))
+        .then_some(Packet::Block { number, length })
+}
+
+/// Receiving side of a YMODEM transfer.
+pub trait YModemTransfer: TimeoutRead + Write {
+    /// Requests the header block that opens a transfer, and returns an iterator over
+    /// the data blocks that follow. Files larger than `max_size` are refused.
+    fn ymodem(
+        &mut self,
+        max_retries: Option<u32>,
+        max_size: usize,
+    ) -> Result<YModemBlocks<Self>, Error> {
+        let mut buffer = [0u8; LONG_PAYLOAD_SIZE];
+        let mut retries = 0;
+        while max_retries.map_or(true, |max| retries < max) {
+            if self.write_char(CRC_REQUEST as char).is_err() {
+                retries += 1;
+                continue;
+            }
+            match read_packet(self, &mut buffer) {
+                Some(Packet::Block { number: 0, length }) => {
+                    let header = parse_header(&buffer[..length]).ok_or(TRANSFER_FAILED)?;
+                    if header.size > max_size {
+                        self.write_char(CAN as char).ok();
+                        self.write_char(CAN as char).ok();
+                        return Err(Error::ImageTooLargeForBank);
+                    }
+                    self.write_char(ACK as char).map_err(|_| TRANSFER_FAILED)?;
+                    return Ok(YModemBlocks::new(self, header, max_retries));
+                }
+                Some(Packet::Cancelled) => return Err(TRANSFER_FAILED),
+                _ => retries += 1,
+            }
+        }
+        Err(TRANSFER_FAILED)
+    }
+}
+
+impl<T: TimeoutRead + Write> YModemTransfer for T {}
+
+/// Iterator over the data of a YMODEM transfer, in [`BLOCK_SIZE`] byte blocks.
+pub struct YModemBlocks<'a, S: TimeoutRead + Write + ?Sized> {
+    serial: &'a mut S,
+    header: Header,
+    max_retries: Option<u32>,
+    buffer: [u8; LONG_PAYLOAD_SIZE],
+    buffered: usize,
+    position: usize,
+    block_number: u8,
+    received: usize,
+    reply: u8,
+    finished: bool,
+    completed: bool,
+}
+
+impl<'a, S: TimeoutRead + Write + ?Sized> YModemBlocks<'a, S> {
+    fn new(serial: &'a mut S, header: Header, max_retries: Option<u32>) -> Self {
+        Self {
+            serial,
+            header,
+            max_retries,
+            buffer: [0u8; LONG_PAYLOAD_SIZE],
+            buffered: 0,
+            position: 0,
+            block_number: 0,
+            received: 0,
+            reply: CRC_REQUEST,
+            finished: false,
+            completed: false,
+        }
+    }
+
+    /// File information announced by the sender.
+    pub fn header(&self) -> Header { self.header }
+
+    /// Whether the sender closed the transfer cleanly, as opposed to the transfer
+    /// being abandoned after too many timeouts or errors.
+    pub fn completed(&self) -> bool { self.completed }
+
+    /// Stores a received block, dropping the padding past the end of the file.
+    fn accept(&mut self, number: u8, length: usize) {
+        self.block_number = number;
+        self.reply = ACK;
+        let remaining = self.header.size.saturating_sub(self.received).min(length);
+        self.buffer[remaining..length].iter_mut().for_each(|b| *b = 0xFF);
+        self.received += remaining;
+        self.buffered = (remaining + BLOCK_SIZE - 1) / BLOCK_SIZE * BLOCK_SIZE;
+        self.position = 0;
+    }
+
+    /// Acknowledges the end of transmission, then the empty header closing the batch.
+    fn close(&mut self) {
+        self.finished = true;
+        self.completed = true;
+        if self.serial.write_char(ACK as char).is_err()
+            || self.serial.write_char(CRC_REQUEST as char).is_err()
+        {
+            return;
+        }
+        if let Some(Packet::Block { number: 0, .. }) = read_packet(self.serial, &mut self.buffer) {
+            // There's no recovering from a failure here, so the result is irrelevant.
+            let _ = self.serial.write_char(ACK as char);
+        }
+    }
+}
+
+impl<'a, S: TimeoutRead + Write + ?Sized> Iterator for YModemBlocks<'a, S> {
+    type Item = [u8; BLOCK_SIZE];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.position < self.buffered {
+                let mut block = [0u8; BLOCK_SIZE];
+                block.copy_from_slice(&self.buffer[self.position..self.position + BLOCK_SIZE]);
+                self.position += BLOCK_SIZE;
+                return Some(block);
+            }
+            if self.finished {
+                return None;
+            }
+
+            let mut retries = 0;
+            let mut end_of_transmission = false;
+            let received = loop {
+                if self.max_retries.map_or(false, |max| retries >= max) {
+                    break false;
+                }
+                if self.serial.write_char(self.reply as char).is_err() {
+                    retries += 1;
+                    continue;
+                }
+                match read_packet(self.serial, &mut self.buffer) {
+                    Some(Packet::Block { number, length })
+                        if number == self.block_number.wrapping_add(1) =>
+                    {
+                        self.accept(number, length);
+                        break true;
+                    }
+                    // Repeated block, as the sender missed our acknowledgement.
+                    Some(Packet::Block { number, .. }) if number == self.block_number => {
+                        self.reply = ACK;
+                        retries += 1;
+                    }
+                    Some(Packet::EndOfTransmission) if end_of_transmission => {
+                        self.close();
+                        return None;
+                    }
+                    Some(Packet::EndOfTransmission) => {
+                        end_of_transmission = true;
+                        self.reply = NAK;
+                    }
+                    Some(Packet::Cancelled) => break false,
+                    _ => {
+                        retries += 1;
+                        self.reply = if self.block_number == 0 { CRC_REQUEST } else { NAK };
+                    }
+                }
+            };
+
+            if !received {
+                self.finished = true;
+                return None;
+            }
+        }
+    }
+}
+
+impl<'a, S: TimeoutRead + Write + ?Sized> Drop for YModemBlocks<'a, S> {
+    // Must fully consume the iterator on drop
+    // to close the ymodem communication cleanly
+    fn drop(&mut self) { self.for_each(drop); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::doubles::ScriptedSerial;
+    use std::{collections::VecDeque, convert::TryInto};
+
+    /// Sending side of a YMODEM transfer, building the blocks a host would send.
+    struct YModemSession {
+        block_number: u8,
+    }
+
+    impl YModemSession {
+        fn new() -> Self { Self { block_number: 0 } }
+
+        /// Builds the header block announcing a file. Names are truncated to fit the block.
+        fn header_packet(&self, name: &str, size: usize) -> [u8; SHORT_PACKET_SIZE] {
+            let mut payload = [0u8; SHORT_PAYLOAD_SIZE];
+            let mut digits = [0u8; 20];
+            let digit_count = core::iter::successors(Some(size), |s| (*s >= 10).then_some(s / 10))
+                .zip(digits.iter_mut())
+                .map(|(value, digit)| *digit = b'0' + (value % 10) as u8)
+                .count();
+            let name = &name.as_bytes()[..name.len().min(SHORT_PAYLOAD_SIZE - digit_count - 2)];
+            payload[..name.len()].copy_from_slice(name);
+            let size_field = &mut payload[name.len() + 1..][..digit_count];
+            size_field
+                .iter_mut()
+                .zip(digits[..digit_count].iter().rev())
+                .for_each(|(s, d)| *s = *d);
+            Self::short_packet(0, &payload)
+        }
+
+        /// Builds the empty header block closing a batch.
+        fn closing_packet(&self) -> [u8; SHORT_PACKET_SIZE] {
+            Self::short_packet(0, &[0u8; SHORT_PAYLOAD_SIZE])
+        }
+
+        /// Wraps the next 128 bytes of the file into a block.
+        fn packet(&mut self, payload: &[u8; SHORT_PAYLOAD_SIZE]) -> [u8; SHORT_PACKET_SIZE] {
+            self.block_number = self.block_number.wrapping_add(1);
+            Self::short_packet(self.block_number, payload)
+        }
+
+        /// Wraps the next 1024 bytes of the file into a block.
+        fn long_packet(&mut self, payload: &[u8; LONG_PAYLOAD_SIZE]) -> [u8; LONG_PACKET_SIZE] {
+            self.block_number = self.block_number.wrapping_add(1);
+            let mut packet = [0u8; LONG_PACKET_SIZE];
+            Self::frame(STX, self.block_number, payload, &mut packet);
+            packet
+        }
+
+        fn short_packet(number: u8, payload: &[u8; SHORT_PAYLOAD_SIZE]) -> [u8; SHORT_PACKET_SIZE] {
+            let mut packet = [0u8; SHORT_PACKET_SIZE];
+            Self::frame(SOH, number, payload, &mut packet);
+            packet
+        }
+
+        fn frame(start: u8, number: u8, payload: &[u8], packet: &mut [u8]) {
+            packet[..PACKET_HEADER_SIZE].copy_from_slice(&[start, number, !number]);
+            packet[PACKET_HEADER_SIZE..][..payload.len()].copy_from_slice(payload);
+            let crc = crc16(payload).to_be_bytes();
+            packet[PACKET_HEADER_SIZE + payload.len()..].copy_from_slice(&crc);
+        }
+    }
+
+    fn sample_file() -> Vec<u8> { (0..1200u32).map(|i| (i * 7 % 251) as u8).collect() }
+
+    /// Sends a file in a long block followed by short blocks, padded with `0x1A`.
+    fn transmit(name: &str, file: &[u8]) -> ScriptedSerial {
+        let mut session = YModemSession::new();
+        let mut incoming: VecDeque<u8> = session.header_packet(name, file.len()).to_vec().into();
+        let mut padded = file.to_vec();
+        let padded_length = LONG_PAYLOAD_SIZE
+            + (file.len().saturating_sub(LONG_PAYLOAD_SIZE) + SHORT_PAYLOAD_SIZE - 1)
+                / SHORT_PAYLOAD_SIZE
+                * SHORT_PAYLOAD_SIZE;
+        padded.resize(padded_length, 0x1A);
+        let (long, short) = padded.split_at(LONG_PAYLOAD_SIZE);
+        incoming.extend(session.long_packet(long.try_into().unwrap()).iter());
+        for chunk in short.chunks(SHORT_PAYLOAD_SIZE) {
+            incoming.extend(session.packet(chunk.try_into().unwrap()).iter());
+        }
+        incoming.extend([EOT, EOT].iter());
+        incoming.extend(session.closing_packet().iter());
+        ScriptedSerial { incoming, ..Default::default() }
+    }
+
+    #[test]
+    fn crc_matches_reference_check_value() {
+        assert_eq!(0x31C3, crc16(b"123456789"));
+    }
+
+    #[test]
+    fn header_block_is_parsed() {
+        assert_eq!(Some(Header { size: 1234 }), parse_header(b"image.bin\x001234 13730414710\x00"));
+        assert_eq!(Some(Header { size: 5 }), parse_header(b"a\x005\x00\x00\x00"));
+        assert_eq!(None, parse_header(&[0u8; SHORT_PAYLOAD_SIZE]));
+        assert_eq!(None, parse_header(b"image.bin\x00\x00"));
+        assert_eq!(None, parse_header(b"image.bin\x0099999999999999999999999\x00"));
+
+        let session = YModemSession::new();
+        let packet = session.header_packet("golden.bin", 70000);
+        let payload = &packet[PACKET_HEADER_SIZE..][..SHORT_PAYLOAD_SIZE];
+        assert_eq!(Some(Header { size: 70000 }), parse_header(payload));
+    }
+
+    #[test]
+    fn file_is_received_in_blocks_without_padding() {
+        let file = sample_file();
+        let mut serial = transmit("image.bin", &file);
+        let mut blocks = serial.ymodem(Some(2), file.len()).unwrap();
+        assert_eq!(Header { size: file.len() }, blocks.header());
+
+        let received: Vec<u8> = blocks.by_ref().flatten().collect();
+        assert!(blocks.completed());
+        drop(blocks);
+
+        let expected_length = (file.len() + BLOCK_SIZE - 1) / BLOCK_SIZE * BLOCK_SIZE;
+        assert_eq!(expected_length, received.len());
+        assert_eq!(file[..], received[..file.len()]);
+        assert!(received[file.len()..].iter().all(|b| *b == 0xFF));
+        assert_eq!(Some(&ACK), serial.transcript.bytes().last());
+        assert!(serial.incoming.is_empty());
+    }
+
+    #[test]
+    fn file_larger_than_destination_is_refused() {
+        let file = sample_file();
+        let mut serial = transmit("image.bin", &file);
+        assert_eq!(
+            Err(Error::ImageTooLargeForBank),
+            serial.ymodem(Some(2), file.len() - 1).map(|_| ())
+        );
+        assert_eq!(vec![CRC_REQUEST, CAN, CAN], serial.transcript.bytes());
+    }
+
+    #[test]
+    fn corrupted_block_is_requested_again() {
+        let file = sample_file();
+        let mut serial = transmit("image.bin", &file);
+        let mut corrupted: Vec<u8> = serial.incoming.iter().copied().collect();
+        // Flip a payload byte of the long block, then append a clean copy of it.
+        let long_block_start = SHORT_PACKET_SIZE;
+        let clean = corrupted[long_block_start..][..LONG_PACKET_SIZE].to_vec();
+        corrupted[long_block_start + PACKET_HEADER_SIZE] ^= 0xFF;
+        let mut incoming = corrupted[..long_block_start + LONG_PACKET_SIZE].to_vec();
+        incoming.extend(clean);
+        incoming.extend(&corrupted[long_block_start + LONG_PACKET_SIZE..]);
+        serial.incoming = incoming.into();
+
+        let received: Vec<u8> = serial.ymodem(Some(2), file.len()).unwrap().flatten().collect();
+        assert_eq!(file[..], received[..file.len()]);
+    }
+}
@@ -1,13 +1,13 @@
 //! Loadstone Error types and methods
 
 use blue_hal::{hal::serial::Write, uprint};
-use defmt::Format;
 use ufmt::{uwrite, uwriteln};
 
 /// Top level error type for the bootloader. Unlike the specific
 /// module errors, this error contains textual descriptions of the
 /// problem as it is meant to be directly reported through USART.
-#[derive(Debug, Copy, Clone, PartialEq, Format)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(not(feature = "no-defmt"), derive(defmt::Format))]
 pub enum Error {
     /// Error caused by a low level peripheral driver
     DriverError(&'static str),
@@ -17,15 +17,28 @@ pub enum Error {
     DeviceError(&'static str),
     BankInvalid,
     BankEmpty,
+    /// The bank holds data, but no image trailer (magic string) was found in it.
+    ImageUndecorated,
+    /// The image is smaller than the configured minimum image size.
+    ImageTooSmall,
     ImageTooBig,
+    ImageTooLargeForBank,
     ImageIsNotGolden,
     NoGoldenBankSupport,
     FlashCorrupted,
     NoExternalFlash,
     NoImageToRestoreFrom,
     NoRecoverySupport,
+    /// No transfer started before the recovery session timed out.
+    RecoveryTimedOut,
     SignatureInvalid,
     CrcInvalid,
+    KeyUnavailable,
+    UnsupportedAlgorithm,
+    FramingMismatch,
+    DecompressionFailed,
+    AddressOutOfRange,
+    WriteVerificationFailed,
 }
 
 pub trait Convertible {
@@ -82,6 +95,9 @@ impl Error {
             }
             Error::DeviceError(text) => uwriteln!(serial, "[Device Error] -> {}", text),
             Error::ImageTooBig => uwriteln!(serial, "[Logic Error] -> Firmware image too big"),
+            Error::ImageTooLargeForBank => {
+                uwriteln!(serial, "[Logic Error] -> Firmware image too large for destination bank")
+            }
             Error::BankInvalid => uwriteln!(
                 serial,
                 "[Logic Error] -> Bank doesn't exist or is invalid in this context"
@@ -89,6 +105,13 @@ impl Error {
             Error::BankEmpty => {
                 uwriteln!(serial, "[Logic Error] -> Bank is empty (contains no firmware image)")
             }
+            Error::ImageUndecorated => uwriteln!(
+                serial,
+                "[Logic Error] -> Bank holds data, but no firmware image (missing magic string)"
+            ),
+            Error::ImageTooSmall => {
+                uwriteln!(serial, "[Logic Error] -> Firmware image is under the minimum size")
+            }
             Error::FlashCorrupted => {
                 uwriteln!(serial, "[Logic Error] -> Flash memory is corrupted or outdated")
             }
@@ -110,9 +133,30 @@ impl Error {
             Error::NoRecoverySupport => {
                 uwriteln!(serial, "[Logic Error] -> No image recovery support")
             }
+            Error::RecoveryTimedOut => {
+                uwriteln!(serial, "[Logic Error] -> Recovery session timed out")
+            }
             Error::CrcInvalid => {
                 uwriteln!(serial, "[Logic Error] -> Image CRC is invalid")
             }
+            Error::KeyUnavailable => {
+                uwriteln!(serial, "[Logic Error] -> Verifying key could not be retrieved")
+            }
+            Error::UnsupportedAlgorithm => {
+                uwriteln!(serial, "[Logic Error] -> Image verification algorithm not supported")
+            }
+            Error::FramingMismatch => {
+                uwriteln!(serial, "[Logic Error] -> Image frame doesn't match the image body")
+            }
+            Error::DecompressionFailed => {
+                uwriteln!(serial, "[Logic Error] -> Compressed image is malformed")
+            }
+            Error::AddressOutOfRange => {
+                uwriteln!(serial, "[Logic Error] -> Address is outside the bank or address space")
+            }
+            Error::WriteVerificationFailed => {
+                uwriteln!(serial, "[Device Error] -> Flash contents didn't read back as written")
+            }
         }
         .ok()
         .unwrap();
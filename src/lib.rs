@@ -6,6 +6,19 @@
 #![cfg_attr(test, allow(unused_imports))]
 #![cfg_attr(target_arch = "arm", no_std)]
 
+/// Logs through `defmt` at the given level (`error`, `warn`, `info`...). Under the
+/// `no-defmt` feature the message compiles out entirely, arguments included.
+macro_rules! defmt_log {
+    ($level:ident, $format:literal $(, $argument:expr)* $(,)?) => {{
+        #[cfg(not(feature = "no-defmt"))]
+        defmt::$level!($format $(, $argument)*);
+        #[cfg(feature = "no-defmt")]
+        {
+            $(let _ = &$argument;)*
+        }
+    }};
+}
+
 #[cfg(target_arch = "arm")]
 use alloc_cortex_m::CortexMHeap;
 
@@ -18,14 +31,14 @@ pub static ALLOCATOR: CortexMHeap = CortexMHeap::empty();
 #[cfg(target_arch = "arm")]
 #[alloc_error_handler]
 fn oom(_: core::alloc::Layout) -> ! {
-    defmt::error!("Out of heap memory!");
+    defmt_log!(error, "Out of heap memory!");
     loop {}
 }
 
 #[cfg(target_arch = "arm")]
 use panic_semihosting as _;
 
-#[cfg(target_arch = "arm")]
+#[cfg(all(target_arch = "arm", not(feature = "no-defmt")))]
 use defmt_rtt as _; // global logger
 
 pub mod devices;
@@ -5,8 +5,11 @@
 #[allow(unused)]
 use blue_hal::port;
 
-#[cfg(feature = "stm32f412")]
+#[cfg(all(feature = "stm32f412", feature = "cli"))]
 port!(stm32f412: [bootloader, boot_manager, autogenerated, update_signal,]);
 
+#[cfg(all(feature = "stm32f412", not(feature = "cli")))]
+port!(stm32f412: [bootloader, autogenerated, update_signal,]);
+
 #[cfg(feature = "wgm160p")]
 port!(wgm160p: [bootloader, autogenerated, update_signal,]);
@@ -1,20 +1,20 @@
 //! Concrete boot manager construction and flash bank layout
 //! for stm32f412
-use crate::devices::{boot_manager::BootManager, cli::Cli};
-use blue_hal::{drivers::stm32f4::{flash, rcc::Clocks, systick::SysTick}, hal::time, stm32pac};
+use crate::devices::{boot_manager::BootManager, bootloader::external_flash_or_fallback, cli::Cli};
+use blue_hal::{drivers::stm32f4::{flash, rcc::Clocks, systick::SysTick}, hal::time::{self, Now}, stm32pac};
 
-use super::autogenerated::{self, devices, memory_map::{EXTERNAL_BANKS, MCU_BANKS}, pin_configuration::{self, *}, UPDATE_SIGNAL_ENABLED};
+use super::autogenerated::{self, devices, memory_map::{BOOTLOADER_REGION, EXTERNAL_BANKS, MCU_BACKUP_BANK, MCU_BANKS, MCU_STAGING_BANK, MCU_STAGING_ROTATION}, pin_configuration::{self, *}, RECOVERY_ENABLED, UPDATE_SIGNAL_ENABLED};
 #[cfg(feature="ecdsa-verify")]
-use crate::devices::image::EcdsaImageReader as ImageReader;
+type ImageReader = crate::devices::image::EcdsaImageReader<crate::devices::image::EmbeddedKey, { autogenerated::STRICT_SCAN }, { autogenerated::MAX_SCAN_BYTES }, { autogenerated::MIN_IMAGE_SIZE }>;
 #[cfg(not(feature="ecdsa-verify"))]
-use crate::devices::image::CrcImageReader as ImageReader;
+type ImageReader = crate::devices::image::CrcImageReader<{ autogenerated::CRC_POLYNOMIAL }, { autogenerated::STRICT_SCAN }, { autogenerated::MAX_SCAN_BYTES }, { autogenerated::MIN_IMAGE_SIZE }>;
 use super::update_signal::{UpdateSignalWriter, initialize_rtc_backup_domain};
 
-impl Default for BootManager<flash::McuFlash, ExternalFlash, Serial, ImageReader, UpdateSignalWriter> {
+impl Default for BootManager<flash::McuFlash, ExternalFlash, Serial, ImageReader, UpdateSignalWriter, SysTick> {
     fn default() -> Self { Self::new() }
 }
 
-impl BootManager<flash::McuFlash, ExternalFlash, Serial, ImageReader, UpdateSignalWriter> {
+impl BootManager<flash::McuFlash, ExternalFlash, Serial, ImageReader, UpdateSignalWriter, SysTick> {
     pub fn new() -> Self {
         let mut peripherals = stm32pac::Peripherals::take().unwrap();
         let cortex_peripherals = cortex_m::Peripherals::take().unwrap();
@@ -22,7 +22,7 @@ impl BootManager<flash::McuFlash, ExternalFlash, Serial, ImageReader, UpdateSign
 
         initialize_rtc_backup_domain(&mut peripherals.RCC, &mut peripherals.PWR);
 
-        let (serial_pins, qspi_pins) = pin_configuration::pins(
+        let (serial_pins, qspi_pins, _, _) = pin_configuration::pins(
                 peripherals.GPIOA,
                 peripherals.GPIOB,
                 peripherals.GPIOC,
@@ -35,6 +35,7 @@ impl BootManager<flash::McuFlash, ExternalFlash, Serial, ImageReader, UpdateSign
             );
         let clocks = Clocks::hardcoded(peripherals.RCC);
         SysTick::init(cortex_peripherals.SYST, clocks);
+        let start_time = Some(SysTick::now());
         SysTick::wait(time::Seconds(1)); // Gives time for the flash chip to stabilize after powerup
 
         let serial = devices::construct_serial(
@@ -44,8 +45,10 @@ impl BootManager<flash::McuFlash, ExternalFlash, Serial, ImageReader, UpdateSign
             peripherals.USART2,
             peripherals.USART6)
             .expect("Demo app can't function without serial!");
-        let cli = Cli::new(serial).unwrap();
-        let external_flash = devices::construct_flash(qspi_pins, peripherals.QUADSPI);
+        let baud_control = devices::baud_control(&clocks);
+        let cli = Cli::new(serial).unwrap().with_credentials(autogenerated::CLI_CREDENTIALS);
+        let external_flash =
+            external_flash_or_fallback(devices::construct_flash(qspi_pins, peripherals.QUADSPI));
 
         let update_signal = if UPDATE_SIGNAL_ENABLED {
             let rtc = peripherals.RTC;
@@ -62,8 +65,17 @@ impl BootManager<flash::McuFlash, ExternalFlash, Serial, ImageReader, UpdateSign
             cli: Some(cli),
             boot_metrics: None,
             greeting: Some(autogenerated::DEMO_APP_GREETING),
+            recovery_enabled: RECOVERY_ENABLED,
+            baud_control,
             _marker: Default::default(),
             update_signal,
+            start_time,
+            backup_bank: MCU_BACKUP_BANK,
+            staging_bank: MCU_STAGING_BANK,
+            staging_rotation: MCU_STAGING_ROTATION,
+            golden_override: autogenerated::GOLDEN_OVERRIDE,
+            bootloader_region: BOOTLOADER_REGION,
+            verify_writes: autogenerated::VERIFY_WRITES,
         }
     }
 }
@@ -1,5 +1,5 @@
 //! Concrete bootloader construction and flash bank layout for stm32f412
-use crate::{devices::bootloader::Bootloader, error};
+use crate::{devices::{bootloader::{external_flash_or_fallback, Bootloader, StagingRotation}, image::VerificationCache, recovery_pin::RecoveryPin, status_led::StatusLed}, error};
 use crate::error::Error;
 use blue_hal::hal::null::NullError;
 use blue_hal::hal::time::Now;
@@ -9,22 +9,24 @@ use blue_hal::{drivers::{micron::n25q128a_flash,
 use super::autogenerated::{
     self,
     BOOT_TIME_METRICS_ENABLED,
+    BOOT_DELAY_MS,
     UPDATE_SIGNAL_ENABLED,
     RECOVERY_ENABLED, devices,
-    memory_map::{EXTERNAL_BANKS, MCU_BANKS},
+    memory_map::{BOOT_COUNTER, EXTERNAL_BANKS, EXTERNAL_ERASE_SIZE, MCU_BANKS, MCU_SECTORS, MCU_STAGING_BANK,
+    MCU_STAGING_ROTATION, STAGING_ROTATION_STATE, VERIFICATION_CACHE, MCU_BACKUP_BANK},
     pin_configuration::{self, *},
 };
 #[cfg(feature="ecdsa-verify")]
-use crate::devices::image::EcdsaImageReader as ImageReader;
+type ImageReader = crate::devices::image::EcdsaImageReader<crate::devices::image::EmbeddedKey, { autogenerated::STRICT_SCAN }, { autogenerated::MAX_SCAN_BYTES }, { autogenerated::MIN_IMAGE_SIZE }>;
 #[cfg(not(feature="ecdsa-verify"))]
-use crate::devices::image::CrcImageReader as ImageReader;
+type ImageReader = crate::devices::image::CrcImageReader<{ autogenerated::CRC_POLYNOMIAL }, { autogenerated::STRICT_SCAN }, { autogenerated::MAX_SCAN_BYTES }, { autogenerated::MIN_IMAGE_SIZE }>;
 use super::update_signal::{UpdateSignal, initialize_rtc_backup_domain};
 
-impl Default for Bootloader<ExternalFlash, flash::McuFlash, Serial, SysTick, ImageReader, UpdateSignal> {
+impl Default for Bootloader<ExternalFlash, flash::McuFlash, devices::BootSerial, SysTick, ImageReader, UpdateSignal, devices::Led, devices::RecoveryInput> {
     fn default() -> Self { Self::new() }
 }
 
-impl Bootloader<ExternalFlash, flash::McuFlash, Serial, SysTick, ImageReader, UpdateSignal> {
+impl Bootloader<ExternalFlash, flash::McuFlash, devices::BootSerial, SysTick, ImageReader, UpdateSignal, devices::Led, devices::RecoveryInput> {
     pub fn new() -> Self {
         let mut peripherals = stm32pac::Peripherals::take().unwrap();
         let cortex_peripherals = cortex_m::Peripherals::take().unwrap();
@@ -32,7 +34,7 @@ impl Bootloader<ExternalFlash, flash::McuFlash, Serial, SysTick, ImageReader, Up
 
         initialize_rtc_backup_domain(&mut peripherals.RCC, &mut peripherals.PWR);
 
-        let (serial_pins, qspi_pins) = pin_configuration::pins(
+        let (serial_pins, qspi_pins, status_led_pin, recovery_pin) = pin_configuration::pins(
                 peripherals.GPIOA,
                 peripherals.GPIOB,
                 peripherals.GPIOC,
@@ -46,8 +48,12 @@ impl Bootloader<ExternalFlash, flash::McuFlash, Serial, SysTick, ImageReader, Up
         let clocks = Clocks::hardcoded(peripherals.RCC);
         SysTick::init(cortex_peripherals.SYST, clocks);
         SysTick::wait(time::Seconds(1)); // Gives time for the flash chip to stabilize after powerup
-        let optional_external_flash = devices::construct_flash(qspi_pins, peripherals.QUADSPI);
-        let optional_serial = devices::construct_serial(serial_pins, clocks, peripherals.USART1, peripherals.USART2, peripherals.USART6);
+        let optional_external_flash =
+            external_flash_or_fallback(devices::construct_flash(qspi_pins, peripherals.QUADSPI));
+        let optional_serial = devices::buffer_serial(devices::construct_serial(serial_pins, clocks, peripherals.USART1, peripherals.USART2, peripherals.USART6));
+        let status_led = devices::construct_status_led(status_led_pin).map(StatusLed::new);
+        let recovery_pin = devices::construct_recovery_pin(recovery_pin)
+            .map(|pin| RecoveryPin::new(pin, autogenerated::RECOVERY_PIN_ACTIVE_LEVEL));
 
         let start_time = if BOOT_TIME_METRICS_ENABLED {
             Some(SysTick::now())
@@ -66,12 +72,33 @@ impl Bootloader<ExternalFlash, flash::McuFlash, Serial, SysTick, ImageReader, Up
             mcu_flash,
             external_banks: &EXTERNAL_BANKS,
             mcu_banks: &MCU_BANKS,
+            mcu_sectors: &MCU_SECTORS,
+            staging_bank: MCU_STAGING_BANK,
+            staging_rotation: STAGING_ROTATION_STATE
+                .map(|state| StagingRotation { banks: MCU_STAGING_ROTATION, state }),
+            backup_bank: MCU_BACKUP_BANK,
+            golden_override: autogenerated::GOLDEN_OVERRIDE,
+            external_erase_size: EXTERNAL_ERASE_SIZE,
             external_flash: optional_external_flash,
             serial: optional_serial,
             boot_metrics: Default::default(),
             start_time,
             recovery_enabled: RECOVERY_ENABLED,
+            recovery_protocol: autogenerated::RECOVERY_PROTOCOL,
+            recovery_timeout: autogenerated::RECOVERY_TIMEOUT,
+            no_image_fallback: autogenerated::NO_IMAGE_FALLBACK,
+            boot_delay_ms: BOOT_DELAY_MS,
             greeting: autogenerated::LOADSTONE_GREETING,
+            serial_log_level: autogenerated::SERIAL_LOG_LEVEL,
+            boot_counter: BOOT_COUNTER,
+            verification_cache: VERIFICATION_CACHE.map(VerificationCache::new),
+            status_led,
+            recovery_pin,
+            ram_vector_table: autogenerated::RAM_VECTOR_TABLE,
+            self_check: autogenerated::SELF_CHECK,
+            jump_validation: autogenerated::JUMP_VALIDATION,
+            ram_clear: autogenerated::RAM_CLEAR,
+            verify_writes: autogenerated::VERIFY_WRITES,
             _marker: Default::default(),
             update_signal,
         }
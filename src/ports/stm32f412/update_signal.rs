@@ -13,11 +13,14 @@ impl UpdateSignal {
 
 impl update_signal::ReadUpdateSignal for UpdateSignal {
     fn read_update_plan(&self) -> UpdatePlan {
-        match self.rtc.bkpr[0].read().bits() {
-            0x00000000 => UpdatePlan::None,
-            0xFFFFFFFF => UpdatePlan::Any,
-            x => UpdatePlan::Index(x as u8),
-        }
+        self.rtc.bkpr[0].read().bits().into()
+    }
+}
+
+impl update_signal::WriteUpdateSignal for UpdateSignal {
+    fn write_update_plan(&mut self, plan: UpdatePlan) {
+        let bits: u32 = plan.into();
+        self.rtc.bkpr[0].write(|w| unsafe { w.bits(bits) });
     }
 }
 
@@ -33,15 +36,15 @@ impl UpdateSignalWriter {
 
 impl update_signal::WriteUpdateSignal for UpdateSignalWriter {
     fn write_update_plan(&mut self, plan: UpdatePlan) {
-        let bits = match plan {
-            UpdatePlan::None => 0x00000000,
-            UpdatePlan::Any => 0xFFFFFFFF,
-            UpdatePlan::Index(x) => x as u32,
-        };
+        let bits: u32 = plan.into();
         self.rtc.bkpr[0].write(|w| unsafe { w.bits(bits) });
     }
 }
 
+impl update_signal::ReadUpdateSignal for UpdateSignalWriter {
+    fn read_update_plan(&self) -> UpdatePlan { self.rtc.bkpr[0].read().bits().into() }
+}
+
 /// Initializes the backup domain registers of the realtime clock, required for the update signal
 /// to function.
 pub fn initialize_rtc_backup_domain(rcc: &mut blue_hal::stm32pac::RCC, pwr: &mut blue_hal::stm32pac::PWR) {
@@ -1,17 +1,18 @@
 //! Concrete bootloader construction and flash bank layout for the wgm160p
 
 use blue_hal::{drivers::efm32gg11b::{clocks, flash::{self, Flash}}, efm32pac, hal::null::{NullError, NullFlash, NullSerial, NullSystick}};
-use crate::{devices::{bootloader::Bootloader}, error::{self, Error}};
+use crate::{devices::{bootloader::{Bootloader, StagingRotation}, image::VerificationCache, recovery_pin::NullPin, status_led::NullLed}, error::{self, Error}};
 use super::autogenerated;
-use super::autogenerated::memory_map::{EXTERNAL_BANKS, MCU_BANKS};
+use super::autogenerated::memory_map::{BOOT_COUNTER, EXTERNAL_BANKS, EXTERNAL_ERASE_SIZE, MCU_BANKS, MCU_SECTORS, MCU_STAGING_BANK,
+    MCU_STAGING_ROTATION, STAGING_ROTATION_STATE, VERIFICATION_CACHE, MCU_BACKUP_BANK};
 
 #[cfg(feature="ecdsa-verify")]
-use crate::devices::image::EcdsaImageReader as ImageReader;
+type ImageReader = crate::devices::image::EcdsaImageReader<crate::devices::image::EmbeddedKey, { autogenerated::STRICT_SCAN }, { autogenerated::MAX_SCAN_BYTES }, { autogenerated::MIN_IMAGE_SIZE }>;
 #[cfg(not(feature="ecdsa-verify"))]
-use crate::devices::image::CrcImageReader as ImageReader;
+type ImageReader = crate::devices::image::CrcImageReader<{ autogenerated::CRC_POLYNOMIAL }, { autogenerated::STRICT_SCAN }, { autogenerated::MAX_SCAN_BYTES }, { autogenerated::MIN_IMAGE_SIZE }>;
 use super::update_signal::NullUpdateSignal;
 
-impl Bootloader<NullFlash, Flash, NullSerial, NullSystick, ImageReader, NullUpdateSignal> {
+impl Bootloader<NullFlash, Flash, NullSerial, NullSystick, ImageReader, NullUpdateSignal, NullLed, NullPin> {
     pub fn new() -> Self {
         let mut peripherals = efm32pac::Peripherals::take().unwrap();
         let clocks = clocks::Clocks::new(peripherals.CMU, &mut peripherals.MSC);
@@ -20,12 +21,33 @@ impl Bootloader<NullFlash, Flash, NullSerial, NullSystick, ImageReader, NullUpda
             mcu_flash,
             external_banks: &EXTERNAL_BANKS,
             mcu_banks: &MCU_BANKS,
+            mcu_sectors: &MCU_SECTORS,
+            staging_bank: MCU_STAGING_BANK,
+            staging_rotation: STAGING_ROTATION_STATE
+                .map(|state| StagingRotation { banks: MCU_STAGING_ROTATION, state }),
+            backup_bank: MCU_BACKUP_BANK,
+            golden_override: autogenerated::GOLDEN_OVERRIDE,
+            external_erase_size: EXTERNAL_ERASE_SIZE,
             external_flash: None,
             serial: None,
             boot_metrics: Default::default(),
             start_time: None,
             recovery_enabled: false,
+            recovery_protocol: autogenerated::RECOVERY_PROTOCOL,
+            recovery_timeout: autogenerated::RECOVERY_TIMEOUT,
+            no_image_fallback: autogenerated::NO_IMAGE_FALLBACK,
+            boot_delay_ms: 0,
             greeting: autogenerated::LOADSTONE_GREETING,
+            serial_log_level: autogenerated::SERIAL_LOG_LEVEL,
+            boot_counter: BOOT_COUNTER,
+            verification_cache: VERIFICATION_CACHE.map(VerificationCache::new),
+            status_led: None,
+            recovery_pin: None,
+            ram_vector_table: autogenerated::RAM_VECTOR_TABLE,
+            self_check: autogenerated::SELF_CHECK,
+            jump_validation: autogenerated::JUMP_VALIDATION,
+            ram_clear: autogenerated::RAM_CLEAR,
+            verify_writes: autogenerated::VERIFY_WRITES,
             _marker: Default::default(),
             update_signal: None,
         }
@@ -1,4 +1,4 @@
-use crate::devices::update_signal::{ReadUpdateSignal, UpdatePlan};
+use crate::devices::update_signal::{ReadUpdateSignal, UpdatePlan, WriteUpdateSignal};
 
 #[derive(Default)]
 pub struct NullUpdateSignal;
@@ -6,3 +6,7 @@ pub struct NullUpdateSignal;
 impl ReadUpdateSignal for NullUpdateSignal {
     fn read_update_plan(&self) -> UpdatePlan { UpdatePlan::Any }
 }
+
+impl WriteUpdateSignal for NullUpdateSignal {
+    fn write_update_plan(&mut self, _: UpdatePlan) {}
+}
@@ -0,0 +1,51 @@
+use std::fmt::{self, Display, Formatter};
+
+pub enum File {
+    Config,
+    Combined,
+    Output,
+}
+
+impl Display for File {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        use File::*;
+        match self {
+            Config => write!(f, "configuration"),
+            Combined => write!(f, "combined binary"),
+            Output => write!(f, "output image"),
+        }
+    }
+}
+
+pub enum Error {
+    FileReadFailed(File),
+    FileWriteFailed(File),
+    ConfigParseFailed,
+    AddressParseFailed,
+    NoBootableBank,
+    BankOverlapsBootloader,
+    BankOutsideBinary,
+    EmptyApplicationRegion,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        use Error::*;
+        match self {
+            FileReadFailed(file) => write!(f, "Failed to read {} file.", file),
+            FileWriteFailed(file) => write!(f, "Failed to write {} file.", file),
+            ConfigParseFailed => write!(f, "Failed to parse the Loadstone configuration."),
+            AddressParseFailed => write!(f, "Failed to parse the base address."),
+            NoBootableBank => write!(f, "The configuration doesn't define a bootable bank."),
+            BankOverlapsBootloader => {
+                write!(f, "The bootable bank overlaps the bootloader region.")
+            }
+            BankOutsideBinary => {
+                write!(f, "The bootable bank lies outside the combined binary.")
+            }
+            EmptyApplicationRegion => {
+                write!(f, "The application region is empty (fully erased).")
+            }
+        }
+    }
+}
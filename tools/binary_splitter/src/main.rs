@@ -0,0 +1,66 @@
+mod error;
+mod splitting;
+
+use crate::{
+    error::{self as e, Error},
+    splitting::{application_range, extract_application},
+};
+use clap::clap_app;
+use loadstone_config::{memory::internal_flash, Configuration};
+use std::fs;
+
+fn read_configuration(filename: &str) -> Result<Configuration, Error> {
+    let text = fs::read_to_string(filename).map_err(|_| Error::FileReadFailed(e::File::Config))?;
+    ron::from_str(&text).map_err(|_| Error::ConfigParseFailed)
+}
+
+fn parse_address(text: &str) -> Result<u32, Error> {
+    let digits = text.trim_start_matches("0x").trim_start_matches("0X");
+    u32::from_str_radix(digits, 16).map_err(|_| Error::AddressParseFailed)
+}
+
+fn split_binary(
+    configuration_filename: &str,
+    combined_filename: &str,
+    output_filename: &str,
+    base: Option<&str>,
+) -> Result<usize, Error> {
+    let configuration = read_configuration(configuration_filename)?;
+    let base = match base {
+        Some(address) => parse_address(address)?,
+        None => internal_flash(&configuration.port).start,
+    };
+    let combined =
+        fs::read(combined_filename).map_err(|_| Error::FileReadFailed(e::File::Combined))?;
+    let range = application_range(&configuration, base)?;
+    let application = extract_application(&combined, range)?;
+    fs::write(output_filename, application).map_err(|_| Error::FileWriteFailed(e::File::Output))?;
+    Ok(application.len())
+}
+
+fn main() -> Result<(), String> {
+    let matches = clap_app!(app =>
+        (name: env!("CARGO_PKG_NAME"))
+        (version: env!("CARGO_PKG_VERSION"))
+        (about: env!("CARGO_PKG_DESCRIPTION"))
+        (@arg config: +required "The .ron configuration Loadstone was built with.")
+        (@arg combined: +required "The combined bootloader and application binary.")
+        (@arg output: +required "Where to write the extracted application image.")
+        (@arg base: -b --base +takes_value "Address of the first byte of the combined binary, \
+            in hex. Defaults to the start of MCU flash.")
+    )
+    .get_matches();
+
+    match split_binary(
+        matches.value_of("config").unwrap(),
+        matches.value_of("combined").unwrap(),
+        matches.value_of("output").unwrap(),
+        matches.value_of("base"),
+    ) {
+        Ok(written_size) => {
+            println!("Successfully extracted application image ({} bytes).", written_size);
+            Ok(())
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
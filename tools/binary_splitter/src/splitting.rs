@@ -0,0 +1,125 @@
+use crate::error::Error;
+use loadstone_config::Configuration;
+use loadstone_image_format::Layout;
+use std::ops::Range;
+
+/// Value of a byte in erased flash.
+const ERASED_BYTE: u8 = 0xFF;
+
+/// Range of the application (the bootable bank) within a combined binary whose
+/// first byte sits at address `base`.
+pub fn application_range(configuration: &Configuration, base: u32) -> Result<Range<usize>, Error> {
+    let memory_map = &configuration.memory_configuration.internal_memory_map;
+    let bank = memory_map
+        .bootable_index
+        .and_then(|index| memory_map.banks.get(index))
+        .ok_or(Error::NoBootableBank)?;
+
+    let bootloader_end = memory_map.bootloader_location + memory_map.bootloader_length_kb * 1024;
+    if bank.start_address < bootloader_end && bank.end_address() > memory_map.bootloader_location {
+        return Err(Error::BankOverlapsBootloader);
+    }
+
+    let start = bank.start_address.checked_sub(base).ok_or(Error::BankOutsideBinary)? as usize;
+    Ok(start..start + bank.size_kb as usize * 1024)
+}
+
+/// Slices the application out of a combined binary. A decorated application is cut at
+/// the inverted magic string, dropping the flags before it, so only the body is left
+/// to re-sign. Otherwise, trailing erased bytes are trimmed. Binaries commonly end after
+/// their last written byte, so the range may be cut short.
+pub fn extract_application(combined: &[u8], range: Range<usize>) -> Result<&[u8], Error> {
+    if range.start >= combined.len() {
+        return Err(Error::BankOutsideBinary);
+    }
+    let region = &combined[range.start..range.end.min(combined.len())];
+    if let Ok(layout) = Layout::parse(region) {
+        if layout.body_size == 0 {
+            return Err(Error::EmptyApplicationRegion);
+        }
+        return Ok(&region[..layout.body_size]);
+    }
+    let last_written = region
+        .iter()
+        .rposition(|byte| *byte != ERASED_BYTE)
+        .ok_or(Error::EmptyApplicationRegion)?;
+    Ok(&region[..=last_written])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use loadstone_config::memory::Bank;
+
+    const BASE: u32 = 0x0800_0000;
+
+    fn configuration() -> Configuration {
+        let mut configuration = Configuration::default();
+        let memory_map = &mut configuration.memory_configuration.internal_memory_map;
+        memory_map.bootloader_location = BASE;
+        memory_map.bootloader_length_kb = 2;
+        memory_map.banks = vec![Bank { start_address: BASE + 2 * 1024, size_kb: 2 }, Bank {
+            start_address: BASE + 4 * 1024,
+            size_kb: 2,
+        }];
+        memory_map.bootable_index = Some(0);
+        configuration
+    }
+
+    #[test]
+    fn application_range_matches_bootable_bank() {
+        assert_eq!(2048..4096, application_range(&configuration(), BASE).unwrap());
+    }
+
+    #[test]
+    fn bank_overlapping_bootloader_is_rejected() {
+        let mut configuration = configuration();
+        configuration.memory_configuration.internal_memory_map.bootloader_length_kb = 3;
+        assert!(matches!(
+            application_range(&configuration, BASE),
+            Err(Error::BankOverlapsBootloader)
+        ));
+    }
+
+    #[test]
+    fn application_is_extracted_without_trailing_erased_bytes() {
+        let mut combined = vec![0xAAu8; 2048];
+        combined.extend_from_slice(&[0x12, 0x34, 0xFF, 0x56]);
+        combined.resize(6144, ERASED_BYTE);
+        let range = application_range(&configuration(), BASE).unwrap();
+        assert_eq!(&[0x12, 0x34, 0xFF, 0x56], extract_application(&combined, range).unwrap());
+    }
+
+    #[test]
+    fn decorated_application_is_cut_at_the_magic_string() {
+        use loadstone_image_format::{Algorithm, GOLDEN_STRING, MAGIC_STRING_INVERTED};
+        let body = [0x12, 0x34, 0xFF, 0xFF];
+        let mut combined = vec![0xAAu8; 2048];
+        combined.extend_from_slice(&body);
+        combined.extend_from_slice(GOLDEN_STRING.as_bytes());
+        combined.extend_from_slice(&MAGIC_STRING_INVERTED);
+        combined.push(Algorithm::Crc32.id());
+        combined.extend_from_slice(&[0x56, 0x78, 0x9A, 0xFF]);
+        combined.resize(6144, ERASED_BYTE);
+        let range = application_range(&configuration(), BASE).unwrap();
+        assert_eq!(&body, extract_application(&combined, range).unwrap());
+    }
+
+    #[test]
+    fn erased_application_region_is_rejected() {
+        let mut combined = vec![0xAAu8; 2048];
+        combined.resize(6144, ERASED_BYTE);
+        let range = application_range(&configuration(), BASE).unwrap();
+        assert!(matches!(
+            extract_application(&combined, range),
+            Err(Error::EmptyApplicationRegion)
+        ));
+    }
+
+    #[test]
+    fn binary_ending_before_application_is_rejected() {
+        let combined = vec![0xAAu8; 1024];
+        let range = application_range(&configuration(), BASE).unwrap();
+        assert!(matches!(extract_application(&combined, range), Err(Error::BankOutsideBinary)));
+    }
+}
@@ -0,0 +1,65 @@
+use crate::{
+    decorating::magic_string_inverted,
+    error::{self, Error},
+};
+use std::fs::{self, File};
+use std::io::Read;
+
+/// Identifies a golden image body as compressed. Must match `COMPRESSION_MAGIC` in Loadstone.
+const COMPRESSION_MAGIC: &[u8; 4] = b"LSZ1";
+/// Longest literal run emitted. Keeping literal runs short guarantees the magic string of
+/// the embedded image is broken up by control bytes in the compressed stream.
+const MAX_LITERAL_RUN: usize = 16;
+/// Longest run of repeated bytes a single control byte can describe.
+const MAX_REPEAT_RUN: usize = 128;
+
+/// Replaces a fully decorated and signed image with its compressed form, preceded by
+/// the compression header. The result must then be decorated as golden and signed again.
+pub fn compress_file(image_filename: &str) -> Result<usize, Error> {
+    let mut contents = Vec::new();
+    File::open(image_filename)
+        .and_then(|mut file| file.read_to_end(&mut contents))
+        .map_err(|_| Error::FileReadFailed(error::File::Image))?;
+
+    let compressed = compress(&contents);
+    let magic_string = magic_string_inverted();
+    if compressed.windows(magic_string.len()).any(|window| window == magic_string.as_slice()) {
+        return Err(Error::CompressionFailed);
+    }
+
+    fs::write(image_filename, &compressed)
+        .map_err(|_| Error::FileWriteFailed(error::File::Image))?;
+    Ok(compressed.len())
+}
+
+/// PackBits style run length encoding, as decompressed by Loadstone.
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut output = COMPRESSION_MAGIC.to_vec();
+    output.extend_from_slice(&(data.len() as u32).to_le_bytes());
+
+    let mut literals = Vec::new();
+    let flush = |literals: &mut Vec<u8>, output: &mut Vec<u8>| {
+        if !literals.is_empty() {
+            output.push(literals.len() as u8 - 1);
+            output.append(literals);
+        }
+    };
+
+    let mut index = 0;
+    while index < data.len() {
+        let run =
+            data[index..].iter().take(MAX_REPEAT_RUN).take_while(|b| **b == data[index]).count();
+        if run > 1 {
+            flush(&mut literals, &mut output);
+            output.extend_from_slice(&[(257 - run) as u8, data[index]]);
+        } else {
+            literals.push(data[index]);
+            if literals.len() == MAX_LITERAL_RUN {
+                flush(&mut literals, &mut output);
+            }
+        }
+        index += run;
+    }
+    flush(&mut literals, &mut output);
+    output
+}
@@ -3,29 +3,39 @@ use crate::{
     open_image,
 };
 use blue_hal::utilities::iterator::UntilSequence;
-use std::io::{Read, Write};
+use std::io::Write;
 
-/// This string identifies a golden image, and must precede the magic string.
-const GOLDEN_STRING: &str = "XPIcbOUrpG";
-/// This string, INVERTED BYTEWISE must terminate any valid image, before the signature.
-///
-/// Note: Why inverted? Because if we used it as-is, no code that includes this
-/// constant could be used as a firmware image, as it contains the magic string
-/// halfway through.
-pub const MAGIC_STRING: &str = "HSc7c2ptydZH2QkqZWPcJgG3JtnJ6VuA";
-pub fn magic_string_inverted() -> Vec<u8> { MAGIC_STRING.as_bytes().iter().map(|b| !b).collect() }
+pub use loadstone_image_format::{flags_read_back, GOLDEN_STRING, NO_AUTO_UPDATE_STRING};
 
-pub fn decorate_file(image_filename: &str, is_golden: bool) -> Result<(), Error> {
-    let file = open_image(image_filename)?;
-    if file
-        .bytes()
-        .map(|b| b.unwrap())
-        .until_sequence(magic_string_inverted().as_slice())
-        .contains_sequence()
+pub fn magic_string_inverted() -> Vec<u8> { loadstone_image_format::MAGIC_STRING_INVERTED.to_vec() }
+
+/// Appends the flag strings and the inverted magic string to the image, returning the
+/// size of the body they follow.
+pub fn decorate_file(
+    image_filename: &str,
+    is_golden: bool,
+    no_auto_update: bool,
+    assert_not_golden: bool,
+    framed: bool,
+) -> Result<usize, Error> {
+    let body =
+        std::fs::read(image_filename).map_err(|_| Error::FileReadFailed(error::File::Image))?;
+    if !framed
+        && body
+            .iter()
+            .cloned()
+            .until_sequence(magic_string_inverted().as_slice())
+            .contains_sequence()
     {
         return Err(Error::FileAlreadySigned(error::File::Image));
     }
+    check_flags_read_back(image_filename, is_golden, no_auto_update, assert_not_golden)?;
     let mut file = open_image(image_filename)?;
+    if no_auto_update {
+        file.write(NO_AUTO_UPDATE_STRING.as_bytes())
+            .map_err(|_| Error::FileWriteFailed(error::File::Image))?;
+        println!("Successfully appended no auto update string.");
+    }
     if is_golden {
         file.write(GOLDEN_STRING.as_bytes())
             .map_err(|_| Error::FileWriteFailed(error::File::Image))?;
@@ -34,5 +44,35 @@ pub fn decorate_file(image_filename: &str, is_golden: bool) -> Result<(), Error>
     file.write(magic_string_inverted().as_slice())
         .map_err(|_| Error::FileWriteFailed(error::File::Image))?;
     println!("Successfully appended magic string.");
+    Ok(body.len())
+}
+
+/// Loadstone detects flags by the strings right before the magic string, so a body
+/// ending with the same bytes would be misread once decorated (e.g. taken for a golden
+/// image). This is reported as a warning, or as an error if the image was asserted not
+/// to be golden and would be taken for one.
+fn check_flags_read_back(
+    image_filename: &str,
+    is_golden: bool,
+    no_auto_update: bool,
+    assert_not_golden: bool,
+) -> Result<(), Error> {
+    let body =
+        std::fs::read(image_filename).map_err(|_| Error::FileReadFailed(error::File::Image))?;
+    if flags_read_back(&body, no_auto_update, is_golden) {
+        return Ok(());
+    }
+    // The golden string is checked right before the magic string, so only a body followed
+    // by no other flag can pass for golden.
+    let misread_as_golden =
+        !is_golden && !no_auto_update && body.ends_with(GOLDEN_STRING.as_bytes());
+    if assert_not_golden && misread_as_golden {
+        return Err(Error::ImageMisreadAsGolden);
+    }
+    println!(
+        "Warning: the image body ends with the bytes of a flag string, so Loadstone won't \
+        read back the requested flags (golden: {}, no auto update: {}).",
+        is_golden, no_auto_update
+    );
     Ok(())
 }
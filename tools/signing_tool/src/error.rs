@@ -21,6 +21,12 @@ pub enum Error {
     FileWriteFailed(File),
     FileAlreadySigned(File),
     KeyParseFailed,
+    CrcAlgorithmParseFailed,
+    BootloaderRegionParseFailed,
+    BootloaderTooLarge,
+    CompressionFailed,
+    CompressedImageNotGolden,
+    ImageMisreadAsGolden,
 }
 
 impl Display for Error {
@@ -32,6 +38,14 @@ impl Display for Error {
             FileWriteFailed(file) => write!(f, "Failed to write {} file.", file),
             FileAlreadySigned(file) => write!(f, "File already signed ({} file).", file),
             KeyParseFailed => write!(f, "Failed to parse the private key."),
+            CrcAlgorithmParseFailed => write!(f, "Failed to parse the CRC algorithm."),
+            BootloaderRegionParseFailed => write!(f, "Failed to parse the bootloader region size."),
+            BootloaderTooLarge => write!(f, "Bootloader doesn't fit in its region with a CRC."),
+            CompressionFailed => write!(f, "Compressed image contains the magic string."),
+            CompressedImageNotGolden => write!(f, "Only golden images can be compressed."),
+            ImageMisreadAsGolden => {
+                write!(f, "Image body ends with the golden string, so it would pass for golden.")
+            }
         }
     }
 }
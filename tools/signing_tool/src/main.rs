@@ -1,14 +1,17 @@
 mod error;
 mod signing;
 mod decorating;
+mod compressing;
+mod test_vector;
 
 use crate::{
+    compressing::compress_file,
     decorating::decorate_file,
     error::{self as e, Error},
     signing::sign_file,
 };
 use clap::clap_app;
-use signing::calculate_and_append_crc;
+use signing::{append_bootloader_crc, calculate_and_append_crc, parse_crc_polynomial};
 use std::fs::{File, OpenOptions};
 
 fn open_image(filename: &str) -> Result<File, Error> {
@@ -23,16 +26,42 @@ fn process_image_file(
     image_filename: String,
     private_key_filename: Option<String>,
     image_is_golden: bool,
+    no_auto_update: bool,
+    assert_not_golden: bool,
+    compress: bool,
+    framed: bool,
+    crc_polynomial: u32,
 ) -> Result<usize, Error> {
-    decorate_file(&image_filename, image_is_golden)?;
+    if compress {
+        if !image_is_golden {
+            return Err(Error::CompressedImageNotGolden);
+        }
+        // The complete image is signed first, as it's verified again after decompression.
+        process_image_file(
+            image_filename.clone(),
+            private_key_filename.clone(),
+            image_is_golden,
+            no_auto_update,
+            assert_not_golden,
+            false,
+            framed,
+            crc_polynomial,
+        )?;
+        let compressed_size = compress_file(&image_filename)?;
+        println!("Successfully compressed image ({} bytes).", compressed_size);
+    }
+
+    let body_size =
+        decorate_file(&image_filename, image_is_golden, no_auto_update, assert_not_golden, framed)?;
+    let frame = if framed { Some(body_size) } else { None };
 
     if let Some(private_key_filename) = private_key_filename {
         let key_file =
             File::open(private_key_filename).map_err(|_| Error::FileOpenFailed(e::File::Key))?;
         let key = signing::read_key(key_file)?;
-        sign_file(&image_filename, key)
+        sign_file(&image_filename, key, frame)
     } else {
-        calculate_and_append_crc(&image_filename)
+        calculate_and_append_crc(&image_filename, crc_polynomial, frame)
     }
 }
 
@@ -44,23 +73,65 @@ fn main() -> Result<(), String> {
         (about: env!("CARGO_PKG_DESCRIPTION"))
         (@arg image: +required "The firmware image to be signed.")
         (@arg golden: -g --golden "Label the image as golden (Loadstone firmware fallback)")
+        (@arg assert_not_golden: --("assert-not-golden") conflicts_with[golden] "Fail if the image \
+            body happens to end with the golden string, which would make Loadstone take it for \
+            a golden image. Without this, such images are only warned about.")
+        (@arg no_auto_update: -n --("no-auto-update") "Never use the image as an update source, \
+            even if it's newer than the current one. It can still be booted from the bootable bank.")
         (@arg private_key: "The PKCS8 private key used to sign the image. \
-            If absent, an IEEE CRC32 code will be appended instead of a signature.")
+            If absent, a CRC32 code will be appended instead of a signature.")
+        (@arg crc: -c --crc +takes_value "CRC32 variant to append when no private key is supplied: \
+            `ieee` (default), `castagnoli`, or a reflected polynomial in hex. Must match the \
+            `crc_algorithm` in the Loadstone configuration.")
+        (@arg compress: -z --compress "Store the golden image compressed, so it fits a golden bank \
+            smaller than the bootable bank. Loadstone decompresses it when restoring.")
+        (@arg framed: -f --framed "Record the size of the body in the trailer, so Loadstone \
+            reads past any copy of the magic string inside the body (e.g. in an application \
+            that embeds it). Requires a Loadstone version that supports framed images.")
+        (@arg bootloader: -b --bootloader +takes_value "Treat the file as a Loadstone binary for \
+            the bootloader self check: pad it to the given region size (in KB, matching \
+            `bootloader_length_kb`) and append a CRC32 of the region. Other options are ignored.")
+        (@arg test_vector: -t --("test-vector") +takes_value "After signing, print the image as a \
+            Rust byte array constant with the given name, to paste into Loadstone's image tests.")
     )
     .get_matches();
 
     let image_filename = matches.value_of("image").unwrap().to_owned();
+    if let Some(region_kb) = matches.value_of("bootloader") {
+        let region_kb: usize = region_kb
+            .parse()
+            .map_err(|_| Error::BootloaderRegionParseFailed.to_string())?;
+        let written_size = append_bootloader_crc(&image_filename, region_kb * 1024)
+            .map_err(|e| e.to_string())?;
+        println!("Successfully padded bootloader and appended CRC ({} bytes).", written_size);
+        return Ok(());
+    }
     let private_key_filename = matches.value_of("private_key").map(str::to_owned);
+    let crc_polynomial =
+        parse_crc_polynomial(matches.value_of("crc").unwrap_or("ieee")).map_err(|e| e.to_string())?;
+
+    let is_golden = matches.occurrences_of("golden") > 0;
+    let no_auto_update = matches.occurrences_of("no_auto_update") > 0;
 
     match process_image_file(
-        image_filename,
+        image_filename.clone(),
         private_key_filename.clone(),
-        matches.occurrences_of("golden") > 0,
+        is_golden,
+        no_auto_update,
+        matches.occurrences_of("assert_not_golden") > 0,
+        matches.occurrences_of("compress") > 0,
+        matches.occurrences_of("framed") > 0,
+        crc_polynomial,
     ) {
         Ok(written_size) => {
             println!("Successfully appended {} to image ({} bytes).", if
                      private_key_filename.is_some() { "signature " } else { "CRC" },
                      written_size);
+            if let Some(name) = matches.value_of("test_vector") {
+                let literal =
+                    test_vector::rust_literal(&image_filename, name).map_err(|e| e.to_string())?;
+                print!("{}", literal);
+            }
             Ok(())
         }
         Err(e) => Err(e.to_string()),
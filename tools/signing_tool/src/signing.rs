@@ -14,6 +14,13 @@ use std::{
     io::{Read, Write},
 };
 
+use loadstone_image_format::Algorithm;
+
+/// Value of erased flash, used to pad the bootloader binary up to its CRC.
+const ERASED_FLASH_BYTE: u8 = 0xFF;
+/// Size of the CRC32 stored in the last bytes of the bootloader region.
+const BOOTLOADER_CRC_SIZE: usize = 4;
+
 fn read_file(file: &mut File) -> Result<Vec<u8>, Error> {
     let mut contents = Vec::new();
     match file.read_to_end(&mut contents) {
@@ -28,32 +35,92 @@ pub fn read_key(mut file: File) -> Result<SigningKey, Error> {
     SigningKey::from_str(string.as_str()).map_err(|_| Error::KeyParseFailed)
 }
 
-/// Reads the contents of `file` and signs it using P256 ECDSA/SHA256 with the key in `key_file`.
-pub fn sign_file(image_filename: &str, key: SigningKey) -> Result<usize, Error> {
+/// Algorithm identifier, followed by the frame recording `frame` as the body size if
+/// the image is framed.
+fn identifier(algorithm: Algorithm, frame: Option<usize>) -> Vec<u8> {
+    match frame {
+        Some(body_size) => {
+            let mut identifier = vec![algorithm.framed_id()];
+            identifier.extend_from_slice(&(body_size as u32).to_le_bytes());
+            identifier
+        }
+        None => vec![algorithm.id()],
+    }
+}
+
+/// Reads the contents of `file` and signs it using P256 ECDSA/SHA256 with the key in `key_file`,
+/// appending the algorithm identifier (and frame, if any) followed by the signature.
+pub fn sign_file(
+    image_filename: &str,
+    key: SigningKey,
+    frame: Option<usize>,
+) -> Result<usize, Error> {
     let mut file = open_image(image_filename)?;
     let plaintext = read_file(&mut file)?;
     let signature = key.sign(&plaintext);
+    let mut trailer = identifier(Algorithm::P256, frame);
+    trailer.extend_from_slice(signature.as_bytes());
+    let bytes_written =
+        file.write(&trailer).map_err(|_| Error::FileWriteFailed(error::File::Image))?;
+
+    if bytes_written == trailer.len() {
+        Ok(bytes_written)
+    } else {
+        Err(Error::FileWriteFailed(error::File::Image))
+    }
+}
+
+/// Pads a Loadstone binary with erased flash bytes to fill its region, leaving room for a
+/// CRC32 (IEEE) of the padded contents at the very end, as expected by the bootloader self check.
+pub fn append_bootloader_crc(image_filename: &str, region_size: usize) -> Result<usize, Error> {
+    let mut file = open_image(image_filename)?;
+    let mut contents = read_file(&mut file)?;
+    let crc_offset = region_size
+        .checked_sub(BOOTLOADER_CRC_SIZE)
+        .filter(|offset| contents.len() <= *offset)
+        .ok_or(Error::BootloaderTooLarge)?;
+
+    let mut trailer = vec![ERASED_FLASH_BYTE; crc_offset - contents.len()];
+    contents.extend_from_slice(&trailer);
+    trailer.extend_from_slice(&crc32::checksum_ieee(&contents).to_le_bytes());
     let bytes_written =
-        file.write(signature.as_bytes()).map_err(|_| Error::FileWriteFailed(error::File::Image))?;
+        file.write(&trailer).map_err(|_| Error::FileWriteFailed(error::File::Image))?;
 
-    if bytes_written == signature.as_bytes().len() {
+    if bytes_written == trailer.len() {
         Ok(bytes_written)
     } else {
         Err(Error::FileWriteFailed(error::File::Image))
     }
 }
 
-pub fn calculate_and_append_crc(image_filename: &str) -> Result<usize, Error> {
+/// Parses a CRC32 variant by name (`ieee`, `castagnoli`) or as a hex reflected polynomial,
+/// matching the `crc_algorithm` options in the Loadstone configuration.
+pub fn parse_crc_polynomial(algorithm: &str) -> Result<u32, Error> {
+    match algorithm.to_lowercase().as_str() {
+        "ieee" => Ok(crc32::IEEE),
+        "castagnoli" | "crc32c" => Ok(crc32::CASTAGNOLI),
+        custom => u32::from_str_radix(custom.trim_start_matches("0x"), 16)
+            .map_err(|_| Error::CrcAlgorithmParseFailed),
+    }
+}
+
+pub fn calculate_and_append_crc(
+    image_filename: &str,
+    polynomial: u32,
+    frame: Option<usize>,
+) -> Result<usize, Error> {
     let mut file = open_image(image_filename)?;
     let plaintext = read_file(&mut file)?;
 
-    let mut digest = crc32::Digest::new(crc32::IEEE);
+    let mut digest = crc32::Digest::new(polynomial);
     digest.write(&plaintext);
 
+    let mut trailer = identifier(Algorithm::Crc32, frame);
+    trailer.extend_from_slice(&digest.sum32().to_le_bytes());
     let bytes_written =
-        file.write(&digest.sum32().to_le_bytes()).map_err(|_| Error::FileWriteFailed(error::File::Image))?;
+        file.write(&trailer).map_err(|_| Error::FileWriteFailed(error::File::Image))?;
 
-    if bytes_written == core::mem::size_of::<u32>() {
+    if bytes_written == trailer.len() {
         Ok(bytes_written)
     } else {
         Err(Error::FileWriteFailed(error::File::Image))
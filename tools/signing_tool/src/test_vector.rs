@@ -0,0 +1,81 @@
+use crate::error::{self, Error};
+use loadstone_image_format::{Algorithm, Layout, GOLDEN_STRING, MAGIC_STRING};
+use std::fs;
+
+/// Bytes per line of the emitted array, matching the existing test vectors.
+const BYTES_PER_LINE: usize = 8;
+
+/// Reads a decorated and signed image, and formats it as a Rust byte array constant
+/// that can be pasted into Loadstone's image test modules. Each section of the image
+/// is labelled with a comment. Sections are located with the image format parser
+/// shared with Loadstone, so the labels match what the device finds.
+pub fn rust_literal(image_filename: &str, name: &str) -> Result<String, Error> {
+    let image = fs::read(image_filename).map_err(|_| Error::FileReadFailed(error::File::Image))?;
+    let layout = Layout::parse(&image).map_err(|_| Error::FileReadFailed(error::File::Image))?;
+
+    let (image, _) = image.split_at(layout.total_size());
+    let (body, trailer) = image.split_at(layout.magic_string_offset);
+    let (body, golden_string) =
+        body.split_at(body.len() - if layout.golden { GOLDEN_STRING.len() } else { 0 });
+    let (body, no_auto_update_string) = body.split_at(layout.body_size);
+    let (magic_string, trailer) = trailer.split_at(MAGIC_STRING.len());
+    let (algorithm, trailer) = trailer.split_at(Algorithm::ID_SIZE);
+    let (frame, signature) = trailer.split_at(layout.digest_offset() - layout.frame_offset());
+
+    let sections = [
+        ("Image", body),
+        ("No auto update string", no_auto_update_string),
+        ("Golden string", golden_string),
+        ("Magic string inverted", magic_string),
+        ("Algorithm", algorithm),
+        ("Frame", frame),
+        (if signature.len() == 4 { "CRC" } else { "Signature" }, signature),
+    ];
+
+    let mut literal = format!("    #[rustfmt::skip]\n    const {}: &[u8] = &[\n", name);
+    for (label, bytes) in sections.iter().filter(|(_, bytes)| !bytes.is_empty()) {
+        literal.push_str(&format!("        // {}\n", label));
+        for line in bytes.chunks(BYTES_PER_LINE) {
+            let line: Vec<String> = line.iter().map(|byte| format!("{:#04x},", byte)).collect();
+            literal.push_str(&format!("        {}\n", line.join(" ")));
+        }
+    }
+    literal.push_str("    ];\n");
+    Ok(literal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use loadstone_image_format::MAGIC_STRING_INVERTED;
+
+    /// Bytes listed in an array literal, ignoring its comments.
+    fn parse_literal(literal: &str) -> Vec<u8> {
+        literal
+            .lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with("0x"))
+            .flat_map(|line| line.split_whitespace())
+            .map(|byte| u8::from_str_radix(byte.trim_end_matches(',').trim_start_matches("0x"), 16))
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn literal_holds_the_image_bytes() {
+        let mut image = b"hello world\n".to_vec();
+        image.extend_from_slice(GOLDEN_STRING.as_bytes());
+        image.extend_from_slice(&MAGIC_STRING_INVERTED);
+        image.push(Algorithm::Crc32.id());
+        image.extend_from_slice(&[0xf0, 0xc9, 0x42, 0xad]);
+        let filename = std::env::temp_dir().join("loadstone_test_vector_round_trip.bin");
+        fs::write(&filename, &image).unwrap();
+
+        let literal = rust_literal(filename.to_str().unwrap(), "TEST_IMAGE").unwrap();
+        fs::remove_file(&filename).unwrap();
+        assert!(literal.contains("const TEST_IMAGE: &[u8] = &["));
+        assert!(literal.contains("// Golden string") && literal.contains("// CRC"));
+        assert!(!literal.contains("// Frame"));
+        assert_eq!(image, parse_literal(&literal));
+    }
+}